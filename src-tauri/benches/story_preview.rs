@@ -0,0 +1,85 @@
+//! Reading a story preview from a large export: the partial reader used by
+//! the sync server against parsing the whole export into a `Value`.
+//! Run with `cargo bench --bench story_preview`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[path = "../src/sync/preview.rs"]
+mod preview;
+
+/// An export with `entries` entries and a large embedded image
+fn story_json(entries: usize) -> String {
+    let passage = "The corridor narrowed, and the torchlight caught old carvings. ".repeat(16);
+    let entries: Vec<_> = (0..entries)
+        .map(|i| {
+            serde_json::json!({
+                "id": format!("entry-{}", i),
+                "type": "narration",
+                "content": passage,
+                "position": i,
+                "metadata": { "tokens": 240, "model": "bench" },
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "version": "1.0",
+        "story": {
+            "id": "bench",
+            "title": "Benchmark",
+            "genre": "Fantasy",
+            "updatedAt": 1_700_000_000_000i64,
+            "favorite": true,
+            "pinned": false,
+            "ageRating": "teen",
+            "contentWarnings": ["violence"],
+        },
+        "entries": entries,
+        "embeddedImages": [{ "id": "image-1", "imageData": "iVBORw0KGgo".repeat(200_000) }],
+    })
+    .to_string()
+}
+
+fn bench_preview(c: &mut Criterion) {
+    let json = story_json(5_000);
+    println!("export: {} bytes", json.len());
+
+    let mut group = c.benchmark_group("story_preview");
+    group.bench_function("value", |b| {
+        b.iter(|| {
+            let data: serde_json::Value = serde_json::from_str(&json).unwrap();
+            let story = &data["story"];
+            (
+                story["id"].as_str().map(String::from),
+                story["title"].as_str().map(String::from),
+                story["genre"].as_str().map(String::from),
+                story["updatedAt"].as_i64(),
+                story["favorite"].as_bool(),
+                story["pinned"].as_bool(),
+                story["ageRating"].as_str().map(String::from),
+                story["contentWarnings"].as_array().map(Vec::len),
+                data["entries"].as_array().map(Vec::len),
+            )
+        })
+    });
+    group.bench_function("partial", |b| {
+        b.iter(|| {
+            let data = preview::read(&json).unwrap();
+            let story = data.story.unwrap();
+            (
+                story.id,
+                story.title,
+                story.genre,
+                story.updated_at,
+                story.favorite,
+                story.pinned,
+                story.age_rating,
+                story.content_warnings.map(|w| w.len()),
+                data.entries.0,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_preview);
+criterion_main!(benches);
@@ -0,0 +1,76 @@
+//! Encoding a story response in each sync wire format.
+//! Run with `cargo bench --bench sync_encoding`; sizes are printed first.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde::{Deserialize, Serialize};
+
+// Header negotiation is not exercised here
+#[allow(dead_code)]
+#[path = "../src/sync/codec.rs"]
+mod codec;
+
+use codec::WireFormat;
+
+/// Same wire shape as the `StoryData` variant of `SyncResponse`
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Response {
+    StoryData { data: String },
+}
+
+/// An Aventura export with `entries` passages of dialogue-heavy prose and
+/// one embedded image, about the size of a long story
+fn story_json(entries: usize) -> String {
+    let passage = "\"Keep your voice down,\" she said. The lantern swung, and \
+                   shadows slid across the \"old\" map pinned to the wall.\n\n"
+        .repeat(8);
+    let entries: Vec<_> = (0..entries)
+        .map(|i| {
+            serde_json::json!({
+                "id": format!("entry-{}", i),
+                "type": if i % 2 == 0 { "user_action" } else { "narration" },
+                "content": passage,
+                "position": i,
+                "createdAt": 1_700_000_000_000i64 + i as i64,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "version": "1.0",
+        "story": { "id": "bench", "title": "Benchmark", "genre": "Fantasy" },
+        "entries": entries,
+        "embeddedImages": [{ "id": "image-1", "imageData": "iVBORw0KGgo".repeat(100_000) }],
+    })
+    .to_string()
+}
+
+fn bench_formats(c: &mut Criterion) {
+    let response = Response::StoryData {
+        data: story_json(2_000),
+    };
+    let formats = [
+        ("json", WireFormat::Json),
+        ("msgpack", WireFormat::MessagePack),
+        ("cbor", WireFormat::Cbor),
+    ];
+
+    for (name, format) in formats {
+        let bytes = format.encode(&response).unwrap();
+        println!("{}: {} bytes", name, bytes.len());
+    }
+
+    let mut group = c.benchmark_group("story_response");
+    for (name, format) in formats {
+        let bytes = format.encode(&response).unwrap();
+        group.bench_function(format!("{}/encode", name), |b| {
+            b.iter(|| format.encode(&response).unwrap())
+        });
+        group.bench_function(format!("{}/decode", name), |b| {
+            b.iter(|| format.decode::<Response>(&bytes).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_formats);
+criterion_main!(benches);
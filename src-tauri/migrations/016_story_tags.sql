@@ -0,0 +1,3 @@
+-- Migration 016: Add library tags to stories
+-- Stores a JSON array of tag strings
+ALTER TABLE stories ADD COLUMN tags TEXT;
@@ -0,0 +1,3 @@
+-- Migration 017: Add favorite and pinned flags to stories
+ALTER TABLE stories ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE stories ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Migration 018: Add content ratings to stories
+-- age_rating is one of everyone, teen, mature, adult; content_warnings is a JSON array
+ALTER TABLE stories ADD COLUMN age_rating TEXT;
+ALTER TABLE stories ADD COLUMN content_warnings TEXT;
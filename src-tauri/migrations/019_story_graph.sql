@@ -0,0 +1,26 @@
+-- Migration 019: Store the branch graph of each story
+-- Rebuilt from story_entries and branches whenever the graph is requested
+
+CREATE TABLE IF NOT EXISTS story_graph_nodes (
+    story_id TEXT NOT NULL,
+    entry_id TEXT NOT NULL,
+    branch_id TEXT,                  -- NULL for the main branch
+    position INTEGER NOT NULL,
+    reachable INTEGER NOT NULL DEFAULT 1,
+
+    PRIMARY KEY (story_id, entry_id),
+    FOREIGN KEY (story_id) REFERENCES stories(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS story_graph_edges (
+    story_id TEXT NOT NULL,
+    from_entry_id TEXT NOT NULL,
+    to_entry_id TEXT NOT NULL,
+    kind TEXT NOT NULL,              -- 'next' or 'choice'
+    label TEXT,                      -- Branch name for choice edges
+
+    PRIMARY KEY (story_id, from_entry_id, to_entry_id),
+    FOREIGN KEY (story_id) REFERENCES stories(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_story_graph_edges_to ON story_graph_edges(story_id, to_entry_id);
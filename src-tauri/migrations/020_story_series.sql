@@ -0,0 +1,3 @@
+-- Migration 020: Link the parts of a split story
+-- JSON with the series ID and title, the part number and the neighbouring parts
+ALTER TABLE stories ADD COLUMN series TEXT;
@@ -0,0 +1,3 @@
+-- Migration 021: Ambience assigned to a story's scenes
+-- JSON with the default track and the tracks of locations and chapters
+ALTER TABLE stories ADD COLUMN ambience TEXT;
@@ -0,0 +1,3 @@
+-- Migration 022: Revision numbers for optimistic concurrency
+-- Bumped by every save; a save based on an older revision is rejected
+ALTER TABLE stories ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
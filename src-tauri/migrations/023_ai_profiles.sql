@@ -0,0 +1,8 @@
+-- Migration 023: Per-story AI profiles
+-- The profile is stored as JSON without its API key, which stays in the keychain
+CREATE TABLE IF NOT EXISTS ai_profiles (
+    story_id TEXT PRIMARY KEY,
+    profile TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (story_id) REFERENCES stories(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Migration 024: Link a translated variant to the story it was made from
+-- JSON with the source story ID and the language it was translated into
+ALTER TABLE stories ADD COLUMN variant_of TEXT;
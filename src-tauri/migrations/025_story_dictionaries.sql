@@ -0,0 +1,8 @@
+-- Migration 025: Per-story custom spellcheck words
+-- Words are matched without regard to case, so a word is kept once per story
+CREATE TABLE IF NOT EXISTS story_dictionary_words (
+    story_id TEXT NOT NULL,
+    word TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (story_id, word),
+    FOREIGN KEY (story_id) REFERENCES stories(id) ON DELETE CASCADE
+);
@@ -0,0 +1,110 @@
+// Reading controls for shared stories and exported sites: text size, theme,
+// a progress bar and returning to where the reader left off. Preferences are
+// kept in the browser only.
+(function () {
+  "use strict";
+
+  var root = document.documentElement;
+  var themes = ["light", "sepia", "dark"];
+  var prefsKey = "aventura-reader";
+  var placeKey = "aventura-reader:" + location.pathname;
+
+  function read(key) {
+    try {
+      return localStorage.getItem(key);
+    } catch (e) {
+      return null;
+    }
+  }
+
+  function write(key, value) {
+    try {
+      localStorage.setItem(key, value);
+    } catch (e) {
+      // Private windows and file:// pages may refuse storage
+    }
+  }
+
+  var prefs = {};
+  try {
+    prefs = JSON.parse(read(prefsKey)) || {};
+  } catch (e) {
+    prefs = {};
+  }
+
+  function apply() {
+    if (themes.indexOf(prefs.theme) >= 0) {
+      root.setAttribute("data-theme", prefs.theme);
+    }
+    root.style.setProperty("--scale", String(prefs.scale || 1));
+  }
+
+  function change(update) {
+    update();
+    write(prefsKey, JSON.stringify(prefs));
+    apply();
+  }
+
+  function scaleBy(step) {
+    var scale = Math.round(((prefs.scale || 1) + step) * 10) / 10;
+    prefs.scale = Math.min(1.6, Math.max(0.8, scale));
+  }
+
+  apply();
+
+  document.addEventListener("DOMContentLoaded", function () {
+    var bar = document.createElement("div");
+    bar.className = "reader-bar";
+    function button(label, title, action) {
+      var element = document.createElement("button");
+      element.type = "button";
+      element.textContent = label;
+      element.title = title;
+      element.setAttribute("aria-label", title);
+      element.addEventListener("click", function () {
+        change(action);
+      });
+      bar.appendChild(element);
+    }
+    button("A−", "Smaller text", function () {
+      scaleBy(-0.1);
+    });
+    button("A+", "Larger text", function () {
+      scaleBy(0.1);
+    });
+    button("◐", "Change theme", function () {
+      var current = root.getAttribute("data-theme") || themes[0];
+      prefs.theme = themes[(themes.indexOf(current) + 1) % themes.length];
+    });
+    document.body.insertBefore(bar, document.body.firstChild);
+
+    var progress = document.createElement("div");
+    progress.className = "reader-progress";
+    document.body.appendChild(progress);
+
+    var pending = false;
+    function update() {
+      pending = false;
+      var max = root.scrollHeight - window.innerHeight;
+      var y = window.scrollY;
+      progress.style.width = (max > 0 ? Math.min(100, (y / max) * 100) : 100) + "%";
+      write(placeKey, String(Math.round(y)));
+    }
+    window.addEventListener(
+      "scroll",
+      function () {
+        if (!pending) {
+          pending = true;
+          window.requestAnimationFrame(update);
+        }
+      },
+      { passive: true }
+    );
+
+    var place = Number(read(placeKey));
+    if (place > 0 && !location.hash) {
+      window.scrollTo(0, place);
+    }
+    update();
+  });
+})();
@@ -0,0 +1,200 @@
+//! Scene beat outlines. The model is asked for the beats of one act as
+//! JSON, constrained by a schema when the provider supports structured
+//! output. The reply is checked here either way, since a provider that
+//! ignored the schema may send anything.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+use super::metadata::source_text;
+use super::proxy::{complete_structured, json_object};
+use super::types::{ChatMessage, ProviderConfig, SamplingParams};
+use crate::annotations::{Annotation, PlannedBeat};
+use crate::story::StoryExport;
+
+const MIN_BEATS: usize = 3;
+const MAX_BEATS: usize = 8;
+
+const MAX_TITLE_CHARS: usize = 120;
+
+/// Characters named per beat
+const MAX_BEAT_CHARACTERS: usize = 6;
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    beats: Vec<ReplyBeat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplyBeat {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    summary: String,
+    #[serde(default)]
+    characters: Vec<String>,
+    #[serde(default)]
+    location: Option<String>,
+}
+
+/// The schema of the reply, in the strict form providers require: every
+/// property listed as required and no others allowed
+fn reply_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "beats": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "title": { "type": "string" },
+                        "summary": { "type": "string" },
+                        "characters": { "type": "array", "items": { "type": "string" } },
+                        "location": { "type": ["string", "null"] },
+                    },
+                    "required": ["title", "summary", "characters", "location"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+        "required": ["beats"],
+        "additionalProperties": false,
+    })
+}
+
+/// The story's own beats and those already planned for other acts
+fn plan_text(export: &StoryExport, planned: &[Annotation], act: u32) -> String {
+    let mut text = String::new();
+    if !export.story_beats.is_empty() {
+        text.push_str("\nStory beats so far:\n");
+        for beat in &export.story_beats {
+            text.push_str(&format!("- [{}] {}\n", beat.status, beat.title.trim()));
+        }
+    }
+    let mut others: Vec<&PlannedBeat> = planned
+        .iter()
+        .filter_map(|a| a.beat.as_ref())
+        .filter(|beat| beat.act != act)
+        .collect();
+    others.sort_by_key(|beat| (beat.act, beat.order));
+    if !others.is_empty() {
+        text.push_str("\nBeats planned for other acts:\n");
+        for beat in others {
+            text.push_str(&format!("- Act {}: {}\n", beat.act, beat.title));
+        }
+    }
+    text
+}
+
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Check the model's beats, returning each with its summary. Beats without
+/// a title or summary are dropped, character names the story knows are
+/// written the way the story writes them, and the rest are numbered in
+/// order.
+fn validate(
+    export: &StoryExport,
+    act: u32,
+    beats: Vec<ReplyBeat>,
+) -> Result<Vec<(PlannedBeat, String)>, String> {
+    let known: Vec<&str> = export.characters.iter().map(|c| c.name.as_str()).collect();
+    let mut valid = Vec::new();
+    for beat in beats {
+        let title: String = clean(&beat.title).chars().take(MAX_TITLE_CHARS).collect();
+        let summary = clean(&beat.summary);
+        if title.is_empty() || summary.is_empty() {
+            continue;
+        }
+        let mut seen = HashSet::new();
+        let characters = beat
+            .characters
+            .iter()
+            .map(|name| clean(name))
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                known
+                    .iter()
+                    .find(|k| k.eq_ignore_ascii_case(&name))
+                    .map_or(name, |k| k.to_string())
+            })
+            .filter(|name| seen.insert(name.to_lowercase()))
+            .take(MAX_BEAT_CHARACTERS)
+            .collect();
+        let location = beat.location.map(|l| clean(&l)).filter(|l| !l.is_empty());
+        valid.push((
+            PlannedBeat {
+                act,
+                order: valid.len() as u32 + 1,
+                title,
+                characters,
+                location,
+            },
+            summary,
+        ));
+        if valid.len() == MAX_BEATS {
+            break;
+        }
+    }
+    if valid.is_empty() {
+        return Err("The outline contained no usable beats".to_string());
+    }
+    Ok(valid)
+}
+
+fn parse_reply(reply: &str) -> Result<Reply, String> {
+    let json = json_object(reply).ok_or("Outline response contained no JSON")?;
+    serde_json::from_str(json).map_err(|e| format!("Invalid outline response: {}", e))
+}
+
+/// Ask the model to outline an act as a sequence of scene beats that carry
+/// the story on from where it is, returning each beat with its summary
+pub async fn generate(
+    export: &StoryExport,
+    planned: &[Annotation],
+    act: u32,
+    provider: &ProviderConfig,
+) -> Result<Vec<(PlannedBeat, String)>, String> {
+    if act == 0 {
+        return Err("Acts are numbered from 1".to_string());
+    }
+    let (mut source, _) = source_text(export);
+    source.push_str(&plan_text(export, planned, act));
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: format!(
+                "You plan interactive fiction. Outline act {} of the story as {} to {} \
+                 scene beats in the order they happen. Reply with JSON only, in the form \
+                 {{\"beats\": [{{\"title\": string, \"summary\": string, \
+                 \"characters\": [string], \"location\": string or null}}]}}. A title is \
+                 a few words. A summary is one or two sentences on what happens and \
+                 what changes. Name the characters involved as the story names them, \
+                 and the location if the beat has one. Build on the story so far and \
+                 any beats already planned; do not repeat events that have happened.",
+                act, MIN_BEATS, MAX_BEATS
+            ),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: source,
+        },
+    ];
+    let sampling = SamplingParams {
+        temperature: Some(0.8),
+        ..Default::default()
+    };
+
+    let reply = complete_structured(
+        provider,
+        &messages,
+        &sampling,
+        "beat_outline",
+        &reply_schema(),
+    )
+    .await?;
+    validate(export, act, parse_reply(&reply)?.beats)
+}
@@ -0,0 +1,442 @@
+//! Local model benchmarks. A GGUF model is loaded into llama.cpp's
+//! `llama-server` once per context size; the load time, prompt and
+//! generation speed and the server's peak memory are measured on this
+//! machine. Results are cached per model file until it changes, so users
+//! can compare models and pick one their hardware runs at a usable speed.
+//! Progress is announced with `benchmark://progress`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex;
+
+use crate::hardware::HardwareState;
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Cached results keyed by model path, in the app data directory
+pub const LOCAL_BENCHMARKS_FILE: &str = "local_benchmarks.json";
+
+/// Runtime used when none is configured; looked up on PATH
+const DEFAULT_SERVER: &str = "llama-server";
+
+/// Longest wait for a model to load before giving up on a context size
+const LOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Longest wait for the measured completion
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How often the server's health and memory are checked while loading
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Share of the context filled with prompt, leaving room for the reply
+/// and for the rough token estimate being off
+const PROMPT_SHARE: f64 = 0.5;
+
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Generation speed below which a model is too slow to play with
+const MIN_VIABLE_TOKENS_PER_SEC: f64 = 5.0;
+
+const FILLER: &str = "The caravan wound through the pass as the light failed, the \
+    guards counting the torches and the merchants counting their coin. Somewhere \
+    above them a bell rang twice, and the old scout said nothing at all. ";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BenchmarkOptions {
+    /// Path to `llama-server`; found on PATH when unset
+    pub server_path: Option<String>,
+    pub context_sizes: Vec<u32>,
+    /// Tokens generated at each context size
+    pub generate_tokens: u32,
+    /// Layers offloaded to the GPU; the count recommended for this
+    /// machine's hardware when unset
+    pub gpu_layers: Option<i32>,
+    /// Measure again even if the cached result is still valid
+    pub rerun: bool,
+}
+
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        Self {
+            server_path: None,
+            context_sizes: vec![2048, 4096, 8192],
+            generate_tokens: 128,
+            gpu_layers: None,
+            rerun: false,
+        }
+    }
+}
+
+/// Measurements at one context size
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextRun {
+    pub context_size: u32,
+    pub load_ms: u64,
+    pub prompt_tokens: u64,
+    pub prompt_tokens_per_sec: f64,
+    pub generated_tokens: u64,
+    pub tokens_per_sec: f64,
+    /// Resident memory of the server at its highest; memory held on a GPU
+    /// is not included
+    pub peak_memory_bytes: Option<u64>,
+    /// The model failed to load or generate at this size
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalBenchmark {
+    pub model_path: String,
+    pub model_name: String,
+    pub model_size_bytes: u64,
+    /// Modification time of the model file; a newer file is benchmarked again
+    pub model_modified_at: i64,
+    pub benchmarked_at: i64,
+    pub gpu_layers: Option<i32>,
+    pub runs: Vec<ContextRun>,
+    /// Largest context size generating at a usable speed
+    pub largest_viable_context: Option<u32>,
+    pub viable: bool,
+}
+
+type LocalBenchmarks = HashMap<String, LocalBenchmark>;
+
+/// State managed by Tauri for local benchmarks
+#[derive(Default)]
+pub struct BenchmarkState {
+    /// Held while a benchmark runs; two at once would skew both
+    running: Mutex<()>,
+}
+
+/// Payload of the `benchmark://progress` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BenchmarkProgress<'a> {
+    model_path: &'a str,
+    context_size: u32,
+    /// "loading", "generating" or "done"
+    stage: &'a str,
+}
+
+/// Kills the server however the benchmark ends
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Resident memory of a process in bytes, from the platform's process list
+fn resident_memory(pid: u32) -> Option<u64> {
+    if cfg!(windows) {
+        // "name","pid","session","#","12,345 K"
+        let output = Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+            .output()
+            .ok()?;
+        let text = String::from_utf8_lossy(&output.stdout);
+        let memory = text.trim().rsplit("\",\"").next()?;
+        let kb: String = memory.chars().filter(char::is_ascii_digit).collect();
+        kb.parse::<u64>().ok().map(|kb| kb * 1024)
+    } else {
+        let output = Command::new("ps")
+            .args(["-o", "rss=", "-p", &pid.to_string()])
+            .output()
+            .ok()?;
+        let kb = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(kb * 1024)
+    }
+}
+
+fn free_port() -> Result<u16, String> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .map_err(|e| format!("Failed to find a free port: {}", e))
+}
+
+fn spawn_server(
+    model_path: &str,
+    context_size: u32,
+    port: u16,
+    options: &BenchmarkOptions,
+) -> Result<Server, String> {
+    let program = options
+        .server_path
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or(DEFAULT_SERVER);
+    let mut command = Command::new(program);
+    command
+        .arg("-m")
+        .arg(model_path)
+        .args(["-c", &context_size.to_string()])
+        .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if let Some(layers) = options.gpu_layers {
+        command.args(["-ngl", &layers.to_string()]);
+    }
+    command.spawn().map(Server).map_err(|e| {
+        format!(
+            "Benchmarks require llama.cpp's {} on PATH or a server path: {}",
+            DEFAULT_SERVER, e
+        )
+    })
+}
+
+/// Wait until the server reports the model loaded, tracking its memory
+async fn wait_for_load(
+    client: &reqwest::Client,
+    server: &mut Server,
+    port: u16,
+    peak: &mut Option<u64>,
+) -> Result<(), String> {
+    let started = Instant::now();
+    let url = format!("http://127.0.0.1:{}/health", port);
+    loop {
+        if let Some(status) = server.0.try_wait().ok().flatten() {
+            return Err(format!(
+                "The runtime exited while loading the model: {}",
+                status
+            ));
+        }
+        *peak = (*peak).max(resident_memory(server.0.id()));
+        let healthy = client
+            .get(&url)
+            .timeout(POLL_INTERVAL * 4)
+            .send()
+            .await
+            .is_ok_and(|r| r.status().is_success());
+        if healthy {
+            return Ok(());
+        }
+        if started.elapsed() > LOAD_TIMEOUT {
+            return Err(format!(
+                "The model did not load within {} seconds",
+                LOAD_TIMEOUT.as_secs()
+            ));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// A prompt of roughly `tokens` tokens
+fn filler_prompt(tokens: usize) -> String {
+    let chars = tokens * CHARS_PER_TOKEN;
+    FILLER.chars().cycle().take(chars).collect()
+}
+
+fn number(timings: Option<&Value>, key: &str) -> Option<f64> {
+    timings?.get(key)?.as_f64()
+}
+
+/// Fill part of the context and generate, reading the speeds from the
+/// server's own timings, or the wall clock if it gives none
+async fn generate(
+    client: &reqwest::Client,
+    port: u16,
+    context_size: u32,
+    options: &BenchmarkOptions,
+    run: &mut ContextRun,
+) -> Result<(), String> {
+    let prompt_tokens = (f64::from(context_size) * PROMPT_SHARE) as usize;
+    let started = Instant::now();
+    let response = client
+        .post(format!("http://127.0.0.1:{}/completion", port))
+        .json(&json!({
+            "prompt": filler_prompt(prompt_tokens),
+            "n_predict": options.generate_tokens,
+            "cache_prompt": false,
+            "ignore_eos": true,
+        }))
+        .timeout(GENERATION_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("Generation failed: {}", e))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!("Runtime returned {}: {}", status, text));
+    }
+    let value: Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid runtime response: {}", e))?;
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let timings = value.get("timings");
+    run.prompt_tokens = number(timings, "prompt_n").unwrap_or(prompt_tokens as f64) as u64;
+    run.prompt_tokens_per_sec = number(timings, "prompt_per_second").unwrap_or(0.0);
+    run.generated_tokens = number(timings, "predicted_n")
+        .or_else(|| value.get("tokens_predicted").and_then(Value::as_f64))
+        .unwrap_or(0.0) as u64;
+    run.tokens_per_sec = number(timings, "predicted_per_second")
+        .unwrap_or_else(|| run.generated_tokens as f64 / elapsed.max(f64::EPSILON));
+    Ok(())
+}
+
+async fn measure(
+    app: &AppHandle,
+    client: &reqwest::Client,
+    model_path: &str,
+    context_size: u32,
+    options: &BenchmarkOptions,
+) -> ContextRun {
+    let mut run = ContextRun {
+        context_size,
+        ..Default::default()
+    };
+    let progress = |stage| {
+        let _ = app.emit(
+            "benchmark://progress",
+            BenchmarkProgress {
+                model_path,
+                context_size,
+                stage,
+            },
+        );
+    };
+
+    progress("loading");
+    let mut peak = None;
+    let result = async {
+        let port = free_port()?;
+        let mut server = spawn_server(model_path, context_size, port, options)?;
+        let started = Instant::now();
+        wait_for_load(client, &mut server, port, &mut peak).await?;
+        run.load_ms = started.elapsed().as_millis() as u64;
+
+        progress("generating");
+        generate(client, port, context_size, options, &mut run).await?;
+        peak = peak.max(resident_memory(server.0.id()));
+        Ok::<_, String>(())
+    }
+    .await;
+    run.peak_memory_bytes = peak;
+    run.error = result.err();
+    progress("done");
+    run
+}
+
+/// Size and modification time of the model, which tell a cached result
+/// apart from a replaced file
+fn model_file(model_path: &str) -> Result<(u64, i64), String> {
+    let metadata = std::fs::metadata(model_path)
+        .map_err(|e| format!("Cannot read model {}: {}", model_path, e))?;
+    if !metadata.is_file() {
+        return Err(format!("Not a model file: {}", model_path));
+    }
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as i64);
+    Ok((metadata.len(), modified))
+}
+
+/// Benchmark a model across the context sizes, or return the cached result
+/// while the model file is unchanged
+pub async fn run(
+    app: &AppHandle,
+    state: &BenchmarkState,
+    model_path: &str,
+    mut options: BenchmarkOptions,
+) -> Result<LocalBenchmark, String> {
+    let (size, modified) = model_file(model_path)?;
+    if options.gpu_layers.is_none() {
+        let hardware = app.state::<HardwareState>().capabilities(false).await?;
+        options.gpu_layers = hardware.recommended.gpu_layers;
+    }
+    let mut cached: LocalBenchmarks = store::load_json(app, LOCAL_BENCHMARKS_FILE)?;
+    if let Some(result) = cached.get(model_path).filter(|r| {
+        !options.rerun
+            && r.model_size_bytes == size
+            && r.model_modified_at == modified
+            && r.gpu_layers == options.gpu_layers
+    }) {
+        return Ok(result.clone());
+    }
+    let mut sizes = options.context_sizes.clone();
+    sizes.retain(|s| *s >= 256);
+    sizes.sort_unstable();
+    sizes.dedup();
+    if sizes.is_empty() || options.generate_tokens == 0 {
+        return Err("Give at least one context size of 256 tokens or more".to_string());
+    }
+
+    let _running = state
+        .running
+        .try_lock()
+        .map_err(|_| "A benchmark is already running".to_string())?;
+    let client = reqwest::Client::new();
+    let mut runs = Vec::with_capacity(sizes.len());
+    for context_size in sizes {
+        let run = measure(app, &client, model_path, context_size, &options).await;
+        // A model that can't load a smaller context won't load a larger one
+        let stop = run.error.is_some() && run.load_ms == 0;
+        runs.push(run);
+        if stop {
+            break;
+        }
+    }
+    if runs.iter().all(|r| r.error.is_some()) {
+        return Err(runs
+            .into_iter()
+            .find_map(|r| r.error)
+            .unwrap_or_else(|| "The benchmark failed".to_string()));
+    }
+
+    let largest_viable_context = runs
+        .iter()
+        .filter(|r| r.error.is_none() && r.tokens_per_sec >= MIN_VIABLE_TOKENS_PER_SEC)
+        .map(|r| r.context_size)
+        .max();
+    let result = LocalBenchmark {
+        model_path: model_path.to_string(),
+        model_name: Path::new(model_path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| model_path.to_string()),
+        model_size_bytes: size,
+        model_modified_at: modified,
+        benchmarked_at: now_ms(),
+        gpu_layers: options.gpu_layers,
+        runs,
+        largest_viable_context,
+        viable: largest_viable_context.is_some(),
+    };
+    cached = store::load_json(app, LOCAL_BENCHMARKS_FILE)?;
+    cached.insert(model_path.to_string(), result.clone());
+    store::save_json(app, LOCAL_BENCHMARKS_FILE, &cached)?;
+    Ok(result)
+}
+
+/// Cached results, fastest generation first, so the best fit for this
+/// machine is at the top
+pub fn list(app: &AppHandle) -> Result<Vec<LocalBenchmark>, String> {
+    let cached: LocalBenchmarks = store::load_json(app, LOCAL_BENCHMARKS_FILE)?;
+    let mut results: Vec<LocalBenchmark> = cached.into_values().collect();
+    let best = |r: &LocalBenchmark| {
+        r.runs
+            .iter()
+            .filter(|run| run.error.is_none())
+            .map(|run| run.tokens_per_sec)
+            .fold(0.0, f64::max)
+    };
+    results.sort_by(|a, b| best(b).total_cmp(&best(a)));
+    Ok(results)
+}
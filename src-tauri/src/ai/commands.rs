@@ -0,0 +1,725 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::Mutex;
+
+use super::beats;
+use super::benchmark::{self, BenchmarkOptions, BenchmarkState, LocalBenchmark};
+use super::context::{self as story_context, ContextPreview};
+use super::experiment::{
+    self, ExperimentInput, ExperimentOptions, PromptExperiment, PromptTemplate,
+    PROMPT_EXPERIMENTS_FILE,
+};
+use super::filter::{
+    self, classify, CompiledFilter, FilterConfig, FilterResult, FilterRule, StreamFilter,
+    Strictness,
+};
+use super::metadata::{self, MetadataSuggestions};
+use super::overflow;
+use super::postprocess::{
+    self, PostProcessConfig, PostProcessResult, PostProcessor, POSTPROCESS_CONFIG_FILE,
+};
+use super::profile::{
+    delete_profile, import_profiles, load_profile, load_profiles, save_profile, story_provider,
+    AiProfile, AiProfileExport, ContextStrategy,
+};
+use super::proxy::{self, stream_chat};
+use super::sampling::{
+    self, SamplingPreset, SamplingPresets, SamplingTranslation, SAMPLING_PRESETS_FILE,
+};
+use super::trace::{self, PendingTrace, TraceConfig, TraceRange, AI_TRACE_CONFIG_FILE};
+use super::translate::{translate_story, TranslationResult};
+use super::types::{AiDoneEvent, AiStatusEvent, AiStreamRequest, ProviderConfig, SamplingParams};
+use crate::annotations::{self, Annotation};
+use crate::game::{context, quests, recaps};
+use crate::profiles;
+use crate::store;
+use crate::story::rows;
+use crate::style::{self, StyleLibrary, STYLE_REFERENCES_FILE};
+use crate::sync::keys::now_ms;
+use crate::tables;
+
+/// Number of recent requests kept around so they can be regenerated
+const MAX_CACHED_REQUESTS: usize = 32;
+
+/// A streaming task tagged with the generation that started it
+struct InFlightStream {
+    generation: u64,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+/// Recently sent requests, evicted oldest-first
+#[derive(Default)]
+struct RequestCache {
+    order: VecDeque<String>,
+    requests: HashMap<String, AiStreamRequest>,
+}
+
+impl RequestCache {
+    fn insert(&mut self, request_id: String, request: AiStreamRequest) {
+        if self.requests.insert(request_id.clone(), request).is_none() {
+            self.order.push_back(request_id);
+        }
+        while self.order.len() > MAX_CACHED_REQUESTS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.requests.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// State managed by Tauri for AI proxy operations
+pub struct AiState {
+    /// Streams currently in flight, keyed by request ID
+    streams: Arc<Mutex<HashMap<String, InFlightStream>>>,
+    /// Prompts of recent requests, for regeneration
+    cache: Arc<Mutex<RequestCache>>,
+    /// Incremented for every stream started, so a finished task never
+    /// unregisters a newer stream that reused its request ID
+    next_generation: Arc<Mutex<u64>>,
+}
+
+impl Default for AiState {
+    fn default() -> Self {
+        Self {
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            cache: Arc::new(Mutex::new(RequestCache::default())),
+            next_generation: Arc::new(Mutex::new(0)),
+        }
+    }
+}
+
+impl AiState {
+    /// Abort every stream and forget cached requests, when the profile changes
+    pub(crate) async fn reset(&self) {
+        for (_, stream) in self.streams.lock().await.drain() {
+            stream.handle.abort();
+        }
+        *self.cache.lock().await = RequestCache::default();
+    }
+
+    /// Abort the stream for a request ID, if one is running
+    async fn abort_stream(&self, request_id: &str) -> bool {
+        if let Some(stream) = self.streams.lock().await.remove(request_id) {
+            stream.handle.abort();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Spawn a streaming task for a request, replacing any stream with the same ID
+    async fn start_stream(
+        &self,
+        app: AppHandle,
+        request_id: String,
+        mut request: AiStreamRequest,
+    ) -> Result<(), String> {
+        let filter_config: FilterConfig = filter::load_config(&app)?;
+        tables::expand_messages(&app, &mut request.messages)?;
+        if let Some(story_id) = request.story_id.as_deref() {
+            profiles::check_story(&app, story_id).await?;
+            story_context::apply(&app, story_id, &mut request.messages).await?;
+            recaps::touch(&app, story_id).await?;
+            context::inject_first(
+                &mut request.messages,
+                &recaps::context_text(&app, story_id)?,
+            );
+            let block = context::request_stat_block(&app, story_id).await?;
+            context::inject(&mut request.messages, &block);
+            let reminder = quests::reminder(&app, story_id).await?;
+            context::inject(&mut request.messages, &reminder);
+            context::inject(
+                &mut request.messages,
+                &style::context_text(&app, story_id).await?,
+            );
+            if let Some(preset) = sampling::story_sampling(&app, story_id).await? {
+                request.sampling = preset.merged_with(&request.sampling);
+            }
+        }
+        let postprocess_config: PostProcessConfig =
+            store::load_json(&app, POSTPROCESS_CONFIG_FILE)?;
+        let mut overflow_steps = match request.story_id.as_deref() {
+            Some(story_id) => overflow::story_steps(&app, story_id).await?,
+            None => Vec::new(),
+        };
+        let strictness = profiles::check_generation(
+            &app,
+            &request.provider.base_url,
+            filter_config.strictness_for(request.story_id.as_deref()),
+        )?;
+        let compiled = CompiledFilter::new(&filter_config.rules, strictness)?;
+        let classifier = filter_config
+            .classifier
+            .filter(|c| strictness != Strictness::Off && c.min_strictness <= strictness);
+        // A reply the classifier may still block, or the chain may still
+        // change, must not be shown first
+        let hold = classifier.is_some() || !postprocess_config.chain.is_empty();
+        let mut trace = request
+            .story_id
+            .as_deref()
+            .filter(|_| trace::enabled(&app))
+            .map(|story_id| PendingTrace::new(story_id, &request_id, &request));
+
+        let generation = {
+            let mut next = self.next_generation.lock().await;
+            *next += 1;
+            *next
+        };
+
+        // Hold the lock until the new handle is registered so a task that
+        // finishes immediately cannot race its own registration
+        let mut in_flight = self.streams.lock().await;
+        if let Some(previous) = in_flight.remove(&request_id) {
+            previous.handle.abort();
+        }
+
+        let streams = self.streams.clone();
+        let id = request_id.clone();
+        let handle = tokio::spawn(async move {
+            let mut attempt = 0;
+            let streamed = loop {
+                let filter = StreamFilter::new(compiled.clone());
+                let raw = trace.as_mut().map(|t| &mut t.response);
+                let result = stream_chat(&app, &id, &request, filter, hold, raw).await;
+                let Err(ref error) = result else {
+                    break result;
+                };
+                attempt += 1;
+                if !overflow::retry(&app, &id, &mut request, &mut overflow_steps, attempt, error)
+                    .await
+                {
+                    break result;
+                }
+                // Each attempt is its own call in the trace
+                if let (Some(pending), Some(story_id)) =
+                    (trace.as_mut(), request.story_id.as_deref())
+                {
+                    let failed =
+                        std::mem::replace(pending, PendingTrace::new(story_id, &id, &request));
+                    if let Err(e) = trace::record(&app, failed, &result) {
+                        eprintln!("Failed to record AI trace: {}", e);
+                    }
+                }
+            };
+            let mut post_processors = Vec::new();
+            let streamed = match streamed {
+                Ok(mut outcome) => {
+                    postprocess::continue_cut_off(
+                        &app,
+                        &id,
+                        &request,
+                        &compiled,
+                        &postprocess_config,
+                        hold,
+                        &mut outcome,
+                    )
+                    .await;
+                    let processed = postprocess::apply(&postprocess_config.chain, &outcome.content);
+                    outcome.content = processed.text;
+                    post_processors = processed.applied;
+                    Ok(outcome)
+                }
+                Err(e) => Err(e),
+            };
+            let result = match streamed {
+                Ok(outcome) => match classifier {
+                    Some(ref config) => match classify(&app, config, &outcome.content).await {
+                        Ok(false) => Ok(outcome),
+                        Ok(true) => Err("Generation blocked by content classifier".to_string()),
+                        Err(e) => Err(e),
+                    },
+                    None => Ok(outcome),
+                },
+                Err(e) => Err(e),
+            };
+            if let Some(trace) = trace {
+                if let Err(e) = trace::record(&app, trace, &result) {
+                    eprintln!("Failed to record AI trace: {}", e);
+                }
+            }
+
+            match result {
+                Ok(outcome) => {
+                    if hold && !outcome.content.is_empty() {
+                        proxy::emit_chunk(&app, &id, outcome.content.clone());
+                    }
+                    let _ = app.emit(
+                        "ai://done",
+                        AiDoneEvent {
+                            request_id: id.clone(),
+                            content: outcome.content,
+                            finish_reason: outcome.finish_reason,
+                            post_processors,
+                        },
+                    );
+                }
+                Err(message) => {
+                    let _ = app.emit(
+                        "ai://error",
+                        AiStatusEvent {
+                            request_id: id.clone(),
+                            message,
+                        },
+                    );
+                }
+            }
+
+            let mut streams = streams.lock().await;
+            if streams.get(&id).map(|s| s.generation) == Some(generation) {
+                streams.remove(&id);
+            }
+        });
+
+        in_flight.insert(request_id, InFlightStream { generation, handle });
+        Ok(())
+    }
+}
+
+/// Start streaming a chat completion through the backend.
+/// Deltas arrive as `ai://chunk` events, followed by `ai://done` or `ai://error`.
+/// While a content classifier or post-processing chain applies, the reply
+/// arrives as one chunk once it has been checked and processed.
+#[tauri::command]
+pub async fn ai_stream(
+    app: AppHandle,
+    state: State<'_, AiState>,
+    request_id: String,
+    request: AiStreamRequest,
+) -> Result<(), String> {
+    state
+        .cache
+        .lock()
+        .await
+        .insert(request_id.clone(), request.clone());
+    state.start_stream(app, request_id, request).await
+}
+
+/// Abort an in-flight stream, closing the connection to the provider.
+/// Returns false if the request had already finished.
+#[tauri::command]
+pub async fn ai_cancel(
+    app: AppHandle,
+    state: State<'_, AiState>,
+    request_id: String,
+) -> Result<bool, String> {
+    let aborted = state.abort_stream(&request_id).await;
+    if aborted {
+        let _ = app.emit(
+            "ai://cancelled",
+            AiStatusEvent {
+                request_id,
+                message: "Generation cancelled".to_string(),
+            },
+        );
+    }
+    Ok(aborted)
+}
+
+/// Replay a cached request with modified sampling parameters.
+/// Any stream still running for the request is aborted first.
+#[tauri::command]
+pub async fn ai_regenerate(
+    app: AppHandle,
+    state: State<'_, AiState>,
+    request_id: String,
+    variation_params: SamplingParams,
+) -> Result<(), String> {
+    let mut request = state
+        .cache
+        .lock()
+        .await
+        .requests
+        .get(&request_id)
+        .cloned()
+        .ok_or_else(|| format!("No cached request to regenerate: {}", request_id))?;
+
+    request.sampling = request.sampling.merged_with(&variation_params);
+    state.start_stream(app, request_id, request).await
+}
+
+/// Get the content filter configuration
+#[tauri::command]
+pub async fn get_filter_config(app: AppHandle) -> Result<FilterConfig, String> {
+    filter::load_config(&app)
+}
+
+/// Save the content filter configuration after validating every rule
+#[tauri::command]
+pub async fn set_filter_config(app: AppHandle, config: FilterConfig) -> Result<(), String> {
+    CompiledFilter::new(&config.rules, Strictness::High)?;
+    filter::save_config(&app, config)
+}
+
+/// Set the filter strictness for a single story.
+/// Passing no strictness removes the override so the default applies.
+#[tauri::command]
+pub async fn set_story_filter_strictness(
+    app: AppHandle,
+    story_id: String,
+    strictness: Option<Strictness>,
+) -> Result<(), String> {
+    let mut config: FilterConfig = filter::load_config(&app)?;
+    match strictness {
+        Some(level) => {
+            config.story_strictness.insert(story_id, level);
+        }
+        None => {
+            config.story_strictness.remove(&story_id);
+        }
+    }
+    filter::save_config(&app, config)
+}
+
+/// Run text through the filter without generating anything.
+/// Draft rules can be passed to try them out before saving.
+#[tauri::command]
+pub async fn test_filter(
+    app: AppHandle,
+    text: String,
+    strictness: Option<Strictness>,
+    rules: Option<Vec<FilterRule>>,
+) -> Result<FilterResult, String> {
+    let config: FilterConfig = filter::load_config(&app)?;
+    let strictness = strictness.unwrap_or(config.default_strictness);
+    let rules = rules.unwrap_or(config.rules);
+    Ok(CompiledFilter::new(&rules, strictness)?.apply(&text))
+}
+
+/// Get the chain replies are post-processed with
+#[tauri::command]
+pub async fn get_postprocess_config(app: AppHandle) -> Result<PostProcessConfig, String> {
+    store::load_json(&app, POSTPROCESS_CONFIG_FILE)
+}
+
+#[tauri::command]
+pub async fn set_postprocess_config(
+    app: AppHandle,
+    config: PostProcessConfig,
+) -> Result<(), String> {
+    config.validate()?;
+    store::save_json(&app, POSTPROCESS_CONFIG_FILE, &config)
+}
+
+/// Run text through the post-processing chain without generating anything.
+/// A draft chain can be passed to try it out before saving.
+#[tauri::command]
+pub async fn test_postprocess(
+    app: AppHandle,
+    text: String,
+    chain: Option<Vec<PostProcessor>>,
+) -> Result<PostProcessResult, String> {
+    let config: PostProcessConfig = store::load_json(&app, POSTPROCESS_CONFIG_FILE)?;
+    Ok(postprocess::apply(&chain.unwrap_or(config.chain), &text))
+}
+
+/// Translate a saved story's entries into another language via an AI
+/// provider, producing a new story variant in Aventura export format that
+/// names its source
+#[tauri::command]
+pub async fn translate_entries(
+    app: AppHandle,
+    story_id: String,
+    target_language: String,
+    provider: ProviderConfig,
+) -> Result<TranslationResult, String> {
+    profiles::check_story(&app, &story_id).await?;
+    profiles::check_generation(&app, &provider.base_url, Strictness::Off)?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    translate_story(&app, export, &target_language, &provider).await
+}
+
+/// Ask the story's AI profile provider to propose titles, a synopsis, a
+/// genre, tags and content warnings from the story text
+#[tauri::command]
+pub async fn suggest_metadata(
+    app: AppHandle,
+    story_id: String,
+) -> Result<MetadataSuggestions, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let provider = story_provider(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    metadata::suggest(&export, &provider).await
+}
+
+/// Ask the story's AI profile provider to outline an act as scene beats,
+/// saved as beat annotations on the story. Unresolved beats already
+/// planned for the act are replaced.
+#[tauri::command]
+pub async fn generate_beats(
+    app: AppHandle,
+    story_id: String,
+    act: u32,
+) -> Result<Vec<Annotation>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let provider = story_provider(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let planned = annotations::list(&app, &story_id, None, true)?;
+    let beats = beats::generate(&export, &planned, act, &provider).await?;
+    annotations::replace_beats(&app, &story_id, act, beats)
+}
+
+/// Get the AI profile for a story, if one has been saved
+#[tauri::command]
+pub async fn get_ai_profile(app: AppHandle, story_id: String) -> Result<Option<AiProfile>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    load_profile(&app, &story_id).await
+}
+
+/// List the saved AI profiles of stories the current user profile sees
+#[tauri::command]
+pub async fn list_ai_profiles(app: AppHandle) -> Result<Vec<AiProfile>, String> {
+    let mut ai_profiles = load_profiles(&app).await?;
+    let visible = profiles::visible_stories(&app, ai_profiles.keys().cloned().collect()).await?;
+    let mut list: Vec<AiProfile> = visible
+        .iter()
+        .filter_map(|id| ai_profiles.remove(id))
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(list)
+}
+
+/// Create or replace the AI profile for a story. A key given with the
+/// provider goes to the OS keychain; without one the stored key is kept.
+#[tauri::command]
+pub async fn save_ai_profile(app: AppHandle, mut profile: AiProfile) -> Result<AiProfile, String> {
+    profile.validate()?;
+    profiles::check_story(&app, &profile.story_id).await?;
+    if let Some(name) = profile.sampling_preset.as_deref() {
+        let presets: SamplingPresets = store::load_json(&app, SAMPLING_PRESETS_FILE)?;
+        if !presets.contains_key(name) {
+            return Err(format!("Sampling preset '{}' does not exist", name));
+        }
+    }
+    if let Some(id) = profile.style_reference.as_deref() {
+        let library: StyleLibrary = store::load_json(&app, STYLE_REFERENCES_FILE)?;
+        if library.get(id).is_none() {
+            return Err(format!("Style reference not found: {}", id));
+        }
+    }
+    profile.updated_at = now_ms();
+    save_profile(&app, profile).await
+}
+
+/// Delete the AI profile for a story. Returns false if there was none.
+#[tauri::command]
+pub async fn delete_ai_profile(app: AppHandle, story_id: String) -> Result<bool, String> {
+    profiles::check_story(&app, &story_id).await?;
+    delete_profile(&app, &story_id).await
+}
+
+/// Export profiles as JSON without API keys. Exports the profile of every
+/// story the current user profile sees when no story IDs are given.
+#[tauri::command]
+pub async fn export_ai_profiles(
+    app: AppHandle,
+    story_ids: Option<Vec<String>>,
+) -> Result<String, String> {
+    let ai_profiles = load_profiles(&app).await?;
+    let ids = story_ids.unwrap_or_else(|| ai_profiles.keys().cloned().collect());
+    let visible = profiles::visible_stories(&app, ids).await?;
+    let selected: Vec<&AiProfile> = visible
+        .iter()
+        .filter_map(|id| ai_profiles.get(id))
+        .collect();
+    serde_json::to_string_pretty(&AiProfileExport::new(selected))
+        .map_err(|e| format!("Failed to serialize profiles: {}", e))
+}
+
+/// Import profiles from an export, returning how many were saved.
+/// Existing profiles are only replaced when `overwrite` is set, and
+/// profiles of stories the current user profile does not see are skipped.
+#[tauri::command]
+pub async fn import_ai_profiles(
+    app: AppHandle,
+    profiles_json: String,
+    overwrite: bool,
+) -> Result<usize, String> {
+    let export: AiProfileExport = serde_json::from_str(&profiles_json)
+        .map_err(|e| format!("Invalid profile export: {}", e))?;
+    for profile in &export.profiles {
+        profile.validate()?;
+    }
+    let ids = export.profiles.iter().map(|p| p.story_id.clone()).collect();
+    let visible = profiles::visible_stories(&app, ids).await?;
+    let imported = export
+        .profiles
+        .into_iter()
+        .filter(|p| visible.contains(&p.story_id))
+        .collect();
+    import_profiles(&app, imported, overwrite).await
+}
+
+#[tauri::command]
+pub async fn get_ai_trace_config(app: AppHandle) -> Result<TraceConfig, String> {
+    store::load_json(&app, AI_TRACE_CONFIG_FILE)
+}
+
+/// Turn recording of story generations on or off and set what exports
+/// redact. Turning it off keeps the calls already recorded.
+#[tauri::command]
+pub async fn set_ai_trace_config(app: AppHandle, config: TraceConfig) -> Result<(), String> {
+    if config.max_records == 0 {
+        return Err("Keep at least one call per story".to_string());
+    }
+    store::save_json(&app, AI_TRACE_CONFIG_FILE, &config)
+}
+
+/// The story's recorded AI calls as redacted JSON, optionally only those
+/// started within a time range
+#[tauri::command]
+pub async fn export_ai_trace(
+    app: AppHandle,
+    story_id: String,
+    range: Option<TraceRange>,
+) -> Result<String, String> {
+    profiles::check_story(&app, &story_id).await?;
+    trace::export(&app, &story_id, range.unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn clear_ai_trace(app: AppHandle, story_id: String) -> Result<bool, String> {
+    profiles::check_story(&app, &story_id).await?;
+    trace::clear(&app, &story_id)
+}
+
+/// Run two prompt templates over the same sample inputs and keep their
+/// outputs side by side with length, keyword, latency and cost metrics.
+/// Progress is reported with `experiment://progress` events.
+#[tauri::command]
+pub async fn run_prompt_experiment(
+    app: AppHandle,
+    template_a: PromptTemplate,
+    template_b: PromptTemplate,
+    inputs: Vec<ExperimentInput>,
+    provider: ProviderConfig,
+    options: Option<ExperimentOptions>,
+) -> Result<PromptExperiment, String> {
+    profiles::check_generation(&app, &provider.base_url, Strictness::Off)?;
+    experiment::run(
+        &app,
+        template_a,
+        template_b,
+        inputs,
+        &provider,
+        options.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Kept experiments, newest first
+#[tauri::command]
+pub async fn list_prompt_experiments(app: AppHandle) -> Result<Vec<PromptExperiment>, String> {
+    let mut experiments: Vec<PromptExperiment> = store::load_json(&app, PROMPT_EXPERIMENTS_FILE)?;
+    experiments.reverse();
+    Ok(experiments)
+}
+
+#[tauri::command]
+pub async fn delete_prompt_experiment(app: AppHandle, id: String) -> Result<bool, String> {
+    let mut experiments: Vec<PromptExperiment> = store::load_json(&app, PROMPT_EXPERIMENTS_FILE)?;
+    let before = experiments.len();
+    experiments.retain(|e| e.id != id);
+    if experiments.len() == before {
+        return Ok(false);
+    }
+    store::save_json(&app, PROMPT_EXPERIMENTS_FILE, &experiments)?;
+    Ok(true)
+}
+
+/// Measure load time, speed and memory of a local GGUF model on this
+/// machine at a few context sizes, using llama.cpp's server. A cached
+/// result is returned while the model file is unchanged, unless a rerun is
+/// asked for. Progress is reported with `benchmark://progress` events.
+#[tauri::command]
+pub async fn benchmark_local_model(
+    app: AppHandle,
+    state: State<'_, BenchmarkState>,
+    model_path: String,
+    options: Option<BenchmarkOptions>,
+) -> Result<LocalBenchmark, String> {
+    benchmark::run(&app, &state, &model_path, options.unwrap_or_default()).await
+}
+
+/// Benchmarked models, fastest first
+#[tauri::command]
+pub async fn list_local_benchmarks(app: AppHandle) -> Result<Vec<LocalBenchmark>, String> {
+    benchmark::list(&app)
+}
+
+/// The messages a request for the story would carry under a context
+/// strategy, the profile's own when none is given, with what was included
+#[tauri::command]
+pub async fn preview_context(
+    app: AppHandle,
+    story_id: String,
+    strategy: Option<ContextStrategy>,
+) -> Result<ContextPreview, String> {
+    story_context::preview(&app, &story_id, strategy).await
+}
+
+/// Saved sampling presets, by name
+#[tauri::command]
+pub async fn list_sampling_presets(app: AppHandle) -> Result<Vec<SamplingPreset>, String> {
+    let presets: SamplingPresets = store::load_json(&app, SAMPLING_PRESETS_FILE)?;
+    let mut list: Vec<SamplingPreset> = presets.into_values().collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(list)
+}
+
+/// Create or replace the sampling preset with the same name
+#[tauri::command]
+pub async fn save_sampling_preset(
+    app: AppHandle,
+    mut preset: SamplingPreset,
+) -> Result<SamplingPreset, String> {
+    preset.name = preset.name.trim().to_string();
+    preset.validate()?;
+    preset.updated_at = now_ms();
+    let mut presets: SamplingPresets = store::load_json(&app, SAMPLING_PRESETS_FILE)?;
+    presets.insert(preset.name.clone(), preset.clone());
+    store::save_json(&app, SAMPLING_PRESETS_FILE, &presets)?;
+    Ok(preset)
+}
+
+/// Delete a sampling preset no AI profile uses. Returns false if there was
+/// none.
+#[tauri::command]
+pub async fn delete_sampling_preset(app: AppHandle, name: String) -> Result<bool, String> {
+    let profiles = load_profiles(&app).await?;
+    let users: Vec<&str> = profiles
+        .values()
+        .filter(|p| p.sampling_preset.as_deref() == Some(name.as_str()))
+        .map(|p| p.name.as_str())
+        .collect();
+    if !users.is_empty() {
+        return Err(format!(
+            "Sampling preset '{}' is used by {}",
+            name,
+            users.join(", ")
+        ));
+    }
+    let mut presets: SamplingPresets = store::load_json(&app, SAMPLING_PRESETS_FILE)?;
+    let removed = presets.remove(&name).is_some();
+    if removed {
+        store::save_json(&app, SAMPLING_PRESETS_FILE, &presets)?;
+    }
+    Ok(removed)
+}
+
+/// The request fields sampling becomes for a provider, and what it cannot
+/// take
+#[tauri::command]
+pub async fn translate_sampling(
+    base_url: String,
+    sampling: SamplingParams,
+) -> Result<SamplingTranslation, String> {
+    sampling.validate()?;
+    Ok(sampling::translate(&base_url, &sampling))
+}
@@ -0,0 +1,567 @@
+//! Context assembly: turns a story into the messages sent with a request,
+//! following the AI profile's context strategy. Streamed requests naming a
+//! story have their conversation rebuilt this way. Entries are read along
+//! the story's current branch, so a branch sees its parent's entries up to
+//! where it forked and none of its siblings'.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tauri::AppHandle;
+
+use super::profile::{self, AiProfile, ContextStrategy};
+use super::types::ChatMessage;
+use crate::game::context::{self as game_context, estimate_tokens};
+use crate::game::{quests, recaps};
+use crate::profiles;
+use crate::story::rows;
+use crate::story::text::plain_text;
+use crate::story::types::{StoryEntry, StoryExport};
+use crate::style;
+use crate::tables;
+
+/// Recent entries whose words make up the retrieval query
+const QUERY_ENTRIES: usize = 2;
+
+/// Words too common to tell passages apart
+const STOPWORDS: [&str; 40] = [
+    "the", "and", "you", "your", "that", "with", "for", "was", "are", "but", "not", "his", "her",
+    "she", "him", "they", "them", "their", "this", "there", "then", "than", "have", "has", "had",
+    "from", "into", "what", "when", "where", "who", "will", "would", "could", "should", "about",
+    "were", "been", "its", "all",
+];
+
+/// What a request for the story would carry
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextPreview {
+    pub strategy: ContextStrategy,
+    pub messages: Vec<ChatMessage>,
+    /// Entries on the current branch
+    pub total_entries: usize,
+    /// Entries sent as messages
+    pub included_entries: usize,
+    /// Older entries brought back by retrieval, oldest first
+    pub retrieved_entry_ids: Vec<String>,
+    /// Chapter summaries standing in for older entries
+    pub summarized_chapters: usize,
+    pub estimated_tokens: usize,
+}
+
+/// Entries visible on the story's current branch, in story order: the
+/// branch's own entries after its parent's, up to each fork
+pub fn branch_entries(export: &StoryExport) -> Vec<&StoryEntry> {
+    let branches: HashMap<&str, _> = export.branches.iter().map(|b| (b.id.as_str(), b)).collect();
+    let position: HashMap<&str, i64> = export
+        .entries
+        .iter()
+        .map(|e| (e.id.as_str(), e.position))
+        .collect();
+
+    // From the current branch up to the main line, with the last position
+    // each ancestor contributes
+    let mut lineage: Vec<(Option<&str>, i64)> = Vec::new();
+    let mut branch = export
+        .story
+        .extra
+        .get("currentBranchId")
+        .and_then(|v| v.as_str());
+    let mut limit = i64::MAX;
+    while let Some(id) = branch {
+        if lineage.iter().any(|(b, _)| *b == Some(id)) || lineage.len() > branches.len() {
+            break;
+        }
+        lineage.push((Some(id), limit));
+        let Some(info) = branches.get(id) else {
+            break;
+        };
+        limit = position
+            .get(info.fork_entry_id.as_str())
+            .copied()
+            .unwrap_or(limit);
+        branch = info.parent_branch_id.as_deref();
+    }
+    lineage.push((None, limit));
+
+    let mut entries: Vec<&StoryEntry> = export
+        .entries
+        .iter()
+        .filter(|e| {
+            lineage
+                .iter()
+                .any(|(b, limit)| e.branch_id.as_deref() == *b && e.position <= *limit)
+        })
+        .collect();
+    entries.sort_by_key(|e| e.position);
+    entries
+}
+
+fn role(entry: &StoryEntry) -> Option<&'static str> {
+    match entry.entry_type.as_str() {
+        "user_action" => Some("user"),
+        "narration" => Some("assistant"),
+        _ => None,
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3 && !STOPWORDS.contains(w))
+        .map(String::from)
+        .collect()
+}
+
+/// Older entries sharing the rarest words with the latest ones, scored by
+/// inverse document frequency; the best `count`, in story order
+fn retrieve<'a>(
+    older: &[&'a StoryEntry],
+    recent: &[&StoryEntry],
+    count: usize,
+) -> Vec<&'a StoryEntry> {
+    let query: HashSet<String> = recent
+        .iter()
+        .rev()
+        .take(QUERY_ENTRIES)
+        .flat_map(|e| words(&plain_text(&e.content)))
+        .collect();
+    if query.is_empty() || count == 0 {
+        return Vec::new();
+    }
+    let documents: Vec<HashSet<String>> = older
+        .iter()
+        .map(|e| words(&plain_text(&e.content)))
+        .collect();
+    let total = documents.len() as f64;
+    let idf = |word: &String| {
+        let frequency = documents.iter().filter(|d| d.contains(word)).count() as f64;
+        (total / frequency.max(1.0)).ln() + 1.0
+    };
+    let weights: HashMap<&String, f64> = query.iter().map(|w| (w, idf(w))).collect();
+
+    let mut scored: Vec<(usize, f64)> = documents
+        .iter()
+        .enumerate()
+        .map(|(index, document)| {
+            let score = weights
+                .iter()
+                .filter(|(word, _)| document.contains(**word))
+                .map(|(_, weight)| weight)
+                .sum::<f64>();
+            (index, score)
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .collect();
+    // Best first; the later entry wins a tie
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+    scored.truncate(count);
+    scored.sort_by_key(|(index, _)| *index);
+    scored.into_iter().map(|(index, _)| older[index]).collect()
+}
+
+/// Build the messages for a story under a strategy, before the game
+/// context that is added when a request is sent
+pub fn assemble(
+    export: &StoryExport,
+    system_prompt: &str,
+    strategy: &ContextStrategy,
+) -> ContextPreview {
+    let entries: Vec<&StoryEntry> = branch_entries(export)
+        .into_iter()
+        .filter(|e| role(e).is_some() && !plain_text(&e.content).is_empty())
+        .collect();
+    let window = match strategy {
+        ContextStrategy::Full => entries.len(),
+        ContextStrategy::RecencyWindow { entries: n }
+        | ContextStrategy::SummaryAndRecency { entries: n }
+        | ContextStrategy::RetrievalAugmented { entries: n, .. } => *n,
+    };
+    let split = entries.len().saturating_sub(window);
+    let (older, recent) = entries.split_at(split);
+
+    let mut system = vec![system_prompt.trim().to_string()];
+    let mut summarized_chapters = 0;
+    let mut retrieved_entry_ids = Vec::new();
+    match strategy {
+        ContextStrategy::SummaryAndRecency { .. } => {
+            let older_ids: HashSet<&str> = older.iter().map(|e| e.id.as_str()).collect();
+            let mut chapters: Vec<_> = export
+                .chapters
+                .iter()
+                .filter(|c| !c.summary.trim().is_empty())
+                .filter(|c| older_ids.contains(c.end_entry_id.as_str()))
+                .collect();
+            chapters.sort_by_key(|c| c.number);
+            if !chapters.is_empty() {
+                summarized_chapters = chapters.len();
+                let lines: Vec<String> = chapters
+                    .iter()
+                    .map(|c| match c.title.as_deref().filter(|t| !t.is_empty()) {
+                        Some(title) => format!("{}. {}: {}", c.number, title, c.summary.trim()),
+                        None => format!("{}. {}", c.number, c.summary.trim()),
+                    })
+                    .collect();
+                system.push(format!("[STORY SO FAR]\n{}", lines.join("\n")));
+            }
+        }
+        ContextStrategy::RetrievalAugmented { retrieved, .. } => {
+            let passages = retrieve(older, recent, *retrieved);
+            if !passages.is_empty() {
+                retrieved_entry_ids = passages.iter().map(|e| e.id.clone()).collect();
+                let text: Vec<String> = passages.iter().map(|e| plain_text(&e.content)).collect();
+                system.push(format!(
+                    "[RELEVANT EARLIER PASSAGES]\n{}",
+                    text.join("\n\n")
+                ));
+            }
+        }
+        _ => {}
+    }
+
+    let mut messages = Vec::new();
+    let system = system
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if !system.is_empty() {
+        messages.push(ChatMessage {
+            role: "system".to_string(),
+            content: system,
+        });
+    }
+    messages.extend(recent.iter().filter_map(|e| {
+        Some(ChatMessage {
+            role: role(e)?.to_string(),
+            content: plain_text(&e.content),
+        })
+    }));
+
+    ContextPreview {
+        strategy: strategy.clone(),
+        total_entries: entries.len(),
+        included_entries: recent.len(),
+        retrieved_entry_ids,
+        summarized_chapters,
+        estimated_tokens: 0,
+        messages,
+    }
+}
+
+/// The story under a strategy, with roll macros in its profile's system
+/// prompt filled in. None when the story has no saved rows yet.
+async fn story_context(
+    app: &AppHandle,
+    story_id: &str,
+    profile: Option<&AiProfile>,
+    strategy: &ContextStrategy,
+) -> Result<Option<ContextPreview>, String> {
+    strategy.validate()?;
+    let Some(export) = rows::load(app, story_id).await? else {
+        return Ok(None);
+    };
+    let mut system_prompt = vec![ChatMessage {
+        role: "system".to_string(),
+        content: profile.map_or("", |p| p.system_prompt.as_str()).to_string(),
+    }];
+    tables::expand_messages(app, &mut system_prompt)?;
+    Ok(Some(assemble(&export, &system_prompt[0].content, strategy)))
+}
+
+/// Replace the conversation of a story request with the selection of the
+/// story its profile's strategy makes. The request's own system messages
+/// are kept and the assembled system text is added to the first of them. A
+/// last user message the story does not have yet, such as the action being
+/// answered, stays at the end. Requests for stories whose profile sets no
+/// strategy are left as they are.
+pub async fn apply(
+    app: &AppHandle,
+    story_id: &str,
+    messages: &mut Vec<ChatMessage>,
+) -> Result<(), String> {
+    let profile = profile::load_profile(app, story_id).await?;
+    let Some(strategy) = profile.as_ref().and_then(|p| p.context_strategy.clone()) else {
+        return Ok(());
+    };
+    let Some(assembled) = story_context(app, story_id, profile.as_ref(), &strategy).await? else {
+        return Ok(());
+    };
+    let latest = messages
+        .iter()
+        .rev()
+        .find(|m| m.role != "system")
+        .filter(|m| m.role == "user")
+        .filter(|m| {
+            assembled
+                .messages
+                .last()
+                .is_none_or(|last| last.role == "system" || last.content.trim() != m.content.trim())
+        })
+        .cloned();
+
+    messages.retain(|m| m.role == "system");
+    let mut conversation = assembled.messages.into_iter().peekable();
+    if let Some(system) = conversation.next_if(|m| m.role == "system") {
+        game_context::inject(messages, &system.content);
+    }
+    messages.extend(conversation);
+    messages.extend(latest);
+    Ok(())
+}
+
+/// What would be sent for the story: its profile's system prompt, the
+/// strategy's selection of the story, and the recap, stat block, quest
+/// reminder and style guide every story request gets. Uses the profile's
+/// strategy unless given one, and the default strategy when the profile
+/// sets none.
+pub async fn preview(
+    app: &AppHandle,
+    story_id: &str,
+    strategy: Option<ContextStrategy>,
+) -> Result<ContextPreview, String> {
+    profiles::check_story(app, story_id).await?;
+    let profile = profile::load_profile(app, story_id).await?;
+    let strategy = strategy
+        .or_else(|| profile.as_ref().and_then(|p| p.context_strategy.clone()))
+        .unwrap_or_default();
+    let mut preview = story_context(app, story_id, profile.as_ref(), &strategy)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    game_context::inject_first(&mut preview.messages, &recaps::context_text(app, story_id)?);
+    game_context::inject(
+        &mut preview.messages,
+        &game_context::request_stat_block(app, story_id).await?,
+    );
+    game_context::inject(
+        &mut preview.messages,
+        &quests::reminder(app, story_id).await?,
+    );
+    game_context::inject(
+        &mut preview.messages,
+        &style::context_text(app, story_id).await?,
+    );
+    preview.estimated_tokens = preview
+        .messages
+        .iter()
+        .map(|m| estimate_tokens(&m.content))
+        .sum();
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn entry(id: &str, kind: &str, content: &str, position: i64, branch: Option<&str>) -> Value {
+        json!({
+            "id": id,
+            "type": kind,
+            "content": content,
+            "position": position,
+            "branchId": branch,
+        })
+    }
+
+    fn story(entries: Vec<Value>, branches: Value, current: Option<&str>) -> StoryExport {
+        serde_json::from_value(json!({
+            "version": "1.7.0",
+            "story": { "id": "s1", "title": "Test", "currentBranchId": current },
+            "entries": entries,
+            "branches": branches,
+        }))
+        .unwrap()
+    }
+
+    fn ids<'a>(entries: impl IntoIterator<Item = &'a StoryEntry>) -> Vec<&'a str> {
+        entries.into_iter().map(|e| e.id.as_str()).collect()
+    }
+
+    /// Main line e1..e3; `b1` forks at e2, `b2` at e1 and `b3` from `b1`
+    /// at its first entry
+    fn forked() -> StoryExport {
+        story(
+            vec![
+                entry("e1", "narration", "one", 1, None),
+                entry("e2", "narration", "two", 2, None),
+                entry("e3", "narration", "three", 3, None),
+                entry("b1a", "narration", "b1 first", 4, Some("b1")),
+                entry("b1b", "narration", "b1 second", 5, Some("b1")),
+                entry("b2a", "narration", "b2 first", 6, Some("b2")),
+                entry("b3a", "narration", "b3 first", 7, Some("b3")),
+            ],
+            json!([
+                { "id": "b1", "name": "One", "parentBranchId": null, "forkEntryId": "e2" },
+                { "id": "b2", "name": "Two", "parentBranchId": null, "forkEntryId": "e1" },
+                { "id": "b3", "name": "Three", "parentBranchId": "b1", "forkEntryId": "b1a" },
+            ]),
+            None,
+        )
+    }
+
+    #[test]
+    fn main_line_sees_no_branches() {
+        let export = forked();
+        assert_eq!(ids(branch_entries(&export)), ["e1", "e2", "e3"]);
+    }
+
+    #[test]
+    fn branch_sees_parent_up_to_its_fork() {
+        let mut export = forked();
+        export.story.extra["currentBranchId"] = json!("b1");
+        assert_eq!(ids(branch_entries(&export)), ["e1", "e2", "b1a", "b1b"]);
+        export.story.extra["currentBranchId"] = json!("b2");
+        assert_eq!(ids(branch_entries(&export)), ["e1", "b2a"]);
+    }
+
+    #[test]
+    fn nested_branch_stops_at_each_fork() {
+        let mut export = forked();
+        export.story.extra["currentBranchId"] = json!("b3");
+        assert_eq!(ids(branch_entries(&export)), ["e1", "e2", "b1a", "b3a"]);
+    }
+
+    #[test]
+    fn branch_cycle_ends() {
+        let export = story(
+            vec![
+                entry("e1", "narration", "one", 1, None),
+                entry("x1", "narration", "x", 2, Some("x")),
+                entry("y1", "narration", "y", 3, Some("y")),
+            ],
+            json!([
+                { "id": "x", "name": "X", "parentBranchId": "y", "forkEntryId": "y1" },
+                { "id": "y", "name": "Y", "parentBranchId": "x", "forkEntryId": "x1" },
+            ]),
+            Some("x"),
+        );
+        let entries = ids(branch_entries(&export));
+        assert!(entries.contains(&"x1"));
+        assert!(entries.contains(&"e1"));
+    }
+
+    fn passages(texts: &[&str]) -> Vec<StoryEntry> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                serde_json::from_value(entry(&format!("p{}", i), "narration", text, i as i64, None))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn retrieval_ranks_rare_words_first() {
+        let older = passages(&[
+            "The castle stood above the village.",
+            "A dragon slept beneath the castle.",
+            "The castle bells rang at noon.",
+        ]);
+        let recent = passages(&["Where does the dragon sleep in the castle?"]);
+        let older: Vec<&StoryEntry> = older.iter().collect();
+        let recent: Vec<&StoryEntry> = recent.iter().collect();
+        assert_eq!(ids(retrieve(&older, &recent, 1)), ["p1"]);
+    }
+
+    #[test]
+    fn retrieval_returns_story_order_and_later_wins_ties() {
+        let older = passages(&[
+            "A lantern hung by the door.",
+            "Rain fell on the roof.",
+            "She lit the lantern again.",
+            "The dragon and the lantern.",
+        ]);
+        let recent = passages(&["The dragon carried a lantern."]);
+        let older: Vec<&StoryEntry> = older.iter().collect();
+        let recent: Vec<&StoryEntry> = recent.iter().collect();
+        // p3 matches both words; p0 and p2 tie on one and the later is taken
+        assert_eq!(ids(retrieve(&older, &recent, 2)), ["p2", "p3"]);
+    }
+
+    #[test]
+    fn retrieval_without_query_finds_nothing() {
+        let older = passages(&["The castle stood above the village."]);
+        let recent = passages(&["And so it was."]);
+        let older: Vec<&StoryEntry> = older.iter().collect();
+        let recent: Vec<&StoryEntry> = recent.iter().collect();
+        assert!(retrieve(&older, &recent, 3).is_empty());
+        assert!(retrieve(&older, &[], 3).is_empty());
+    }
+
+    /// Six alternating turns, the first two a chapter with a summary
+    fn conversation() -> StoryExport {
+        let mut export = story(
+            vec![
+                entry("e1", "user_action", "I enter the mill.", 1, None),
+                entry("e2", "narration", "The miller hides a silver key.", 2, None),
+                entry("e3", "user_action", "I walk to the river.", 3, None),
+                entry("e4", "narration", "The river runs fast and cold.", 4, None),
+                entry("e5", "user_action", "I search for the silver key.", 5, None),
+                entry("e6", "narration", "You remember the miller.", 6, None),
+            ],
+            json!([]),
+            None,
+        );
+        export.chapters = serde_json::from_value(json!([{
+            "id": "c1",
+            "number": 1,
+            "title": "The Mill",
+            "startEntryId": "e1",
+            "endEntryId": "e2",
+            "summary": "A key is hidden.",
+        }]))
+        .unwrap();
+        export
+    }
+
+    fn roles(preview: &ContextPreview) -> Vec<&str> {
+        preview.messages.iter().map(|m| m.role.as_str()).collect()
+    }
+
+    #[test]
+    fn full_sends_every_entry() {
+        let preview = assemble(&conversation(), "Be brief.", &ContextStrategy::Full);
+        assert_eq!(preview.total_entries, 6);
+        assert_eq!(preview.included_entries, 6);
+        assert_eq!(roles(&preview)[..3], ["system", "user", "assistant"]);
+        assert_eq!(preview.messages[0].content, "Be brief.");
+    }
+
+    #[test]
+    fn recency_window_keeps_the_latest() {
+        let strategy = ContextStrategy::RecencyWindow { entries: 2 };
+        let preview = assemble(&conversation(), "", &strategy);
+        assert_eq!(preview.included_entries, 2);
+        assert_eq!(roles(&preview), ["user", "assistant"]);
+        assert_eq!(preview.messages[1].content, "You remember the miller.");
+    }
+
+    #[test]
+    fn summary_covers_chapters_left_out() {
+        let strategy = ContextStrategy::SummaryAndRecency { entries: 2 };
+        let preview = assemble(&conversation(), "Be brief.", &strategy);
+        assert_eq!(preview.summarized_chapters, 1);
+        assert!(preview.messages[0]
+            .content
+            .contains("[STORY SO FAR]\n1. The Mill: A key is hidden."));
+
+        // A chapter still inside the window is not summarized
+        let strategy = ContextStrategy::SummaryAndRecency { entries: 6 };
+        let preview = assemble(&conversation(), "Be brief.", &strategy);
+        assert_eq!(preview.summarized_chapters, 0);
+    }
+
+    #[test]
+    fn retrieval_brings_back_older_passages() {
+        let strategy = ContextStrategy::RetrievalAugmented {
+            entries: 2,
+            retrieved: 1,
+        };
+        let preview = assemble(&conversation(), "", &strategy);
+        assert_eq!(preview.retrieved_entry_ids, ["e2"]);
+        assert!(preview.messages[0]
+            .content
+            .starts_with("[RELEVANT EARLIER PASSAGES]\nThe miller hides a silver key."));
+        assert_eq!(preview.included_entries, 2);
+    }
+}
@@ -0,0 +1,349 @@
+//! Prompt experiments: two templates run over the same sample inputs with
+//! the same provider, their outputs kept side by side with simple metrics
+//! (length, keyword hits, latency and cost) so template authors can tell
+//! which variant does better. Progress is announced with
+//! `experiment://progress`.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+use super::proxy::complete_chat_with_usage;
+use super::types::{ChatMessage, ProviderConfig, SamplingParams};
+use crate::game::context::estimate_tokens;
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Finished experiments, in the app data directory
+pub const PROMPT_EXPERIMENTS_FILE: &str = "prompt_experiments.json";
+
+/// Experiments kept, newest last
+const MAX_EXPERIMENTS: usize = 50;
+
+/// Sample inputs per experiment; each costs two completions
+const MAX_INPUTS: usize = 50;
+
+/// A prompt with `{{name}}` placeholders filled from each input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTemplate {
+    #[serde(default)]
+    pub system: String,
+    pub user: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExperimentInput {
+    /// Shown beside the outputs, such as "tavern scene"
+    #[serde(default)]
+    pub label: String,
+    pub variables: BTreeMap<String, String>,
+}
+
+/// Price of the provider's model, for the cost metric
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPricing {
+    /// Currency per million prompt tokens
+    pub input_per_million: f64,
+    /// Currency per million completion tokens
+    pub output_per_million: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExperimentOptions {
+    pub name: Option<String>,
+    /// Same for both variants, so only the templates differ
+    pub sampling: SamplingParams,
+    /// Words or phrases counted in each output, case-insensitively
+    pub keywords: Vec<String>,
+    pub pricing: Option<ModelPricing>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunMetrics {
+    pub chars: usize,
+    pub words: usize,
+    /// Keyword occurrences in the output
+    pub keyword_hits: usize,
+    /// Distinct keywords that occur at least once
+    pub keywords_matched: usize,
+    pub latency_ms: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// The provider reported no usage, so the token counts are estimated
+    pub tokens_estimated: bool,
+    /// Set when the experiment has pricing
+    pub cost: Option<f64>,
+}
+
+/// One variant's completion for one input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariantRun {
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub metrics: RunMetrics,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExperimentRow {
+    pub input: ExperimentInput,
+    pub a: VariantRun,
+    pub b: VariantRun,
+}
+
+/// A variant's metrics over the inputs it completed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariantSummary {
+    pub completed: usize,
+    pub failed: usize,
+    pub mean_words: f64,
+    pub mean_keyword_hits: f64,
+    pub mean_latency_ms: f64,
+    pub total_tokens: u64,
+    pub total_cost: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptExperiment {
+    pub id: String,
+    pub name: String,
+    pub created_at: i64,
+    /// Model the variants ran on; keys are never stored
+    pub model: String,
+    pub template_a: PromptTemplate,
+    pub template_b: PromptTemplate,
+    pub options: ExperimentOptions,
+    pub rows: Vec<ExperimentRow>,
+    pub summary_a: VariantSummary,
+    pub summary_b: VariantSummary,
+}
+
+/// Payload of the `experiment://progress` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExperimentProgress<'a> {
+    experiment_id: &'a str,
+    completed: usize,
+    total: usize,
+}
+
+fn placeholder() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap())
+}
+
+/// Fill a template's placeholders, failing on a variable the input lacks
+fn fill(template: &str, variables: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut missing = None;
+    let filled = placeholder().replace_all(template, |captures: &regex::Captures| {
+        let value = variables.get(&captures[1]);
+        if value.is_none() {
+            missing.get_or_insert_with(|| captures[1].to_string());
+        }
+        value.cloned().unwrap_or_default()
+    });
+    match missing {
+        Some(name) => Err(format!("Input has no value for {{{{{}}}}}", name)),
+        None => Ok(filled.into_owned()),
+    }
+}
+
+fn messages(
+    template: &PromptTemplate,
+    variables: &BTreeMap<String, String>,
+) -> Result<Vec<ChatMessage>, String> {
+    let mut messages = Vec::new();
+    let system = fill(&template.system, variables)?;
+    if !system.trim().is_empty() {
+        messages.push(ChatMessage {
+            role: "system".to_string(),
+            content: system,
+        });
+    }
+    messages.push(ChatMessage {
+        role: "user".to_string(),
+        content: fill(&template.user, variables)?,
+    });
+    Ok(messages)
+}
+
+/// Occurrences of each keyword, case-insensitively
+fn keyword_counts(output: &str, keywords: &[String]) -> Vec<usize> {
+    let output = output.to_lowercase();
+    keywords
+        .iter()
+        .map(|k| k.trim().to_lowercase())
+        .map(|k| {
+            if k.is_empty() {
+                0
+            } else {
+                output.matches(k.as_str()).count()
+            }
+        })
+        .collect()
+}
+
+async fn run_variant(
+    provider: &ProviderConfig,
+    messages: &[ChatMessage],
+    options: &ExperimentOptions,
+) -> VariantRun {
+    let started = Instant::now();
+    let result = complete_chat_with_usage(provider, messages, &options.sampling).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let completion = match result {
+        Ok(completion) => completion,
+        Err(e) => {
+            return VariantRun {
+                output: None,
+                error: Some(e),
+                metrics: RunMetrics {
+                    latency_ms,
+                    ..Default::default()
+                },
+            }
+        }
+    };
+
+    let output = completion.content;
+    let counts = keyword_counts(&output, &options.keywords);
+    let (prompt_tokens, completion_tokens) = match completion.usage {
+        Some(usage) => (usage.prompt_tokens, usage.completion_tokens),
+        None => (
+            messages
+                .iter()
+                .map(|m| estimate_tokens(&m.content) as u64)
+                .sum(),
+            estimate_tokens(&output) as u64,
+        ),
+    };
+    let cost = options.pricing.map(|p| {
+        (prompt_tokens as f64 * p.input_per_million
+            + completion_tokens as f64 * p.output_per_million)
+            / 1_000_000.0
+    });
+    VariantRun {
+        metrics: RunMetrics {
+            chars: output.chars().count(),
+            words: output.split_whitespace().count(),
+            keyword_hits: counts.iter().sum(),
+            keywords_matched: counts.iter().filter(|c| **c > 0).count(),
+            latency_ms,
+            prompt_tokens,
+            completion_tokens,
+            tokens_estimated: completion.usage.is_none(),
+            cost,
+        },
+        output: Some(output),
+        error: None,
+    }
+}
+
+fn summarize<'a>(runs: impl Iterator<Item = &'a VariantRun>, priced: bool) -> VariantSummary {
+    let mut summary = VariantSummary::default();
+    let mut cost = 0.0;
+    for run in runs {
+        if run.output.is_none() {
+            summary.failed += 1;
+            continue;
+        }
+        let metrics = &run.metrics;
+        summary.completed += 1;
+        summary.mean_words += metrics.words as f64;
+        summary.mean_keyword_hits += metrics.keyword_hits as f64;
+        summary.mean_latency_ms += metrics.latency_ms as f64;
+        summary.total_tokens += metrics.prompt_tokens + metrics.completion_tokens;
+        cost += metrics.cost.unwrap_or(0.0);
+    }
+    if summary.completed > 0 {
+        let n = summary.completed as f64;
+        summary.mean_words /= n;
+        summary.mean_keyword_hits /= n;
+        summary.mean_latency_ms /= n;
+    }
+    summary.total_cost = priced.then_some(cost);
+    summary
+}
+
+/// Run both templates over every input, one after the other so latencies
+/// compare fairly, and keep the experiment
+pub async fn run(
+    app: &AppHandle,
+    template_a: PromptTemplate,
+    template_b: PromptTemplate,
+    inputs: Vec<ExperimentInput>,
+    provider: &ProviderConfig,
+    options: ExperimentOptions,
+) -> Result<PromptExperiment, String> {
+    if inputs.is_empty() || inputs.len() > MAX_INPUTS {
+        return Err(format!("Experiments take 1 to {} inputs", MAX_INPUTS));
+    }
+    if template_a.user.trim().is_empty() || template_b.user.trim().is_empty() {
+        return Err("Both templates need a user prompt".to_string());
+    }
+    // Fill every prompt first so a missing variable fails before any
+    // completion is paid for
+    let prompts = inputs
+        .iter()
+        .map(|input| {
+            Ok((
+                messages(&template_a, &input.variables)?,
+                messages(&template_b, &input.variables)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let id = Uuid::new_v4().to_string();
+    let total = inputs.len();
+    let mut rows = Vec::with_capacity(total);
+    for (index, (input, (prompt_a, prompt_b))) in inputs.into_iter().zip(prompts).enumerate() {
+        let a = run_variant(provider, &prompt_a, &options).await;
+        let b = run_variant(provider, &prompt_b, &options).await;
+        rows.push(ExperimentRow { input, a, b });
+        let _ = app.emit(
+            "experiment://progress",
+            ExperimentProgress {
+                experiment_id: &id,
+                completed: index + 1,
+                total,
+            },
+        );
+    }
+
+    let priced = options.pricing.is_some();
+    let experiment = PromptExperiment {
+        id,
+        name: options
+            .name
+            .clone()
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| "Untitled experiment".to_string()),
+        created_at: now_ms(),
+        model: provider.model.clone(),
+        summary_a: summarize(rows.iter().map(|r| &r.a), priced),
+        summary_b: summarize(rows.iter().map(|r| &r.b), priced),
+        template_a,
+        template_b,
+        options,
+        rows,
+    };
+    let mut experiments: Vec<PromptExperiment> = store::load_json(app, PROMPT_EXPERIMENTS_FILE)?;
+    experiments.push(experiment.clone());
+    let excess = experiments.len().saturating_sub(MAX_EXPERIMENTS);
+    experiments.drain(..excess);
+    store::save_json(app, PROMPT_EXPERIMENTS_FILE, &experiments)?;
+    Ok(experiment)
+}
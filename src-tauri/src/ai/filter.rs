@@ -0,0 +1,385 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+use crate::sync::keys::{self, ApiKeyEntry};
+use crate::{profiles, store};
+
+/// File in the app data directory holding the filter configuration
+const FILTER_CONFIG_FILE: &str = "content_filter.json";
+
+/// Keychain account holding the classifier's API key
+const CLASSIFIER_KEY_ACCOUNT: &str = "content-classifier";
+
+/// How aggressively generated text is filtered.
+/// Rules only apply when the active strictness is at or above their level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Strictness {
+    Off,
+    #[default]
+    Low,
+    Medium,
+    High,
+}
+
+/// What happens to text matched by a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterAction {
+    /// Replace each character of the match with an asterisk
+    Mask,
+    /// Remove the match entirely
+    Remove,
+    /// Reject the whole generation
+    Block,
+}
+
+/// How a rule finds text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FilterMatcher {
+    /// A regular expression
+    Regex { pattern: String },
+    /// Whole words, matched case-insensitively
+    Wordlist { words: Vec<String> },
+}
+
+/// A user-authored filter rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterRule {
+    pub id: String,
+    pub name: String,
+    pub matcher: FilterMatcher,
+    pub action: FilterAction,
+    /// Lowest strictness at which this rule is active
+    pub min_strictness: Strictness,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Optional external classifier consulted once a generation completes.
+/// The endpoint receives `{ "text": ... }` and must answer `{ "flagged": bool }`.
+/// While a classifier applies, a reply reaches the UI only after it is cleared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassifierConfig {
+    pub url: String,
+    /// Only set when saving a new key, which goes to the OS keychain (or
+    /// the secrets file in portable mode); never written to the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Lowest strictness at which the classifier is consulted
+    pub min_strictness: Strictness,
+}
+
+/// Persisted filter pipeline configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterConfig {
+    #[serde(default)]
+    pub rules: Vec<FilterRule>,
+    #[serde(default)]
+    pub classifier: Option<ClassifierConfig>,
+    #[serde(default)]
+    pub default_strictness: Strictness,
+    /// Per-story strictness overrides, keyed by story ID
+    #[serde(default)]
+    pub story_strictness: HashMap<String, Strictness>,
+}
+
+/// Move the classifier key a configuration carries into the keychain,
+/// leaving the configuration without it. Returns whether there was one.
+fn move_key_to_keychain(config: &mut FilterConfig) -> Result<bool, String> {
+    let Some(api_key) = config.classifier.as_mut().and_then(|c| c.api_key.take()) else {
+        return Ok(false);
+    };
+    keys::store_in_keychain(&[ApiKeyEntry {
+        provider: CLASSIFIER_KEY_ACCOUNT.to_string(),
+        api_key,
+    }])?;
+    Ok(true)
+}
+
+/// The filter configuration, without the classifier key. A key older
+/// versions wrote into the file is moved to the keychain on the way.
+pub fn load_config(app: &AppHandle) -> Result<FilterConfig, String> {
+    let mut config: FilterConfig = store::load_json(app, FILTER_CONFIG_FILE)?;
+    if move_key_to_keychain(&mut config)? {
+        store::save_json(app, FILTER_CONFIG_FILE, &config)?;
+    }
+    Ok(config)
+}
+
+/// Write the filter configuration, keeping a classifier key it carries in
+/// the keychain. Without one the stored key is kept; removing the
+/// classifier forgets it.
+pub fn save_config(app: &AppHandle, mut config: FilterConfig) -> Result<(), String> {
+    move_key_to_keychain(&mut config)?;
+    if config.classifier.is_none() {
+        keys::remove_from_keychain(CLASSIFIER_KEY_ACCOUNT)?;
+    }
+    store::save_json(app, FILTER_CONFIG_FILE, &config)
+}
+
+impl FilterConfig {
+    /// Strictness for a story, falling back to the default level
+    pub fn strictness_for(&self, story_id: Option<&str>) -> Strictness {
+        story_id
+            .and_then(|id| self.story_strictness.get(id))
+            .copied()
+            .unwrap_or(self.default_strictness)
+    }
+}
+
+/// A single piece of text matched by a rule
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterMatch {
+    pub rule_id: String,
+    pub action: FilterAction,
+    pub start: usize,
+    pub end: usize,
+    pub matched: String,
+}
+
+/// Result of running text through the filter
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterResult {
+    pub text: String,
+    pub matches: Vec<FilterMatch>,
+    /// ID of the rule that blocked the text, if any
+    pub blocked_by: Option<String>,
+}
+
+/// Rules compiled for a particular strictness level
+#[derive(Clone)]
+pub struct CompiledFilter {
+    rules: Vec<(FilterRule, Regex)>,
+}
+
+impl CompiledFilter {
+    /// Compile the enabled rules that apply at the given strictness
+    pub fn new(rules: &[FilterRule], strictness: Strictness) -> Result<Self, String> {
+        let mut compiled = Vec::new();
+        if strictness == Strictness::Off {
+            return Ok(Self { rules: compiled });
+        }
+
+        for rule in rules {
+            if !rule.enabled || rule.min_strictness > strictness {
+                continue;
+            }
+            compiled.push((rule.clone(), compile(&rule.name, &rule.matcher)?));
+        }
+        Ok(Self { rules: compiled })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Apply all rules in order. Offsets in the result refer to the original text.
+    pub fn apply(&self, text: &str) -> FilterResult {
+        let mut matches = Vec::new();
+        let mut blocked_by = None;
+
+        for (rule, regex) in &self.rules {
+            for m in regex.find_iter(text) {
+                if m.start() == m.end() {
+                    continue;
+                }
+                matches.push(FilterMatch {
+                    rule_id: rule.id.clone(),
+                    action: rule.action,
+                    start: m.start(),
+                    end: m.end(),
+                    matched: m.as_str().to_string(),
+                });
+                if rule.action == FilterAction::Block && blocked_by.is_none() {
+                    blocked_by = Some(rule.id.clone());
+                }
+            }
+        }
+
+        if blocked_by.is_some() {
+            return FilterResult {
+                text: String::new(),
+                matches,
+                blocked_by,
+            };
+        }
+
+        FilterResult {
+            text: rewrite(text, &matches),
+            matches,
+            blocked_by,
+        }
+    }
+}
+
+/// Build the regex for a matcher; `name` identifies it in errors
+pub fn compile(name: &str, matcher: &FilterMatcher) -> Result<Regex, String> {
+    let pattern = match matcher {
+        FilterMatcher::Regex { pattern } => pattern.clone(),
+        FilterMatcher::Wordlist { words } => {
+            let alternatives: Vec<String> = words
+                .iter()
+                .map(|w| w.trim())
+                .filter(|w| !w.is_empty())
+                .map(regex::escape)
+                .collect();
+            if alternatives.is_empty() {
+                return Err(format!("Rule '{}' has an empty word list", name));
+            }
+            format!(r"\b(?:{})\b", alternatives.join("|"))
+        }
+    };
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(matches!(matcher, FilterMatcher::Wordlist { .. }))
+        .build()
+        .map_err(|e| format!("Invalid pattern in rule '{}': {}", name, e))
+}
+
+/// Apply mask/remove actions, skipping matches that overlap an earlier one
+fn rewrite(text: &str, matches: &[FilterMatch]) -> String {
+    let mut ordered: Vec<&FilterMatch> = matches.iter().collect();
+    ordered.sort_by_key(|m| m.start);
+
+    let mut output = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for m in ordered {
+        if m.start < cursor {
+            continue;
+        }
+        output.push_str(&text[cursor..m.start]);
+        if m.action == FilterAction::Mask {
+            output.extend(m.matched.chars().map(|_| '*'));
+        }
+        cursor = m.end;
+    }
+    output.push_str(&text[cursor..]);
+    output
+}
+
+/// Applies a compiled filter to streamed deltas.
+/// Text after the last whitespace is held back so words split across chunks
+/// are still matched; patterns spanning a held-back boundary may be missed.
+pub struct StreamFilter {
+    filter: CompiledFilter,
+    pending: String,
+}
+
+impl StreamFilter {
+    pub fn new(filter: CompiledFilter) -> Self {
+        Self {
+            filter,
+            pending: String::new(),
+        }
+    }
+
+    /// Feed a delta, returning the filtered text that is safe to emit
+    pub fn push(&mut self, delta: &str) -> Result<String, String> {
+        if self.filter.is_empty() {
+            return Ok(delta.to_string());
+        }
+        self.pending.push_str(delta);
+        let Some(split) = self.pending.rfind(char::is_whitespace) else {
+            return Ok(String::new());
+        };
+        // Keep the whitespace character with the emitted part
+        let split = split
+            + self.pending[split..]
+                .chars()
+                .next()
+                .map_or(0, char::len_utf8);
+        let ready: String = self.pending.drain(..split).collect();
+        self.filter_text(&ready)
+    }
+
+    /// Flush any held-back text at the end of the stream
+    pub fn finish(&mut self) -> Result<String, String> {
+        let rest = std::mem::take(&mut self.pending);
+        self.filter_text(&rest)
+    }
+
+    fn filter_text(&self, text: &str) -> Result<String, String> {
+        let result = self.filter.apply(text);
+        match result.blocked_by {
+            Some(rule_id) => Err(format!(
+                "Generation blocked by content filter rule: {}",
+                rule_id
+            )),
+            None => Ok(result.text),
+        }
+    }
+}
+
+/// Ask the configured classifier whether text should be blocked. The
+/// classifier is sent the text, so the active profile must be allowed to
+/// use it like any provider.
+pub async fn classify(
+    app: &AppHandle,
+    config: &ClassifierConfig,
+    text: &str,
+) -> Result<bool, String> {
+    profiles::check_generation(app, &config.url, Strictness::Off)?;
+    let client = reqwest::Client::new();
+    let mut builder = client
+        .post(&config.url)
+        .json(&serde_json::json!({ "text": text }))
+        .timeout(std::time::Duration::from_secs(30));
+    if let Some(key) = keys::read_from_keychain(CLASSIFIER_KEY_ACCOUNT)? {
+        builder = builder.bearer_auth(key);
+    }
+
+    let response: serde_json::Value = builder
+        .send()
+        .await
+        .map_err(|e| format!("Classifier request failed: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("Classifier returned an error: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid classifier response: {}", e))?;
+
+    response
+        .get("flagged")
+        .and_then(|v| v.as_bool())
+        .ok_or_else(|| "Classifier response is missing 'flagged'".to_string())
+}
+
+/// Run a reply generated in one piece through the rules and, when the
+/// strictness calls for it, the classifier, as streamed replies are
+pub async fn filter_reply(
+    app: &AppHandle,
+    config: &FilterConfig,
+    strictness: Strictness,
+    text: &str,
+) -> Result<String, String> {
+    let result = CompiledFilter::new(&config.rules, strictness)?.apply(text);
+    if let Some(rule_id) = result.blocked_by {
+        return Err(format!(
+            "Generation blocked by content filter rule: {}",
+            rule_id
+        ));
+    }
+    let classifier = config
+        .classifier
+        .as_ref()
+        .filter(|c| strictness != Strictness::Off && c.min_strictness <= strictness);
+    if let Some(classifier) = classifier {
+        if classify(app, classifier, &result.text).await? {
+            return Err("Generation blocked by content classifier".to_string());
+        }
+    }
+    Ok(result.text)
+}
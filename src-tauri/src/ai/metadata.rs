@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use super::proxy::{complete_chat, json_object};
+use super::types::{ChatMessage, ProviderConfig, SamplingParams};
+use crate::story::text::plain_text;
+use crate::story::StoryExport;
+
+/// Story text sent to the model, in characters
+const MAX_SOURCE_CHARS: usize = 12_000;
+
+/// Passages sampled from the middle of a story too long to send whole
+const MIDDLE_SAMPLES: usize = 8;
+
+const MAX_TITLES: usize = 5;
+const MAX_TAGS: usize = 8;
+
+/// Characters listed by name in the prompt
+const MAX_CHARACTERS: usize = 20;
+
+/// Proposed metadata for a story, for the user to pick from
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataSuggestions {
+    pub story_id: String,
+    pub titles: Vec<String>,
+    pub synopsis: String,
+    pub genre: Option<String>,
+    /// Lowercase genre and theme tags
+    pub tags: Vec<String>,
+    /// Lowercase, empty when nothing needs a warning
+    pub content_warnings: Vec<String>,
+    /// Only part of the story text fit in the request
+    pub trimmed: bool,
+}
+
+/// The reply the model is asked for
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Reply {
+    titles: Vec<String>,
+    synopsis: String,
+    genre: Option<String>,
+    tags: Vec<String>,
+    content_warnings: Vec<String>,
+}
+
+/// The first `max` characters of a passage
+fn clip(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Fit the passages into `budget` characters. A story that is too long is
+/// sent as its opening, evenly spaced samples from the middle and its
+/// ending, so the model sees how it starts, develops and where it is now.
+/// Returns the text and whether anything was left out.
+fn trim_passages(passages: &[String], budget: usize) -> (String, bool) {
+    let total: usize = passages.iter().map(|p| p.chars().count() + 2).sum();
+    if total <= budget {
+        return (passages.join("\n\n"), false);
+    }
+
+    let edge_budget = budget / 4;
+    let mut used = 0;
+    let mut opening = Vec::new();
+    for passage in passages {
+        if used >= edge_budget {
+            break;
+        }
+        opening.push(clip(passage, edge_budget - used));
+        used += passage.chars().count().min(edge_budget - used);
+    }
+    let head = opening.len();
+
+    used = 0;
+    let mut ending = Vec::new();
+    for passage in passages[head..].iter().rev() {
+        if used >= edge_budget {
+            break;
+        }
+        let len = passage.chars().count();
+        // Keep the end of the passage nearest the present
+        let skip = len.saturating_sub(edge_budget - used);
+        let kept: String = passage.chars().skip(skip).collect();
+        ending.push(if skip > 0 {
+            format!("…{}", kept)
+        } else {
+            kept
+        });
+        used += len - skip;
+    }
+    ending.reverse();
+    let tail = passages.len() - ending.len();
+
+    let middle = &passages[head..tail];
+    let samples = MIDDLE_SAMPLES.min(middle.len());
+    let mut sections = vec![opening.join("\n\n")];
+    if let Some(sample_budget) = (budget - 2 * edge_budget).checked_div(samples) {
+        let sampled: Vec<String> = (0..samples)
+            .map(|i| clip(&middle[i * middle.len() / samples], sample_budget))
+            .collect();
+        sections.push(sampled.join("\n\n[...]\n\n"));
+    }
+    sections.push(ending.join("\n\n"));
+    sections.retain(|s| !s.is_empty());
+    (sections.join("\n\n[...]\n\n"), true)
+}
+
+/// Describe the story for the model: what it is set up as, then as much of
+/// the main branch as fits
+pub(super) fn source_text(export: &StoryExport) -> (String, bool) {
+    let mut source = format!("Current title: {}\n", export.story.title);
+    if let Some(genre) = export.story.genre.as_deref().filter(|g| !g.is_empty()) {
+        source.push_str(&format!("Current genre: {}\n", genre));
+    }
+    if let Some(description) = export
+        .story
+        .description
+        .as_deref()
+        .filter(|d| !d.is_empty())
+    {
+        source.push_str(&format!("Premise: {}\n", description));
+    }
+    if !export.characters.is_empty() {
+        let names: Vec<&str> = export
+            .characters
+            .iter()
+            .take(MAX_CHARACTERS)
+            .map(|c| c.name.as_str())
+            .collect();
+        source.push_str(&format!("Characters: {}\n", names.join(", ")));
+    }
+    let mut chapters: Vec<_> = export
+        .chapters
+        .iter()
+        .filter(|c| !c.summary.trim().is_empty())
+        .collect();
+    chapters.sort_by_key(|c| c.number);
+    if !chapters.is_empty() {
+        source.push_str("\nChapter summaries:\n");
+        for chapter in chapters {
+            source.push_str(&format!("{}. {}\n", chapter.number, chapter.summary.trim()));
+        }
+    }
+
+    let mut entries: Vec<_> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none() && e.entry_type != "system")
+        .collect();
+    entries.sort_by_key(|e| e.position);
+    let passages: Vec<String> = entries
+        .iter()
+        .map(|e| plain_text(&e.content))
+        .filter(|t| !t.is_empty())
+        .collect();
+    let budget = MAX_SOURCE_CHARS.saturating_sub(source.chars().count());
+    let (text, trimmed) = trim_passages(&passages, budget);
+    source.push_str("\nStory text:\n");
+    source.push_str(&text);
+    (source, trimmed)
+}
+
+/// Trimmed, lowercased and without duplicates, in the order given
+fn normalize(values: Vec<String>, max: usize) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    values
+        .into_iter()
+        .map(|v| v.trim().to_lowercase())
+        .filter(|v| !v.is_empty() && seen.insert(v.clone()))
+        .take(max)
+        .collect()
+}
+
+fn parse_reply(reply: &str) -> Result<Reply, String> {
+    let json = json_object(reply).ok_or("Suggestion response contained no JSON")?;
+    serde_json::from_str(json).map_err(|e| format!("Invalid suggestion response: {}", e))
+}
+
+/// Ask the model for titles, a synopsis, a genre, tags and content warnings
+pub async fn suggest(
+    export: &StoryExport,
+    provider: &ProviderConfig,
+) -> Result<MetadataSuggestions, String> {
+    let (source, trimmed) = source_text(export);
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: format!(
+                "You catalogue interactive fiction. Reply with JSON only, in the form \
+                 {{\"titles\": [string], \"synopsis\": string, \"genre\": string, \
+                 \"tags\": [string], \"contentWarnings\": [string]}}. Suggest up to {} \
+                 distinct titles that fit the story's tone. The synopsis is two to four \
+                 sentences for a library listing and does not spoil the latest events. \
+                 The genre is one or two words. Give up to {} short tags for genre, \
+                 setting and themes. List content warnings such as violence, gore, \
+                 sexual content, self-harm or substance use only for what the text \
+                 actually contains, or an empty list.",
+                MAX_TITLES, MAX_TAGS
+            ),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: source,
+        },
+    ];
+    let sampling = SamplingParams {
+        temperature: Some(0.7),
+        ..Default::default()
+    };
+
+    let reply = parse_reply(&complete_chat(provider, &messages, &sampling).await?)?;
+    let mut titles = Vec::new();
+    for title in reply.titles {
+        let title = title.trim().trim_matches('"').trim().to_string();
+        if !title.is_empty() && !titles.contains(&title) && titles.len() < MAX_TITLES {
+            titles.push(title);
+        }
+    }
+    Ok(MetadataSuggestions {
+        story_id: export.story.id.clone(),
+        titles,
+        synopsis: reply.synopsis.trim().to_string(),
+        genre: reply
+            .genre
+            .map(|g| g.trim().to_string())
+            .filter(|g| !g.is_empty()),
+        tags: normalize(reply.tags, MAX_TAGS),
+        content_warnings: normalize(reply.content_warnings, usize::MAX),
+        trimmed,
+    })
+}
@@ -0,0 +1,19 @@
+pub mod beats;
+pub mod benchmark;
+pub mod commands;
+pub mod context;
+pub mod experiment;
+pub mod filter;
+pub mod metadata;
+pub mod overflow;
+pub mod postprocess;
+pub mod profile;
+pub mod proxy;
+pub mod sampling;
+pub mod trace;
+pub mod translate;
+pub mod types;
+
+pub use benchmark::BenchmarkState;
+pub use commands::AiState;
+pub use trace::TraceState;
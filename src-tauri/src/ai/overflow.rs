@@ -0,0 +1,257 @@
+//! Recovery from context-length errors. When a provider rejects a request
+//! as too long for its model, the request is adjusted by the next step of
+//! the story profile's overflow rules and sent again, and an
+//! `ai://adjusted` event says what changed.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use super::profile;
+use super::proxy::complete_chat;
+use super::types::{AiStreamRequest, ChatMessage, SamplingParams};
+use crate::game::context;
+
+/// Longest list of steps a profile may have
+const MAX_STEPS: usize = 6;
+
+/// Phrases providers use when a prompt does not fit the model's context
+const OVERFLOW_PHRASES: [&str; 10] = [
+    "context_length_exceeded",
+    "maximum context length",
+    "context length",
+    "context window",
+    "prompt is too long",
+    "input is too long",
+    "too many tokens",
+    "exceeds the context",
+    "exceed_context_size",
+    "n_ctx",
+];
+
+/// One way of making a request fit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum OverflowStep {
+    /// Replace the older half of the conversation with a summary of it,
+    /// written by the same model and added to the system prompt; falls
+    /// back to truncating when the summary fails
+    Summarize,
+    /// Drop the older half of the conversation, keeping system messages
+    /// and the latest turn
+    Truncate,
+    /// Send the request to a model with a larger context on the same
+    /// provider
+    #[serde(rename_all = "camelCase")]
+    FallbackModel { model: String },
+}
+
+/// Steps tried in order when a story request overflows the context
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OverflowRules {
+    pub enabled: bool,
+    pub steps: Vec<OverflowStep>,
+}
+
+impl Default for OverflowRules {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            steps: vec![OverflowStep::Summarize, OverflowStep::Truncate],
+        }
+    }
+}
+
+impl OverflowRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.steps.len() > MAX_STEPS {
+            return Err(format!("Overflow rules take at most {} steps", MAX_STEPS));
+        }
+        if self.steps.iter().any(
+            |step| matches!(step, OverflowStep::FallbackModel { model } if model.trim().is_empty()),
+        ) {
+            return Err("Fallback model steps need a model".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What was changed before a retry
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Adjustment {
+    #[serde(rename_all = "camelCase")]
+    Summarized {
+        summarized_messages: usize,
+        remaining_messages: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    Truncated {
+        removed_messages: usize,
+        remaining_messages: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    FallbackModel { from: String, to: String },
+}
+
+/// Payload of the `ai://adjusted` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiAdjustedEvent {
+    pub request_id: String,
+    /// Retry about to be sent, counting from 1
+    pub attempt: usize,
+    /// The provider's error that caused the retry
+    pub reason: String,
+    pub adjustment: Adjustment,
+}
+
+/// Overflow steps from a story's AI profile; none when it has no profile
+/// or recovery is turned off
+pub async fn story_steps(app: &AppHandle, story_id: &str) -> Result<Vec<OverflowStep>, String> {
+    Ok(profile::load_profile(app, story_id)
+        .await?
+        .map(|p| p.overflow)
+        .filter(|rules| rules.enabled)
+        .map(|rules| rules.steps.clone())
+        .unwrap_or_default())
+}
+
+/// Whether a provider error says the prompt was too long for the model
+pub fn is_context_overflow(error: &str) -> bool {
+    if !error.starts_with("Provider returned") {
+        return false;
+    }
+    let error = error.to_lowercase();
+    OVERFLOW_PHRASES.iter().any(|phrase| error.contains(phrase))
+}
+
+/// Remove the older half of the non-system messages, always keeping the
+/// last one. Returns what was removed, or None when nothing can go.
+fn take_older_half(messages: &mut Vec<ChatMessage>) -> Option<Vec<ChatMessage>> {
+    let conversation = messages.iter().filter(|m| m.role != "system").count();
+    let remove = conversation / 2;
+    if remove == 0 {
+        return None;
+    }
+    let mut removed = Vec::with_capacity(remove);
+    messages.retain(|m| {
+        if m.role == "system" || removed.len() == remove {
+            return true;
+        }
+        removed.push(m.clone());
+        false
+    });
+    Some(removed)
+}
+
+/// Summarize dropped messages with the request's own provider and model
+async fn summarize(request: &AiStreamRequest, removed: &[ChatMessage]) -> Result<String, String> {
+    let transcript: Vec<String> = removed
+        .iter()
+        .map(|m| format!("{}: {}", m.role, m.content))
+        .collect();
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: "Summarize this part of a story conversation in a short paragraph. \
+                      Keep names, places, decisions and unresolved threads. \
+                      Reply with the summary only."
+                .to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: transcript.join("\n\n"),
+        },
+    ];
+    let sampling = SamplingParams {
+        temperature: Some(0.3),
+        max_tokens: Some(400),
+        ..Default::default()
+    };
+    let summary = complete_chat(&request.provider, &messages, &sampling).await?;
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return Err("Summary came back empty".to_string());
+    }
+    Ok(summary.to_string())
+}
+
+/// Apply the first step that still changes the request, consuming the
+/// steps tried. None when no step is left that could help.
+async fn adjust(
+    request: &mut AiStreamRequest,
+    steps: &mut Vec<OverflowStep>,
+) -> Option<Adjustment> {
+    while !steps.is_empty() {
+        match steps.remove(0) {
+            OverflowStep::Summarize => {
+                let Some(removed) = take_older_half(&mut request.messages) else {
+                    continue;
+                };
+                match summarize(request, &removed).await {
+                    Ok(summary) => {
+                        context::inject(
+                            &mut request.messages,
+                            &format!("[EARLIER IN THE STORY]\n{}", summary),
+                        );
+                        return Some(Adjustment::Summarized {
+                            summarized_messages: removed.len(),
+                            remaining_messages: request.messages.len(),
+                        });
+                    }
+                    Err(_) => {
+                        return Some(Adjustment::Truncated {
+                            removed_messages: removed.len(),
+                            remaining_messages: request.messages.len(),
+                        })
+                    }
+                }
+            }
+            OverflowStep::Truncate => {
+                if let Some(removed) = take_older_half(&mut request.messages) {
+                    return Some(Adjustment::Truncated {
+                        removed_messages: removed.len(),
+                        remaining_messages: request.messages.len(),
+                    });
+                }
+            }
+            OverflowStep::FallbackModel { model } => {
+                if model != request.provider.model {
+                    let from = std::mem::replace(&mut request.provider.model, model.clone());
+                    return Some(Adjustment::FallbackModel { from, to: model });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Adjust a request that overflowed the context for another attempt,
+/// announcing the change. Returns false when the error was something else
+/// or the steps are used up.
+pub async fn retry(
+    app: &AppHandle,
+    request_id: &str,
+    request: &mut AiStreamRequest,
+    steps: &mut Vec<OverflowStep>,
+    attempt: usize,
+    error: &str,
+) -> bool {
+    if !is_context_overflow(error) {
+        return false;
+    }
+    let Some(adjustment) = adjust(request, steps).await else {
+        return false;
+    };
+    let _ = app.emit(
+        "ai://adjusted",
+        AiAdjustedEvent {
+            request_id: request_id.to_string(),
+            attempt,
+            reason: error.to_string(),
+            adjustment,
+        },
+    );
+    true
+}
@@ -0,0 +1,268 @@
+//! Post-processing of streamed replies. Once a stream ends, a reply the
+//! model cut off can be continued with a follow-up request, and the text
+//! then goes through the configured chain of processors before
+//! `ai://done` carries it to the UI. The chain is empty until the user
+//! picks processors; while it has any, chunks are held back and the
+//! processed reply is emitted as one chunk, so the UI never shows text the
+//! chain removes.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use tauri::AppHandle;
+
+use super::filter::{CompiledFilter, StreamFilter};
+use super::proxy::{stream_chat, StreamOutcome};
+use super::types::{AiStreamRequest, ChatMessage};
+
+/// File in the app data directory holding the post-processing config
+pub const POSTPROCESS_CONFIG_FILE: &str = "postprocess_config.json";
+
+/// Follow-up requests allowed per reply
+const MAX_CONTINUES: usize = 3;
+
+/// Sentences shorter than this may repeat without being collapsed
+const MIN_REPEAT_CHARS: usize = 20;
+
+/// Longest run of sentences recognized as repeating itself
+const MAX_LOOP_SENTENCES: usize = 3;
+
+/// One step of the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PostProcessor {
+    /// Cut chat-template tokens, the model writing the user's turn and
+    /// context headers echoed back from the prompt
+    StripInstructions,
+    /// Drop a run of sentences repeating the run right before it, which is
+    /// how a model stuck in a loop shows. A line that comes back later,
+    /// like a refrain, is kept.
+    CollapseRepetition,
+    /// Cut a trailing unfinished sentence. Replies ending in a dash or
+    /// closing emphasis are taken as finished.
+    TrimIncomplete,
+}
+
+impl PostProcessor {
+    fn name(self) -> &'static str {
+        match self {
+            PostProcessor::StripInstructions => "stripInstructions",
+            PostProcessor::CollapseRepetition => "collapseRepetition",
+            PostProcessor::TrimIncomplete => "trimIncomplete",
+        }
+    }
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            PostProcessor::StripInstructions => strip_instructions(text),
+            PostProcessor::CollapseRepetition => collapse_repetition(text),
+            PostProcessor::TrimIncomplete => trim_incomplete(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PostProcessConfig {
+    /// Processors run in this order; none by default
+    pub chain: Vec<PostProcessor>,
+    /// Ask the model to finish a reply it stopped at the token limit
+    pub auto_continue: bool,
+    pub max_continues: usize,
+}
+
+impl Default for PostProcessConfig {
+    fn default() -> Self {
+        Self {
+            chain: Vec::new(),
+            auto_continue: false,
+            max_continues: 1,
+        }
+    }
+}
+
+impl PostProcessConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_continues > MAX_CONTINUES {
+            return Err(format!(
+                "At most {} continue requests are allowed",
+                MAX_CONTINUES
+            ));
+        }
+        let mut seen = HashSet::new();
+        if let Some(repeated) = self.chain.iter().find(|p| !seen.insert(**p)) {
+            return Err(format!("{} is in the chain twice", repeated.name()));
+        }
+        Ok(())
+    }
+}
+
+/// Text after the chain, with the processors that changed it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostProcessResult {
+    pub text: String,
+    pub applied: Vec<String>,
+}
+
+pub fn apply(chain: &[PostProcessor], text: &str) -> PostProcessResult {
+    let mut text = text.to_string();
+    let mut applied = Vec::new();
+    for processor in chain {
+        let processed = processor.apply(&text);
+        if processed != text {
+            applied.push(processor.name().to_string());
+            text = processed;
+        }
+    }
+    PostProcessResult { text, applied }
+}
+
+fn turn_marker() -> &'static Regex {
+    static TURN: OnceLock<Regex> = OnceLock::new();
+    TURN.get_or_init(|| {
+        Regex::new(
+            r"(?im)<\|im_start\|>|<\|start_header_id\|>|\[INST\]|^\s*(?:###\s*)?(?:user|human|instruction)\s*:",
+        )
+        .unwrap()
+    })
+}
+
+fn special_token() -> &'static Regex {
+    static TOKEN: OnceLock<Regex> = OnceLock::new();
+    TOKEN.get_or_init(|| {
+        Regex::new(r"<\|(?:im_end|eot_id|end_header_id|endoftext|end)\|>|</s>|\[/INST\]").unwrap()
+    })
+}
+
+fn header_line() -> &'static Regex {
+    static HEADER: OnceLock<Regex> = OnceLock::new();
+    HEADER.get_or_init(|| {
+        Regex::new(
+            r"(?m)^[ \t]*(?:\[[A-Z][A-Z ]+\]|(?:###\s*)?(?:assistant|response)\s*:)[ \t]*\n?",
+        )
+        .unwrap()
+    })
+}
+
+fn strip_instructions(text: &str) -> String {
+    // A marker at the very start is left for the header pass rather than
+    // cutting the whole reply
+    let text = match turn_marker().find(text) {
+        Some(marker) if !text[..marker.start()].trim().is_empty() => &text[..marker.start()],
+        _ => text,
+    };
+    let text = special_token().replace_all(text, "");
+    header_line().replace_all(&text, "").trim().to_string()
+}
+
+fn sentence() -> &'static Regex {
+    static SENTENCE: OnceLock<Regex> = OnceLock::new();
+    SENTENCE.get_or_init(|| Regex::new(r#"[^.!?…\n]+(?:[.!?…]+["'”’)]*)?[ \t]*|\n\s*"#).unwrap())
+}
+
+fn collapse_repetition(text: &str) -> String {
+    // Parts kept so far, and where the sentences long enough to compare are
+    let mut kept: Vec<(&str, String)> = Vec::new();
+    let mut sentences: Vec<usize> = Vec::new();
+    for part in sentence().find_iter(text).map(|m| m.as_str()) {
+        let key: String = part
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect();
+        let compared = key.chars().count() >= MIN_REPEAT_CHARS;
+        kept.push((part, key));
+        if !compared {
+            continue;
+        }
+        sentences.push(kept.len() - 1);
+        for run in 1..=MAX_LOOP_SENTENCES.min(sentences.len() / 2) {
+            let latest = &sentences[sentences.len() - run..];
+            let before = &sentences[sentences.len() - 2 * run..sentences.len() - run];
+            if latest
+                .iter()
+                .zip(before)
+                .all(|(a, b)| kept[*a].1 == kept[*b].1)
+            {
+                kept.truncate(latest[0]);
+                sentences.truncate(sentences.len() - run);
+                break;
+            }
+        }
+    }
+    let out: String = kept.into_iter().map(|(part, _)| part).collect();
+    // Paragraph breaks left behind by dropped sentences
+    static BLANKS: OnceLock<Regex> = OnceLock::new();
+    let blanks = BLANKS.get_or_init(|| Regex::new(r"\n\s*\n\s*\n").unwrap());
+    blanks.replace_all(out.trim_end(), "\n\n").into_owned()
+}
+
+fn trim_incomplete(text: &str) -> String {
+    let text = text.trim_end();
+    let complete = |c: char| ".!?…\"'”’)*_—–".contains(c);
+    if text.chars().last().is_none_or(complete) {
+        return text.to_string();
+    }
+    match text.rfind(|c: char| ".!?…".contains(c)) {
+        Some(end) => {
+            let end = end + text[end..].chars().next().map_or(1, char::len_utf8);
+            let rest = &text[end..];
+            // Keep closing quotes and brackets that belong to the sentence
+            let closing = rest
+                .find(|c: char| !"\"'”’)*".contains(c))
+                .unwrap_or(rest.len());
+            text[..end + closing].to_string()
+        }
+        None => text.to_string(),
+    }
+}
+
+/// The request asking the model to carry on from a reply it was cut off in
+fn continuation(request: &AiStreamRequest, partial: &str) -> AiStreamRequest {
+    let mut follow_up = request.clone();
+    follow_up.messages.push(ChatMessage {
+        role: "assistant".to_string(),
+        content: partial.to_string(),
+    });
+    follow_up.messages.push(ChatMessage {
+        role: "user".to_string(),
+        content: "Continue exactly where you stopped, without repeating anything.".to_string(),
+    });
+    follow_up
+}
+
+/// Finish a reply the model stopped at the token limit, streaming the rest
+/// under the same request ID, held back like the reply was. A failed
+/// follow-up keeps what was written.
+pub async fn continue_cut_off(
+    app: &AppHandle,
+    request_id: &str,
+    request: &AiStreamRequest,
+    filter: &CompiledFilter,
+    config: &PostProcessConfig,
+    hold: bool,
+    outcome: &mut StreamOutcome,
+) {
+    if !config.auto_continue {
+        return;
+    }
+    for _ in 0..config.max_continues.min(MAX_CONTINUES) {
+        if outcome.finish_reason.as_deref() != Some("length") {
+            return;
+        }
+        let follow_up = continuation(request, &outcome.content);
+        let filter = StreamFilter::new(filter.clone());
+        match stream_chat(app, request_id, &follow_up, filter, hold, None).await {
+            Ok(more) => {
+                outcome.content.push_str(&more.content);
+                outcome.finish_reason = more.finish_reason;
+            }
+            Err(e) => {
+                eprintln!("Failed to continue cut-off reply: {}", e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Per-story AI profiles, one row per story in the `ai_profiles` table.
+//! API keys are kept in the OS keychain, or the secrets file in portable
+//! mode, never in the database.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, Sqlite, SqlitePool};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+use super::filter::Strictness;
+use super::overflow::OverflowRules;
+use super::types::ProviderConfig;
+use crate::profiles::{self, database};
+use crate::store;
+use crate::sync::keys::{self, ApiKeyEntry};
+
+/// Profiles from before they were kept in the database, in the app data
+/// directory. Each moves into the database once its story is there.
+const LEGACY_AI_PROFILES_FILE: &str = "ai_profiles.json";
+
+/// Version written into profile exports
+const PROFILE_EXPORT_VERSION: u32 = 1;
+
+/// How much of the story is sent to the model with each request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ContextStrategy {
+    /// Every entry in the story
+    Full,
+    /// Only the most recent entries
+    #[serde(rename_all = "camelCase")]
+    RecencyWindow { entries: usize },
+    /// Chapter summaries followed by the most recent entries
+    #[serde(rename_all = "camelCase")]
+    SummaryAndRecency { entries: usize },
+    /// Earlier entries that share the most words with the latest ones,
+    /// followed by the most recent entries
+    #[serde(rename_all = "camelCase")]
+    RetrievalAugmented { entries: usize, retrieved: usize },
+}
+
+impl Default for ContextStrategy {
+    fn default() -> Self {
+        ContextStrategy::SummaryAndRecency { entries: 20 }
+    }
+}
+
+impl ContextStrategy {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ContextStrategy::RecencyWindow { entries: 0 }
+            | ContextStrategy::SummaryAndRecency { entries: 0 }
+            | ContextStrategy::RetrievalAugmented { entries: 0, .. } => {
+                Err("Context window must include at least one entry".to_string())
+            }
+            ContextStrategy::RetrievalAugmented { retrieved: 0, .. } => {
+                Err("Retrieval must bring back at least one entry".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Generation settings for one story
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiProfile {
+    pub story_id: String,
+    pub name: String,
+    pub provider: ProviderConfig,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub system_prompt: String,
+    /// How much of the story requests carry; unset leaves requests as the
+    /// frontend builds them
+    #[serde(default)]
+    pub context_strategy: Option<ContextStrategy>,
+    /// Name of the sampling preset requests for the story start from
+    #[serde(default)]
+    pub sampling_preset: Option<String>,
+    /// ID of the style reference whose guide requests for the story carry;
+    /// unset uses the default style, if any
+    #[serde(default)]
+    pub style_reference: Option<String>,
+    /// How requests that overflow the model's context are retried
+    #[serde(default)]
+    pub overflow: OverflowRules,
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+impl AiProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.story_id.trim().is_empty() {
+            return Err("Profile is missing a story ID".to_string());
+        }
+        if self.provider.base_url.trim().is_empty() || self.provider.model.trim().is_empty() {
+            return Err(format!(
+                "Profile '{}' needs a provider URL and model",
+                self.name
+            ));
+        }
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(format!(
+                    "Temperature must be between 0 and 2, got {}",
+                    temperature
+                ));
+            }
+        }
+        if let Some(strategy) = &self.context_strategy {
+            strategy.validate()?;
+        }
+        self.overflow.validate()
+    }
+
+    /// Copy of the profile safe to write to a file or send to another device
+    pub fn without_api_key(&self) -> AiProfile {
+        let mut profile = self.clone();
+        profile.provider.api_key = None;
+        profile
+    }
+}
+
+/// Profiles keyed by story ID
+pub type AiProfiles = HashMap<String, AiProfile>;
+
+/// Keychain account holding the API key of a story's profile
+fn key_account(story_id: &str) -> String {
+    format!("ai-profile:{}", story_id)
+}
+
+/// Move the API keys profiles carry into the keychain through `sync::keys`,
+/// which uses the secrets file in portable mode, leaving the profiles
+/// without them
+fn move_keys_to_keychain<'a>(
+    profiles: impl IntoIterator<Item = &'a mut AiProfile>,
+) -> Result<(), String> {
+    let entries: Vec<ApiKeyEntry> = profiles
+        .into_iter()
+        .filter_map(|p| {
+            let api_key = p.provider.api_key.take()?;
+            Some(ApiKeyEntry {
+                provider: key_account(&p.story_id),
+                api_key,
+            })
+        })
+        .collect();
+    keys::store_in_keychain(&entries).map(|_| ())
+}
+
+/// Write a profile for a story in the database. Returns whether it was
+/// written: the story must exist, and a profile already there is only
+/// replaced with `overwrite`.
+async fn write<'e>(
+    db: impl Executor<'e, Database = Sqlite>,
+    profile: &AiProfile,
+    overwrite: bool,
+) -> Result<bool, String> {
+    let json = serde_json::to_string(&profile.without_api_key())
+        .map_err(|e| format!("Failed to serialize AI profile: {}", e))?;
+    let sql = format!(
+        "INSERT OR {} INTO ai_profiles (story_id, profile, updated_at) \
+         SELECT ?, ?, ? WHERE EXISTS (SELECT 1 FROM stories WHERE id = ?)",
+        if overwrite { "REPLACE" } else { "IGNORE" }
+    );
+    let result = sqlx::query(&sql)
+        .bind(&profile.story_id)
+        .bind(json)
+        .bind(profile.updated_at)
+        .bind(&profile.story_id)
+        .execute(db)
+        .await
+        .map_err(|e| format!("Failed to save AI profile: {}", e))?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Move profiles from the old file into the database, keeping those whose
+/// story is not in it yet
+async fn adopt_legacy_file(app: &AppHandle, pool: &SqlitePool) -> Result<(), String> {
+    let path = store::data_file(app, LEGACY_AI_PROFILES_FILE)?;
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut legacy: AiProfiles = store::load_json(app, LEGACY_AI_PROFILES_FILE)?;
+    move_keys_to_keychain(legacy.values_mut())?;
+    let mut adopted = Vec::new();
+    for profile in legacy.values() {
+        let (exists,): (bool,) =
+            sqlx::query_as("SELECT EXISTS (SELECT 1 FROM stories WHERE id = ?)")
+                .bind(&profile.story_id)
+                .fetch_one(pool)
+                .await
+                .map_err(|e| format!("Failed to read stories: {}", e))?;
+        if exists {
+            write(pool, profile, false).await?;
+            adopted.push(profile.story_id.clone());
+        }
+    }
+    for story_id in adopted {
+        legacy.remove(&story_id);
+    }
+    match legacy.is_empty() {
+        true => std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", LEGACY_AI_PROFILES_FILE, e)),
+        false => store::save_json(app, LEGACY_AI_PROFILES_FILE, &legacy),
+    }
+}
+
+async fn open(app: &AppHandle) -> Result<SqlitePool, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    if let Err(e) = adopt_legacy_file(app, &pool).await {
+        eprintln!("Failed to move AI profiles into the database: {}", e);
+    }
+    Ok(pool)
+}
+
+fn parse(story_id: &str, json: &str) -> Option<AiProfile> {
+    match serde_json::from_str(json) {
+        Ok(profile) => Some(profile),
+        Err(e) => {
+            eprintln!("Invalid AI profile for story {}: {}", story_id, e);
+            None
+        }
+    }
+}
+
+/// Every saved profile, without API keys
+pub async fn load_profiles(app: &AppHandle) -> Result<AiProfiles, String> {
+    let pool = open(app).await?;
+    let rows: Result<Vec<(String, String)>, String> =
+        sqlx::query_as("SELECT story_id, profile FROM ai_profiles")
+            .fetch_all(&pool)
+            .await
+            .map_err(|e| format!("Failed to read AI profiles: {}", e));
+    pool.close().await;
+    Ok(rows?
+        .into_iter()
+        .filter_map(|(story_id, json)| Some((story_id.clone(), parse(&story_id, &json)?)))
+        .collect())
+}
+
+/// A story's profile, without its API key
+pub async fn load_profile(app: &AppHandle, story_id: &str) -> Result<Option<AiProfile>, String> {
+    let pool = open(app).await?;
+    let row: Result<Option<(String,)>, String> =
+        sqlx::query_as("SELECT profile FROM ai_profiles WHERE story_id = ?")
+            .bind(story_id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(|e| format!("Failed to read AI profile: {}", e));
+    pool.close().await;
+    Ok(row?.and_then(|(json,)| parse(story_id, &json)))
+}
+
+/// Create or replace a story's profile, moving the API key it carries to
+/// the keychain. Returns the profile as stored.
+pub async fn save_profile(app: &AppHandle, mut profile: AiProfile) -> Result<AiProfile, String> {
+    move_keys_to_keychain([&mut profile])?;
+    let pool = open(app).await?;
+    let written = write(&pool, &profile, true).await;
+    pool.close().await;
+    if !written? {
+        return Err(format!("Story not found: {}", profile.story_id));
+    }
+    Ok(profile)
+}
+
+/// Delete a story's profile and forget its API key. Returns whether there
+/// was one.
+pub async fn delete_profile(app: &AppHandle, story_id: &str) -> Result<bool, String> {
+    let pool = open(app).await?;
+    let result = sqlx::query("DELETE FROM ai_profiles WHERE story_id = ?")
+        .bind(story_id)
+        .execute(&pool)
+        .await
+        .map_err(|e| format!("Failed to delete AI profile: {}", e));
+    pool.close().await;
+    let removed = result?.rows_affected() > 0;
+    if removed {
+        keys::remove_from_keychain(&key_account(story_id))?;
+    }
+    Ok(removed)
+}
+
+/// Save imported profiles, returning how many were written. Existing
+/// profiles are only replaced with `overwrite` and keep their API key,
+/// since it stays in the keychain and exports never carry one. Profiles of
+/// stories not in the library are skipped.
+pub async fn import_profiles(
+    app: &AppHandle,
+    imported: Vec<AiProfile>,
+    overwrite: bool,
+) -> Result<usize, String> {
+    let pool = open(app).await?;
+    let saved = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        let mut written = 0;
+        for mut profile in imported {
+            profile.provider.api_key = None;
+            if write(&mut *tx, &profile, overwrite).await? {
+                written += 1;
+            }
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to save AI profiles: {}", e))?;
+        Ok(written)
+    }
+    .await;
+    pool.close().await;
+    saved
+}
+
+/// Provider from a story's AI profile, with its key from the keychain, for
+/// backend features that call the model on their own. Fails if the story
+/// has no profile or the current user profile may not use the provider.
+pub async fn story_provider(app: &AppHandle, story_id: &str) -> Result<ProviderConfig, String> {
+    let mut provider = load_profile(app, story_id)
+        .await?
+        .map(|p| p.provider)
+        .ok_or("Set up an AI profile for this story first")?;
+    profiles::check_generation(app, &provider.base_url, Strictness::Off)?;
+    provider.api_key = keys::read_from_keychain(&key_account(story_id))?;
+    Ok(provider)
+}
+
+/// Portable file format for sharing profiles; API keys are never included
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiProfileExport {
+    pub version: u32,
+    pub profiles: Vec<AiProfile>,
+}
+
+impl AiProfileExport {
+    pub fn new<'a>(profiles: impl IntoIterator<Item = &'a AiProfile>) -> Self {
+        Self {
+            version: PROFILE_EXPORT_VERSION,
+            profiles: profiles
+                .into_iter()
+                .map(AiProfile::without_api_key)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter};
+
+use super::filter::StreamFilter;
+use super::sampling;
+use super::trace::RawResponse;
+use super::types::{AiChunkEvent, AiStreamRequest, ChatMessage, ProviderConfig, SamplingParams};
+
+/// Phrases in the error body of a provider that does not take a JSON
+/// schema for the reply
+const SCHEMA_UNSUPPORTED_PHRASES: [&str; 4] = [
+    "response_format",
+    "response format",
+    "structured output",
+    "schema",
+];
+
+/// Result of a completed stream
+pub struct StreamOutcome {
+    pub content: String,
+    pub finish_reason: Option<String>,
+}
+
+/// Build the JSON body for an OpenAI-compatible chat completion request,
+/// with sampling in the provider's own field names
+fn build_request_body(
+    provider: &ProviderConfig,
+    messages: &[ChatMessage],
+    sampling: &SamplingParams,
+    stream: bool,
+) -> Value {
+    let mut body = json!({
+        "model": provider.model,
+        "messages": messages,
+        "stream": stream,
+    });
+    if let Some(obj) = body.as_object_mut() {
+        obj.extend(sampling::translate(&provider.base_url, sampling).fields);
+    }
+    body
+}
+
+/// Token counts a provider reported for a completion
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Reply to a non-streaming completion
+pub struct Completion {
+    pub content: String,
+    /// Missing when the provider does not report usage
+    pub usage: Option<Usage>,
+}
+
+/// Why a non-streaming completion failed
+enum CompletionError {
+    /// The provider answered with an error status
+    Status(reqwest::StatusCode, String),
+    Other(String),
+}
+
+impl From<CompletionError> for String {
+    fn from(error: CompletionError) -> Self {
+        match error {
+            CompletionError::Status(status, text) => {
+                format!("Provider returned {}: {}", status, text)
+            }
+            CompletionError::Other(message) => message,
+        }
+    }
+}
+
+async fn send_completion(
+    provider: &ProviderConfig,
+    body: &Value,
+) -> Result<Completion, CompletionError> {
+    let client = reqwest::Client::new();
+    let mut builder = client
+        .post(provider.chat_completions_url())
+        .json(body)
+        .timeout(std::time::Duration::from_secs(120));
+    if let Some(ref key) = provider.api_key {
+        builder = builder.bearer_auth(key);
+    }
+
+    let response = builder
+        .send()
+        .await
+        .map_err(|e| CompletionError::Other(format!("Connection failed: {}", e)))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(CompletionError::Status(status, text));
+    }
+
+    let value: Value = response
+        .json()
+        .await
+        .map_err(|e| CompletionError::Other(format!("Invalid response: {}", e)))?;
+    let content = value
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .and_then(|c| c.get("message"))
+        .and_then(|m| m.get("content"))
+        .and_then(|c| c.as_str())
+        .map(String::from)
+        .ok_or_else(|| {
+            CompletionError::Other("Provider response contained no message".to_string())
+        })?;
+    let usage = value.get("usage").map(|u| Usage {
+        prompt_tokens: u.get("prompt_tokens").and_then(Value::as_u64).unwrap_or(0),
+        completion_tokens: u
+            .get("completion_tokens")
+            .and_then(Value::as_u64)
+            .unwrap_or(0),
+    });
+    Ok(Completion { content, usage })
+}
+
+/// Send a non-streaming chat completion and return the reply text
+pub async fn complete_chat(
+    provider: &ProviderConfig,
+    messages: &[ChatMessage],
+    sampling: &SamplingParams,
+) -> Result<String, String> {
+    Ok(complete_chat_with_usage(provider, messages, sampling)
+        .await?
+        .content)
+}
+
+/// Like `complete_chat`, with the token usage the provider reported
+pub async fn complete_chat_with_usage(
+    provider: &ProviderConfig,
+    messages: &[ChatMessage],
+    sampling: &SamplingParams,
+) -> Result<Completion, String> {
+    let body = build_request_body(provider, messages, sampling, false);
+    Ok(send_completion(provider, &body).await?)
+}
+
+/// Whether a provider rejected a request because of its `response_format`,
+/// rather than for something asking again without it would not fix
+fn is_schema_unsupported(status: reqwest::StatusCode, body: &str) -> bool {
+    if status != reqwest::StatusCode::BAD_REQUEST
+        && status != reqwest::StatusCode::UNPROCESSABLE_ENTITY
+    {
+        return false;
+    }
+    let body = body.to_lowercase();
+    SCHEMA_UNSUPPORTED_PHRASES
+        .iter()
+        .any(|phrase| body.contains(phrase))
+}
+
+/// Send a non-streaming chat completion whose reply must match a JSON
+/// schema. Providers that reject structured output are asked again without
+/// it, so the reply may still need checking against the schema.
+pub async fn complete_structured(
+    provider: &ProviderConfig,
+    messages: &[ChatMessage],
+    sampling: &SamplingParams,
+    name: &str,
+    schema: &Value,
+) -> Result<String, String> {
+    let mut body = build_request_body(provider, messages, sampling, false);
+    body["response_format"] = json!({
+        "type": "json_schema",
+        "json_schema": { "name": name, "strict": true, "schema": schema },
+    });
+    match send_completion(provider, &body).await {
+        Err(CompletionError::Status(status, text)) if is_schema_unsupported(status, &text) => {
+            complete_chat(provider, messages, sampling).await
+        }
+        result => Ok(result?.content),
+    }
+}
+
+/// The JSON object in a reply that may wrap it in prose or a code fence
+pub fn json_object(reply: &str) -> Option<&str> {
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    (start < end).then(|| &reply[start..=end])
+}
+
+/// Extract the content delta and finish reason from one SSE `data:` payload
+fn parse_sse_data(data: &str) -> Option<(String, Option<String>)> {
+    let value: Value = serde_json::from_str(data).ok()?;
+    let choice = value.get("choices")?.get(0)?;
+    let delta = choice
+        .get("delta")
+        .and_then(|d| d.get("content"))
+        .and_then(|c| c.as_str())
+        .unwrap_or("")
+        .to_string();
+    let finish_reason = choice
+        .get("finish_reason")
+        .and_then(|r| r.as_str())
+        .map(String::from);
+    Some((delta, finish_reason))
+}
+
+/// Record a filtered delta as part of the final content and emit it,
+/// unless the stream's chunks are held back
+fn emit_delta(app: &AppHandle, request_id: &str, content: &mut String, delta: String, hold: bool) {
+    if delta.is_empty() {
+        return;
+    }
+    content.push_str(&delta);
+    if !hold {
+        emit_chunk(app, request_id, delta);
+    }
+}
+
+/// Send text to the UI as an `ai://chunk` event
+pub fn emit_chunk(app: &AppHandle, request_id: &str, delta: String) {
+    let _ = app.emit(
+        "ai://chunk",
+        AiChunkEvent {
+            request_id: request_id.to_string(),
+            delta,
+        },
+    );
+}
+
+/// The body a streaming request is sent with, extra fields included
+pub fn stream_body(request: &AiStreamRequest) -> Value {
+    let mut body = build_request_body(
+        &request.provider,
+        &request.messages,
+        &request.sampling,
+        true,
+    );
+    if let (Some(extra), Some(obj)) = (&request.extra_body, body.as_object_mut()) {
+        for (key, value) in extra {
+            obj.insert(key.clone(), value.clone());
+        }
+    }
+    body
+}
+
+/// Stream a chat completion, emitting filtered `ai://chunk` events as deltas arrive.
+/// With `hold`, no chunks are emitted and the caller sends the reply once it
+/// has checked it. With `raw`, what the provider sends is kept there as it
+/// arrives, for tracing. Dropping the returned future closes the HTTP
+/// connection, which is how cancellation reaches the provider.
+pub async fn stream_chat(
+    app: &AppHandle,
+    request_id: &str,
+    request: &AiStreamRequest,
+    mut filter: StreamFilter,
+    hold: bool,
+    mut raw: Option<&mut RawResponse>,
+) -> Result<StreamOutcome, String> {
+    let body = stream_body(request);
+
+    let client = reqwest::Client::new();
+    let mut builder = client
+        .post(request.provider.chat_completions_url())
+        .json(&body);
+    if let Some(ref key) = request.provider.api_key {
+        builder = builder.bearer_auth(key);
+    }
+
+    let mut response = builder
+        .send()
+        .await
+        .map_err(|e| format!("Connection failed: {}", e))?;
+    if let Some(raw) = raw.as_deref_mut() {
+        raw.status = Some(response.status().as_u16());
+    }
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        if let Some(raw) = raw.as_deref_mut() {
+            raw.push(&text);
+        }
+        return Err(format!("Provider returned {}: {}", status, text));
+    }
+
+    let mut content = String::new();
+    let mut finish_reason = None;
+    let mut buffer: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Stream interrupted: {}", e))?
+    {
+        buffer.extend_from_slice(&chunk);
+
+        // Process every complete line, keeping any partial line for the next chunk
+        while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+            let line_bytes: Vec<u8> = buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line_bytes);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if let Some(raw) = raw.as_deref_mut() {
+                raw.push(data);
+            }
+            if data == "[DONE]" {
+                let rest = filter.finish()?;
+                emit_delta(app, request_id, &mut content, rest, hold);
+                return Ok(StreamOutcome {
+                    content,
+                    finish_reason,
+                });
+            }
+
+            if let Some((delta, reason)) = parse_sse_data(data) {
+                if let Some(raw) = raw.as_deref_mut() {
+                    raw.text.push_str(&delta);
+                }
+                if reason.is_some() {
+                    finish_reason = reason;
+                }
+                let filtered = filter.push(&delta)?;
+                emit_delta(app, request_id, &mut content, filtered, hold);
+            }
+        }
+    }
+
+    let rest = filter.finish()?;
+    emit_delta(app, request_id, &mut content, rest, hold);
+    Ok(StreamOutcome {
+        content,
+        finish_reason,
+    })
+}
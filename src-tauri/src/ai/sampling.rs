@@ -0,0 +1,271 @@
+//! Named sampling presets and the translation of sampling parameters into
+//! the fields each provider's API expects. Providers that speak the OpenAI
+//! format still differ in what they accept: some name the repetition
+//! penalty differently, some take no logit biases and OpenAI allows at
+//! most four stop sequences. Parameters a provider cannot take are left
+//! out rather than failing the request.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use tauri::AppHandle;
+
+use super::profile;
+use super::types::SamplingParams;
+use crate::store;
+
+/// Saved presets keyed by name, in the app data directory
+pub const SAMPLING_PRESETS_FILE: &str = "sampling_presets.json";
+
+const MAX_STOP_SEQUENCES: usize = 16;
+
+/// OpenAI and Groq reject more stop sequences than this
+const OPENAI_MAX_STOP_SEQUENCES: usize = 4;
+
+const MAX_LOGIT_BIASES: usize = 300;
+
+/// Reusable sampling settings an AI profile can refer to by name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SamplingPreset {
+    pub name: String,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// Above 1 discourages repeating tokens, below 1 encourages it
+    #[serde(default)]
+    pub repetition_penalty: Option<f32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+    /// Bias from -100 to 100 keyed by token ID
+    #[serde(default)]
+    pub logit_bias: BTreeMap<String, f32>,
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+impl SamplingPreset {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Sampling preset needs a name".to_string());
+        }
+        self.params().validate()
+    }
+
+    pub fn params(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            repetition_penalty: self.repetition_penalty,
+            stop: self.stop.clone(),
+            logit_bias: self.logit_bias.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+pub type SamplingPresets = HashMap<String, SamplingPreset>;
+
+impl SamplingParams {
+    /// Check the values are in ranges every provider accepts
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(format!(
+                    "Temperature must be between 0 and 2, got {}",
+                    temperature
+                ));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(top_p > 0.0 && top_p <= 1.0) {
+                return Err(format!(
+                    "Top P must be above 0 and at most 1, got {}",
+                    top_p
+                ));
+            }
+        }
+        if let Some(penalty) = self.repetition_penalty {
+            if !(penalty > 0.0 && penalty <= 2.0) {
+                return Err(format!(
+                    "Repetition penalty must be above 0 and at most 2, got {}",
+                    penalty
+                ));
+            }
+        }
+        if self.stop.len() > MAX_STOP_SEQUENCES {
+            return Err(format!(
+                "At most {} stop sequences are allowed",
+                MAX_STOP_SEQUENCES
+            ));
+        }
+        if self.stop.iter().any(|s| s.is_empty()) {
+            return Err("Stop sequences cannot be empty".to_string());
+        }
+        if self.logit_bias.len() > MAX_LOGIT_BIASES {
+            return Err(format!(
+                "At most {} logit biases are allowed",
+                MAX_LOGIT_BIASES
+            ));
+        }
+        for (token, bias) in &self.logit_bias {
+            if token.parse::<u32>().is_err() {
+                return Err(format!("Logit bias key '{}' is not a token ID", token));
+            }
+            if !(-100.0..=100.0).contains(bias) {
+                return Err(format!(
+                    "Logit bias for token {} must be between -100 and 100, got {}",
+                    token, bias
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which API dialect a provider speaks, judged from its base URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProviderApi {
+    OpenAi,
+    OpenRouter,
+    Anthropic,
+    Mistral,
+    Groq,
+    Ollama,
+    LlamaCpp,
+    KoboldCpp,
+    /// Any other OpenAI-compatible server, such as vLLM or
+    /// text-generation-webui
+    Generic,
+}
+
+impl ProviderApi {
+    pub fn detect(base_url: &str) -> ProviderApi {
+        let Ok(url) = reqwest::Url::parse(base_url.trim()) else {
+            return ProviderApi::Generic;
+        };
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let hosted = |domain: &str| host == domain || host.ends_with(&format!(".{}", domain));
+        if hosted("openai.com") {
+            ProviderApi::OpenAi
+        } else if hosted("openrouter.ai") {
+            ProviderApi::OpenRouter
+        } else if hosted("anthropic.com") {
+            ProviderApi::Anthropic
+        } else if hosted("mistral.ai") {
+            ProviderApi::Mistral
+        } else if hosted("groq.com") {
+            ProviderApi::Groq
+        } else {
+            // Local servers are told apart by their default ports
+            match url.port() {
+                Some(11434) => ProviderApi::Ollama,
+                Some(5001) => ProviderApi::KoboldCpp,
+                Some(8080) => ProviderApi::LlamaCpp,
+                _ => ProviderApi::Generic,
+            }
+        }
+    }
+}
+
+/// Sampling parameters as a provider's request fields
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SamplingTranslation {
+    pub api: ProviderApi,
+    pub fields: Map<String, Value>,
+    /// Parameters the provider does not take, left out of the request
+    pub dropped: Vec<String>,
+}
+
+pub fn translate(base_url: &str, sampling: &SamplingParams) -> SamplingTranslation {
+    let api = ProviderApi::detect(base_url);
+    let mut fields = Map::new();
+    let mut dropped = Vec::new();
+
+    if let Some(mut temperature) = sampling.temperature {
+        // Anthropic's temperature only goes up to 1
+        if api == ProviderApi::Anthropic {
+            temperature = temperature.min(1.0);
+        }
+        fields.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = sampling.top_p {
+        fields.insert("top_p".to_string(), json!(top_p));
+    }
+    if let Some(max_tokens) = sampling.max_tokens {
+        fields.insert("max_tokens".to_string(), json!(max_tokens));
+    }
+    if let Some(seed) = sampling.seed {
+        let key = match api {
+            ProviderApi::Mistral => "random_seed",
+            _ => "seed",
+        };
+        fields.insert(key.to_string(), json!(seed));
+    }
+    if let Some(penalty) = sampling.repetition_penalty {
+        let key = match api {
+            ProviderApi::OpenRouter | ProviderApi::Generic => Some("repetition_penalty"),
+            ProviderApi::LlamaCpp => Some("repeat_penalty"),
+            ProviderApi::KoboldCpp => Some("rep_pen"),
+            _ => None,
+        };
+        match key {
+            Some(key) => {
+                fields.insert(key.to_string(), json!(penalty));
+            }
+            None => dropped.push("repetitionPenalty".to_string()),
+        }
+    }
+    if !sampling.stop.is_empty() {
+        let limit = match api {
+            ProviderApi::OpenAi | ProviderApi::Groq => OPENAI_MAX_STOP_SEQUENCES,
+            _ => MAX_STOP_SEQUENCES,
+        };
+        if sampling.stop.len() > limit {
+            dropped.extend(
+                sampling.stop[limit..]
+                    .iter()
+                    .map(|s| format!("stop: {:?}", s)),
+            );
+        }
+        let stop: Vec<&String> = sampling.stop.iter().take(limit).collect();
+        fields.insert("stop".to_string(), json!(stop));
+    }
+    if !sampling.logit_bias.is_empty() {
+        match api {
+            ProviderApi::Anthropic
+            | ProviderApi::Mistral
+            | ProviderApi::Groq
+            | ProviderApi::Ollama => dropped.push("logitBias".to_string()),
+            _ => {
+                fields.insert("logit_bias".to_string(), json!(sampling.logit_bias));
+            }
+        }
+    }
+
+    SamplingTranslation {
+        api,
+        fields,
+        dropped,
+    }
+}
+
+/// Sampling from the preset a story's AI profile names, if any. A profile
+/// naming a preset that is gone, such as one imported from another device,
+/// leaves the request's own sampling as it is.
+pub async fn story_sampling(
+    app: &AppHandle,
+    story_id: &str,
+) -> Result<Option<SamplingParams>, String> {
+    let Some(name) = profile::load_profile(app, story_id)
+        .await?
+        .and_then(|p| p.sampling_preset)
+    else {
+        return Ok(None);
+    };
+    let presets: SamplingPresets = store::load_json(app, SAMPLING_PRESETS_FILE)?;
+    Ok(presets.get(&name).map(SamplingPreset::params))
+}
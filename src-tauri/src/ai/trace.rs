@@ -0,0 +1,373 @@
+//! Opt-in trace of a story's AI calls: the request body exactly as sent,
+//! the raw streamed payloads and how the call ended, one JSON line per call
+//! in a file per story. Traces are exported redacted for debugging prompts
+//! or attaching to a provider bug report. API keys are never recorded.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use super::proxy::{stream_body, StreamOutcome};
+use super::types::AiStreamRequest;
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Directory in the app data directory holding a trace file per story
+pub const AI_TRACES_DIR: &str = "ai_traces";
+
+pub const AI_TRACE_CONFIG_FILE: &str = "ai_trace_config.json";
+
+/// Raw response payloads kept per call, in bytes. Longer streams are cut
+/// off and marked truncated.
+const MAX_RAW_BYTES: usize = 256 * 1024;
+
+/// Records over the limit tolerated before the file is rewritten, so every
+/// call doesn't rewrite it
+const PRUNE_SLACK: usize = 20;
+
+const TRACE_EXPORT_VERSION: u32 = 1;
+
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TraceConfig {
+    /// Record calls at all; off unless the user turns it on
+    pub enabled: bool,
+    /// Calls kept per story, newest
+    pub max_records: usize,
+    /// Words replaced in exports on top of keys and email addresses, such
+    /// as the user's or characters' names
+    pub redact_terms: Vec<String>,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_records: 200,
+            redact_terms: Vec::new(),
+        }
+    }
+}
+
+/// State managed by Tauri for AI traces
+#[derive(Default)]
+pub struct TraceState {
+    /// Records in each story's trace file, counted on the first write since
+    /// launch. The lock serializes writes to the files.
+    counts: std::sync::Mutex<HashMap<String, usize>>,
+}
+
+/// What a provider sent back, collected while streaming
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawResponse {
+    /// HTTP status, when a response arrived at all
+    pub status: Option<u16>,
+    /// Each SSE `data:` payload, or the error body
+    pub payloads: Vec<String>,
+    /// The streamed text before the content filter
+    pub text: String,
+    /// Payloads were dropped past `MAX_RAW_BYTES`
+    pub truncated: bool,
+    #[serde(skip)]
+    bytes: usize,
+}
+
+impl RawResponse {
+    pub fn push(&mut self, payload: &str) {
+        if self.bytes + payload.len() > MAX_RAW_BYTES {
+            self.truncated = true;
+            return;
+        }
+        self.bytes += payload.len();
+        self.payloads.push(payload.to_string());
+    }
+}
+
+/// One recorded call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceRecord {
+    pub id: String,
+    pub story_id: String,
+    pub request_id: String,
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// Chat completions URL, without credentials or query
+    pub endpoint: String,
+    /// Body sent to the provider: model, messages, sampling and extra fields
+    pub request: Value,
+    pub response: RawResponse,
+    /// Text delivered to the app after filtering
+    pub content: Option<String>,
+    pub finish_reason: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Calls started within these times, in milliseconds, inclusive
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TraceRange {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+impl TraceRange {
+    fn contains(&self, time: i64) -> bool {
+        self.from.is_none_or(|from| time >= from) && self.to.is_none_or(|to| time <= to)
+    }
+}
+
+fn trace_path(app: &AppHandle, story_id: &str) -> Result<PathBuf, String> {
+    if story_id.is_empty() || story_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid story ID: {}", story_id));
+    }
+    let dir = store::data_file(app, AI_TRACES_DIR)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create trace directory: {}", e))?;
+    Ok(dir.join(format!("{}.jsonl", story_id)))
+}
+
+/// Whether calls are being recorded
+pub fn enabled(app: &AppHandle) -> bool {
+    store::load_json::<TraceConfig>(app, AI_TRACE_CONFIG_FILE).is_ok_and(|c| c.enabled)
+}
+
+/// The base URL with any user name, password or query removed, since
+/// some providers take keys there
+fn endpoint(request: &AiStreamRequest) -> String {
+    let url = request.provider.chat_completions_url();
+    match reqwest::Url::parse(&url) {
+        Ok(mut parsed) => {
+            let _ = parsed.set_username("");
+            let _ = parsed.set_password(None);
+            parsed.set_query(None);
+            parsed.to_string()
+        }
+        Err(_) => url,
+    }
+}
+
+fn read(app: &AppHandle, story_id: &str) -> Result<Vec<TraceRecord>, String> {
+    let path = trace_path(app, story_id)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read AI trace: {}", e))?;
+    // A line cut short by a crash is skipped rather than losing the trace
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// A call being traced, recorded once it ends
+pub struct PendingTrace {
+    story_id: String,
+    request_id: String,
+    started_at: i64,
+    endpoint: String,
+    body: Value,
+    /// Filled in by `stream_chat`
+    pub response: RawResponse,
+}
+
+impl PendingTrace {
+    pub fn new(story_id: &str, request_id: &str, request: &AiStreamRequest) -> Self {
+        Self {
+            story_id: story_id.to_string(),
+            request_id: request_id.to_string(),
+            started_at: now_ms(),
+            endpoint: endpoint(request),
+            body: stream_body(request),
+            response: RawResponse::default(),
+        }
+    }
+}
+
+fn write_all(path: &Path, records: &[TraceRecord]) -> Result<(), String> {
+    let mut contents = String::new();
+    for record in records {
+        contents.push_str(
+            &serde_json::to_string(record)
+                .map_err(|e| format!("Failed to serialize AI trace: {}", e))?,
+        );
+        contents.push('\n');
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write AI trace: {}", e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to save AI trace: {}", e))
+}
+
+/// Append a finished call to the story's trace, dropping the oldest calls
+/// once the trace is well over its limit
+pub fn record(
+    app: &AppHandle,
+    trace: PendingTrace,
+    result: &Result<StreamOutcome, String>,
+) -> Result<(), String> {
+    let config: TraceConfig = store::load_json(app, AI_TRACE_CONFIG_FILE)?;
+    let (content, finish_reason, error) = match result {
+        Ok(outcome) => (
+            Some(outcome.content.clone()),
+            outcome.finish_reason.clone(),
+            None,
+        ),
+        Err(e) => (None, None, Some(e.clone())),
+    };
+    let record = TraceRecord {
+        id: Uuid::new_v4().to_string(),
+        story_id: trace.story_id,
+        request_id: trace.request_id,
+        started_at: trace.started_at,
+        finished_at: now_ms(),
+        endpoint: trace.endpoint,
+        request: trace.body,
+        response: trace.response,
+        content,
+        finish_reason,
+        error,
+    };
+    let line = serde_json::to_string(&record)
+        .map_err(|e| format!("Failed to serialize AI trace: {}", e))?;
+
+    let state = app.state::<TraceState>();
+    let mut counts = state
+        .counts
+        .lock()
+        .map_err(|_| "AI traces are unavailable".to_string())?;
+    let count = match counts.get(&record.story_id) {
+        Some(count) => *count,
+        None => read(app, &record.story_id)?.len(),
+    };
+    let path = trace_path(app, &record.story_id)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open AI trace: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to write AI trace: {}", e))?;
+
+    let mut count = count + 1;
+    if count > config.max_records + PRUNE_SLACK {
+        let records = read(app, &record.story_id)?;
+        let kept = &records[records.len().saturating_sub(config.max_records)..];
+        write_all(&path, kept)?;
+        count = kept.len();
+    }
+    counts.insert(record.story_id, count);
+    Ok(())
+}
+
+/// Forget a story's recorded calls. Returns whether there were any.
+pub fn clear(app: &AppHandle, story_id: &str) -> Result<bool, String> {
+    let state = app.state::<TraceState>();
+    let mut counts = state
+        .counts
+        .lock()
+        .map_err(|_| "AI traces are unavailable".to_string())?;
+    counts.remove(story_id);
+    let path = trace_path(app, story_id)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(&path).map_err(|e| format!("Failed to delete AI trace: {}", e))?;
+    Ok(true)
+}
+
+/// API keys and bearer tokens as providers format them
+fn secret() -> &'static Regex {
+    static SECRET: OnceLock<Regex> = OnceLock::new();
+    SECRET.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:sk|pk|rk|key|api|hf|gsk|xai)[-_][A-Za-z0-9_\-]{16,}|\bbearer\s+[A-Za-z0-9._\-]{8,}")
+            .unwrap()
+    })
+}
+
+fn email() -> &'static Regex {
+    static EMAIL: OnceLock<Regex> = OnceLock::new();
+    EMAIL.get_or_init(|| Regex::new(r"[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}").unwrap())
+}
+
+/// Replaces secrets, email addresses and the configured terms in every
+/// string of a JSON value, counting what it replaced
+struct Redactor {
+    terms: Option<Regex>,
+    count: usize,
+}
+
+impl Redactor {
+    fn new(terms: &[String]) -> Result<Self, String> {
+        let terms: Vec<String> = terms
+            .iter()
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(regex::escape)
+            .collect();
+        let terms = if terms.is_empty() {
+            None
+        } else {
+            Some(
+                Regex::new(&format!(r"(?i)\b(?:{})\b", terms.join("|")))
+                    .map_err(|e| format!("Invalid redaction terms: {}", e))?,
+            )
+        };
+        Ok(Self { terms, count: 0 })
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+        for pattern in [Some(secret()), Some(email()), self.terms.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            let found = pattern.find_iter(&text).count();
+            if found > 0 {
+                self.count += found;
+                text = pattern.replace_all(&text, REDACTED).into_owned();
+            }
+        }
+        text
+    }
+
+    fn value(&mut self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.text(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.value(item)),
+            Value::Object(fields) => fields.values_mut().for_each(|field| self.value(field)),
+            _ => {}
+        }
+    }
+}
+
+/// The story's calls within the range as redacted JSON, oldest first
+pub fn export(app: &AppHandle, story_id: &str, range: TraceRange) -> Result<String, String> {
+    let config: TraceConfig = store::load_json(app, AI_TRACE_CONFIG_FILE)?;
+    let records: Vec<TraceRecord> = read(app, story_id)?
+        .into_iter()
+        .filter(|r| range.contains(r.started_at))
+        .collect();
+    let mut records = serde_json::to_value(&records)
+        .map_err(|e| format!("Failed to serialize AI trace: {}", e))?;
+    let mut redactor = Redactor::new(&config.redact_terms)?;
+    redactor.value(&mut records);
+    let export = json!({
+        "version": TRACE_EXPORT_VERSION,
+        "storyId": story_id,
+        "exportedAt": now_ms(),
+        "range": range,
+        "redactions": redactor.count,
+        "records": records,
+    });
+    serde_json::to_string_pretty(&export)
+        .map_err(|e| format!("Failed to serialize AI trace: {}", e))
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter};
+
+use super::proxy::complete_chat;
+use super::types::{ChatMessage, ProviderConfig, SamplingParams};
+use crate::store;
+use crate::story::StoryExport;
+
+/// File in the app data directory caching translated passages
+pub const TRANSLATION_CACHE_FILE: &str = "translation_cache.json";
+
+/// Key in `story` linking a translation to its source, kept in the stories
+/// table's `variant_of` column
+const VARIANT_KEY: &str = "variantOf";
+
+/// The story a translated variant was made from, stored on the variant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariantLink {
+    pub source_story_id: String,
+    pub language: String,
+}
+
+/// Cached translations keyed by a hash of model, language and source text
+pub type TranslationCache = HashMap<String, String>;
+
+/// Payload of the `translate://progress` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslationProgress {
+    pub story_id: String,
+    pub completed: usize,
+    pub total: usize,
+}
+
+/// Result of translating a story
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslationResult {
+    /// The translated variant in Aventura export format, with a fresh story ID
+    pub story_json: String,
+    pub source_story_id: String,
+    pub target_language: String,
+    pub translated_count: usize,
+    pub cached_count: usize,
+}
+
+fn cache_key(model: &str, language: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model.as_bytes());
+    hasher.update([0]);
+    hasher.update(language.as_bytes());
+    hasher.update([0]);
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Translate one passage, consulting the cache first.
+/// Returns the translation and whether it came from the cache.
+async fn translate_text(
+    provider: &ProviderConfig,
+    cache: &mut TranslationCache,
+    text: &str,
+    target_language: &str,
+) -> Result<(String, bool), String> {
+    let key = cache_key(&provider.model, target_language, text);
+    if let Some(cached) = cache.get(&key) {
+        return Ok((cached.clone(), true));
+    }
+
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: format!(
+                "You are a literary translator. Translate the user's text into {}. \
+                 Preserve paragraph breaks, formatting, tone, and proper names. \
+                 Reply with the translation only.",
+                target_language
+            ),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: text.to_string(),
+        },
+    ];
+    let sampling = SamplingParams {
+        temperature: Some(0.3),
+        ..Default::default()
+    };
+
+    let translated = complete_chat(provider, &messages, &sampling)
+        .await?
+        .trim()
+        .to_string();
+    cache.insert(key, translated.clone());
+    Ok((translated, false))
+}
+
+/// Translate a story's title, description, and entries into a new story variant
+/// linked to its source. Emits `translate://progress` after every entry and saves the cache as it goes,
+/// so an interrupted run resumes without re-translating finished entries.
+pub async fn translate_story(
+    app: &AppHandle,
+    mut export: StoryExport,
+    target_language: &str,
+    provider: &ProviderConfig,
+) -> Result<TranslationResult, String> {
+    let mut cache: TranslationCache = store::load_json(app, TRANSLATION_CACHE_FILE)?;
+
+    let source_story_id = export.story.id.clone();
+    let total = export.entries.len();
+    let mut translated_count = 0;
+    let mut cached_count = 0;
+
+    let (title, _) =
+        translate_text(provider, &mut cache, &export.story.title, target_language).await?;
+    export.story.title = title;
+    if let Some(description) = export
+        .story
+        .description
+        .clone()
+        .filter(|d| !d.trim().is_empty())
+    {
+        let (translated, _) =
+            translate_text(provider, &mut cache, &description, target_language).await?;
+        export.story.description = Some(translated);
+    }
+
+    for (index, entry) in export.entries.iter_mut().enumerate() {
+        if !entry.content.trim().is_empty() {
+            let (translated, from_cache) =
+                translate_text(provider, &mut cache, &entry.content, target_language).await?;
+            entry.content = translated;
+            if from_cache {
+                cached_count += 1;
+            } else {
+                translated_count += 1;
+                store::save_json(app, TRANSLATION_CACHE_FILE, &cache)?;
+            }
+        }
+
+        let _ = app.emit(
+            "translate://progress",
+            TranslationProgress {
+                story_id: source_story_id.clone(),
+                completed: index + 1,
+                total,
+            },
+        );
+    }
+    store::save_json(app, TRANSLATION_CACHE_FILE, &cache)?;
+
+    // The frontend importer assigns fresh IDs to everything else; the story ID
+    // is replaced here so the variant never collides with its source.
+    let story_id = uuid::Uuid::new_v4().to_string();
+    for entry in &mut export.entries {
+        entry.story_id = story_id.clone();
+    }
+    export.story.id = story_id;
+    export.story.extra.insert(
+        VARIANT_KEY.to_string(),
+        json!(VariantLink {
+            source_story_id: source_story_id.clone(),
+            language: target_language.to_string(),
+        }),
+    );
+    export.exported_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+
+    Ok(TranslationResult {
+        story_json: export.to_json()?,
+        source_story_id,
+        target_language: target_language.to_string(),
+        translated_count,
+        cached_count,
+    })
+}
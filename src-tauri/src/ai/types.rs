@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A single chat message in OpenAI-compatible format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+/// Sampling parameters for a completion request.
+/// Unset fields are left to the provider's defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SamplingParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub repetition_penalty: Option<f32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+    /// Bias from -100 to 100 keyed by token ID
+    #[serde(default)]
+    pub logit_bias: BTreeMap<String, f32>,
+}
+
+impl SamplingParams {
+    /// Overlay another set of parameters on top of this one
+    pub fn merged_with(&self, other: &SamplingParams) -> SamplingParams {
+        let mut logit_bias = self.logit_bias.clone();
+        logit_bias.extend(other.logit_bias.clone());
+        SamplingParams {
+            temperature: other.temperature.or(self.temperature),
+            top_p: other.top_p.or(self.top_p),
+            max_tokens: other.max_tokens.or(self.max_tokens),
+            seed: other.seed.or(self.seed),
+            repetition_penalty: other.repetition_penalty.or(self.repetition_penalty),
+            stop: if other.stop.is_empty() {
+                self.stop.clone()
+            } else {
+                other.stop.clone()
+            },
+            logit_bias,
+        }
+    }
+}
+
+/// OpenAI-compatible endpoint and model to send requests to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderConfig {
+    /// Provider base URL (e.g. "https://openrouter.ai/api/v1")
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+impl ProviderConfig {
+    /// URL of the chat completions endpoint
+    pub fn chat_completions_url(&self) -> String {
+        format!("{}/chat/completions", self.base_url.trim_end_matches('/'))
+    }
+}
+
+/// Streaming chat completion request forwarded by the AI proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiStreamRequest {
+    #[serde(flatten)]
+    pub provider: ProviderConfig,
+    /// Story the generation belongs to, used to pick the filter strictness,
+    /// to build the conversation from the story's context strategy and to
+    /// remind the model of the story's open quests
+    #[serde(default)]
+    pub story_id: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub sampling: SamplingParams,
+    /// Provider-specific fields merged into the request body as-is
+    #[serde(default)]
+    pub extra_body: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Payload of the `ai://chunk` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiChunkEvent {
+    pub request_id: String,
+    pub delta: String,
+}
+
+/// Payload of the `ai://done` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiDoneEvent {
+    pub request_id: String,
+    /// The reply after post-processing, which may differ from the chunks
+    pub content: String,
+    pub finish_reason: Option<String>,
+    /// Post-processors that changed the reply, in the order they ran
+    pub post_processors: Vec<String>,
+}
+
+/// Payload of the `ai://error` and `ai://cancelled` events
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiStatusEvent {
+    pub request_id: String,
+    pub message: String,
+}
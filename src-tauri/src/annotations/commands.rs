@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use super::feedback::{self, FeedbackImport};
+use super::{Annotation, AnnotationKind, AnnotationPatch};
+use crate::profiles;
+use crate::story::rows;
+
+/// State managed by Tauri for annotations
+#[derive(Default)]
+pub struct AnnotationState {
+    /// Serializes changes to the annotations file
+    pub(crate) writes: std::sync::Mutex<()>,
+}
+
+/// A story's annotations, optionally only those on one entry. Resolved
+/// ones are included unless `include_resolved` is false.
+#[tauri::command]
+pub async fn list_annotations(
+    app: AppHandle,
+    story_id: String,
+    entry_id: Option<String>,
+    include_resolved: Option<bool>,
+) -> Result<Vec<Annotation>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::list(
+        &app,
+        &story_id,
+        entry_id.as_deref(),
+        include_resolved.unwrap_or(true),
+    )
+}
+
+/// Attach a comment, TODO or AI feedback to an entry
+#[tauri::command]
+pub async fn add_annotation(
+    app: AppHandle,
+    story_id: String,
+    entry_id: String,
+    kind: AnnotationKind,
+    text: String,
+    quote: Option<String>,
+) -> Result<Annotation, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::add(&app, &story_id, &entry_id, kind, &text, quote)
+}
+
+#[tauri::command]
+pub async fn update_annotation(
+    app: AppHandle,
+    story_id: String,
+    annotation_id: String,
+    patch: AnnotationPatch,
+) -> Result<Annotation, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::edit(&app, &story_id, &annotation_id, patch)
+}
+
+#[tauri::command]
+pub async fn delete_annotation(
+    app: AppHandle,
+    story_id: String,
+    annotation_id: String,
+) -> Result<bool, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::delete(&app, &story_id, &annotation_id)
+}
+
+/// Unresolved TODOs across the library, oldest first
+#[tauri::command]
+pub async fn list_open_todos(app: AppHandle) -> Result<Vec<Annotation>, String> {
+    super::open_todos(&app).await
+}
+
+/// Attach reviewers' feedback to the entries it is about: comments and
+/// tracked changes from a Word document, or a CSV or Markdown file pairing
+/// quoted passages with comments. Passages are matched to entries loosely,
+/// so small edits since the file was exported do not lose the feedback.
+#[tauri::command]
+pub async fn import_feedback(
+    app: AppHandle,
+    story_id: String,
+    file: String,
+) -> Result<FeedbackImport, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let (format, items) = tokio::task::spawn_blocking(move || feedback::read(&PathBuf::from(file)))
+        .await
+        .map_err(|e| format!("Feedback import failed: {}", e))??;
+    feedback::attach(&app, &export, format, items)
+}
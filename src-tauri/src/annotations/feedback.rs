@@ -0,0 +1,521 @@
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use tauri::AppHandle;
+
+use super::{Annotation, AnnotationKind, MAX_ANNOTATION_CHARS};
+use crate::story::text::plain_text;
+use crate::story::StoryExport;
+
+/// Share of a passage's word triples an entry must contain to be its match
+const MIN_MATCH: f64 = 0.5;
+
+/// Characters of text around a tracked change used to find its entry
+const CHANGE_CONTEXT_CHARS: usize = 80;
+
+/// Largest document part read from a DOCX file
+const MAX_PART_BYTES: u64 = 64 * 1024 * 1024;
+
+const QUOTE_COLUMNS: &[&str] = &["quote", "passage", "excerpt", "selection", "text"];
+const COMMENT_COLUMNS: &[&str] = &["comment", "note", "feedback", "remark"];
+const AUTHOR_COLUMNS: &[&str] = &["author", "reviewer", "name"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FeedbackFormat {
+    Docx,
+    Csv,
+    Markdown,
+}
+
+/// A reviewer's remark about a passage of the story
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedbackItem {
+    /// The passage the remark is about, used to find its entry
+    pub quote: String,
+    pub text: String,
+    pub author: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedbackImport {
+    pub story_id: String,
+    pub format: FeedbackFormat,
+    pub attached: Vec<Annotation>,
+    /// Feedback whose passage was not found in any entry
+    pub unmatched: Vec<FeedbackItem>,
+}
+
+pub fn format_of(path: &Path) -> Result<FeedbackFormat, String> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "docx" => Ok(FeedbackFormat::Docx),
+        "csv" => Ok(FeedbackFormat::Csv),
+        "md" | "markdown" | "txt" => Ok(FeedbackFormat::Markdown),
+        _ => Err(format!(
+            "Unsupported feedback file: {} (expected .docx, .csv or .md)",
+            path.display()
+        )),
+    }
+}
+
+/// Read the feedback in a file
+pub fn read(path: &Path) -> Result<(FeedbackFormat, Vec<FeedbackItem>), String> {
+    let format = format_of(path)?;
+    let items = match format {
+        FeedbackFormat::Docx => read_docx(path)?,
+        FeedbackFormat::Csv => parse_csv(&read_text(path)?)?,
+        FeedbackFormat::Markdown => parse_markdown(&read_text(path)?),
+    };
+    Ok((format, items))
+}
+
+fn read_text(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read feedback file: {}", e))
+}
+
+// --- DOCX ---
+
+fn read_docx(path: &Path) -> Result<Vec<FeedbackItem>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open document: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Not a Word document: {}", e))?;
+    let mut part = |name: &str| -> Result<Option<String>, String> {
+        let Ok(entry) = archive.by_name(name) else {
+            return Ok(None);
+        };
+        let mut xml = String::new();
+        entry
+            .take(MAX_PART_BYTES)
+            .read_to_string(&mut xml)
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        Ok(Some(xml))
+    };
+    let document = part("word/document.xml")?.ok_or("Not a Word document: no word/document.xml")?;
+    let comments = part("word/comments.xml")?.unwrap_or_default();
+    parse_docx(&document, &comments)
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// Text, tab and break elements of WordprocessingML, as text
+struct WordText {
+    /// Inside `w:t` or `w:delText`
+    in_text: bool,
+}
+
+impl WordText {
+    /// What an event adds to the text, if anything
+    fn text(&mut self, event: &Event) -> Option<String> {
+        match event {
+            Event::Start(e) if matches!(e.name().as_ref(), b"w:t" | b"w:delText") => {
+                self.in_text = true;
+                None
+            }
+            Event::End(e) if matches!(e.name().as_ref(), b"w:t" | b"w:delText") => {
+                self.in_text = false;
+                None
+            }
+            Event::Empty(e) => match e.name().as_ref() {
+                b"w:tab" => Some(" ".to_string()),
+                b"w:br" | b"w:cr" => Some("\n".to_string()),
+                _ => None,
+            },
+            Event::End(e) if e.name().as_ref() == b"w:p" => Some("\n".to_string()),
+            Event::Text(t) if self.in_text => t.decode().ok().map(|t| t.into_owned()),
+            Event::GeneralRef(r) if self.in_text => match r.resolve_char_ref() {
+                Ok(Some(c)) => Some(c.to_string()),
+                _ => r
+                    .decode()
+                    .ok()
+                    .and_then(|name| resolve_predefined_entity(&name))
+                    .map(str::to_string),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Comment text by comment ID, with its author
+fn parse_comments(xml: &str) -> Result<HashMap<String, (Option<String>, String)>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut words = WordText { in_text: false };
+    let mut comments = HashMap::new();
+    let mut current: Option<(String, Option<String>, String)> = None;
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Failed to read comments: {}", e))?;
+        match &event {
+            Event::Eof => break,
+            Event::Start(e) if e.name().as_ref() == b"w:comment" => {
+                current = Some((
+                    attribute(e, "w:id").unwrap_or_default(),
+                    attribute(e, "w:author").filter(|a| !a.trim().is_empty()),
+                    String::new(),
+                ));
+            }
+            Event::End(e) if e.name().as_ref() == b"w:comment" => {
+                if let Some((id, author, text)) = current.take() {
+                    comments.insert(id, (author, text.trim().to_string()));
+                }
+            }
+            _ => {
+                if let (Some(text), Some((_, _, comment))) = (words.text(&event), &mut current) {
+                    comment.push_str(&text);
+                }
+            }
+        }
+    }
+    Ok(comments)
+}
+
+enum Change {
+    Insertion { at: usize, text: String },
+    Deletion { start: usize, end: usize },
+}
+
+/// The text just before `start` and just after `end`, with what lies between
+fn around(text: &str, start: usize, end: usize) -> String {
+    let before: String = text[..start]
+        .chars()
+        .rev()
+        .take(CHANGE_CONTEXT_CHARS)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let after: String = text[end..].chars().take(CHANGE_CONTEXT_CHARS).collect();
+    format!("{}{}{}", before, &text[start..end], after)
+}
+
+/// Comments and tracked changes in a document, each with the passage of the
+/// original text it is about. Inserted text is not part of the original, so
+/// it is kept apart; deleted text is.
+fn parse_docx(document: &str, comments: &str) -> Result<Vec<FeedbackItem>, String> {
+    let comments = parse_comments(comments)?;
+    let mut reader = Reader::from_str(document);
+    let mut words = WordText { in_text: false };
+    let mut text = String::new();
+    let mut ranges: HashMap<String, usize> = HashMap::new();
+    let mut items = Vec::new();
+    let mut changes: Vec<(Change, Option<String>)> = Vec::new();
+    // Open insertion or deletion: where it started and who made it
+    let mut insertion: Option<(usize, String, Option<String>)> = None;
+    let mut deletion: Option<(usize, Option<String>)> = None;
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Failed to read document: {}", e))?;
+        match &event {
+            Event::Eof => break,
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"w:commentRangeStart" => {
+                if let Some(id) = attribute(e, "w:id") {
+                    ranges.insert(id, text.len());
+                }
+            }
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"w:commentRangeEnd" => {
+                let Some(id) = attribute(e, "w:id") else {
+                    continue;
+                };
+                let (Some(start), Some((author, comment))) =
+                    (ranges.remove(&id), comments.get(&id))
+                else {
+                    continue;
+                };
+                let mut quote = text[start..].trim().to_string();
+                if quote.is_empty() {
+                    quote = around(&text, start, start).trim().to_string();
+                }
+                if !comment.is_empty() {
+                    items.push(FeedbackItem {
+                        quote,
+                        text: comment.clone(),
+                        author: author.clone(),
+                    });
+                }
+            }
+            Event::Start(e) if e.name().as_ref() == b"w:ins" => {
+                insertion = Some((text.len(), String::new(), attribute(e, "w:author")));
+            }
+            Event::End(e) if e.name().as_ref() == b"w:ins" => {
+                if let Some((at, inserted, author)) = insertion.take() {
+                    changes.push((Change::Insertion { at, text: inserted }, author));
+                }
+            }
+            Event::Start(e) if e.name().as_ref() == b"w:del" => {
+                deletion = Some((text.len(), attribute(e, "w:author")));
+            }
+            Event::End(e) if e.name().as_ref() == b"w:del" => {
+                if let Some((start, author)) = deletion.take() {
+                    let end = text.len();
+                    changes.push((Change::Deletion { start, end }, author));
+                }
+            }
+            _ => {
+                if let Some(added) = words.text(&event) {
+                    match &mut insertion {
+                        Some((_, inserted, _)) if added != "\n" => inserted.push_str(&added),
+                        _ => text.push_str(&added),
+                    }
+                }
+            }
+        }
+    }
+
+    // A deletion followed by an insertion at the same place is a replacement
+    let mut changes = changes.into_iter().peekable();
+    while let Some((change, author)) = changes.next() {
+        let item = match change {
+            Change::Deletion { start, end } => {
+                let deleted = text[start..end].trim().to_string();
+                let replaced_by = match changes.peek() {
+                    Some((Change::Insertion { at, text }, next_author))
+                        if *at == end && *next_author == author =>
+                    {
+                        Some(text.trim().to_string())
+                    }
+                    _ => None,
+                };
+                if replaced_by.is_some() {
+                    changes.next();
+                }
+                FeedbackItem {
+                    quote: around(&text, start, end).trim().to_string(),
+                    text: match replaced_by {
+                        Some(new) => format!("Suggested replacement: “{}” → “{}”", deleted, new),
+                        None => format!("Suggested deletion: “{}”", deleted),
+                    },
+                    author,
+                }
+            }
+            Change::Insertion { at, text: inserted } => FeedbackItem {
+                quote: around(&text, at, at).trim().to_string(),
+                text: format!("Suggested insertion: “{}”", inserted.trim()),
+                author,
+            },
+        };
+        items.push(item);
+    }
+    items.retain(|item| !item.quote.is_empty());
+    Ok(items)
+}
+
+// --- CSV ---
+
+/// Rows of a CSV file, with quoted fields that may hold commas, quotes and
+/// line breaks
+pub(crate) fn csv_rows(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|r| r.iter().any(|f| !f.trim().is_empty()));
+    rows
+}
+
+/// Feedback from CSV with quote, comment and optional author columns, named
+/// in a header row or in that order
+fn parse_csv(text: &str) -> Result<Vec<FeedbackItem>, String> {
+    let mut rows = csv_rows(text).into_iter().peekable();
+    let column = |header: &[String], names: &[&str]| {
+        header
+            .iter()
+            .position(|h| names.contains(&h.trim().to_lowercase().as_str()))
+    };
+    let (quote, comment, author) = match rows.peek() {
+        Some(header) if column(header, COMMENT_COLUMNS).is_some() => {
+            let columns = (
+                column(header, QUOTE_COLUMNS)
+                    .ok_or("The CSV file has no quote or passage column")?,
+                column(header, COMMENT_COLUMNS).unwrap_or_default(),
+                column(header, AUTHOR_COLUMNS),
+            );
+            rows.next();
+            columns
+        }
+        _ => (0, 1, Some(2)),
+    };
+    let field = |row: &[String], index: usize| row.get(index).map(|f| f.trim().to_string());
+    Ok(rows
+        .filter_map(|row| {
+            Some(FeedbackItem {
+                quote: field(&row, quote).filter(|q| !q.is_empty())?,
+                text: field(&row, comment).filter(|c| !c.is_empty())?,
+                author: author
+                    .and_then(|a| field(&row, a))
+                    .filter(|a| !a.is_empty()),
+            })
+        })
+        .collect())
+}
+
+// --- Markdown ---
+
+/// Feedback from Markdown where each quoted passage (`> ...`) is followed by
+/// the comment on it
+fn parse_markdown(text: &str) -> Vec<FeedbackItem> {
+    let mut items = Vec::new();
+    let mut quote: Vec<&str> = Vec::new();
+    let mut comment: Vec<&str> = Vec::new();
+    let mut finish = |quote: &mut Vec<&str>, comment: &mut Vec<&str>| {
+        let text = comment.join("\n").trim().to_string();
+        if !quote.is_empty() && !text.is_empty() {
+            items.push(FeedbackItem {
+                quote: quote.join(" ").trim().to_string(),
+                text,
+                author: None,
+            });
+        }
+        quote.clear();
+        comment.clear();
+    };
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if let Some(quoted) = trimmed.strip_prefix('>') {
+            if !comment.is_empty() {
+                finish(&mut quote, &mut comment);
+            }
+            quote.push(quoted.trim());
+        } else if trimmed.starts_with('#') {
+            finish(&mut quote, &mut comment);
+        } else if !quote.is_empty() {
+            comment.push(line);
+        }
+    }
+    finish(&mut quote, &mut comment);
+    items
+}
+
+// --- Alignment ---
+
+fn normalized_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(|w| w.trim_matches('\'').to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+fn triples(words: &[String]) -> HashSet<String> {
+    words.windows(3).map(|w| w.join(" ")).collect()
+}
+
+struct AlignedEntry {
+    id: String,
+    /// Normalized words, space separated with a space at each end
+    joined: String,
+    triples: HashSet<String>,
+}
+
+/// The main branch entry a passage most likely comes from: one containing it
+/// word for word, or else the one sharing most of its word triples
+fn best_entry<'a>(entries: &'a [AlignedEntry], quote: &str) -> Option<&'a str> {
+    let words = normalized_words(quote);
+    if words.is_empty() {
+        return None;
+    }
+    let joined = format!(" {} ", words.join(" "));
+    if let Some(entry) = entries.iter().find(|e| e.joined.contains(&joined)) {
+        return Some(&entry.id);
+    }
+    let wanted = triples(&words);
+    if wanted.is_empty() {
+        return None;
+    }
+    let (best, score) = entries
+        .iter()
+        .map(|e| (e, wanted.intersection(&e.triples).count()))
+        .max_by_key(|(_, shared)| *shared)?;
+    (score as f64 / wanted.len() as f64 >= MIN_MATCH).then_some(best.id.as_str())
+}
+
+/// Attach each item as a comment on the entry its passage is found in
+pub fn attach(
+    app: &AppHandle,
+    export: &StoryExport,
+    format: FeedbackFormat,
+    items: Vec<FeedbackItem>,
+) -> Result<FeedbackImport, String> {
+    let mut main: Vec<_> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none() && e.entry_type != "system")
+        .collect();
+    main.sort_by_key(|e| e.position);
+    let entries: Vec<AlignedEntry> = main
+        .iter()
+        .map(|e| {
+            let words = normalized_words(&plain_text(&e.content));
+            AlignedEntry {
+                id: e.id.clone(),
+                joined: format!(" {} ", words.join(" ")),
+                triples: triples(&words),
+            }
+        })
+        .collect();
+
+    let mut notes = Vec::new();
+    let mut unmatched = Vec::new();
+    for item in items {
+        let Some(entry_id) = best_entry(&entries, &item.quote) else {
+            unmatched.push(item);
+            continue;
+        };
+        let text = match &item.author {
+            Some(author) => format!("{}: {}", author, item.text),
+            None => item.text.clone(),
+        };
+        notes.push((
+            entry_id.to_string(),
+            AnnotationKind::Comment,
+            text.chars().take(MAX_ANNOTATION_CHARS).collect(),
+            Some(item.quote),
+        ));
+    }
+    Ok(FeedbackImport {
+        story_id: export.story.id.clone(),
+        format,
+        attached: super::add_all(app, &export.story.id, notes)?,
+        unmatched,
+    })
+}
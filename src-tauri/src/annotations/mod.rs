@@ -0,0 +1,287 @@
+//! Notes attached to story entries: editor comments, TODOs and feedback from
+//! the AI, and planned scene beats, which belong to the story as a whole.
+//! They are kept in the app data directory rather than in the story, so
+//! they never reach the model or a reader and are left out of exports
+//! unless an export asks for them.
+
+pub mod commands;
+pub mod feedback;
+
+pub use commands::AnnotationState;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, Manager};
+
+use crate::profiles;
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Annotations of every story, in the app data directory
+pub const ANNOTATIONS_FILE: &str = "annotations.json";
+
+/// Longest annotation accepted, in characters
+const MAX_ANNOTATION_CHARS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnnotationKind {
+    Comment,
+    Todo,
+    AiFeedback,
+    /// A planned scene beat; not attached to an entry
+    Beat,
+}
+
+/// Where a planned beat falls in the outline and what it involves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedBeat {
+    /// Starts at 1
+    pub act: u32,
+    /// Position within the act, starting at 1
+    pub order: u32,
+    pub title: String,
+    #[serde(default)]
+    pub characters: Vec<String>,
+    #[serde(default)]
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub id: String,
+    pub story_id: String,
+    /// Empty for beats
+    pub entry_id: String,
+    pub kind: AnnotationKind,
+    pub text: String,
+    /// The passage of the entry the note is about, if it is about one part
+    #[serde(default)]
+    pub quote: Option<String>,
+    /// Done, for TODOs; addressed, for comments and feedback
+    #[serde(default)]
+    pub resolved: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat: Option<PlannedBeat>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Changes to an annotation; unset fields are left as they are
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnnotationPatch {
+    pub kind: Option<AnnotationKind>,
+    pub text: Option<String>,
+    /// An empty quote removes it
+    pub quote: Option<String>,
+    pub resolved: Option<bool>,
+}
+
+/// Annotations keyed by story ID
+pub type Annotations = HashMap<String, Vec<Annotation>>;
+
+fn check_text(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Annotations cannot be empty".to_string());
+    }
+    if text.chars().count() > MAX_ANNOTATION_CHARS {
+        return Err(format!(
+            "Annotations can be at most {} characters",
+            MAX_ANNOTATION_CHARS
+        ));
+    }
+    Ok(text.to_string())
+}
+
+/// Read, change and write the annotations file
+fn update<T>(
+    app: &AppHandle,
+    change: impl FnOnce(&mut Annotations) -> Result<T, String>,
+) -> Result<T, String> {
+    let state = app.state::<AnnotationState>();
+    let _write = state
+        .writes
+        .lock()
+        .map_err(|_| "Annotations are unavailable".to_string())?;
+    let mut annotations: Annotations = store::load_json(app, ANNOTATIONS_FILE)?;
+    let result = change(&mut annotations)?;
+    annotations.retain(|_, list| !list.is_empty());
+    store::save_json(app, ANNOTATIONS_FILE, &annotations)?;
+    Ok(result)
+}
+
+/// A story's annotations in the order they were added, optionally only
+/// those on one entry or only unresolved ones
+pub fn list(
+    app: &AppHandle,
+    story_id: &str,
+    entry_id: Option<&str>,
+    include_resolved: bool,
+) -> Result<Vec<Annotation>, String> {
+    let mut annotations: Annotations = store::load_json(app, ANNOTATIONS_FILE)?;
+    Ok(annotations
+        .remove(story_id)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|a| entry_id.is_none_or(|id| a.entry_id == id))
+        .filter(|a| include_resolved || !a.resolved)
+        .collect())
+}
+
+fn new_annotation(
+    story_id: &str,
+    entry_id: &str,
+    kind: AnnotationKind,
+    text: &str,
+    quote: Option<String>,
+) -> Result<Annotation, String> {
+    if kind != AnnotationKind::Beat && entry_id.trim().is_empty() {
+        return Err("Annotations must be attached to an entry".to_string());
+    }
+    let now = now_ms();
+    Ok(Annotation {
+        id: uuid::Uuid::new_v4().to_string(),
+        story_id: story_id.to_string(),
+        entry_id: entry_id.to_string(),
+        kind,
+        text: check_text(text)?,
+        quote: quote.filter(|q| !q.trim().is_empty()),
+        resolved: false,
+        beat: None,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+pub fn add(
+    app: &AppHandle,
+    story_id: &str,
+    entry_id: &str,
+    kind: AnnotationKind,
+    text: &str,
+    quote: Option<String>,
+) -> Result<Annotation, String> {
+    let annotation = new_annotation(story_id, entry_id, kind, text, quote)?;
+    update(app, |annotations| {
+        annotations
+            .entry(story_id.to_string())
+            .or_default()
+            .push(annotation.clone());
+        Ok(())
+    })?;
+    Ok(annotation)
+}
+
+/// Attach several annotations in one write. Each is (entry ID, kind, text,
+/// quote).
+pub fn add_all(
+    app: &AppHandle,
+    story_id: &str,
+    notes: Vec<(String, AnnotationKind, String, Option<String>)>,
+) -> Result<Vec<Annotation>, String> {
+    let added = notes
+        .into_iter()
+        .map(|(entry_id, kind, text, quote)| {
+            new_annotation(story_id, &entry_id, kind, &text, quote)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if added.is_empty() {
+        return Ok(added);
+    }
+    update(app, |annotations| {
+        annotations
+            .entry(story_id.to_string())
+            .or_default()
+            .extend(added.iter().cloned());
+        Ok(())
+    })?;
+    Ok(added)
+}
+
+pub fn edit(
+    app: &AppHandle,
+    story_id: &str,
+    annotation_id: &str,
+    patch: AnnotationPatch,
+) -> Result<Annotation, String> {
+    let text = patch.text.as_deref().map(check_text).transpose()?;
+    update(app, |annotations| {
+        let annotation = annotations
+            .get_mut(story_id)
+            .and_then(|list| list.iter_mut().find(|a| a.id == annotation_id))
+            .ok_or_else(|| format!("Annotation not found: {}", annotation_id))?;
+        if let Some(kind) = patch.kind {
+            annotation.kind = kind;
+        }
+        if let Some(text) = text {
+            annotation.text = text;
+        }
+        if let Some(quote) = patch.quote {
+            annotation.quote = Some(quote).filter(|q| !q.trim().is_empty());
+        }
+        if let Some(resolved) = patch.resolved {
+            annotation.resolved = resolved;
+        }
+        annotation.updated_at = now_ms();
+        Ok(annotation.clone())
+    })
+}
+
+/// Returns whether the annotation existed
+pub fn delete(app: &AppHandle, story_id: &str, annotation_id: &str) -> Result<bool, String> {
+    update(app, |annotations| {
+        let Some(list) = annotations.get_mut(story_id) else {
+            return Ok(false);
+        };
+        let before = list.len();
+        list.retain(|a| a.id != annotation_id);
+        Ok(list.len() != before)
+    })
+}
+
+/// Replace the unresolved beats planned for an act with a new outline.
+/// Beats already played out (resolved) are kept.
+pub fn replace_beats(
+    app: &AppHandle,
+    story_id: &str,
+    act: u32,
+    beats: Vec<(PlannedBeat, String)>,
+) -> Result<Vec<Annotation>, String> {
+    let added = beats
+        .into_iter()
+        .map(|(beat, text)| {
+            let mut annotation = new_annotation(story_id, "", AnnotationKind::Beat, &text, None)?;
+            annotation.beat = Some(beat);
+            Ok(annotation)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    update(app, |annotations| {
+        let list = annotations.entry(story_id.to_string()).or_default();
+        list.retain(|a| a.resolved || a.beat.as_ref().is_none_or(|beat| beat.act != act));
+        list.extend(added.iter().cloned());
+        Ok(())
+    })?;
+    Ok(added)
+}
+
+/// Unresolved TODOs in every story the current profile can see, oldest first
+pub async fn open_todos(app: &AppHandle) -> Result<Vec<Annotation>, String> {
+    let annotations: Annotations = store::load_json(app, ANNOTATIONS_FILE)?;
+    let visible: HashSet<String> =
+        profiles::visible_stories(app, annotations.keys().cloned().collect())
+            .await?
+            .into_iter()
+            .collect();
+    let mut todos: Vec<Annotation> = annotations
+        .into_iter()
+        .filter(|(story_id, _)| visible.contains(story_id))
+        .flat_map(|(_, list)| list)
+        .filter(|a| a.kind == AnnotationKind::Todo && !a.resolved)
+        .collect();
+    todos.sort_by_key(|a| a.created_at);
+    Ok(todos)
+}
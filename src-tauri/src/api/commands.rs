@@ -0,0 +1,189 @@
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+use tokio::sync::Mutex;
+
+use super::server::{bind_listener, build_router, spawn_server, ApiServerState};
+use super::tokens::{
+    generate_token, hash_token, ApiScope, ApiToken, CreatedApiToken, API_TOKENS_FILE,
+};
+use crate::profiles;
+use crate::store;
+use crate::sync::commands::parse_story_preview;
+use crate::sync::keys::now_ms;
+use crate::sync::payload::{SpillConfig, StoryPayload, SPILL_CONFIG_FILE};
+use crate::sync::server::StoriesData;
+
+/// Port used when the caller does not pick one
+const DEFAULT_API_PORT: u16 = 7878;
+
+/// State managed by Tauri for the local REST API
+pub struct ApiState {
+    server_handle: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
+    server_state: Arc<Mutex<Option<ApiServerState>>>,
+    /// Shared with the server so token edits and usage updates never interleave
+    tokens_lock: Arc<Mutex<()>>,
+}
+
+impl Default for ApiState {
+    fn default() -> Self {
+        Self {
+            server_handle: Arc::new(Mutex::new(None)),
+            server_state: Arc::new(Mutex::new(None)),
+            tokens_lock: Arc::new(Mutex::new(())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalApiInfo {
+    pub url: String,
+    pub port: u16,
+}
+
+/// The stories the active profile may see, ready to serve
+async fn parse_stories(
+    app: &AppHandle,
+    stories_json: Vec<String>,
+) -> Result<Vec<StoriesData>, String> {
+    let spill: SpillConfig = store::load_json(app, SPILL_CONFIG_FILE).unwrap_or_default();
+    Ok(profiles::visible_exports(app, stories_json)
+        .await?
+        .into_iter()
+        .filter_map(|json| match parse_story_preview(&json) {
+            Ok(preview) => Some(StoriesData {
+                preview,
+                full_data: StoryPayload::new(json, &spill),
+            }),
+            Err(e) => {
+                eprintln!("Failed to parse story: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Start the read-only REST API on localhost
+#[tauri::command]
+pub async fn start_local_api(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    port: Option<u16>,
+    stories_json: Vec<String>,
+) -> Result<LocalApiInfo, String> {
+    stop_local_api(state.clone()).await?;
+
+    let listener = bind_listener(port.unwrap_or(DEFAULT_API_PORT)).await?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to get local address: {}", e))?
+        .port();
+
+    let server_state = ApiServerState {
+        stories: Arc::new(Mutex::new(parse_stories(&app, stories_json).await?)),
+        app,
+        tokens_lock: state.tokens_lock.clone(),
+    };
+    let handle = spawn_server(listener, build_router(server_state.clone()));
+
+    *state.server_handle.lock().await = Some(handle);
+    *state.server_state.lock().await = Some(server_state);
+
+    Ok(LocalApiInfo {
+        url: format!("http://127.0.0.1:{}/api/v1", port),
+        port,
+    })
+}
+
+/// Stop the local REST API
+#[tauri::command]
+pub async fn stop_local_api(state: State<'_, ApiState>) -> Result<(), String> {
+    if let Some(handle) = state.server_handle.lock().await.take() {
+        handle.abort();
+    }
+    *state.server_state.lock().await = None;
+    Ok(())
+}
+
+/// Replace the library snapshot served by the API
+#[tauri::command]
+pub async fn update_local_api_stories(
+    state: State<'_, ApiState>,
+    stories_json: Vec<String>,
+) -> Result<(), String> {
+    if let Some(ref server) = *state.server_state.lock().await {
+        *server.stories.lock().await = parse_stories(&server.app, stories_json).await?;
+    }
+    Ok(())
+}
+
+/// Create a token. The secret is only returned here and cannot be shown again.
+#[tauri::command]
+pub async fn create_api_token(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    name: String,
+    scopes: Vec<ApiScope>,
+) -> Result<CreatedApiToken, String> {
+    if scopes.is_empty() {
+        return Err("A token needs at least one scope".to_string());
+    }
+    let token = generate_token();
+    let info = ApiToken {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        scopes,
+        created_at: now_ms(),
+        last_used_at: None,
+        token_hash: hash_token(&token),
+    };
+
+    let _guard = state.tokens_lock.lock().await;
+    let mut tokens: Vec<ApiToken> = store::load_json(&app, API_TOKENS_FILE)?;
+    tokens.push(info.clone());
+    store::save_json(&app, API_TOKENS_FILE, &tokens)?;
+
+    Ok(CreatedApiToken {
+        info: ApiToken {
+            token_hash: String::new(),
+            ..info
+        },
+        token,
+    })
+}
+
+/// List tokens without their secrets
+#[tauri::command]
+pub async fn list_api_tokens(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<Vec<ApiToken>, String> {
+    let _guard = state.tokens_lock.lock().await;
+    let tokens: Vec<ApiToken> = store::load_json(&app, API_TOKENS_FILE)?;
+    Ok(tokens
+        .into_iter()
+        .map(|t| ApiToken {
+            token_hash: String::new(),
+            ..t
+        })
+        .collect())
+}
+
+/// Revoke a token. Returns false if it did not exist.
+#[tauri::command]
+pub async fn revoke_api_token(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    token_id: String,
+) -> Result<bool, String> {
+    let _guard = state.tokens_lock.lock().await;
+    let mut tokens: Vec<ApiToken> = store::load_json(&app, API_TOKENS_FILE)?;
+    let before = tokens.len();
+    tokens.retain(|t| t.id != token_id);
+    if tokens.len() == before {
+        return Ok(false);
+    }
+    store::save_json(&app, API_TOKENS_FILE, &tokens)?;
+    Ok(true)
+}
@@ -0,0 +1,5 @@
+pub mod commands;
+pub mod server;
+pub mod tokens;
+
+pub use commands::ApiState;
@@ -0,0 +1,171 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tauri::AppHandle;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+use super::tokens::{find_token, ApiScope, ApiToken, API_TOKENS_FILE};
+use crate::store;
+use crate::sync::keys::now_ms;
+use crate::sync::server::StoriesData;
+use crate::sync::types::SyncStoryPreview;
+use crate::tables;
+
+/// Shared state for the local REST API
+#[derive(Clone)]
+pub struct ApiServerState {
+    pub app: AppHandle,
+    /// Snapshot of the library provided by the frontend
+    pub stories: Arc<Mutex<Vec<StoriesData>>>,
+    /// Serializes updates to the token file
+    pub tokens_lock: Arc<Mutex<()>>,
+}
+
+/// Bind the API on the loopback interface only
+pub async fn bind_listener(port: u16) -> Result<TcpListener, String> {
+    TcpListener::bind(("127.0.0.1", port))
+        .await
+        .map_err(|e| format!("Failed to bind local API on port {}: {}", port, e))
+}
+
+pub fn build_router(state: ApiServerState) -> Router {
+    Router::new()
+        .route("/api/v1/stories", get(list_stories))
+        .route("/api/v1/stories/{id}", get(get_story))
+        .route("/api/v1/stories/{id}/export", get(export_story))
+        .route("/api/v1/tables", get(list_tables))
+        .route("/api/v1/tables/{pack}/{table}/roll", get(roll_table))
+        .with_state(state)
+}
+
+pub fn spawn_server(listener: TcpListener, app: Router) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            eprintln!("Local API error: {}", e);
+        }
+    })
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+/// Check the bearer token carries a scope, recording when it was last used
+async fn authorize(
+    state: &ApiServerState,
+    headers: &HeaderMap,
+    scope: ApiScope,
+) -> Result<(), Response> {
+    let secret = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Missing bearer token"))?;
+
+    let _guard = state.tokens_lock.lock().await;
+    let mut tokens: Vec<ApiToken> = store::load_json(&state.app, API_TOKENS_FILE)
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e))?;
+    let token = find_token(&mut tokens, secret.trim())
+        .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Invalid token"))?;
+    if !token.scopes.contains(&scope) {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "Token lacks the required scope",
+        ));
+    }
+    token.last_used_at = Some(now_ms());
+    let _ = store::save_json(&state.app, API_TOKENS_FILE, &tokens);
+    Ok(())
+}
+
+async fn list_stories(State(state): State<ApiServerState>, headers: HeaderMap) -> Response {
+    if let Err(response) = authorize(&state, &headers, ApiScope::ReadStories).await {
+        return response;
+    }
+    let stories = state.stories.lock().await;
+    let previews: Vec<SyncStoryPreview> = stories.iter().map(|s| s.preview.clone()).collect();
+    Json(previews).into_response()
+}
+
+async fn get_story(
+    State(state): State<ApiServerState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(response) = authorize(&state, &headers, ApiScope::ReadStories).await {
+        return response;
+    }
+    let stories = state.stories.lock().await;
+    match stories.iter().find(|s| s.preview.id == id) {
+        Some(story) => Json(story.preview.clone()).into_response(),
+        None => error(StatusCode::NOT_FOUND, "Story not found"),
+    }
+}
+
+async fn export_story(
+    State(state): State<ApiServerState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(response) = authorize(&state, &headers, ApiScope::ExportStories).await {
+        return response;
+    }
+    let payload = state
+        .stories
+        .lock()
+        .await
+        .iter()
+        .find(|s| s.preview.id == id)
+        .map(|s| s.full_data.clone());
+    match payload.map(|p| p.read()) {
+        Some(Ok(json)) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+        Some(Err(e)) => error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+        None => error(StatusCode::NOT_FOUND, "Story not found"),
+    }
+}
+
+async fn list_tables(State(state): State<ApiServerState>, headers: HeaderMap) -> Response {
+    if let Err(response) = authorize(&state, &headers, ApiScope::RollTables).await {
+        return response;
+    }
+    match tables::list(&state.app) {
+        Ok(packs) => Json(packs).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+#[derive(Deserialize)]
+struct RollQuery {
+    seed: Option<u64>,
+}
+
+async fn roll_table(
+    State(state): State<ApiServerState>,
+    headers: HeaderMap,
+    Path((pack, table)): Path<(String, String)>,
+    Query(query): Query<RollQuery>,
+) -> Response {
+    if let Err(response) = authorize(&state, &headers, ApiScope::RollTables).await {
+        return response;
+    }
+    let packs = match tables::list(&state.app) {
+        Ok(packs) => packs,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    };
+    let table_id = format!("{}/{}", pack, table);
+    match tables::roll(
+        &packs,
+        &table_id,
+        query.seed.unwrap_or_else(|| now_ms() as u64),
+    ) {
+        Ok(roll) => Json(roll).into_response(),
+        Err(e) => error(StatusCode::NOT_FOUND, &e),
+    }
+}
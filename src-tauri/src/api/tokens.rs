@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// File in the app data directory holding hashed API tokens
+pub const API_TOKENS_FILE: &str = "api_tokens.json";
+
+/// Prefix that makes Aventura tokens easy to recognise in scripts and logs
+const TOKEN_PREFIX: &str = "avt_";
+
+/// What a token is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ApiScope {
+    /// List stories and their metadata
+    ReadStories,
+    /// Download full story exports
+    ExportStories,
+    /// List and roll on random tables
+    RollTables,
+}
+
+/// A long-lived token; only the hash of the secret is stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub(crate) token_hash: String,
+}
+
+/// Returned once when a token is created; the secret cannot be recovered later
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub info: ApiToken,
+    pub token: String,
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn generate_token() -> String {
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Find the token matching a bearer secret
+pub fn find_token<'a>(tokens: &'a mut [ApiToken], secret: &str) -> Option<&'a mut ApiToken> {
+    let hash = hash_token(secret);
+    tokens.iter_mut().find(|t| t.token_hash == hash)
+}
@@ -0,0 +1,49 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{ImageBuffer, ImageFormat, Rgba};
+use std::io::Cursor;
+use tauri::AppHandle;
+
+use super::Attachment;
+
+/// Read an image from the system clipboard, convert it to PNG and store it.
+/// Returns the attachment so it can be placed in a scene entry.
+#[tauri::command]
+pub async fn import_attachment_from_clipboard(app: AppHandle) -> Result<Attachment, String> {
+    // Clipboard access and PNG encoding both block
+    let (png, dimensions) = tokio::task::spawn_blocking(|| {
+        let mut clipboard =
+            arboard::Clipboard::new().map_err(|e| format!("Failed to open clipboard: {}", e))?;
+        let data = clipboard
+            .get_image()
+            .map_err(|_| "The clipboard does not contain an image".to_string())?;
+        let (width, height) = (data.width as u32, data.height as u32);
+        let buffer: ImageBuffer<Rgba<u8>, _> =
+            ImageBuffer::from_raw(width, height, data.bytes.into_owned())
+                .ok_or("Clipboard image data is incomplete")?;
+        let mut png = Vec::new();
+        buffer
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode image: {}", e))?;
+        Ok::<_, String>((png, (width, height)))
+    })
+    .await
+    .map_err(|e| format!("Clipboard task failed: {}", e))??;
+
+    super::save(&app, &png, "image/png", Some(dimensions))
+}
+
+#[tauri::command]
+pub async fn get_attachment(app: AppHandle, id: String) -> Result<Attachment, String> {
+    super::get(&app, &id)
+}
+
+/// Attachment contents as a data URL, for showing in the UI
+#[tauri::command]
+pub async fn get_attachment_data_url(app: AppHandle, id: String) -> Result<String, String> {
+    let (attachment, bytes) = super::load(&app, &id)?;
+    Ok(format!(
+        "data:{};base64,{}",
+        attachment.mime_type,
+        STANDARD.encode(bytes)
+    ))
+}
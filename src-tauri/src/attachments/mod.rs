@@ -0,0 +1,96 @@
+//! Content-addressed store for images and other media attached to stories.
+//! An attachment's ID is the SHA-256 of its bytes, so storing the same file
+//! twice keeps one copy.
+
+pub mod commands;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Directory in the app data directory holding attachments
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// Metadata kept next to each attachment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub id: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// Pixel size, for images
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    pub created_at: i64,
+}
+
+fn attachments_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = store::data_file(app, ATTACHMENTS_DIR)?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create attachments directory: {}", e))?;
+    Ok(dir)
+}
+
+fn check_id(id: &str) -> Result<(), String> {
+    if id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(format!("Invalid attachment ID: {}", id))
+    }
+}
+
+/// Store bytes, returning the existing attachment if the same content is already stored
+pub fn save(
+    app: &AppHandle,
+    bytes: &[u8],
+    mime_type: &str,
+    dimensions: Option<(u32, u32)>,
+) -> Result<Attachment, String> {
+    let id: String = Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if let Ok(existing) = get(app, &id) {
+        return Ok(existing);
+    }
+
+    let dir = attachments_dir(app)?;
+    let attachment = Attachment {
+        id: id.clone(),
+        mime_type: mime_type.to_string(),
+        size: bytes.len() as u64,
+        width: dimensions.map(|(w, _)| w),
+        height: dimensions.map(|(_, h)| h),
+        created_at: now_ms(),
+    };
+    fs::write(dir.join(&id), bytes).map_err(|e| format!("Failed to save attachment: {}", e))?;
+    let meta = serde_json::to_string_pretty(&attachment)
+        .map_err(|e| format!("Failed to serialize attachment: {}", e))?;
+    // Metadata last: an attachment without it is treated as missing
+    fs::write(dir.join(format!("{}.json", id)), meta)
+        .map_err(|e| format!("Failed to save attachment: {}", e))?;
+    Ok(attachment)
+}
+
+/// Metadata of a stored attachment
+pub fn get(app: &AppHandle, id: &str) -> Result<Attachment, String> {
+    check_id(id)?;
+    let json = fs::read_to_string(attachments_dir(app)?.join(format!("{}.json", id)))
+        .map_err(|_| format!("Attachment not found: {}", id))?;
+    serde_json::from_str(&json).map_err(|e| format!("Invalid attachment metadata: {}", e))
+}
+
+/// Contents and metadata of an attachment
+pub fn load(app: &AppHandle, id: &str) -> Result<(Attachment, Vec<u8>), String> {
+    let attachment = get(app, id)?;
+    let bytes = fs::read(attachments_dir(app)?.join(id))
+        .map_err(|e| format!("Failed to read attachment: {}", e))?;
+    Ok((attachment, bytes))
+}
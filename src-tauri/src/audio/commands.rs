@@ -0,0 +1,136 @@
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+use super::player::{AmbiencePlayer, AmbienceStatus};
+use super::scenes::{AmbienceScene, AmbienceTrack, StoryAmbience};
+use crate::attachments;
+use crate::story::{rows, StoryExport};
+
+/// Crossfade used when the caller does not choose one
+const DEFAULT_FADE_MS: u64 = 2000;
+
+/// State managed by Tauri for audio
+#[derive(Default)]
+pub struct AudioState {
+    pub(crate) ambience: AmbiencePlayer,
+}
+
+fn fade(fade_ms: Option<u64>) -> Duration {
+    Duration::from_millis(fade_ms.unwrap_or(DEFAULT_FADE_MS))
+}
+
+fn play_track(
+    app: &AppHandle,
+    player: &AmbiencePlayer,
+    track: AmbienceTrack,
+    fade: Duration,
+) -> Result<AmbienceStatus, String> {
+    // Already playing: only the volume can differ
+    if player.status().attachment_id.as_deref() == Some(track.attachment_id.as_str()) {
+        player.set_volume(track.volume, fade)?;
+        return Ok(player.status());
+    }
+    let (_, bytes) = attachments::load(app, &track.attachment_id)?;
+    player.play(
+        track.attachment_id,
+        bytes,
+        track.looping,
+        track.volume,
+        fade,
+    )?;
+    Ok(player.status())
+}
+
+/// Play an audio attachment as ambience, crossfading from whatever is playing
+#[tauri::command]
+pub async fn play_ambience(
+    app: AppHandle,
+    state: State<'_, AudioState>,
+    attachment_id: String,
+    looping: Option<bool>,
+    volume: Option<f32>,
+    fade_ms: Option<u64>,
+) -> Result<AmbienceStatus, String> {
+    let track = AmbienceTrack {
+        attachment_id,
+        looping: looping.unwrap_or(true),
+        volume: volume.unwrap_or(0.6),
+    };
+    play_track(&app, &state.ambience, track, fade(fade_ms))
+}
+
+/// Fade the ambience out
+#[tauri::command]
+pub async fn stop_ambience(
+    state: State<'_, AudioState>,
+    fade_ms: Option<u64>,
+) -> Result<AmbienceStatus, String> {
+    state.ambience.stop(fade(fade_ms))?;
+    Ok(state.ambience.status())
+}
+
+#[tauri::command]
+pub async fn set_ambience_volume(
+    state: State<'_, AudioState>,
+    volume: f32,
+    fade_ms: Option<u64>,
+) -> Result<AmbienceStatus, String> {
+    let fade = Duration::from_millis(fade_ms.unwrap_or(300));
+    state.ambience.set_volume(volume, fade)?;
+    Ok(state.ambience.status())
+}
+
+#[tauri::command]
+pub async fn get_ambience_status(state: State<'_, AudioState>) -> Result<AmbienceStatus, String> {
+    Ok(state.ambience.status())
+}
+
+async fn load_story(app: &AppHandle, story_id: &str) -> Result<StoryExport, String> {
+    rows::load(app, story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))
+}
+
+/// Assign a track to a scene of a story, or clear it when `track` is null.
+/// Returns the story's assignments as saved.
+#[tauri::command]
+pub async fn set_scene_ambience(
+    app: AppHandle,
+    story_id: String,
+    scene: AmbienceScene,
+    track: Option<AmbienceTrack>,
+) -> Result<StoryAmbience, String> {
+    let export = load_story(&app, &story_id).await?;
+    let mut ambience = StoryAmbience::of(&export);
+    ambience.assign(scene, track);
+    ambience.prune(&export);
+    ambience.save(&app, &story_id).await?;
+    Ok(ambience)
+}
+
+#[tauri::command]
+pub async fn get_story_ambience(app: AppHandle, story_id: String) -> Result<StoryAmbience, String> {
+    Ok(StoryAmbience::of(&load_story(&app, &story_id).await?))
+}
+
+/// Play the ambience assigned to where the story is now. Call after each
+/// new entry or scene change; the current track carries on uninterrupted,
+/// and ambience fades out when nothing is assigned.
+#[tauri::command]
+pub async fn update_story_ambience(
+    app: AppHandle,
+    state: State<'_, AudioState>,
+    story_id: String,
+    fade_ms: Option<u64>,
+) -> Result<AmbienceStatus, String> {
+    let export = load_story(&app, &story_id).await?;
+    match StoryAmbience::of(&export).track_for(&export) {
+        Some(track) => play_track(&app, &state.ambience, track, fade(fade_ms)),
+        None => {
+            if state.ambience.status().attachment_id.is_some() {
+                state.ambience.stop(fade(fade_ms))?;
+            }
+            Ok(state.ambience.status())
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! Atmospheric audio played by the backend, so it keeps going while the
+//! window is hidden and follows the story without the frontend managing it.
+
+pub mod commands;
+pub mod player;
+pub mod scenes;
+
+pub use commands::AudioState;
@@ -0,0 +1,299 @@
+//! Ambience playback. Audio output handles cannot leave the thread that
+//! opened them, so one thread owns the output device and takes commands over
+//! a channel, stepping volume fades between them. The thread ends, letting
+//! go of the device, once the scene falls silent, and the next track starts
+//! a new one.
+
+use rodio::{Decoder, OutputStream, Sink};
+use serde::Serialize;
+use std::io::Cursor;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often fades are advanced
+const FADE_STEP: Duration = Duration::from_millis(40);
+
+/// What the ambience player is doing
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmbienceStatus {
+    /// Attachment being played, None when silent
+    pub attachment_id: Option<String>,
+    pub looping: bool,
+    pub volume: f32,
+}
+
+enum Command {
+    Play {
+        attachment_id: String,
+        bytes: Vec<u8>,
+        looping: bool,
+        volume: f32,
+        fade: Duration,
+    },
+    Stop {
+        fade: Duration,
+    },
+    SetVolume {
+        volume: f32,
+        fade: Duration,
+    },
+}
+
+/// A sink moving from one volume to another
+struct Voice {
+    sink: Sink,
+    from: f32,
+    to: f32,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Voice {
+    fn volume_now(&self) -> f32 {
+        let t = if self.duration.is_zero() {
+            1.0
+        } else {
+            (self.started.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+        };
+        self.from + (self.to - self.from) * t
+    }
+
+    fn retarget(&mut self, to: f32, duration: Duration) {
+        self.from = self.volume_now();
+        self.to = to;
+        self.started = Instant::now();
+        self.duration = duration;
+    }
+
+    /// Apply the current volume; true once the fade has finished
+    fn step(&self) -> bool {
+        self.sink.set_volume(self.volume_now());
+        self.started.elapsed() >= self.duration
+    }
+}
+
+/// Sender to the playback thread, None while no thread is running
+type CommandSlot = Arc<Mutex<Option<Sender<Command>>>>;
+
+/// Handle to the playback thread, started when a track is played
+#[derive(Default)]
+pub struct AmbiencePlayer {
+    commands: CommandSlot,
+    status: Arc<Mutex<AmbienceStatus>>,
+}
+
+impl AmbiencePlayer {
+    pub fn status(&self) -> AmbienceStatus {
+        self.status.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    fn send(&self, mut command: Command) -> Result<(), String> {
+        let mut commands = self
+            .commands
+            .lock()
+            .map_err(|_| "Audio player is unavailable".to_string())?;
+        if let Some(sender) = commands.as_ref() {
+            // A send only fails if the thread has died
+            match sender.send(command) {
+                Ok(()) => return Ok(()),
+                Err(mpsc::SendError(returned)) => {
+                    *commands = None;
+                    command = returned;
+                }
+            }
+        }
+        // Without a thread nothing is playing, so there is nothing to stop
+        // or turn down
+        if !matches!(command, Command::Play { .. }) {
+            return Ok(());
+        }
+        self.start(&mut commands, command)
+    }
+
+    fn start(&self, slot: &mut Option<Sender<Command>>, first: Command) -> Result<(), String> {
+        let (sender, receiver) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let status = self.status.clone();
+        let slot_handle = self.commands.clone();
+        std::thread::Builder::new()
+            .name("ambience".to_string())
+            .spawn(move || run(receiver, slot_handle, status, ready_tx))
+            .map_err(|e| format!("Failed to start audio thread: {}", e))?;
+        ready_rx
+            .recv()
+            .map_err(|_| "Audio thread exited".to_string())??;
+        sender
+            .send(first)
+            .map_err(|_| "Audio thread exited".to_string())?;
+        *slot = Some(sender);
+        Ok(())
+    }
+
+    /// Crossfade to a track. Playing the track that is already on only
+    /// changes its volume, so callers can re-send the current scene's track.
+    pub fn play(
+        &self,
+        attachment_id: String,
+        bytes: Vec<u8>,
+        looping: bool,
+        volume: f32,
+        fade: Duration,
+    ) -> Result<(), String> {
+        // Probe here so an unplayable file is reported to the caller
+        Decoder::new(Cursor::new(bytes.clone()))
+            .map_err(|e| format!("Unsupported audio file: {}", e))?;
+        self.send(Command::Play {
+            attachment_id,
+            bytes,
+            looping,
+            volume: volume.clamp(0.0, 1.0),
+            fade,
+        })
+    }
+
+    pub fn stop(&self, fade: Duration) -> Result<(), String> {
+        self.send(Command::Stop { fade })
+    }
+
+    pub fn set_volume(&self, volume: f32, fade: Duration) -> Result<(), String> {
+        self.send(Command::SetVolume {
+            volume: volume.clamp(0.0, 1.0),
+            fade,
+        })
+    }
+}
+
+fn run(
+    commands: Receiver<Command>,
+    slot: CommandSlot,
+    status: Arc<Mutex<AmbienceStatus>>,
+    ready: Sender<Result<(), String>>,
+) {
+    let (_stream, handle) = match OutputStream::try_default() {
+        Ok(output) => output,
+        Err(e) => {
+            let _ = ready.send(Err(format!("No audio output device: {}", e)));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+
+    let mut current: Option<Voice> = None;
+    let mut fading_out: Vec<Voice> = Vec::new();
+    // A command taken off the channel while deciding whether to exit
+    let mut next: Option<Command> = None;
+    let set_status = |value: AmbienceStatus| {
+        if let Ok(mut status) = status.lock() {
+            *status = value;
+        }
+    };
+
+    loop {
+        let command = match next.take() {
+            Some(command) => Ok(command),
+            None => commands.recv_timeout(FADE_STEP),
+        };
+        match command {
+            Ok(Command::Play {
+                attachment_id,
+                bytes,
+                looping,
+                volume,
+                fade,
+            }) => {
+                let playing = status.lock().ok().and_then(|s| s.attachment_id.clone());
+                if let (Some(voice), true) = (
+                    current.as_mut(),
+                    playing.as_deref() == Some(attachment_id.as_str()),
+                ) {
+                    voice.retarget(volume, fade);
+                    set_status(AmbienceStatus {
+                        attachment_id: Some(attachment_id),
+                        looping,
+                        volume,
+                    });
+                    continue;
+                }
+
+                let sink = match Sink::try_new(&handle) {
+                    Ok(sink) => sink,
+                    Err(e) => {
+                        eprintln!("Failed to open audio sink: {}", e);
+                        continue;
+                    }
+                };
+                sink.set_volume(0.0);
+                let appended = if looping {
+                    Decoder::new_looped(Cursor::new(bytes)).map(|s| sink.append(s))
+                } else {
+                    Decoder::new(Cursor::new(bytes)).map(|s| sink.append(s))
+                };
+                if let Err(e) = appended {
+                    eprintln!("Cannot play ambience {}: {}", attachment_id, e);
+                    continue;
+                }
+                if let Some(mut previous) = current.take() {
+                    previous.retarget(0.0, fade);
+                    fading_out.push(previous);
+                }
+                current = Some(Voice {
+                    sink,
+                    from: 0.0,
+                    to: volume,
+                    started: Instant::now(),
+                    duration: fade,
+                });
+                set_status(AmbienceStatus {
+                    attachment_id: Some(attachment_id),
+                    looping,
+                    volume,
+                });
+            }
+            Ok(Command::Stop { fade }) => {
+                if let Some(mut previous) = current.take() {
+                    previous.retarget(0.0, fade);
+                    fading_out.push(previous);
+                }
+                set_status(AmbienceStatus::default());
+            }
+            Ok(Command::SetVolume { volume, fade }) => {
+                if let Some(voice) = current.as_mut() {
+                    voice.retarget(volume, fade);
+                }
+                if let Ok(mut status) = status.lock() {
+                    status.volume = volume;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if let Some(voice) = &current {
+            voice.step();
+            // A track that was not looped has ended
+            if voice.sink.empty() {
+                current = None;
+                set_status(AmbienceStatus::default());
+            }
+        }
+        fading_out.retain(|voice| !voice.step());
+
+        // Silent: exit, unless a command came in. Senders hold the slot
+        // while sending, so none can arrive once the slot is cleared.
+        if current.is_none() && fading_out.is_empty() {
+            let Ok(mut slot) = slot.lock() else {
+                break;
+            };
+            match commands.try_recv() {
+                Ok(command) => next = Some(command),
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => {
+                    *slot = None;
+                    set_status(AmbienceStatus::default());
+                    break;
+                }
+            }
+        }
+    }
+}
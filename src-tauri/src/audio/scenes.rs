@@ -0,0 +1,163 @@
+//! Ambience assigned to parts of a story, kept in the stories table's
+//! `ambience` column. Exports carry it as `story.ambience`, so it travels
+//! with the story through sync and backups. The track for the current scene
+//! is the current location's, then the chapter's the newest entry falls in,
+//! then the story's default.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tauri::AppHandle;
+
+use crate::profiles::{self, database};
+use crate::story::StoryExport;
+use crate::sync::keys::now_ms;
+
+/// Key of the assignments in an export's story fields
+const AMBIENCE_FIELD: &str = "ambience";
+
+fn default_looping() -> bool {
+    true
+}
+
+fn default_volume() -> f32 {
+    0.6
+}
+
+/// A track and how to play it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmbienceTrack {
+    pub attachment_id: String,
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+    /// From 0 to 1
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+}
+
+/// Which part of a story a track belongs to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum AmbienceScene {
+    /// Played when nothing more specific applies
+    Default,
+    #[serde(rename_all = "camelCase")]
+    Location { location_id: String },
+    #[serde(rename_all = "camelCase")]
+    Chapter { chapter_id: String },
+}
+
+/// All of a story's ambience assignments
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StoryAmbience {
+    pub default_track: Option<AmbienceTrack>,
+    pub locations: BTreeMap<String, AmbienceTrack>,
+    pub chapters: BTreeMap<String, AmbienceTrack>,
+}
+
+impl StoryAmbience {
+    /// Assignments stored in a story; malformed ones are treated as none
+    pub fn of(export: &StoryExport) -> Self {
+        export
+            .story
+            .extra
+            .get(AMBIENCE_FIELD)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.default_track.is_none() && self.locations.is_empty() && self.chapters.is_empty()
+    }
+
+    /// Store the assignments in a story's row, clearing the column when
+    /// there are none
+    pub async fn save(&self, app: &AppHandle, story_id: &str) -> Result<(), String> {
+        let column = if self.is_empty() {
+            None
+        } else {
+            Some(
+                serde_json::to_string(self)
+                    .map_err(|e| format!("Failed to serialize ambience: {}", e))?,
+            )
+        };
+        let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+        let result = sqlx::query("UPDATE stories SET ambience = ?, updated_at = ? WHERE id = ?")
+            .bind(column)
+            .bind(now_ms())
+            .bind(story_id)
+            .execute(&pool)
+            .await
+            .map_err(|e| format!("Failed to update story: {}", e));
+        pool.close().await;
+        if result?.rows_affected() == 0 {
+            return Err(format!("Story not found: {}", story_id));
+        }
+        Ok(())
+    }
+
+    /// Assign a track to a scene, or clear it with None
+    pub fn assign(&mut self, scene: AmbienceScene, track: Option<AmbienceTrack>) {
+        match scene {
+            AmbienceScene::Default => self.default_track = track,
+            AmbienceScene::Location { location_id } => match track {
+                Some(track) => {
+                    self.locations.insert(location_id, track);
+                }
+                None => {
+                    self.locations.remove(&location_id);
+                }
+            },
+            AmbienceScene::Chapter { chapter_id } => match track {
+                Some(track) => {
+                    self.chapters.insert(chapter_id, track);
+                }
+                None => {
+                    self.chapters.remove(&chapter_id);
+                }
+            },
+        }
+    }
+
+    /// Drop assignments for locations and chapters the story no longer has
+    pub fn prune(&mut self, export: &StoryExport) {
+        self.locations
+            .retain(|id, _| export.locations.iter().any(|l| &l.id == id));
+        self.chapters
+            .retain(|id, _| export.chapters.iter().any(|c| &c.id == id));
+    }
+
+    /// Track for where the story is now
+    pub fn track_for(&self, export: &StoryExport) -> Option<AmbienceTrack> {
+        let location = export
+            .locations
+            .iter()
+            .filter(|l| l.current)
+            .find_map(|l| self.locations.get(&l.id));
+        location
+            .or_else(|| current_chapter(export).and_then(|id| self.chapters.get(id)))
+            .or(self.default_track.as_ref())
+            .cloned()
+    }
+}
+
+/// Chapter containing the newest entry of the main branch
+fn current_chapter(export: &StoryExport) -> Option<&str> {
+    let positions: HashMap<&str, i64> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none())
+        .map(|e| (e.id.as_str(), e.position))
+        .collect();
+    let newest = positions.values().max()?;
+    export
+        .chapters
+        .iter()
+        .find(|chapter| {
+            let start = positions.get(chapter.start_entry_id.as_str());
+            let end = positions.get(chapter.end_entry_id.as_str());
+            matches!((start, end), (Some(start), Some(end)) if start <= newest && newest <= end)
+        })
+        .map(|chapter| chapter.id.as_str())
+}
@@ -0,0 +1,20 @@
+use tauri::State;
+use tokio::sync::Mutex;
+
+use super::DeepLink;
+
+/// State managed by Tauri for deep links
+#[derive(Default)]
+pub struct DeepLinkState {
+    /// Accepted links the frontend has not taken yet
+    pub(crate) pending: Mutex<Vec<DeepLink>>,
+}
+
+/// Take the links accepted since the last call, including the one the app
+/// was launched with. Each is also announced with a `deeplink://open` event.
+#[tauri::command]
+pub async fn take_pending_deep_links(
+    state: State<'_, DeepLinkState>,
+) -> Result<Vec<DeepLink>, String> {
+    Ok(std::mem::take(&mut *state.pending.lock().await))
+}
@@ -0,0 +1,220 @@
+//! `aventura://` links, opened by the OS when the user clicks one:
+//!
+//! - `aventura://sync?ip=..&port=..&token=..` connects to a sync server, like
+//!   scanning its QR code
+//! - `aventura://story/<id>` opens a story
+//! - `aventura://import?url=https://..` downloads a shared story export
+//!
+//! Links that reach out to other machines are confirmed with a native dialog
+//! first. Accepted links are queued and announced with `deeplink://open`;
+//! links that arrive before the window is ready are collected by the
+//! frontend with `take_pending_deep_links`.
+
+pub mod commands;
+
+pub use commands::DeepLinkState;
+
+use reqwest::Url;
+use serde::Serialize;
+use std::net::IpAddr;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_deep_link::DeepLinkExt;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::import::{remote, watcher};
+use crate::portable;
+use crate::sync::device::DeviceIdentity;
+use crate::sync::health;
+
+pub const SCHEME: &str = "aventura";
+
+const MAX_TOKEN_LEN: usize = 256;
+
+/// A validated link
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DeepLink {
+    /// Same fields as a scanned sync QR code
+    #[serde(rename_all = "camelCase")]
+    Sync {
+        ip: String,
+        port: u16,
+        token: String,
+        /// Who runs the server, if it answered a health check after the
+        /// link was confirmed
+        device: Option<DeviceIdentity>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Story { story_id: String },
+    /// Handled in the backend: the story is offered like a watched import
+    Import { url: String },
+}
+
+fn is_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 128
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Check a link's shape and values without acting on it
+pub fn parse(url: &Url) -> Result<DeepLink, String> {
+    if url.scheme() != SCHEME {
+        return Err(format!("Not an {}:// link", SCHEME));
+    }
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    match url.host_str().unwrap_or_default() {
+        "sync" => {
+            let ip = param("ip").ok_or("Sync link has no ip")?;
+            ip.parse::<IpAddr>()
+                .map_err(|_| format!("Invalid IP address in sync link: {}", ip))?;
+            let port = param("port")
+                .and_then(|p| p.parse::<u16>().ok())
+                .filter(|p| *p != 0)
+                .ok_or("Sync link has no valid port")?;
+            let token = param("token")
+                .filter(|t| {
+                    !t.is_empty()
+                        && t.len() <= MAX_TOKEN_LEN
+                        && t.chars().all(|c| c.is_ascii_graphic())
+                })
+                .ok_or("Sync link has no valid token")?;
+            Ok(DeepLink::Sync {
+                ip,
+                port,
+                token,
+                device: None,
+            })
+        }
+        "story" => {
+            let story_id = url.path().trim_matches('/');
+            if !is_id(story_id) {
+                return Err("Story link has no valid story ID".to_string());
+            }
+            Ok(DeepLink::Story {
+                story_id: story_id.to_string(),
+            })
+        }
+        "import" => {
+            let target = param("url").ok_or("Import link has no url")?;
+            Ok(DeepLink::Import {
+                url: remote::parse_url(&target)?.to_string(),
+            })
+        }
+        other => Err(format!("Unknown link: {}://{}", SCHEME, other)),
+    }
+}
+
+/// Ask the user before following a link. Opening a story needs no consent.
+async fn confirm(app: &AppHandle, link: &DeepLink) -> bool {
+    let (title, message, accept) = match link {
+        DeepLink::Story { .. } => return true,
+        DeepLink::Sync { ip, port, .. } => (
+            "Connect to device?",
+            format!(
+                "A link wants to connect Aventura to {}:{} for syncing. Only continue if you trust this device.",
+                ip, port
+            ),
+            "Connect",
+        ),
+        DeepLink::Import { url } => (
+            "Import story?",
+            format!(
+                "A link wants to download and import a story from:\n\n{}\n\nOnly continue if you trust the source.",
+                url
+            ),
+            "Import",
+        ),
+    };
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .message(message)
+        .title(title)
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            accept.to_string(),
+            "Cancel".to_string(),
+        ))
+        .show(move |confirmed| {
+            let _ = tx.send(confirmed);
+        });
+    rx.await.unwrap_or(false)
+}
+
+async fn follow(app: &AppHandle, link: DeepLink) -> Result<(), String> {
+    if let DeepLink::Import { url } = &link {
+        let import = remote::fetch(&remote::parse_url(url)?).await?;
+        watcher::offer(app, import).await;
+    }
+    app.state::<DeepLinkState>()
+        .pending
+        .lock()
+        .await
+        .push(link.clone());
+    let _ = app.emit("deeplink://open", link);
+    Ok(())
+}
+
+/// Validate, confirm and follow one link, reporting failures with
+/// `deeplink://error`
+pub async fn handle(app: &AppHandle, url: Url) {
+    let mut link = match parse(&url) {
+        Ok(link) => link,
+        Err(e) => {
+            let _ = app.emit("deeplink://error", e);
+            return;
+        }
+    };
+    if !confirm(app, &link).await {
+        return;
+    }
+    // The peer is only contacted once the user has agreed to it
+    if let DeepLink::Sync {
+        ip, port, device, ..
+    } = &mut link
+    {
+        *device = health::fetch(app, ip, *port)
+            .await
+            .ok()
+            .map(|info| info.device);
+    }
+    if let Err(e) = follow(app, link).await {
+        let _ = app.emit("deeplink://error", e);
+    }
+}
+
+/// Handle the link the app was launched with and any opened while it runs
+pub fn register(app: &AppHandle) {
+    // Installed builds register the scheme at install time; this covers
+    // development builds and unpacked copies on Linux and Windows. Portable
+    // mode leaves the system untouched.
+    #[cfg(any(target_os = "linux", windows))]
+    if !portable::is_enabled() {
+        if let Err(e) = app.deep_link().register_all() {
+            eprintln!("Failed to register {}:// links: {}", SCHEME, e);
+        }
+    }
+
+    let opener = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        for url in event.urls() {
+            spawn_handle(&opener, url);
+        }
+    });
+
+    if let Ok(Some(urls)) = app.deep_link().get_current() {
+        for url in urls {
+            spawn_handle(app, url);
+        }
+    }
+}
+
+fn spawn_handle(app: &AppHandle, url: Url) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move { handle(&app, url).await });
+}
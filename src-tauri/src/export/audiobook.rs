@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+use tauri::{AppHandle, Emitter};
+
+use super::mp3::{self, ChapterMark};
+use crate::story::text::plain_text;
+use crate::story::StoryExport;
+
+/// Longest text sent in a single speech request (OpenAI caps input at 4096 chars)
+const MAX_SPEECH_CHARS: usize = 4000;
+
+/// OpenAI-compatible text-to-speech endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsProviderConfig {
+    /// Full URL of the speech endpoint (e.g. "https://api.openai.com/v1/audio/speech")
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+/// Output container for an audiobook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AudiobookFormat {
+    Mp3,
+    /// AAC audiobook; requires ffmpeg on PATH
+    M4b,
+}
+
+/// Payload of the `audiobook://progress` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudiobookProgress {
+    pub story_id: String,
+    pub completed: usize,
+    pub total: usize,
+}
+
+/// Summary of a finished audiobook export
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudiobookResult {
+    pub path: String,
+    pub duration_ms: u64,
+    pub chapter_count: usize,
+    pub entry_count: usize,
+}
+
+/// Split text into pieces short enough for one speech request,
+/// preferring paragraph and then sentence boundaries
+fn split_for_speech(text: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+
+    let sentences = text
+        .split_inclusive("\n\n")
+        .flat_map(|paragraph| paragraph.split_inclusive(['.', '!', '?']));
+    for sentence in sentences {
+        if current.len() + sentence.len() > MAX_SPEECH_CHARS && !current.trim().is_empty() {
+            pieces.push(std::mem::take(&mut current));
+        }
+        // A single overlong sentence is cut at a char boundary
+        let mut rest = sentence;
+        while rest.len() > MAX_SPEECH_CHARS {
+            let mut cut = MAX_SPEECH_CHARS;
+            while !rest.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            pieces.push(rest[..cut].to_string());
+            rest = &rest[cut..];
+        }
+        current.push_str(rest);
+    }
+    if !current.trim().is_empty() {
+        pieces.push(current);
+    }
+    pieces
+}
+
+/// Synthesize one piece of text to MP3
+async fn synthesize(tts: &TtsProviderConfig, voice: &str, text: &str) -> Result<Vec<u8>, String> {
+    let client = reqwest::Client::new();
+    let mut builder = client
+        .post(&tts.endpoint)
+        .json(&serde_json::json!({
+            "model": tts.model,
+            "voice": voice,
+            "input": text,
+            "response_format": "mp3",
+        }))
+        .timeout(std::time::Duration::from_secs(120));
+    if let Some(ref key) = tts.api_key {
+        builder = builder.bearer_auth(key);
+    }
+
+    let response = builder
+        .send()
+        .await
+        .map_err(|e| format!("Speech request failed: {}", e))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!("Speech provider returned {}: {}", status, text));
+    }
+    response
+        .bytes()
+        .await
+        .map(|b| b.to_vec())
+        .map_err(|e| format!("Failed to read speech audio: {}", e))
+}
+
+/// Memory-system chapter titles paired with the entry that starts each chapter.
+/// Stories without chapters get one marker per entry instead.
+fn chapter_titles(export: &StoryExport) -> Vec<(String, String)> {
+    let mut chapters: Vec<_> = export.chapters.iter().collect();
+    chapters.sort_by_key(|c| c.number);
+    chapters
+        .into_iter()
+        .map(|c| {
+            let title = c
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("Chapter {}", c.number));
+            (c.start_entry_id.clone(), title)
+        })
+        .collect()
+}
+
+/// Write the MP3 to an M4B with chapter metadata using ffmpeg
+fn encode_m4b(mp3_path: &Path, output: &Path, chapters: &[ChapterMark]) -> Result<(), String> {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        let title = chapter
+            .title
+            .replace('\\', "\\\\")
+            .replace('=', "\\=")
+            .replace(';', "\\;")
+            .replace('#', "\\#")
+            .replace('\n', " ");
+        metadata.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start_ms, chapter.end_ms, title
+        ));
+    }
+    let metadata_path = mp3_path.with_extension("ffmeta");
+    std::fs::write(&metadata_path, metadata)
+        .map_err(|e| format!("Failed to write chapter metadata: {}", e))?;
+
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(mp3_path)
+        .arg("-i")
+        .arg(&metadata_path)
+        .args([
+            "-map",
+            "0:a",
+            "-map_metadata",
+            "1",
+            "-c:a",
+            "aac",
+            "-b:a",
+            "64k",
+        ])
+        .arg(output)
+        .status();
+    let _ = std::fs::remove_file(&metadata_path);
+
+    match status {
+        Ok(s) if s.success() => Ok(()),
+        Ok(s) => Err(format!("ffmpeg failed with {}", s)),
+        Err(e) => Err(format!("M4B export requires ffmpeg on PATH: {}", e)),
+    }
+}
+
+/// Synthesize every narration and action entry and write them as one audiobook
+pub async fn export_audiobook(
+    app: &AppHandle,
+    export: &StoryExport,
+    voice: &str,
+    format: AudiobookFormat,
+    tts: &TtsProviderConfig,
+    path: &Path,
+) -> Result<AudiobookResult, String> {
+    let chapter_starts = chapter_titles(export);
+
+    let mut entries: Vec<_> = export
+        .entries
+        .iter()
+        .filter(|e| e.entry_type == "narration" || e.entry_type == "user_action")
+        .collect();
+    entries.sort_by_key(|e| e.position);
+    let total = entries.len();
+
+    let mut audio = Vec::new();
+    let mut chapters: Vec<ChapterMark> = Vec::new();
+    let mut elapsed_ms = 0;
+
+    for (index, entry) in entries.iter().enumerate() {
+        let chapter_title = if chapter_starts.is_empty() {
+            Some(format!("Part {}", index + 1))
+        } else {
+            chapter_starts
+                .iter()
+                .find(|(start, _)| *start == entry.id)
+                .map(|(_, title)| title.clone())
+        };
+        if let Some(title) = chapter_title {
+            if let Some(previous) = chapters.last_mut() {
+                previous.end_ms = elapsed_ms;
+            }
+            chapters.push(ChapterMark {
+                title,
+                start_ms: elapsed_ms,
+                end_ms: elapsed_ms,
+            });
+        }
+
+        for piece in split_for_speech(&plain_text(&entry.content)) {
+            let segment = synthesize(tts, voice, &piece).await?;
+            let segment = mp3::strip_tags(&segment);
+            elapsed_ms += mp3::duration_ms(segment);
+            audio.extend_from_slice(segment);
+        }
+
+        let _ = app.emit(
+            "audiobook://progress",
+            AudiobookProgress {
+                story_id: export.story.id.clone(),
+                completed: index + 1,
+                total,
+            },
+        );
+    }
+    if let Some(last) = chapters.last_mut() {
+        last.end_ms = elapsed_ms;
+    }
+
+    let mut mp3_data = mp3::build_tag(&export.story.title, "Aventura", &chapters);
+    mp3_data.extend(audio);
+
+    match format {
+        AudiobookFormat::Mp3 => std::fs::write(path, &mp3_data)
+            .map_err(|e| format!("Failed to write audiobook: {}", e))?,
+        AudiobookFormat::M4b => {
+            let mp3_path = path.with_extension("tmp.mp3");
+            std::fs::write(&mp3_path, &mp3_data)
+                .map_err(|e| format!("Failed to write audiobook: {}", e))?;
+            let result = encode_m4b(&mp3_path, path, &chapters);
+            let _ = std::fs::remove_file(&mp3_path);
+            result?;
+        }
+    }
+
+    Ok(AudiobookResult {
+        path: path.to_string_lossy().to_string(),
+        duration_ms: elapsed_ms,
+        chapter_count: chapters.len(),
+        entry_count: total,
+    })
+}
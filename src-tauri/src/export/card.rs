@@ -0,0 +1,171 @@
+//! Character cards for other AI chat tools: the character's portrait as a
+//! PNG with its definition embedded in text chunks. The V2 card goes under
+//! the `chara` keyword, which every tool reads, and the same card as V3
+//! under `ccv3` for the tools that prefer it.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::Crc;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use serde_json::{json, Value};
+use std::io::Cursor;
+use tauri::AppHandle;
+
+use crate::profiles::{self, database};
+
+/// Portrait size when the character has none
+const PLACEHOLDER_WIDTH: u32 = 512;
+const PLACEHOLDER_HEIGHT: u32 = 768;
+
+/// A character as the story keeps it
+#[derive(Debug, Clone)]
+pub struct CardCharacter {
+    pub story_id: String,
+    pub name: String,
+    pub description: String,
+    pub relationship: Option<String>,
+    pub traits: Vec<String>,
+    /// Base64 image data, with or without a data URL prefix
+    pub portrait: Option<String>,
+}
+
+/// Story ID, name, description, relationship, traits and portrait
+type CharacterRow = (
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+pub async fn load_character(app: &AppHandle, character_id: &str) -> Result<CardCharacter, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let row: Result<Option<CharacterRow>, String> = sqlx::query_as(
+        "SELECT story_id, name, description, relationship, traits, portrait \
+         FROM characters WHERE id = ?",
+    )
+    .bind(character_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| format!("Failed to read character: {}", e));
+    pool.close().await;
+    let (story_id, name, description, relationship, traits, portrait) =
+        row?.ok_or_else(|| format!("Character not found: {}", character_id))?;
+    Ok(CardCharacter {
+        story_id,
+        name,
+        description: description.unwrap_or_default(),
+        relationship: relationship.filter(|r| !r.trim().is_empty()),
+        traits: traits
+            .and_then(|t| serde_json::from_str(&t).ok())
+            .unwrap_or_default(),
+        portrait: portrait.filter(|p| !p.trim().is_empty()),
+    })
+}
+
+/// The card's data, shared by the V2 and V3 layouts
+fn card_data(character: &CardCharacter) -> Value {
+    json!({
+        "name": character.name,
+        "description": character.description,
+        "personality": character.traits.join(", "),
+        "scenario": "",
+        "first_mes": "",
+        "mes_example": "",
+        "creator_notes": "Exported from Aventura",
+        "system_prompt": "",
+        "post_history_instructions": "",
+        "alternate_greetings": [],
+        "tags": [],
+        "creator": "",
+        "character_version": "",
+        "extensions": {
+            "aventura": {
+                "relationship": character.relationship,
+                "traits": character.traits,
+            }
+        },
+    })
+}
+
+/// The card as V2 and V3 JSON
+pub fn card_json(character: &CardCharacter) -> (String, String) {
+    let data = card_data(character);
+    let v2 = json!({ "spec": "chara_card_v2", "spec_version": "2.0", "data": data });
+    let v3 = json!({ "spec": "chara_card_v3", "spec_version": "3.0", "data": data });
+    (v2.to_string(), v3.to_string())
+}
+
+/// The character's portrait as PNG, or a plain placeholder without one
+fn portrait_png(character: &CardCharacter) -> Result<Vec<u8>, String> {
+    let image = match character.portrait.as_deref() {
+        Some(portrait) => {
+            let data = portrait
+                .split_once(";base64,")
+                .map_or(portrait, |(_, data)| data);
+            let bytes = STANDARD
+                .decode(data.trim())
+                .map_err(|e| format!("Invalid portrait data: {}", e))?;
+            image::load_from_memory(&bytes)
+                .map_err(|e| format!("Unsupported portrait image: {}", e))?
+        }
+        None => DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            PLACEHOLDER_WIDTH,
+            PLACEHOLDER_HEIGHT,
+            Rgba([48, 52, 64, 255]),
+        )),
+    };
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode portrait: {}", e))?;
+    Ok(png)
+}
+
+fn text_chunk(keyword: &str, text: &str) -> Vec<u8> {
+    let mut body = b"tEXt".to_vec();
+    body.extend_from_slice(keyword.as_bytes());
+    body.push(0);
+    body.extend_from_slice(text.as_bytes());
+    let mut crc = Crc::new();
+    crc.update(&body);
+
+    let mut chunk = ((body.len() - 4) as u32).to_be_bytes().to_vec();
+    chunk.extend(body);
+    chunk.extend_from_slice(&crc.sum().to_be_bytes());
+    chunk
+}
+
+/// Add text chunks to a PNG right before its image data
+fn embed(png: &[u8], chunks: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+    let mut offset = 8;
+    while offset + 8 <= png.len() {
+        let len = u32::from_be_bytes([
+            png[offset],
+            png[offset + 1],
+            png[offset + 2],
+            png[offset + 3],
+        ]) as usize;
+        if &png[offset + 4..offset + 8] == b"IDAT" {
+            let mut out = png[..offset].to_vec();
+            chunks.iter().for_each(|chunk| out.extend_from_slice(chunk));
+            out.extend_from_slice(&png[offset..]);
+            return Ok(out);
+        }
+        offset += 12 + len;
+    }
+    Err("The portrait PNG has no image data".to_string())
+}
+
+/// The character card PNG
+pub fn render(character: &CardCharacter) -> Result<Vec<u8>, String> {
+    let (v2, v3) = card_json(character);
+    let png = portrait_png(character)?;
+    embed(
+        &png,
+        &[
+            text_chunk("chara", &STANDARD.encode(v2)),
+            text_chunk("ccv3", &STANDARD.encode(v3)),
+        ],
+    )
+}
@@ -0,0 +1,558 @@
+use chrono::{Local, TimeZone};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use super::site::escape_html;
+use crate::story::text::plain_text;
+use crate::story::types::StoryEntry;
+use crate::story::versions::StoryVersion;
+use crate::story::StoryExport;
+
+/// Unchanged words shown on each side of an edit
+const CONTEXT_WORDS: usize = 12;
+
+/// Word grids larger than this are not compared word by word; the edited
+/// part is shown as replaced instead
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Characters shown of an entry that was removed
+const REMOVED_EXCERPT_CHARS: usize = 400;
+
+const STYLE: &str = "body{font-family:Georgia,serif;max-width:46em;margin:2em auto;padding:0 1em;line-height:1.6;color:#222}\
+    .meta{color:#666}.entry{white-space:pre-wrap}\
+    ins{background:#d7f5dd;text-decoration:none}del{background:#fbdada;color:#733}";
+
+/// Output format for a change report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+/// What changed, in numbers and names
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeSummary {
+    pub entries_added: usize,
+    pub entries_edited: usize,
+    pub entries_removed: usize,
+    pub words_added: usize,
+    pub words_removed: usize,
+    pub chapters_added: Vec<String>,
+    pub characters_added: Vec<String>,
+    pub lorebook_added: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeReport {
+    pub story_id: String,
+    pub title: String,
+    pub since: i64,
+    /// The version the story was compared against. It is newer than `since`
+    /// when the story has no version that old.
+    pub baseline: StoryVersion,
+    pub summary: ChangeSummary,
+    pub format: ChangeFormat,
+    pub content: String,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Span {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Where an earlier copy of a story is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snapshot {
+    /// A checkpoint saved in the story's database
+    Checkpoint,
+    /// A commit in the story history repository
+    History,
+    /// A version kept before a merge or another operation replaced the story
+    Version,
+}
+
+/// The newest copy from no later than `since`, or the oldest there is
+pub fn baseline(
+    mut candidates: Vec<(StoryVersion, Snapshot)>,
+    since: i64,
+) -> Option<(StoryVersion, Snapshot)> {
+    candidates.sort_by_key(|(v, _)| std::cmp::Reverse(v.created_at));
+    let index = candidates
+        .iter()
+        .position(|(v, _)| v.created_at <= since)
+        .or(candidates.len().checked_sub(1))?;
+    Some(candidates.swap_remove(index))
+}
+
+fn checkpoints(current: &StoryExport) -> impl Iterator<Item = &Value> {
+    current
+        .extra
+        .get("checkpoints")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+/// The story's checkpoints, described as versions
+pub fn checkpoint_versions(current: &StoryExport) -> Vec<StoryVersion> {
+    checkpoints(current)
+        .filter_map(|checkpoint| {
+            let name = checkpoint.get("name").and_then(Value::as_str)?;
+            Some(StoryVersion {
+                id: checkpoint.get("id").and_then(Value::as_str)?.to_string(),
+                story_id: current.story.id.clone(),
+                title: current.story.title.clone(),
+                label: format!("Checkpoint \"{}\"", name),
+                created_at: checkpoint.get("createdAt").and_then(Value::as_i64)?,
+            })
+        })
+        .collect()
+}
+
+fn snapshot<T: DeserializeOwned>(
+    checkpoint: &Value,
+    field: &str,
+) -> Result<Option<Vec<T>>, String> {
+    match checkpoint.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|e| format!("Checkpoint has an invalid {}: {}", field, e)),
+    }
+}
+
+/// The story as it was when a checkpoint was saved. Checkpoints keep the
+/// entries and world state only, so the rest comes from the current story.
+pub fn at_checkpoint(current: &StoryExport, checkpoint_id: &str) -> Result<StoryExport, String> {
+    let checkpoint = checkpoints(current)
+        .find(|c| c.get("id").and_then(Value::as_str) == Some(checkpoint_id))
+        .ok_or_else(|| format!("Checkpoint not found: {}", checkpoint_id))?;
+    let mut story = current.clone();
+    story.entries = snapshot(checkpoint, "entriesSnapshot")?.unwrap_or_default();
+    story.characters = snapshot(checkpoint, "charactersSnapshot")?.unwrap_or_default();
+    story.locations = snapshot(checkpoint, "locationsSnapshot")?.unwrap_or_default();
+    story.items = snapshot(checkpoint, "itemsSnapshot")?.unwrap_or_default();
+    story.story_beats = snapshot(checkpoint, "storyBeatsSnapshot")?.unwrap_or_default();
+    story.chapters = snapshot(checkpoint, "chaptersSnapshot")?.unwrap_or_default();
+    // Older checkpoints did not record the lorebook
+    if let Some(lorebook) = snapshot(checkpoint, "lorebookEntriesSnapshot")? {
+        story.lorebook_entries = lorebook;
+    }
+    Ok(story)
+}
+
+/// Words with the whitespace after them, so spans join back into the text
+fn words(text: &str) -> Vec<&str> {
+    text.split_inclusive(char::is_whitespace)
+        .filter(|w| !w.trim().is_empty() || w.contains('\n'))
+        .collect()
+}
+
+fn word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+fn push(spans: &mut Vec<Span>, span: Span) {
+    match (spans.last_mut(), span) {
+        (Some(Span::Same(last)), Span::Same(text))
+        | (Some(Span::Added(last)), Span::Added(text))
+        | (Some(Span::Removed(last)), Span::Removed(text)) => last.push_str(&text),
+        (_, span) => spans.push(span),
+    }
+}
+
+/// Word-level differences between two texts, by longest common subsequence
+/// after trimming what they start and end with in common
+fn diff_words(old: &str, new: &str) -> Vec<Span> {
+    let (old, new) = (words(old), words(new));
+    let same = |a: &str, b: &str| a.trim_end() == b.trim_end();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| same(a, b)).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut spans = Vec::new();
+    push(&mut spans, Span::Same(new[..prefix].concat()));
+    if a.len().saturating_mul(b.len()) > MAX_DIFF_CELLS {
+        push(&mut spans, Span::Removed(a.concat()));
+        push(&mut spans, Span::Added(b.concat()));
+    } else {
+        // lengths[i][j]: common subsequence of a[i..] and b[j..]
+        let width = b.len() + 1;
+        let mut lengths = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i * width + j] = if same(a[i], b[j]) {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && same(a[i], b[j]) {
+                push(&mut spans, Span::Same(b[j].to_string()));
+                i += 1;
+                j += 1;
+            } else if i < a.len()
+                && (j == b.len() || lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+            {
+                push(&mut spans, Span::Removed(a[i].to_string()));
+                i += 1;
+            } else {
+                push(&mut spans, Span::Added(b[j].to_string()));
+                j += 1;
+            }
+        }
+    }
+    push(&mut spans, Span::Same(new[new.len() - suffix..].concat()));
+    spans.retain(|s| !matches!(s, Span::Same(text) if text.is_empty()));
+    spans
+}
+
+/// Shorten unchanged text between edits to the words next to them
+fn trim_context(text: &str, before_edit: bool, after_edit: bool) -> String {
+    let words = words(text);
+    let keep = |range: &[&str]| range.concat();
+    match (after_edit, before_edit) {
+        (true, true) if words.len() > CONTEXT_WORDS * 2 => format!(
+            "{}… {}",
+            keep(&words[..CONTEXT_WORDS]),
+            keep(&words[words.len() - CONTEXT_WORDS..])
+        ),
+        (true, false) if words.len() > CONTEXT_WORDS => {
+            format!("{}…", keep(&words[..CONTEXT_WORDS]).trim_end())
+        }
+        (false, true) if words.len() > CONTEXT_WORDS => {
+            format!("…{}", keep(&words[words.len() - CONTEXT_WORDS..]))
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '*' | '_' | '~' | '`' | '[' | ']' | '<' | '>' | '#' | '\\'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape(text: &str, format: ChangeFormat) -> String {
+    match format {
+        ChangeFormat::Markdown => escape_markdown(text),
+        ChangeFormat::Html => escape_html(text),
+    }
+}
+
+/// Mark each line of a run of text, leaving the whitespace around it outside
+/// the marks so Markdown still recognises them
+fn mark(text: &str, open: &str, close: &str, format: ChangeFormat) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            let start = line.len() - line.trim_start().len();
+            let end = line.trim_end().len().max(start);
+            if start == end {
+                return line.to_string();
+            }
+            format!(
+                "{}{}{}{}{}",
+                &line[..start],
+                open,
+                escape(&line[start..end], format),
+                close,
+                &line[end..]
+            )
+        })
+        .collect()
+}
+
+fn added(text: &str, format: ChangeFormat) -> String {
+    match format {
+        ChangeFormat::Markdown => mark(text, "**", "**", format),
+        ChangeFormat::Html => mark(text, "<ins>", "</ins>", format),
+    }
+}
+
+fn removed(text: &str, format: ChangeFormat) -> String {
+    match format {
+        ChangeFormat::Markdown => mark(text, "~~", "~~", format),
+        ChangeFormat::Html => mark(text, "<del>", "</del>", format),
+    }
+}
+
+fn render_spans(spans: &[Span], format: ChangeFormat) -> String {
+    spans
+        .iter()
+        .enumerate()
+        .map(|(i, span)| match span {
+            Span::Same(text) => escape(&trim_context(text, i + 1 < spans.len(), i > 0), format),
+            Span::Added(text) => added(text, format),
+            Span::Removed(text) => removed(text, format),
+        })
+        .collect()
+}
+
+fn date(ms: i64) -> String {
+    Local
+        .timestamp_millis_opt(ms)
+        .single()
+        .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+fn main_entries(export: &StoryExport) -> Vec<&StoryEntry> {
+    let mut main: Vec<&StoryEntry> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none() && e.entry_type != "system")
+        .collect();
+    main.sort_by_key(|e| e.position);
+    main
+}
+
+/// Chapter heading for each entry in a chapter
+fn chapter_titles(export: &StoryExport, main: &[&StoryEntry]) -> HashMap<String, String> {
+    let index: HashMap<&str, usize> = main
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (e.id.as_str(), i))
+        .collect();
+    let mut titles = HashMap::new();
+    for chapter in &export.chapters {
+        let (Some(&start), Some(&end)) = (
+            index.get(chapter.start_entry_id.as_str()),
+            index.get(chapter.end_entry_id.as_str()),
+        ) else {
+            continue;
+        };
+        let title = match &chapter.title {
+            Some(title) if !title.trim().is_empty() => {
+                format!("Chapter {}: {}", chapter.number, title.trim())
+            }
+            _ => format!("Chapter {}", chapter.number),
+        };
+        for entry in main.iter().take(end + 1).skip(start) {
+            titles.insert(entry.id.clone(), title.clone());
+        }
+    }
+    titles
+}
+
+/// Names in `current` under IDs that `baseline` does not have
+fn new_names<T>(
+    current: &[T],
+    baseline: &[T],
+    id: impl Fn(&T) -> &str,
+    name: impl Fn(&T) -> String,
+) -> Vec<String> {
+    let old: HashSet<&str> = baseline.iter().map(&id).collect();
+    current
+        .iter()
+        .filter(|item| !old.contains(id(item)))
+        .map(name)
+        .collect()
+}
+
+struct Document {
+    format: ChangeFormat,
+    body: String,
+}
+
+impl Document {
+    fn heading(&mut self, level: usize, text: &str) {
+        match self.format {
+            ChangeFormat::Markdown => self.body.push_str(&format!(
+                "{} {}\n\n",
+                "#".repeat(level),
+                escape_markdown(text)
+            )),
+            ChangeFormat::Html => self
+                .body
+                .push_str(&format!("<h{level}>{}</h{level}>\n", escape_html(text))),
+        }
+    }
+
+    /// `text` is already rendered for the format
+    fn paragraph(&mut self, text: &str, class: &str) {
+        match self.format {
+            ChangeFormat::Markdown => {
+                self.body.push_str(text.trim_end());
+                self.body.push_str("\n\n");
+            }
+            ChangeFormat::Html => {
+                self.body
+                    .push_str(&format!("<p class=\"{}\">{}</p>\n", class, text.trim_end()))
+            }
+        }
+    }
+}
+
+/// Render what changed from `baseline` to `current` as a document to share
+pub fn report(
+    current: &StoryExport,
+    baseline: &StoryExport,
+    version: &StoryVersion,
+    format: ChangeFormat,
+) -> (String, ChangeSummary) {
+    let main = main_entries(current);
+    let old_main = main_entries(baseline);
+    let old_by_id: HashMap<&str, &StoryEntry> =
+        old_main.iter().map(|e| (e.id.as_str(), *e)).collect();
+    let current_ids: HashSet<&str> = main.iter().map(|e| e.id.as_str()).collect();
+    let chapters = chapter_titles(current, &main);
+
+    let mut summary = ChangeSummary {
+        chapters_added: new_names(
+            &current.chapters,
+            &baseline.chapters,
+            |c| c.id.as_str(),
+            |c| match &c.title {
+                Some(title) if !title.trim().is_empty() => title.trim().to_string(),
+                _ => format!("Chapter {}", c.number),
+            },
+        ),
+        characters_added: new_names(
+            &current.characters,
+            &baseline.characters,
+            |c| c.id.as_str(),
+            |c| c.name.clone(),
+        ),
+        lorebook_added: new_names(
+            &current.lorebook_entries,
+            &baseline.lorebook_entries,
+            |l| l.id.as_str(),
+            |l| l.name.clone(),
+        ),
+        ..Default::default()
+    };
+
+    let mut changes = Document {
+        format,
+        body: String::new(),
+    };
+    let mut chapter: Option<&String> = None;
+    for (number, entry) in main.iter().enumerate() {
+        let text = plain_text(&entry.content);
+        let (label, rendered) = match old_by_id.get(entry.id.as_str()) {
+            None => {
+                summary.entries_added += 1;
+                summary.words_added += word_count(&text);
+                ("new", added(&text, format))
+            }
+            Some(old) => {
+                let old_text = plain_text(&old.content);
+                if old_text.trim() == text.trim() {
+                    continue;
+                }
+                let spans = diff_words(&old_text, &text);
+                for span in &spans {
+                    match span {
+                        Span::Added(words) => summary.words_added += word_count(words),
+                        Span::Removed(words) => summary.words_removed += word_count(words),
+                        Span::Same(_) => {}
+                    }
+                }
+                summary.entries_edited += 1;
+                ("edited", render_spans(&spans, format))
+            }
+        };
+        let entry_chapter = chapters.get(&entry.id);
+        if entry_chapter.is_some() && entry_chapter != chapter {
+            changes.heading(2, entry_chapter.map_or("", String::as_str));
+            chapter = entry_chapter;
+        }
+        changes.heading(3, &format!("Entry {} ({})", number + 1, label));
+        changes.paragraph(&rendered, "entry");
+    }
+
+    let gone: Vec<&&StoryEntry> = old_main
+        .iter()
+        .filter(|e| !current_ids.contains(e.id.as_str()))
+        .collect();
+    if !gone.is_empty() {
+        changes.heading(2, "Removed entries");
+        for entry in gone {
+            let text = plain_text(&entry.content);
+            summary.entries_removed += 1;
+            summary.words_removed += word_count(&text);
+            let mut excerpt: String = text.chars().take(REMOVED_EXCERPT_CHARS).collect();
+            if excerpt.len() < text.len() {
+                excerpt.push('…');
+            }
+            changes.paragraph(&removed(&excerpt, format), "entry");
+        }
+    }
+
+    let mut document = Document {
+        format,
+        body: String::new(),
+    };
+    document.heading(1, &format!("Changes to {}", current.story.title));
+    document.paragraph(
+        &escape(
+            &format!(
+                "Since {} ({}). {} entries added, {} edited, {} removed; {} words added, {} removed.",
+                date(version.created_at),
+                version.label,
+                summary.entries_added,
+                summary.entries_edited,
+                summary.entries_removed,
+                summary.words_added,
+                summary.words_removed
+            ),
+            format,
+        ),
+        "meta",
+    );
+    for (label, names) in [
+        ("New chapters", &summary.chapters_added),
+        ("New characters", &summary.characters_added),
+        ("New lorebook entries", &summary.lorebook_added),
+    ] {
+        if !names.is_empty() {
+            document.paragraph(
+                &escape(&format!("{}: {}", label, names.join(", ")), format),
+                "meta",
+            );
+        }
+    }
+    if changes.body.is_empty() {
+        document.paragraph(&escape("No changes to the story text.", format), "meta");
+    }
+    document.body.push_str(&changes.body);
+
+    let content = match format {
+        ChangeFormat::Markdown => document.body,
+        ChangeFormat::Html => format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            escape_html(&format!("Changes to {}", current.story.title)),
+            STYLE,
+            document.body
+        ),
+    };
+    (content, summary)
+}
@@ -0,0 +1,285 @@
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+
+use super::audiobook::{self, AudiobookFormat, AudiobookResult, TtsProviderConfig};
+use super::card;
+use super::changes::{self, ChangeFormat, ChangeReport, Snapshot};
+use super::cover::{self, CoverBackground, CoverStyle};
+use super::obsidian::{self, ObsidianExportResult, ObsidianOptions};
+use super::schedule::{ExportRule, ExportRunStatus, ExportScheduler};
+use super::site::{self, SiteExportResult, SiteTheme};
+use super::summary::{self, SummaryExportResult, SummaryFormat};
+use super::twine;
+use crate::ai::filter::Strictness;
+use crate::ai::types::ProviderConfig;
+use crate::attachments::Attachment;
+use crate::history::{self, commands::open_if_enabled};
+use crate::profiles;
+use crate::story::versions::StoryVersion;
+use crate::story::{rows, versions, StoryExport};
+
+/// State managed by Tauri for exports
+#[derive(Default)]
+pub struct ExportState {
+    /// Scheduled export rules and their worker
+    pub(crate) scheduler: ExportScheduler,
+}
+
+/// Synthesize a saved story to an MP3 or M4B audiobook with chapter markers.
+/// Reports progress per entry via `audiobook://progress` events.
+#[tauri::command]
+pub async fn export_audiobook(
+    app: AppHandle,
+    story_id: String,
+    voice: String,
+    format: AudiobookFormat,
+    tts: TtsProviderConfig,
+    path: String,
+) -> Result<AudiobookResult, String> {
+    profiles::check_story(&app, &story_id).await?;
+    profiles::check_generation(&app, &tts.endpoint, Strictness::Off)?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    audiobook::export_audiobook(&app, &export, &voice, format, &tts, &PathBuf::from(path)).await
+}
+
+/// Render a saved story as a self-contained static website in the given
+/// directory
+#[tauri::command]
+pub async fn export_story_site(
+    app: AppHandle,
+    story_id: String,
+    theme: Option<SiteTheme>,
+    path: String,
+) -> Result<SiteExportResult, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    tokio::task::spawn_blocking(move || {
+        site::export_site(
+            &export,
+            theme.unwrap_or_default(),
+            &PathBuf::from(path),
+            cover::cover_for(&app, &story_id).as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| format!("Site export failed: {}", e))?
+}
+
+/// Export a branching story as Twee 3 source for Twine
+#[tauri::command]
+pub async fn export_story_twine(story_json: String, path: String) -> Result<(), String> {
+    let export = StoryExport::from_json(&story_json)?;
+    std::fs::write(&path, twine::to_twee(&export))
+        .map_err(|e| format!("Failed to write Twine export: {}", e))
+}
+
+/// Write stories into an Obsidian vault as Markdown notes with front matter and
+/// wiki-links to character, location and lore notes
+#[tauri::command]
+pub async fn export_to_obsidian(
+    app: AppHandle,
+    vault_path: String,
+    folder: String,
+    stories_json: Vec<String>,
+    options: Option<ObsidianOptions>,
+) -> Result<ObsidianExportResult, String> {
+    let stories_json = profiles::visible_exports(&app, stories_json).await?;
+    let options = options.unwrap_or_default();
+    tokio::task::spawn_blocking(move || {
+        obsidian::export_vault(&vault_path, &folder, &stories_json, &options)
+    })
+    .await
+    .map_err(|e| format!("Obsidian export failed: {}", e))?
+}
+
+/// Generate a one-page synopsis (logline, characters, beats and stats) for a
+/// saved story, optionally writing it as Markdown or PDF
+#[tauri::command]
+pub async fn generate_story_summary(
+    app: AppHandle,
+    story_id: String,
+    provider: ProviderConfig,
+    format: Option<SummaryFormat>,
+    path: Option<String>,
+) -> Result<SummaryExportResult, String> {
+    profiles::check_story(&app, &story_id).await?;
+    profiles::check_generation(&app, &provider.base_url, Strictness::Off)?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let summary = summary::generate_summary(&export, &provider).await?;
+    let truncated = match &path {
+        Some(path) => {
+            let cover = cover::cover_for(&app, &export.story.id);
+            summary::write_summary(
+                &summary,
+                format.unwrap_or_default(),
+                &PathBuf::from(path),
+                cover.as_deref(),
+            )?
+        }
+        None => false,
+    };
+    Ok(SummaryExportResult {
+        summary,
+        path,
+        truncated,
+    })
+}
+
+/// Report what changed in a story since a time, for beta readers or
+/// co-authors: the story in the database is compared against its newest
+/// checkpoint, history commit or saved version from before then, with
+/// additions and deletions highlighted. Optionally writes the report as
+/// Markdown or HTML.
+#[tauri::command]
+pub async fn export_changes(
+    app: AppHandle,
+    story_id: String,
+    since: i64,
+    format: Option<ChangeFormat>,
+    path: Option<String>,
+) -> Result<ChangeReport, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let current = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let mut candidates: Vec<_> = changes::checkpoint_versions(&current)
+        .into_iter()
+        .map(|v| (v, Snapshot::Checkpoint))
+        .collect();
+    candidates.extend(
+        versions::list_versions(&app, &story_id)?
+            .into_iter()
+            .map(|v| (v, Snapshot::Version)),
+    );
+    let repo = open_if_enabled(&app)?;
+    if let Some(ref repo) = repo {
+        let commits = history::story_history(repo, &story_id, usize::MAX)?;
+        candidates.extend(commits.into_iter().map(|commit| {
+            let version = StoryVersion {
+                id: commit.id,
+                story_id: story_id.clone(),
+                title: current.story.title.clone(),
+                label: commit
+                    .message
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                created_at: commit.time * 1000,
+            };
+            (version, Snapshot::History)
+        }));
+    }
+    let (baseline, source) = changes::baseline(candidates, since)
+        .ok_or("The story has no checkpoints, history or saved versions to compare against yet")?;
+    let old = match source {
+        Snapshot::Checkpoint => changes::at_checkpoint(&current, &baseline.id)?,
+        Snapshot::Version => {
+            StoryExport::from_json(&versions::load_version(&app, &story_id, &baseline.id)?)?
+        }
+        Snapshot::History => {
+            let repo = repo.as_ref().ok_or("Git history is not enabled")?;
+            StoryExport::from_json(&history::story_at(repo, &story_id, &baseline.id)?)?
+        }
+    };
+    let format = format.unwrap_or_default();
+    let (content, summary) = changes::report(&current, &old, &baseline, format);
+    if let Some(path) = &path {
+        std::fs::write(path, &content)
+            .map_err(|e| format!("Failed to write change report: {}", e))?;
+    }
+    Ok(ChangeReport {
+        story_id,
+        title: current.story.title,
+        since,
+        baseline,
+        summary,
+        format,
+        content,
+        path,
+    })
+}
+
+/// Compose a cover for a story from its title and genre, over one of its
+/// images, an attachment or a generated background. The cover is stored as
+/// an attachment and used by later EPUB, PDF and site exports.
+#[tauri::command]
+pub async fn generate_cover(
+    app: AppHandle,
+    story_json: String,
+    style: Option<CoverStyle>,
+    background: Option<CoverBackground>,
+) -> Result<Attachment, String> {
+    tokio::task::spawn_blocking(move || {
+        let export = StoryExport::from_json(&story_json)?;
+        let picture = background
+            .map(|background| cover::load_background(&app, &export, &background))
+            .transpose()?;
+        let png = cover::render(&export, style.unwrap_or_default(), picture.as_ref())?;
+        cover::save_cover(&app, &export.story.id, &png)
+    })
+    .await
+    .map_err(|e| format!("Cover generation failed: {}", e))?
+}
+
+/// Write a character as a character card PNG that other AI chat tools can
+/// import: the portrait with the character's definition embedded
+#[tauri::command]
+pub async fn export_character_card(
+    app: AppHandle,
+    character_id: String,
+    path: String,
+) -> Result<(), String> {
+    let character = card::load_character(&app, &character_id).await?;
+    profiles::check_story(&app, &character.story_id).await?;
+    tokio::task::spawn_blocking(move || {
+        let png = card::render(&character)?;
+        std::fs::write(&path, png).map_err(|e| format!("Failed to write character card: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Character card export failed: {}", e))?
+}
+
+/// Scheduled export rules with the status of their last run
+#[tauri::command]
+pub async fn list_export_rules(
+    app: AppHandle,
+    state: State<'_, ExportState>,
+) -> Result<Vec<ExportRule>, String> {
+    state.scheduler.list(&app).await
+}
+
+/// Create or update a scheduled export rule. Rules without an ID get one.
+#[tauri::command]
+pub async fn save_export_rule(
+    app: AppHandle,
+    state: State<'_, ExportState>,
+    rule: ExportRule,
+) -> Result<ExportRule, String> {
+    state.scheduler.save(&app, rule).await
+}
+
+#[tauri::command]
+pub async fn delete_export_rule(
+    app: AppHandle,
+    state: State<'_, ExportState>,
+    rule_id: String,
+) -> Result<bool, String> {
+    state.scheduler.delete(&app, &rule_id).await
+}
+
+/// Run a rule now instead of waiting for it to be due
+#[tauri::command]
+pub async fn run_export_rule(
+    app: AppHandle,
+    state: State<'_, ExportState>,
+    rule_id: String,
+) -> Result<ExportRunStatus, String> {
+    state.scheduler.run(&app, &rule_id).await
+}
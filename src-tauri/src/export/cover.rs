@@ -0,0 +1,349 @@
+//! Story covers: the title set over a supplied picture or a generated
+//! gradient, rendered at ebook cover size and kept as an attachment.
+//! EPUB, PDF and site exports pick up a story's cover automatically.
+
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{imageops, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Cursor;
+use tauri::AppHandle;
+
+use super::pdf::JpegImage;
+use crate::attachments::{self, Attachment};
+use crate::store;
+use crate::story::StoryExport;
+
+/// Story ID to cover attachment ID, in the app data directory
+pub const STORY_COVERS_FILE: &str = "story_covers.json";
+
+/// 2:3, the shape most ebook stores expect
+pub const COVER_WIDTH: u32 = 1600;
+pub const COVER_HEIGHT: u32 = 2400;
+
+/// Covers shown when picking stories to pull from a peer
+const THUMBNAIL_WIDTH: u32 = 96;
+const THUMBNAIL_HEIGHT: u32 = 144;
+
+const MARGIN: f32 = 140.0;
+const MAX_TITLE_LINES: usize = 4;
+
+static TITLE_FONT: &[u8] = include_bytes!("../../fonts/DejaVuSerif-Bold.ttf");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CoverStyle {
+    /// Deep blue with gold lettering, title high on the page
+    #[default]
+    Classic,
+    /// Bold color, title low on the page
+    Modern,
+    /// Black and white
+    Noir,
+    /// Warm paper tones with dark lettering
+    Parchment,
+}
+
+struct Palette {
+    top: [u8; 3],
+    bottom: [u8; 3],
+    title: [u8; 3],
+    subtitle: [u8; 3],
+    /// Vertical center of the title, as a share of the height
+    title_at: f32,
+}
+
+impl CoverStyle {
+    fn palette(self) -> Palette {
+        match self {
+            CoverStyle::Classic => Palette {
+                top: [18, 32, 64],
+                bottom: [6, 10, 24],
+                title: [232, 196, 110],
+                subtitle: [200, 190, 170],
+                title_at: 0.3,
+            },
+            CoverStyle::Modern => Palette {
+                top: [225, 70, 60],
+                bottom: [120, 24, 70],
+                title: [255, 255, 255],
+                subtitle: [255, 220, 210],
+                title_at: 0.7,
+            },
+            CoverStyle::Noir => Palette {
+                top: [44, 44, 44],
+                bottom: [4, 4, 4],
+                title: [245, 245, 245],
+                subtitle: [170, 170, 170],
+                title_at: 0.5,
+            },
+            CoverStyle::Parchment => Palette {
+                top: [242, 230, 200],
+                bottom: [210, 188, 150],
+                title: [60, 40, 24],
+                subtitle: [110, 84, 60],
+                title_at: 0.35,
+            },
+        }
+    }
+}
+
+/// Picture behind the title; a gradient is generated when there is none
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum CoverBackground {
+    /// An image already in the attachment store
+    #[serde(rename_all = "camelCase")]
+    Attachment { attachment_id: String },
+    /// One of the story's generated images
+    #[serde(rename_all = "camelCase")]
+    StoryImage { image_id: String },
+}
+
+fn lerp(a: [u8; 3], b: [u8; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| a[i] as f32 + (b[i] as f32 - a[i] as f32) * t)
+}
+
+/// Gradient with faint diagonal bands placed by the story ID, so covers in
+/// the same style still differ
+fn generated_background(palette: &Palette, seed: &str) -> RgbaImage {
+    let hash = Sha256::digest(seed.as_bytes());
+    let angle = hash[0] as f32 / 255.0 * std::f32::consts::PI;
+    let (sin, cos) = angle.sin_cos();
+    let period = 180.0 + hash[1] as f32 * 2.0;
+
+    RgbaImage::from_fn(COVER_WIDTH, COVER_HEIGHT, |x, y| {
+        let (fx, fy) = (x as f32, y as f32);
+        let base = lerp(palette.top, palette.bottom, fy / COVER_HEIGHT as f32);
+        let band = ((fx * cos + fy * sin) / period * std::f32::consts::TAU).sin() * 6.0;
+        // Darken toward the corners
+        let dx = fx / COVER_WIDTH as f32 - 0.5;
+        let dy = fy / COVER_HEIGHT as f32 - 0.5;
+        let vignette = 1.0 - (dx * dx + dy * dy) * 0.6;
+        let [r, g, b] = base.map(|c| ((c + band) * vignette).clamp(0.0, 255.0) as u8);
+        Rgba([r, g, b, 255])
+    })
+}
+
+/// Scale a picture to fill the cover and shade it so the title stays readable
+fn picture_background(picture: &DynamicImage, palette: &Palette) -> RgbaImage {
+    let mut image = picture
+        .resize_to_fill(COVER_WIDTH, COVER_HEIGHT, imageops::FilterType::Lanczos3)
+        .to_rgba8();
+    let shade = palette.bottom;
+    for (_, y, pixel) in image.enumerate_pixels_mut() {
+        let distance = (y as f32 / COVER_HEIGHT as f32 - palette.title_at).abs();
+        let alpha = (0.65 - distance).clamp(0.15, 0.6);
+        for i in 0..3 {
+            pixel[i] = (pixel[i] as f32 * (1.0 - alpha) + shade[i] as f32 * alpha) as u8;
+        }
+    }
+    image
+}
+
+fn line_width<F: Font>(font: &impl ScaleFont<F>, text: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+fn wrap<F: Font>(font: &impl ScaleFont<F>, text: &str, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if !line.is_empty() && line_width(font, &candidate) > max_width {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        } else {
+            line = candidate;
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Draw centered lines with their block centered on `center_y`
+fn draw_lines(
+    image: &mut RgbaImage,
+    font: &FontRef<'static>,
+    lines: &[String],
+    size: f32,
+    center_y: f32,
+    color: [u8; 3],
+) {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let line_height = scaled.height() * 1.1;
+    let mut baseline = center_y - line_height * lines.len() as f32 / 2.0 + scaled.ascent();
+
+    for line in lines {
+        let mut x = (COVER_WIDTH as f32 - line_width(&scaled, line)) / 2.0;
+        let mut previous = None;
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                x += scaled.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(size, ab_glyph::point(x, baseline));
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i32 + gx as i32;
+                    let py = bounds.min.y as i32 + gy as i32;
+                    if px < 0 || py < 0 || px >= COVER_WIDTH as i32 || py >= COVER_HEIGHT as i32 {
+                        return;
+                    }
+                    let pixel = image.get_pixel_mut(px as u32, py as u32);
+                    for i in 0..3 {
+                        pixel[i] =
+                            (pixel[i] as f32 * (1.0 - coverage) + color[i] as f32 * coverage) as u8;
+                    }
+                });
+            }
+            x += scaled.h_advance(id);
+            previous = Some(id);
+        }
+        baseline += line_height;
+    }
+}
+
+/// Render a cover for a story as PNG bytes
+pub fn render(
+    export: &StoryExport,
+    style: CoverStyle,
+    picture: Option<&DynamicImage>,
+) -> Result<Vec<u8>, String> {
+    let font = FontRef::try_from_slice(TITLE_FONT).map_err(|e| format!("Invalid font: {}", e))?;
+    let palette = style.palette();
+    let mut image = match picture {
+        Some(picture) => picture_background(picture, &palette),
+        None => generated_background(&palette, &export.story.id),
+    };
+
+    // Largest size at which the title fits in a few lines
+    let max_width = COVER_WIDTH as f32 - 2.0 * MARGIN;
+    let title = export.story.title.trim();
+    let title = if title.is_empty() { "Untitled" } else { title };
+    let mut size = 200.0;
+    let mut lines = wrap(&font.as_scaled(PxScale::from(size)), title, max_width);
+    while size > 60.0
+        && (lines.len() > MAX_TITLE_LINES
+            || lines
+                .iter()
+                .any(|l| line_width(&font.as_scaled(PxScale::from(size)), l) > max_width))
+    {
+        size -= 10.0;
+        lines = wrap(&font.as_scaled(PxScale::from(size)), title, max_width);
+    }
+    let center = COVER_HEIGHT as f32 * palette.title_at;
+    draw_lines(&mut image, &font, &lines, size, center, palette.title);
+
+    if let Some(genre) = export.story.genre.as_deref().filter(|g| !g.is_empty()) {
+        let block = size * 1.1 * lines.len() as f32;
+        draw_lines(
+            &mut image,
+            &font,
+            &[genre.to_uppercase()],
+            56.0,
+            center + block / 2.0 + 90.0,
+            palette.subtitle,
+        );
+    }
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode cover: {}", e))?;
+    Ok(png)
+}
+
+/// Decode the picture a cover should use
+pub fn load_background(
+    app: &AppHandle,
+    export: &StoryExport,
+    background: &CoverBackground,
+) -> Result<DynamicImage, String> {
+    let bytes = match background {
+        CoverBackground::Attachment { attachment_id } => attachments::load(app, attachment_id)?.1,
+        CoverBackground::StoryImage { image_id } => {
+            let image = export
+                .embedded_images
+                .iter()
+                .find(|i| &i.id == image_id)
+                .ok_or_else(|| format!("Image not found: {}", image_id))?;
+            STANDARD
+                .decode(&image.image_data)
+                .map_err(|e| format!("Invalid image data for {}: {}", image_id, e))?
+        }
+    };
+    image::load_from_memory(&bytes).map_err(|e| format!("Unsupported background image: {}", e))
+}
+
+/// Re-encode a cover for formats that take JPEG, such as PDF
+pub fn to_jpeg(png: &[u8]) -> Result<JpegImage, String> {
+    let image = image::load_from_memory(png)
+        .map_err(|e| format!("Invalid cover image: {}", e))?
+        .to_rgb8();
+    let mut data = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+        .map_err(|e| format!("Failed to encode cover: {}", e))?;
+    Ok(JpegImage {
+        data,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+/// A small JPEG of a cover, base64 encoded
+pub fn thumbnail(cover: &[u8]) -> Result<String, String> {
+    let image = image::load_from_memory(cover)
+        .map_err(|e| format!("Invalid cover image: {}", e))?
+        .resize_to_fill(
+            THUMBNAIL_WIDTH,
+            THUMBNAIL_HEIGHT,
+            imageops::FilterType::Triangle,
+        )
+        .to_rgb8();
+    let mut data = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    Ok(STANDARD.encode(data))
+}
+
+/// Store a rendered cover and make it the story's cover
+pub fn save_cover(app: &AppHandle, story_id: &str, png: &[u8]) -> Result<Attachment, String> {
+    let attachment = attachments::save(app, png, "image/png", Some((COVER_WIDTH, COVER_HEIGHT)))?;
+    let mut covers: HashMap<String, String> = store::load_json(app, STORY_COVERS_FILE)?;
+    covers.insert(story_id.to_string(), attachment.id.clone());
+    store::save_json(app, STORY_COVERS_FILE, &covers)?;
+    Ok(attachment)
+}
+
+/// PNG bytes of a story's cover, if it has one
+pub fn cover_for(app: &AppHandle, story_id: &str) -> Option<Vec<u8>> {
+    let covers: HashMap<String, String> = store::load_json(app, STORY_COVERS_FILE).ok()?;
+    let id = covers.get(story_id)?;
+    attachments::load(app, id).ok().map(|(_, bytes)| bytes)
+}
+
+/// Thumbnail of a story's cover, if it has one that can be read
+pub fn thumbnail_for(app: &AppHandle, story_id: &str) -> Option<String> {
+    thumbnail(&cover_for(app, story_id)?).ok()
+}
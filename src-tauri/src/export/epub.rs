@@ -0,0 +1,179 @@
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::site::{escape_html, paginate, render_content};
+use crate::story::StoryExport;
+
+/// Format milliseconds since the epoch as an RFC 3339 UTC timestamp
+pub fn rfc3339(ms: i64) -> String {
+    let secs = ms.div_euclid(1000);
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{}</title></head>
+<body>
+{}
+</body>
+</html>
+"#,
+        escape_html(title),
+        body
+    )
+}
+
+/// Build an EPUB 3 book from a story's main branch, one section per chapter,
+/// opening on the PNG cover if one is given
+pub fn build_epub(export: &StoryExport, cover: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let mut entries: Vec<_> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none())
+        .collect();
+    entries.sort_by_key(|e| e.position);
+    let pages = paginate(export, entries, "chapter-");
+
+    let title = escape_html(&export.story.title);
+    let book_id = format!("urn:uuid:{}", export.story.id);
+    let modified = rfc3339(export.story.updated_at);
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut nav = String::new();
+    let mut sections = Vec::new();
+    let mut cover_meta = String::new();
+    if cover.is_some() {
+        manifest.push_str(concat!(
+            "    <item id=\"cover-image\" href=\"cover.png\" media-type=\"image/png\" properties=\"cover-image\"/>\n",
+            "    <item id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+        ));
+        spine.push_str("    <itemref idref=\"cover\"/>\n");
+        // EPUB 2 readers look for the cover here
+        cover_meta.push_str("\n    <meta name=\"cover\" content=\"cover-image\"/>");
+        sections.push((
+            "cover.xhtml".to_string(),
+            xhtml(
+                &export.story.title,
+                &format!(
+                    "<div style=\"text-align: center\"><img src=\"cover.png\" alt=\"{}\" style=\"max-width: 100%; max-height: 100%\"/></div>",
+                    title
+                ),
+            ),
+        ));
+    }
+    for (index, page) in pages.iter().enumerate() {
+        let id = format!("s{}", index + 1);
+        manifest.push_str(&format!(
+            "    <item id=\"{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            id, page.file
+        ));
+        spine.push_str(&format!("    <itemref idref=\"{}\"/>\n", id));
+        nav.push_str(&format!(
+            "      <li><a href=\"{}\">{}</a></li>\n",
+            page.file,
+            escape_html(&page.title)
+        ));
+
+        let mut body = format!("<h2>{}</h2>\n", escape_html(&page.title));
+        for entry in &page.entries {
+            if entry.entry_type == "user_action" {
+                body.push_str(&format!(
+                    "<blockquote>{}</blockquote>\n",
+                    render_content(&entry.content)
+                ));
+            } else {
+                body.push_str(&render_content(&entry.content));
+            }
+        }
+        sections.push((page.file.clone(), xhtml(&page.title, &body)));
+    }
+
+    let opf = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">{book_id}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>en</dc:language>
+    <dc:creator>Aventura</dc:creator>
+    <meta property="dcterms:modified">{modified}</meta>{cover_meta}
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#
+    );
+    let nav_doc = xhtml(
+        &export.story.title,
+        &format!(
+            "<nav epub:type=\"toc\">\n  <h1>{}</h1>\n  <ol>\n{}  </ol>\n</nav>",
+            title, nav
+        ),
+    );
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, contents: &[u8], options: SimpleFileOptions| {
+        zip.start_file(name, options)
+            .and_then(|_| zip.write_all(contents).map_err(Into::into))
+            .map_err(|e| format!("Failed to write {}: {}", name, e))
+    };
+
+    // The mimetype must be the first entry and uncompressed
+    add("mimetype", b"application/epub+zip", stored)?;
+    add(
+        "META-INF/container.xml",
+        br#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#,
+        deflated,
+    )?;
+    add("OEBPS/content.opf", opf.as_bytes(), deflated)?;
+    add("OEBPS/nav.xhtml", nav_doc.as_bytes(), deflated)?;
+    for (file, contents) in &sections {
+        add(&format!("OEBPS/{}", file), contents.as_bytes(), deflated)?;
+    }
+    if let Some(cover) = cover {
+        // PNG is already compressed
+        add("OEBPS/cover.png", cover, stored)?;
+    }
+
+    zip.finish()
+        .map(Cursor::into_inner)
+        .map_err(|e| format!("Failed to finish EPUB: {}", e))
+}
@@ -0,0 +1,16 @@
+pub mod audiobook;
+pub mod card;
+pub mod changes;
+pub mod commands;
+pub mod cover;
+pub mod epub;
+pub mod mp3;
+pub mod obsidian;
+pub mod pdf;
+pub mod reader;
+pub mod schedule;
+pub mod site;
+pub mod summary;
+pub mod twine;
+
+pub use commands::ExportState;
@@ -0,0 +1,157 @@
+//! Minimal MP3 handling for audiobook export: stripping tags from synthesized
+//! segments, measuring their duration, and writing ID3v2 chapter frames.
+
+/// Length of an ID3v2 tag at the start of the data, if present
+fn id3v2_len(data: &[u8]) -> usize {
+    if data.len() < 10 || &data[0..3] != b"ID3" {
+        return 0;
+    }
+    let size = data[6..10]
+        .iter()
+        .fold(0usize, |acc, b| (acc << 7) | (*b as usize & 0x7f));
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    (10 + size + footer).min(data.len())
+}
+
+/// Remove ID3v2/ID3v1 tags so segments can be concatenated into one stream
+pub fn strip_tags(data: &[u8]) -> &[u8] {
+    let data = &data[id3v2_len(data)..];
+    if data.len() >= 128 && &data[data.len() - 128..data.len() - 125] == b"TAG" {
+        &data[..data.len() - 128]
+    } else {
+        data
+    }
+}
+
+/// Parse a frame header, returning (frame length in bytes, duration in microseconds)
+fn parse_frame_header(header: &[u8]) -> Option<(usize, u64)> {
+    if header.len() < 4 || header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+
+    // 0 = MPEG 2.5, 2 = MPEG 2, 3 = MPEG 1
+    let version = (header[1] >> 3) & 0x03;
+    let layer = (header[1] >> 1) & 0x03;
+    if version == 1 || layer != 1 {
+        // Reserved version, or not Layer III
+        return None;
+    }
+
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = ((header[2] >> 2) & 0x03) as usize;
+    let padding = ((header[2] >> 1) & 0x01) as usize;
+    if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+        return None;
+    }
+
+    const MPEG1_BITRATES: [usize; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const MPEG2_BITRATES: [usize; 15] =
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    const SAMPLE_RATES: [usize; 3] = [44100, 48000, 32000];
+
+    let (bitrate, sample_rate, samples) = match version {
+        3 => (
+            MPEG1_BITRATES[bitrate_index],
+            SAMPLE_RATES[sample_rate_index],
+            1152,
+        ),
+        2 => (
+            MPEG2_BITRATES[bitrate_index],
+            SAMPLE_RATES[sample_rate_index] / 2,
+            576,
+        ),
+        _ => (
+            MPEG2_BITRATES[bitrate_index],
+            SAMPLE_RATES[sample_rate_index] / 4,
+            576,
+        ),
+    };
+
+    let length = samples / 8 * bitrate * 1000 / sample_rate + padding;
+    let duration = samples as u64 * 1_000_000 / sample_rate as u64;
+    Some((length, duration))
+}
+
+/// Total playing time of an untagged MP3 stream in milliseconds
+pub fn duration_ms(data: &[u8]) -> u64 {
+    let mut offset = 0;
+    let mut micros = 0u64;
+    while offset + 4 <= data.len() {
+        match parse_frame_header(&data[offset..]) {
+            Some((length, duration)) if length > 0 => {
+                micros += duration;
+                offset += length;
+            }
+            _ => offset += 1,
+        }
+    }
+    micros / 1000
+}
+
+/// A chapter marker within the audiobook
+pub struct ChapterMark {
+    pub title: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+fn frame(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 10);
+    out.extend_from_slice(id);
+    out.extend_from_slice(&syncsafe(body.len()));
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(body);
+    out
+}
+
+fn text_frame(id: &[u8; 4], text: &str) -> Vec<u8> {
+    // Encoding 3 = UTF-8 (ID3v2.4)
+    let mut body = vec![3];
+    body.extend_from_slice(text.as_bytes());
+    frame(id, &body)
+}
+
+fn syncsafe(size: usize) -> [u8; 4] {
+    [
+        ((size >> 21) & 0x7f) as u8,
+        ((size >> 14) & 0x7f) as u8,
+        ((size >> 7) & 0x7f) as u8,
+        (size & 0x7f) as u8,
+    ]
+}
+
+/// Build an ID3v2.4 tag with the title, artist and a table of contents
+pub fn build_tag(title: &str, artist: &str, chapters: &[ChapterMark]) -> Vec<u8> {
+    let mut frames = Vec::new();
+    frames.extend(text_frame(b"TIT2", title));
+    frames.extend(text_frame(b"TPE1", artist));
+
+    if !chapters.is_empty() {
+        // Top-level, ordered table of contents
+        let mut toc = b"toc\0".to_vec();
+        toc.push(0x03);
+        toc.push(chapters.len().min(255) as u8);
+        for index in 0..chapters.len().min(255) {
+            toc.extend_from_slice(format!("ch{}\0", index).as_bytes());
+        }
+        frames.extend(frame(b"CTOC", &toc));
+
+        for (index, chapter) in chapters.iter().enumerate() {
+            let mut body = format!("ch{}\0", index).into_bytes();
+            body.extend_from_slice(&(chapter.start_ms as u32).to_be_bytes());
+            body.extend_from_slice(&(chapter.end_ms as u32).to_be_bytes());
+            // Byte offsets unused
+            body.extend_from_slice(&[0xff; 8]);
+            body.extend(text_frame(b"TIT2", &chapter.title));
+            frames.extend(frame(b"CHAP", &body));
+        }
+    }
+
+    let mut tag = b"ID3".to_vec();
+    tag.extend_from_slice(&[4, 0, 0]);
+    tag.extend_from_slice(&syncsafe(frames.len()));
+    tag.extend(frames);
+    tag
+}
@@ -0,0 +1,441 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::story::types::StoryEntry;
+use crate::story::StoryExport;
+
+/// Manifest in the export folder recording what each story looked like when
+/// written and which files the export created for it
+const MANIFEST_FILE: &str = ".aventura-export.json";
+
+/// Characters of the story ID added to folder names, so stories sharing a
+/// title get folders of their own
+const SHORT_ID_CHARS: usize = 8;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObsidianOptions {
+    /// Skip stories whose content has not changed since the last export
+    #[serde(default = "default_true")]
+    pub incremental: bool,
+    /// Write a note per character, location and lorebook entry
+    #[serde(default = "default_true")]
+    pub entity_notes: bool,
+    /// Turn the first mention of each entity in an entry into a wiki-link
+    #[serde(default = "default_true")]
+    pub link_entities: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ObsidianOptions {
+    fn default() -> Self {
+        Self {
+            incremental: true,
+            entity_notes: true,
+            link_entities: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObsidianExportResult {
+    pub written_stories: usize,
+    pub skipped_stories: usize,
+    pub note_count: usize,
+}
+
+/// What the last export wrote for one story
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestEntry {
+    hash: String,
+    /// Files written, relative to the export folder. Only these are ever
+    /// deleted, so notes the user added beside them are left alone.
+    #[serde(default)]
+    files: Vec<String>,
+}
+
+/// Keyed by story ID
+type Manifest = HashMap<String, ManifestEntry>;
+
+/// A note linked from story text
+struct EntityNote {
+    note: String,
+    kind: &'static str,
+    names: Vec<String>,
+    body: String,
+}
+
+/// Make a title safe to use as a note file name and wiki-link target
+fn note_name(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c {
+            '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    // Leading dots hide a file, and "." or ".." name a directory
+    let cleaned = cleaned.trim_start_matches('.').trim().to_string();
+    if cleaned.is_empty() {
+        "Untitled".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// Folder and main note name of a story: its title and the start of its ID
+fn story_name(export: &StoryExport) -> String {
+    let short_id: String = export
+        .story
+        .id
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .take(SHORT_ID_CHARS)
+        .collect();
+    note_name(&format!("{} {}", export.story.title, short_id))
+}
+
+/// Quote a value for YAML front matter
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+fn front_matter(fields: &[(&str, String)]) -> String {
+    let mut out = String::from("---\n");
+    for (key, value) in fields {
+        out.push_str(&format!("{}: {}\n", key, value));
+    }
+    out.push_str("---\n\n");
+    out
+}
+
+fn entity_notes(export: &StoryExport) -> Vec<EntityNote> {
+    let mut notes = Vec::new();
+    for character in &export.characters {
+        let mut body = character.description.clone().unwrap_or_default();
+        if let Some(ref relationship) = character.relationship {
+            body.push_str(&format!("\n\n**Relationship:** {}", relationship));
+        }
+        if !character.traits.is_empty() {
+            body.push_str(&format!("\n\n**Traits:** {}", character.traits.join(", ")));
+        }
+        notes.push(EntityNote {
+            note: note_name(&character.name),
+            kind: "character",
+            names: vec![character.name.clone()],
+            body,
+        });
+    }
+    for location in &export.locations {
+        notes.push(EntityNote {
+            note: note_name(&location.name),
+            kind: "location",
+            names: vec![location.name.clone()],
+            body: location.description.clone().unwrap_or_default(),
+        });
+    }
+    for entry in &export.lorebook_entries {
+        // Characters and locations already have notes of their own
+        if notes.iter().any(|n| n.names[0] == entry.name) {
+            continue;
+        }
+        let mut names = vec![entry.name.clone()];
+        names.extend(entry.aliases.iter().cloned());
+        notes.push(EntityNote {
+            note: note_name(&entry.name),
+            kind: "lore",
+            names,
+            body: entry.description.clone(),
+        });
+    }
+    notes
+}
+
+/// Links the first mention of each entity in a piece of text
+struct Linker {
+    pattern: Option<Regex>,
+    targets: HashMap<String, String>,
+}
+
+impl Linker {
+    fn new(notes: &[EntityNote]) -> Self {
+        let mut targets = HashMap::new();
+        for note in notes {
+            for name in &note.names {
+                if !name.trim().is_empty() {
+                    targets.insert(name.clone(), note.note.clone());
+                }
+            }
+        }
+        // Longest names first so "Old Tom" wins over "Tom"
+        let mut names: Vec<&String> = targets.keys().collect();
+        names.sort_by_key(|n| std::cmp::Reverse(n.len()));
+        let alternatives: Vec<String> = names.iter().map(|n| regex::escape(n)).collect();
+        let pattern = (!alternatives.is_empty())
+            .then(|| Regex::new(&format!(r"\b(?:{})\b", alternatives.join("|"))).ok())
+            .flatten();
+        Self { pattern, targets }
+    }
+
+    fn link(&self, text: &str) -> String {
+        let Some(ref pattern) = self.pattern else {
+            return text.to_string();
+        };
+        let mut linked = std::collections::HashSet::new();
+        pattern
+            .replace_all(text, |caps: &regex::Captures| {
+                let name = &caps[0];
+                let note = &self.targets[name];
+                if !linked.insert(note.clone()) {
+                    return name.to_string();
+                }
+                if note == name {
+                    format!("[[{}]]", note)
+                } else {
+                    format!("[[{}|{}]]", note, name)
+                }
+            })
+            .into_owned()
+    }
+}
+
+fn render_entries(
+    entries: &[&StoryEntry],
+    export: &StoryExport,
+    linker: Option<&Linker>,
+) -> String {
+    let chapter_starts: HashMap<&str, String> = export
+        .chapters
+        .iter()
+        .map(|c| {
+            let heading = match c.title {
+                Some(ref title) => format!("## Chapter {}: {}", c.number, title),
+                None => format!("## Chapter {}", c.number),
+            };
+            (c.start_entry_id.as_str(), heading)
+        })
+        .collect();
+
+    let mut out = String::new();
+    for entry in entries {
+        if let Some(heading) = chapter_starts.get(entry.id.as_str()) {
+            out.push_str(heading);
+            out.push_str("\n\n");
+        }
+        let content = match linker {
+            Some(linker) => linker.link(entry.content.trim()),
+            None => entry.content.trim().to_string(),
+        };
+        if entry.entry_type == "user_action" {
+            for line in content.lines() {
+                out.push_str("> ");
+                out.push_str(line);
+                out.push('\n');
+            }
+        } else {
+            out.push_str(&content);
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes notes under the export folder, recording each one
+struct NoteWriter<'a> {
+    dir: &'a Path,
+    files: Vec<String>,
+}
+
+impl NoteWriter<'_> {
+    fn write(&mut self, relative: PathBuf, contents: String) -> Result<(), String> {
+        let path = self.dir.join(&relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        self.files.push(relative.to_string_lossy().into_owned());
+        Ok(())
+    }
+}
+
+/// Delete files an earlier export wrote that this one did not, and the
+/// folders they leave empty
+fn remove_stale(dir: &Path, previous: &[String], written: &[String]) {
+    for file in previous.iter().filter(|f| !written.contains(f)) {
+        let relative = Path::new(file);
+        // The manifest is in the vault, so a path leaving the folder is ignored
+        if relative.is_absolute()
+            || relative
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            continue;
+        }
+        let path = dir.join(relative);
+        if fs::remove_file(&path).is_err() {
+            continue;
+        }
+        // Removing a folder fails while it still holds anything
+        for parent in relative.ancestors().skip(1) {
+            if parent.as_os_str().is_empty() || fs::remove_dir(dir.join(parent)).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Write one story's notes, returning the files written relative to `dir`
+fn write_story(
+    dir: &Path,
+    export: &StoryExport,
+    options: &ObsidianOptions,
+) -> Result<Vec<String>, String> {
+    let title = story_name(export);
+    let story_dir = PathBuf::from(&title);
+    let mut writer = NoteWriter {
+        dir,
+        files: Vec::new(),
+    };
+
+    let notes = entity_notes(export);
+    let linker = options.link_entities.then(|| Linker::new(&notes));
+
+    if options.entity_notes {
+        for note in &notes {
+            let kind_dir = story_dir.join(match note.kind {
+                "character" => "Characters",
+                "location" => "Locations",
+                _ => "Lore",
+            });
+            let mut contents = front_matter(&[
+                ("type", note.kind.to_string()),
+                ("story", yaml_string(&format!("[[{}]]", title))),
+                (
+                    "aliases",
+                    serde_json::to_string(&note.names[1..]).unwrap_or_default(),
+                ),
+            ]);
+            contents.push_str(&format!("# {}\n\n{}\n", note.names[0], note.body.trim()));
+            writer.write(kind_dir.join(format!("{}.md", note.note)), contents)?;
+        }
+    }
+
+    let mut by_branch: HashMap<Option<&str>, Vec<&StoryEntry>> = HashMap::new();
+    for entry in &export.entries {
+        by_branch
+            .entry(entry.branch_id.as_deref())
+            .or_default()
+            .push(entry);
+    }
+    for entries in by_branch.values_mut() {
+        entries.sort_by_key(|e| e.position);
+    }
+
+    let mut branch_links = Vec::new();
+    for branch in &export.branches {
+        let Some(entries) = by_branch.get(&Some(branch.id.as_str())) else {
+            continue;
+        };
+        let name = note_name(&format!("{} - {}", title, branch.name));
+        let mut contents = front_matter(&[
+            ("type", "branch".to_string()),
+            ("story", yaml_string(&format!("[[{}]]", title))),
+            ("branch", yaml_string(&branch.name)),
+        ]);
+        contents.push_str(&format!("# {}\n\n", branch.name));
+        contents.push_str(&render_entries(entries, export, linker.as_ref()));
+        writer.write(story_dir.join(format!("{}.md", name)), contents)?;
+        branch_links.push(format!("- [[{}|{}]]", name, branch.name));
+    }
+
+    let mut fields = vec![
+        ("type", "story".to_string()),
+        ("id", yaml_string(&export.story.id)),
+        ("title", yaml_string(&export.story.title)),
+        ("created", export.story.created_at.to_string()),
+        ("updated", export.story.updated_at.to_string()),
+        ("entries", export.entries.len().to_string()),
+    ];
+    if let Some(ref genre) = export.story.genre {
+        fields.push(("genre", yaml_string(genre)));
+    }
+    let mut contents = front_matter(&fields);
+    contents.push_str(&format!("# {}\n\n", export.story.title));
+    if let Some(ref description) = export.story.description {
+        contents.push_str(&format!("{}\n\n", description.trim()));
+    }
+    if !branch_links.is_empty() {
+        contents.push_str("**Branches**\n\n");
+        contents.push_str(&branch_links.join("\n"));
+        contents.push_str("\n\n");
+    }
+    let main: &[&StoryEntry] = by_branch.get(&None).map(Vec::as_slice).unwrap_or(&[]);
+    contents.push_str(&render_entries(main, export, linker.as_ref()));
+    writer.write(story_dir.join(format!("{}.md", title)), contents)?;
+    Ok(writer.files)
+}
+
+/// Export stories into an Obsidian vault as linked Markdown notes
+pub fn export_vault(
+    vault_path: &str,
+    folder: &str,
+    stories_json: &[String],
+    options: &ObsidianOptions,
+) -> Result<ObsidianExportResult, String> {
+    let vault = Path::new(vault_path);
+    if !vault.is_dir() {
+        return Err(format!("Vault not found: {}", vault_path));
+    }
+    let dir = vault.join(folder.trim_matches('/'));
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let manifest_path = dir.join(MANIFEST_FILE);
+    let mut manifest: Manifest = fs::read_to_string(&manifest_path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+
+    let mut result = ObsidianExportResult::default();
+    for json in stories_json {
+        let export = StoryExport::from_json(json)?;
+        let hash: String = Sha256::digest(json.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let previous = manifest.remove(&export.story.id).unwrap_or_default();
+        let unchanged =
+            previous.hash == hash && previous.files.iter().all(|f| dir.join(f).exists());
+        if options.incremental && unchanged {
+            result.skipped_stories += 1;
+            manifest.insert(export.story.id.clone(), previous);
+            continue;
+        }
+
+        let files = write_story(&dir, &export, options)?;
+        // Notes for entities or branches that no longer exist, and the old
+        // folder of a renamed story
+        remove_stale(&dir, &previous.files, &files);
+        result.note_count += files.len();
+        result.written_stories += 1;
+        manifest.insert(export.story.id.clone(), ManifestEntry { hash, files });
+    }
+
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize export manifest: {}", e))?;
+    fs::write(&manifest_path, manifest_json)
+        .map_err(|e| format!("Failed to write export manifest: {}", e))?;
+    Ok(result)
+}
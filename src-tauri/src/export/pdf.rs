@@ -0,0 +1,222 @@
+//! Minimal single-page PDF writer for text documents, using the standard
+//! Helvetica fonts so nothing needs to be embedded. A JPEG cover can go on
+//! a page of its own in front of the text.
+
+/// A4 page size in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+
+/// One block of text on the page
+pub struct TextBlock {
+    pub text: String,
+    pub size: f32,
+    pub bold: bool,
+    /// Extra space above the block, in points
+    pub space_before: f32,
+}
+
+impl TextBlock {
+    pub fn heading(text: impl Into<String>, size: f32) -> Self {
+        TextBlock {
+            text: text.into(),
+            size,
+            bold: true,
+            space_before: size * 0.6,
+        }
+    }
+
+    pub fn body(text: impl Into<String>) -> Self {
+        TextBlock {
+            text: text.into(),
+            size: 10.5,
+            bold: false,
+            space_before: 2.0,
+        }
+    }
+}
+
+/// A baseline JPEG, embedded as is with the DCT filter
+pub struct JpegImage {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Encode text as a PDF string literal in WinAnsi; unmappable characters become '?'
+fn pdf_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('(');
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\u{2018}' | '\u{2019}' => out.push('\''),
+            '\u{201c}' | '\u{201d}' => out.push('"'),
+            '\u{2013}' | '\u{2014}' => out.push('-'),
+            '\u{2026}' => out.push_str("..."),
+            ' '..='~' => out.push(c),
+            '\u{a0}'..='\u{ff}' => out.push_str(&format!("\\{:03o}", c as u32)),
+            _ => out.push('?'),
+        }
+    }
+    out.push(')');
+    out
+}
+
+/// Greedy word wrap using Helvetica's average glyph width
+fn wrap(text: &str, size: f32, bold: bool) -> Vec<String> {
+    let glyph = size * if bold { 0.56 } else { 0.5 };
+    let max_chars = ((PAGE_WIDTH - 2.0 * MARGIN) / glyph).max(10.0) as usize;
+
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Page that shows an image as large as fits, centered
+fn cover_page(image: &JpegImage) -> (String, Vec<u8>) {
+    let scale = (PAGE_WIDTH / image.width as f32).min(PAGE_HEIGHT / image.height as f32);
+    let (width, height) = (image.width as f32 * scale, image.height as f32 * scale);
+    let content = format!(
+        "q {:.1} 0 0 {:.1} {:.1} {:.1} cm /Im1 Do Q\n",
+        width,
+        height,
+        (PAGE_WIDTH - width) / 2.0,
+        (PAGE_HEIGHT - height) / 2.0
+    );
+    let mut xobject = format!(
+        "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+         /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\nstream\n",
+        image.width,
+        image.height,
+        image.data.len()
+    )
+    .into_bytes();
+    xobject.extend_from_slice(&image.data);
+    xobject.extend_from_slice(b"\nendstream");
+    (content, xobject)
+}
+
+/// Lay out blocks top to bottom on a single page, after the cover if there is
+/// one. Text that does not fit is cut off with an ellipsis. Returns the PDF
+/// and whether anything was cut.
+pub fn single_page(
+    title: &str,
+    blocks: &[TextBlock],
+    cover: Option<&JpegImage>,
+) -> (Vec<u8>, bool) {
+    let mut content = String::from("BT\n");
+    let mut y = PAGE_HEIGHT - MARGIN;
+    let mut truncated = false;
+
+    'blocks: for block in blocks {
+        y -= block.space_before;
+        let leading = block.size * 1.3;
+        let font = if block.bold { "F2" } else { "F1" };
+        for line in wrap(&block.text, block.size, block.bold) {
+            if y - leading < MARGIN {
+                content.push_str(&format!(
+                    "/F1 10.5 Tf 1 0 0 1 {} {:.1} Tm (...) Tj\n",
+                    MARGIN,
+                    y - leading
+                ));
+                truncated = true;
+                break 'blocks;
+            }
+            y -= leading;
+            content.push_str(&format!(
+                "/{} {} Tf 1 0 0 1 {} {:.1} Tm {} Tj\n",
+                font,
+                block.size,
+                MARGIN,
+                y,
+                pdf_string(&line)
+            ));
+        }
+    }
+    content.push_str("ET\n");
+
+    let kids = if cover.is_some() {
+        "[8 0 R 3 0 R] /Count 2"
+    } else {
+        "[3 0 R] /Count 1"
+    };
+    let mut objects: Vec<Vec<u8>> = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!("<< /Type /Pages /Kids {} >>", kids),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 4 0 R /F2 5 0 R >> >> /Contents 6 0 R >>",
+            PAGE_WIDTH, PAGE_HEIGHT
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ),
+        format!("<< /Title {} /Producer (Aventura) >>", pdf_string(title)),
+    ]
+    .map(String::into_bytes)
+    .into();
+    if let Some(image) = cover {
+        let (content, xobject) = cover_page(image);
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /XObject << /Im1 10 0 R >> >> /Contents 9 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT
+            )
+            .into_bytes(),
+        );
+        objects.push(
+            format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                content.len(),
+                content
+            )
+            .into_bytes(),
+        );
+        objects.push(xobject);
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        trailer.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    trailer.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R /Info 7 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    ));
+    pdf.extend_from_slice(trailer.as_bytes());
+    (pdf, truncated)
+}
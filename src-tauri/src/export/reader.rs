@@ -0,0 +1,111 @@
+//! The web reader built into the app: the stylesheet and script of shared
+//! story pages and exported sites. Both are embedded in the binary, so
+//! serving or exporting a story needs no network access or frontend build.
+
+use include_dir::{include_dir, Dir};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+
+static READER: Dir = include_dir!("$CARGO_MANIFEST_DIR/reader");
+
+/// Brotli quality used for served assets; they are compressed only once
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_WINDOW: u32 = 22;
+
+pub struct ReaderAsset {
+    pub content_type: &'static str,
+    pub body: &'static [u8],
+    /// Brotli-compressed body, when that is smaller
+    pub brotli: Option<Vec<u8>>,
+    pub etag: String,
+}
+
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit('.').next() {
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("html") => "text/html; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+fn compress(body: &[u8]) -> Option<Vec<u8>> {
+    let mut compressed = Vec::new();
+    {
+        let mut writer =
+            brotli::CompressorWriter::new(&mut compressed, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+        writer.write_all(body).ok()?;
+    }
+    (compressed.len() < body.len()).then_some(compressed)
+}
+
+fn hex(digest: &[u8], chars: usize) -> String {
+    digest
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()
+        .chars()
+        .take(chars)
+        .collect()
+}
+
+fn assets() -> &'static HashMap<String, ReaderAsset> {
+    static ASSETS: OnceLock<HashMap<String, ReaderAsset>> = OnceLock::new();
+    ASSETS.get_or_init(|| {
+        READER
+            .files()
+            .filter_map(|file| {
+                let name = file.path().to_str()?.to_string();
+                let body = file.contents();
+                let asset = ReaderAsset {
+                    content_type: content_type(&name),
+                    body,
+                    brotli: compress(body),
+                    etag: format!("\"{}\"", hex(&Sha256::digest(body), 16)),
+                };
+                Some((name, asset))
+            })
+            .collect()
+    })
+}
+
+/// Changes whenever any asset does, so asset URLs can be cached forever
+pub fn version() -> &'static str {
+    static VERSION: OnceLock<String> = OnceLock::new();
+    VERSION.get_or_init(|| {
+        let mut names: Vec<&String> = assets().keys().collect();
+        names.sort();
+        let mut hasher = Sha256::new();
+        for name in names {
+            hasher.update(name.as_bytes());
+            hasher.update(assets()[name].body);
+        }
+        hex(&hasher.finalize(), 12)
+    })
+}
+
+pub fn asset(name: &str) -> Option<&'static ReaderAsset> {
+    assets().get(name)
+}
+
+/// Tags loading the reader, with asset URLs starting with `base`
+pub fn head(base: &str) -> String {
+    format!(
+        "<link rel=\"stylesheet\" href=\"{base}reader.css\">\n<script src=\"{base}reader.js\" defer></script>\n"
+    )
+}
+
+/// Copy the reader into an exported site
+pub fn write_assets(dir: &Path) -> Result<(), String> {
+    for (name, asset) in assets() {
+        fs::write(dir.join(name), asset.body)
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    }
+    Ok(())
+}
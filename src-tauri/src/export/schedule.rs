@@ -0,0 +1,378 @@
+//! Scheduled exports. Rules live in the app data directory and a worker
+//! runs the due ones, reading their stories from the current profile's
+//! database. A run that fails is tried again after `RETRY_DELAY_MS`.
+
+use chrono::{Local, Months, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use super::epub::{build_epub, rfc3339};
+use super::{cover, site, twine, ExportState};
+use crate::profiles;
+use crate::store;
+use crate::story::{rows, StoryExport};
+use crate::sync::keys::now_ms;
+
+/// Export rules in the app data directory
+pub const EXPORT_RULES_FILE: &str = "export_rules.json";
+
+/// How often the worker looks for due rules
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// Wait before trying a failed run again
+const RETRY_DELAY_MS: i64 = 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportInterval {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl ExportInterval {
+    /// When a rule that ran at `from` runs next. Months are calendar months
+    /// in local time, clamped to the end of shorter months.
+    fn next_after(self, from: i64) -> i64 {
+        match self {
+            ExportInterval::Daily => from + DAY_MS,
+            ExportInterval::Weekly => from + 7 * DAY_MS,
+            ExportInterval::Monthly => Local
+                .timestamp_millis_opt(from)
+                .single()
+                .and_then(|time| time.checked_add_months(Months::new(1)))
+                .map_or(from + 30 * DAY_MS, |time| time.timestamp_millis()),
+        }
+    }
+}
+
+/// Which stories a rule exports
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ExportTarget {
+    #[serde(rename_all = "camelCase")]
+    Story {
+        story_id: String,
+    },
+    Library,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScheduledFormat {
+    Epub,
+    Site,
+    Twine,
+    /// Aventura export JSON, one file per story
+    Aventura,
+    /// A single zip of Aventura exports
+    Archive,
+}
+
+/// Result of the last run of a rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRunStatus {
+    pub ran_at: i64,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Files or directories written
+    #[serde(default)]
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRule {
+    pub id: String,
+    pub name: String,
+    pub target: ExportTarget,
+    pub format: ScheduledFormat,
+    pub folder: String,
+    pub interval: ExportInterval,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub next_run_at: i64,
+    #[serde(default)]
+    pub last_run: Option<ExportRunStatus>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// File-name-safe version of a title
+pub fn slug(title: &str) -> String {
+    let slug: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        "story".to_string()
+    } else {
+        slug
+    }
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<String, String> {
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// The stories a rule targets, as export JSON
+async fn target_stories(app: &AppHandle, target: &ExportTarget) -> Result<Vec<String>, String> {
+    let ids = match target {
+        ExportTarget::Story { story_id } => {
+            profiles::check_story(app, story_id).await?;
+            vec![story_id.clone()]
+        }
+        ExportTarget::Library => {
+            profiles::visible_stories(app, rows::story_ids(app).await?).await?
+        }
+    };
+    let mut stories = Vec::with_capacity(ids.len());
+    for id in ids {
+        match rows::load(app, &id).await? {
+            Some(export) => stories.push(export.to_json()?),
+            None => return Err(format!("Story not found: {}", id)),
+        }
+    }
+    Ok(stories)
+}
+
+/// Export the given stories according to a rule, returning what was written
+pub fn run_rule(
+    app: &AppHandle,
+    rule: &ExportRule,
+    stories_json: &[String],
+) -> Result<Vec<String>, String> {
+    let folder = PathBuf::from(&rule.folder);
+    fs::create_dir_all(&folder)
+        .map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+    let date = &rfc3339(now_ms())[..10];
+    let exports = stories_json
+        .iter()
+        .map(|json| StoryExport::from_json(json).map(|export| (json, export)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if exports.is_empty() {
+        return Err("No stories to export".to_string());
+    }
+
+    if rule.format == ScheduledFormat::Archive {
+        let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (json, export) in &exports {
+            let name = format!("{}-{}.avt", slug(&export.story.title), &export.story.id);
+            zip.start_file(name, SimpleFileOptions::default())
+                .and_then(|_| zip.write_all(json.as_bytes()).map_err(Into::into))
+                .map_err(|e| format!("Failed to write archive: {}", e))?;
+        }
+        let bytes = zip
+            .finish()
+            .map_err(|e| format!("Failed to write archive: {}", e))?
+            .into_inner();
+        let path = folder.join(format!("{}-{}.zip", slug(&rule.name), date));
+        return Ok(vec![write(&path, &bytes)?]);
+    }
+
+    let mut outputs = Vec::with_capacity(exports.len());
+    for (json, export) in &exports {
+        let base = format!("{}-{}", slug(&export.story.title), date);
+        let cover = cover::cover_for(app, &export.story.id);
+        let output = match rule.format {
+            ScheduledFormat::Epub => write(
+                &folder.join(format!("{}.epub", base)),
+                &build_epub(export, cover.as_deref())?,
+            )?,
+            ScheduledFormat::Twine => write(
+                &folder.join(format!("{}.twee", base)),
+                twine::to_twee(export).as_bytes(),
+            )?,
+            ScheduledFormat::Aventura => {
+                write(&folder.join(format!("{}.avt", base)), json.as_bytes())?
+            }
+            ScheduledFormat::Site => {
+                site::export_site(
+                    export,
+                    Default::default(),
+                    &folder.join(&base),
+                    cover.as_deref(),
+                )?
+                .path
+            }
+            ScheduledFormat::Archive => unreachable!("archives are written above"),
+        };
+        outputs.push(output);
+    }
+    Ok(outputs)
+}
+
+/// Tracks the worker and which rules are being run
+#[derive(Clone, Default)]
+pub struct ExportScheduler {
+    lock: Arc<Mutex<()>>,
+    running: Arc<Mutex<HashSet<String>>>,
+    worker_running: Arc<AtomicBool>,
+}
+
+impl ExportScheduler {
+    pub async fn list(&self, app: &AppHandle) -> Result<Vec<ExportRule>, String> {
+        let _guard = self.lock.lock().await;
+        store::load_json(app, EXPORT_RULES_FILE)
+    }
+
+    /// Add or replace a rule. New rules run at the next check.
+    pub async fn save(&self, app: &AppHandle, mut rule: ExportRule) -> Result<ExportRule, String> {
+        if rule.folder.trim().is_empty() {
+            return Err("Export folder is required".to_string());
+        }
+        let guard = self.lock.lock().await;
+        let mut rules: Vec<ExportRule> = store::load_json(app, EXPORT_RULES_FILE)?;
+        if rule.id.is_empty() {
+            rule.id = uuid::Uuid::new_v4().to_string();
+        }
+        match rules.iter_mut().find(|r| r.id == rule.id) {
+            Some(existing) => {
+                rule.last_run = existing.last_run.clone();
+                *existing = rule.clone();
+            }
+            None => rules.push(rule.clone()),
+        }
+        store::save_json(app, EXPORT_RULES_FILE, &rules)?;
+        drop(guard);
+
+        self.ensure_worker(app.clone());
+        Ok(rule)
+    }
+
+    pub async fn delete(&self, app: &AppHandle, rule_id: &str) -> Result<bool, String> {
+        let _guard = self.lock.lock().await;
+        let mut rules: Vec<ExportRule> = store::load_json(app, EXPORT_RULES_FILE)?;
+        let before = rules.len();
+        rules.retain(|r| r.id != rule_id);
+        if rules.len() == before {
+            return Ok(false);
+        }
+        store::save_json(app, EXPORT_RULES_FILE, &rules)?;
+        Ok(true)
+    }
+
+    /// Run a rule now, recording the outcome and scheduling the next run.
+    /// A failed run is scheduled again after `RETRY_DELAY_MS`.
+    pub async fn run(&self, app: &AppHandle, rule_id: &str) -> Result<ExportRunStatus, String> {
+        if !self.running.lock().await.insert(rule_id.to_string()) {
+            return Err("This export is already running".to_string());
+        }
+        let result = self.run_now(app, rule_id).await;
+        self.running.lock().await.remove(rule_id);
+        result
+    }
+
+    async fn run_now(&self, app: &AppHandle, rule_id: &str) -> Result<ExportRunStatus, String> {
+        let rule = self
+            .list(app)
+            .await?
+            .into_iter()
+            .find(|r| r.id == rule_id)
+            .ok_or_else(|| format!("Export rule not found: {}", rule_id))?;
+
+        let result = match target_stories(app, &rule.target).await {
+            Ok(stories_json) => {
+                let job = rule.clone();
+                let handle = app.clone();
+                tokio::task::spawn_blocking(move || run_rule(&handle, &job, &stories_json))
+                    .await
+                    .map_err(|e| format!("Export failed: {}", e))?
+            }
+            Err(e) => Err(e),
+        };
+        let ran_at = now_ms();
+        let status = match result {
+            Ok(outputs) => ExportRunStatus {
+                ran_at,
+                success: true,
+                error: None,
+                outputs,
+            },
+            Err(error) => ExportRunStatus {
+                ran_at,
+                success: false,
+                error: Some(error),
+                outputs: Vec::new(),
+            },
+        };
+
+        let _guard = self.lock.lock().await;
+        let mut rules: Vec<ExportRule> = store::load_json(app, EXPORT_RULES_FILE)?;
+        if let Some(stored) = rules.iter_mut().find(|r| r.id == rule_id) {
+            stored.next_run_at = if status.success {
+                stored.interval.next_after(ran_at)
+            } else {
+                ran_at + RETRY_DELAY_MS
+            };
+            stored.last_run = Some(status.clone());
+        }
+        store::save_json(app, EXPORT_RULES_FILE, &rules)?;
+        Ok(status)
+    }
+
+    /// Start the scheduling loop unless it is already running.
+    /// The loop stops once no rule is enabled.
+    pub fn ensure_worker(&self, app: AppHandle) {
+        if self.worker_running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let scheduler = self.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                scheduler.run_due(&app).await;
+                // Checked under the lock `save` writes under, so a rule
+                // enabled now either is seen here or starts a new worker
+                let guard = scheduler.lock.lock().await;
+                let enabled = store::load_json::<Vec<ExportRule>>(&app, EXPORT_RULES_FILE)
+                    .is_ok_and(|rules| rules.iter().any(|r| r.enabled));
+                if !enabled {
+                    scheduler.worker_running.store(false, Ordering::SeqCst);
+                    break;
+                }
+                drop(guard);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Run the rules that are due, one after another
+    async fn run_due(&self, app: &AppHandle) {
+        let Ok(rules) = self.list(app).await else {
+            return;
+        };
+        let now = now_ms();
+        for rule in rules.iter().filter(|r| r.enabled && r.next_run_at <= now) {
+            if let Err(e) = self.run(app, &rule.id).await {
+                eprintln!("Scheduled export {} failed: {}", rule.name, e);
+            }
+        }
+    }
+}
+
+/// Start the scheduler at launch
+pub fn resume(app: &AppHandle) {
+    let scheduler = app.state::<ExportState>().scheduler.clone();
+    scheduler.ensure_worker(app.clone());
+}
@@ -0,0 +1,376 @@
+use ammonia::Builder;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::ImageFormat;
+use pulldown_cmark::{html, Options, Parser};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+use super::reader;
+use crate::story::types::{EmbeddedImage, StoryEntry};
+use crate::story::StoryExport;
+
+/// Entries per page when a story has no chapters
+const ENTRIES_PER_PAGE: usize = 30;
+
+/// Visual theme for the exported site
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SiteTheme {
+    #[default]
+    Light,
+    Dark,
+    Sepia,
+}
+
+impl SiteTheme {
+    /// Theme of the web reader, set as `data-theme` on the page
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            SiteTheme::Light => "light",
+            SiteTheme::Dark => "dark",
+            SiteTheme::Sepia => "sepia",
+        }
+    }
+}
+
+/// Summary of a finished site export
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteExportResult {
+    pub path: String,
+    pub page_count: usize,
+    pub image_count: usize,
+}
+
+/// A page of the site: a chapter or a run of entries
+pub(crate) struct Page<'a> {
+    pub file: String,
+    pub title: String,
+    pub entries: Vec<&'a StoryEntry>,
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Allow-list sanitizer for rendered content. Inline styles are kept for
+/// visual prose, except ones that load from elsewhere or run code.
+fn sanitizer() -> &'static Builder<'static> {
+    static SANITIZER: OnceLock<Builder<'static>> = OnceLock::new();
+    SANITIZER.get_or_init(|| {
+        let mut builder = Builder::default();
+        builder
+            .add_generic_attributes(["style", "class"])
+            .attribute_filter(|_, attribute, value| {
+                let lowered = value.to_ascii_lowercase();
+                let unsafe_style = ["url(", "expression(", "javascript:", "@import"]
+                    .iter()
+                    .any(|p| lowered.contains(p));
+                (attribute != "style" || !unsafe_style).then_some(value.into())
+            });
+        builder
+    })
+}
+
+/// Render entry content (Markdown or visual-prose HTML) to HTML safe to
+/// share. Only known-safe tags and attributes are kept, and void elements
+/// are closed so the result is also valid XHTML for EPUB.
+pub(crate) fn render_content(content: &str) -> String {
+    let mut rendered = String::new();
+    html::push_html(
+        &mut rendered,
+        Parser::new_ext(content, Options::ENABLE_STRIKETHROUGH),
+    );
+    let clean = sanitizer().clean(&rendered).to_string();
+
+    static VOID: OnceLock<Regex> = OnceLock::new();
+    let void = VOID.get_or_init(|| Regex::new(r"<(br|hr|img|wbr)((?:\s[^>]*)?)>").unwrap());
+    void.replace_all(&clean, "<$1$2 />").into_owned()
+}
+
+/// File extension for image data, from its first bytes. None for anything
+/// a browser or reader might not show.
+pub(crate) fn image_extension(bytes: &[u8]) -> Option<&'static str> {
+    match image::guess_format(bytes).ok()? {
+        ImageFormat::Png => Some("png"),
+        ImageFormat::Jpeg => Some("jpg"),
+        ImageFormat::Gif => Some("gif"),
+        ImageFormat::WebP => Some("webp"),
+        _ => None,
+    }
+}
+
+/// Split one branch's entries into pages, by memory chapter when available
+pub(crate) fn paginate<'a>(
+    export: &'a StoryExport,
+    entries: Vec<&'a StoryEntry>,
+    prefix: &str,
+) -> Vec<Page<'a>> {
+    let mut chapters: Vec<_> = export.chapters.iter().collect();
+    chapters.sort_by_key(|c| c.number);
+
+    let mut pages = Vec::new();
+    let chapter_starts: HashMap<&str, String> = chapters
+        .iter()
+        .map(|c| {
+            let title = c
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("Chapter {}", c.number));
+            (c.start_entry_id.as_str(), title)
+        })
+        .collect();
+
+    if chapter_starts.is_empty() {
+        for (index, chunk) in entries.chunks(ENTRIES_PER_PAGE).enumerate() {
+            pages.push(Page {
+                file: format!("{}{}.html", prefix, index + 1),
+                title: format!("Part {}", index + 1),
+                entries: chunk.to_vec(),
+            });
+        }
+        return pages;
+    }
+
+    for entry in entries {
+        if let Some(title) = chapter_starts.get(entry.id.as_str()) {
+            pages.push(Page {
+                file: format!("{}{}.html", prefix, pages.len() + 1),
+                title: title.clone(),
+                entries: Vec::new(),
+            });
+        }
+        if pages.is_empty() {
+            pages.push(Page {
+                file: format!("{}1.html", prefix),
+                title: "Prologue".to_string(),
+                entries: Vec::new(),
+            });
+        }
+        if let Some(page) = pages.last_mut() {
+            page.entries.push(entry);
+        }
+    }
+    pages
+}
+
+fn document(theme: SiteTheme, title: &str, story_title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en" data-theme="{}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{} · {}</title>
+{}</head>
+<body>
+<main>
+{}
+</main>
+</body>
+</html>
+"#,
+        theme.name(),
+        escape_html(title),
+        escape_html(story_title),
+        reader::head(""),
+        body
+    )
+}
+
+fn render_entry(
+    entry: &StoryEntry,
+    images: &HashMap<&str, Vec<(&EmbeddedImage, String)>>,
+    choices: &HashMap<&str, Vec<(String, String)>>,
+) -> String {
+    let class = if entry.entry_type == "user_action" {
+        "entry action"
+    } else {
+        "entry"
+    };
+    let mut html = format!(
+        "<section class=\"{}\">\n{}",
+        class,
+        render_content(&entry.content)
+    );
+
+    for (image, file) in images.get(entry.id.as_str()).into_iter().flatten() {
+        html.push_str(&format!(
+            "<figure><img src=\"images/{}\" alt=\"{}\"><figcaption>{}</figcaption></figure>\n",
+            file,
+            escape_html(&image.source_text),
+            escape_html(&image.source_text)
+        ));
+    }
+
+    if let Some(options) = choices.get(entry.id.as_str()) {
+        html.push_str("<div class=\"choices\"><strong>Paths from here</strong><ul>\n");
+        for (file, name) in options {
+            html.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                file,
+                escape_html(name)
+            ));
+        }
+        html.push_str("</ul></div>\n");
+    }
+
+    html.push_str("</section>\n");
+    html
+}
+
+fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), String> {
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Render a story to a self-contained static website in `dir`.
+/// Branches get their own page series, linked from the entry they fork at.
+/// A PNG cover is shown at the top of the contents page.
+pub fn export_site(
+    export: &StoryExport,
+    theme: SiteTheme,
+    dir: &Path,
+    cover: Option<&[u8]>,
+) -> Result<SiteExportResult, String> {
+    fs::create_dir_all(dir.join("images"))
+        .map_err(|e| format!("Failed to create site directory: {}", e))?;
+
+    let mut by_branch: HashMap<Option<&str>, Vec<&StoryEntry>> = HashMap::new();
+    for entry in &export.entries {
+        by_branch
+            .entry(entry.branch_id.as_deref())
+            .or_default()
+            .push(entry);
+    }
+    for entries in by_branch.values_mut() {
+        entries.sort_by_key(|e| e.position);
+    }
+
+    let mut series: Vec<(String, Vec<Page>)> = vec![(
+        "Main story".to_string(),
+        paginate(
+            export,
+            by_branch.remove(&None).unwrap_or_default(),
+            "page-",
+        ),
+    )];
+    let mut choices: HashMap<&str, Vec<(String, String)>> = HashMap::new();
+    for (index, branch) in export.branches.iter().enumerate() {
+        let entries = by_branch
+            .remove(&Some(branch.id.as_str()))
+            .unwrap_or_default();
+        let pages = paginate(export, entries, &format!("branch-{}-", index + 1));
+        if let Some(first) = pages.first() {
+            choices
+                .entry(branch.fork_entry_id.as_str())
+                .or_default()
+                .push((first.file.clone(), branch.name.clone()));
+        }
+        series.push((branch.name.clone(), pages));
+    }
+
+    let mut images: HashMap<&str, Vec<(&EmbeddedImage, String)>> = HashMap::new();
+    let mut image_count = 0;
+    for image in export
+        .embedded_images
+        .iter()
+        .filter(|i| !i.image_data.is_empty())
+    {
+        // The ID names a file, so only a UUID is trusted with it
+        let id =
+            Uuid::parse_str(&image.id).map_err(|_| format!("Invalid image ID: {}", image.id))?;
+        let bytes = STANDARD
+            .decode(&image.image_data)
+            .map_err(|e| format!("Invalid image data for {}: {}", image.id, e))?;
+        let extension = image_extension(&bytes)
+            .ok_or_else(|| format!("Image {} is not a PNG, JPEG, GIF or WebP", image.id))?;
+        let file = format!("{}.{}", id.hyphenated(), extension);
+        write_file(&dir.join("images").join(&file), bytes)?;
+        images
+            .entry(image.entry_id.as_str())
+            .or_default()
+            .push((image, file));
+        image_count += 1;
+    }
+
+    let title = &export.story.title;
+    let mut index = String::new();
+    if let Some(cover) = cover {
+        write_file(&dir.join("cover.png"), cover)?;
+        index.push_str(&format!(
+            "<img class=\"cover\" src=\"cover.png\" alt=\"{}\">\n",
+            escape_html(title)
+        ));
+    }
+    index.push_str(&format!("<h1>{}</h1>\n", escape_html(title)));
+    if let Some(genre) = &export.story.genre {
+        index.push_str(&format!("<p class=\"meta\">{}</p>\n", escape_html(genre)));
+    }
+    if let Some(description) = &export.story.description {
+        index.push_str(&render_content(description));
+    }
+
+    let mut page_count = 1;
+    for (name, pages) in &series {
+        if pages.is_empty() {
+            continue;
+        }
+        index.push_str(&format!("<h2>{}</h2>\n<ol>\n", escape_html(name)));
+        for (i, page) in pages.iter().enumerate() {
+            index.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                page.file,
+                escape_html(&page.title)
+            ));
+
+            let mut body = format!(
+                "<nav><a href=\"index.html\">{}</a> · {}</nav>\n<h1>{}</h1>\n",
+                escape_html(title),
+                escape_html(name),
+                escape_html(&page.title)
+            );
+            for entry in &page.entries {
+                body.push_str(&render_entry(entry, &images, &choices));
+            }
+            let previous = i.checked_sub(1).and_then(|p| pages.get(p));
+            body.push_str(&format!(
+                "<nav class=\"pager\"><span>{}</span><span>{}</span></nav>\n",
+                previous.map_or(String::new(), |p| format!(
+                    "<a href=\"{}\">← Previous</a>",
+                    p.file
+                )),
+                pages.get(i + 1).map_or(String::new(), |p| format!(
+                    "<a href=\"{}\">Next →</a>",
+                    p.file
+                ))
+            ));
+
+            write_file(
+                &dir.join(&page.file),
+                document(theme, &page.title, title, &body),
+            )?;
+            page_count += 1;
+        }
+        index.push_str("</ol>\n");
+    }
+
+    write_file(
+        &dir.join("index.html"),
+        document(theme, "Contents", title, &index),
+    )?;
+    reader::write_assets(dir)?;
+
+    Ok(SiteExportResult {
+        path: dir.to_string_lossy().to_string(),
+        page_count,
+        image_count,
+    })
+}
@@ -0,0 +1,322 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::cover;
+use super::pdf::{self, JpegImage, TextBlock};
+use crate::ai::proxy::{complete_chat, json_object};
+use crate::ai::types::{ChatMessage, ProviderConfig, SamplingParams};
+use crate::story::text::plain_text;
+use crate::story::StoryExport;
+
+/// Story text sent to the model when chapters have no summaries
+const MAX_SOURCE_CHARS: usize = 16_000;
+
+/// Output format for a story summary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SummaryFormat {
+    #[default]
+    Markdown,
+    Pdf,
+}
+
+/// Counts shown under the synopsis
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryStats {
+    pub entry_count: usize,
+    pub word_count: usize,
+    pub chapter_count: usize,
+    pub character_count: usize,
+    pub location_count: usize,
+    pub branch_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryCharacter {
+    pub name: String,
+    #[serde(default)]
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterBeat {
+    pub title: String,
+    pub beat: String,
+}
+
+/// Structured one-page synopsis of a story
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorySummary {
+    pub story_id: String,
+    pub title: String,
+    pub genre: Option<String>,
+    pub logline: String,
+    pub characters: Vec<SummaryCharacter>,
+    pub beats: Vec<ChapterBeat>,
+    pub stats: SummaryStats,
+}
+
+/// Result of generating a summary, and where it was written if a path was given
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryExportResult {
+    pub summary: StorySummary,
+    pub path: Option<String>,
+    /// True when the PDF could not fit everything on one page
+    pub truncated: bool,
+}
+
+/// The part of the summary written by the model
+#[derive(Debug, Deserialize)]
+struct Synopsis {
+    logline: String,
+    #[serde(default)]
+    characters: Vec<SummaryCharacter>,
+    #[serde(default)]
+    beats: Vec<ChapterBeat>,
+}
+
+fn stats(export: &StoryExport) -> SummaryStats {
+    let main: Vec<_> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none() && e.entry_type != "system")
+        .collect();
+    SummaryStats {
+        entry_count: main.len(),
+        word_count: main
+            .iter()
+            .map(|e| plain_text(&e.content).split_whitespace().count())
+            .sum(),
+        chapter_count: export.chapters.len(),
+        character_count: export.characters.len(),
+        location_count: export.locations.len(),
+        branch_count: export.branches.len(),
+    }
+}
+
+/// Describe the story for the model: chapter summaries when the story has
+/// them, otherwise the opening and closing of the main branch
+fn source_text(export: &StoryExport) -> String {
+    let mut source = format!("Title: {}\n", export.story.title);
+    if let Some(genre) = &export.story.genre {
+        source.push_str(&format!("Genre: {}\n", genre));
+    }
+    if let Some(description) = export
+        .story
+        .description
+        .as_deref()
+        .filter(|d| !d.is_empty())
+    {
+        source.push_str(&format!("Premise: {}\n", description));
+    }
+    if !export.characters.is_empty() {
+        source.push_str("\nCharacters:\n");
+        for character in &export.characters {
+            source.push_str(&format!(
+                "- {}: {}\n",
+                character.name,
+                character.description.as_deref().unwrap_or("")
+            ));
+        }
+    }
+
+    let mut chapters: Vec<_> = export.chapters.iter().collect();
+    chapters.sort_by_key(|c| c.number);
+    if !chapters.is_empty() && chapters.iter().all(|c| !c.summary.trim().is_empty()) {
+        source.push_str("\nChapters:\n");
+        for chapter in chapters {
+            source.push_str(&format!(
+                "Chapter {} ({}): {}\n",
+                chapter.number,
+                chapter.title.as_deref().unwrap_or("untitled"),
+                chapter.summary
+            ));
+        }
+        return source;
+    }
+
+    let mut entries: Vec<_> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none() && e.entry_type != "system")
+        .collect();
+    entries.sort_by_key(|e| e.position);
+    let text: Vec<char> = entries
+        .iter()
+        .map(|e| plain_text(&e.content))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+        .chars()
+        .collect();
+    source.push_str("\nStory text:\n");
+    if text.len() <= MAX_SOURCE_CHARS {
+        source.extend(text.iter());
+    } else {
+        let half = MAX_SOURCE_CHARS / 2;
+        source.extend(text[..half].iter());
+        source.push_str("\n\n[...]\n\n");
+        source.extend(text[text.len() - half..].iter());
+    }
+    source
+}
+
+/// Pull the JSON object out of a reply that may be wrapped in prose or a code fence
+fn parse_synopsis(reply: &str) -> Result<Synopsis, String> {
+    let json = json_object(reply).ok_or("Summary response contained no JSON")?;
+    serde_json::from_str(json).map_err(|e| format!("Invalid summary response: {}", e))
+}
+
+/// Ask the model for a logline, character roles and beats, and combine them
+/// with stats computed from the story
+pub async fn generate_summary(
+    export: &StoryExport,
+    provider: &ProviderConfig,
+) -> Result<StorySummary, String> {
+    let beat_instruction = if export.chapters.is_empty() {
+        "3 to 7 beats covering the whole story, titled by act or turning point"
+    } else {
+        "one beat per chapter, in order, titled with the chapter's title or \"Chapter N\""
+    };
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: format!(
+                "You write one-page pitch summaries of interactive fiction. Reply with JSON only, \
+                 in the form {{\"logline\": string, \"characters\": [{{\"name\": string, \
+                 \"role\": string}}], \"beats\": [{{\"title\": string, \"beat\": string}}]}}. \
+                 The logline is one sentence. List at most 6 characters, each role a short \
+                 phrase. Give {}, each beat one or two sentences.",
+                beat_instruction
+            ),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: source_text(export),
+        },
+    ];
+    let sampling = SamplingParams {
+        temperature: Some(0.4),
+        ..Default::default()
+    };
+
+    let synopsis = parse_synopsis(&complete_chat(provider, &messages, &sampling).await?)?;
+    Ok(StorySummary {
+        story_id: export.story.id.clone(),
+        title: export.story.title.clone(),
+        genre: export.story.genre.clone(),
+        logline: synopsis.logline.trim().to_string(),
+        characters: synopsis.characters,
+        beats: synopsis.beats,
+        stats: stats(export),
+    })
+}
+
+fn stats_line(stats: &SummaryStats) -> String {
+    let mut parts = vec![
+        format!("{} words", stats.word_count),
+        format!("{} entries", stats.entry_count),
+    ];
+    for (count, label) in [
+        (stats.chapter_count, "chapters"),
+        (stats.character_count, "characters"),
+        (stats.location_count, "locations"),
+        (stats.branch_count, "branches"),
+    ] {
+        if count > 0 {
+            parts.push(format!("{} {}", count, label));
+        }
+    }
+    parts.join(" · ")
+}
+
+/// Render a summary as Markdown
+pub fn to_markdown(summary: &StorySummary) -> String {
+    let mut md = format!("# {}\n\n", summary.title);
+    if let Some(genre) = &summary.genre {
+        md.push_str(&format!("*{}*\n\n", genre));
+    }
+    md.push_str(&format!("> {}\n\n", summary.logline));
+    if !summary.characters.is_empty() {
+        md.push_str("## Characters\n\n");
+        for character in &summary.characters {
+            md.push_str(&format!("- **{}** — {}\n", character.name, character.role));
+        }
+        md.push('\n');
+    }
+    if !summary.beats.is_empty() {
+        md.push_str("## Story beats\n\n");
+        for (index, beat) in summary.beats.iter().enumerate() {
+            md.push_str(&format!(
+                "{}. **{}** — {}\n",
+                index + 1,
+                beat.title,
+                beat.beat
+            ));
+        }
+        md.push('\n');
+    }
+    md.push_str(&format!("---\n\n{}\n", stats_line(&summary.stats)));
+    md
+}
+
+/// Render a summary as a single-page PDF, behind the cover if there is one.
+/// Returns whether content was cut off.
+pub fn to_pdf(summary: &StorySummary, cover: Option<&JpegImage>) -> (Vec<u8>, bool) {
+    let mut blocks = vec![TextBlock::heading(&summary.title, 20.0)];
+    if let Some(genre) = &summary.genre {
+        blocks.push(TextBlock::body(genre));
+    }
+    blocks.push(TextBlock {
+        space_before: 10.0,
+        ..TextBlock::body(&summary.logline)
+    });
+    if !summary.characters.is_empty() {
+        blocks.push(TextBlock::heading("Characters", 13.0));
+        for character in &summary.characters {
+            blocks.push(TextBlock::body(format!(
+                "{} - {}",
+                character.name, character.role
+            )));
+        }
+    }
+    if !summary.beats.is_empty() {
+        blocks.push(TextBlock::heading("Story beats", 13.0));
+        for (index, beat) in summary.beats.iter().enumerate() {
+            blocks.push(TextBlock::body(format!(
+                "{}. {}: {}",
+                index + 1,
+                beat.title,
+                beat.beat
+            )));
+        }
+    }
+    blocks.push(TextBlock {
+        space_before: 12.0,
+        ..TextBlock::body(stats_line(&summary.stats))
+    });
+    pdf::single_page(&summary.title, &blocks, cover)
+}
+
+/// Write a summary to disk in the given format. A PNG cover goes on the
+/// first page of PDFs.
+pub fn write_summary(
+    summary: &StorySummary,
+    format: SummaryFormat,
+    path: &Path,
+    cover: Option<&[u8]>,
+) -> Result<bool, String> {
+    let (bytes, truncated) = match format {
+        SummaryFormat::Markdown => (to_markdown(summary).into_bytes(), false),
+        SummaryFormat::Pdf => {
+            let cover = cover.map(cover::to_jpeg).transpose()?;
+            to_pdf(summary, cover.as_ref())
+        }
+    };
+    std::fs::write(path, bytes).map_err(|e| format!("Failed to write summary: {}", e))?;
+    Ok(truncated)
+}
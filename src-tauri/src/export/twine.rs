@@ -0,0 +1,58 @@
+use crate::story::graph::{EdgeKind, StoryGraph};
+use crate::story::StoryExport;
+
+/// Passage name for an entry; Twine links by name, so it must be unique
+fn passage_name(position: i64, id: &str) -> String {
+    format!(
+        "Entry {} ({})",
+        position,
+        id.chars().take(8).collect::<String>()
+    )
+}
+
+/// Remove characters that would break Twee passage headers or links
+fn twee_text(text: &str) -> String {
+    text.replace("[[", "[ [")
+        .replace("]]", "] ]")
+        .replace("\n::", "\n ::")
+}
+
+/// Render a story as Twee 3 source, importable into Twine.
+/// Each entry becomes a passage linked to the entries that follow it.
+pub fn to_twee(export: &StoryExport) -> String {
+    let graph = StoryGraph::build(export);
+    let adjacency = graph.adjacency();
+    let names: std::collections::HashMap<&str, String> = graph
+        .nodes
+        .iter()
+        .map(|n| (n.id.as_str(), passage_name(n.position, &n.id)))
+        .collect();
+
+    let mut out = format!(":: StoryTitle\n{}\n\n", twee_text(&export.story.title));
+    let story_data = serde_json::json!({
+        "ifid": export.story.id.to_uppercase(),
+        "format": "Harlowe",
+        "start": graph.root.as_ref().and_then(|r| names.get(r.as_str())),
+    });
+    out.push_str(&format!(":: StoryData\n{}\n\n", story_data));
+
+    for entry in &export.entries {
+        let Some(name) = names.get(entry.id.as_str()) else {
+            continue;
+        };
+        out.push_str(&format!(":: {}\n{}\n", name, twee_text(&entry.content)));
+
+        for edge in adjacency.get(entry.id.as_str()).into_iter().flatten() {
+            let Some(target) = names.get(edge.to.as_str()) else {
+                continue;
+            };
+            let label = match (edge.kind, &edge.label) {
+                (EdgeKind::Choice, Some(label)) => twee_text(label),
+                _ => "Continue".to_string(),
+            };
+            out.push_str(&format!("\n[[{}->{}]]", label, target));
+        }
+        out.push_str("\n\n");
+    }
+    out
+}
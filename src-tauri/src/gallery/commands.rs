@@ -0,0 +1,38 @@
+use tauri::AppHandle;
+
+use super::{GalleryConfig, GalleryFilter, GalleryListing, InstalledGalleryItem};
+use crate::import::remote;
+use crate::store;
+
+#[tauri::command]
+pub async fn get_gallery_config(app: AppHandle) -> Result<GalleryConfig, String> {
+    store::load_json(&app, super::GALLERY_CONFIG_FILE)
+}
+
+#[tauri::command]
+pub async fn set_gallery_config(app: AppHandle, config: GalleryConfig) -> Result<(), String> {
+    if let Some(url) = config.index_url.as_deref().filter(|u| !u.trim().is_empty()) {
+        remote::parse_url(url)?;
+    }
+    store::save_json(&app, super::GALLERY_CONFIG_FILE, &config)
+}
+
+/// List gallery items matching the filter, from the cached index while it
+/// is fresh
+#[tauri::command]
+pub async fn browse_gallery(
+    app: AppHandle,
+    filter: Option<GalleryFilter>,
+) -> Result<GalleryListing, String> {
+    super::browse(&app, &filter.unwrap_or_default()).await
+}
+
+/// Download a gallery item and verify its signature. The content is returned
+/// for the frontend to save as a template or import as a story.
+#[tauri::command]
+pub async fn install_gallery_item(
+    app: AppHandle,
+    id: String,
+) -> Result<InstalledGalleryItem, String> {
+    super::install(&app, &id).await
+}
@@ -0,0 +1,284 @@
+//! Community gallery: a JSON index of shared templates, scenarios and stories
+//! hosted at a configurable HTTPS address. The index is cached in the app
+//! data directory so the gallery can be browsed offline, and every download
+//! must carry a minisign signature from the configured publisher key.
+
+pub mod commands;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use minisign_verify::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::import::remote;
+use crate::store;
+use crate::story::StoryExport;
+use crate::sync::keys::now_ms;
+
+/// Gallery settings in the app data directory
+pub const GALLERY_CONFIG_FILE: &str = "gallery_config.json";
+
+/// Last index fetched, in the app data directory
+pub const GALLERY_CACHE_FILE: &str = "gallery_index.json";
+
+fn default_cache_minutes() -> u32 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GalleryConfig {
+    /// HTTPS address of the index JSON
+    #[serde(default)]
+    pub index_url: Option<String>,
+    /// Minisign public key downloads must be signed with, either the key
+    /// itself or a base64-encoded `.pub` file
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// How long the cached index is used before it is fetched again
+    #[serde(default = "default_cache_minutes")]
+    pub cache_minutes: u32,
+}
+
+impl Default for GalleryConfig {
+    fn default() -> Self {
+        Self {
+            index_url: None,
+            public_key: None,
+            cache_minutes: default_cache_minutes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GalleryItemKind {
+    Template,
+    Scenario,
+    Story,
+}
+
+/// One shared item as listed in the index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GalleryItem {
+    pub id: String,
+    pub kind: GalleryItemKind,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// HTTPS address of the item's JSON
+    pub url: String,
+    /// Minisign signature of the file, as the `.sig` file's text or that
+    /// text base64-encoded
+    pub signature: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+/// The index file published by a gallery
+#[derive(Debug, Deserialize)]
+struct GalleryIndex {
+    items: Vec<GalleryItem>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedIndex {
+    index_url: String,
+    fetched_at: i64,
+    items: Vec<GalleryItem>,
+}
+
+/// What to show from the index
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GalleryFilter {
+    /// Words that must all appear in the name, description, author or genre
+    pub query: Option<String>,
+    pub kind: Option<GalleryItemKind>,
+    pub tag: Option<String>,
+    /// Fetch the index even if the cached copy is fresh
+    pub refresh: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GalleryListing {
+    pub items: Vec<GalleryItem>,
+    pub fetched_at: i64,
+    /// The index could not be fetched and the cached copy is shown instead
+    pub stale: bool,
+}
+
+/// A verified download, for the frontend to add to its templates or stories
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledGalleryItem {
+    pub item: GalleryItem,
+    pub content: String,
+}
+
+fn index_url(config: &GalleryConfig) -> Result<String, String> {
+    config
+        .index_url
+        .clone()
+        .filter(|url| !url.trim().is_empty())
+        .ok_or_else(|| "No gallery is configured".to_string())
+}
+
+async fn fetch_index(url: &str) -> Result<Vec<GalleryItem>, String> {
+    let json = remote::json_text(remote::download(&remote::parse_url(url)?).await?)?;
+    let index: GalleryIndex =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid gallery index: {}", e))?;
+    let mut items: Vec<GalleryItem> = Vec::with_capacity(index.items.len());
+    for item in index.items {
+        if !item.id.is_empty() && !items.iter().any(|i| i.id == item.id) {
+            items.push(item);
+        }
+    }
+    Ok(items)
+}
+
+/// The index, from the cache while it is fresh. A failed fetch falls back to
+/// any cached copy of the same index.
+async fn load_index(app: &AppHandle, refresh: bool) -> Result<GalleryListing, String> {
+    let config: GalleryConfig = store::load_json(app, GALLERY_CONFIG_FILE)?;
+    let url = index_url(&config)?;
+    let cached: CachedIndex = store::load_json(app, GALLERY_CACHE_FILE)?;
+    let cached = Some(cached).filter(|c| c.index_url == url);
+    let max_age = i64::from(config.cache_minutes) * 60_000;
+    if let Some(cached) = cached.as_ref() {
+        if !refresh && now_ms() - cached.fetched_at < max_age {
+            return Ok(GalleryListing {
+                items: cached.items.clone(),
+                fetched_at: cached.fetched_at,
+                stale: false,
+            });
+        }
+    }
+
+    match fetch_index(&url).await {
+        Ok(items) => {
+            let fresh = CachedIndex {
+                index_url: url,
+                fetched_at: now_ms(),
+                items,
+            };
+            store::save_json(app, GALLERY_CACHE_FILE, &fresh)?;
+            Ok(GalleryListing {
+                items: fresh.items,
+                fetched_at: fresh.fetched_at,
+                stale: false,
+            })
+        }
+        Err(e) => match cached {
+            Some(cached) => Ok(GalleryListing {
+                items: cached.items,
+                fetched_at: cached.fetched_at,
+                stale: true,
+            }),
+            None => Err(e),
+        },
+    }
+}
+
+fn matches(item: &GalleryItem, filter: &GalleryFilter) -> bool {
+    if filter.kind.is_some_and(|kind| kind != item.kind) {
+        return false;
+    }
+    if let Some(tag) = filter.tag.as_deref().filter(|t| !t.is_empty()) {
+        if !item.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            return false;
+        }
+    }
+    let Some(query) = filter.query.as_deref() else {
+        return true;
+    };
+    let haystack = [
+        item.name.as_str(),
+        item.description.as_str(),
+        item.author.as_deref().unwrap_or_default(),
+        item.genre.as_deref().unwrap_or_default(),
+    ]
+    .join(" ")
+    .to_lowercase();
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .all(|word| haystack.contains(word))
+}
+
+/// Items in the index that pass the filter
+pub async fn browse(app: &AppHandle, filter: &GalleryFilter) -> Result<GalleryListing, String> {
+    let mut listing = load_index(app, filter.refresh).await?;
+    listing.items.retain(|item| matches(item, filter));
+    Ok(listing)
+}
+
+/// Minisign data given either as text or as base64 of that text
+fn decode_text(value: &str) -> String {
+    let value = value.trim();
+    STANDARD
+        .decode(value)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .filter(|text| text.contains("untrusted comment"))
+        .unwrap_or_else(|| value.to_string())
+}
+
+fn public_key(config: &GalleryConfig) -> Result<PublicKey, String> {
+    let key = config
+        .public_key
+        .as_deref()
+        .filter(|key| !key.trim().is_empty())
+        .ok_or("The gallery has no public key, so downloads cannot be verified")?;
+    PublicKey::from_base64(key.trim())
+        .or_else(|_| PublicKey::decode(&decode_text(key)))
+        .map_err(|e| format!("Invalid gallery public key: {}", e))
+}
+
+/// Check a download against the publisher's signature
+fn verify(key: &PublicKey, data: &[u8], signature: &str) -> Result<(), String> {
+    let signature = Signature::decode(&decode_text(signature))
+        .map_err(|e| format!("Invalid signature: {}", e))?;
+    key.verify(data, &signature, true)
+        .map_err(|_| "The download does not match its signature".to_string())
+}
+
+/// Download an item, verify its signature and check that it parses
+pub async fn install(app: &AppHandle, id: &str) -> Result<InstalledGalleryItem, String> {
+    let config: GalleryConfig = store::load_json(app, GALLERY_CONFIG_FILE)?;
+    let key = public_key(&config)?;
+    let item = load_index(app, false)
+        .await?
+        .items
+        .into_iter()
+        .find(|item| item.id == id)
+        .ok_or_else(|| format!("Gallery item not found: {}", id))?;
+
+    let body = remote::download(&remote::parse_url(&item.url)?).await?;
+    verify(&key, &body, &item.signature)?;
+    let content = remote::json_text(body)?;
+    match item.kind {
+        GalleryItemKind::Story => {
+            StoryExport::from_json(&content)?;
+        }
+        GalleryItemKind::Template | GalleryItemKind::Scenario => {
+            let value: serde_json::Value = serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid {}: {}", item.name, e))?;
+            if !value.is_object() {
+                return Err(format!("Invalid {}: expected a JSON object", item.name));
+            }
+        }
+    }
+    Ok(InstalledGalleryItem { item, content })
+}
@@ -0,0 +1,597 @@
+//! Turn-based combat. Combatants are drawn from character sheets and act in
+//! initiative order; attacks are a d20 plus the attacker's `attack` stat
+//! against the target's `defense`, and hits deal a roll of the `damage` die.
+//! Every roll comes from the combat's seed and round, so the same round with
+//! the same actions always resolves the same way. The model only describes
+//! the result; the numbers are decided here. Running fights are kept here
+//! by combat ID, so the frontend names a fight rather than handing its
+//! state back.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::sheet::{CharacterSheet, StatusEffect};
+use crate::rng::SplitMix64;
+
+/// Defense of a combatant whose sheet has no `defense` stat
+const DEFAULT_DEFENSE: i64 = 10;
+
+/// Damage die of a combatant whose sheet has no `damage` stat
+const DEFAULT_DAMAGE_DIE: i64 = 4;
+
+/// Total a flee roll must reach
+const FLEE_DIFFICULTY: i64 = 10;
+
+/// Bonus or penalty of the effects that change rolls
+const EFFECT_MODIFIER: i64 = 2;
+
+/// Effect given by the defend action
+const DEFENDING: &str = "defending";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CombatSide {
+    Party,
+    Enemies,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Combatant {
+    pub sheet_id: String,
+    pub name: String,
+    pub side: CombatSide,
+    pub hp: i64,
+    pub max_hp: i64,
+    pub attack: i64,
+    pub defense: i64,
+    pub damage_die: i64,
+    /// The sheet's `initiative` stat, which also helps to flee
+    pub initiative_bonus: i64,
+    /// Rolled when the combat starts
+    pub initiative: i64,
+    pub effects: Vec<StatusEffect>,
+    #[serde(default)]
+    pub fled: bool,
+}
+
+impl Combatant {
+    fn from_sheet(sheet: &CharacterSheet, side: CombatSide, rng: &mut SplitMix64) -> Self {
+        let stat = |name: &str, default: i64| sheet.stats.get(name).copied().unwrap_or(default);
+        let initiative_bonus = stat("initiative", 0);
+        Self {
+            sheet_id: sheet.id.clone(),
+            name: sheet.name.clone(),
+            side,
+            hp: sheet.hp,
+            max_hp: sheet.max_hp,
+            attack: stat("attack", 0),
+            defense: stat("defense", DEFAULT_DEFENSE),
+            damage_die: stat("damage", DEFAULT_DAMAGE_DIE).max(1),
+            initiative_bonus,
+            initiative: rng.roll(20) as i64 + initiative_bonus,
+            effects: sheet.effects.clone(),
+            fled: false,
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.hp > 0 && !self.fled
+    }
+
+    fn has(&self, effect: &str) -> bool {
+        self.effects
+            .iter()
+            .any(|e| e.name.eq_ignore_ascii_case(effect))
+    }
+
+    fn attack_bonus(&self) -> i64 {
+        let mut bonus = self.attack;
+        if self.has("blessed") {
+            bonus += EFFECT_MODIFIER;
+        }
+        if self.has("weakened") {
+            bonus -= EFFECT_MODIFIER;
+        }
+        bonus
+    }
+
+    fn defense_total(&self) -> i64 {
+        match self.has(DEFENDING) {
+            true => self.defense + EFFECT_MODIFIER,
+            false => self.defense,
+        }
+    }
+
+    /// Effects to save back to the sheet, leaving out those that only
+    /// mean something during a fight
+    pub fn lasting_effects(&self) -> Vec<StatusEffect> {
+        self.effects
+            .iter()
+            .filter(|e| !e.name.eq_ignore_ascii_case(DEFENDING))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CombatOutcome {
+    Victory,
+    Defeat,
+    /// Every member of the party still standing got away
+    Escaped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CombatState {
+    pub id: String,
+    pub story_id: String,
+    pub seed: u64,
+    /// Rounds resolved so far
+    pub round: u32,
+    /// In initiative order
+    pub combatants: Vec<Combatant>,
+    #[serde(default)]
+    pub outcome: Option<CombatOutcome>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum CombatActionKind {
+    Attack {
+        target: String,
+    },
+    /// Raise defense until the combatant's next turn
+    Defend,
+    /// Put a status effect on a combatant, such as a spell or a potion would
+    Apply {
+        target: String,
+        effect: StatusEffect,
+    },
+    Flee,
+    Wait,
+}
+
+/// What one combatant does this round. Enemies without an action attack
+/// the weakest member of the party; party members without one wait.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CombatAction {
+    /// Sheet ID of the combatant acting
+    pub actor: String,
+    #[serde(flatten)]
+    pub action: CombatActionKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CombatEvent {
+    #[serde(rename_all = "camelCase")]
+    Attack {
+        actor: String,
+        target: String,
+        /// The d20
+        roll: u32,
+        total: i64,
+        defense: i64,
+        hit: bool,
+        critical: bool,
+        damage: i64,
+        target_hp: i64,
+    },
+    Defend {
+        actor: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    EffectApplied {
+        actor: String,
+        target: String,
+        effect: StatusEffect,
+    },
+    #[serde(rename_all = "camelCase")]
+    EffectDamage {
+        target: String,
+        effect: String,
+        damage: i64,
+        target_hp: i64,
+    },
+    EffectExpired {
+        target: String,
+        effect: String,
+    },
+    Skipped {
+        actor: String,
+        reason: String,
+    },
+    Fled {
+        actor: String,
+        roll: u32,
+        success: bool,
+    },
+    Defeated {
+        target: String,
+    },
+}
+
+/// A resolved round: the new state, what happened, and a prompt asking the
+/// model to narrate it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CombatRound {
+    pub state: CombatState,
+    pub round: u32,
+    pub events: Vec<CombatEvent>,
+    pub narration_prompt: String,
+}
+
+/// State managed by Tauri for running fights
+#[derive(Default)]
+pub struct ActiveCombats {
+    /// Fights not over yet, keyed by combat ID
+    pub(crate) combats: Mutex<HashMap<String, CombatState>>,
+}
+
+/// Start a fight between sheets, rolling initiative
+pub fn start(
+    story_id: &str,
+    party: &[CharacterSheet],
+    enemies: &[CharacterSheet],
+    seed: u64,
+) -> Result<CombatState, String> {
+    if party.is_empty() || enemies.is_empty() {
+        return Err("Combat needs at least one combatant on each side".to_string());
+    }
+    let mut rng = SplitMix64(seed);
+    let sides = [(party, CombatSide::Party), (enemies, CombatSide::Enemies)];
+    let mut combatants = Vec::new();
+    for (sheets, side) in sides {
+        for sheet in sheets {
+            combatants.push(Combatant::from_sheet(sheet, side, &mut rng));
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    if !combatants.iter().all(|c| seen.insert(c.sheet_id.clone())) {
+        return Err("A sheet can only fight once in the same combat".to_string());
+    }
+    if combatants.iter().any(|c| c.hp <= 0) {
+        return Err("Combatants need hit points left".to_string());
+    }
+    combatants.sort_by_key(|c| std::cmp::Reverse(c.initiative));
+    Ok(CombatState {
+        id: Uuid::new_v4().to_string(),
+        story_id: story_id.to_string(),
+        seed,
+        round: 0,
+        combatants,
+        outcome: None,
+    })
+}
+
+/// Damage a combatant's effects deal at the start of its turn
+fn effect_damage(effect: &str, rng: &mut SplitMix64) -> Option<i64> {
+    match effect.to_lowercase().as_str() {
+        "poisoned" => Some(rng.roll(4) as i64),
+        "burning" => Some(rng.roll(6) as i64),
+        _ => None,
+    }
+}
+
+fn outcome(combatants: &[Combatant]) -> Option<CombatOutcome> {
+    let standing = |side| combatants.iter().any(|c| c.side == side && c.active());
+    if !standing(CombatSide::Enemies) {
+        return Some(CombatOutcome::Victory);
+    }
+    if !standing(CombatSide::Party) {
+        let escaped = combatants
+            .iter()
+            .any(|c| c.side == CombatSide::Party && c.fled);
+        return Some(match escaped {
+            true => CombatOutcome::Escaped,
+            false => CombatOutcome::Defeat,
+        });
+    }
+    None
+}
+
+/// The active party member with the fewest hit points, for enemies to attack
+fn weakest_target(combatants: &[Combatant]) -> Option<String> {
+    combatants
+        .iter()
+        .filter(|c| c.side == CombatSide::Party && c.active())
+        .min_by_key(|c| c.hp)
+        .map(|c| c.sheet_id.clone())
+}
+
+/// Resolve one round: each combatant still standing acts in initiative order
+pub fn resolve_round(
+    mut state: CombatState,
+    actions: &[CombatAction],
+) -> Result<CombatRound, String> {
+    if state.outcome.is_some() {
+        return Err("The combat is already over".to_string());
+    }
+    let mut planned: HashMap<&str, &CombatActionKind> = HashMap::new();
+    for action in actions {
+        if !state.combatants.iter().any(|c| c.sheet_id == action.actor) {
+            return Err(format!("Not in this combat: {}", action.actor));
+        }
+        if planned.insert(&action.actor, &action.action).is_some() {
+            return Err("Each combatant can act once per round".to_string());
+        }
+    }
+
+    state.round += 1;
+    let mut rng = SplitMix64(state.seed.wrapping_add(state.round as u64));
+    let mut events = Vec::new();
+    for turn in 0..state.combatants.len() {
+        if state.outcome.is_some() {
+            break;
+        }
+        if !state.combatants[turn].active() {
+            continue;
+        }
+        let actor_id = state.combatants[turn].sheet_id.clone();
+
+        // Effects act first, then count down
+        let mut stunned = false;
+        let effects = state.combatants[turn].effects.clone();
+        for effect in &effects {
+            if let Some(damage) = effect_damage(&effect.name, &mut rng) {
+                let actor = &mut state.combatants[turn];
+                actor.hp = (actor.hp - damage).max(0);
+                events.push(CombatEvent::EffectDamage {
+                    target: actor_id.clone(),
+                    effect: effect.name.clone(),
+                    damage,
+                    target_hp: actor.hp,
+                });
+            }
+            stunned |= effect.name.eq_ignore_ascii_case("stunned");
+        }
+        let actor = &mut state.combatants[turn];
+        actor
+            .effects
+            .retain_mut(|effect| match effect.rounds.as_mut() {
+                Some(rounds) if *rounds <= 1 => {
+                    events.push(CombatEvent::EffectExpired {
+                        target: actor_id.clone(),
+                        effect: effect.name.clone(),
+                    });
+                    false
+                }
+                Some(rounds) => {
+                    *rounds -= 1;
+                    true
+                }
+                None => true,
+            });
+        if actor.hp == 0 {
+            events.push(CombatEvent::Defeated {
+                target: actor_id.clone(),
+            });
+            state.outcome = outcome(&state.combatants);
+            continue;
+        }
+        if stunned {
+            events.push(CombatEvent::Skipped {
+                actor: actor_id,
+                reason: "stunned".to_string(),
+            });
+            continue;
+        }
+
+        let action = match planned.get(actor_id.as_str()) {
+            Some(action) => (*action).clone(),
+            None if actor.side == CombatSide::Enemies => match weakest_target(&state.combatants) {
+                Some(target) => CombatActionKind::Attack { target },
+                None => CombatActionKind::Wait,
+            },
+            None => CombatActionKind::Wait,
+        };
+        act(&mut state, turn, action, &mut rng, &mut events)?;
+        state.outcome = outcome(&state.combatants);
+    }
+
+    let narration_prompt = narration_prompt(&state, &events);
+    Ok(CombatRound {
+        round: state.round,
+        state,
+        events,
+        narration_prompt,
+    })
+}
+
+fn act(
+    state: &mut CombatState,
+    turn: usize,
+    action: CombatActionKind,
+    rng: &mut SplitMix64,
+    events: &mut Vec<CombatEvent>,
+) -> Result<(), String> {
+    let actor_id = state.combatants[turn].sheet_id.clone();
+    let find = |state: &CombatState, id: &str| {
+        state
+            .combatants
+            .iter()
+            .position(|c| c.sheet_id == id)
+            .ok_or_else(|| format!("Not in this combat: {}", id))
+    };
+    match action {
+        CombatActionKind::Attack { target } => {
+            let index = find(state, &target)?;
+            if !state.combatants[index].active() {
+                events.push(CombatEvent::Skipped {
+                    actor: actor_id,
+                    reason: format!(
+                        "{} is already out of the fight",
+                        state.combatants[index].name
+                    ),
+                });
+                return Ok(());
+            }
+            let roll = rng.roll(20);
+            let total = roll as i64 + state.combatants[turn].attack_bonus();
+            let defense = state.combatants[index].defense_total();
+            let critical = roll == 20;
+            let hit = critical || (roll != 1 && total >= defense);
+            let die = state.combatants[turn].damage_die as u32;
+            let damage = match (hit, critical) {
+                (false, _) => 0,
+                (true, false) => rng.roll(die) as i64,
+                (true, true) => rng.roll(die) as i64 + rng.roll(die) as i64,
+            };
+            let defender = &mut state.combatants[index];
+            defender.hp = (defender.hp - damage).max(0);
+            events.push(CombatEvent::Attack {
+                actor: actor_id,
+                target: target.clone(),
+                roll,
+                total,
+                defense,
+                hit,
+                critical,
+                damage,
+                target_hp: defender.hp,
+            });
+            if defender.hp == 0 {
+                events.push(CombatEvent::Defeated { target });
+            }
+        }
+        CombatActionKind::Defend => {
+            let actor = &mut state.combatants[turn];
+            actor
+                .effects
+                .retain(|e| !e.name.eq_ignore_ascii_case(DEFENDING));
+            actor.effects.push(StatusEffect {
+                name: DEFENDING.to_string(),
+                rounds: Some(1),
+            });
+            events.push(CombatEvent::Defend { actor: actor_id });
+        }
+        CombatActionKind::Apply { target, effect } => {
+            let index = find(state, &target)?;
+            let name = effect.name.trim().to_string();
+            if name.is_empty() {
+                return Err("Effects need a name".to_string());
+            }
+            let effect = StatusEffect {
+                name,
+                rounds: effect.rounds,
+            };
+            let target_combatant = &mut state.combatants[index];
+            target_combatant
+                .effects
+                .retain(|e| !e.name.eq_ignore_ascii_case(&effect.name));
+            target_combatant.effects.push(effect.clone());
+            events.push(CombatEvent::EffectApplied {
+                actor: actor_id,
+                target,
+                effect,
+            });
+        }
+        CombatActionKind::Flee => {
+            let roll = rng.roll(20);
+            let success = roll as i64 + state.combatants[turn].initiative_bonus >= FLEE_DIFFICULTY;
+            state.combatants[turn].fled = success;
+            events.push(CombatEvent::Fled {
+                actor: actor_id,
+                roll,
+                success,
+            });
+        }
+        CombatActionKind::Wait => events.push(CombatEvent::Skipped {
+            actor: actor_id,
+            reason: "waited".to_string(),
+        }),
+    }
+    Ok(())
+}
+
+fn describe(event: &CombatEvent, name: &dyn Fn(&str) -> String) -> String {
+    match event {
+        CombatEvent::Attack {
+            actor,
+            target,
+            hit,
+            critical,
+            damage,
+            target_hp,
+            ..
+        } => match (hit, critical) {
+            (false, _) => format!("{} attacks {} and misses.", name(actor), name(target)),
+            (true, critical) => format!(
+                "{} {} {} for {} damage ({} HP left).",
+                name(actor),
+                if *critical {
+                    "lands a critical hit on"
+                } else {
+                    "hits"
+                },
+                name(target),
+                damage,
+                target_hp
+            ),
+        },
+        CombatEvent::Defend { actor } => format!("{} takes a defensive stance.", name(actor)),
+        CombatEvent::EffectApplied {
+            actor,
+            target,
+            effect,
+        } => match actor == target {
+            true => format!("{} becomes {}.", name(actor), effect.name),
+            false => format!("{} makes {} {}.", name(actor), name(target), effect.name),
+        },
+        CombatEvent::EffectDamage {
+            target,
+            effect,
+            damage,
+            target_hp,
+        } => format!(
+            "{} takes {} damage from being {} ({} HP left).",
+            name(target),
+            damage,
+            effect,
+            target_hp
+        ),
+        CombatEvent::EffectExpired { target, effect } => {
+            format!("{} is no longer {}.", name(target), effect)
+        }
+        CombatEvent::Skipped { actor, reason } => {
+            format!("{} does not act: {}.", name(actor), reason)
+        }
+        CombatEvent::Fled { actor, success, .. } => match success {
+            true => format!("{} escapes the fight.", name(actor)),
+            false => format!("{} tries to flee but cannot get away.", name(actor)),
+        },
+        CombatEvent::Defeated { target } => format!("{} falls.", name(target)),
+    }
+}
+
+fn narration_prompt(state: &CombatState, events: &[CombatEvent]) -> String {
+    let names: HashMap<&str, &str> = state
+        .combatants
+        .iter()
+        .map(|c| (c.sheet_id.as_str(), c.name.as_str()))
+        .collect();
+    let name = |id: &str| names.get(id).copied().unwrap_or(id).to_string();
+    let mut prompt = format!(
+        "Narrate round {} of the fight in one or two paragraphs. These outcomes are \
+         already decided: describe them in order without changing who hits, how much \
+         harm is done or who falls, and do not add attacks that are not listed.\n",
+        state.round
+    );
+    for event in events {
+        prompt.push_str(&format!("- {}\n", describe(event, &name)));
+    }
+    match state.outcome {
+        Some(CombatOutcome::Victory) => prompt.push_str("The party wins the fight.\n"),
+        Some(CombatOutcome::Defeat) => prompt.push_str("The party is defeated.\n"),
+        Some(CombatOutcome::Escaped) => prompt.push_str("The party escapes.\n"),
+        None => prompt.push_str("The fight goes on.\n"),
+    }
+    prompt
+}
@@ -0,0 +1,523 @@
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+use super::combat::{self, ActiveCombats, CombatAction, CombatRound, CombatState};
+use super::context::{self, StatBlock, StatBlockConfig, STAT_BLOCK_CONFIG_FILE};
+use super::inventory::{self, Inventory, ItemChange, ItemDefinition, StoryInventory};
+use super::quests::{
+    self, NewQuest, Quest, QuestReminderConfig, QuestUpdate, QUEST_REMINDER_CONFIG_FILE,
+};
+use super::recaps::{self, PlaySession, RecapConfig, RECAP_CONFIG_FILE};
+use super::session::{submit_action, GameSession};
+use super::sheet::{self, CharacterSheet};
+use super::spectator::{SpectatorEntry, SpectatorInfo};
+use super::types::{GameConfig, GameEvent, GameStatus, HOST_PLAYER_ID};
+use crate::ai::filter::Strictness;
+use crate::profiles;
+use crate::store;
+use crate::story::lock::LockReason;
+use crate::story::StoryState;
+use crate::sync::commands::{generate_qr_code, get_local_ip};
+use crate::sync::keys::now_ms;
+use crate::sync::SyncState;
+use crate::webhooks::{self, WebhookEvent};
+
+/// Viewers allowed when the host does not choose a limit
+const DEFAULT_MAX_VIEWERS: usize = 10;
+
+/// Hard limit on concurrent viewers
+const MAX_VIEWERS: usize = 50;
+
+/// Start a multiplayer session on the running sync server.
+/// Players join with the same QR code used for sync.
+#[tauri::command]
+pub async fn start_game_session(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    stories: State<'_, StoryState>,
+    config: GameConfig,
+) -> Result<GameStatus, String> {
+    profiles::check_story(&app, &config.story_id).await?;
+    profiles::check_generation(&app, &config.provider.base_url, Strictness::Off)?;
+    let server = state
+        .server_state()
+        .await
+        .ok_or("Start the sync server before hosting a game")?;
+    let mut game = server.game.lock().await;
+    if let Some(previous) = game.take() {
+        previous.broadcast(GameEvent::Ended);
+    }
+    let lock = stories
+        .locks
+        .try_acquire(&config.story_id, LockReason::Collaboration, None)?;
+    webhooks::fire(
+        &app,
+        WebhookEvent::SessionStarted,
+        format!("{} started a multiplayer session", config.host_name),
+        serde_json::json!({ "kind": "game", "storyId": config.story_id }),
+    );
+    let session = GameSession::new(app, config, lock);
+    let status = session.status();
+    *game = Some(session);
+    Ok(status)
+}
+
+/// End the current session and disconnect all players
+#[tauri::command]
+pub async fn end_game_session(state: State<'_, SyncState>) -> Result<(), String> {
+    if let Some(server) = state.server_state().await {
+        if let Some(session) = server.game.lock().await.take() {
+            session.broadcast(GameEvent::Ended);
+        }
+    }
+    Ok(())
+}
+
+/// Get the current session status, if a game is running
+#[tauri::command]
+pub async fn get_game_status(state: State<'_, SyncState>) -> Result<Option<GameStatus>, String> {
+    let Some(server) = state.server_state().await else {
+        return Ok(None);
+    };
+    let status = server.game.lock().await.as_ref().map(|s| s.status());
+    Ok(status)
+}
+
+/// Take the host's turn
+#[tauri::command]
+pub async fn game_submit_action(state: State<'_, SyncState>, action: String) -> Result<(), String> {
+    let server = state
+        .server_state()
+        .await
+        .ok_or("No game session is running")?;
+    submit_action(&server.game, HOST_PLAYER_ID, &action).await
+}
+
+/// Let viewers on the network follow the story read-only.
+/// Viewers get their own token, so they cannot sync or join a game.
+#[tauri::command]
+pub async fn start_spectator_mode(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    title: String,
+    max_viewers: Option<usize>,
+) -> Result<SpectatorInfo, String> {
+    let server = state
+        .server_state()
+        .await
+        .ok_or("Start the sync server before enabling spectator mode")?;
+    let viewer_token = Uuid::new_v4().to_string();
+    let max_viewers = max_viewers
+        .unwrap_or(DEFAULT_MAX_VIEWERS)
+        .clamp(1, MAX_VIEWERS);
+
+    let qr_json = serde_json::json!({
+        "ip": get_local_ip()?,
+        "port": server.port,
+        "viewerToken": viewer_token,
+        "version": app.package_info().version.to_string(),
+    })
+    .to_string();
+    let qr_code_base64 = generate_qr_code(&qr_json)?;
+
+    webhooks::fire(
+        &app,
+        WebhookEvent::SessionStarted,
+        format!("Spectator mode started for \"{}\"", title),
+        serde_json::json!({ "kind": "spectate", "title": title, "maxViewers": max_viewers }),
+    );
+    server
+        .spectators
+        .start(title, viewer_token.clone(), max_viewers)
+        .await;
+    Ok(SpectatorInfo {
+        viewer_token,
+        max_viewers,
+        qr_code_base64,
+    })
+}
+
+/// Disconnect all viewers
+#[tauri::command]
+pub async fn stop_spectator_mode(state: State<'_, SyncState>) -> Result<(), String> {
+    if let Some(server) = state.server_state().await {
+        server.spectators.stop().await;
+    }
+    Ok(())
+}
+
+/// Send a new or edited entry to viewers
+#[tauri::command]
+pub async fn publish_spectator_entry(
+    state: State<'_, SyncState>,
+    entry: SpectatorEntry,
+) -> Result<(), String> {
+    let server = state
+        .server_state()
+        .await
+        .ok_or("Spectator mode is not running")?;
+    server.spectators.publish(entry).await
+}
+
+/// Number of viewers currently connected
+#[tauri::command]
+pub async fn get_spectator_count(state: State<'_, SyncState>) -> Result<usize, String> {
+    Ok(match state.server_state().await {
+        Some(server) => server.spectators.viewer_count(),
+        None => 0,
+    })
+}
+
+#[tauri::command]
+pub async fn list_character_sheets(
+    app: AppHandle,
+    story_id: String,
+) -> Result<Vec<CharacterSheet>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    sheet::list(&app, &story_id)
+}
+
+/// Add a character sheet to a story, or replace the one with the same ID
+#[tauri::command]
+pub async fn save_character_sheet(
+    app: AppHandle,
+    story_id: String,
+    sheet: CharacterSheet,
+) -> Result<CharacterSheet, String> {
+    profiles::check_story(&app, &story_id).await?;
+    sheet::save(&app, &story_id, sheet)
+}
+
+#[tauri::command]
+pub async fn delete_character_sheet(
+    app: AppHandle,
+    story_id: String,
+    sheet_id: String,
+) -> Result<bool, String> {
+    profiles::check_story(&app, &story_id).await?;
+    sheet::delete(&app, &story_id, &sheet_id)
+}
+
+#[tauri::command]
+pub async fn get_stat_block_config(app: AppHandle) -> Result<StatBlockConfig, String> {
+    store::load_json(&app, STAT_BLOCK_CONFIG_FILE)
+}
+
+#[tauri::command]
+pub async fn set_stat_block_config(app: AppHandle, config: StatBlockConfig) -> Result<(), String> {
+    if config.token_budget == 0 {
+        return Err("The token budget must be at least 1".to_string());
+    }
+    store::save_json(&app, STAT_BLOCK_CONFIG_FILE, &config)
+}
+
+/// The stat block as the model would see it for a story
+#[tauri::command]
+pub async fn get_stat_block(app: AppHandle, story_id: String) -> Result<StatBlock, String> {
+    profiles::check_story(&app, &story_id).await?;
+    context::stat_block(&app, &story_id).await
+}
+
+/// Start a fight between character sheets of a story, rolling initiative.
+/// The seed decides every roll; without one the fight gets a fresh seed.
+#[tauri::command]
+pub async fn start_combat(
+    app: AppHandle,
+    combats: State<'_, ActiveCombats>,
+    story_id: String,
+    party: Vec<String>,
+    enemies: Vec<String>,
+    seed: Option<u64>,
+) -> Result<CombatState, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let sheets = sheet::list(&app, &story_id)?;
+    let pick = |ids: &[String]| {
+        ids.iter()
+            .map(|id| {
+                sheets
+                    .iter()
+                    .find(|s| &s.id == id)
+                    .cloned()
+                    .ok_or_else(|| format!("Character sheet not found: {}", id))
+            })
+            .collect::<Result<Vec<_>, String>>()
+    };
+    let state = combat::start(
+        &story_id,
+        &pick(&party)?,
+        &pick(&enemies)?,
+        seed.unwrap_or_else(|| now_ms() as u64),
+    )?;
+    combats
+        .combats
+        .lock()
+        .await
+        .insert(state.id.clone(), state.clone());
+    Ok(state)
+}
+
+/// Resolve one round of a fight. Returns the new state, the rolls and their
+/// results, and a prompt for the model to narrate them. Hit points and
+/// lasting effects are saved back to the sheets; a fight that is over is
+/// let go.
+#[tauri::command]
+pub async fn resolve_combat_round(
+    app: AppHandle,
+    combats: State<'_, ActiveCombats>,
+    combat_id: String,
+    actions: Vec<CombatAction>,
+) -> Result<CombatRound, String> {
+    let mut combats = combats.combats.lock().await;
+    let state = combats
+        .get(&combat_id)
+        .cloned()
+        .ok_or_else(|| format!("Combat not found: {}", combat_id))?;
+    profiles::check_story(&app, &state.story_id).await?;
+    let round = combat::resolve_round(state, &actions)?;
+    let vitals: Vec<_> = round
+        .state
+        .combatants
+        .iter()
+        .map(|c| (c.sheet_id.clone(), c.hp, c.lasting_effects()))
+        .collect();
+    sheet::set_vitals(&app, &round.state.story_id, &vitals)?;
+    match round.state.outcome {
+        Some(_) => combats.remove(&combat_id),
+        None => combats.insert(combat_id, round.state.clone()),
+    };
+    Ok(round)
+}
+
+/// A running fight as it stands
+#[tauri::command]
+pub async fn get_combat(
+    combats: State<'_, ActiveCombats>,
+    combat_id: String,
+) -> Result<Option<CombatState>, String> {
+    Ok(combats.combats.lock().await.get(&combat_id).cloned())
+}
+
+/// Abandon a fight. Sheets keep the hit points and effects of the last
+/// round. Returns whether the fight was running.
+#[tauri::command]
+pub async fn end_combat(
+    combats: State<'_, ActiveCombats>,
+    combat_id: String,
+) -> Result<bool, String> {
+    Ok(combats.combats.lock().await.remove(&combat_id).is_some())
+}
+
+/// A story's item definitions, who holds what, and the transaction history
+#[tauri::command]
+pub async fn get_inventory(app: AppHandle, story_id: String) -> Result<StoryInventory, String> {
+    profiles::check_story(&app, &story_id).await?;
+    inventory::get(&app, &story_id).await
+}
+
+/// Add an item definition to a story, or replace the one with the same ID
+#[tauri::command]
+pub async fn save_item_definition(
+    app: AppHandle,
+    story_id: String,
+    definition: ItemDefinition,
+) -> Result<ItemDefinition, String> {
+    profiles::check_story(&app, &story_id).await?;
+    inventory::save_definition(&app, &story_id, definition).await
+}
+
+#[tauri::command]
+pub async fn delete_item_definition(
+    app: AppHandle,
+    story_id: String,
+    item_id: String,
+) -> Result<bool, String> {
+    profiles::check_story(&app, &story_id).await?;
+    inventory::delete_definition(&app, &story_id, &item_id).await
+}
+
+/// Give a character sheet items. Returns its inventory after the change.
+#[tauri::command]
+pub async fn add_item(
+    app: AppHandle,
+    story_id: String,
+    holder_id: String,
+    item_id: String,
+    quantity: i64,
+    reason: Option<String>,
+) -> Result<Inventory, String> {
+    profiles::check_story(&app, &story_id).await?;
+    inventory::update(&app, &story_id, |story, sheets| {
+        story.add(
+            inventory::holder(sheets, &holder_id)?,
+            ItemChange {
+                item_id: &item_id,
+                quantity,
+                reason,
+            },
+        )
+    })
+    .await
+}
+
+/// Take items from a character sheet, such as when they are used up or lost
+#[tauri::command]
+pub async fn remove_item(
+    app: AppHandle,
+    story_id: String,
+    holder_id: String,
+    item_id: String,
+    quantity: i64,
+    reason: Option<String>,
+) -> Result<Inventory, String> {
+    profiles::check_story(&app, &story_id).await?;
+    inventory::update(&app, &story_id, |story, sheets| {
+        story.remove(
+            inventory::holder(sheets, &holder_id)?,
+            ItemChange {
+                item_id: &item_id,
+                quantity,
+                reason,
+            },
+        )
+    })
+    .await
+}
+
+/// Move items from one character sheet to another. With a price the
+/// receiver buys them. Returns both inventories after the change, giver first.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn transfer_item(
+    app: AppHandle,
+    story_id: String,
+    from_id: String,
+    to_id: String,
+    item_id: String,
+    quantity: i64,
+    price: Option<i64>,
+    reason: Option<String>,
+) -> Result<Vec<Inventory>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let (from, to) = inventory::update(&app, &story_id, |story, sheets| {
+        story.transfer(
+            inventory::holder(sheets, &from_id)?,
+            inventory::holder(sheets, &to_id)?,
+            ItemChange {
+                item_id: &item_id,
+                quantity,
+                reason,
+            },
+            price.unwrap_or(0),
+        )
+    })
+    .await?;
+    Ok(vec![from, to])
+}
+
+/// Add money to a character sheet's purse, or spend it with a negative amount
+#[tauri::command]
+pub async fn adjust_currency(
+    app: AppHandle,
+    story_id: String,
+    holder_id: String,
+    amount: i64,
+    reason: Option<String>,
+) -> Result<Inventory, String> {
+    profiles::check_story(&app, &story_id).await?;
+    inventory::update(&app, &story_id, |story, sheets| {
+        story.adjust_currency(inventory::holder(sheets, &holder_id)?, amount, reason)
+    })
+    .await
+}
+
+/// The story's quests on its current branch
+#[tauri::command]
+pub async fn list_quests(app: AppHandle, story_id: String) -> Result<Vec<Quest>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    quests::list(&app, &story_id).await
+}
+
+/// Add an active quest, optionally linked to the entry that introduced it
+#[tauri::command]
+pub async fn create_quest(
+    app: AppHandle,
+    story_id: String,
+    quest: NewQuest,
+) -> Result<Quest, String> {
+    profiles::check_story(&app, &story_id).await?;
+    quests::create(&app, &story_id, quest).await
+}
+
+/// Change a quest's details, objectives or status
+#[tauri::command]
+pub async fn update_quest(
+    app: AppHandle,
+    story_id: String,
+    quest_id: String,
+    update: QuestUpdate,
+) -> Result<Quest, String> {
+    profiles::check_story(&app, &story_id).await?;
+    quests::update(&app, &story_id, &quest_id, update).await
+}
+
+#[tauri::command]
+pub async fn delete_quest(
+    app: AppHandle,
+    story_id: String,
+    quest_id: String,
+) -> Result<bool, String> {
+    profiles::check_story(&app, &story_id).await?;
+    quests::delete(&app, &story_id, &quest_id).await
+}
+
+#[tauri::command]
+pub async fn get_quest_reminder_config(app: AppHandle) -> Result<QuestReminderConfig, String> {
+    store::load_json(&app, QUEST_REMINDER_CONFIG_FILE)
+}
+
+#[tauri::command]
+pub async fn set_quest_reminder_config(
+    app: AppHandle,
+    config: QuestReminderConfig,
+) -> Result<(), String> {
+    store::save_json(&app, QUEST_REMINDER_CONFIG_FILE, &config)
+}
+
+/// The open-quest reminder as the model would see it for a story
+#[tauri::command]
+pub async fn get_quest_reminder(app: AppHandle, story_id: String) -> Result<String, String> {
+    profiles::check_story(&app, &story_id).await?;
+    quests::reminder(&app, &story_id).await
+}
+
+/// The story's finished play sessions with their recaps, newest first
+#[tauri::command]
+pub async fn get_session_recaps(
+    app: AppHandle,
+    story_id: String,
+) -> Result<Vec<PlaySession>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    recaps::recaps(&app, &story_id)
+}
+
+/// End the story's play session now and write its recap. Returns nothing
+/// when no session was running or nothing was written in it.
+#[tauri::command]
+pub async fn end_play_session(
+    app: AppHandle,
+    story_id: String,
+) -> Result<Option<PlaySession>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    recaps::end(&app, &story_id).await
+}
+
+#[tauri::command]
+pub async fn get_recap_config(app: AppHandle) -> Result<RecapConfig, String> {
+    store::load_json(&app, RECAP_CONFIG_FILE)
+}
+
+#[tauri::command]
+pub async fn set_recap_config(app: AppHandle, config: RecapConfig) -> Result<(), String> {
+    recaps::validate(&config)?;
+    store::save_json(&app, RECAP_CONFIG_FILE, &config)
+}
@@ -0,0 +1,394 @@
+//! Game state for the model. Character sheets, what each character
+//! carries and has given up, and the inventory are compiled into a compact
+//! stat block, kept within a token budget, that is added to the system
+//! prompt of multiplayer sessions and of streamed story requests, so the
+//! narration stays true to the numbers.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+use super::inventory::{self, StoryInventory};
+use super::sheet::{self, CharacterSheet};
+use crate::ai::types::ChatMessage;
+use crate::profiles::{self, database};
+use crate::store;
+
+/// Stat block settings, in the app data directory
+pub const STAT_BLOCK_CONFIG_FILE: &str = "stat_block.json";
+
+/// Rough size of a token in English text, for budgeting
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Placeholders a line template may use
+const PLACEHOLDERS: [&str; 9] = [
+    "{name}",
+    "{hp}",
+    "{maxHp}",
+    "{stats}",
+    "{effects}",
+    "{items}",
+    "{currency}",
+    "{gone}",
+    "{inventory}",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StatBlockConfig {
+    pub enabled: bool,
+    /// Add the block to streamed requests for stories with character
+    /// sheets, not only to multiplayer sessions
+    pub in_story_requests: bool,
+    pub token_budget: usize,
+    /// First line of the block
+    pub header: String,
+    /// One line per sheet, with `{name}`, `{hp}`, `{maxHp}`, `{stats}`,
+    /// `{effects}`, `{items}`, `{currency}` and `{gone}`, the items the
+    /// character gave up. Sections are separated by `|`, and a section whose
+    /// placeholders are all empty is left out.
+    pub sheet_template: String,
+    /// The inventory line, with `{inventory}`
+    pub inventory_template: String,
+}
+
+impl Default for StatBlockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            in_story_requests: true,
+            token_budget: 200,
+            header: "[GAME STATE]".to_string(),
+            sheet_template: "{name} | HP {hp}/{maxHp} | {stats} | Effects: {effects} \
+                             | Carries: {items} | Coin: {currency} | No longer has: {gone}"
+                .to_string(),
+            inventory_template: "Inventory: {inventory}".to_string(),
+        }
+    }
+}
+
+/// A compiled stat block
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatBlock {
+    /// Empty when there is nothing to report or the block is turned off
+    pub text: String,
+    /// Estimated
+    pub tokens: usize,
+    pub sheets: usize,
+    /// Sheets and inventory items that did not fit the budget
+    pub omitted: usize,
+}
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Fill a template. Sections, separated by `|`, whose placeholders all came
+/// out empty are dropped along with their labels.
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    let value = |placeholder: &str| {
+        values
+            .iter()
+            .find(|(p, _)| *p == placeholder)
+            .map_or("", |(_, v)| v.as_str())
+    };
+    template
+        .split('|')
+        .filter_map(|section| {
+            let used: Vec<&str> = PLACEHOLDERS
+                .iter()
+                .copied()
+                .filter(|p| section.contains(p))
+                .collect();
+            if !used.is_empty() && used.iter().all(|p| value(p).is_empty()) {
+                return None;
+            }
+            let mut section = section.trim().to_string();
+            for placeholder in used {
+                section = section.replace(placeholder, value(placeholder));
+            }
+            Some(section).filter(|s| !s.is_empty())
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Items a character gave up that are listed, most recent first
+const MAX_GONE: usize = 5;
+
+fn sheet_line(
+    config: &StatBlockConfig,
+    sheet: &CharacterSheet,
+    inventory: &StoryInventory,
+    names: &HashMap<&str, &str>,
+) -> String {
+    let stats = sheet
+        .stats
+        .iter()
+        .map(|(name, value)| format!("{} {}", name, value))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let effects = sheet
+        .effects
+        .iter()
+        .map(|e| match e.rounds {
+            Some(1) => format!("{} (1 round)", e.name),
+            Some(rounds) => format!("{} ({} rounds)", e.name, rounds),
+            None => e.name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let held = inventory.inventory(&sheet.id);
+    let items = held
+        .map(|held| {
+            held.items
+                .iter()
+                .filter_map(|stack| {
+                    let name = &inventory.definition(&stack.item_id).ok()?.name;
+                    Some(match stack.quantity {
+                        1 => name.clone(),
+                        quantity => format!("{} x{}", name, quantity),
+                    })
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+    let currency = held.map(|h| h.currency.to_string()).unwrap_or_default();
+    let gone = inventory
+        .given_up(&sheet.id, names)
+        .into_iter()
+        .take(MAX_GONE)
+        .map(|(name, how)| format!("{} ({})", name, how))
+        .collect::<Vec<_>>()
+        .join(", ");
+    fill(
+        &config.sheet_template,
+        &[
+            ("{name}", sheet.name.clone()),
+            ("{hp}", sheet.hp.to_string()),
+            ("{maxHp}", sheet.max_hp.to_string()),
+            ("{stats}", stats),
+            ("{effects}", effects),
+            ("{items}", items),
+            ("{currency}", currency),
+            ("{gone}", gone),
+        ],
+    )
+}
+
+/// An item as the stat block lists it
+#[derive(Debug, Clone)]
+pub struct CarriedItem {
+    pub name: String,
+    pub quantity: i64,
+    pub equipped: bool,
+}
+
+impl CarriedItem {
+    fn label(&self) -> String {
+        let mut label = self.name.clone();
+        if self.quantity > 1 {
+            label = format!("{} x{}", label, self.quantity);
+        }
+        if self.equipped {
+            label.push_str(" (equipped)");
+        }
+        label
+    }
+}
+
+/// Compile sheets, with what they hold, and items into a block within the
+/// budget. Sheets come in the order given and the inventory last; whatever
+/// does not fit is left out, equipped items last.
+pub fn compile(
+    config: &StatBlockConfig,
+    sheets: &[CharacterSheet],
+    inventory: &StoryInventory,
+    items: &[CarriedItem],
+) -> StatBlock {
+    if !config.enabled || (sheets.is_empty() && items.is_empty()) {
+        return StatBlock::default();
+    }
+    let mut lines = vec![config.header.trim().to_string()];
+    let mut used = estimate_tokens(&lines[0]);
+    let mut block = StatBlock::default();
+    let names: HashMap<&str, &str> = sheets
+        .iter()
+        .map(|s| (s.id.as_str(), s.name.as_str()))
+        .collect();
+    for sheet in sheets {
+        let line = sheet_line(config, sheet, inventory, &names);
+        let tokens = estimate_tokens(&line) + 1;
+        if used + tokens > config.token_budget {
+            block.omitted += 1;
+            continue;
+        }
+        used += tokens;
+        lines.push(line);
+        block.sheets += 1;
+    }
+
+    let mut items: Vec<&CarriedItem> = items.iter().collect();
+    items.sort_by_key(|i| !i.equipped);
+    let mut carried: Vec<String> = Vec::new();
+    for item in &items {
+        let mut candidate = carried.clone();
+        candidate.push(item.label());
+        let line = fill(
+            &config.inventory_template,
+            &[("{inventory}", candidate.join(", "))],
+        );
+        if used + estimate_tokens(&line) + 1 > config.token_budget {
+            break;
+        }
+        carried = candidate;
+    }
+    block.omitted += items.len() - carried.len();
+    if !carried.is_empty() {
+        let line = fill(
+            &config.inventory_template,
+            &[("{inventory}", carried.join(", "))],
+        );
+        used += estimate_tokens(&line) + 1;
+        lines.push(line);
+    }
+
+    if lines.len() == 1 {
+        return StatBlock {
+            omitted: block.omitted,
+            ..StatBlock::default()
+        };
+    }
+    block.text = lines.join("\n");
+    block.tokens = used;
+    block
+}
+
+/// ID and relationship of a character on the story's current branch
+type CharacterRow = (String, Option<String>);
+
+/// The story's characters and carried items on its current branch
+async fn world(
+    app: &AppHandle,
+    story_id: &str,
+) -> Result<(Vec<CharacterRow>, Vec<CarriedItem>), String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let read = async {
+        let characters: Vec<CharacterRow> = sqlx::query_as(
+            "SELECT c.id, c.relationship FROM characters c JOIN stories s ON s.id = c.story_id \
+             WHERE c.story_id = ? AND c.branch_id IS s.current_branch_id",
+        )
+        .bind(story_id)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| format!("Failed to read characters: {}", e))?;
+        let items: Vec<(String, Option<i64>, Option<bool>)> = sqlx::query_as(
+            "SELECT i.name, i.quantity, i.equipped FROM items i JOIN stories s ON s.id = i.story_id \
+             WHERE i.story_id = ? AND i.location = 'inventory' \
+             AND i.branch_id IS s.current_branch_id ORDER BY i.name",
+        )
+        .bind(story_id)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| format!("Failed to read items: {}", e))?;
+        Ok::<_, String>((characters, items))
+    }
+    .await;
+    pool.close().await;
+    let (characters, items) = read?;
+    let items = items
+        .into_iter()
+        .map(|(name, quantity, equipped)| CarriedItem {
+            name,
+            quantity: quantity.unwrap_or(1),
+            equipped: equipped.unwrap_or(false),
+        })
+        .collect();
+    Ok((characters, items))
+}
+
+/// The story's stat block. Sheets of the story's characters on its current
+/// branch come first, the player character's leading, then sheets that
+/// belong to no character; sheets of characters on other branches are
+/// left out.
+pub async fn stat_block(app: &AppHandle, story_id: &str) -> Result<StatBlock, String> {
+    let config: StatBlockConfig = store::load_json(app, STAT_BLOCK_CONFIG_FILE)?;
+    if !config.enabled {
+        return Ok(StatBlock::default());
+    }
+    let sheets = sheet::list(app, story_id)?;
+    if sheets.is_empty() {
+        return Ok(StatBlock::default());
+    }
+    let inventory = inventory::load(app, story_id, &sheets).await?;
+    let (characters, items) = world(app, story_id).await?;
+    let rank = |sheet: &CharacterSheet| match &sheet.character_id {
+        Some(id) => characters
+            .iter()
+            .find(|(c, _)| c == id)
+            .map(|(_, relationship)| match relationship.as_deref() {
+                Some("self") => 0,
+                _ => 1,
+            }),
+        None => Some(2),
+    };
+    let mut ranked: Vec<(u8, CharacterSheet)> = sheets
+        .into_iter()
+        .filter_map(|sheet| Some((rank(&sheet)?, sheet)))
+        .collect();
+    ranked.sort_by_key(|(rank, _)| *rank);
+    let sheets: Vec<CharacterSheet> = ranked.into_iter().map(|(_, sheet)| sheet).collect();
+    Ok(compile(&config, &sheets, &inventory, &items))
+}
+
+/// The stat block a streamed story request carries: empty when the story
+/// has no character sheets or the block is kept to multiplayer sessions
+pub async fn request_stat_block(app: &AppHandle, story_id: &str) -> Result<String, String> {
+    let config: StatBlockConfig = store::load_json(app, STAT_BLOCK_CONFIG_FILE)?;
+    if !config.in_story_requests {
+        return Ok(String::new());
+    }
+    Ok(stat_block(app, story_id).await?.text)
+}
+
+/// Put context that sets the scene, such as a recap, at the top of the
+/// system prompt, or ahead of the conversation when there is none
+pub fn inject_first(messages: &mut Vec<ChatMessage>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match messages.iter_mut().find(|m| m.role == "system") {
+        Some(system) => {
+            system.content = format!("{}\n\n{}", text, system.content.trim_start());
+        }
+        None => messages.insert(
+            0,
+            ChatMessage {
+                role: "system".to_string(),
+                content: text.to_string(),
+            },
+        ),
+    }
+}
+
+/// Add game context, such as a stat block, to the system prompt, or ahead
+/// of the conversation when there is none
+pub fn inject(messages: &mut Vec<ChatMessage>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match messages.iter_mut().find(|m| m.role == "system") {
+        Some(system) => {
+            system.content = format!("{}\n\n{}", system.content.trim_end(), text);
+        }
+        None => messages.insert(
+            0,
+            ChatMessage {
+                role: "system".to_string(),
+                content: text.to_string(),
+            },
+        ),
+    }
+}
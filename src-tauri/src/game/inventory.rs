@@ -0,0 +1,740 @@
+//! Inventories and money. A story's items are its item rows on the current
+//! branch; each row's metadata records which character sheets hold how many
+//! and the transactions that moved them, so holdings travel with the story
+//! like the items do. Purses are kept on the sheets. Every change goes
+//! through a transaction that is checked and recorded, so what a character
+//! owns and what they gave up can both be told to the model.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use super::sheet::{self, CharacterSheet};
+use crate::profiles::{self, database};
+use crate::sync::keys::now_ms;
+
+/// Transactions kept per item and per purse, newest last
+const MAX_HISTORY: usize = 200;
+
+const MAX_NAME_CHARS: usize = 100;
+
+/// State managed by Tauri for inventories
+#[derive(Default)]
+pub struct InventoryState {
+    /// Serializes changes to item rows and purses
+    pub(crate) writes: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemDefinition {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Worth in currency, as a guide for prices
+    #[serde(default)]
+    pub value: i64,
+    /// At most one exists in the story, held by one character at a time
+    #[serde(default)]
+    pub unique: bool,
+}
+
+impl ItemDefinition {
+    fn validate(&mut self) -> Result<(), String> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err("Items need a name".to_string());
+        }
+        if self.name.chars().count() > MAX_NAME_CHARS {
+            return Err(format!(
+                "Names can be at most {} characters",
+                MAX_NAME_CHARS
+            ));
+        }
+        if self.value < 0 {
+            return Err("Item value can't be negative".to_string());
+        }
+        self.description = self.description.trim().to_string();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemStack {
+    pub item_id: String,
+    pub quantity: i64,
+}
+
+/// What one character sheet holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inventory {
+    pub holder_id: String,
+    #[serde(default)]
+    pub items: Vec<ItemStack>,
+    #[serde(default)]
+    pub currency: i64,
+}
+
+impl Inventory {
+    fn new(holder_id: &str) -> Self {
+        Self {
+            holder_id: holder_id.to_string(),
+            items: Vec::new(),
+            currency: 0,
+        }
+    }
+
+    pub fn quantity(&self, item_id: &str) -> i64 {
+        self.items
+            .iter()
+            .find(|s| s.item_id == item_id)
+            .map_or(0, |s| s.quantity)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionKind {
+    Add,
+    Remove,
+    Transfer,
+    Currency,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    pub id: String,
+    pub kind: TransactionKind,
+    /// The sheet that gained, lost or gave away
+    pub holder_id: String,
+    /// The receiving sheet of a transfer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_id: Option<String>,
+    #[serde(default)]
+    pub quantity: i64,
+    /// Change to the holder's purse; for a transfer, the price the receiver
+    /// paid the holder
+    #[serde(default)]
+    pub currency: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub at: i64,
+}
+
+/// A character sheet's money and the changes to it that moved no items
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Purse {
+    pub balance: i64,
+    pub history: Vec<Transaction>,
+}
+
+/// What an item's row records in its metadata besides the frontend's keys
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ItemRecord {
+    value: i64,
+    unique: bool,
+    /// Quantity held by each character sheet
+    holders: BTreeMap<String, i64>,
+    history: Vec<Transaction>,
+}
+
+/// A story's items, who holds what, and how it got there
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StoryInventory {
+    pub definitions: Vec<ItemDefinition>,
+    pub inventories: Vec<Inventory>,
+    pub history: Vec<Transaction>,
+}
+
+/// Item, quantity and reason of a change to an inventory
+pub struct ItemChange<'a> {
+    pub item_id: &'a str,
+    pub quantity: i64,
+    pub reason: Option<String>,
+}
+
+impl StoryInventory {
+    pub fn definition(&self, item_id: &str) -> Result<&ItemDefinition, String> {
+        self.definitions
+            .iter()
+            .find(|d| d.id == item_id)
+            .ok_or_else(|| format!("Item not found: {}", item_id))
+    }
+
+    pub fn inventory(&self, holder_id: &str) -> Option<&Inventory> {
+        self.inventories.iter().find(|i| i.holder_id == holder_id)
+    }
+
+    fn inventory_mut(&mut self, holder_id: &str) -> &mut Inventory {
+        match self
+            .inventories
+            .iter()
+            .position(|i| i.holder_id == holder_id)
+        {
+            Some(index) => &mut self.inventories[index],
+            None => {
+                self.inventories.push(Inventory::new(holder_id));
+                self.inventories.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// The holder's inventory as it stands, empty if they never had one
+    fn snapshot(&self, holder_id: &str) -> Inventory {
+        self.inventory(holder_id)
+            .cloned()
+            .unwrap_or_else(|| Inventory::new(holder_id))
+    }
+
+    fn record(&mut self, transaction: Transaction) {
+        self.history.push(transaction);
+    }
+
+    /// What the item's row records: its definition's extras, holders and
+    /// the latest transactions that moved it
+    fn item_record(&self, definition: &ItemDefinition) -> ItemRecord {
+        ItemRecord {
+            value: definition.value,
+            unique: definition.unique,
+            holders: self
+                .inventories
+                .iter()
+                .map(|i| (i.holder_id.clone(), i.quantity(&definition.id)))
+                .filter(|(_, quantity)| *quantity > 0)
+                .collect(),
+            history: latest(
+                self.history
+                    .iter()
+                    .filter(|t| t.item_id.as_deref() == Some(definition.id.as_str())),
+            ),
+        }
+    }
+
+    /// The sheet's purse: its money and the changes that moved no items
+    fn purse(&self, holder_id: &str) -> Purse {
+        Purse {
+            balance: self.inventory(holder_id).map_or(0, |i| i.currency),
+            history: latest(
+                self.history
+                    .iter()
+                    .filter(|t| t.item_id.is_none() && t.holder_id == holder_id),
+            ),
+        }
+    }
+
+    fn put(&mut self, holder_id: &str, item_id: &str, quantity: i64) -> Result<(), String> {
+        let definition = self.definition(item_id)?;
+        if definition.unique {
+            if quantity > 1 {
+                return Err(format!("There is only one {}", definition.name));
+            }
+            if self.inventories.iter().any(|i| i.quantity(item_id) > 0) {
+                return Err(format!("{} is already held", definition.name));
+            }
+        }
+        let inventory = self.inventory_mut(holder_id);
+        match inventory.items.iter_mut().find(|s| s.item_id == item_id) {
+            Some(stack) => {
+                stack.quantity = stack
+                    .quantity
+                    .checked_add(quantity)
+                    .ok_or_else(|| "That is too many items".to_string())?;
+            }
+            None => inventory.items.push(ItemStack {
+                item_id: item_id.to_string(),
+                quantity,
+            }),
+        }
+        Ok(())
+    }
+
+    fn take(
+        &mut self,
+        holder: &CharacterSheet,
+        item_id: &str,
+        quantity: i64,
+    ) -> Result<(), String> {
+        let name = self.definition(item_id)?.name.clone();
+        let held = self
+            .inventory(&holder.id)
+            .map_or(0, |i| i.quantity(item_id));
+        if held < quantity {
+            return Err(match held {
+                0 => format!("{} has no {}", holder.name, name),
+                held => format!("{} has only {} {}", holder.name, held, name),
+            });
+        }
+        let inventory = self.inventory_mut(&holder.id);
+        for stack in &mut inventory.items {
+            if stack.item_id == item_id {
+                stack.quantity -= quantity;
+            }
+        }
+        inventory.items.retain(|s| s.quantity > 0);
+        Ok(())
+    }
+
+    fn pay(&mut self, holder: &CharacterSheet, amount: i64) -> Result<(), String> {
+        let inventory = self.inventory_mut(&holder.id);
+        let balance = inventory
+            .currency
+            .checked_add(amount)
+            .ok_or_else(|| "That is too much money".to_string())?;
+        if balance < 0 {
+            return Err(format!(
+                "{} has only {} to spend",
+                holder.name, inventory.currency
+            ));
+        }
+        inventory.currency = balance;
+        Ok(())
+    }
+
+    /// Give a character items. Returns their inventory after the change.
+    pub fn add(
+        &mut self,
+        holder: &CharacterSheet,
+        change: ItemChange,
+    ) -> Result<Inventory, String> {
+        check_quantity(change.quantity)?;
+        self.put(&holder.id, change.item_id, change.quantity)?;
+        self.record(transaction(TransactionKind::Add, &holder.id, &change));
+        Ok(self.snapshot(&holder.id))
+    }
+
+    /// Take items from a character, who must have enough of them
+    pub fn remove(
+        &mut self,
+        holder: &CharacterSheet,
+        change: ItemChange,
+    ) -> Result<Inventory, String> {
+        check_quantity(change.quantity)?;
+        self.take(holder, change.item_id, change.quantity)?;
+        self.record(transaction(TransactionKind::Remove, &holder.id, &change));
+        Ok(self.snapshot(&holder.id))
+    }
+
+    /// Move items between characters. With a price the receiver buys them,
+    /// and must be able to afford it. Nothing changes unless all of it can.
+    pub fn transfer(
+        &mut self,
+        from: &CharacterSheet,
+        to: &CharacterSheet,
+        change: ItemChange,
+        price: i64,
+    ) -> Result<(Inventory, Inventory), String> {
+        check_quantity(change.quantity)?;
+        if from.id == to.id {
+            return Err("Items can't be transferred to their holder".to_string());
+        }
+        if price < 0 {
+            return Err("Prices can't be negative".to_string());
+        }
+        let mut next = self.clone();
+        next.take(from, change.item_id, change.quantity)?;
+        next.put(&to.id, change.item_id, change.quantity)?;
+        if price > 0 {
+            next.pay(to, -price)?;
+            next.pay(from, price)?;
+        }
+        let mut record = transaction(TransactionKind::Transfer, &from.id, &change);
+        record.to_id = Some(to.id.clone());
+        record.currency = price;
+        next.record(record);
+        *self = next;
+        Ok((self.snapshot(&from.id), self.snapshot(&to.id)))
+    }
+
+    /// Add to or, with a negative amount, spend from a character's purse,
+    /// which can't go below zero
+    pub fn adjust_currency(
+        &mut self,
+        holder: &CharacterSheet,
+        amount: i64,
+        reason: Option<String>,
+    ) -> Result<Inventory, String> {
+        if amount == 0 {
+            return Err("The amount can't be zero".to_string());
+        }
+        self.pay(holder, amount)?;
+        self.record(Transaction {
+            id: Uuid::new_v4().to_string(),
+            kind: TransactionKind::Currency,
+            holder_id: holder.id.clone(),
+            to_id: None,
+            item_id: None,
+            quantity: 0,
+            currency: amount,
+            reason,
+            at: now_ms(),
+        });
+        Ok(self.snapshot(&holder.id))
+    }
+
+    /// Items the holder gave up and doesn't have again, newest first, each
+    /// with how it went: "sold to Mira", "given to Mira" or the reason it
+    /// was removed
+    pub fn given_up(&self, holder_id: &str, names: &HashMap<&str, &str>) -> Vec<(String, String)> {
+        let held = self.inventory(holder_id);
+        let mut gone: Vec<(String, String)> = Vec::new();
+        for record in self.history.iter().rev() {
+            if record.holder_id != holder_id {
+                continue;
+            }
+            let Some(item_id) = &record.item_id else {
+                continue;
+            };
+            if held.is_some_and(|i| i.quantity(item_id) > 0) {
+                continue;
+            }
+            let Ok(definition) = self.definition(item_id) else {
+                continue;
+            };
+            if gone.iter().any(|(name, _)| *name == definition.name) {
+                continue;
+            }
+            let receiver = record
+                .to_id
+                .as_deref()
+                .map(|id| names.get(id).copied().unwrap_or("someone"));
+            let how = match (record.kind, receiver) {
+                (TransactionKind::Transfer, Some(to)) if record.currency > 0 => {
+                    format!("sold to {}", to)
+                }
+                (TransactionKind::Transfer, Some(to)) => format!("given to {}", to),
+                (TransactionKind::Remove, _) => {
+                    record.reason.clone().unwrap_or_else(|| "lost".to_string())
+                }
+                _ => continue,
+            };
+            gone.push((definition.name.clone(), how));
+        }
+        gone
+    }
+}
+
+fn check_quantity(quantity: i64) -> Result<(), String> {
+    if quantity < 1 {
+        return Err("The quantity must be at least 1".to_string());
+    }
+    Ok(())
+}
+
+fn transaction(kind: TransactionKind, holder_id: &str, change: &ItemChange) -> Transaction {
+    Transaction {
+        id: Uuid::new_v4().to_string(),
+        kind,
+        holder_id: holder_id.to_string(),
+        to_id: None,
+        item_id: Some(change.item_id.to_string()),
+        quantity: change.quantity,
+        currency: 0,
+        reason: change
+            .reason
+            .as_deref()
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(String::from),
+        at: now_ms(),
+    }
+}
+
+fn latest<'a>(history: impl Iterator<Item = &'a Transaction>) -> Vec<Transaction> {
+    let history: Vec<Transaction> = history.cloned().collect();
+    let excess = history.len().saturating_sub(MAX_HISTORY);
+    history[excess..].to_vec()
+}
+
+/// ID, name, description and metadata of an item row
+type ItemRow = (String, String, Option<String>, Option<String>);
+
+fn parse_record(metadata: Option<&str>) -> ItemRecord {
+    metadata
+        .and_then(|m| serde_json::from_str(m).ok())
+        .unwrap_or_default()
+}
+
+/// Write the record into a row's metadata, keeping the keys the frontend set
+fn merge_record(metadata: Option<&str>, record: &ItemRecord) -> Result<String, String> {
+    let mut metadata: Map<String, Value> = metadata
+        .and_then(|m| serde_json::from_str(m).ok())
+        .unwrap_or_default();
+    if let Value::Object(fields) =
+        serde_json::to_value(record).map_err(|e| format!("Failed to serialize inventory: {}", e))?
+    {
+        metadata.extend(fields);
+    }
+    serde_json::to_string(&metadata).map_err(|e| format!("Failed to serialize inventory: {}", e))
+}
+
+/// A story's items, inventories and history, from its item rows on the
+/// current branch and the purses of its sheets
+pub async fn load(
+    app: &AppHandle,
+    story_id: &str,
+    sheets: &[CharacterSheet],
+) -> Result<StoryInventory, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let rows: Result<Vec<ItemRow>, String> = sqlx::query_as(
+        "SELECT i.id, i.name, i.description, i.metadata FROM items i \
+         JOIN stories s ON s.id = i.story_id \
+         WHERE i.story_id = ? AND i.branch_id IS s.current_branch_id ORDER BY i.name",
+    )
+    .bind(story_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| format!("Failed to read items: {}", e));
+    pool.close().await;
+
+    let mut inventory = StoryInventory::default();
+    for (id, name, description, metadata) in rows? {
+        let record = parse_record(metadata.as_deref());
+        for (holder_id, quantity) in record.holders {
+            if quantity > 0 {
+                inventory.inventory_mut(&holder_id).items.push(ItemStack {
+                    item_id: id.clone(),
+                    quantity,
+                });
+            }
+        }
+        inventory.history.extend(record.history);
+        inventory.definitions.push(ItemDefinition {
+            id,
+            name,
+            description: description.unwrap_or_default(),
+            value: record.value,
+            unique: record.unique,
+        });
+    }
+    for sheet in sheets {
+        if sheet.purse != Purse::default() {
+            inventory.inventory_mut(&sheet.id).currency = sheet.purse.balance;
+            inventory
+                .history
+                .extend(sheet.purse.history.iter().cloned());
+        }
+    }
+    inventory.history.sort_by_key(|t| t.at);
+    Ok(inventory)
+}
+
+/// A story's items, inventories and history
+pub async fn get(app: &AppHandle, story_id: &str) -> Result<StoryInventory, String> {
+    let sheets = sheet::list(app, story_id)?;
+    load(app, story_id, &sheets).await
+}
+
+/// Write the item rows that changed between `before` and `after`: new
+/// definitions become rows on the story's current branch, deleted ones are
+/// removed
+async fn save_rows(
+    app: &AppHandle,
+    story_id: &str,
+    before: &StoryInventory,
+    after: &StoryInventory,
+) -> Result<(), String> {
+    let changed: Vec<(&ItemDefinition, ItemRecord)> = after
+        .definitions
+        .iter()
+        .map(|d| (d, after.item_record(d)))
+        .filter(|(d, record)| {
+            before
+                .definition(&d.id)
+                .ok()
+                .is_none_or(|old| old != *d || before.item_record(old) != *record)
+        })
+        .collect();
+    let deleted: Vec<&str> = before
+        .definitions
+        .iter()
+        .filter(|d| after.definition(&d.id).is_err())
+        .map(|d| d.id.as_str())
+        .collect();
+    if changed.is_empty() && deleted.is_empty() {
+        return Ok(());
+    }
+
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let saved = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        for (definition, record) in &changed {
+            let row: Option<(Option<String>,)> =
+                sqlx::query_as("SELECT metadata FROM items WHERE id = ? AND story_id = ?")
+                    .bind(&definition.id)
+                    .bind(story_id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|e| format!("Failed to read item: {}", e))?;
+            let description = Some(definition.description.as_str()).filter(|d| !d.is_empty());
+            match row {
+                Some((metadata,)) => {
+                    sqlx::query(
+                        "UPDATE items SET name = ?, description = ?, metadata = ? WHERE id = ?",
+                    )
+                    .bind(&definition.name)
+                    .bind(description)
+                    .bind(merge_record(metadata.as_deref(), record)?)
+                    .bind(&definition.id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| format!("Failed to update item: {}", e))?;
+                }
+                None => {
+                    sqlx::query(
+                        "INSERT INTO items (id, story_id, name, description, quantity, \
+                         equipped, location, metadata, branch_id) \
+                         SELECT ?, id, ?, ?, 1, 0, NULL, ?, current_branch_id \
+                         FROM stories WHERE id = ?",
+                    )
+                    .bind(&definition.id)
+                    .bind(&definition.name)
+                    .bind(description)
+                    .bind(merge_record(None, record)?)
+                    .bind(story_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| format!("Failed to add item: {}", e))?;
+                }
+            }
+        }
+        for item_id in &deleted {
+            sqlx::query("DELETE FROM items WHERE id = ? AND story_id = ?")
+                .bind(item_id)
+                .bind(story_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to delete item: {}", e))?;
+        }
+        sqlx::query("UPDATE stories SET updated_at = ? WHERE id = ?")
+            .bind(now_ms())
+            .bind(story_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to update story: {}", e))?;
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to save items: {}", e))
+    }
+    .await;
+    pool.close().await;
+    saved
+}
+
+/// Read, change and write a story's inventory. The change gets the story's
+/// character sheets to check holders against. Item rows are written before
+/// purses, so a failed write leaves money where it was.
+pub async fn update<T>(
+    app: &AppHandle,
+    story_id: &str,
+    change: impl FnOnce(&mut StoryInventory, &[CharacterSheet]) -> Result<T, String>,
+) -> Result<T, String> {
+    let state = app.state::<InventoryState>();
+    let _write = state.writes.lock().await;
+    let sheets = sheet::list(app, story_id)?;
+    let before = load(app, story_id, &sheets).await?;
+    let mut after = before.clone();
+    let result = change(&mut after, &sheets)?;
+    save_rows(app, story_id, &before, &after).await?;
+    let purses: Vec<(String, Purse)> = sheets
+        .iter()
+        .map(|s| (s.id.clone(), after.purse(&s.id)))
+        .filter(|(id, purse)| *purse != before.purse(id))
+        .collect();
+    if !purses.is_empty() {
+        sheet::set_purses(app, story_id, &purses)?;
+    }
+    Ok(result)
+}
+
+/// The sheet with the given ID, to hold items
+pub fn holder<'a>(sheets: &'a [CharacterSheet], id: &str) -> Result<&'a CharacterSheet, String> {
+    sheets
+        .iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| format!("Character sheet not found: {}", id))
+}
+
+/// Add a definition, or replace the one with the same ID
+pub async fn save_definition(
+    app: &AppHandle,
+    story_id: &str,
+    mut definition: ItemDefinition,
+) -> Result<ItemDefinition, String> {
+    definition.validate()?;
+    if definition.id.trim().is_empty() {
+        definition.id = Uuid::new_v4().to_string();
+    }
+    update(app, story_id, |inventory, _| {
+        if inventory
+            .definitions
+            .iter()
+            .any(|d| d.id != definition.id && d.name.eq_ignore_ascii_case(&definition.name))
+        {
+            return Err(format!(
+                "There is already an item named {}",
+                definition.name
+            ));
+        }
+        if definition.unique
+            && inventory
+                .inventories
+                .iter()
+                .map(|i| i.quantity(&definition.id))
+                .sum::<i64>()
+                > 1
+        {
+            return Err(format!(
+                "{} can't be unique while more than one is held",
+                definition.name
+            ));
+        }
+        match inventory
+            .definitions
+            .iter_mut()
+            .find(|d| d.id == definition.id)
+        {
+            Some(existing) => *existing = definition.clone(),
+            None => inventory.definitions.push(definition.clone()),
+        }
+        Ok(())
+    })
+    .await?;
+    Ok(definition)
+}
+
+/// Delete a definition nobody holds. Returns whether it existed.
+pub async fn delete_definition(
+    app: &AppHandle,
+    story_id: &str,
+    item_id: &str,
+) -> Result<bool, String> {
+    update(app, story_id, |inventory, _| {
+        let Ok(definition) = inventory.definition(item_id) else {
+            return Ok(false);
+        };
+        if inventory
+            .inventories
+            .iter()
+            .any(|i| i.quantity(item_id) > 0)
+        {
+            return Err(format!("{} is still held", definition.name));
+        }
+        inventory.definitions.retain(|d| d.id != item_id);
+        Ok(true)
+    })
+    .await
+}
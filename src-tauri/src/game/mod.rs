@@ -0,0 +1,16 @@
+pub mod combat;
+pub mod commands;
+pub mod context;
+pub mod inventory;
+pub mod quests;
+pub mod recaps;
+pub mod server;
+pub mod session;
+pub mod sheet;
+pub mod spectator;
+pub mod types;
+
+pub use combat::ActiveCombats;
+pub use inventory::InventoryState;
+pub use recaps::RecapState;
+pub use sheet::SheetState;
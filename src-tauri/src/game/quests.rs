@@ -0,0 +1,494 @@
+//! Quests: story beats of the quest type, with objectives and the entries
+//! where they were taken up and resolved. Objectives and entry links live
+//! in the beat's metadata, so quests travel with the story like any other
+//! beat. Open quests are recalled to the model so long campaigns don't
+//! drop their threads.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sqlx::{Sqlite, Transaction};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+use crate::profiles::{self, database};
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Quest reminder settings, in the app data directory
+pub const QUEST_REMINDER_CONFIG_FILE: &str = "quest_reminders.json";
+
+const MAX_TITLE_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct QuestReminderConfig {
+    pub enabled: bool,
+    /// Open quests recalled at most, most recently taken up first
+    pub max_quests: usize,
+    /// First line of the reminder
+    pub header: String,
+}
+
+impl Default for QuestReminderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_quests: 5,
+            header: "[OPEN OBJECTIVES]".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QuestStatus {
+    /// Known but not taken up yet
+    Pending,
+    Active,
+    Completed,
+    Failed,
+}
+
+impl QuestStatus {
+    pub fn is_open(self) -> bool {
+        matches!(self, Self::Pending | Self::Active)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Active => "active",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn parse(status: Option<&str>) -> Self {
+        match status {
+            Some("active") => Self::Active,
+            Some("completed") => Self::Completed,
+            Some("failed") => Self::Failed,
+            _ => Self::Pending,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuestObjective {
+    pub text: String,
+    #[serde(default)]
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quest {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub status: QuestStatus,
+    pub objectives: Vec<QuestObjective>,
+    /// The story entry where the quest was taken up
+    pub introduced_entry_id: Option<String>,
+    /// The story entry where it was completed or failed
+    pub resolved_entry_id: Option<String>,
+    pub triggered_at: Option<i64>,
+    pub resolved_at: Option<i64>,
+    pub branch_id: Option<String>,
+    /// The rest of the beat's metadata, kept as it was
+    #[serde(skip)]
+    metadata: Map<String, Value>,
+}
+
+impl Quest {
+    fn metadata(&self) -> String {
+        let mut metadata = self.metadata.clone();
+        metadata.insert("objectives".to_string(), json!(self.objectives));
+        for (key, id) in [
+            ("introducedEntryId", &self.introduced_entry_id),
+            ("resolvedEntryId", &self.resolved_entry_id),
+        ] {
+            match id {
+                Some(id) => metadata.insert(key.to_string(), json!(id)),
+                None => metadata.remove(key),
+            };
+        }
+        Value::Object(metadata).to_string()
+    }
+
+    /// Objectives left, or the title alone when there are none
+    fn reminder(&self) -> String {
+        let open: Vec<&str> = self
+            .objectives
+            .iter()
+            .filter(|o| !o.done)
+            .map(|o| o.text.as_str())
+            .collect();
+        if open.is_empty() {
+            format!("- {}", self.title)
+        } else {
+            format!("- {}: {}", self.title, open.join("; "))
+        }
+    }
+}
+
+/// A new quest. It starts active.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewQuest {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub objectives: Vec<String>,
+    /// The story entry where it was taken up
+    #[serde(default)]
+    pub entry_id: Option<String>,
+}
+
+/// Changes to a quest; fields left out stay as they are
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct QuestUpdate {
+    pub title: Option<String>,
+    /// An empty description clears it
+    pub description: Option<String>,
+    pub objectives: Option<Vec<QuestObjective>>,
+    pub status: Option<QuestStatus>,
+    /// The story entry where the status changed. Completing or failing a
+    /// quest links it as where the quest was resolved, taking it up links
+    /// it as where the quest was introduced if nothing is yet.
+    pub entry_id: Option<String>,
+}
+
+fn clean_title(title: &str) -> Result<String, String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("Quests need a title".to_string());
+    }
+    if title.chars().count() > MAX_TITLE_CHARS {
+        return Err(format!(
+            "Titles can be at most {} characters",
+            MAX_TITLE_CHARS
+        ));
+    }
+    Ok(title.to_string())
+}
+
+fn clean_description(description: Option<String>) -> Option<String> {
+    description
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty())
+}
+
+fn clean_objectives(objectives: Vec<QuestObjective>) -> Vec<QuestObjective> {
+    objectives
+        .into_iter()
+        .map(|o| QuestObjective {
+            text: o.text.trim().to_string(),
+            done: o.done,
+        })
+        .filter(|o| !o.text.is_empty())
+        .collect()
+}
+
+/// Move a quest to a status, stamping and linking the change
+fn set_status(quest: &mut Quest, status: QuestStatus, entry_id: Option<String>, now: i64) {
+    match status {
+        QuestStatus::Completed | QuestStatus::Failed => {
+            if quest.status != status {
+                quest.resolved_at = Some(now);
+                quest.resolved_entry_id = entry_id;
+            } else if entry_id.is_some() {
+                quest.resolved_entry_id = entry_id;
+            }
+        }
+        QuestStatus::Active | QuestStatus::Pending => {
+            quest.resolved_at = None;
+            quest.resolved_entry_id = None;
+            if status == QuestStatus::Active {
+                quest.triggered_at.get_or_insert(now);
+                if quest.introduced_entry_id.is_none() {
+                    quest.introduced_entry_id = entry_id;
+                }
+            }
+        }
+    }
+    quest.status = status;
+}
+
+/// ID, title, description, status, triggered and resolved times, metadata
+/// and branch of a quest beat
+type QuestRow = (
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<i64>,
+    Option<i64>,
+    Option<String>,
+    Option<String>,
+);
+
+const QUEST_COLUMNS: &str = "b.id, b.title, b.description, b.status, b.triggered_at, \
+                             b.resolved_at, b.metadata, b.branch_id";
+
+fn from_row(row: QuestRow) -> Quest {
+    let (id, title, description, status, triggered_at, resolved_at, metadata, branch_id) = row;
+    let mut metadata: Map<String, Value> = metadata
+        .and_then(|m| serde_json::from_str(&m).ok())
+        .unwrap_or_default();
+    let objectives = metadata
+        .remove("objectives")
+        .and_then(|o| serde_json::from_value(o).ok())
+        .unwrap_or_default();
+    let mut link = |key: &str| match metadata.remove(key) {
+        Some(Value::String(id)) => Some(id),
+        _ => None,
+    };
+    Quest {
+        introduced_entry_id: link("introducedEntryId"),
+        resolved_entry_id: link("resolvedEntryId"),
+        id,
+        title,
+        description,
+        status: QuestStatus::parse(status.as_deref()),
+        objectives,
+        triggered_at,
+        resolved_at,
+        branch_id,
+        metadata,
+    }
+}
+
+async fn check_entry(
+    tx: &mut Transaction<'_, Sqlite>,
+    story_id: &str,
+    entry_id: Option<&str>,
+) -> Result<(), String> {
+    let Some(entry_id) = entry_id else {
+        return Ok(());
+    };
+    let found: Option<(String,)> =
+        sqlx::query_as("SELECT id FROM story_entries WHERE id = ? AND story_id = ?")
+            .bind(entry_id)
+            .bind(story_id)
+            .fetch_optional(&mut **tx)
+            .await
+            .map_err(|e| format!("Failed to read story entries: {}", e))?;
+    match found {
+        Some(_) => Ok(()),
+        None => Err(format!("Story entry not found: {}", entry_id)),
+    }
+}
+
+async fn touch_story(
+    tx: &mut Transaction<'_, Sqlite>,
+    story_id: &str,
+    now: i64,
+) -> Result<(), String> {
+    sqlx::query("UPDATE stories SET updated_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(story_id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| format!("Failed to update story: {}", e))?;
+    Ok(())
+}
+
+/// The story's quests on its current branch, in the order they were added
+pub async fn list(app: &AppHandle, story_id: &str) -> Result<Vec<Quest>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let rows: Result<Vec<QuestRow>, String> = sqlx::query_as(&format!(
+        "SELECT {} FROM story_beats b JOIN stories s ON s.id = b.story_id \
+         WHERE b.story_id = ? AND b.type = 'quest' AND b.branch_id IS s.current_branch_id \
+         ORDER BY b.rowid",
+        QUEST_COLUMNS
+    ))
+    .bind(story_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| format!("Failed to read quests: {}", e));
+    pool.close().await;
+    Ok(rows?.into_iter().map(from_row).collect())
+}
+
+/// Add an active quest on the story's current branch
+pub async fn create(app: &AppHandle, story_id: &str, new: NewQuest) -> Result<Quest, String> {
+    let now = now_ms();
+    let mut quest = Quest {
+        id: Uuid::new_v4().to_string(),
+        title: clean_title(&new.title)?,
+        description: clean_description(new.description),
+        status: QuestStatus::Pending,
+        objectives: clean_objectives(
+            new.objectives
+                .into_iter()
+                .map(|text| QuestObjective { text, done: false })
+                .collect(),
+        ),
+        introduced_entry_id: None,
+        resolved_entry_id: None,
+        triggered_at: None,
+        resolved_at: None,
+        branch_id: None,
+        metadata: Map::new(),
+    };
+    set_status(&mut quest, QuestStatus::Active, new.entry_id, now);
+
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let created = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        let branch: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT current_branch_id FROM stories WHERE id = ?")
+                .bind(story_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to read story: {}", e))?;
+        let (branch_id,) = branch.ok_or_else(|| format!("Story not found: {}", story_id))?;
+        quest.branch_id = branch_id;
+        check_entry(&mut tx, story_id, quest.introduced_entry_id.as_deref()).await?;
+        sqlx::query(
+            "INSERT INTO story_beats (id, story_id, title, description, type, status, \
+             triggered_at, resolved_at, metadata, branch_id) \
+             VALUES (?, ?, ?, ?, 'quest', ?, ?, ?, ?, ?)",
+        )
+        .bind(&quest.id)
+        .bind(story_id)
+        .bind(&quest.title)
+        .bind(&quest.description)
+        .bind(quest.status.as_str())
+        .bind(quest.triggered_at)
+        .bind(quest.resolved_at)
+        .bind(quest.metadata())
+        .bind(&quest.branch_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to add quest: {}", e))?;
+        touch_story(&mut tx, story_id, now).await?;
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to save quest: {}", e))?;
+        Ok(quest)
+    }
+    .await;
+    pool.close().await;
+    created
+}
+
+/// Change a quest, returning it as stored
+pub async fn update(
+    app: &AppHandle,
+    story_id: &str,
+    quest_id: &str,
+    update: QuestUpdate,
+) -> Result<Quest, String> {
+    let title = update.title.as_deref().map(clean_title).transpose()?;
+    let now = now_ms();
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let updated = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        let row: Option<QuestRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM story_beats b WHERE b.id = ? AND b.story_id = ? AND b.type = 'quest'",
+            QUEST_COLUMNS
+        ))
+        .bind(quest_id)
+        .bind(story_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to read quest: {}", e))?;
+        let mut quest = from_row(row.ok_or_else(|| format!("Quest not found: {}", quest_id))?);
+        check_entry(&mut tx, story_id, update.entry_id.as_deref()).await?;
+
+        if let Some(title) = title {
+            quest.title = title;
+        }
+        if update.description.is_some() {
+            quest.description = clean_description(update.description);
+        }
+        if let Some(objectives) = update.objectives {
+            quest.objectives = clean_objectives(objectives);
+        }
+        if let Some(status) = update.status {
+            set_status(&mut quest, status, update.entry_id, now);
+        }
+
+        sqlx::query(
+            "UPDATE story_beats SET title = ?, description = ?, status = ?, triggered_at = ?, \
+             resolved_at = ?, metadata = ? WHERE id = ?",
+        )
+        .bind(&quest.title)
+        .bind(&quest.description)
+        .bind(quest.status.as_str())
+        .bind(quest.triggered_at)
+        .bind(quest.resolved_at)
+        .bind(quest.metadata())
+        .bind(&quest.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to update quest: {}", e))?;
+        touch_story(&mut tx, story_id, now).await?;
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to save quest: {}", e))?;
+        Ok(quest)
+    }
+    .await;
+    pool.close().await;
+    updated
+}
+
+/// Returns whether the quest existed
+pub async fn delete(app: &AppHandle, story_id: &str, quest_id: &str) -> Result<bool, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let result =
+        sqlx::query("DELETE FROM story_beats WHERE id = ? AND story_id = ? AND type = 'quest'")
+            .bind(quest_id)
+            .bind(story_id)
+            .execute(&pool)
+            .await
+            .map_err(|e| format!("Failed to delete quest: {}", e));
+    pool.close().await;
+    Ok(result?.rows_affected() > 0)
+}
+
+/// Reminder of open quests and their objectives left. Active quests come
+/// before pending ones, the most recently taken up first.
+pub fn compile_reminder(config: &QuestReminderConfig, quests: &[Quest]) -> String {
+    if !config.enabled {
+        return String::new();
+    }
+    let mut open: Vec<&Quest> = quests.iter().filter(|q| q.status.is_open()).collect();
+    open.sort_by_key(|q| {
+        (
+            q.status != QuestStatus::Active,
+            std::cmp::Reverse(q.triggered_at),
+        )
+    });
+    let lines: Vec<String> = open
+        .into_iter()
+        .take(config.max_quests)
+        .map(Quest::reminder)
+        .collect();
+    if lines.is_empty() {
+        return String::new();
+    }
+    format!("{}\n{}", config.header.trim(), lines.join("\n"))
+}
+
+/// The story's open-quest reminder, empty when there is nothing to recall or
+/// reminders are off
+pub async fn reminder(app: &AppHandle, story_id: &str) -> Result<String, String> {
+    let config: QuestReminderConfig = store::load_json(app, QUEST_REMINDER_CONFIG_FILE)?;
+    if !config.enabled || config.max_quests == 0 {
+        return Ok(String::new());
+    }
+    Ok(compile_reminder(&config, &list(app, story_id).await?))
+}
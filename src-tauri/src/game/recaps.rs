@@ -0,0 +1,413 @@
+//! Play sessions and their recaps. A story's session starts with the first
+//! save, generation or game action and ends after a stretch of inactivity
+//! or when the player ends it. Running sessions are written down, so one
+//! cut short by the app closing is ended on the next launch. The entries
+//! written during the session are then summed up by the story's AI profile
+//! as a short "previously on" recap, announced with `session://ended`, kept
+//! with the session and optionally put at the top of the next session's
+//! context.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::ai::profile::story_provider;
+use crate::ai::proxy::complete_chat;
+use crate::ai::types::{ChatMessage, SamplingParams};
+use crate::store;
+use crate::story::rows;
+use crate::story::text::plain_text;
+use crate::sync::keys::now_ms;
+
+/// Recap settings, in the app data directory
+pub const RECAP_CONFIG_FILE: &str = "session_recaps.json";
+
+/// Finished sessions of every story, in the app data directory
+pub const PLAY_SESSIONS_FILE: &str = "play_sessions.json";
+
+/// Running sessions, in the app data directory
+pub const ACTIVE_SESSIONS_FILE: &str = "active_sessions.json";
+
+/// Sessions kept per story, newest last
+const MAX_SESSIONS: usize = 100;
+
+/// Session text sent to the model, in characters. Longer sessions are sent
+/// from their end, which matters most for what comes next.
+const MAX_SOURCE_CHARS: usize = 10_000;
+
+const MAX_IDLE_MINUTES: u32 = 24 * 60;
+
+/// Entries this much older than a session still belong to it, since the
+/// player's action is saved just before the generation that starts it
+const START_GRACE_MS: i64 = 2 * 60_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RecapConfig {
+    /// Track sessions and write recaps at all
+    pub enabled: bool,
+    /// Minutes without a save or generation that end a session
+    pub idle_minutes: u32,
+    /// Put the latest recap at the top of the story's context
+    pub include_in_context: bool,
+    /// Length the model is asked to keep to
+    pub max_words: usize,
+    /// First line of the recap in the context
+    pub header: String,
+}
+
+impl Default for RecapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_minutes: 30,
+            include_in_context: true,
+            max_words: 120,
+            header: "[PREVIOUSLY]".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionEnd {
+    /// Nothing happened for the configured idle time
+    Idle,
+    /// The player ended the session
+    Command,
+    /// The app closed while the session was running
+    Closed,
+}
+
+/// A finished play session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaySession {
+    pub id: String,
+    pub story_id: String,
+    pub started_at: i64,
+    /// Last save or generation in the session
+    pub last_activity_at: i64,
+    pub ended_at: i64,
+    pub ended_by: SessionEnd,
+    /// Entries written during the session
+    pub entry_count: usize,
+    pub recap: Option<String>,
+    /// Why no recap was written, such as a missing AI profile
+    #[serde(default)]
+    pub recap_error: Option<String>,
+}
+
+/// Finished sessions keyed by story ID
+type PlaySessions = HashMap<String, Vec<PlaySession>>;
+
+/// A running session as written down
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenSession {
+    id: String,
+    started_at: i64,
+    last_activity_at: i64,
+}
+
+struct ActiveSession {
+    open: OpenSession,
+    /// Ends the session once the idle time has passed
+    timer: JoinHandle<()>,
+}
+
+/// Write down the running sessions
+fn save_active(app: &AppHandle, active: &HashMap<String, ActiveSession>) {
+    let open: HashMap<&String, &OpenSession> = active.iter().map(|(id, s)| (id, &s.open)).collect();
+    if let Err(e) = store::save_json(app, ACTIVE_SESSIONS_FILE, &open) {
+        eprintln!("Failed to save play sessions: {}", e);
+    }
+}
+
+/// State managed by Tauri for play sessions
+#[derive(Default)]
+pub struct RecapState {
+    /// Running sessions keyed by story ID
+    active: Mutex<HashMap<String, ActiveSession>>,
+    /// Serializes changes to the sessions file
+    writes: std::sync::Mutex<()>,
+}
+
+/// Check recap settings before they are saved
+pub fn validate(config: &RecapConfig) -> Result<(), String> {
+    if config.idle_minutes == 0 || config.idle_minutes > MAX_IDLE_MINUTES {
+        return Err(format!(
+            "Sessions end after 1 to {} idle minutes",
+            MAX_IDLE_MINUTES
+        ));
+    }
+    if config.max_words == 0 {
+        return Err("Recaps need at least one word".to_string());
+    }
+    Ok(())
+}
+
+/// Note activity on a story, starting a session if none is running and
+/// pushing back the end of the running one
+pub async fn touch(app: &AppHandle, story_id: &str) -> Result<(), String> {
+    let config: RecapConfig = store::load_json(app, RECAP_CONFIG_FILE)?;
+    if !config.enabled {
+        return Ok(());
+    }
+    let state = app.state::<RecapState>();
+    let mut active = state.active.lock().await;
+    let now = now_ms();
+    let (id, started_at) = match active.remove(story_id) {
+        Some(session) => {
+            session.timer.abort();
+            (session.open.id, session.open.started_at)
+        }
+        None => (Uuid::new_v4().to_string(), now),
+    };
+    let timer = tokio::spawn(end_when_idle(
+        app.clone(),
+        story_id.to_string(),
+        id.clone(),
+        Duration::from_secs(u64::from(config.idle_minutes.clamp(1, MAX_IDLE_MINUTES)) * 60),
+    ));
+    active.insert(
+        story_id.to_string(),
+        ActiveSession {
+            open: OpenSession {
+                id,
+                started_at,
+                last_activity_at: now,
+            },
+            timer,
+        },
+    );
+    save_active(app, &active);
+    Ok(())
+}
+
+async fn end_when_idle(app: AppHandle, story_id: String, session_id: String, idle: Duration) {
+    tokio::time::sleep(idle).await;
+    let session = {
+        let state = app.state::<RecapState>();
+        let mut active = state.active.lock().await;
+        if active.get(&story_id).map(|s| s.open.id.as_str()) != Some(session_id.as_str()) {
+            return;
+        }
+        let session = active.remove(&story_id);
+        save_active(&app, &active);
+        session
+    };
+    if let Some(session) = session {
+        if let Err(e) = finish(&app, &story_id, session.open, SessionEnd::Idle).await {
+            eprintln!("Failed to record play session: {}", e);
+        }
+    }
+}
+
+/// End the story's running session now. Returns the recorded session, or
+/// None when no session was running or nothing was written in it.
+pub async fn end(app: &AppHandle, story_id: &str) -> Result<Option<PlaySession>, String> {
+    let session = {
+        let state = app.state::<RecapState>();
+        let mut active = state.active.lock().await;
+        let session = active.remove(story_id);
+        save_active(app, &active);
+        session
+    };
+    match session {
+        Some(session) => {
+            session.timer.abort();
+            finish(app, story_id, session.open, SessionEnd::Command).await
+        }
+        None => Ok(None),
+    }
+}
+
+/// End the sessions that were running when the app last closed
+pub fn resume(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let open: HashMap<String, OpenSession> = match store::load_json(&app, ACTIVE_SESSIONS_FILE)
+        {
+            Ok(open) => open,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        if open.is_empty() {
+            return;
+        }
+        {
+            let state = app.state::<RecapState>();
+            let active = state.active.lock().await;
+            save_active(&app, &active);
+        }
+        for (story_id, session) in open {
+            if let Err(e) = finish(&app, &story_id, session, SessionEnd::Closed).await {
+                eprintln!("Failed to record play session: {}", e);
+            }
+        }
+    });
+}
+
+/// Write the recap and store the session, unless nothing was written in it
+async fn finish(
+    app: &AppHandle,
+    story_id: &str,
+    session: OpenSession,
+    ended_by: SessionEnd,
+) -> Result<Option<PlaySession>, String> {
+    let export = rows::load(app, story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let mut entries: Vec<_> = export
+        .entries
+        .iter()
+        .filter(|e| e.created_at >= session.started_at - START_GRACE_MS)
+        .filter(|e| e.entry_type != "system" && e.entry_type != "retry")
+        .collect();
+    if entries.is_empty() {
+        return Ok(None);
+    }
+    entries.sort_by_key(|e| e.position);
+    let passages: Vec<String> = entries
+        .iter()
+        .map(|e| plain_text(&e.content))
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let config: RecapConfig = store::load_json(app, RECAP_CONFIG_FILE)?;
+    let previous = recaps(app, story_id)?.into_iter().find_map(|s| s.recap);
+    let (recap, recap_error) = match write_recap(
+        app,
+        story_id,
+        &passages,
+        previous.as_deref(),
+        config.max_words.max(1),
+    )
+    .await
+    {
+        Ok(recap) => (Some(recap), None),
+        Err(e) => (None, Some(e)),
+    };
+
+    let recorded = PlaySession {
+        id: session.id,
+        story_id: story_id.to_string(),
+        started_at: session.started_at,
+        last_activity_at: session.last_activity_at,
+        ended_at: now_ms(),
+        ended_by,
+        entry_count: entries.len(),
+        recap,
+        recap_error,
+    };
+    {
+        let state = app.state::<RecapState>();
+        let _write = state
+            .writes
+            .lock()
+            .map_err(|_| "Play sessions are unavailable".to_string())?;
+        let mut sessions: PlaySessions = store::load_json(app, PLAY_SESSIONS_FILE)?;
+        let story = sessions.entry(story_id.to_string()).or_default();
+        story.push(recorded.clone());
+        let excess = story.len().saturating_sub(MAX_SESSIONS);
+        story.drain(..excess);
+        store::save_json(app, PLAY_SESSIONS_FILE, &sessions)?;
+    }
+    let _ = app.emit("session://ended", &recorded);
+    Ok(Some(recorded))
+}
+
+/// The end of the session's text, as much as fits in `budget` characters
+fn session_text(passages: &[String], budget: usize) -> String {
+    let mut used = 0;
+    let mut kept = Vec::new();
+    for passage in passages.iter().rev() {
+        let len = passage.chars().count();
+        if used + len > budget {
+            if kept.is_empty() {
+                let skip = len - budget;
+                kept.push(format!(
+                    "…{}",
+                    passage.chars().skip(skip).collect::<String>()
+                ));
+            }
+            break;
+        }
+        used += len + 2;
+        kept.push(passage.clone());
+    }
+    kept.reverse();
+    kept.join("\n\n")
+}
+
+async fn write_recap(
+    app: &AppHandle,
+    story_id: &str,
+    passages: &[String],
+    previous: Option<&str>,
+    max_words: usize,
+) -> Result<String, String> {
+    let provider = story_provider(app, story_id).await?;
+    let mut source = String::new();
+    if let Some(previous) = previous {
+        source.push_str(&format!("Recap of the session before:\n{}\n\n", previous));
+    }
+    source.push_str("This session:\n");
+    source.push_str(&session_text(passages, MAX_SOURCE_CHARS));
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: format!(
+                "You write the \"previously on\" recap read at the start of the next \
+                 session of an interactive story. In at most {} words of plain prose, \
+                 recount what happened this session: the key events, decisions, \
+                 discoveries and where the characters were left. Use the earlier \
+                 recap only for context. No headings, lists or commentary.",
+                max_words
+            ),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: source,
+        },
+    ];
+    let sampling = SamplingParams {
+        temperature: Some(0.5),
+        ..Default::default()
+    };
+    let recap = complete_chat(&provider, &messages, &sampling).await?;
+    let recap = recap.trim();
+    if recap.is_empty() {
+        return Err("The model returned an empty recap".to_string());
+    }
+    Ok(recap.to_string())
+}
+
+/// The story's finished sessions, newest first
+pub fn recaps(app: &AppHandle, story_id: &str) -> Result<Vec<PlaySession>, String> {
+    let mut sessions: PlaySessions = store::load_json(app, PLAY_SESSIONS_FILE)?;
+    let mut story = sessions.remove(story_id).unwrap_or_default();
+    story.reverse();
+    Ok(story)
+}
+
+/// The latest recap under the configured header, or nothing when recaps
+/// stay out of the context
+pub fn context_text(app: &AppHandle, story_id: &str) -> Result<String, String> {
+    let config: RecapConfig = store::load_json(app, RECAP_CONFIG_FILE)?;
+    if !config.enabled || !config.include_in_context {
+        return Ok(String::new());
+    }
+    Ok(recaps(app, story_id)?
+        .into_iter()
+        .find_map(|s| s.recap)
+        .map(|recap| format!("{}\n{}", config.header, recap))
+        .unwrap_or_default())
+}
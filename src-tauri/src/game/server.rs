@@ -0,0 +1,154 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+
+use super::session::{submit_action, SharedGame};
+use super::types::{
+    ActionRequest, GameEvent, GameStatus, JoinRequest, JoinResponse, PlayerCredentials,
+};
+use crate::sync::server::ServerState;
+
+/// Routes for multiplayer sessions, merged into the sync router
+pub fn routes() -> Router<ServerState> {
+    Router::new()
+        .route("/game/join", post(handle_join))
+        .route("/game/action", post(handle_action))
+        .route("/game/leave", post(handle_leave))
+        .route("/game/status", get(handle_status))
+        .route("/game/ws", get(handle_socket))
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(serde_json::json!({ "error": message.into() }))).into_response()
+}
+
+/// Check credentials against the running session
+async fn authorize(game: &SharedGame, credentials: &PlayerCredentials) -> Result<(), Response> {
+    match game.lock().await.as_ref() {
+        None => Err(error(StatusCode::NOT_FOUND, "No game session is running")),
+        Some(session) if session.authenticate(&credentials.player_id, &credentials.secret) => {
+            Ok(())
+        }
+        Some(_) => Err(error(
+            StatusCode::UNAUTHORIZED,
+            "Invalid player credentials",
+        )),
+    }
+}
+
+async fn handle_join(
+    State(state): State<ServerState>,
+    Json(request): Json<JoinRequest>,
+) -> Response {
+    if request.token != state.token {
+        return error(StatusCode::UNAUTHORIZED, "Invalid authentication token");
+    }
+    let mut guard = state.game.lock().await;
+    let Some(session) = guard.as_mut() else {
+        return error(StatusCode::NOT_FOUND, "No game session is running");
+    };
+    match session.join(&request.name) {
+        Ok((player_id, secret)) => Json(JoinResponse {
+            player_id,
+            secret,
+            status: session.status(),
+        })
+        .into_response(),
+        Err(message) => error(StatusCode::CONFLICT, message),
+    }
+}
+
+async fn handle_action(
+    State(state): State<ServerState>,
+    Json(request): Json<ActionRequest>,
+) -> Response {
+    if let Err(response) = authorize(&state.game, &request.credentials).await {
+        return response;
+    }
+    match submit_action(&state.game, &request.credentials.player_id, &request.action).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(message) => error(StatusCode::CONFLICT, message),
+    }
+}
+
+async fn handle_leave(
+    State(state): State<ServerState>,
+    Json(credentials): Json<PlayerCredentials>,
+) -> Response {
+    if let Err(response) = authorize(&state.game, &credentials).await {
+        return response;
+    }
+    if let Some(session) = state.game.lock().await.as_mut() {
+        session.leave(&credentials.player_id);
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn handle_status(
+    State(state): State<ServerState>,
+    Query(credentials): Query<PlayerCredentials>,
+) -> Response {
+    if let Err(response) = authorize(&state.game, &credentials).await {
+        return response;
+    }
+    match state.game.lock().await.as_ref() {
+        Some(session) => Json::<GameStatus>(session.status()).into_response(),
+        None => error(StatusCode::NOT_FOUND, "No game session is running"),
+    }
+}
+
+/// Upgrade to a WebSocket that receives every `GameEvent` as JSON text
+async fn handle_socket(
+    State(state): State<ServerState>,
+    Query(credentials): Query<PlayerCredentials>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if let Err(response) = authorize(&state.game, &credentials).await {
+        return response;
+    }
+    let Some(events) = state.game.lock().await.as_ref().map(|s| s.subscribe()) else {
+        return error(StatusCode::NOT_FOUND, "No game session is running");
+    };
+    ws.on_upgrade(move |socket| forward_events(socket, events))
+}
+
+async fn forward_events(
+    mut socket: WebSocket,
+    mut events: tokio::sync::broadcast::Receiver<GameEvent>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    // The client fell behind; it can refetch /game/status
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                let ended = matches!(event, GameEvent::Ended);
+                let Ok(text) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if socket.send(Message::Text(text.into())).await.is_err() || ended {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                // Players only listen on the socket; stop when they disconnect
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
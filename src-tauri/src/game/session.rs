@@ -0,0 +1,275 @@
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::{broadcast, Mutex};
+use uuid::Uuid;
+
+use super::types::{
+    GameConfig, GameEntry, GameEntryKind, GameEvent, GameStatus, Player, HOST_PLAYER_ID,
+};
+use super::{context, quests, recaps};
+use crate::ai::filter;
+use crate::ai::proxy::complete_chat;
+use crate::ai::types::ChatMessage;
+use crate::story::lock::StoryLockGuard;
+use crate::{profiles, style};
+
+/// Events buffered per WebSocket before slow clients start missing them
+const EVENT_BUFFER: usize = 64;
+
+/// The running session, shared between the Tauri commands and the HTTP handlers
+pub type SharedGame = Arc<Mutex<Option<GameSession>>>;
+
+struct PlayerSlot {
+    player: Player,
+    secret: String,
+}
+
+/// Turn-based multiplayer session hosted on the sync server
+pub struct GameSession {
+    app: AppHandle,
+    config: GameConfig,
+    players: Vec<PlayerSlot>,
+    /// Index into `players` of whoever acts next
+    turn: usize,
+    generating: bool,
+    entries: Vec<GameEntry>,
+    events: broadcast::Sender<GameEvent>,
+    /// Keeps the story locked for co-editing until the session is dropped
+    _lock: StoryLockGuard,
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+impl GameSession {
+    /// Create a session with the host in the first player slot
+    pub fn new(app: AppHandle, config: GameConfig, lock: StoryLockGuard) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let host = PlayerSlot {
+            player: Player {
+                id: HOST_PLAYER_ID.to_string(),
+                name: config.host_name.clone(),
+            },
+            secret: Uuid::new_v4().to_string(),
+        };
+        Self {
+            app,
+            config,
+            players: vec![host],
+            turn: 0,
+            generating: false,
+            entries: Vec::new(),
+            events,
+            _lock: lock,
+        }
+    }
+
+    pub fn status(&self) -> GameStatus {
+        GameStatus {
+            story_id: self.config.story_id.clone(),
+            players: self.players.iter().map(|s| s.player.clone()).collect(),
+            current_player_id: self.current_player().map(|p| p.id.clone()),
+            generating: self.generating,
+            entries: self.entries.clone(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GameEvent> {
+        self.events.subscribe()
+    }
+
+    /// Send an event to every WebSocket and to the host window
+    pub fn broadcast(&self, event: GameEvent) {
+        let _ = self.app.emit("game://event", &event);
+        let _ = self.events.send(event);
+    }
+
+    fn current_player(&self) -> Option<&Player> {
+        self.players.get(self.turn).map(|s| &s.player)
+    }
+
+    /// Add a player and return their ID and secret
+    pub fn join(&mut self, name: &str) -> Result<(String, String), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Player name cannot be empty".to_string());
+        }
+        if self.players.iter().any(|s| s.player.name == name) {
+            return Err(format!("Name already taken: {}", name));
+        }
+
+        let player = Player {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+        };
+        let secret = Uuid::new_v4().to_string();
+        self.players.push(PlayerSlot {
+            player: player.clone(),
+            secret: secret.clone(),
+        });
+        let id = player.id.clone();
+        self.broadcast(GameEvent::PlayerJoined { player });
+        Ok((id, secret))
+    }
+
+    /// Remove a player, keeping the turn with whoever was due to act
+    pub fn leave(&mut self, player_id: &str) {
+        let Some(index) = self.players.iter().position(|s| s.player.id == player_id) else {
+            return;
+        };
+        if player_id == HOST_PLAYER_ID {
+            return;
+        }
+        self.players.remove(index);
+        if index < self.turn {
+            self.turn -= 1;
+        }
+        if self.turn >= self.players.len() {
+            self.turn = 0;
+        }
+        self.broadcast(GameEvent::PlayerLeft {
+            player_id: player_id.to_string(),
+        });
+        if let Some(player) = self.current_player() {
+            let player_id = player.id.clone();
+            self.broadcast(GameEvent::TurnStarted { player_id });
+        }
+    }
+
+    pub fn authenticate(&self, player_id: &str, secret: &str) -> bool {
+        self.players
+            .iter()
+            .any(|s| s.player.id == player_id && s.secret == secret)
+    }
+
+    /// Record a player's action if it is their turn, returning the generation request
+    fn accept_action(&mut self, player_id: &str, action: &str) -> Result<Vec<ChatMessage>, String> {
+        if self.generating {
+            return Err("The story is still being written".to_string());
+        }
+        let current = self.current_player().ok_or("No players in session")?;
+        if current.id != player_id {
+            return Err(format!("It is {}'s turn", current.name));
+        }
+        let action = action.trim();
+        if action.is_empty() {
+            return Err("Action cannot be empty".to_string());
+        }
+
+        let entry = GameEntry {
+            id: Uuid::new_v4().to_string(),
+            kind: GameEntryKind::Action,
+            player_id: Some(player_id.to_string()),
+            content: format!("{}: {}", current.name, action),
+            created_at: now_ms(),
+        };
+        self.entries.push(entry.clone());
+        self.generating = true;
+        self.broadcast(GameEvent::Entry { entry });
+        Ok(self.prompt())
+    }
+
+    /// System prompt followed by the most recent shared entries
+    fn prompt(&self) -> Vec<ChatMessage> {
+        let skip = self.entries.len().saturating_sub(self.config.history_limit);
+        let mut messages = vec![ChatMessage {
+            role: "system".to_string(),
+            content: self.config.system_prompt.clone(),
+        }];
+        messages.extend(self.entries[skip..].iter().map(|e| {
+            ChatMessage {
+                role: match e.kind {
+                    GameEntryKind::Action => "user",
+                    GameEntryKind::Narration => "assistant",
+                }
+                .to_string(),
+                content: e.content.clone(),
+            }
+        }));
+        messages
+    }
+
+    /// Store the narration (or failure) and pass the turn on
+    fn finish_turn(&mut self, result: Result<String, String>) {
+        self.generating = false;
+        match result {
+            Ok(content) => {
+                let entry = GameEntry {
+                    id: Uuid::new_v4().to_string(),
+                    kind: GameEntryKind::Narration,
+                    player_id: None,
+                    content,
+                    created_at: now_ms(),
+                };
+                self.entries.push(entry.clone());
+                self.broadcast(GameEvent::Entry { entry });
+                self.turn = (self.turn + 1) % self.players.len().max(1);
+            }
+            Err(message) => {
+                // The action stays in the transcript; the same player may try again
+                self.broadcast(GameEvent::GenerationFailed { message });
+            }
+        }
+        if let Some(player) = self.current_player() {
+            let player_id = player.id.clone();
+            self.broadcast(GameEvent::TurnStarted { player_id });
+        }
+    }
+}
+
+/// Accept a player's action and generate the narration in the background,
+/// with the story's latest recap and stat block added to the system prompt.
+/// The narration goes through the content filter at the story's
+/// strictness, raised to the active profile's minimum. The lock is released while the provider is working so players can still
+/// join, leave and fetch the status.
+pub async fn submit_action(game: &SharedGame, player_id: &str, action: &str) -> Result<(), String> {
+    let (mut messages, provider, sampling, app, story_id) = {
+        let mut guard = game.lock().await;
+        let session = guard.as_mut().ok_or("No game session is running")?;
+        let messages = session.accept_action(player_id, action)?;
+        (
+            messages,
+            session.config.provider.clone(),
+            session.config.sampling.clone(),
+            session.app.clone(),
+            session.config.story_id.clone(),
+        )
+    };
+
+    if let Err(e) = recaps::touch(&app, &story_id).await {
+        eprintln!("Failed to track play session: {}", e);
+    }
+
+    let game = game.clone();
+    tokio::spawn(async move {
+        let result = async {
+            // Checked every turn, since the host may switch to a profile
+            // that may not use the provider or filters more strictly
+            let filter_config = filter::load_config(&app)?;
+            let strictness = profiles::check_generation(
+                &app,
+                &provider.base_url,
+                filter_config.strictness_for(Some(&story_id)),
+            )?;
+            let block = context::stat_block(&app, &story_id).await?;
+            let reminder = quests::reminder(&app, &story_id).await?;
+            let recap = recaps::context_text(&app, &story_id)?;
+            let style = style::context_text(&app, &story_id).await?;
+            context::inject_first(&mut messages, &recap);
+            context::inject(&mut messages, &block.text);
+            context::inject(&mut messages, &reminder);
+            context::inject(&mut messages, &style);
+            let reply = complete_chat(&provider, &messages, &sampling).await?;
+            filter::filter_reply(&app, &filter_config, strictness, &reply).await
+        }
+        .await;
+        if let Some(session) = game.lock().await.as_mut() {
+            session.finish_turn(result);
+        }
+    });
+    Ok(())
+}
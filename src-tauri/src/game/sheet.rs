@@ -0,0 +1,197 @@
+//! Character sheets: hit points, stats and status effects for the story's
+//! characters, and for combatants the story has no character for. They are
+//! kept in the app data directory, by story.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use super::inventory::Purse;
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Character sheets of every story, in the app data directory
+pub const CHARACTER_SHEETS_FILE: &str = "character_sheets.json";
+
+const MAX_NAME_CHARS: usize = 100;
+
+/// State managed by Tauri for character sheets
+#[derive(Default)]
+pub struct SheetState {
+    /// Serializes changes to the character sheets file
+    pub(crate) writes: std::sync::Mutex<()>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusEffect {
+    pub name: String,
+    /// Rounds left; None lasts until removed
+    #[serde(default)]
+    pub rounds: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterSheet {
+    pub id: String,
+    /// The story character the sheet belongs to, if any
+    #[serde(default)]
+    pub character_id: Option<String>,
+    pub name: String,
+    pub hp: i64,
+    pub max_hp: i64,
+    /// Named scores such as strength or attack, by name
+    #[serde(default)]
+    pub stats: BTreeMap<String, i64>,
+    #[serde(default)]
+    pub effects: Vec<StatusEffect>,
+    /// Money, changed only through inventory transactions
+    #[serde(default)]
+    pub purse: Purse,
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+impl CharacterSheet {
+    /// Check the sheet, trimming names and keeping hit points within the maximum
+    pub fn validate(&mut self) -> Result<(), String> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err("Character sheets need a name".to_string());
+        }
+        if self.name.chars().count() > MAX_NAME_CHARS {
+            return Err(format!(
+                "Names can be at most {} characters",
+                MAX_NAME_CHARS
+            ));
+        }
+        if self.max_hp <= 0 {
+            return Err("Maximum HP must be at least 1".to_string());
+        }
+        self.hp = self.hp.min(self.max_hp);
+        self.stats = std::mem::take(&mut self.stats)
+            .into_iter()
+            .map(|(name, value)| (name.trim().to_lowercase(), value))
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        self.effects.retain(|e| !e.name.trim().is_empty());
+        for effect in &mut self.effects {
+            effect.name = effect.name.trim().to_string();
+        }
+        Ok(())
+    }
+}
+
+/// Sheets keyed by story ID
+pub type CharacterSheets = HashMap<String, Vec<CharacterSheet>>;
+
+/// Read, change and write the character sheets file
+fn update<T>(
+    app: &AppHandle,
+    change: impl FnOnce(&mut CharacterSheets) -> Result<T, String>,
+) -> Result<T, String> {
+    let state = app.state::<SheetState>();
+    let _write = state
+        .writes
+        .lock()
+        .map_err(|_| "Character sheets are unavailable".to_string())?;
+    let mut sheets: CharacterSheets = store::load_json(app, CHARACTER_SHEETS_FILE)?;
+    let result = change(&mut sheets)?;
+    sheets.retain(|_, list| !list.is_empty());
+    store::save_json(app, CHARACTER_SHEETS_FILE, &sheets)?;
+    Ok(result)
+}
+
+/// A story's character sheets in the order they were added
+pub fn list(app: &AppHandle, story_id: &str) -> Result<Vec<CharacterSheet>, String> {
+    let mut sheets: CharacterSheets = store::load_json(app, CHARACTER_SHEETS_FILE)?;
+    Ok(sheets.remove(story_id).unwrap_or_default())
+}
+
+/// Add a sheet, or replace the one with the same ID
+pub fn save(
+    app: &AppHandle,
+    story_id: &str,
+    mut sheet: CharacterSheet,
+) -> Result<CharacterSheet, String> {
+    sheet.validate()?;
+    if sheet.id.trim().is_empty() {
+        sheet.id = Uuid::new_v4().to_string();
+    }
+    sheet.updated_at = now_ms();
+    update(app, |sheets| {
+        let list = sheets.entry(story_id.to_string()).or_default();
+        if let Some(character_id) = &sheet.character_id {
+            if list
+                .iter()
+                .any(|s| s.id != sheet.id && s.character_id.as_ref() == Some(character_id))
+            {
+                return Err("That character already has a sheet".to_string());
+            }
+        }
+        match list.iter_mut().find(|s| s.id == sheet.id) {
+            Some(existing) => {
+                sheet.purse = existing.purse.clone();
+                *existing = sheet.clone();
+            }
+            None => {
+                sheet.purse = Purse::default();
+                list.push(sheet.clone());
+            }
+        }
+        Ok(())
+    })?;
+    Ok(sheet)
+}
+
+/// Returns whether the sheet existed
+pub fn delete(app: &AppHandle, story_id: &str, sheet_id: &str) -> Result<bool, String> {
+    update(app, |sheets| {
+        let Some(list) = sheets.get_mut(story_id) else {
+            return Ok(false);
+        };
+        let before = list.len();
+        list.retain(|s| s.id != sheet_id);
+        Ok(list.len() != before)
+    })
+}
+
+/// Set the hit points and effects of several sheets in one write, as a
+/// fight left them. Each is (sheet ID, hit points, effects); sheets deleted
+/// since are skipped.
+pub fn set_vitals(
+    app: &AppHandle,
+    story_id: &str,
+    vitals: &[(String, i64, Vec<StatusEffect>)],
+) -> Result<(), String> {
+    let now = now_ms();
+    update(app, |sheets| {
+        for sheet in sheets.get_mut(story_id).into_iter().flatten() {
+            if let Some((_, hp, effects)) = vitals.iter().find(|(id, _, _)| *id == sheet.id) {
+                sheet.hp = (*hp).clamp(0, sheet.max_hp);
+                sheet.effects = effects.clone();
+                sheet.updated_at = now;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Set the purses of several sheets in one write; sheets deleted since are
+/// skipped
+pub fn set_purses(
+    app: &AppHandle,
+    story_id: &str,
+    purses: &[(String, Purse)],
+) -> Result<(), String> {
+    update(app, |sheets| {
+        for sheet in sheets.get_mut(story_id).into_iter().flatten() {
+            if let Some((_, purse)) = purses.iter().find(|(id, _)| *id == sheet.id) {
+                sheet.purse = purse.clone();
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,246 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::sync::server::ServerState;
+
+/// Entries replayed to viewers who join partway through
+const BACKLOG_ENTRIES: usize = 50;
+
+/// Events buffered per viewer before slow viewers start missing them
+const EVENT_BUFFER: usize = 64;
+
+/// An entry as shown to viewers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpectatorEntry {
+    pub id: String,
+    pub entry_type: String,
+    pub content: String,
+}
+
+/// Messages sent to viewers over the WebSocket
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SpectatorEvent {
+    /// Sent once on connect with the story title and recent entries
+    Welcome {
+        title: String,
+        entries: Vec<SpectatorEntry>,
+    },
+    /// A new entry, or a new version of an entry already sent
+    Entry {
+        entry: SpectatorEntry,
+    },
+    Ended,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpectatorInfo {
+    pub viewer_token: String,
+    pub max_viewers: usize,
+    pub qr_code_base64: String,
+}
+
+/// Broadcast state for read-only viewers
+struct Broadcast {
+    viewer_token: String,
+    max_viewers: usize,
+    title: String,
+    backlog: VecDeque<SpectatorEntry>,
+    events: broadcast::Sender<SpectatorEvent>,
+}
+
+/// Viewers connected to the sync server, separate from sync and game clients
+#[derive(Clone, Default)]
+pub struct SpectatorHub {
+    broadcast: Arc<Mutex<Option<Broadcast>>>,
+    viewers: Arc<AtomicUsize>,
+}
+
+/// Decrements the viewer count when a socket closes
+struct ViewerSlot(Arc<AtomicUsize>);
+
+impl Drop for ViewerSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl SpectatorHub {
+    /// Start (or restart) a broadcast with a fresh viewer token
+    pub async fn start(&self, title: String, viewer_token: String, max_viewers: usize) {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let previous = self.broadcast.lock().await.replace(Broadcast {
+            viewer_token,
+            max_viewers,
+            title,
+            backlog: VecDeque::new(),
+            events,
+        });
+        if let Some(previous) = previous {
+            let _ = previous.events.send(SpectatorEvent::Ended);
+        }
+    }
+
+    /// End the broadcast and disconnect every viewer
+    pub async fn stop(&self) {
+        if let Some(previous) = self.broadcast.lock().await.take() {
+            let _ = previous.events.send(SpectatorEvent::Ended);
+        }
+    }
+
+    /// Send an entry to viewers, replacing it in the backlog if it was sent before
+    pub async fn publish(&self, entry: SpectatorEntry) -> Result<(), String> {
+        let mut guard = self.broadcast.lock().await;
+        let broadcast = guard.as_mut().ok_or("Spectator mode is not running")?;
+        match broadcast.backlog.iter_mut().find(|e| e.id == entry.id) {
+            Some(existing) => *existing = entry.clone(),
+            None => {
+                broadcast.backlog.push_back(entry.clone());
+                if broadcast.backlog.len() > BACKLOG_ENTRIES {
+                    broadcast.backlog.pop_front();
+                }
+            }
+        }
+        let _ = broadcast.events.send(SpectatorEvent::Entry { entry });
+        Ok(())
+    }
+
+    pub async fn is_running(&self) -> bool {
+        self.broadcast.lock().await.is_some()
+    }
+
+    pub fn viewer_count(&self) -> usize {
+        self.viewers.load(Ordering::SeqCst)
+    }
+
+    /// Reserve a viewer slot if the token is valid and the cap allows it
+    async fn admit(
+        &self,
+        token: &str,
+    ) -> Result<
+        (
+            ViewerSlot,
+            SpectatorEvent,
+            broadcast::Receiver<SpectatorEvent>,
+        ),
+        Response,
+    > {
+        let guard = self.broadcast.lock().await;
+        let Some(broadcast) = guard.as_ref() else {
+            return Err(error(
+                StatusCode::NOT_FOUND,
+                "Spectator mode is not running",
+            ));
+        };
+        if token != broadcast.viewer_token {
+            return Err(error(StatusCode::UNAUTHORIZED, "Invalid viewer token"));
+        }
+
+        let admitted = self
+            .viewers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < broadcast.max_viewers).then_some(count + 1)
+            })
+            .is_ok();
+        if !admitted {
+            return Err(error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The viewer limit has been reached",
+            ));
+        }
+
+        let welcome = SpectatorEvent::Welcome {
+            title: broadcast.title.clone(),
+            entries: broadcast.backlog.iter().cloned().collect(),
+        };
+        Ok((
+            ViewerSlot(self.viewers.clone()),
+            welcome,
+            broadcast.events.subscribe(),
+        ))
+    }
+}
+
+/// Routes for viewers, merged into the sync router
+pub fn routes() -> Router<ServerState> {
+    Router::new().route("/spectate/ws", get(handle_socket))
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+#[derive(Deserialize)]
+struct ViewerQuery {
+    token: String,
+}
+
+async fn handle_socket(
+    State(state): State<ServerState>,
+    Query(query): Query<ViewerQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    match state.spectators.admit(&query.token).await {
+        Ok((slot, welcome, events)) => {
+            ws.on_upgrade(move |socket| forward_events(socket, slot, welcome, events))
+        }
+        Err(response) => response,
+    }
+}
+
+async fn send(socket: &mut WebSocket, event: &SpectatorEvent) -> bool {
+    let Ok(text) = serde_json::to_string(event) else {
+        return true;
+    };
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+async fn forward_events(
+    mut socket: WebSocket,
+    _slot: ViewerSlot,
+    welcome: SpectatorEvent,
+    mut events: broadcast::Receiver<SpectatorEvent>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    if !send(&mut socket, &welcome).await {
+        return;
+    }
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                let ended = matches!(event, SpectatorEvent::Ended);
+                if !send(&mut socket, &event).await || ended {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                // Viewers are read-only; anything but a close is ignored
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
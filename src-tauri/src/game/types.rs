@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ai::types::{ProviderConfig, SamplingParams};
+
+/// ID of the player slot belonging to the host
+pub const HOST_PLAYER_ID: &str = "host";
+
+/// Settings for a multiplayer session, chosen by the host
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameConfig {
+    pub story_id: String,
+    pub host_name: String,
+    /// Provider used to generate the narration after each turn
+    pub provider: ProviderConfig,
+    /// Instructions sent ahead of the shared transcript
+    pub system_prompt: String,
+    #[serde(default)]
+    pub sampling: SamplingParams,
+    /// Most recent entries included in each generation request
+    #[serde(default = "default_history_limit")]
+    pub history_limit: usize,
+}
+
+fn default_history_limit() -> usize {
+    40
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Player {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GameEntryKind {
+    Action,
+    Narration,
+}
+
+/// An entry added to the shared story during the session
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameEntry {
+    pub id: String,
+    pub kind: GameEntryKind,
+    /// Player who wrote an action; None for narration
+    pub player_id: Option<String>,
+    pub content: String,
+    pub created_at: i64,
+}
+
+/// Events broadcast to connected players and emitted to the host window
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GameEvent {
+    PlayerJoined { player: Player },
+    PlayerLeft { player_id: String },
+    TurnStarted { player_id: String },
+    Entry { entry: GameEntry },
+    GenerationFailed { message: String },
+    Ended,
+}
+
+/// Snapshot of the session for newly connected clients and the host UI
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameStatus {
+    pub story_id: String,
+    pub players: Vec<Player>,
+    pub current_player_id: Option<String>,
+    /// True while the narration for the last action is being generated
+    pub generating: bool,
+    pub entries: Vec<GameEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinRequest {
+    /// Sync server token from the QR code
+    pub token: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinResponse {
+    pub player_id: String,
+    /// Secret authenticating this player's actions and WebSocket
+    pub secret: String,
+    pub status: GameStatus,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerCredentials {
+    pub player_id: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionRequest {
+    #[serde(flatten)]
+    pub credentials: PlayerCredentials,
+    pub action: String,
+}
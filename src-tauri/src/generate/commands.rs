@@ -0,0 +1,32 @@
+use super::names::{self, CultureInfo};
+use super::world::{self, WorldSeed, WorldSeedOptions};
+use crate::sync::keys::now_ms;
+
+/// Cultures names can be generated for
+#[tauri::command]
+pub async fn list_name_cultures() -> Result<Vec<CultureInfo>, String> {
+    Ok(names::cultures())
+}
+
+/// Distinct full names in a culture. The seed decides the names; without
+/// one the batch gets a fresh seed.
+#[tauri::command]
+pub async fn generate_names(
+    culture: String,
+    count: usize,
+    seed: Option<u64>,
+) -> Result<Vec<String>, String> {
+    names::generate(
+        names::culture(&culture)?,
+        count,
+        seed.unwrap_or_else(|| now_ms() as u64),
+    )
+}
+
+/// A world to start from: its name, era, regions, factions and hooks
+#[tauri::command]
+pub async fn generate_world_seed(options: Option<WorldSeedOptions>) -> Result<WorldSeed, String> {
+    let options = options.unwrap_or_default();
+    let seed = options.seed.unwrap_or_else(|| now_ms() as u64);
+    world::generate(&options, seed)
+}
@@ -0,0 +1,7 @@
+//! Offline worldbuilding generators: names from curated syllable grammars
+//! and whole world seeds from curated word lists. They need no model and
+//! give the same results for the same seed on every platform.
+
+pub mod commands;
+pub mod names;
+pub mod world;
@@ -0,0 +1,234 @@
+//! Names from syllable grammars. Each culture builds given names from an
+//! opening, optional middles and an ending, and family names from two
+//! halves, so a culture's names sound alike without repeating.
+
+use serde::Serialize;
+
+use crate::rng::SplitMix64;
+
+/// Names generated at once at most
+pub const MAX_NAMES: usize = 100;
+
+/// Tries per name before a batch settles for fewer, when a small grammar
+/// runs out of new names
+const TRIES_PER_NAME: usize = 20;
+
+/// Letters longer than this make an unpronounceable given name
+const MAX_GIVEN_CHARS: usize = 11;
+
+pub struct Culture {
+    pub id: &'static str,
+    pub name: &'static str,
+    starts: &'static [&'static str],
+    middles: &'static [&'static str],
+    ends: &'static [&'static str],
+    /// Middles in a given name at most
+    max_middles: usize,
+    family_firsts: &'static [&'static str],
+    family_seconds: &'static [&'static str],
+    /// Joins given and family name, such as " " or " of the "
+    joiner: &'static str,
+}
+
+pub const CULTURES: [Culture; 6] = [
+    Culture {
+        id: "nordic",
+        name: "Nordic",
+        starts: &[
+            "As", "Bj", "Ey", "Gun", "Hal", "Ing", "Kar", "Leif", "Ragn", "Sig", "Sven", "Thor",
+            "Ulf", "Vig", "Frey", "Sol",
+        ],
+        middles: &["a", "e", "i", "o", "ar", "ol"],
+        ends: &[
+            "rid", "mund", "dis", "helm", "ulf", "a", "vald", "hild", "stein", "orn", "ny", "run",
+        ],
+        max_middles: 1,
+        family_firsts: &[
+            "Bjorn", "Erik", "Halvar", "Ivar", "Olaf", "Sten", "Thor", "Ulf", "Gunnar", "Ragnar",
+        ],
+        family_seconds: &["sson", "sdottir", "sen"],
+        joiner: " ",
+    },
+    Culture {
+        id: "elven",
+        name: "Elven",
+        starts: &[
+            "Ae", "Cael", "El", "Fa", "Gal", "Il", "Lae", "Mir", "Nim", "Syl", "Tha", "Va", "Ere",
+            "Lu",
+        ],
+        middles: &["a", "e", "i", "la", "ri", "wen", "ly", "the"],
+        ends: &[
+            "riel", "lian", "wyn", "dor", "thas", "nor", "iel", "ra", "las", "wen", "dir", "ne",
+        ],
+        max_middles: 2,
+        family_firsts: &[
+            "Moon", "Star", "Silver", "Dawn", "Leaf", "Mist", "Sun", "Night", "Wind", "Dew",
+        ],
+        family_seconds: &[
+            "whisper", "song", "bough", "glade", "brook", "shade", "bloom", "fall",
+        ],
+        joiner: " ",
+    },
+    Culture {
+        id: "dwarven",
+        name: "Dwarven",
+        starts: &[
+            "Bal", "Bor", "Dur", "Gim", "Kaz", "Thr", "Brom", "Dag", "Grun", "Har", "Mor", "Tor",
+        ],
+        middles: &["a", "o", "u", "in", "ar"],
+        ends: &[
+            "in", "ak", "grim", "dum", "rik", "li", "gar", "nir", "din", "ra", "unn", "hild",
+        ],
+        max_middles: 1,
+        family_firsts: &[
+            "Iron", "Stone", "Anvil", "Copper", "Deep", "Gold", "Hammer", "Granite", "Coal",
+            "Forge",
+        ],
+        family_seconds: &[
+            "beard", "hand", "delver", "fist", "shield", "helm", "breaker", "brow",
+        ],
+        joiner: " ",
+    },
+    Culture {
+        id: "latin",
+        name: "Imperial",
+        starts: &[
+            "Au", "Cae", "Cor", "Fla", "Jul", "Luc", "Mar", "Oc", "Pub", "Quin", "Sev", "Val",
+            "Cas", "Dom",
+        ],
+        middles: &["i", "e", "ul", "ent", "er", "av"],
+        ends: &[
+            "ius", "ia", "us", "a", "ian", "ina", "ix", "or", "ella", "anus", "itus", "illa",
+        ],
+        max_middles: 1,
+        family_firsts: &[
+            "Aur", "Corn", "Fab", "Jun", "Lic", "Octav", "Sempr", "Tull", "Val", "Claud",
+        ],
+        family_seconds: &["ius", "ianus", "ellus", "inius"],
+        joiner: " ",
+    },
+    Culture {
+        id: "desert",
+        name: "Desert",
+        starts: &[
+            "Am", "Fa", "Ha", "Ja", "Ka", "Kha", "Ma", "Na", "Ra", "Sa", "Ta", "Za", "Ya",
+        ],
+        middles: &["a", "i", "hi", "ri", "sh", "mi"],
+        ends: &[
+            "ra", "lim", "sir", "mir", "ida", "ran", "zad", "nah", "bir", "yan", "la", "ud",
+        ],
+        max_middles: 1,
+        family_firsts: &[
+            "Sand", "Sun", "Dune", "Oasis", "Salt", "Amber", "Copper", "Red", "Star", "Spice",
+        ],
+        family_seconds: &["reach", "well", "wind", "spring", "hollow", "road"],
+        joiner: " of the ",
+    },
+    Culture {
+        id: "eastern",
+        name: "Eastern",
+        starts: &[
+            "Ai", "Chi", "Har", "Ka", "Ken", "Mi", "Na", "Ren", "Sa", "Shi", "Ta", "Yu", "Ho", "Ri",
+        ],
+        middles: &["ka", "mi", "ro", "na", "to", "ki"],
+        ends: &[
+            "ko", "ro", "ki", "na", "to", "ji", "ya", "mi", "ru", "shi", "zo", "ne",
+        ],
+        max_middles: 1,
+        family_firsts: &[
+            "Taka", "Matsu", "Yama", "Mori", "Kawa", "Ishi", "Kuro", "Haya", "Fuji", "Sora",
+        ],
+        family_seconds: &["moto", "mura", "shita", "kawa", "hara", "da", "no"],
+        joiner: " ",
+    },
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CultureInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+}
+
+pub fn cultures() -> Vec<CultureInfo> {
+    CULTURES
+        .iter()
+        .map(|c| CultureInfo {
+            id: c.id,
+            name: c.name,
+        })
+        .collect()
+}
+
+pub fn culture(id: &str) -> Result<&'static Culture, String> {
+    let id = id.trim().to_lowercase();
+    CULTURES
+        .iter()
+        .find(|c| c.id == id || c.name.to_lowercase() == id)
+        .ok_or_else(|| format!("Unknown culture: {}", id))
+}
+
+pub(super) fn pick<'a>(rng: &mut SplitMix64, options: &[&'a str]) -> &'a str {
+    options[rng.below(options.len())]
+}
+
+/// Three vowels or three of a letter in a row, or a sound said twice like
+/// "nana" or "wenwen", reads badly
+fn awkward(name: &str) -> bool {
+    let letters: Vec<char> = name.to_lowercase().chars().collect();
+    let vowel = |c: &char| "aeiouy".contains(*c);
+    letters
+        .windows(3)
+        .any(|w| w.iter().all(vowel) || (w[0] == w[1] && w[1] == w[2]))
+        || (2..=3).any(|len| letters.windows(len * 2).any(|w| w[..len] == w[len..]))
+}
+
+impl Culture {
+    pub fn given_name(&self, rng: &mut SplitMix64) -> String {
+        loop {
+            let mut name = pick(rng, self.starts).to_string();
+            for _ in 0..rng.below(self.max_middles + 1) {
+                name.push_str(pick(rng, self.middles));
+            }
+            name.push_str(pick(rng, self.ends));
+            if name.chars().count() <= MAX_GIVEN_CHARS && !awkward(&name) {
+                return name;
+            }
+        }
+    }
+
+    /// Two halves written as one word, like "Stoneshield" or "Moonwhisper"
+    pub fn family_name(&self, rng: &mut SplitMix64) -> String {
+        format!(
+            "{}{}",
+            pick(rng, self.family_firsts),
+            pick(rng, self.family_seconds)
+        )
+    }
+
+    pub fn full_name(&self, rng: &mut SplitMix64) -> String {
+        let given = self.given_name(rng);
+        let family = self.family_name(rng);
+        format!("{}{}{}", given, self.joiner, family)
+    }
+}
+
+/// Distinct full names from a culture. The same culture, count and seed
+/// always give the same names.
+pub fn generate(culture: &Culture, count: usize, seed: u64) -> Result<Vec<String>, String> {
+    if count == 0 || count > MAX_NAMES {
+        return Err(format!("Names are generated 1 to {} at a time", MAX_NAMES));
+    }
+    let mut rng = SplitMix64(seed);
+    let mut names: Vec<String> = Vec::with_capacity(count);
+    for _ in 0..count * TRIES_PER_NAME {
+        if names.len() == count {
+            break;
+        }
+        let name = culture.full_name(&mut rng);
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
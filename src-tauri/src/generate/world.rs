@@ -0,0 +1,338 @@
+//! A world seed: a named world with its era and climate, regions with a
+//! landmark and a chief settlement each, factions with goals and rivals,
+//! and story hooks tying them together. Everything comes from curated
+//! word lists and one seed.
+
+use serde::{Deserialize, Serialize};
+
+use super::names::{self, pick, Culture, CULTURES};
+use crate::rng::SplitMix64;
+
+const MAX_REGIONS: usize = 12;
+const MAX_FACTIONS: usize = 8;
+const MAX_HOOKS: usize = 10;
+
+const WORLD_ENDINGS: [&str; 10] = [
+    "ria", "heim", "dor", "mar", "thal", "oria", "essa", "garde", "vane", "os",
+];
+
+const ERAS: [&str; 8] = [
+    "an age of crumbling empires",
+    "the long peace after a great war",
+    "a dark age following a cataclysm",
+    "an era of exploration and new frontiers",
+    "the twilight of the old gods",
+    "a golden age on the edge of collapse",
+    "a time of plague and recovery",
+    "the rise of a young empire",
+];
+
+const CLIMATES: [&str; 6] = [
+    "temperate, with hard winters",
+    "warm and dry, ringed by deserts",
+    "cold and stormy, mostly coast and fjord",
+    "humid and overgrown",
+    "harsh highland, cut by deep valleys",
+    "mild islands scattered across a wide sea",
+];
+
+const TERRAINS: [&str; 10] = [
+    "Marches",
+    "Wilds",
+    "Reach",
+    "Highlands",
+    "Fens",
+    "Wastes",
+    "Vale",
+    "Coast",
+    "Forest",
+    "Steppe",
+];
+
+const REGION_ADJECTIVES: [&str; 12] = [
+    "Ashen",
+    "Broken",
+    "Emerald",
+    "Frozen",
+    "Gilded",
+    "Hollow",
+    "Iron",
+    "Silent",
+    "Sunken",
+    "Thorned",
+    "Whispering",
+    "Crimson",
+];
+
+const LANDMARKS: [&str; 12] = [
+    "a drowned temple",
+    "a toppled colossus",
+    "a tower no one has climbed",
+    "a forest of petrified trees",
+    "a bridge older than the kingdom",
+    "a crater that glows at night",
+    "an abandoned dwarven gate",
+    "a lake that reflects other skies",
+    "a field of standing stones",
+    "a shipwreck far from any sea",
+    "a monastery carved into a cliff",
+    "a battlefield where nothing grows",
+];
+
+const PLACE_FIRSTS: [&str; 14] = [
+    "Raven", "Stone", "Ash", "Oak", "Wolf", "Black", "Gold", "Salt", "Thorn", "Elder", "Frost",
+    "Hearth", "Mill", "Swan",
+];
+
+const PLACE_SECONDS: [&str; 12] = [
+    "ford", "moor", "wick", "hold", "haven", "gate", "stead", "bury", "mere", "fell", "crest",
+    "brook",
+];
+
+const SETTLEMENT_SIZES: [&str; 4] = ["village", "town", "city", "fortress"];
+
+const FACTION_ADJECTIVES: [&str; 10] = [
+    "Crimson", "Silver", "Veiled", "Iron", "Pale", "Gilded", "Ember", "Azure", "Hidden", "Last",
+];
+
+/// Group word and the kind of faction it names
+const FACTION_GROUPS: [(&str, &str); 10] = [
+    ("Order", "knightly order"),
+    ("Circle", "cabal of mages"),
+    ("Company", "mercenary company"),
+    ("Guild", "merchant guild"),
+    ("Court", "noble house"),
+    ("Hand", "thieves' guild"),
+    ("Choir", "temple"),
+    ("Watch", "border guard"),
+    ("Covenant", "secret society"),
+    ("Fleet", "league of captains"),
+];
+
+const GOALS: [&str; 10] = [
+    "restore a fallen dynasty",
+    "control the trade roads",
+    "recover a lost relic",
+    "keep an old evil sealed away",
+    "overthrow the ruling council",
+    "spread their faith to every town",
+    "find the source of a strange sickness",
+    "claim the land's mines for themselves",
+    "avenge a betrayal from a generation ago",
+    "open a passage to another world",
+];
+
+const HOOKS: [&str; 8] = [
+    "{faction} is searching {landmark} in the {region} and needs guides who ask no questions.",
+    "A courier from {settlement} arrives dead, carrying a letter addressed to {faction}.",
+    "{faction} and {rival} both claim {settlement}, and its people are picking sides.",
+    "Travellers near {landmark} in the {region} have started disappearing.",
+    "{settlement} is offering a reward for proof of what {faction} is really after.",
+    "A stranger in {settlement} claims {rival} plan to {goal}.",
+    "The roads out of the {region} are closed, and {faction} won't say why.",
+    "A map found in {settlement} marks {landmark} with the seal of {rival}.",
+];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WorldSeedOptions {
+    /// Without one the world gets a fresh seed
+    pub seed: Option<u64>,
+    /// Culture for the world's name; picked by the seed when unset
+    pub culture: Option<String>,
+    pub regions: Option<usize>,
+    pub factions: Option<usize>,
+    pub hooks: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settlement {
+    pub name: String,
+    pub size: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub name: String,
+    pub landmark: String,
+    pub settlement: Settlement,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Faction {
+    pub name: String,
+    pub kind: String,
+    pub goal: String,
+    /// Region the faction is based in
+    pub seat: String,
+    /// Leader, named in the world's culture
+    pub leader: String,
+    pub rival: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldSeed {
+    pub seed: u64,
+    pub culture: String,
+    pub name: String,
+    pub era: String,
+    pub climate: String,
+    pub regions: Vec<Region>,
+    pub factions: Vec<Faction>,
+    pub hooks: Vec<String>,
+}
+
+fn count(value: Option<usize>, default: usize, max: usize, what: &str) -> Result<usize, String> {
+    match value.unwrap_or(default) {
+        n if n == 0 || n > max => Err(format!("A world has 1 to {} {}", max, what)),
+        n => Ok(n),
+    }
+}
+
+/// Up to `count` picks from a list, each used once while any are left
+fn distinct<'a>(rng: &mut SplitMix64, options: &[&'a str], count: usize) -> Vec<&'a str> {
+    let mut left: Vec<&str> = options.to_vec();
+    (0..count)
+        .map(|_| {
+            if left.is_empty() {
+                left = options.to_vec();
+            }
+            left.swap_remove(rng.below(left.len()))
+        })
+        .collect()
+}
+
+fn region_names(rng: &mut SplitMix64, count: usize) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    while names.len() < count {
+        let name = format!("{} {}", pick(rng, &REGION_ADJECTIVES), pick(rng, &TERRAINS));
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn settlement_names(rng: &mut SplitMix64, count: usize) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    while names.len() < count {
+        let name = format!("{}{}", pick(rng, &PLACE_FIRSTS), pick(rng, &PLACE_SECONDS));
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (placeholder, value)| {
+            text.replace(placeholder, value)
+        })
+}
+
+/// Generate a world. The same options always give the same world.
+pub fn generate(options: &WorldSeedOptions, seed: u64) -> Result<WorldSeed, String> {
+    let regions = count(options.regions, 4, MAX_REGIONS, "regions")?;
+    let factions = count(options.factions, 3, MAX_FACTIONS, "factions")?;
+    let hooks = count(options.hooks, 3, MAX_HOOKS, "hooks")?;
+    let mut rng = SplitMix64(seed);
+    let culture: &Culture = match options.culture.as_deref().filter(|c| !c.trim().is_empty()) {
+        Some(id) => names::culture(id)?,
+        None => &CULTURES[rng.below(CULTURES.len())],
+    };
+
+    let name = format!(
+        "{}{}",
+        culture
+            .given_name(&mut rng)
+            .trim_end_matches(|c: char| "aeiouy".contains(c)),
+        pick(&mut rng, &WORLD_ENDINGS)
+    );
+    let era = pick(&mut rng, &ERAS).to_string();
+    let climate = pick(&mut rng, &CLIMATES).to_string();
+
+    let region_names = region_names(&mut rng, regions);
+    let settlements = settlement_names(&mut rng, regions);
+    let landmarks = distinct(&mut rng, &LANDMARKS, regions);
+    let regions: Vec<Region> = region_names
+        .into_iter()
+        .zip(settlements)
+        .zip(landmarks)
+        .map(|((name, settlement), landmark)| Region {
+            name,
+            landmark: landmark.to_string(),
+            settlement: Settlement {
+                name: settlement,
+                size: pick(&mut rng, &SETTLEMENT_SIZES).to_string(),
+            },
+        })
+        .collect();
+
+    let adjectives = distinct(&mut rng, &FACTION_ADJECTIVES, factions);
+    let goals = distinct(&mut rng, &GOALS, factions);
+    let mut built: Vec<Faction> = adjectives
+        .into_iter()
+        .zip(goals)
+        .map(|(adjective, goal)| {
+            let (group, kind) = FACTION_GROUPS[rng.below(FACTION_GROUPS.len())];
+            Faction {
+                name: format!("The {} {}", adjective, group),
+                kind: kind.to_string(),
+                goal: goal.to_string(),
+                seat: regions[rng.below(regions.len())].name.clone(),
+                leader: culture.full_name(&mut rng),
+                rival: None,
+            }
+        })
+        .collect();
+    if built.len() > 1 {
+        for index in 0..built.len() {
+            let other = (index + 1 + rng.below(built.len() - 1)) % built.len();
+            built[index].rival = Some(built[other].name.clone());
+        }
+    }
+
+    // Faction names start with "The", which reads "the" mid-sentence
+    let mid_sentence = |name: &str| name.replacen("The ", "the ", 1);
+    let hooks = distinct(&mut rng, &HOOKS, hooks)
+        .into_iter()
+        .map(|template| {
+            let region = &regions[rng.below(regions.len())];
+            let faction = &built[rng.below(built.len())];
+            let rival = faction.rival.as_deref().unwrap_or("rivals of the crown");
+            let hook = fill(
+                template,
+                &[
+                    ("{faction}", &mid_sentence(&faction.name)),
+                    ("{rival}", &mid_sentence(rival)),
+                    ("{goal}", &faction.goal),
+                    ("{region}", &region.name),
+                    ("{landmark}", &region.landmark),
+                    ("{settlement}", &region.settlement.name),
+                ],
+            );
+            let mut chars = hook.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+        .collect();
+
+    Ok(WorldSeed {
+        seed,
+        culture: culture.id.to_string(),
+        name,
+        era,
+        climate,
+        regions,
+        factions: built,
+        hooks,
+    })
+}
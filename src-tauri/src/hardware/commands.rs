@@ -0,0 +1,36 @@
+use tauri::State;
+use tokio::sync::Mutex;
+
+use super::HardwareCapabilities;
+
+/// State managed by Tauri for hardware detection
+#[derive(Default)]
+pub struct HardwareState {
+    /// Detected once per launch unless a refresh is asked for
+    cached: Mutex<Option<HardwareCapabilities>>,
+}
+
+impl HardwareState {
+    pub(crate) async fn capabilities(&self, refresh: bool) -> Result<HardwareCapabilities, String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(capabilities) = cached.as_ref().filter(|_| !refresh) {
+            return Ok(capabilities.clone());
+        }
+        let capabilities = tokio::task::spawn_blocking(super::detect)
+            .await
+            .map_err(|e| format!("Hardware detection failed: {}", e))?;
+        *cached = Some(capabilities.clone());
+        Ok(capabilities)
+    }
+}
+
+/// GPU backends, video memory, CPU features and the local AI defaults they
+/// suggest. Detected once per launch; `refresh` looks again, such as after
+/// a driver install.
+#[tauri::command]
+pub async fn get_hardware_capabilities(
+    state: State<'_, HardwareState>,
+    refresh: Option<bool>,
+) -> Result<HardwareCapabilities, String> {
+    state.capabilities(refresh.unwrap_or(false)).await
+}
@@ -0,0 +1,311 @@
+//! Hardware capabilities: GPU backends (CUDA, Metal, Vulkan) with their
+//! video memory, CPU vector features and system memory. Detection asks the
+//! platform's own tools and libraries, so it needs no GPU crates, and
+//! anything that can't be found is reported as missing rather than failing.
+//! The report recommends defaults for running models and embeddings locally.
+
+pub mod commands;
+
+pub use commands::HardwareState;
+
+use serde::Serialize;
+use std::process::Command;
+
+/// Video memory a 7B model needs to run fully offloaded at 4-bit
+const FULL_OFFLOAD_VRAM_MB: u64 = 6 * 1024;
+
+/// Layers offloaded when the GPU is too small to hold a whole model, or
+/// its size is unknown; llama.cpp treats a count above the model's layers
+/// as all of them
+const ALL_LAYERS: i32 = 999;
+const PARTIAL_LAYERS: i32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Backend {
+    Cuda,
+    Metal,
+    Vulkan,
+    Cpu,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Gpu {
+    pub name: String,
+    pub backend: Backend,
+    /// Unknown when the platform does not say
+    pub vram_mb: Option<u64>,
+    /// Shares system memory, as on Apple silicon
+    pub unified_memory: bool,
+    pub driver_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuInfo {
+    /// "x86_64" or "aarch64"
+    pub arch: String,
+    pub threads: usize,
+    pub avx: bool,
+    pub avx2: bool,
+    pub avx512: bool,
+    pub fma: bool,
+    pub neon: bool,
+}
+
+/// Settings local models and embeddings should start from on this machine
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendedDefaults {
+    pub backend: Backend,
+    /// Model layers to offload to the GPU; none on CPU-only machines
+    pub gpu_layers: Option<i32>,
+    /// CPU threads for generation, leaving some for the app
+    pub threads: usize,
+    pub context_size: u32,
+    pub embedding_backend: Backend,
+    pub embedding_batch_size: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HardwareCapabilities {
+    pub os: String,
+    pub cpu: CpuInfo,
+    pub system_memory_mb: Option<u64>,
+    pub cuda: bool,
+    pub metal: bool,
+    pub vulkan: bool,
+    pub gpus: Vec<Gpu>,
+    pub recommended: RecommendedDefaults,
+    pub detected_at: i64,
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn cpu() -> CpuInfo {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    #[allow(unused_mut)]
+    let mut info = CpuInfo {
+        arch: std::env::consts::ARCH.to_string(),
+        threads,
+        avx: false,
+        avx2: false,
+        avx512: false,
+        fma: false,
+        neon: false,
+    };
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        info.avx = std::arch::is_x86_feature_detected!("avx");
+        info.avx2 = std::arch::is_x86_feature_detected!("avx2");
+        info.avx512 = std::arch::is_x86_feature_detected!("avx512f");
+        info.fma = std::arch::is_x86_feature_detected!("fma");
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        info.neon = std::arch::is_aarch64_feature_detected!("neon");
+    }
+    info
+}
+
+fn system_memory_mb() -> Option<u64> {
+    match std::env::consts::OS {
+        "linux" => {
+            let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+            let line = meminfo.lines().find(|l| l.starts_with("MemTotal:"))?;
+            let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+            Some(kb / 1024)
+        }
+        "macos" => {
+            let bytes: u64 = run("sysctl", &["-n", "hw.memsize"])?.trim().parse().ok()?;
+            Some(bytes / (1024 * 1024))
+        }
+        "windows" => {
+            let bytes: u64 = run(
+                "powershell",
+                &[
+                    "-NoProfile",
+                    "-Command",
+                    "(Get-CimInstance Win32_ComputerSystem).TotalPhysicalMemory",
+                ],
+            )?
+            .trim()
+            .parse()
+            .ok()?;
+            Some(bytes / (1024 * 1024))
+        }
+        _ => None,
+    }
+}
+
+/// NVIDIA GPUs as `nvidia-smi` lists them
+fn cuda_gpus() -> Vec<Gpu> {
+    let Some(output) = run(
+        "nvidia-smi",
+        &[
+            "--query-gpu=name,memory.total,driver_version",
+            "--format=csv,noheader,nounits",
+        ],
+    ) else {
+        return Vec::new();
+    };
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let name = fields.first().filter(|n| !n.is_empty())?;
+            Some(Gpu {
+                name: name.to_string(),
+                backend: Backend::Cuda,
+                vram_mb: fields.get(1).and_then(|m| m.parse().ok()),
+                unified_memory: false,
+                driver_version: fields.get(2).map(|v| v.to_string()),
+            })
+        })
+        .collect()
+}
+
+/// The Mac's GPU. Apple silicon shares system memory with it; an Intel
+/// Mac's discrete GPU reports its own.
+fn metal_gpus(system_memory_mb: Option<u64>) -> Vec<Gpu> {
+    if std::env::consts::OS != "macos" {
+        return Vec::new();
+    }
+    let apple_silicon = std::env::consts::ARCH == "aarch64";
+    let profile = run("system_profiler", &["SPDisplaysDataType"]).unwrap_or_default();
+    let name = profile
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Chipset Model:"))
+        .map(|n| n.trim().to_string())
+        .unwrap_or_else(|| "Apple GPU".to_string());
+    let vram_mb = if apple_silicon {
+        system_memory_mb
+    } else {
+        profile
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("VRAM (Total):"))
+            .and_then(|v| {
+                let mut parts = v.split_whitespace();
+                let amount: u64 = parts.next()?.parse().ok()?;
+                Some(match parts.next() {
+                    Some("GB") => amount * 1024,
+                    _ => amount,
+                })
+            })
+    };
+    vec![Gpu {
+        name,
+        backend: Backend::Metal,
+        vram_mb,
+        unified_memory: apple_silicon,
+        driver_version: None,
+    }]
+}
+
+/// Hardware devices `vulkaninfo` lists. Software renderers such as
+/// llvmpipe are left out, and a Vulkan loader without a device to run on
+/// counts as no Vulkan at all.
+fn vulkan_gpus() -> Vec<Gpu> {
+    let Some(summary) = run("vulkaninfo", &["--summary"]) else {
+        return Vec::new();
+    };
+    // Each device's block gives its type before its name
+    let mut device_type = "";
+    let mut gpus = Vec::new();
+    for line in summary.lines().map(str::trim) {
+        let value = |key: &str| {
+            line.strip_prefix(key)
+                .map(|v| v.trim_start_matches([' ', '=']).trim())
+        };
+        if let Some(kind) = value("deviceType") {
+            device_type = kind;
+        } else if let Some(name) = value("deviceName") {
+            let software = device_type.contains("CPU")
+                || ["llvmpipe", "swiftshader", "lavapipe"]
+                    .iter()
+                    .any(|s| name.to_lowercase().contains(s));
+            if !name.is_empty() && !software {
+                gpus.push(Gpu {
+                    name: name.to_string(),
+                    backend: Backend::Vulkan,
+                    vram_mb: None,
+                    unified_memory: device_type.contains("INTEGRATED"),
+                    driver_version: None,
+                });
+            }
+            device_type = "";
+        }
+    }
+    gpus
+}
+
+/// Prefer CUDA, then Metal, then Vulkan, offloading everything only when
+/// the GPU is known to be large enough for a typical model
+fn recommend(cpu: &CpuInfo, gpus: &[Gpu]) -> RecommendedDefaults {
+    let best = [Backend::Cuda, Backend::Metal, Backend::Vulkan]
+        .into_iter()
+        .find_map(|backend| {
+            gpus.iter()
+                .filter(|g| g.backend == backend)
+                .max_by_key(|g| g.vram_mb.unwrap_or(0))
+        });
+    let threads = if cpu.threads > 4 {
+        cpu.threads - 2
+    } else {
+        cpu.threads.max(1)
+    };
+    match best {
+        Some(gpu) => {
+            let roomy = gpu.vram_mb.is_some_and(|mb| mb >= FULL_OFFLOAD_VRAM_MB);
+            RecommendedDefaults {
+                backend: gpu.backend,
+                gpu_layers: Some(if roomy { ALL_LAYERS } else { PARTIAL_LAYERS }),
+                threads,
+                context_size: if roomy { 8192 } else { 4096 },
+                embedding_backend: gpu.backend,
+                embedding_batch_size: 64,
+            }
+        }
+        None => {
+            let fast_cpu = cpu.avx2 || cpu.neon;
+            RecommendedDefaults {
+                backend: Backend::Cpu,
+                gpu_layers: None,
+                threads,
+                context_size: if fast_cpu { 4096 } else { 2048 },
+                embedding_backend: Backend::Cpu,
+                embedding_batch_size: if fast_cpu { 16 } else { 4 },
+            }
+        }
+    }
+}
+
+/// Look at the machine. Runs several external tools, so call it off the
+/// async runtime.
+pub fn detect() -> HardwareCapabilities {
+    let cpu = cpu();
+    let system_memory_mb = system_memory_mb();
+    let mut gpus = cuda_gpus();
+    gpus.extend(metal_gpus(system_memory_mb));
+    gpus.extend(vulkan_gpus());
+    let recommended = recommend(&cpu, &gpus);
+    HardwareCapabilities {
+        os: std::env::consts::OS.to_string(),
+        cuda: gpus.iter().any(|g| g.backend == Backend::Cuda),
+        metal: gpus.iter().any(|g| g.backend == Backend::Metal),
+        vulkan: gpus.iter().any(|g| g.backend == Backend::Vulkan),
+        cpu,
+        system_memory_mb,
+        gpus,
+        recommended,
+        detected_at: crate::sync::keys::now_ms(),
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+use tokio::sync::Mutex;
+
+use super::{
+    commit_story, open_repo, push, story_at, story_history, GitHistoryConfig, HistoryCommit,
+    DEFAULT_REPO_DIR, GIT_HISTORY_FILE, REMOTE_TOKEN_ACCOUNT,
+};
+use crate::store;
+use crate::story::rows;
+use crate::sync::keys::{self, ApiKeyEntry};
+
+/// Number of commits returned when no limit is given
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// State managed by Tauri for the history repository
+#[derive(Default)]
+pub struct HistoryState {
+    /// Held while a story is committed. Every story shares the index and
+    /// the branch, so commits run one at a time.
+    commits: Mutex<()>,
+}
+
+fn repo_path(app: &AppHandle, config: &GitHistoryConfig) -> Result<PathBuf, String> {
+    match config.repo_path {
+        Some(ref path) => Ok(PathBuf::from(path)),
+        None => store::data_file(app, DEFAULT_REPO_DIR),
+    }
+}
+
+/// Load the config and open the repository, failing if history is disabled
+fn open_enabled(app: &AppHandle) -> Result<(GitHistoryConfig, git2::Repository), String> {
+    let config: GitHistoryConfig = store::load_json(app, GIT_HISTORY_FILE)?;
+    if !config.enabled {
+        return Err("Git history is not enabled".to_string());
+    }
+    let repo = open_repo(&repo_path(app, &config)?)?;
+    Ok((config, repo))
+}
+
+/// The history repository, or None when history is disabled
+pub(crate) fn open_if_enabled(app: &AppHandle) -> Result<Option<git2::Repository>, String> {
+    let config: GitHistoryConfig = store::load_json(app, GIT_HISTORY_FILE)?;
+    if !config.enabled {
+        return Ok(None);
+    }
+    open_repo(&repo_path(app, &config)?).map(Some)
+}
+
+#[tauri::command]
+pub async fn get_git_history_config(app: AppHandle) -> Result<GitHistoryConfig, String> {
+    store::load_json(&app, GIT_HISTORY_FILE)
+}
+
+/// Save history settings. The remote token goes to the keychain, never to disk.
+#[tauri::command]
+pub async fn set_git_history_config(
+    app: AppHandle,
+    config: GitHistoryConfig,
+    remote_token: Option<String>,
+) -> Result<(), String> {
+    if let Some(ref url) = config.remote_url {
+        if !url.starts_with("https://") {
+            return Err("Only HTTPS remotes are supported".to_string());
+        }
+    }
+    if let Some(token) = remote_token {
+        keys::store_in_keychain(&[ApiKeyEntry {
+            provider: REMOTE_TOKEN_ACCOUNT.to_string(),
+            api_key: token,
+        }])?;
+    }
+    if config.enabled {
+        open_repo(&repo_path(&app, &config)?)?;
+    }
+    store::save_json(&app, GIT_HISTORY_FILE, &config)
+}
+
+/// Commit a story as it is in the database. The frontend calls this once a
+/// story has been saved; returns None when history is disabled or nothing
+/// changed since the last commit.
+#[tauri::command]
+pub async fn commit_story_history(
+    app: AppHandle,
+    state: State<'_, HistoryState>,
+    story_id: String,
+    message: Option<String>,
+) -> Result<Option<HistoryCommit>, String> {
+    let config: GitHistoryConfig = store::load_json(&app, GIT_HISTORY_FILE)?;
+    if !config.enabled {
+        return Ok(None);
+    }
+    let path = repo_path(&app, &config)?;
+    let _commit = state.commits.lock().await;
+    let story_json = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?
+        .to_json()?;
+    tokio::task::spawn_blocking(move || commit_story(&open_repo(&path)?, &story_json, message))
+        .await
+        .map_err(|e| format!("History commit failed: {}", e))?
+}
+
+/// Commits that changed a story, newest first
+#[tauri::command]
+pub async fn get_story_history(
+    app: AppHandle,
+    story_id: String,
+    limit: Option<usize>,
+) -> Result<Vec<HistoryCommit>, String> {
+    let (_, repo) = open_enabled(&app)?;
+    story_history(&repo, &story_id, limit.unwrap_or(DEFAULT_HISTORY_LIMIT))
+}
+
+/// Story JSON as it was at a commit, for diffing or restoring
+#[tauri::command]
+pub async fn get_story_at_revision(
+    app: AppHandle,
+    story_id: String,
+    commit_id: String,
+) -> Result<String, String> {
+    let (_, repo) = open_enabled(&app)?;
+    story_at(&repo, &story_id, &commit_id)
+}
+
+/// Push the history repository to the configured remote
+#[tauri::command]
+pub async fn push_story_history(app: AppHandle) -> Result<(), String> {
+    let (config, repo) = open_enabled(&app)?;
+    let url = config.remote_url.ok_or("No remote is configured")?;
+    let username = config.remote_username.unwrap_or_else(|| "git".to_string());
+    let token = keys::read_from_keychain(REMOTE_TOKEN_ACCOUNT)?
+        .ok_or("No access token stored for the remote")?;
+    tokio::task::spawn_blocking(move || push(&repo, &url, &username, &token))
+        .await
+        .map_err(|e| format!("History push failed: {}", e))?
+}
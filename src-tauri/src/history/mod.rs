@@ -0,0 +1,340 @@
+pub mod commands;
+
+pub use commands::HistoryState;
+
+use git2::{
+    Cred, IndexAddOption, PushOptions, RemoteCallbacks, Repository, RepositoryInitOptions,
+    Signature, Sort,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::story::text::plain_text;
+use crate::story::StoryExport;
+
+/// File in the app data directory holding the history settings
+pub const GIT_HISTORY_FILE: &str = "git_history.json";
+
+/// Default repository location inside the app data directory
+pub const DEFAULT_REPO_DIR: &str = "story-history";
+
+/// Keychain account holding the remote's access token
+pub const REMOTE_TOKEN_ACCOUNT: &str = "git-history-remote";
+
+const BRANCH: &str = "main";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitHistoryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Repository directory; the app data directory is used when unset
+    #[serde(default)]
+    pub repo_path: Option<String>,
+    /// HTTPS remote to push to
+    #[serde(default)]
+    pub remote_url: Option<String>,
+    #[serde(default)]
+    pub remote_username: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryCommit {
+    pub id: String,
+    pub message: String,
+    /// Seconds since the Unix epoch
+    pub time: i64,
+}
+
+fn git_error(context: &str) -> impl Fn(git2::Error) -> String + '_ {
+    move |e| format!("{}: {}", context, e.message())
+}
+
+/// Open the history repository, creating it on first use
+pub fn open_repo(path: &Path) -> Result<Repository, String> {
+    if path.join(".git").exists() {
+        return Repository::open(path).map_err(git_error("Failed to open history repository"));
+    }
+    fs::create_dir_all(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut options = RepositoryInitOptions::new();
+    options.initial_head(BRANCH);
+    Repository::init_opts(path, &options).map_err(git_error("Failed to create history repository"))
+}
+
+/// Paths of a story's files relative to the repository root
+fn story_paths(story_id: &str) -> Result<(PathBuf, PathBuf), String> {
+    if story_id.is_empty() || story_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid story ID: {}", story_id));
+    }
+    let base = Path::new("stories");
+    Ok((
+        base.join(format!("{}.json", story_id)),
+        base.join(format!("{}.md", story_id)),
+    ))
+}
+
+/// Pretty JSON without the export timestamp, so unchanged stories produce no diff
+fn normalized_json(story_json: &str) -> Result<String, String> {
+    let mut value: Value =
+        serde_json::from_str(story_json).map_err(|e| format!("Invalid story JSON: {}", e))?;
+    if let Some(obj) = value.as_object_mut() {
+        obj.remove("exportedAt");
+    }
+    serde_json::to_string_pretty(&value).map_err(|e| format!("Failed to serialize story: {}", e))
+}
+
+/// Readable transcript, so `git diff` and `git blame` work on the prose
+fn transcript(export: &StoryExport) -> String {
+    let mut entries: Vec<_> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none())
+        .collect();
+    entries.sort_by_key(|e| e.position);
+    let mut out = format!("# {}\n\n", export.story.title);
+    for entry in entries {
+        let text = plain_text(&entry.content);
+        if entry.entry_type == "user_action" {
+            out.push_str(&format!("> {}\n\n", text.trim()));
+        } else {
+            out.push_str(&format!("{}\n\n", text.trim()));
+        }
+    }
+    out
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, word)
+    } else {
+        format!("{} {}s", count, word)
+    }
+}
+
+/// Describe what changed between two versions of a story
+pub fn commit_message(previous: Option<&StoryExport>, current: &StoryExport) -> String {
+    let title = &current.story.title;
+    let Some(previous) = previous else {
+        return format!(
+            "Add \"{}\" ({})",
+            title,
+            plural(current.entries.len(), "entry")
+        );
+    };
+
+    let old: HashMap<&str, &str> = previous
+        .entries
+        .iter()
+        .map(|e| (e.id.as_str(), e.content.as_str()))
+        .collect();
+    let new: HashMap<&str, &str> = current
+        .entries
+        .iter()
+        .map(|e| (e.id.as_str(), e.content.as_str()))
+        .collect();
+    let added = new.keys().filter(|id| !old.contains_key(*id)).count();
+    let removed = old.keys().filter(|id| !new.contains_key(*id)).count();
+    let edited = new
+        .iter()
+        .filter(|(id, content)| old.get(*id).is_some_and(|o| o != *content))
+        .count();
+
+    let mut changes = Vec::new();
+    if added > 0 {
+        changes.push(format!("add {}", plural(added, "entry")));
+    }
+    if edited > 0 {
+        changes.push(format!("edit {}", plural(edited, "entry")));
+    }
+    if removed > 0 {
+        changes.push(format!("remove {}", plural(removed, "entry")));
+    }
+    let counted = [
+        (
+            previous.characters.len(),
+            current.characters.len(),
+            "character",
+        ),
+        (
+            previous.locations.len(),
+            current.locations.len(),
+            "location",
+        ),
+        (
+            previous.lorebook_entries.len(),
+            current.lorebook_entries.len(),
+            "lorebook entry",
+        ),
+    ];
+    for (before, after, word) in counted {
+        if after > before {
+            changes.push(format!("add {}", plural(after - before, word)));
+        } else if before > after {
+            changes.push(format!("remove {}", plural(before - after, word)));
+        }
+    }
+    if previous.story.title != current.story.title {
+        changes.push(format!("rename from \"{}\"", previous.story.title));
+    }
+
+    if changes.is_empty() {
+        format!("Update \"{}\"", title)
+    } else {
+        format!("Update \"{}\": {}", title, changes.join(", "))
+    }
+}
+
+/// Contents of a file at HEAD, if it exists there
+fn head_file(repo: &Repository, path: &Path) -> Option<String> {
+    let tree = repo.head().ok()?.peel_to_tree().ok()?;
+    let blob = tree
+        .get_path(path)
+        .ok()?
+        .to_object(repo)
+        .ok()?
+        .peel_to_blob()
+        .ok()?;
+    String::from_utf8(blob.content().to_vec()).ok()
+}
+
+/// Write a story's files and commit them. Returns None when nothing changed.
+pub fn commit_story(
+    repo: &Repository,
+    story_json: &str,
+    message: Option<String>,
+) -> Result<Option<HistoryCommit>, String> {
+    let export = StoryExport::from_json(story_json)?;
+    let (json_path, md_path) = story_paths(&export.story.id)?;
+    let previous = head_file(repo, &json_path).and_then(|s| StoryExport::from_json(&s).ok());
+
+    let workdir = repo
+        .workdir()
+        .ok_or("History repository has no working directory")?;
+    fs::create_dir_all(workdir.join("stories"))
+        .map_err(|e| format!("Failed to create stories directory: {}", e))?;
+    fs::write(workdir.join(&json_path), normalized_json(story_json)?)
+        .map_err(|e| format!("Failed to write story: {}", e))?;
+    fs::write(workdir.join(&md_path), transcript(&export))
+        .map_err(|e| format!("Failed to write transcript: {}", e))?;
+
+    let mut index = repo.index().map_err(git_error("Failed to open index"))?;
+    index
+        .add_all([&json_path, &md_path], IndexAddOption::DEFAULT, None)
+        .map_err(git_error("Failed to stage story"))?;
+    index.write().map_err(git_error("Failed to write index"))?;
+    let tree_id = index
+        .write_tree()
+        .map_err(git_error("Failed to write tree"))?;
+
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    if parent.as_ref().is_some_and(|p| p.tree_id() == tree_id) {
+        return Ok(None);
+    }
+
+    let message = message.unwrap_or_else(|| commit_message(previous.as_ref(), &export));
+    let tree = repo
+        .find_tree(tree_id)
+        .map_err(git_error("Failed to read tree"))?;
+    let signature =
+        Signature::now("Aventura", "aventura@localhost").map_err(git_error("Invalid signature"))?;
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    let id = repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message,
+            &tree,
+            &parents,
+        )
+        .map_err(git_error("Failed to commit"))?;
+
+    Ok(Some(HistoryCommit {
+        id: id.to_string(),
+        message,
+        time: signature.when().seconds(),
+    }))
+}
+
+/// Commits that changed a story, newest first
+pub fn story_history(
+    repo: &Repository,
+    story_id: &str,
+    limit: usize,
+) -> Result<Vec<HistoryCommit>, String> {
+    let (json_path, _) = story_paths(story_id)?;
+    let mut walk = repo
+        .revwalk()
+        .map_err(git_error("Failed to read history"))?;
+    if walk.push_head().is_err() {
+        return Ok(Vec::new());
+    }
+    walk.set_sorting(Sort::TIME)
+        .map_err(git_error("Failed to read history"))?;
+
+    let blob_at = |commit: &git2::Commit| {
+        commit
+            .tree()
+            .ok()
+            .and_then(|t| t.get_path(&json_path).ok())
+            .map(|e| e.id())
+    };
+
+    let mut commits = Vec::new();
+    for oid in walk {
+        let commit = repo
+            .find_commit(oid.map_err(git_error("Failed to read history"))?)
+            .map_err(git_error("Failed to read commit"))?;
+        let current = blob_at(&commit);
+        let parent = commit.parent(0).ok().and_then(|p| blob_at(&p));
+        if current.is_some() && current != parent {
+            commits.push(HistoryCommit {
+                id: commit.id().to_string(),
+                message: commit.message().unwrap_or_default().to_string(),
+                time: commit.time().seconds(),
+            });
+            if commits.len() >= limit {
+                break;
+            }
+        }
+    }
+    Ok(commits)
+}
+
+/// Story JSON as it was at a given commit
+pub fn story_at(repo: &Repository, story_id: &str, commit_id: &str) -> Result<String, String> {
+    let (json_path, _) = story_paths(story_id)?;
+    let oid = git2::Oid::from_str(commit_id).map_err(git_error("Invalid commit ID"))?;
+    let commit = repo
+        .find_commit(oid)
+        .map_err(git_error("Commit not found"))?;
+    let entry = commit
+        .tree()
+        .and_then(|t| t.get_path(&json_path))
+        .map_err(git_error("Story not present in that commit"))?;
+    let blob = entry
+        .to_object(repo)
+        .and_then(|o| o.peel_to_blob())
+        .map_err(git_error("Failed to read story"))?;
+    String::from_utf8(blob.content().to_vec()).map_err(|_| "Story is not valid UTF-8".to_string())
+}
+
+/// Push the history branch to an HTTPS remote using a personal access token
+pub fn push(repo: &Repository, url: &str, username: &str, token: &str) -> Result<(), String> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|_, _, _| Cred::userpass_plaintext(username, token));
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+
+    let mut remote = repo
+        .remote_anonymous(url)
+        .map_err(git_error("Invalid remote"))?;
+    let refspec = format!("refs/heads/{0}:refs/heads/{0}", BRANCH);
+    remote
+        .push(&[refspec.as_str()], Some(&mut options))
+        .map_err(git_error("Push failed"))
+}
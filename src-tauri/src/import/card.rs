@@ -0,0 +1,426 @@
+//! Character cards: PNG portraits with the character's JSON embedded in a
+//! text chunk, as SillyTavern and similar apps write them. V1 and V2 cards
+//! keep base64 JSON under the `chara` keyword and V3 cards under `ccv3`,
+//! which wins when both are present. The chunk may be tEXt, zTXt or iTXt.
+//!
+//! A card becomes a character sheet, a lorebook holding the character and
+//! any entries of the card's own lorebook, and a portrait attachment.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::read::ZlibDecoder;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::io::{Cursor, Read};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+use crate::attachments::{self, Attachment};
+use crate::lorebook::{
+    self, EntryInjection, Lorebook, LorebookApplyReport, LorebookConflict, LorebookEntryData,
+    LorebookInfo,
+};
+use crate::profiles::{self, database};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Largest text chunk read once decompressed
+const MAX_CARD_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Personality pieces longer than this are prose, not traits
+const MAX_TRAIT_CHARS: usize = 40;
+
+/// Decompress a zlib stream, refusing ones that grow past `MAX_CARD_BYTES`
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data)
+        .take(MAX_CARD_BYTES + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| format!("Invalid compressed card data: {}", e))?;
+    if out.len() as u64 > MAX_CARD_BYTES {
+        return Err("Character card data is too large".to_string());
+    }
+    Ok(out)
+}
+
+/// Keyword and text of a tEXt, zTXt or iTXt chunk
+fn text_chunk(kind: &[u8], data: &[u8]) -> Result<Option<(String, Vec<u8>)>, String> {
+    let Some(nul) = data.iter().position(|b| *b == 0) else {
+        return Ok(None);
+    };
+    let keyword = String::from_utf8_lossy(&data[..nul]).to_string();
+    let rest = &data[nul + 1..];
+    let text = match kind {
+        b"tEXt" => rest.to_vec(),
+        // Compression method, then the zlib stream
+        b"zTXt" => inflate(rest.get(1..).unwrap_or_default())?,
+        b"iTXt" => {
+            let (compressed, rest) = match rest {
+                [flag, _method, rest @ ..] => (*flag == 1, rest),
+                _ => return Ok(None),
+            };
+            // Language tag and translated keyword, each ending in a NUL
+            let mut rest = rest;
+            for _ in 0..2 {
+                let Some(nul) = rest.iter().position(|b| *b == 0) else {
+                    return Ok(None);
+                };
+                rest = &rest[nul + 1..];
+            }
+            if compressed {
+                inflate(rest)?
+            } else {
+                rest.to_vec()
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some((keyword, text)))
+}
+
+/// The card JSON embedded in a PNG, with the image's pixel size
+pub fn extract_json(png: &[u8]) -> Result<(String, (u32, u32)), String> {
+    let mut rest = png.strip_prefix(PNG_SIGNATURE).ok_or("Not a PNG image")?;
+    let mut size = (0, 0);
+    let mut chara = None;
+    let mut ccv3 = None;
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = &rest[4..8];
+        let Some(data) = rest.get(8..8 + len) else {
+            return Err("The PNG is truncated".to_string());
+        };
+        if kind == b"IHDR" && len >= 8 {
+            size = (
+                u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            );
+        }
+        if let Some((keyword, text)) = text_chunk(kind, data)? {
+            match keyword.to_ascii_lowercase().as_str() {
+                "chara" => chara = Some(text),
+                "ccv3" => ccv3 = Some(text),
+                _ => {}
+            }
+        }
+        if kind == b"IEND" {
+            break;
+        }
+        rest = rest.get(12 + len..).unwrap_or_default();
+    }
+    let text = ccv3
+        .or(chara)
+        .ok_or("This PNG has no character card data")?;
+    let text = String::from_utf8_lossy(&text);
+    let json = STANDARD
+        .decode(text.trim())
+        .map_err(|e| format!("Invalid character card data: {}", e))?;
+    let json = String::from_utf8(json).map_err(|_| "Character card data is not UTF-8")?;
+    Ok((json, size))
+}
+
+/// A card from a PNG or a bare JSON file
+pub fn read(data: &[u8]) -> Result<CharacterCard, String> {
+    if data.starts_with(PNG_SIGNATURE) {
+        return parse(&extract_json(data)?.0);
+    }
+    let json = std::str::from_utf8(data).map_err(|_| "Not a PNG or JSON character card")?;
+    parse(json.trim_start_matches('\u{feff}'))
+}
+
+/// An entry of a card's own lorebook
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CardBookEntry {
+    pub keys: Vec<String>,
+    pub secondary_keys: Vec<String>,
+    pub content: String,
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    pub enabled: Option<bool>,
+    pub constant: Option<bool>,
+    pub insertion_order: Option<Number>,
+    pub priority: Option<Number>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct CardBook {
+    entries: Vec<CardBookEntry>,
+}
+
+/// Card fields, the same for V1, V2 and V3
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct CardFields {
+    name: String,
+    description: String,
+    personality: String,
+    scenario: String,
+    first_mes: String,
+    mes_example: String,
+    alternate_greetings: Vec<String>,
+    creator_notes: String,
+    tags: Vec<String>,
+    creator: String,
+    character_book: Option<CardBook>,
+}
+
+/// A character card, normalized from any version
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterCard {
+    /// "v1", "v2" or "v3"
+    pub version: String,
+    pub name: String,
+    pub description: String,
+    pub personality: String,
+    pub scenario: String,
+    pub first_message: String,
+    pub alternate_greetings: Vec<String>,
+    pub example_messages: String,
+    pub creator_notes: String,
+    pub tags: Vec<String>,
+    pub creator: String,
+    pub book: Vec<CardBookEntry>,
+}
+
+pub fn parse(json: &str) -> Result<CharacterCard, String> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| format!("Invalid character card: {}", e))?;
+    let version = match value["spec"].as_str() {
+        Some("chara_card_v3") => "v3",
+        Some("chara_card_v2") => "v2",
+        Some(spec) => return Err(format!("Unsupported character card format: {}", spec)),
+        None => "v1",
+    };
+    let fields = if version == "v1" {
+        value
+    } else {
+        value
+            .get("data")
+            .cloned()
+            .ok_or("Character card has no data")?
+    };
+    let fields: CardFields =
+        serde_json::from_value(fields).map_err(|e| format!("Invalid character card: {}", e))?;
+    if fields.name.trim().is_empty() {
+        return Err("Character card has no name".to_string());
+    }
+    Ok(CharacterCard {
+        version: version.to_string(),
+        name: fields.name.trim().to_string(),
+        description: fields.description,
+        personality: fields.personality,
+        scenario: fields.scenario,
+        first_message: fields.first_mes,
+        alternate_greetings: fields.alternate_greetings,
+        example_messages: fields.mes_example,
+        creator_notes: fields.creator_notes,
+        tags: fields.tags,
+        creator: fields.creator,
+        book: fields.character_book.map(|b| b.entries).unwrap_or_default(),
+    })
+}
+
+/// A character sheet made from a card
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterSheet {
+    pub name: String,
+    pub description: String,
+    pub traits: Vec<String>,
+}
+
+impl CharacterCard {
+    /// Cards use `{{char}}` for the character's name
+    fn fill(&self, text: &str) -> String {
+        text.replace("{{char}}", &self.name)
+            .replace("<BOT>", &self.name)
+            .trim()
+            .to_string()
+    }
+
+    /// A short comma-separated personality becomes traits; prose is kept
+    /// in the description instead
+    pub fn sheet(&self) -> CharacterSheet {
+        let personality = self.fill(&self.personality);
+        let pieces: Vec<String> = personality
+            .split([',', ';', '\n'])
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+        let is_list =
+            !pieces.is_empty() && pieces.iter().all(|p| p.chars().count() <= MAX_TRAIT_CHARS);
+        let mut description = self.fill(&self.description);
+        if !is_list && !personality.is_empty() {
+            description = format!("{}\n\nPersonality: {}", description, personality)
+                .trim()
+                .to_string();
+        }
+        CharacterSheet {
+            name: self.name.clone(),
+            description,
+            traits: if is_list { pieces } else { Vec::new() },
+        }
+    }
+
+    /// Lorebook entries: the character, then the card's own entries
+    pub fn lorebook_entries(&self) -> Vec<LorebookEntryData> {
+        let sheet = self.sheet();
+        let mut entries = vec![LorebookEntryData {
+            name: self.name.clone(),
+            entry_type: "character".to_string(),
+            description: sheet.description,
+            hidden_info: None,
+            aliases: Vec::new(),
+            state: None,
+            injection: EntryInjection {
+                keywords: vec![self.name.clone()],
+                ..Default::default()
+            },
+        }];
+        entries.extend(
+            self.book
+                .iter()
+                .filter(|e| !e.content.trim().is_empty())
+                .map(|entry| {
+                    let keywords: Vec<String> = entry
+                        .keys
+                        .iter()
+                        .chain(&entry.secondary_keys)
+                        .map(|k| k.trim().to_string())
+                        .filter(|k| !k.is_empty())
+                        .collect();
+                    let name = [&entry.name, &entry.comment]
+                        .into_iter()
+                        .flatten()
+                        .map(|n| n.trim())
+                        .find(|n| !n.is_empty())
+                        .map(String::from)
+                        .or_else(|| keywords.first().cloned())
+                        .unwrap_or_else(|| format!("{} lore", self.name));
+                    let mode = if entry.enabled == Some(false) {
+                        "never"
+                    } else if entry.constant == Some(true) {
+                        "always"
+                    } else {
+                        "keyword"
+                    };
+                    LorebookEntryData {
+                        name,
+                        entry_type: "concept".to_string(),
+                        description: self.fill(&entry.content),
+                        hidden_info: None,
+                        aliases: Vec::new(),
+                        state: None,
+                        injection: EntryInjection {
+                            mode: mode.to_string(),
+                            keywords,
+                            priority: entry.priority.clone().or(entry.insertion_order.clone()),
+                        },
+                    }
+                }),
+        );
+        entries
+    }
+}
+
+/// The portrait without the card data, as a plain PNG
+fn clean_portrait(png: &[u8]) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory(png).map_err(|e| format!("Invalid card image: {}", e))?;
+    let mut data = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode portrait: {}", e))?;
+    Ok(data)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterCardImport {
+    pub card: CharacterCard,
+    pub character: CharacterSheet,
+    pub portrait: Attachment,
+    /// The character and the card's own entries, added to the library
+    pub lorebook: LorebookInfo,
+    /// ID of the character added to the story, if one was given
+    pub character_id: Option<String>,
+    /// Entries added to the story, if one was given
+    pub applied: Option<LorebookApplyReport>,
+}
+
+/// Read a character card PNG. The portrait is stored as an attachment and
+/// the lorebook saved to the library; with a story, the character and the
+/// lorebook are added to it too.
+pub async fn import(
+    app: &AppHandle,
+    png: &[u8],
+    story_id: Option<&str>,
+) -> Result<CharacterCardImport, String> {
+    let (json, size) = extract_json(png)?;
+    let card = parse(&json)?;
+    let image = png.to_vec();
+    let portrait = tokio::task::spawn_blocking(move || clean_portrait(&image))
+        .await
+        .map_err(|e| format!("Failed to clean portrait: {}", e))??;
+    let attachment = attachments::save(app, &portrait, "image/png", Some(size))?;
+    let mut tags = card.tags.clone();
+    tags.retain(|t| !t.trim().is_empty());
+    let book = Lorebook::new(
+        &card.name,
+        &format!("Imported from {}'s character card", card.name),
+        tags,
+        card.lorebook_entries(),
+    );
+    lorebook::save(app, &book)?;
+
+    let (character_id, applied) = match story_id {
+        Some(story_id) => {
+            let id = add_character(app, story_id, &card.sheet(), &portrait).await?;
+            let report = lorebook::apply(app, &book, story_id, LorebookConflict::Skip).await?;
+            (Some(id), Some(report))
+        }
+        None => (None, None),
+    };
+    Ok(CharacterCardImport {
+        character: card.sheet(),
+        card,
+        portrait: attachment,
+        lorebook: book.info(false),
+        character_id,
+        applied,
+    })
+}
+
+/// Add a character to a story's main line
+async fn add_character(
+    app: &AppHandle,
+    story_id: &str,
+    sheet: &CharacterSheet,
+    portrait: &[u8],
+) -> Result<String, String> {
+    let id = Uuid::new_v4().to_string();
+    let traits = serde_json::to_string(&sheet.traits)
+        .map_err(|e| format!("Failed to serialize traits: {}", e))?;
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let result = sqlx::query(
+        "INSERT INTO characters (id, story_id, name, description, relationship, traits, \
+         visual_descriptors, portrait, status, metadata, branch_id) \
+         SELECT ?, id, ?, ?, NULL, ?, '[]', ?, 'active', NULL, NULL FROM stories WHERE id = ?",
+    )
+    .bind(&id)
+    .bind(&sheet.name)
+    .bind(&sheet.description)
+    .bind(traits)
+    .bind(STANDARD.encode(portrait))
+    .bind(story_id)
+    .execute(&pool)
+    .await
+    .map_err(|e| format!("Failed to add character: {}", e));
+    pool.close().await;
+    if result?.rows_affected() == 0 {
+        return Err(format!("Story not found: {}", story_id));
+    }
+    Ok(id)
+}
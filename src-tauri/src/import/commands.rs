@@ -0,0 +1,106 @@
+use notify::RecommendedWatcher;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+use tokio::sync::Mutex;
+
+use super::card::{self, CharacterCard, CharacterCardImport};
+use super::preview::{self, ImportPreview};
+use super::remote;
+use super::watcher::{self, WatchFolderConfig, WatchedImport, WATCH_CONFIG_FILE};
+use crate::profiles;
+use crate::store;
+
+/// State managed by Tauri for imports
+#[derive(Default)]
+pub struct ImportState {
+    /// Watcher for the import folder, when enabled
+    pub(crate) watcher: Mutex<Option<RecommendedWatcher>>,
+    /// Stories found in the watch folder, opened or linked that the
+    /// frontend has not imported yet
+    pub(crate) pending: Arc<Mutex<Vec<WatchedImport>>>,
+}
+
+#[tauri::command]
+pub async fn get_watch_folder_config(app: AppHandle) -> Result<WatchFolderConfig, String> {
+    store::load_json(&app, WATCH_CONFIG_FILE)
+}
+
+/// Save the watch folder settings and start or stop the watcher to match
+#[tauri::command]
+pub async fn set_watch_folder_config(
+    app: AppHandle,
+    state: State<'_, ImportState>,
+    config: WatchFolderConfig,
+) -> Result<(), String> {
+    let mut slot = state.watcher.lock().await;
+    *slot = None;
+    if let Some(folder) = config.folder.as_deref().filter(|_| config.enabled) {
+        *slot = Some(watcher::start(&app, folder)?);
+    }
+    store::save_json(&app, WATCH_CONFIG_FILE, &config)
+}
+
+/// Stories waiting to be imported, including any left from the last run.
+/// Each is also announced with an `import://story` event when it arrives.
+#[tauri::command]
+pub async fn get_watched_imports(
+    state: State<'_, ImportState>,
+) -> Result<Vec<WatchedImport>, String> {
+    Ok(state.pending.lock().await.clone())
+}
+
+/// Report a queued story as imported, or failed with `error`, so it leaves
+/// the queue and its file is moved aside
+#[tauri::command]
+pub async fn finish_watched_import(
+    app: AppHandle,
+    id: String,
+    error: Option<String>,
+) -> Result<(), String> {
+    watcher::finish(&app, &id, error).await
+}
+
+/// Download a shared story export from an https:// link and validate it.
+/// The story is returned for the frontend to import, like a watched file.
+#[tauri::command]
+pub async fn import_story_from_url(url: String) -> Result<WatchedImport, String> {
+    remote::fetch(&remote::parse_url(&url)?).await
+}
+
+/// Show what importing a story would do before it is committed: a summary,
+/// validation warnings and, when the story already exists, an entry-level
+/// diff against `local_json` or else the copy in the library. Works for
+/// stories from sync pulls, files and links alike.
+#[tauri::command]
+pub async fn preview_import(
+    app: AppHandle,
+    json: String,
+    local_json: Option<String>,
+) -> Result<ImportPreview, String> {
+    preview::preview(&app, &json, local_json.as_deref()).await
+}
+
+/// Read a character card from PNG or JSON file contents without importing
+/// it, for the setup wizard's card import
+#[tauri::command]
+pub async fn read_character_card(data: Vec<u8>) -> Result<CharacterCard, String> {
+    card::read(&data)
+}
+
+/// Import a character card PNG: the portrait becomes an attachment and the
+/// character and the card's lorebook a lorebook in the library. With a
+/// story, the character and its lorebook entries are added to it as well.
+#[tauri::command]
+pub async fn import_character_card(
+    app: AppHandle,
+    path: String,
+    story_id: Option<String>,
+) -> Result<CharacterCardImport, String> {
+    if let Some(story_id) = story_id.as_deref() {
+        profiles::check_story(&app, story_id).await?;
+    }
+    let png = tokio::fs::read(&path)
+        .await
+        .map_err(|e| format!("Failed to read character card: {}", e))?;
+    card::import(&app, &png, story_id.as_deref()).await
+}
@@ -0,0 +1,7 @@
+pub mod card;
+pub mod commands;
+pub mod preview;
+pub mod remote;
+pub mod watcher;
+
+pub use commands::ImportState;
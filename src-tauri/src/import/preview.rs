@@ -0,0 +1,334 @@
+//! What an import would do, shown before the user commits to it: the
+//! story's summary, anything suspicious about the export and, when the
+//! story is already here, how the two copies differ entry by entry. The
+//! local copy is found the way sync finds it: by ID, or by title for copies
+//! imported before imports kept IDs.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tauri::AppHandle;
+
+use crate::export::cover;
+use crate::story::graph::StoryGraph;
+use crate::story::merge::{excerpt, match_ids};
+use crate::story::rating::ContentRating;
+use crate::story::types::StoryEntry;
+use crate::story::{archive, rows, StoryExport};
+use crate::sync::types::SyncStoryPreview;
+
+/// Changed entries listed individually; the rest are only counted
+const MAX_LISTED_ENTRIES: usize = 50;
+
+/// Where the local copy compared against came from
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LocalSource {
+    /// Passed in by the frontend from its database
+    Provided,
+    /// Read from the library database
+    Library,
+}
+
+/// Counts of records added, removed and changed by an import
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeCounts {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EntryChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One entry the import adds, removes or rewrites
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryChange {
+    pub entry_id: String,
+    pub kind: EntryChangeKind,
+    pub local_excerpt: Option<String>,
+    pub incoming_excerpt: Option<String>,
+}
+
+/// How the import differs from the copy already here
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportDiff {
+    pub source: LocalSource,
+    /// ID of the local copy, which differs from the import's for copies
+    /// matched by title
+    pub local_story_id: String,
+    pub local_title: String,
+    pub local_updated_at: i64,
+    /// The local copy was edited after the incoming one
+    pub local_is_newer: bool,
+    pub title_changed: bool,
+    pub entries: ChangeCounts,
+    /// Up to `MAX_LISTED_ENTRIES` of the added, removed and changed entries
+    pub entry_changes: Vec<EntryChange>,
+    pub characters: ChangeCounts,
+    pub locations: ChangeCounts,
+    pub items: ChangeCounts,
+    pub story_beats: ChangeCounts,
+    pub lorebook_entries: ChangeCounts,
+    pub chapters: ChangeCounts,
+    pub branches: ChangeCounts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPreview {
+    pub story: SyncStoryPreview,
+    pub warnings: Vec<String>,
+    /// Present when the story already exists
+    pub diff: Option<ImportDiff>,
+    /// A story with the same ID is archived. It cannot be restored while
+    /// the imported copy is in the library.
+    pub archived: bool,
+}
+
+/// Problems with the export worth confirming before it is imported
+fn warnings(export: &StoryExport) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if export.story.title.trim().is_empty() {
+        warnings.push("The story has no title".to_string());
+    }
+    if export.entries.is_empty() {
+        warnings.push("The story has no entries".to_string());
+    }
+
+    let mut seen = HashSet::new();
+    let duplicates = export
+        .entries
+        .iter()
+        .filter(|e| !seen.insert(e.id.as_str()))
+        .count();
+    if duplicates > 0 {
+        warnings.push(format!(
+            "{} entries share an ID with another entry",
+            duplicates
+        ));
+    }
+    let foreign = export
+        .entries
+        .iter()
+        .filter(|e| !e.story_id.is_empty() && e.story_id != export.story.id)
+        .count();
+    if foreign > 0 {
+        warnings.push(format!("{} entries belong to a different story", foreign));
+    }
+    let branches: HashSet<&str> = export.branches.iter().map(|b| b.id.as_str()).collect();
+    let unknown_branch = export
+        .entries
+        .iter()
+        .filter(|e| {
+            e.branch_id
+                .as_deref()
+                .is_some_and(|b| !branches.contains(b))
+        })
+        .count();
+    if unknown_branch > 0 {
+        warnings.push(format!(
+            "{} entries are on a branch that is not in the export",
+            unknown_branch
+        ));
+    }
+
+    let issues = StoryGraph::build(export).issues;
+    if !issues.cycles.is_empty() {
+        warnings.push(format!(
+            "The branch graph has {} cycles",
+            issues.cycles.len()
+        ));
+    }
+    if !issues.unreachable.is_empty() {
+        warnings.push(format!(
+            "{} entries cannot be reached from the start of the story",
+            issues.unreachable.len()
+        ));
+    }
+    if !issues.orphaned_branches.is_empty() {
+        warnings.push(format!(
+            "{} branches fork from an entry that does not exist",
+            issues.orphaned_branches.len()
+        ));
+    }
+    warnings
+}
+
+/// Compare two lists of records by ID
+fn count_changes<T: Serialize>(
+    local: &[T],
+    incoming: &[T],
+    id: impl Fn(&T) -> &str,
+) -> ChangeCounts {
+    let local: HashMap<&str, &T> = local.iter().map(|r| (id(r), r)).collect();
+    let mut counts = ChangeCounts::default();
+    let mut matched = HashSet::new();
+    for record in incoming {
+        match local.get(id(record)) {
+            Some(existing) => {
+                matched.insert(id(record));
+                if serde_json::to_value(existing).ok() == serde_json::to_value(record).ok() {
+                    counts.unchanged += 1;
+                } else {
+                    counts.changed += 1;
+                }
+            }
+            None => counts.added += 1,
+        }
+    }
+    counts.removed = local.keys().filter(|id| !matched.contains(*id)).count();
+    counts
+}
+
+fn entry_changes(local: &[StoryEntry], incoming: &[StoryEntry]) -> Vec<EntryChange> {
+    let local_by_id: HashMap<&str, &StoryEntry> =
+        local.iter().map(|e| (e.id.as_str(), e)).collect();
+    let incoming_ids: HashSet<&str> = incoming.iter().map(|e| e.id.as_str()).collect();
+    let changed = incoming
+        .iter()
+        .filter_map(|entry| match local_by_id.get(entry.id.as_str()) {
+            None => Some(EntryChange {
+                entry_id: entry.id.clone(),
+                kind: EntryChangeKind::Added,
+                local_excerpt: None,
+                incoming_excerpt: Some(excerpt(entry)),
+            }),
+            Some(existing)
+                if serde_json::to_value(existing).ok() != serde_json::to_value(entry).ok() =>
+            {
+                Some(EntryChange {
+                    entry_id: entry.id.clone(),
+                    kind: EntryChangeKind::Changed,
+                    local_excerpt: Some(excerpt(existing)),
+                    incoming_excerpt: Some(excerpt(entry)),
+                })
+            }
+            Some(_) => None,
+        });
+    let removed = local
+        .iter()
+        .filter(|e| !incoming_ids.contains(e.id.as_str()))
+        .map(|entry| EntryChange {
+            entry_id: entry.id.clone(),
+            kind: EntryChangeKind::Removed,
+            local_excerpt: Some(excerpt(entry)),
+            incoming_excerpt: None,
+        });
+    changed.chain(removed).take(MAX_LISTED_ENTRIES).collect()
+}
+
+fn diff(
+    local: &StoryExport,
+    incoming: &StoryExport,
+    source: LocalSource,
+) -> Result<ImportDiff, String> {
+    // Records the copies know under different IDs compare as the same record
+    let incoming = &match_ids(local, incoming)?;
+    Ok(ImportDiff {
+        source,
+        local_story_id: local.story.id.clone(),
+        local_title: local.story.title.clone(),
+        local_updated_at: local.story.updated_at,
+        local_is_newer: local.story.updated_at > incoming.story.updated_at,
+        title_changed: local.story.title != incoming.story.title,
+        entries: count_changes(&local.entries, &incoming.entries, |e| &e.id),
+        entry_changes: entry_changes(&local.entries, &incoming.entries),
+        characters: count_changes(&local.characters, &incoming.characters, |c| &c.id),
+        locations: count_changes(&local.locations, &incoming.locations, |l| &l.id),
+        items: count_changes(&local.items, &incoming.items, |i| &i.id),
+        story_beats: count_changes(&local.story_beats, &incoming.story_beats, |b| &b.id),
+        lorebook_entries: count_changes(&local.lorebook_entries, &incoming.lorebook_entries, |l| {
+            &l.id
+        }),
+        chapters: count_changes(&local.chapters, &incoming.chapters, |c| &c.id),
+        branches: count_changes(&local.branches, &incoming.branches, |b| &b.id),
+    })
+}
+
+fn flag(export: &StoryExport, field: &str) -> bool {
+    export
+        .story
+        .extra
+        .get(field)
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+fn rating(export: &StoryExport) -> ContentRating {
+    let extra = &export.story.extra;
+    let warnings: Vec<String> = extra
+        .get("contentWarnings")
+        .and_then(|w| serde_json::from_value(w.clone()).ok())
+        .unwrap_or_default();
+    ContentRating::from_fields(extra.get("ageRating").and_then(|r| r.as_str()), &warnings)
+}
+
+/// Preview an export. The local copy is the one given, if any, or else the
+/// same story in the library database.
+pub async fn preview(
+    app: &AppHandle,
+    json: &str,
+    local_json: Option<&str>,
+) -> Result<ImportPreview, String> {
+    let incoming = StoryExport::from_json(json)?;
+    let story_id = incoming.story.id.clone();
+
+    let local = match local_json {
+        Some(local_json) => {
+            let local = StoryExport::from_json(local_json)?;
+            if local.story.id != story_id && local.story.title != incoming.story.title {
+                return Err("The local copy is a different story".to_string());
+            }
+            Some((local, LocalSource::Provided))
+        }
+        None => match rows::find_story(app, &story_id, &incoming.story.title).await? {
+            Some(local_id) => rows::load(app, &local_id)
+                .await?
+                .map(|local| (local, LocalSource::Library)),
+            None => None,
+        },
+    };
+    let diff = local
+        .map(|(local, source)| diff(&local, &incoming, source))
+        .transpose()?;
+    let archived = archive::list(app)?.iter().any(|s| s.story_id == story_id);
+
+    let mut warnings = warnings(&incoming);
+    if diff.as_ref().is_some_and(|d| d.local_is_newer) {
+        warnings.push("The copy here was changed more recently than this one".to_string());
+    }
+    if archived {
+        warnings.push(
+            "A story with this ID is archived and cannot be restored alongside this one"
+                .to_string(),
+        );
+    }
+
+    let thumbnail = cover::thumbnail_for(app, &story_id);
+    Ok(ImportPreview {
+        story: SyncStoryPreview {
+            id: story_id,
+            title: incoming.story.title.clone(),
+            genre: incoming.story.genre.clone(),
+            updated_at: incoming.story.updated_at,
+            entry_count: incoming.entries.len(),
+            size: None,
+            favorite: flag(&incoming, "favorite"),
+            pinned: flag(&incoming, "pinned"),
+            rating: rating(&incoming),
+            thumbnail,
+        },
+        warnings,
+        diff,
+        archived,
+    })
+}
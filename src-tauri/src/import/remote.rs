@@ -0,0 +1,150 @@
+//! Stories shared as links: an export fetched over HTTPS is validated and
+//! offered to the frontend the same way as a file from the watch folder.
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{redirect, Url};
+use std::time::Duration;
+
+use super::watcher::WatchedImport;
+use crate::story::StoryExport;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Larger downloads are refused, before or while they arrive
+const MAX_DOWNLOAD_BYTES: usize = 50 * 1024 * 1024;
+
+const MAX_REDIRECTS: usize = 5;
+
+/// Content types a JSON download may be served as. Hosts often label
+/// unknown files as octet-stream or text, and a missing type is allowed.
+const ALLOWED_CONTENT_TYPES: [&str; 5] = [
+    "application/json",
+    "application/x-aventura",
+    "application/octet-stream",
+    "text/plain",
+    "text/json",
+];
+
+/// Only HTTPS links are fetched
+pub fn parse_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url.trim()).map_err(|e| format!("Invalid link: {}", e))?;
+    if parsed.scheme() != "https" {
+        return Err("Only https:// links can be imported".to_string());
+    }
+    if parsed.host_str().is_none() {
+        return Err("The link has no host".to_string());
+    }
+    Ok(parsed)
+}
+
+fn check_content_type(content_type: Option<&str>) -> Result<(), String> {
+    let Some(content_type) = content_type else {
+        return Ok(());
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    if ALLOWED_CONTENT_TYPES.contains(&essence.as_str()) {
+        return Ok(());
+    }
+    if essence == "text/html" {
+        return Err(
+            "The link leads to a web page, not a file. Use the direct download link.".to_string(),
+        );
+    }
+    Err(format!(
+        "The link does not lead to a JSON file ({})",
+        essence
+    ))
+}
+
+/// Download a file over HTTPS. Redirects must stay on HTTPS, the content type
+/// must suit a JSON file, and the body is limited to `MAX_DOWNLOAD_BYTES`.
+pub async fn download(url: &Url) -> Result<Vec<u8>, String> {
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .redirect(redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("Too many redirects")
+            } else if attempt.url().scheme() != "https" {
+                attempt.error("Redirected away from https://")
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let mut response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|e| format!("Download failed: {}", e))?
+        .error_for_status()
+        .map_err(|e| format!("Download failed: {}", e))?;
+
+    check_content_type(
+        response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+    )?;
+    let too_large = || {
+        format!(
+            "The download is larger than {} MB",
+            MAX_DOWNLOAD_BYTES / (1024 * 1024)
+        )
+    };
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_DOWNLOAD_BYTES as u64)
+    {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Download failed: {}", e))?
+    {
+        if body.len() + chunk.len() > MAX_DOWNLOAD_BYTES {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Text of a downloaded JSON file, without a byte order mark
+pub fn json_text(body: Vec<u8>) -> Result<String, String> {
+    let text = String::from_utf8(body).map_err(|_| "The file is not valid UTF-8".to_string())?;
+    Ok(match text.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => text,
+    })
+}
+
+/// Download a story export and check that it parses
+pub async fn fetch(url: &Url) -> Result<WatchedImport, String> {
+    let story_json = json_text(download(url).await?)?;
+    let export = StoryExport::from_json(&story_json)?;
+    if export.story.id.trim().is_empty() {
+        return Err("The story has no ID".to_string());
+    }
+    let file_name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("story.avt")
+        .to_string();
+    Ok(WatchedImport {
+        id: String::new(),
+        path: None,
+        file_name,
+        story_id: export.story.id,
+        title: export.story.title,
+        story_json,
+    })
+}
@@ -0,0 +1,278 @@
+//! Watched import folder: story files dropped into it (for example by
+//! Syncthing or Dropbox) are validated and queued for the frontend to
+//! import. A file stays where it is until the frontend reports the import,
+//! then moves into an archive subfolder, or the rejected one if the import
+//! failed. The queue is saved, so stories offered before the app closed are
+//! imported on the next launch.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use super::ImportState;
+use crate::store;
+use crate::story::StoryExport;
+use crate::sync::keys::now_ms;
+
+/// Watch folder settings in the app data directory
+pub const WATCH_CONFIG_FILE: &str = "import_watch.json";
+
+/// Stories offered to the frontend and not imported yet
+const PENDING_IMPORTS_FILE: &str = "pending_imports.json";
+
+/// Subfolders for processed files
+const ARCHIVE_DIR: &str = "imported";
+const REJECTED_DIR: &str = "rejected";
+
+const EXTENSIONS: [&str; 3] = ["aventura", "avt", "json"];
+
+/// How long a file's size must stay unchanged before it is read, so files
+/// still being written by a sync client are not picked up half-way
+const SETTLE_DELAY: Duration = Duration::from_millis(1500);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchFolderConfig {
+    pub enabled: bool,
+    pub folder: Option<String>,
+}
+
+/// A validated story waiting to be imported, from the watch folder or a link
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedImport {
+    /// Set when the story is queued; the frontend passes it back once imported
+    #[serde(default)]
+    pub id: String,
+    /// The watched file, moved aside once the import is finished
+    #[serde(default)]
+    pub path: Option<String>,
+    pub file_name: String,
+    pub story_id: String,
+    pub title: String,
+    pub story_json: String,
+}
+
+/// Payload of the `import://rejected` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedImport {
+    pub file_name: String,
+    pub error: String,
+}
+
+pub fn is_story_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Move a processed file aside, prefixing a timestamp so names never collide
+fn move_to(path: &Path, subfolder: &str) -> Result<(), String> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(());
+    };
+    let dir = parent.join(subfolder);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let target = dir.join(format!("{}-{}", now_ms(), name.to_string_lossy()));
+    fs::rename(path, &target).map_err(|e| format!("Failed to move {}: {}", path.display(), e))
+}
+
+/// Wait until a file stops growing. Returns false if it disappeared.
+async fn settle(path: &Path) -> bool {
+    let mut last = None;
+    loop {
+        let Ok(meta) = tokio::fs::metadata(path).await else {
+            return false;
+        };
+        if last == Some(meta.len()) {
+            return true;
+        }
+        last = Some(meta.len());
+        tokio::time::sleep(SETTLE_DELAY).await;
+    }
+}
+
+/// Queue a validated story for the frontend and announce it. A watched
+/// file already in the queue is not offered twice.
+pub async fn offer(app: &AppHandle, mut import: WatchedImport) {
+    let state = app.state::<ImportState>();
+    let mut pending = state.pending.lock().await;
+    if import.path.is_some() && pending.iter().any(|p| p.path == import.path) {
+        return;
+    }
+    import.id = Uuid::new_v4().to_string();
+    pending.push(import.clone());
+    if let Err(e) = store::save_json(app, PENDING_IMPORTS_FILE, &*pending) {
+        eprintln!("Watched import: {}", e);
+    }
+    drop(pending);
+    let _ = app.emit("import://story", import);
+}
+
+/// Take a story off the queue once the frontend has imported it, or failed
+/// to. A watched file goes to the archive or, with an error, the rejected
+/// folder.
+pub async fn finish(app: &AppHandle, id: &str, error: Option<String>) -> Result<(), String> {
+    let state = app.state::<ImportState>();
+    let mut pending = state.pending.lock().await;
+    let index = pending
+        .iter()
+        .position(|p| p.id == id)
+        .ok_or_else(|| format!("No pending import: {}", id))?;
+    let import = pending.remove(index);
+    store::save_json(app, PENDING_IMPORTS_FILE, &*pending)?;
+    drop(pending);
+
+    let Some(path) = import.path.as_deref().map(Path::new) else {
+        return Ok(());
+    };
+    if error.is_some() {
+        move_to(path, REJECTED_DIR)
+    } else {
+        move_to(path, ARCHIVE_DIR)
+    }
+}
+
+/// Read and validate a story file
+async fn read_story(path: &Path, file_name: String) -> Result<WatchedImport, String> {
+    let story_json = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let export = StoryExport::from_json(&story_json)?;
+    Ok(WatchedImport {
+        id: String::new(),
+        path: None,
+        file_name,
+        story_id: export.story.id,
+        title: export.story.title,
+        story_json,
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Offer a story file the user opened with the app. Unlike watched files it
+/// is left where it is.
+pub async fn open_file(app: &AppHandle, path: &Path) {
+    let file_name = file_name(path);
+    match read_story(path, file_name.clone()).await {
+        Ok(import) => offer(app, import).await,
+        Err(error) => {
+            let _ = app.emit("import://rejected", RejectedImport { file_name, error });
+        }
+    }
+}
+
+async fn process(app: &AppHandle, path: PathBuf) {
+    if !is_story_file(&path) || !settle(&path).await {
+        return;
+    }
+    let file_name = file_name(&path);
+    match read_story(&path, file_name.clone()).await {
+        Ok(import) => {
+            let path = Some(path.to_string_lossy().to_string());
+            offer(app, WatchedImport { path, ..import }).await;
+        }
+        Err(error) => {
+            if let Err(e) = move_to(&path, REJECTED_DIR) {
+                eprintln!("Watched import: {}", e);
+            }
+            let _ = app.emit("import://rejected", RejectedImport { file_name, error });
+        }
+    }
+}
+
+/// Watch a folder, processing files already in it and any added later.
+/// Dropping the returned watcher stops it.
+pub fn start(app: &AppHandle, folder: &str) -> Result<RecommendedWatcher, String> {
+    let folder = PathBuf::from(folder);
+    if !folder.is_dir() {
+        return Err(format!("Not a folder: {}", folder.display()));
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+    let events = tx.clone();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        if let Ok(event) = result {
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                for path in event.paths {
+                    let _ = events.send(path);
+                }
+            }
+        }
+    })
+    .map_err(|e| format!("Failed to start folder watcher: {}", e))?;
+    watcher
+        .watch(&folder, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch {}: {}", folder.display(), e))?;
+
+    if let Ok(existing) = fs::read_dir(&folder) {
+        for entry in existing.flatten() {
+            let _ = tx.send(entry.path());
+        }
+    }
+
+    // The channel closes when the watcher, which owns the last sender, is dropped
+    drop(tx);
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        // Writes fire many events per file; a file already queued is not
+        // offered again, and one moved aside is skipped
+        while let Some(path) = rx.recv().await {
+            process(&app, path).await;
+        }
+    });
+    Ok(watcher)
+}
+
+fn load_pending(app: &AppHandle) -> Vec<WatchedImport> {
+    store::load_json(app, PENDING_IMPORTS_FILE).unwrap_or_else(|e| {
+        eprintln!("Watched import: {}", e);
+        Vec::new()
+    })
+}
+
+/// Stop the watcher and start the active profile's instead, with the
+/// stories that profile had not imported yet
+pub async fn restart(app: &AppHandle) {
+    let state = app.state::<ImportState>();
+    *state.pending.lock().await = load_pending(app);
+    *state.watcher.lock().await = None;
+    watch(app);
+}
+
+/// Restore the queue at launch and start the watcher if it was enabled
+pub fn resume(app: &AppHandle) {
+    if let Ok(mut pending) = app.state::<ImportState>().pending.try_lock() {
+        *pending = load_pending(app);
+    }
+    watch(app);
+}
+
+fn watch(app: &AppHandle) {
+    let config: WatchFolderConfig = store::load_json(app, WATCH_CONFIG_FILE).unwrap_or_default();
+    let Some(folder) = config.folder.filter(|_| config.enabled) else {
+        return;
+    };
+    match start(app, &folder) {
+        Ok(watcher) => {
+            let state = app.state::<ImportState>();
+            if let Ok(mut slot) = state.watcher.try_lock() {
+                *slot = Some(watcher);
+            };
+        }
+        Err(e) => eprintln!("Watched import: {}", e),
+    }
+}
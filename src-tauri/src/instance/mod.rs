@@ -0,0 +1,242 @@
+//! Only one Aventura process runs per user. The first one to start holds a
+//! lock file in the app config directory and listens on a loopback port
+//! recorded next to it. A second launch, for example from opening a story
+//! file or an `aventura://` link, hands its arguments to that port and exits
+//! instead of opening the same database a second time. The running instance
+//! brings its window forward and opens what it was sent.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::deeplink::{self, SCHEME};
+use crate::import::watcher;
+use crate::location;
+
+/// Held by the running instance
+const LOCK_FILE: &str = "instance.lock";
+
+/// Port and token of the running instance
+const ENDPOINT_FILE: &str = "instance.json";
+
+/// How long a second launch keeps trying to reach the first, which may
+/// still be starting up
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Longest message accepted from a second launch
+const MAX_MESSAGE_BYTES: u64 = 64 * 1024;
+
+const ACK: &[u8] = b"ok";
+
+/// State managed by Tauri for the single-instance lock
+#[derive(Default)]
+pub struct InstanceState {
+    /// Released when the app exits
+    lock: Mutex<Option<File>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Endpoint {
+    port: u16,
+    /// Keeps other local programs from driving the app through the port
+    token: String,
+}
+
+/// What a second launch was started with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Launch {
+    token: String,
+    args: Vec<String>,
+    /// Relative file arguments are resolved against this
+    cwd: PathBuf,
+}
+
+fn open_lock(dir: &Path) -> Result<File, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))
+        .map_err(|e| format!("Failed to open {}: {}", LOCK_FILE, e))
+}
+
+fn read_endpoint(dir: &Path) -> Option<Endpoint> {
+    let json = fs::read_to_string(dir.join(ENDPOINT_FILE)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+fn write_endpoint(dir: &Path, endpoint: &Endpoint) -> Result<(), String> {
+    let path = dir.join(ENDPOINT_FILE);
+    let json = serde_json::to_string(endpoint)
+        .map_err(|e| format!("Failed to serialize {}: {}", ENDPOINT_FILE, e))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write {}: {}", ENDPOINT_FILE, e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to save {}: {}", ENDPOINT_FILE, e))
+}
+
+fn send(endpoint: &Endpoint, launch: &Launch) -> io::Result<()> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, endpoint.port));
+    let mut stream = TcpStream::connect_timeout(&addr, RETRY_DELAY)?;
+    stream.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+    stream.write_all(&serde_json::to_vec(launch)?)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut reply = Vec::new();
+    stream.take(ACK.len() as u64).read_to_end(&mut reply)?;
+    if reply == ACK {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "launch was refused",
+        ))
+    }
+}
+
+/// Hand this launch's arguments to the running instance, waiting for it to
+/// finish starting if its port is not open yet
+fn forward(dir: &Path) -> Result<(), String> {
+    let deadline = Instant::now() + FORWARD_TIMEOUT;
+    let mut last_error = String::from("it has not opened its port");
+    loop {
+        if let Some(endpoint) = read_endpoint(dir) {
+            let launch = Launch {
+                token: endpoint.token.clone(),
+                args: std::env::args().skip(1).collect(),
+                cwd: std::env::current_dir().unwrap_or_default(),
+            };
+            match send(&endpoint, &launch) {
+                Ok(()) => return Ok(()),
+                Err(e) => last_error = e.to_string(),
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "Aventura is already running but did not respond: {}",
+                last_error
+            ));
+        }
+        std::thread::sleep(RETRY_DELAY);
+    }
+}
+
+/// Bring the main window to the front
+fn focus(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Open story files among the arguments and, when `links` is set,
+/// `aventura://` links. Flags and anything else are ignored.
+async fn open_args(app: &AppHandle, args: Vec<String>, cwd: &Path, links: bool) {
+    for arg in args {
+        if arg.starts_with('-') {
+            continue;
+        }
+        if let Ok(url) = reqwest::Url::parse(&arg) {
+            if url.scheme() == SCHEME {
+                if links {
+                    deeplink::handle(app, url).await;
+                }
+                continue;
+            }
+        }
+        let path = cwd.join(&arg);
+        if watcher::is_story_file(&path) {
+            watcher::open_file(app, &path).await;
+        }
+    }
+}
+
+async fn accept(app: &AppHandle, mut stream: tokio::net::TcpStream, token: &str) {
+    let mut buf = Vec::new();
+    let mut limited = (&mut stream).take(MAX_MESSAGE_BYTES);
+    let read = limited.read_to_end(&mut buf);
+    if !matches!(tokio::time::timeout(FORWARD_TIMEOUT, read).await, Ok(Ok(_))) {
+        return;
+    }
+    let Ok(launch) = serde_json::from_slice::<Launch>(&buf) else {
+        return;
+    };
+    if launch.token != token {
+        return;
+    }
+    let _ = stream.write_all(ACK).await;
+    drop(stream);
+    focus(app);
+    open_args(app, launch.args, &launch.cwd, true).await;
+}
+
+fn listen(app: &AppHandle, listener: TcpListener, token: String) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let listener = match tokio::net::TcpListener::from_std(listener) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to listen for other launches: {}", e);
+                return;
+            }
+        };
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let app = app.clone();
+            let token = token.clone();
+            tauri::async_runtime::spawn(async move { accept(&app, stream, &token).await });
+        }
+    });
+}
+
+/// Become the running instance, or pass this launch on to the one already
+/// running. Returns false in the second case, when the caller should exit.
+pub fn start(app: &AppHandle) -> Result<bool, String> {
+    let dir = location::config_dir(app)?;
+    let file = open_lock(&dir)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(fs::TryLockError::WouldBlock) => {
+            forward(&dir)?;
+            return Ok(false);
+        }
+        Err(fs::TryLockError::Error(e)) => {
+            return Err(format!("Failed to lock {}: {}", LOCK_FILE, e));
+        }
+    }
+    if let Ok(mut held) = app.state::<InstanceState>().lock.lock() {
+        *held = Some(file);
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        .map_err(|e| format!("Failed to open instance port: {}", e))?;
+    let endpoint = Endpoint {
+        port: listener
+            .local_addr()
+            .map_err(|e| format!("Failed to open instance port: {}", e))?
+            .port(),
+        token: uuid::Uuid::new_v4().simple().to_string(),
+    };
+    write_endpoint(&dir, &endpoint)?;
+    listen(app, listener, endpoint.token);
+
+    // Links the app was launched with come through the deep link plugin
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        open_args(&app, std::env::args().skip(1).collect(), &cwd, false).await;
+    });
+    Ok(true)
+}
@@ -1,15 +1,154 @@
 use tauri_plugin_sql::{Migration, MigrationKind};
 
+mod ai;
+mod annotations;
+mod api;
+mod attachments;
+mod audio;
+mod deeplink;
+mod export;
+mod gallery;
+mod game;
+mod generate;
+mod hardware;
+mod history;
+mod import;
+mod instance;
+mod library;
+mod location;
+mod lorebook;
+mod map;
+mod portable;
+mod profiles;
+mod proofing;
+mod publish;
+mod rng;
+mod stats;
+mod storage;
+mod store;
+mod story;
+mod style;
 mod sync;
+mod tables;
+mod webhooks;
 
+use ai::commands::{
+    ai_cancel, ai_regenerate, ai_stream, benchmark_local_model, clear_ai_trace, delete_ai_profile,
+    delete_prompt_experiment, delete_sampling_preset, export_ai_profiles, export_ai_trace,
+    generate_beats, get_ai_profile, get_ai_trace_config, get_filter_config, get_postprocess_config,
+    import_ai_profiles, list_ai_profiles, list_local_benchmarks, list_prompt_experiments,
+    list_sampling_presets, preview_context, run_prompt_experiment, save_ai_profile,
+    save_sampling_preset, set_ai_trace_config, set_filter_config, set_postprocess_config,
+    set_story_filter_strictness, suggest_metadata, test_filter, test_postprocess,
+    translate_entries, translate_sampling,
+};
+use annotations::commands::{
+    add_annotation, delete_annotation, import_feedback, list_annotations, list_open_todos,
+    update_annotation,
+};
+use api::commands::{
+    create_api_token, list_api_tokens, revoke_api_token, start_local_api, stop_local_api,
+    update_local_api_stories,
+};
+use attachments::commands::{
+    get_attachment, get_attachment_data_url, import_attachment_from_clipboard,
+};
+use audio::commands::{
+    get_ambience_status, get_story_ambience, play_ambience, set_ambience_volume,
+    set_scene_ambience, stop_ambience, update_story_ambience,
+};
+use deeplink::commands::take_pending_deep_links;
+use export::commands::{
+    delete_export_rule, export_audiobook, export_changes, export_character_card, export_story_site,
+    export_story_twine, export_to_obsidian, generate_cover, generate_story_summary,
+    list_export_rules, run_export_rule, save_export_rule,
+};
+use gallery::commands::{
+    browse_gallery, get_gallery_config, install_gallery_item, set_gallery_config,
+};
+use game::commands::{
+    add_item, adjust_currency, create_quest, delete_character_sheet, delete_item_definition,
+    delete_quest, end_combat, end_game_session, end_play_session, game_submit_action, get_combat,
+    get_game_status, get_inventory, get_quest_reminder, get_quest_reminder_config,
+    get_recap_config, get_session_recaps, get_spectator_count, get_stat_block,
+    get_stat_block_config, list_character_sheets, list_quests, publish_spectator_entry,
+    remove_item, resolve_combat_round, save_character_sheet, save_item_definition,
+    set_quest_reminder_config, set_recap_config, set_stat_block_config, start_combat,
+    start_game_session, start_spectator_mode, stop_spectator_mode, transfer_item, update_quest,
+};
+use generate::commands::{generate_names, generate_world_seed, list_name_cultures};
+use hardware::commands::get_hardware_capabilities;
+use history::commands::{
+    commit_story_history, get_git_history_config, get_story_at_revision, get_story_history,
+    push_story_history, set_git_history_config,
+};
+use import::commands::{
+    finish_watched_import, get_watch_folder_config, get_watched_imports, import_character_card,
+    import_story_from_url, preview_import, read_character_card, set_watch_folder_config,
+};
+use library::commands::bulk_update_stories;
+use location::commands::{get_data_directory, set_data_directory};
+use lorebook::commands::{
+    apply_lorebook, create_lorebook, delete_lorebook, export_lorebook, get_lorebook,
+    import_lorebook, list_lorebooks, set_lorebook_shared,
+};
+use map::commands::{
+    add_map_location, connect_locations, disconnect_locations, get_reachable_locations,
+    get_story_map, move_party, render_story_map,
+};
+use profiles::commands::{
+    create_profile, delete_profile, filter_visible_stories, get_active_profile, list_profiles,
+    set_profile_pin, switch_profile, update_profile,
+};
+use proofing::commands::{
+    add_to_dictionary, check_text, get_story_dictionary, install_dictionary, list_dictionaries,
+    remove_from_dictionary, spellcheck,
+};
+use publish::commands::{
+    get_story_publication, publish_story, unpublish_story, update_published_story,
+};
+use stats::commands::{
+    get_focus_session, get_goal_progress, get_writing_goals, list_focus_sessions,
+    set_writing_goals, start_focus_session, stop_focus_session,
+};
+use storage::commands::{get_storage_report, reclaim_space};
+use story::commands::{
+    acquire_story_lock, answer_story_interview, archive_story, cancel_story_interview,
+    check_consistency, check_story_writable, combine_stories, delete_story, extract_timeline,
+    get_relationship_graph, get_sanitize_rules, get_story_graph, get_story_lock, get_story_rating,
+    get_story_revision, get_story_version, get_timeline, get_undo_config, list_archived_stories,
+    list_story_locks, list_story_versions, list_trashed_stories, list_undoable_operations,
+    merge_stories, record_import_overwrite, release_story_lock, sanitize_story, save_story,
+    set_sanitize_rules, set_story_rating, set_undo_config, simulate_playthroughs, split_story,
+    start_story_interview, unarchive_story, undo_last_operation,
+};
+use style::commands::{
+    build_style_guide, delete_style_reference, list_style_references, save_style_reference,
+    set_default_style_reference,
+};
 use sync::commands::{
-    clear_received_stories, get_received_stories, start_sync_server, stop_sync_server,
-    sync_connect, sync_pull_story, sync_push_story,
+    apply_received_settings, apply_remote_deletions, cancel_pending_sync_op,
+    clear_received_stories, confirm_key_exchange, decline_remote_deletions, discard_partial_story,
+    get_device_profile, get_keychain_api_key, get_partial_story, get_pending_key_exchange,
+    get_received_stories, get_server_access_log, get_sync_favorites_config, get_sync_http_tuning,
+    get_sync_metrics, get_sync_server_status, get_sync_spill_config, list_network_interfaces,
+    list_partial_stories, list_pending_deletions, list_pending_sync_ops, list_read_shares,
+    list_sync_sessions, publish_opds_catalog, revoke_read_share, serve_sync_on_device,
+    set_device_profile, set_sync_favorites_config, set_sync_http_tuning, set_sync_spill_config,
+    share_story_read_only, share_sync_settings, start_sync_server, stop_sync_server,
+    sync_begin_key_exchange, sync_check_health, sync_close_session, sync_connect,
+    sync_device_request, sync_from_folder, sync_open_session, sync_plan, sync_pull_settings,
+    sync_pull_story, sync_push_settings, sync_push_story, sync_send_api_keys,
+    sync_session_deletions, sync_session_list, sync_session_list_lorebooks, sync_session_pull,
+    sync_session_pull_entries, sync_session_pull_lorebook, sync_session_push, sync_to_folder,
+    unpublish_opds_catalog,
 };
+use tables::commands::{delete_table_pack, import_table_pack, list_table_packs, roll_table};
+use webhooks::commands::{get_webhooks, notify_webhook_event, save_webhooks, test_webhook};
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    let migrations = vec![
+/// Database schema migrations, shared by every profile's database
+fn migrations() -> Vec<Migration> {
+    vec![
         Migration {
             version: 1,
             description: "create_initial_tables",
@@ -100,14 +239,98 @@ pub fn run() {
             sql: include_str!("../migrations/015_branch_world_state.sql"),
             kind: MigrationKind::Up,
         },
-    ];
+        Migration {
+            version: 16,
+            description: "story_tags",
+            sql: include_str!("../migrations/016_story_tags.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 17,
+            description: "story_favorites",
+            sql: include_str!("../migrations/017_story_favorites.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 18,
+            description: "story_ratings",
+            sql: include_str!("../migrations/018_story_ratings.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 19,
+            description: "story_graph",
+            sql: include_str!("../migrations/019_story_graph.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 20,
+            description: "story_series",
+            sql: include_str!("../migrations/020_story_series.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 21,
+            description: "story_ambience",
+            sql: include_str!("../migrations/021_story_ambience.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 22,
+            description: "story_revisions",
+            sql: include_str!("../migrations/022_story_revisions.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 23,
+            description: "ai_profiles",
+            sql: include_str!("../migrations/023_ai_profiles.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 24,
+            description: "story_variants",
+            sql: include_str!("../migrations/024_story_variants.sql"),
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 25,
+            description: "story_dictionaries",
+            sql: include_str!("../migrations/025_story_dictionaries.sql"),
+            kind: MigrationKind::Up,
+        },
+    ]
+}
 
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
     tauri::Builder::default()
         .manage(sync::SyncState::default())
+        .manage(ai::AiState::default())
+        .manage(ai::TraceState::default())
+        .manage(ai::BenchmarkState::default())
+        .manage(hardware::HardwareState::default())
+        .manage(proofing::ProofingState::default())
+        .manage(api::ApiState::default())
+        .manage(import::ImportState::default())
+        .manage(history::HistoryState::default())
+        .manage(export::ExportState::default())
+        .manage(story::StoryState::default())
+        .manage(audio::AudioState::default())
+        .manage(stats::StatsState::default())
+        .manage(annotations::AnnotationState::default())
+        .manage(game::SheetState::default())
+        .manage(game::InventoryState::default())
+        .manage(game::ActiveCombats::default())
+        .manage(game::RecapState::default())
+        .manage(deeplink::DeepLinkState::default())
+        .manage(profiles::ProfileState::default())
+        .manage(location::LocationState::default())
+        .manage(instance::InstanceState::default())
         .plugin(tauri_plugin_opener::init())
         .plugin(
             tauri_plugin_sql::Builder::default()
-                .add_migrations("sqlite:aventura.db", migrations)
+                .add_migrations(profiles::PRIMARY_DATABASE_URL, migrations())
                 .build(),
         )
         .plugin(tauri_plugin_fs::init())
@@ -115,14 +338,301 @@ pub fn run() {
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_http::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_deep_link::init())
+        .setup(|app| {
+            if !instance::start(app.handle())? {
+                std::process::exit(0);
+            }
+            location::start(app.handle())?;
+            tauri::async_runtime::block_on(profiles::migrate_active(app.handle()))?;
+            sync::outbox::resume(app.handle());
+            import::watcher::resume(app.handle());
+            export::schedule::resume(app.handle());
+            stats::goals::resume(app.handle());
+            game::recaps::resume(app.handle());
+            deeplink::register(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             start_sync_server,
             stop_sync_server,
+            list_network_interfaces,
+            get_device_profile,
+            set_device_profile,
+            get_sync_metrics,
+            get_sync_server_status,
+            get_server_access_log,
+            get_sync_spill_config,
+            set_sync_spill_config,
+            get_sync_http_tuning,
+            set_sync_http_tuning,
+            get_sync_favorites_config,
+            set_sync_favorites_config,
             get_received_stories,
             clear_received_stories,
+            sync_check_health,
             sync_connect,
             sync_pull_story,
+            sync_open_session,
+            sync_session_list,
+            sync_plan,
+            sync_session_pull,
+            sync_session_pull_entries,
+            list_partial_stories,
+            get_partial_story,
+            discard_partial_story,
+            sync_session_push,
+            sync_close_session,
+            list_sync_sessions,
+            sync_session_deletions,
+            sync_session_list_lorebooks,
+            sync_session_pull_lorebook,
+            list_pending_deletions,
+            apply_remote_deletions,
+            decline_remote_deletions,
             sync_push_story,
+            list_pending_sync_ops,
+            cancel_pending_sync_op,
+            publish_opds_catalog,
+            unpublish_opds_catalog,
+            share_story_read_only,
+            list_read_shares,
+            revoke_read_share,
+            share_sync_settings,
+            apply_received_settings,
+            sync_pull_settings,
+            sync_push_settings,
+            get_pending_key_exchange,
+            confirm_key_exchange,
+            sync_begin_key_exchange,
+            sync_send_api_keys,
+            get_keychain_api_key,
+            serve_sync_on_device,
+            sync_device_request,
+            sync_to_folder,
+            sync_from_folder,
+            ai_stream,
+            ai_cancel,
+            ai_regenerate,
+            get_filter_config,
+            set_filter_config,
+            set_story_filter_strictness,
+            test_filter,
+            translate_entries,
+            suggest_metadata,
+            generate_beats,
+            get_ai_profile,
+            list_ai_profiles,
+            save_ai_profile,
+            delete_ai_profile,
+            export_ai_profiles,
+            import_ai_profiles,
+            get_ai_trace_config,
+            set_ai_trace_config,
+            export_ai_trace,
+            clear_ai_trace,
+            run_prompt_experiment,
+            list_prompt_experiments,
+            delete_prompt_experiment,
+            benchmark_local_model,
+            list_local_benchmarks,
+            get_hardware_capabilities,
+            preview_context,
+            list_sampling_presets,
+            save_sampling_preset,
+            delete_sampling_preset,
+            translate_sampling,
+            get_postprocess_config,
+            set_postprocess_config,
+            test_postprocess,
+            list_style_references,
+            save_style_reference,
+            delete_style_reference,
+            set_default_style_reference,
+            build_style_guide,
+            check_text,
+            spellcheck,
+            get_story_dictionary,
+            add_to_dictionary,
+            remove_from_dictionary,
+            list_dictionaries,
+            install_dictionary,
+            export_audiobook,
+            export_story_site,
+            export_story_twine,
+            export_to_obsidian,
+            generate_story_summary,
+            export_changes,
+            generate_cover,
+            export_character_card,
+            list_export_rules,
+            save_export_rule,
+            delete_export_rule,
+            run_export_rule,
+            get_story_graph,
+            check_consistency,
+            extract_timeline,
+            get_timeline,
+            get_relationship_graph,
+            simulate_playthroughs,
+            merge_stories,
+            list_story_versions,
+            get_story_version,
+            acquire_story_lock,
+            release_story_lock,
+            get_story_lock,
+            list_story_locks,
+            check_story_writable,
+            save_story,
+            get_story_revision,
+            split_story,
+            combine_stories,
+            get_sanitize_rules,
+            set_sanitize_rules,
+            sanitize_story,
+            archive_story,
+            unarchive_story,
+            list_archived_stories,
+            list_trashed_stories,
+            create_lorebook,
+            list_lorebooks,
+            get_lorebook,
+            delete_lorebook,
+            set_lorebook_shared,
+            export_lorebook,
+            import_lorebook,
+            apply_lorebook,
+            get_story_rating,
+            set_story_rating,
+            delete_story,
+            record_import_overwrite,
+            list_undoable_operations,
+            undo_last_operation,
+            start_story_interview,
+            answer_story_interview,
+            cancel_story_interview,
+            bulk_update_stories,
+            get_undo_config,
+            set_undo_config,
+            get_storage_report,
+            reclaim_space,
+            get_data_directory,
+            set_data_directory,
+            start_game_session,
+            end_game_session,
+            get_game_status,
+            game_submit_action,
+            start_spectator_mode,
+            stop_spectator_mode,
+            publish_spectator_entry,
+            get_spectator_count,
+            list_character_sheets,
+            save_character_sheet,
+            delete_character_sheet,
+            get_stat_block_config,
+            set_stat_block_config,
+            get_stat_block,
+            start_combat,
+            resolve_combat_round,
+            get_combat,
+            end_combat,
+            get_inventory,
+            save_item_definition,
+            delete_item_definition,
+            add_item,
+            remove_item,
+            transfer_item,
+            adjust_currency,
+            list_quests,
+            create_quest,
+            update_quest,
+            delete_quest,
+            get_quest_reminder_config,
+            set_quest_reminder_config,
+            get_quest_reminder,
+            get_session_recaps,
+            end_play_session,
+            get_recap_config,
+            set_recap_config,
+            get_story_map,
+            add_map_location,
+            connect_locations,
+            disconnect_locations,
+            move_party,
+            get_reachable_locations,
+            render_story_map,
+            import_table_pack,
+            list_table_packs,
+            delete_table_pack,
+            roll_table,
+            list_name_cultures,
+            generate_names,
+            generate_world_seed,
+            get_webhooks,
+            save_webhooks,
+            test_webhook,
+            notify_webhook_event,
+            start_local_api,
+            stop_local_api,
+            update_local_api_stories,
+            create_api_token,
+            list_api_tokens,
+            revoke_api_token,
+            get_git_history_config,
+            set_git_history_config,
+            commit_story_history,
+            get_story_history,
+            get_story_at_revision,
+            push_story_history,
+            get_watch_folder_config,
+            set_watch_folder_config,
+            get_watched_imports,
+            finish_watched_import,
+            import_story_from_url,
+            preview_import,
+            import_character_card,
+            read_character_card,
+            get_gallery_config,
+            set_gallery_config,
+            browse_gallery,
+            install_gallery_item,
+            publish_story,
+            update_published_story,
+            unpublish_story,
+            get_story_publication,
+            list_profiles,
+            get_active_profile,
+            switch_profile,
+            create_profile,
+            update_profile,
+            set_profile_pin,
+            delete_profile,
+            filter_visible_stories,
+            take_pending_deep_links,
+            import_attachment_from_clipboard,
+            get_attachment,
+            get_attachment_data_url,
+            play_ambience,
+            stop_ambience,
+            set_ambience_volume,
+            get_ambience_status,
+            set_scene_ambience,
+            get_story_ambience,
+            update_story_ambience,
+            start_focus_session,
+            stop_focus_session,
+            get_focus_session,
+            list_focus_sessions,
+            get_writing_goals,
+            set_writing_goals,
+            get_goal_progress,
+            list_annotations,
+            add_annotation,
+            update_annotation,
+            delete_annotation,
+            list_open_todos,
+            import_feedback,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
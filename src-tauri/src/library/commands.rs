@@ -0,0 +1,29 @@
+use tauri::{AppHandle, State};
+
+use super::{BulkReport, StoryPatch};
+use crate::profiles;
+use crate::story::lock::LockReason;
+use crate::story::StoryState;
+
+/// Retag, change the genre of, archive, delete or export many stories in one
+/// call. Progress is reported per story with `library://progress`; a bulk
+/// delete can be reversed with `undo_last_operation`.
+#[tauri::command]
+pub async fn bulk_update_stories(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+    ids: Vec<String>,
+    patch: StoryPatch,
+) -> Result<BulkReport, String> {
+    let mut story_ids: Vec<String> = Vec::with_capacity(ids.len());
+    for id in ids {
+        profiles::check_story(&app, &id).await?;
+        if !story_ids.contains(&id) {
+            story_ids.push(id);
+        }
+    }
+    let refs: Vec<&str> = story_ids.iter().map(String::as_str).collect();
+    let _locks = state.locks.try_acquire_all(&refs, LockReason::Bulk)?;
+    let _save = state.saves.lock().await;
+    super::bulk_update(&app, &story_ids, &patch).await
+}
@@ -0,0 +1,314 @@
+//! Library-wide operations on many stories at once: retagging, changing
+//! genres, archiving, deleting and exporting. Metadata changes for every
+//! story go through one database transaction, as does a bulk delete, and
+//! `library://progress` reports each story as it is done.
+
+pub mod commands;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use tauri::{AppHandle, Emitter};
+
+use crate::annotations;
+use crate::export::schedule::slug;
+use crate::profiles::{self, database};
+use crate::story::journal::{self, UndoableOperation};
+use crate::story::{archive, rows};
+use crate::sync::keys::now_ms;
+use crate::sync::tombstones;
+
+/// Longest tag accepted, in characters
+const MAX_TAG_CHARS: usize = 64;
+
+/// Changes applied to every selected story
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StoryPatch {
+    /// New genre; an empty string clears it
+    pub genre: Option<String>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    pub favorite: Option<bool>,
+    /// Pinned stories are kept at the top of the library
+    pub pinned: Option<bool>,
+    /// Done after the metadata changes
+    pub action: Option<BulkAction>,
+}
+
+impl StoryPatch {
+    fn changes_metadata(&self) -> bool {
+        self.genre.is_some()
+            || !self.add_tags.is_empty()
+            || !self.remove_tags.is_empty()
+            || self.favorite.is_some()
+            || self.pinned.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum BulkAction {
+    Archive,
+    /// Delete in one step that `undo_last_operation` reverses
+    Delete,
+    /// Write each story to the folder in Aventura format. Annotations are
+    /// left out unless asked for.
+    #[serde(rename_all = "camelCase")]
+    Export {
+        folder: String,
+        #[serde(default)]
+        include_annotations: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BulkPhase {
+    Update,
+    Archive,
+    Delete,
+    Export,
+}
+
+/// Payload of the `library://progress` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkProgress {
+    pub phase: BulkPhase,
+    pub done: usize,
+    pub total: usize,
+    pub story_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkFailure {
+    pub story_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkReport {
+    pub updated: usize,
+    pub archived: usize,
+    pub deleted: usize,
+    /// Files written by an export
+    pub exported: Vec<String>,
+    /// Stories left as they were, with why
+    pub failed: Vec<BulkFailure>,
+    /// Set when stories were deleted
+    pub undo: Option<UndoableOperation>,
+}
+
+fn progress(app: &AppHandle, phase: BulkPhase, done: usize, total: usize, story_id: &str) {
+    let _ = app.emit(
+        "library://progress",
+        BulkProgress {
+            phase,
+            done,
+            total,
+            story_id: story_id.to_string(),
+        },
+    );
+}
+
+fn normalize_tags(tags: &[String]) -> Result<BTreeSet<String>, String> {
+    let mut normalized = BTreeSet::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_CHARS {
+            return Err(format!("Tags can be at most {} characters", MAX_TAG_CHARS));
+        }
+        normalized.insert(tag.to_string());
+    }
+    Ok(normalized)
+}
+
+/// Apply the genre and tag changes to every story in one transaction.
+/// Returns the stories that exist; the rest are reported as failed.
+async fn update_metadata(
+    app: &AppHandle,
+    story_ids: &[String],
+    patch: &StoryPatch,
+    report: &mut BulkReport,
+) -> Result<Vec<String>, String> {
+    let add = normalize_tags(&patch.add_tags)?;
+    let remove = normalize_tags(&patch.remove_tags)?;
+    let genre = patch
+        .genre
+        .as_deref()
+        .map(str::trim)
+        .map(|g| (!g.is_empty()).then(|| g.to_string()));
+    let now = now_ms();
+
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let updated = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        let mut found = Vec::with_capacity(story_ids.len());
+        for (index, story_id) in story_ids.iter().enumerate() {
+            let row: Option<(Option<String>, Option<String>)> =
+                sqlx::query_as("SELECT genre, tags FROM stories WHERE id = ?")
+                    .bind(story_id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|e| format!("Failed to read stories: {}", e))?;
+            let Some((current_genre, tags)) = row else {
+                report.failed.push(BulkFailure {
+                    story_id: story_id.clone(),
+                    error: "Story not found".to_string(),
+                });
+                continue;
+            };
+            if patch.changes_metadata() {
+                let mut tags: BTreeSet<String> = tags
+                    .and_then(|t| serde_json::from_str(&t).ok())
+                    .unwrap_or_default();
+                tags.extend(add.iter().cloned());
+                tags.retain(|t| !remove.contains(t));
+                let tags = serde_json::to_string(&tags)
+                    .map_err(|e| format!("Failed to serialize tags: {}", e))?;
+                sqlx::query(
+                    "UPDATE stories SET genre = ?, tags = ?, favorite = COALESCE(?, favorite), \
+                     pinned = COALESCE(?, pinned), updated_at = ? WHERE id = ?",
+                )
+                .bind(genre.clone().unwrap_or(current_genre))
+                .bind(tags)
+                .bind(patch.favorite)
+                .bind(patch.pinned)
+                .bind(now)
+                .bind(story_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to update story: {}", e))?;
+                progress(app, BulkPhase::Update, index + 1, story_ids.len(), story_id);
+            }
+            found.push(story_id.clone());
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to update stories: {}", e))?;
+        Ok::<_, String>(found)
+    }
+    .await;
+    pool.close().await;
+    let found = updated?;
+    if patch.changes_metadata() {
+        report.updated = found.len();
+    }
+    Ok(found)
+}
+
+/// Add the story's annotations to its export JSON
+fn with_annotations(app: &AppHandle, story_id: &str, json: String) -> Result<String, String> {
+    let list = annotations::list(app, story_id, None, true)?;
+    let mut export: Value =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid story export: {}", e))?;
+    export["annotations"] = serde_json::to_value(list)
+        .map_err(|e| format!("Failed to serialize annotations: {}", e))?;
+    serde_json::to_string(&export).map_err(|e| format!("Failed to serialize story: {}", e))
+}
+
+async fn export(
+    app: &AppHandle,
+    story_ids: &[String],
+    folder: &str,
+    include_annotations: bool,
+    report: &mut BulkReport,
+) -> Result<(), String> {
+    let folder = PathBuf::from(folder.trim());
+    if !folder.is_absolute() {
+        return Err("Choose a full folder path".to_string());
+    }
+    fs::create_dir_all(&folder)
+        .map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let exported = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        for (index, story_id) in story_ids.iter().enumerate() {
+            let rows = archive::read_rows(&mut tx, story_id).await?;
+            let title = rows.text("title").unwrap_or_default();
+            let path = folder.join(format!("{}-{}.avt", slug(&title), story_id));
+            let written = rows::to_export_json(&rows)?
+                .ok_or_else(|| "Story not found".to_string())
+                .and_then(|json| match include_annotations {
+                    true => with_annotations(app, story_id, json),
+                    false => Ok(json),
+                })
+                .and_then(|json| {
+                    fs::write(&path, json)
+                        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+                });
+            match written {
+                Ok(()) => report.exported.push(path.to_string_lossy().to_string()),
+                Err(error) => report.failed.push(BulkFailure {
+                    story_id: story_id.clone(),
+                    error,
+                }),
+            }
+            progress(app, BulkPhase::Export, index + 1, story_ids.len(), story_id);
+        }
+        Ok::<_, String>(())
+    }
+    .await;
+    pool.close().await;
+    exported
+}
+
+/// Apply a patch to many stories. Stories that do not exist are reported
+/// and skipped; a database failure during the metadata update or a delete
+/// leaves every story unchanged.
+pub async fn bulk_update(
+    app: &AppHandle,
+    story_ids: &[String],
+    patch: &StoryPatch,
+) -> Result<BulkReport, String> {
+    let mut report = BulkReport::default();
+    let found = update_metadata(app, story_ids, patch, &mut report).await?;
+    let total = found.len();
+
+    match &patch.action {
+        None => {}
+        Some(BulkAction::Archive) => {
+            for (index, story_id) in found.iter().enumerate() {
+                match archive::archive(app, story_id).await {
+                    Ok(_) => report.archived += 1,
+                    Err(error) => report.failed.push(BulkFailure {
+                        story_id: story_id.clone(),
+                        error,
+                    }),
+                }
+                progress(app, BulkPhase::Archive, index + 1, total, story_id);
+            }
+        }
+        Some(BulkAction::Delete) => {
+            let ids: Vec<&str> = found.iter().map(String::as_str).collect();
+            if !ids.is_empty() {
+                report.undo = Some(journal::delete(app, &ids).await?);
+                report.deleted = ids.len();
+                tombstones::record(app, &ids)?;
+            }
+            if let Some(last) = ids.last() {
+                progress(app, BulkPhase::Delete, total, total, last);
+            }
+        }
+        Some(BulkAction::Export {
+            folder,
+            include_annotations,
+        }) => export(app, &found, folder, *include_annotations, &mut report).await?,
+    }
+    Ok(report)
+}
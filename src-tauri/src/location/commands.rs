@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+use super::{DataDirectoryInfo, DataDirectoryMove};
+use crate::profiles::{self, ProfileState};
+use crate::story::StoryState;
+use crate::sync::SyncState;
+
+/// Time for the result to reach the frontend before the app restarts
+const RESTART_DELAY: Duration = Duration::from_millis(750);
+
+#[tauri::command]
+pub async fn get_data_directory(app: AppHandle) -> Result<DataDirectoryInfo, String> {
+    super::info(&app)
+}
+
+/// Move all stories, attachments, versions, history and databases to
+/// another folder, such as one on an external drive. Each copy is checked
+/// before the app switches over; on failure the copies are removed and the
+/// current folder stays in use. The app restarts on the new folder, and the
+/// old copies are removed then. Admin only.
+#[tauri::command]
+pub async fn set_data_directory(
+    app: AppHandle,
+    profile_state: State<'_, ProfileState>,
+    story_state: State<'_, StoryState>,
+    sync_state: State<'_, SyncState>,
+    path: String,
+) -> Result<DataDirectoryMove, String> {
+    profiles::require_admin(&app)?;
+    let _switching = profile_state.switching.lock().await;
+    let _saves = story_state.saves.lock().await;
+    let _outbox = sync_state.outbox.pause().await;
+    let moved = super::move_to(&app, &PathBuf::from(path.trim())).await?;
+
+    // Nothing may be written to the old folder from here on
+    let handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(RESTART_DELAY).await;
+        handle.restart();
+    });
+    Ok(moved)
+}
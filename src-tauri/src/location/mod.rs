@@ -0,0 +1,454 @@
+//! Where Aventura keeps its data. By default that is the platform's app data
+//! directory, with databases in the app config directory where the SQL
+//! plugin looks for them. The user can move everything to another folder,
+//! such as an external drive; a pointer file in the config directory records
+//! the choice. A lock file in the data folder keeps two instances from
+//! writing to the same data, or one from moving it while another uses it.
+//!
+//! A move copies every file and database to the new folder and checks each
+//! copy before the pointer is changed, so a failure leaves the old folder in
+//! use and untouched. The app then restarts on the new folder and removes
+//! the old copies.
+//!
+//! In portable mode the data folder next to the executable stands in for
+//! both the data and the config directory, and cannot be moved.
+
+pub mod commands;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Manager};
+
+use crate::portable;
+use crate::profiles::database;
+
+/// Pointer to a custom data folder, in the app config directory
+pub const LOCATION_FILE: &str = "data_location.json";
+
+/// Held by the instance using a data folder
+const LOCK_FILE: &str = ".aventura.lock";
+
+/// Database files, each with its own folder under `profiles` apart from the
+/// first profile's
+const DATABASE_FILE: &str = "aventura.db";
+const PROFILES_DIR: &str = "profiles";
+
+/// State managed by Tauri for the data location
+#[derive(Default)]
+pub struct LocationState {
+    /// Resolved on first use; a move takes effect after a restart
+    roots: OnceLock<DataRoots>,
+    /// Lock on the data folder in use, released when the app exits
+    lock: Mutex<Option<File>>,
+}
+
+/// Where data and databases are read from in this run
+#[derive(Debug, Clone)]
+pub struct DataRoots {
+    pub data_dir: PathBuf,
+    /// Folder database URLs are resolved against
+    pub database_dir: PathBuf,
+    /// Data lives in a folder the user chose
+    pub custom: bool,
+}
+
+/// Files left in the old folders by a move, removed on the next start
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Cleanup {
+    data_dir: PathBuf,
+    database_dir: PathBuf,
+    /// Relative to `data_dir`
+    files: Vec<PathBuf>,
+    /// Relative to `database_dir`
+    databases: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct DataLocation {
+    path: Option<PathBuf>,
+    cleanup: Option<Cleanup>,
+}
+
+/// The data folder as shown to the frontend
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataDirectoryInfo {
+    pub path: String,
+    pub custom: bool,
+    /// Running from a portable copy, whose data stays next to the app
+    pub portable: bool,
+}
+
+/// A completed move
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataDirectoryMove {
+    pub path: String,
+    pub files: usize,
+    pub databases: usize,
+    pub bytes: u64,
+}
+
+pub fn config_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    if let Some(root) = portable::root() {
+        return Ok(root.to_path_buf());
+    }
+    app.path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to resolve app config directory: {}", e))
+}
+
+fn load_location(app: &AppHandle) -> Result<DataLocation, String> {
+    let path = config_dir(app)?.join(LOCATION_FILE);
+    if !path.exists() {
+        return Ok(DataLocation::default());
+    }
+    let json = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", LOCATION_FILE, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Invalid JSON in {}: {}", LOCATION_FILE, e))
+}
+
+fn save_location(app: &AppHandle, location: &DataLocation) -> Result<(), String> {
+    let dir = config_dir(app)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    let path = dir.join(LOCATION_FILE);
+    let json = serde_json::to_string_pretty(location)
+        .map_err(|e| format!("Failed to serialize {}: {}", LOCATION_FILE, e))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write {}: {}", LOCATION_FILE, e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to save {}: {}", LOCATION_FILE, e))
+}
+
+fn resolve_roots(app: &AppHandle) -> Result<DataRoots, String> {
+    if let Some(root) = portable::root() {
+        return Ok(DataRoots {
+            data_dir: root.to_path_buf(),
+            database_dir: root.to_path_buf(),
+            custom: true,
+        });
+    }
+    if let Some(path) = load_location(app)?.path {
+        return Ok(DataRoots {
+            data_dir: path.clone(),
+            database_dir: path,
+            custom: true,
+        });
+    }
+    Ok(DataRoots {
+        data_dir: app
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to resolve app data directory: {}", e))?,
+        database_dir: config_dir(app)?,
+        custom: false,
+    })
+}
+
+/// Data and database folders in use
+pub fn roots(app: &AppHandle) -> Result<DataRoots, String> {
+    let state = app.state::<LocationState>();
+    if let Some(roots) = state.roots.get() {
+        return Ok(roots.clone());
+    }
+    let roots = resolve_roots(app)?;
+    Ok(state.roots.get_or_init(|| roots).clone())
+}
+
+/// Take the lock file in a folder, failing if another instance holds it
+fn lock(dir: &Path) -> Result<File, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))
+        .map_err(|e| format!("Failed to lock {}: {}", dir.display(), e))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(fs::TryLockError::WouldBlock) => Err(format!(
+            "{} is in use by another Aventura window",
+            dir.display()
+        )),
+        Err(fs::TryLockError::Error(e)) => Err(format!("Failed to lock {}: {}", dir.display(), e)),
+    }
+}
+
+/// Remove files a move left behind, then any folders they leave empty
+fn clean_up(cleanup: &Cleanup) {
+    let files = cleanup
+        .files
+        .iter()
+        .map(|rel| cleanup.data_dir.join(rel))
+        .chain(
+            cleanup
+                .databases
+                .iter()
+                .flat_map(|rel| database_companions(&cleanup.database_dir.join(rel))),
+        );
+    let mut dirs = Vec::new();
+    for path in files.chain([cleanup.data_dir.join(LOCK_FILE)]) {
+        if let Err(e) = fs::remove_file(&path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                eprintln!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+        dirs.extend(path.parent().map(Path::to_path_buf));
+    }
+    dirs.sort();
+    dirs.dedup();
+    // Deepest first, so parents are empty by the time they are tried
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in dirs {
+        let mut dir = dir.as_path();
+        while dir != cleanup.data_dir && dir != cleanup.database_dir && fs::remove_dir(dir).is_ok()
+        {
+            match dir.parent() {
+                Some(parent) => dir = parent,
+                None => break,
+            }
+        }
+    }
+}
+
+/// Lock the data folder for this instance. Finishes a move by removing the
+/// old copies once the new folder is in use.
+pub fn start(app: &AppHandle) -> Result<(), String> {
+    let roots = roots(app)?;
+    let file = lock(&roots.data_dir)?;
+    if let Ok(mut held) = app.state::<LocationState>().lock.lock() {
+        *held = Some(file);
+    }
+    let mut location = load_location(app)?;
+    if let Some(cleanup) = location.cleanup.take() {
+        clean_up(&cleanup);
+        save_location(app, &location)?;
+    }
+    Ok(())
+}
+
+pub fn info(app: &AppHandle) -> Result<DataDirectoryInfo, String> {
+    let roots = roots(app)?;
+    Ok(DataDirectoryInfo {
+        path: roots.data_dir.display().to_string(),
+        custom: roots.custom,
+        portable: portable::is_enabled(),
+    })
+}
+
+fn database_companions(path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![path.to_path_buf()];
+    for suffix in ["-wal", "-shm", "-journal"] {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        files.push(PathBuf::from(name));
+    }
+    files
+}
+
+fn is_database_file(name: &str) -> bool {
+    name.strip_prefix(DATABASE_FILE)
+        .is_some_and(|rest| matches!(rest, "" | "-wal" | "-shm" | "-journal"))
+}
+
+/// Every database, relative to the database folder
+fn list_databases(database_dir: &Path) -> Vec<PathBuf> {
+    let mut databases = Vec::new();
+    if database_dir.join(DATABASE_FILE).is_file() {
+        databases.push(PathBuf::from(DATABASE_FILE));
+    }
+    if let Ok(entries) = fs::read_dir(database_dir.join(PROFILES_DIR)) {
+        for entry in entries.flatten() {
+            let rel = Path::new(PROFILES_DIR)
+                .join(entry.file_name())
+                .join(DATABASE_FILE);
+            if database_dir.join(&rel).is_file() {
+                databases.push(rel);
+            }
+        }
+    }
+    databases
+}
+
+/// Every data file, relative to the data folder. Databases, the lock and
+/// the location pointer are left out, as are files still being written.
+fn list_files(dir: &Path, rel: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = rel.join(&name);
+        let Ok(kind) = entry.file_type() else {
+            continue;
+        };
+        if kind.is_dir() {
+            list_files(&entry.path(), &path, files)?;
+        } else if kind.is_file()
+            && !is_database_file(&name)
+            && name != LOCK_FILE
+            && name != LOCATION_FILE
+            && !name.ends_with(".tmp")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn file_hash(path: &Path) -> Result<(u64, Vec<u8>), String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok((size, hasher.finalize().to_vec()))
+}
+
+/// Copy a file and check the copy matches byte for byte
+fn copy_verified(from: &Path, to: &Path) -> Result<u64, String> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    fs::copy(from, to).map_err(|e| format!("Failed to copy {}: {}", from.display(), e))?;
+    let original = file_hash(from)?;
+    if file_hash(to)? != original {
+        return Err(format!("The copy of {} does not match", from.display()));
+    }
+    Ok(original.0)
+}
+
+/// Write a consistent copy of a database, even while it is open, and check it
+async fn copy_database(from: &Path, to: &Path) -> Result<u64, String> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let pool = database::open(from, false).await?;
+    let copied = sqlx::query("VACUUM INTO ?")
+        .bind(to.to_string_lossy().to_string())
+        .execute(&pool)
+        .await;
+    pool.close().await;
+    copied.map_err(|e| format!("Failed to copy database: {}", e))?;
+
+    let pool = database::open(to, false).await?;
+    let check = sqlx::query_scalar::<_, String>("PRAGMA integrity_check")
+        .fetch_one(&pool)
+        .await;
+    pool.close().await;
+    match check {
+        Ok(result) if result == "ok" => {}
+        Ok(result) => return Err(format!("The database copy is damaged: {}", result)),
+        Err(e) => return Err(format!("Failed to check database copy: {}", e)),
+    }
+    fs::metadata(to)
+        .map(|m| m.len())
+        .map_err(|e| format!("Failed to read database copy: {}", e))
+}
+
+/// A folder data may be moved into: absolute, empty or not there yet, and
+/// not inside the current folders or containing them
+fn check_target(roots: &DataRoots, target: &Path) -> Result<(), String> {
+    if portable::is_enabled() {
+        return Err("A portable copy keeps its data next to the app".to_string());
+    }
+    if !target.is_absolute() {
+        return Err("Choose a full folder path".to_string());
+    }
+    for current in [&roots.data_dir, &roots.database_dir] {
+        if target.starts_with(current) || current.starts_with(target) {
+            return Err("The new folder must be outside the current data folder".to_string());
+        }
+    }
+    if target.exists() {
+        let mut entries =
+            fs::read_dir(target).map_err(|e| format!("Cannot use {}: {}", target.display(), e))?;
+        if entries.next().is_some() {
+            return Err("Choose an empty folder".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Remove what a failed move copied, leaving the folder itself
+fn roll_back(target: &Path) {
+    if let Ok(entries) = fs::read_dir(target) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let removed = if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            if let Err(e) = removed {
+                eprintln!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+async fn copy_all(
+    roots: &DataRoots,
+    target: &Path,
+    files: &[PathBuf],
+    databases: &[PathBuf],
+) -> Result<u64, String> {
+    let mut bytes = 0;
+    for rel in databases {
+        bytes += copy_database(&roots.database_dir.join(rel), &target.join(rel)).await?;
+    }
+    for rel in files {
+        bytes += copy_verified(&roots.data_dir.join(rel), &target.join(rel))?;
+    }
+    Ok(bytes)
+}
+
+/// Copy all data to a new folder and point the app at it. The caller holds
+/// off writes while this runs and restarts the app afterwards.
+pub async fn move_to(app: &AppHandle, target: &Path) -> Result<DataDirectoryMove, String> {
+    let roots = roots(app)?;
+    check_target(&roots, target)?;
+    let target_lock = lock(target)?;
+
+    let databases = list_databases(&roots.database_dir);
+    let mut files = Vec::new();
+    list_files(&roots.data_dir, Path::new(""), &mut files)?;
+    // Other profiles' databases sit among the data files when both folders
+    // are the same
+    files.retain(|rel| !databases.contains(rel));
+
+    let bytes = match copy_all(&roots, target, &files, &databases).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            drop(target_lock);
+            roll_back(target);
+            return Err(e);
+        }
+    };
+    let location = DataLocation {
+        path: Some(target.to_path_buf()),
+        cleanup: Some(Cleanup {
+            data_dir: roots.data_dir.clone(),
+            database_dir: roots.database_dir.clone(),
+            files: files.clone(),
+            databases: databases.clone(),
+        }),
+    };
+    if let Err(e) = save_location(app, &location) {
+        drop(target_lock);
+        roll_back(target);
+        return Err(e);
+    }
+    Ok(DataDirectoryMove {
+        path: target.display().to_string(),
+        files: files.len(),
+        databases: databases.len(),
+        bytes,
+    })
+}
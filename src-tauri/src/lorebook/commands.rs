@@ -0,0 +1,98 @@
+use std::path::Path;
+use tauri::AppHandle;
+
+use super::{Lorebook, LorebookApplyReport, LorebookConflict, LorebookInfo};
+use crate::profiles;
+
+/// Bundle a story's lore entries into a new lorebook in the library. All of
+/// the main line's entries are taken unless `entry_ids` names some.
+#[tauri::command]
+pub async fn create_lorebook(
+    app: AppHandle,
+    story_id: String,
+    name: String,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+    entry_ids: Option<Vec<String>>,
+) -> Result<LorebookInfo, String> {
+    profiles::check_story(&app, &story_id).await?;
+    if name.trim().is_empty() {
+        return Err("Lorebooks need a name".to_string());
+    }
+    let entries = super::story_entries(&app, &story_id, entry_ids.as_deref()).await?;
+    if entries.is_empty() {
+        return Err("The story has no lore entries to bundle".to_string());
+    }
+    let mut tags: Vec<String> = tags
+        .unwrap_or_default()
+        .iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    let lorebook = Lorebook::new(&name, description.as_deref().unwrap_or(""), tags, entries);
+    super::save(&app, &lorebook)?;
+    Ok(lorebook.info(false))
+}
+
+#[tauri::command]
+pub async fn list_lorebooks(app: AppHandle) -> Result<Vec<LorebookInfo>, String> {
+    super::list(&app)
+}
+
+#[tauri::command]
+pub async fn get_lorebook(app: AppHandle, id: String) -> Result<Lorebook, String> {
+    super::load(&app, &id)
+}
+
+#[tauri::command]
+pub async fn delete_lorebook(app: AppHandle, id: String) -> Result<(), String> {
+    super::delete(&app, &id)
+}
+
+/// Offer a lorebook to other devices through the sync server, or stop
+#[tauri::command]
+pub async fn set_lorebook_shared(app: AppHandle, id: String, shared: bool) -> Result<(), String> {
+    super::load(&app, &id)?;
+    super::set_shared(&app, &id, shared)
+}
+
+/// Write a lorebook from the library to a file the frontend's lorebook
+/// importer reads
+#[tauri::command]
+pub async fn export_lorebook(app: AppHandle, id: String, path: String) -> Result<(), String> {
+    let lorebook = super::load(&app, &id)?;
+    super::write_file(Path::new(&path), &lorebook)
+}
+
+/// Add a lorebook file, as the frontend's lorebook exporter writes, to the
+/// library as a new lorebook. It is named after the file unless a name is
+/// given.
+#[tauri::command]
+pub async fn import_lorebook(
+    app: AppHandle,
+    path: String,
+    name: Option<String>,
+) -> Result<LorebookInfo, String> {
+    let mut lorebook = super::read_file(Path::new(&path))?;
+    if let Some(name) = name.filter(|n| !n.trim().is_empty()) {
+        lorebook.name = name.trim().to_string();
+    }
+    super::save(&app, &lorebook)?;
+    Ok(lorebook.info(false))
+}
+
+/// Add a lorebook's entries to a story. Entries whose name and type the
+/// story already has are skipped unless `conflict` says otherwise.
+#[tauri::command]
+pub async fn apply_lorebook(
+    app: AppHandle,
+    id: String,
+    story_id: String,
+    conflict: Option<LorebookConflict>,
+) -> Result<LorebookApplyReport, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let lorebook = super::load(&app, &id)?;
+    super::apply(&app, &lorebook, &story_id, conflict.unwrap_or_default()).await
+}
@@ -0,0 +1,516 @@
+//! Lorebooks: a story's worldbuilding entries bundled on their own, so they
+//! can be added to other stories. Lorebooks are kept in a library in the
+//! profile's data directory and the ones marked as shared are offered to
+//! other devices by the sync server. Files are the bare list of entries the
+//! frontend's lorebook exporter writes, so either side reads the other's.
+
+pub mod commands;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Number, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+use crate::profiles::{self, database};
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Newest library record version this build reads and the one it writes
+pub const LOREBOOK_VERSION: u32 = 1;
+
+/// Directory in the profile's data directory holding the library
+const LOREBOOKS_DIR: &str = "lorebooks";
+
+/// IDs of the lorebooks the sync server offers, in the app data directory
+pub const SHARED_LOREBOOKS_FILE: &str = "shared_lorebooks.json";
+
+fn default_injection_mode() -> String {
+    "keyword".to_string()
+}
+
+/// When an entry is added to the prompt, as the frontend's `EntryInjection`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryInjection {
+    /// "always", "keyword", "relevant" or "never"
+    #[serde(default = "default_injection_mode")]
+    pub mode: String,
+    /// Words that bring the entry into the prompt in keyword mode
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Number>,
+}
+
+impl Default for EntryInjection {
+    fn default() -> Self {
+        Self {
+            mode: default_injection_mode(),
+            keywords: Vec::new(),
+            priority: None,
+        }
+    }
+}
+
+/// One entry of a lorebook, without anything tying it to a story. The
+/// fields are those of the frontend's `Entry`, whose other fields are
+/// ignored when read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LorebookEntryData {
+    pub name: String,
+    /// "character", "location", "item", "faction", "concept" or "event"
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub hidden_info: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Type-specific state as the story kept it
+    #[serde(default)]
+    pub state: Option<Value>,
+    #[serde(default)]
+    pub injection: EntryInjection,
+}
+
+impl LorebookEntryData {
+    /// The state to store, a bare one of the entry's type when it has none
+    fn state_or_default(&self) -> Value {
+        self.state
+            .clone()
+            .unwrap_or_else(|| json!({ "type": self.entry_type }))
+    }
+}
+
+/// A lorebook in the library
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lorebook {
+    pub version: u32,
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    #[serde(default)]
+    pub updated_at: i64,
+    pub entries: Vec<LorebookEntryData>,
+}
+
+/// A lorebook as listed, without its entries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LorebookInfo {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub entry_count: usize,
+    pub updated_at: i64,
+    /// Offered to other devices by the sync server
+    #[serde(default)]
+    pub shared: bool,
+}
+
+impl Lorebook {
+    pub fn new(
+        name: &str,
+        description: &str,
+        tags: Vec<String>,
+        entries: Vec<LorebookEntryData>,
+    ) -> Self {
+        let now = now_ms();
+        Self {
+            version: LOREBOOK_VERSION,
+            id: Uuid::new_v4().to_string(),
+            name: name.trim().to_string(),
+            description: description.trim().to_string(),
+            tags,
+            created_at: now,
+            updated_at: now,
+            entries,
+        }
+    }
+
+    pub fn info(&self, shared: bool) -> LorebookInfo {
+        LorebookInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            tags: self.tags.clone(),
+            entry_count: self.entries.len(),
+            updated_at: self.updated_at,
+            shared,
+        }
+    }
+
+    /// Check a lorebook read from a file or another device
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version > LOREBOOK_VERSION {
+            return Err(format!(
+                "This lorebook was made by a newer version (format {})",
+                self.version
+            ));
+        }
+        check_id(&self.id)?;
+        if self.name.trim().is_empty() {
+            return Err("The lorebook has no name".to_string());
+        }
+        if let Some(entry) = self
+            .entries
+            .iter()
+            .find(|e| e.name.trim().is_empty() || e.entry_type.trim().is_empty())
+        {
+            return Err(format!(
+                "Lorebook entry without a name or type: {:?}",
+                entry.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn check_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid lorebook ID: {}", id));
+    }
+    Ok(())
+}
+
+fn lorebook_path(app: &AppHandle, id: &str) -> Result<PathBuf, String> {
+    check_id(id)?;
+    let dir = store::data_file(app, LOREBOOKS_DIR)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create lorebook directory: {}", e))?;
+    Ok(dir.join(format!("{}.json", id)))
+}
+
+/// Write through a temporary file beside the target, named so it cannot be
+/// another file of the user's
+fn write_atomic(path: &Path, json: String) -> Result<(), String> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("Not a file path: {}", path.display()))?;
+    let tmp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        Uuid::new_v4().simple()
+    ));
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write lorebook: {}", e))?;
+    fs::rename(&tmp, path).map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        format!("Failed to save lorebook: {}", e)
+    })
+}
+
+/// Read a library record
+fn read_record(path: &Path) -> Result<Lorebook, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("Failed to read lorebook: {}", e))?;
+    let lorebook: Lorebook =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid lorebook: {}", e))?;
+    lorebook.validate()?;
+    Ok(lorebook)
+}
+
+/// Read a lorebook file, a list of entries as the frontend's exporter
+/// writes. The lorebook is named after the file.
+pub fn read_file(path: &Path) -> Result<Lorebook, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("Failed to read lorebook: {}", e))?;
+    let entries: Vec<LorebookEntryData> =
+        serde_json::from_str(&json).map_err(|e| format!("Not an Aventura lorebook file: {}", e))?;
+    if entries.is_empty() {
+        return Err("The lorebook file has no entries".to_string());
+    }
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or_else(|| "Imported lorebook".to_string());
+    let lorebook = Lorebook::new(&name, "", Vec::new(), entries);
+    lorebook.validate()?;
+    Ok(lorebook)
+}
+
+/// Write a lorebook's entries as a file the frontend's importer reads
+pub fn write_file(path: &Path, lorebook: &Lorebook) -> Result<(), String> {
+    let entries: Vec<Value> = lorebook
+        .entries
+        .iter()
+        .map(|entry| {
+            let mut value = json!(entry);
+            value["state"] = entry.state_or_default();
+            value["createdAt"] = json!(lorebook.created_at);
+            value["updatedAt"] = json!(lorebook.updated_at);
+            value
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&entries)
+        .map_err(|e| format!("Failed to serialize lorebook: {}", e))?;
+    write_atomic(path, json)
+}
+
+/// Add a lorebook to the library, replacing one with the same ID
+pub fn save(app: &AppHandle, lorebook: &Lorebook) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(lorebook)
+        .map_err(|e| format!("Failed to serialize lorebook: {}", e))?;
+    write_atomic(&lorebook_path(app, &lorebook.id)?, json)
+}
+
+pub fn load(app: &AppHandle, id: &str) -> Result<Lorebook, String> {
+    let path = lorebook_path(app, id)?;
+    if !path.exists() {
+        return Err(format!("Lorebook not found: {}", id));
+    }
+    read_record(&path)
+}
+
+pub fn delete(app: &AppHandle, id: &str) -> Result<(), String> {
+    let path = lorebook_path(app, id)?;
+    if path.exists() {
+        fs::remove_file(&path).map_err(|e| format!("Failed to remove lorebook: {}", e))?;
+    }
+    set_shared(app, id, false)
+}
+
+fn shared_ids(app: &AppHandle) -> Result<Vec<String>, String> {
+    store::load_json(app, SHARED_LOREBOOKS_FILE)
+}
+
+/// Offer a lorebook to other devices, or stop offering it
+pub fn set_shared(app: &AppHandle, id: &str, shared: bool) -> Result<(), String> {
+    let mut ids = shared_ids(app)?;
+    ids.retain(|shared_id| shared_id != id);
+    if shared {
+        ids.push(id.to_string());
+    }
+    store::save_json(app, SHARED_LOREBOOKS_FILE, &ids)
+}
+
+/// The library, by name. Files that cannot be read are left out.
+pub fn list(app: &AppHandle) -> Result<Vec<LorebookInfo>, String> {
+    let dir = store::data_file(app, LOREBOOKS_DIR)?;
+    let Ok(files) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let shared = shared_ids(app)?;
+    let mut found: Vec<LorebookInfo> = files
+        .flatten()
+        .map(|file| file.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| read_record(&path).ok())
+        .map(|lorebook| {
+            let is_shared = shared.contains(&lorebook.id);
+            lorebook.info(is_shared)
+        })
+        .collect();
+    found.sort_by_key(|l| l.name.to_lowercase());
+    Ok(found)
+}
+
+/// Lorebooks the sync server offers
+pub fn list_shared(app: &AppHandle) -> Result<Vec<LorebookInfo>, String> {
+    Ok(list(app)?.into_iter().filter(|l| l.shared).collect())
+}
+
+/// A shared lorebook, for a peer that asked for it
+pub fn load_shared(app: &AppHandle, id: &str) -> Result<Lorebook, String> {
+    if !shared_ids(app)?.iter().any(|shared| shared == id) {
+        return Err(format!("Lorebook not shared: {}", id));
+    }
+    load(app, id)
+}
+
+/// ID, name, type, description, hidden info, aliases, state and injection
+type EntryRow = (
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Lore entries of a story's main line, or only the given ones
+pub async fn story_entries(
+    app: &AppHandle,
+    story_id: &str,
+    entry_ids: Option<&[String]>,
+) -> Result<Vec<LorebookEntryData>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let rows: Result<Vec<EntryRow>, String> = sqlx::query_as(
+        "SELECT id, name, type, description, hidden_info, aliases, state, injection \
+         FROM entries WHERE story_id = ? AND branch_id IS NULL ORDER BY created_at",
+    )
+    .bind(story_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| format!("Failed to read lorebook entries: {}", e));
+    pool.close().await;
+
+    let json = |text: Option<String>| -> Option<Value> {
+        text.and_then(|t| serde_json::from_str(&t).ok())
+    };
+    Ok(rows?
+        .into_iter()
+        .filter(|row| entry_ids.is_none_or(|ids| ids.contains(&row.0)))
+        .map(
+            |(_, name, entry_type, description, hidden_info, aliases, state, injection)| {
+                LorebookEntryData {
+                    name,
+                    entry_type,
+                    description: description.unwrap_or_default(),
+                    hidden_info,
+                    aliases: json(aliases)
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .unwrap_or_default(),
+                    state: json(state),
+                    injection: json(injection)
+                        .and_then(|i| serde_json::from_value(i).ok())
+                        .unwrap_or_default(),
+                }
+            },
+        )
+        .collect())
+}
+
+/// What to do with a lorebook entry whose name and type a story already has
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LorebookConflict {
+    /// Keep the story's entry
+    #[default]
+    Skip,
+    /// Overwrite the story's description, aliases and injection rules,
+    /// keeping its tracked state
+    Replace,
+    /// Add the lorebook's entry alongside
+    KeepBoth,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LorebookApplyReport {
+    pub added: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+}
+
+fn injection(entry: &LorebookEntryData) -> String {
+    json!({
+        "mode": entry.injection.mode,
+        "keywords": entry.injection.keywords,
+        "priority": entry.injection.priority.clone().unwrap_or_else(|| Number::from(0)),
+    })
+    .to_string()
+}
+
+fn aliases(entry: &LorebookEntryData) -> String {
+    Value::from(entry.aliases.clone()).to_string()
+}
+
+/// Add a lorebook's entries to a story's main line
+pub async fn apply(
+    app: &AppHandle,
+    lorebook: &Lorebook,
+    story_id: &str,
+    conflict: LorebookConflict,
+) -> Result<LorebookApplyReport, String> {
+    let now = now_ms();
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let applied = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM stories WHERE id = ?")
+            .bind(story_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to read story: {}", e))?;
+        if exists.is_none() {
+            return Err(format!("Story not found: {}", story_id));
+        }
+        let rows: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT id, name, type FROM entries WHERE story_id = ? AND branch_id IS NULL",
+        )
+        .bind(story_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to read lorebook entries: {}", e))?;
+        let existing: HashMap<(String, String), String> = rows
+            .into_iter()
+            .map(|(id, name, entry_type)| ((name.trim().to_lowercase(), entry_type), id))
+            .collect();
+
+        let mut report = LorebookApplyReport::default();
+        for entry in &lorebook.entries {
+            let key = (entry.name.trim().to_lowercase(), entry.entry_type.clone());
+            match (existing.get(&key), conflict) {
+                (Some(_), LorebookConflict::Skip) => report.skipped += 1,
+                (Some(id), LorebookConflict::Replace) => {
+                    sqlx::query(
+                        "UPDATE entries SET description = ?, hidden_info = ?, aliases = ?, \
+                         injection = ?, updated_at = ? WHERE id = ?",
+                    )
+                    .bind(&entry.description)
+                    .bind(&entry.hidden_info)
+                    .bind(aliases(entry))
+                    .bind(injection(entry))
+                    .bind(now)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| format!("Failed to update lorebook entry: {}", e))?;
+                    report.replaced += 1;
+                }
+                _ => {
+                    let state = entry.state_or_default();
+                    sqlx::query(
+                        "INSERT INTO entries (id, story_id, name, type, description, \
+                         hidden_info, aliases, state, injection, mention_count, created_by, \
+                         created_at, updated_at, lore_management_blacklisted, branch_id) \
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, 0, 'import', ?, ?, 0, NULL)",
+                    )
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(story_id)
+                    .bind(entry.name.trim())
+                    .bind(&entry.entry_type)
+                    .bind(&entry.description)
+                    .bind(&entry.hidden_info)
+                    .bind(aliases(entry))
+                    .bind(state.to_string())
+                    .bind(injection(entry))
+                    .bind(now)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| format!("Failed to add lorebook entry: {}", e))?;
+                    report.added += 1;
+                }
+            }
+        }
+        if report.added + report.replaced > 0 {
+            sqlx::query("UPDATE stories SET updated_at = ? WHERE id = ?")
+                .bind(now)
+                .bind(story_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to update story: {}", e))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to save lorebook entries: {}", e))?;
+        Ok(report)
+    }
+    .await;
+    pool.close().await;
+    applied
+}
@@ -0,0 +1,100 @@
+use tauri::AppHandle;
+
+use super::{Route, StoryMap};
+use crate::profiles;
+
+/// The story's places on its current branch and where the party is
+#[tauri::command]
+pub async fn get_story_map(app: AppHandle, story_id: String) -> Result<StoryMap, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::load(&app, &story_id).await
+}
+
+/// Add a place to the map, optionally connected both ways with another
+#[tauri::command]
+pub async fn add_map_location(
+    app: AppHandle,
+    story_id: String,
+    name: String,
+    description: Option<String>,
+    connect_to: Option<String>,
+) -> Result<StoryMap, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::add_place(&app, &story_id, &name, description, connect_to).await
+}
+
+/// Connect two places, both ways unless `one_way` is set
+#[tauri::command]
+pub async fn connect_locations(
+    app: AppHandle,
+    story_id: String,
+    from: String,
+    to: String,
+    one_way: Option<bool>,
+) -> Result<StoryMap, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::set_connection(&app, &story_id, &from, &to, true, one_way.unwrap_or(false)).await
+}
+
+/// Remove the connection between two places, both ways unless `one_way` is set
+#[tauri::command]
+pub async fn disconnect_locations(
+    app: AppHandle,
+    story_id: String,
+    from: String,
+    to: String,
+    one_way: Option<bool>,
+) -> Result<StoryMap, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::set_connection(&app, &story_id, &from, &to, false, one_way.unwrap_or(false)).await
+}
+
+/// Move the party to a place connected to where it is. With `travel` set
+/// the place may be anywhere on the map.
+#[tauri::command]
+pub async fn move_party(
+    app: AppHandle,
+    story_id: String,
+    location_id: String,
+    travel: Option<bool>,
+) -> Result<StoryMap, String> {
+    profiles::check_story(&app, &story_id).await?;
+    super::move_party(&app, &story_id, &location_id, travel.unwrap_or(false)).await
+}
+
+/// Places reachable from a place, by default where the party is, within a
+/// number of steps, by default any
+#[tauri::command]
+pub async fn get_reachable_locations(
+    app: AppHandle,
+    story_id: String,
+    from: Option<String>,
+    max_steps: Option<u32>,
+) -> Result<Vec<Route>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let map = super::load(&app, &story_id).await?;
+    let from = match from {
+        Some(from) => from,
+        None => map
+            .current()
+            .ok_or("The party isn't anywhere on the map yet")?
+            .id
+            .clone(),
+    };
+    map.reachable(&from, max_steps.unwrap_or(u32::MAX))
+}
+
+/// Render the map as SVG, and write it to a file when a path is given
+#[tauri::command]
+pub async fn render_story_map(
+    app: AppHandle,
+    story_id: String,
+    path: Option<String>,
+) -> Result<String, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let svg = super::svg::render(&super::load(&app, &story_id).await?);
+    if let Some(path) = path {
+        std::fs::write(&path, &svg).map_err(|e| format!("Failed to write map: {}", e))?;
+    }
+    Ok(svg)
+}
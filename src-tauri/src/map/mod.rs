@@ -0,0 +1,376 @@
+//! The story's map: its named places, the connections between them and
+//! where the party is. Places are the story's location rows on its current
+//! branch, so the map follows the world state the frontend keeps, and
+//! connections are the location's list of places it leads to. A connection
+//! is one way; a path back is a connection of its own.
+
+pub mod commands;
+pub mod svg;
+
+use serde::Serialize;
+use sqlx::{Sqlite, Transaction};
+use std::collections::{HashMap, VecDeque};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+use crate::profiles::{self, database};
+use crate::sync::keys::now_ms;
+
+const MAX_NAME_CHARS: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Place {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub visited: bool,
+    pub current: bool,
+    /// Places this one leads to
+    pub connections: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryMap {
+    pub places: Vec<Place>,
+}
+
+/// A place that can be reached, and the way there
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Route {
+    pub location_id: String,
+    pub name: String,
+    pub steps: u32,
+    /// Places passed through, ending with the destination
+    pub path: Vec<String>,
+}
+
+impl StoryMap {
+    pub fn place(&self, id: &str) -> Result<&Place, String> {
+        self.places
+            .iter()
+            .find(|p| p.id == id)
+            .ok_or_else(|| format!("Location not found: {}", id))
+    }
+
+    /// Where the party is, if anywhere
+    pub fn current(&self) -> Option<&Place> {
+        self.places.iter().find(|p| p.current)
+    }
+
+    /// Shortest step counts from a place to every place reachable from it,
+    /// with the place each was reached from
+    fn search<'a>(&'a self, from: &'a str, max_steps: u32) -> HashMap<&'a str, (u32, &'a str)> {
+        let mut seen: HashMap<&str, (u32, &str)> = HashMap::new();
+        let mut queue = VecDeque::from([(from, 0)]);
+        seen.insert(from, (0, from));
+        while let Some((id, steps)) = queue.pop_front() {
+            if steps == max_steps {
+                continue;
+            }
+            let Ok(place) = self.place(id) else {
+                continue;
+            };
+            for next in &place.connections {
+                // Connections to places on other branches, or deleted, lead nowhere
+                if self.place(next).is_err() || seen.contains_key(next.as_str()) {
+                    continue;
+                }
+                seen.insert(next, (steps + 1, id));
+                queue.push_back((next, steps + 1));
+            }
+        }
+        seen
+    }
+
+    /// Step counts from a place, for places reachable from it
+    pub fn distances<'a>(&'a self, from: &'a str) -> HashMap<&'a str, u32> {
+        self.search(from, u32::MAX)
+            .into_iter()
+            .map(|(id, (steps, _))| (id, steps))
+            .collect()
+    }
+
+    /// Places reachable from a place within a number of steps, nearest
+    /// first, each by one of its shortest paths
+    pub fn reachable(&self, from: &str, max_steps: u32) -> Result<Vec<Route>, String> {
+        self.place(from)?;
+        let seen = self.search(from, max_steps);
+        let mut routes: Vec<Route> = seen
+            .iter()
+            .filter(|(id, _)| **id != from)
+            .filter_map(|(id, (steps, _))| {
+                let mut path = vec![id.to_string()];
+                let mut at = *id;
+                while let Some((_, previous)) = seen.get(at).filter(|(_, p)| *p != from) {
+                    path.push(previous.to_string());
+                    at = previous;
+                }
+                path.reverse();
+                Some(Route {
+                    location_id: id.to_string(),
+                    name: self.place(id).ok()?.name.clone(),
+                    steps: *steps,
+                    path,
+                })
+            })
+            .collect();
+        routes.sort_by(|a, b| a.steps.cmp(&b.steps).then_with(|| a.name.cmp(&b.name)));
+        Ok(routes)
+    }
+}
+
+/// ID, name, description, visited and current flags, and connections of a
+/// location row
+type PlaceRow = (
+    String,
+    String,
+    Option<String>,
+    Option<bool>,
+    Option<bool>,
+    Option<String>,
+);
+
+fn place(row: PlaceRow) -> Place {
+    let (id, name, description, visited, current, connections) = row;
+    Place {
+        id,
+        name,
+        description,
+        visited: visited.unwrap_or(false),
+        current: current.unwrap_or(false),
+        connections: connections
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default(),
+    }
+}
+
+/// The story's current branch and its map
+async fn read(
+    tx: &mut Transaction<'_, Sqlite>,
+    story_id: &str,
+) -> Result<(Option<String>, StoryMap), String> {
+    let branch: Option<(Option<String>,)> =
+        sqlx::query_as("SELECT current_branch_id FROM stories WHERE id = ?")
+            .bind(story_id)
+            .fetch_optional(&mut **tx)
+            .await
+            .map_err(|e| format!("Failed to read story: {}", e))?;
+    let (branch_id,) = branch.ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let rows: Vec<PlaceRow> = sqlx::query_as(
+        "SELECT id, name, description, visited, current, connections FROM locations \
+         WHERE story_id = ? AND branch_id IS ? ORDER BY rowid",
+    )
+    .bind(story_id)
+    .bind(&branch_id)
+    .fetch_all(&mut **tx)
+    .await
+    .map_err(|e| format!("Failed to read locations: {}", e))?;
+    Ok((
+        branch_id,
+        StoryMap {
+            places: rows.into_iter().map(place).collect(),
+        },
+    ))
+}
+
+async fn write_connections(tx: &mut Transaction<'_, Sqlite>, place: &Place) -> Result<(), String> {
+    let connections = serde_json::to_string(&place.connections)
+        .map_err(|e| format!("Failed to serialize connections: {}", e))?;
+    sqlx::query("UPDATE locations SET connections = ? WHERE id = ?")
+        .bind(connections)
+        .bind(&place.id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| format!("Failed to update location: {}", e))?;
+    Ok(())
+}
+
+async fn begin(pool: &sqlx::SqlitePool) -> Result<Transaction<'_, Sqlite>, String> {
+    pool.begin()
+        .await
+        .map_err(|e| format!("Failed to open database: {}", e))
+}
+
+/// Save the story's update time and commit a change. Returns the map as the
+/// change left it.
+async fn finish(mut tx: Transaction<'_, Sqlite>, story_id: &str) -> Result<StoryMap, String> {
+    sqlx::query("UPDATE stories SET updated_at = ? WHERE id = ?")
+        .bind(now_ms())
+        .bind(story_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to update story: {}", e))?;
+    let (_, map) = read(&mut tx, story_id).await?;
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to save the map: {}", e))?;
+    Ok(map)
+}
+
+/// The story's map on its current branch
+pub async fn load(app: &AppHandle, story_id: &str) -> Result<StoryMap, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let loaded = async {
+        let mut tx = begin(&pool).await?;
+        let (_, map) = read(&mut tx, story_id).await?;
+        Ok(map)
+    }
+    .await;
+    pool.close().await;
+    loaded
+}
+
+/// Add a place on the story's current branch, optionally connected both
+/// ways with an existing one
+pub async fn add_place(
+    app: &AppHandle,
+    story_id: &str,
+    name: &str,
+    description: Option<String>,
+    connect_to: Option<String>,
+) -> Result<StoryMap, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Locations need a name".to_string());
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!(
+            "Names can be at most {} characters",
+            MAX_NAME_CHARS
+        ));
+    }
+    let description = description
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty());
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let added = async {
+        let mut tx = begin(&pool).await?;
+        let (branch_id, map) = read(&mut tx, story_id).await?;
+        if map
+            .places
+            .iter()
+            .any(|p| p.name.eq_ignore_ascii_case(&name))
+        {
+            return Err(format!("There is already a location named {}", name));
+        }
+        let mut new = Place {
+            id: Uuid::new_v4().to_string(),
+            name,
+            description,
+            visited: false,
+            current: false,
+            connections: Vec::new(),
+        };
+        if let Some(other) = &connect_to {
+            let mut other = map.place(other)?.clone();
+            other.connections.push(new.id.clone());
+            new.connections.push(other.id.clone());
+            write_connections(&mut tx, &other).await?;
+        }
+        let connections = serde_json::to_string(&new.connections)
+            .map_err(|e| format!("Failed to serialize connections: {}", e))?;
+        sqlx::query(
+            "INSERT INTO locations (id, story_id, name, description, visited, current, \
+             connections, metadata, branch_id) VALUES (?, ?, ?, ?, 0, 0, ?, NULL, ?)",
+        )
+        .bind(&new.id)
+        .bind(story_id)
+        .bind(&new.name)
+        .bind(&new.description)
+        .bind(connections)
+        .bind(branch_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to add location: {}", e))?;
+        finish(tx, story_id).await
+    }
+    .await;
+    pool.close().await;
+    added
+}
+
+/// Connect two places, or with `connected` false disconnect them. Both
+/// directions change unless `one_way` is set.
+pub async fn set_connection(
+    app: &AppHandle,
+    story_id: &str,
+    from: &str,
+    to: &str,
+    connected: bool,
+    one_way: bool,
+) -> Result<StoryMap, String> {
+    if from == to {
+        return Err("A location can't connect to itself".to_string());
+    }
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let changed = async {
+        let mut tx = begin(&pool).await?;
+        let (_, map) = read(&mut tx, story_id).await?;
+        let mut pairs = vec![(map.place(from)?.clone(), to)];
+        if !one_way {
+            pairs.push((map.place(to)?.clone(), from));
+        }
+        for (mut place, other) in pairs {
+            let linked = place.connections.iter().any(|c| c == other);
+            if linked == connected {
+                continue;
+            }
+            if connected {
+                place.connections.push(other.to_string());
+            } else {
+                place.connections.retain(|c| c != other);
+            }
+            write_connections(&mut tx, &place).await?;
+        }
+        finish(tx, story_id).await
+    }
+    .await;
+    pool.close().await;
+    changed
+}
+
+/// Move the party to a place and mark it visited. Unless `travel` is set
+/// the place must connect directly to where the party is; a party that is
+/// nowhere yet may start anywhere.
+pub async fn move_party(
+    app: &AppHandle,
+    story_id: &str,
+    to: &str,
+    travel: bool,
+) -> Result<StoryMap, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let moved = async {
+        let mut tx = begin(&pool).await?;
+        let (branch_id, map) = read(&mut tx, story_id).await?;
+        let destination = map.place(to)?;
+        if let Some(current) = map.current() {
+            if current.id == destination.id {
+                return Err(format!("The party is already at {}", destination.name));
+            }
+            if !travel && !current.connections.iter().any(|c| c == to) {
+                return Err(format!(
+                    "{} can't be reached from {} directly",
+                    destination.name, current.name
+                ));
+            }
+        }
+        sqlx::query("UPDATE locations SET current = 0 WHERE story_id = ? AND branch_id IS ?")
+            .bind(story_id)
+            .bind(&branch_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to update locations: {}", e))?;
+        sqlx::query("UPDATE locations SET current = 1, visited = 1 WHERE id = ?")
+            .bind(to)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to update location: {}", e))?;
+        finish(tx, story_id).await
+    }
+    .await;
+    pool.close().await;
+    moved
+}
@@ -0,0 +1,144 @@
+//! A simple SVG picture of the map. Places sit on rings around where the
+//! party is, one ring per step away; places that can't be reached from
+//! there go on an outer ring. Visited places are filled, the party's place
+//! is marked, and one-way connections carry an arrow.
+
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::TAU;
+use std::fmt::Write;
+
+use super::StoryMap;
+use crate::export::site::escape_html;
+
+/// Distance between rings
+const RING_SPACING: f64 = 110.0;
+/// Space around the outermost ring for labels
+const MARGIN: f64 = 70.0;
+const NODE_RADIUS: f64 = 9.0;
+/// Turn between the first places of neighbouring rings, in radians
+const RING_TWIST: f64 = 0.9;
+
+/// Ring of each place: steps from the center place, with places it can't
+/// reach one ring further out than the farthest it can
+fn rings(map: &StoryMap) -> Vec<usize> {
+    let Some(center) = map.current().or(map.places.first()) else {
+        return Vec::new();
+    };
+    let distances = map.distances(&center.id);
+    let outer = distances.values().max().map_or(0, |d| *d as usize + 1);
+    map.places
+        .iter()
+        .map(|p| distances.get(p.id.as_str()).map_or(outer, |d| *d as usize))
+        .collect()
+}
+
+/// Render the map as a standalone SVG document
+pub fn render(map: &StoryMap) -> String {
+    let rings = rings(map);
+    let outermost = rings.iter().max().copied().unwrap_or(0);
+    let size = 2.0 * (outermost as f64 * RING_SPACING + MARGIN);
+    let center = size / 2.0;
+
+    let mut per_ring: HashMap<usize, usize> = HashMap::new();
+    for ring in &rings {
+        *per_ring.entry(*ring).or_default() += 1;
+    }
+    let mut placed: HashMap<usize, usize> = HashMap::new();
+    let positions: HashMap<&str, (f64, f64)> = map
+        .places
+        .iter()
+        .zip(&rings)
+        .map(|(place, ring)| {
+            let index = placed.entry(*ring).or_default();
+            // Each ring starts a little further round so chains of single
+            // places don't line up over each other
+            let angle =
+                TAU * *index as f64 / per_ring[ring] as f64 + *ring as f64 * RING_TWIST - TAU / 4.0;
+            *index += 1;
+            let radius = *ring as f64 * RING_SPACING;
+            (
+                place.id.as_str(),
+                (center + radius * angle.cos(), center + radius * angle.sin()),
+            )
+        })
+        .collect();
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size:.0}\" height=\"{size:.0}\" \
+         viewBox=\"0 0 {size:.0} {size:.0}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+         <defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" \
+         markerWidth=\"7\" markerHeight=\"7\" orient=\"auto-start-reverse\">\
+         <path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"#888\"/></marker></defs>\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"#fdfbf7\"/>\n"
+    );
+
+    let mut drawn: HashSet<(&str, &str)> = HashSet::new();
+    for place in &map.places {
+        for to in &place.connections {
+            let (Some(a), Some(b)) = (positions.get(place.id.as_str()), positions.get(to.as_str()))
+            else {
+                continue;
+            };
+            if drawn.contains(&(to.as_str(), place.id.as_str())) {
+                continue;
+            }
+            drawn.insert((place.id.as_str(), to.as_str()));
+            let two_way = map
+                .place(to)
+                .is_ok_and(|other| other.connections.contains(&place.id));
+            // Stop the line at the edge of the node so the arrow shows
+            let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt().max(1.0);
+            let end = (
+                b.0 - (b.0 - a.0) * NODE_RADIUS / length,
+                b.1 - (b.1 - a.1) * NODE_RADIUS / length,
+            );
+            let _ = writeln!(
+                svg,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#888\" \
+                 stroke-width=\"1.5\"{}/>",
+                a.0,
+                a.1,
+                end.0,
+                end.1,
+                if two_way {
+                    ""
+                } else {
+                    " marker-end=\"url(#arrow)\""
+                }
+            );
+        }
+    }
+
+    for place in &map.places {
+        let (x, y) = positions[place.id.as_str()];
+        let (fill, stroke, dash) = match (place.current, place.visited) {
+            (true, _) => ("#d9822b", "#8a4b10", ""),
+            (false, true) => ("#9bb0c9", "#44546a", ""),
+            (false, false) => ("#ffffff", "#44546a", " stroke-dasharray=\"3 2\""),
+        };
+        let _ = writeln!(
+            svg,
+            "<g><title>{}</title><circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"{}\" fill=\"{fill}\" \
+             stroke=\"{stroke}\" stroke-width=\"{}\"{dash}/>\
+             <text x=\"{x:.1}\" y=\"{:.1}\" text-anchor=\"middle\"{}>{}</text></g>",
+            escape_html(place.description.as_deref().unwrap_or(&place.name)),
+            if place.current {
+                NODE_RADIUS + 3.0
+            } else {
+                NODE_RADIUS
+            },
+            if place.current { 3 } else { 1 },
+            y + NODE_RADIUS + 16.0,
+            if place.current {
+                " font-weight=\"bold\""
+            } else {
+                ""
+            },
+            escape_html(&place.name),
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
@@ -0,0 +1,50 @@
+//! Portable mode, for running Aventura from removable media on machines
+//! where it is not installed. It is turned on by an `aventura.portable` file
+//! next to the executable (or next to the AppImage on Linux) or by launching
+//! with `--portable`. All data then lives in an `AventuraData` folder beside
+//! the executable, `aventura://` links are not registered with the system
+//! and secrets go to an encrypted file in that folder instead of the OS
+//! keychain.
+
+pub mod secrets;
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Marker file that turns portable mode on
+pub const MARKER_FILE: &str = "aventura.portable";
+
+/// Command line flag that turns portable mode on
+pub const PORTABLE_FLAG: &str = "--portable";
+
+/// Data folder created next to the executable
+const DATA_DIR: &str = "AventuraData";
+
+static ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Folder holding the executable. An AppImage runs from a temporary mount,
+/// so the image file's own folder is used instead.
+fn app_dir() -> Option<PathBuf> {
+    if let Some(image) = std::env::var_os("APPIMAGE") {
+        return PathBuf::from(image).parent().map(Path::to_path_buf);
+    }
+    std::env::current_exe()
+        .ok()?
+        .parent()
+        .map(Path::to_path_buf)
+}
+
+fn detect() -> Option<PathBuf> {
+    let dir = app_dir()?;
+    let flagged = std::env::args().skip(1).any(|arg| arg == PORTABLE_FLAG);
+    (flagged || dir.join(MARKER_FILE).is_file()).then(|| dir.join(DATA_DIR))
+}
+
+/// The portable data folder, when running in portable mode
+pub fn root() -> Option<&'static Path> {
+    ROOT.get_or_init(detect).as_deref()
+}
+
+pub fn is_enabled() -> bool {
+    root().is_some()
+}
@@ -0,0 +1,117 @@
+//! Secrets store used in portable mode in place of the OS keychain. Values
+//! are kept in one ChaCha20-Poly1305 encrypted file, with a random key in a
+//! second file beside it. That keeps tokens out of plain sight and out of
+//! copies of the data file alone, but anyone holding the whole data folder
+//! can read them.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::sync::keys::EncryptedKeys;
+
+const SECRETS_FILE: &str = "secrets.enc";
+const KEY_FILE: &str = "secrets.key";
+
+/// Serialises read-modify-write cycles on the secrets file
+static LOCK: Mutex<()> = Mutex::new(());
+
+type Secrets = BTreeMap<String, String>;
+
+fn root() -> Result<&'static Path, String> {
+    super::root().ok_or_else(|| "Portable mode is not enabled".to_string())
+}
+
+fn file(name: &str) -> Result<PathBuf, String> {
+    let root = root()?;
+    fs::create_dir_all(root).map_err(|e| format!("Failed to create {}: {}", root.display(), e))?;
+    Ok(root.join(name))
+}
+
+/// The encryption key, created on first use
+fn key() -> Result<Key, String> {
+    let path = file(KEY_FILE)?;
+    match fs::read(&path) {
+        Ok(bytes) if bytes.len() == 32 => Ok(*Key::from_slice(&bytes)),
+        Ok(_) => Err(format!("{} is damaged", KEY_FILE)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            fs::write(&path, key).map_err(|e| format!("Failed to write {}: {}", KEY_FILE, e))?;
+            Ok(key)
+        }
+        Err(e) => Err(format!("Failed to read {}: {}", KEY_FILE, e)),
+    }
+}
+
+fn load(cipher: &ChaCha20Poly1305) -> Result<Secrets, String> {
+    let path = file(SECRETS_FILE)?;
+    if !path.exists() {
+        return Ok(Secrets::new());
+    }
+    let json =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", SECRETS_FILE, e))?;
+    let payload: EncryptedKeys = serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid JSON in {}: {}", SECRETS_FILE, e))?;
+    let nonce = STANDARD
+        .decode(&payload.nonce)
+        .ok()
+        .filter(|n| n.len() == 12)
+        .ok_or_else(|| format!("{} is damaged", SECRETS_FILE))?;
+    let ciphertext = STANDARD
+        .decode(&payload.ciphertext)
+        .map_err(|_| format!("{} is damaged", SECRETS_FILE))?;
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| format!("{} could not be decrypted with {}", SECRETS_FILE, KEY_FILE))?;
+    serde_json::from_slice(&plaintext).map_err(|_| format!("{} is damaged", SECRETS_FILE))
+}
+
+fn save(cipher: &ChaCha20Poly1305, secrets: &Secrets) -> Result<(), String> {
+    let path = file(SECRETS_FILE)?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let plaintext =
+        serde_json::to_vec(secrets).map_err(|e| format!("Failed to encode secrets: {}", e))?;
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| "Failed to encrypt secrets".to_string())?;
+    let json = serde_json::to_string(&EncryptedKeys {
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+    .map_err(|e| format!("Failed to serialize {}: {}", SECRETS_FILE, e))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write {}: {}", SECRETS_FILE, e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to save {}: {}", SECRETS_FILE, e))
+}
+
+/// Store values under their account names, replacing any already there
+pub fn set_all<'a>(entries: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<(), String> {
+    let _guard = LOCK.lock().map_err(|_| "Secrets store is unavailable")?;
+    let cipher = ChaCha20Poly1305::new(&key()?);
+    let mut secrets = load(&cipher)?;
+    for (account, value) in entries {
+        secrets.insert(account.to_string(), value.to_string());
+    }
+    save(&cipher, &secrets)
+}
+
+pub fn get(account: &str) -> Result<Option<String>, String> {
+    let _guard = LOCK.lock().map_err(|_| "Secrets store is unavailable")?;
+    let cipher = ChaCha20Poly1305::new(&key()?);
+    Ok(load(&cipher)?.remove(account))
+}
+
+/// Forget a value; a missing one is not an error
+pub fn remove(account: &str) -> Result<(), String> {
+    let _guard = LOCK.lock().map_err(|_| "Secrets store is unavailable")?;
+    let cipher = ChaCha20Poly1305::new(&key()?);
+    let mut secrets = load(&cipher)?;
+    if secrets.remove(account).is_some() {
+        save(&cipher, &secrets)?;
+    }
+    Ok(())
+}
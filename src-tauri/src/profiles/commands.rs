@@ -0,0 +1,182 @@
+use std::sync::{Mutex, RwLock};
+use tauri::AppHandle;
+
+use super::{ActiveProfile, ActiveProfileInfo, PinHash, Profile, ProfileInfo, ProfileRestrictions};
+
+/// State managed by Tauri for profiles
+#[derive(Default)]
+pub struct ProfileState {
+    /// Active profile and its data directory, loaded on first use and
+    /// replaced as one so the two never disagree
+    active: RwLock<Option<ActiveProfile>>,
+    /// Held while wrong PIN attempts are read and written
+    pub(crate) pin_checks: Mutex<()>,
+    /// Held while the profiles file is rewritten
+    pub(crate) writes: Mutex<()>,
+    /// Held for the whole of a profile switch
+    pub(crate) switching: tokio::sync::Mutex<()>,
+}
+
+impl ProfileState {
+    pub(crate) fn cached(&self) -> Option<ActiveProfile> {
+        self.active.read().ok().and_then(|active| active.clone())
+    }
+
+    pub(crate) fn set_active(&self, profile: ActiveProfile) {
+        if let Ok(mut active) = self.active.write() {
+            *active = Some(profile);
+        }
+    }
+}
+
+fn trimmed_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 40 {
+        return Err("Profile names are 1 to 40 characters".to_string());
+    }
+    Ok(name.to_string())
+}
+
+#[tauri::command]
+pub async fn list_profiles(app: AppHandle) -> Result<Vec<ProfileInfo>, String> {
+    let profiles = super::load(&app)?;
+    let active_id = profiles.active().id.clone();
+    Ok(profiles
+        .profiles
+        .iter()
+        .map(|p| p.info(&active_id))
+        .collect())
+}
+
+/// The active profile, with the database URL the frontend should open
+#[tauri::command]
+pub async fn get_active_profile(app: AppHandle) -> Result<ActiveProfileInfo, String> {
+    Ok(super::current(&app)?.info())
+}
+
+/// Make another profile active, checking its PIN if it has one. After
+/// `MAX_PIN_ATTEMPTS` wrong PINs the profile is locked for a minute. The
+/// switch takes effect at once; the frontend reopens the returned database.
+#[tauri::command]
+pub async fn switch_profile(
+    app: AppHandle,
+    id: String,
+    pin: Option<String>,
+) -> Result<ActiveProfileInfo, String> {
+    let target = super::load(&app)?.get(&id)?.clone();
+    super::verify_pin(&app, &target, pin.as_deref())?;
+    Ok(super::switch(&app, &id).await?.info())
+}
+
+/// Add a profile. Admin only.
+#[tauri::command]
+pub async fn create_profile(
+    app: AppHandle,
+    name: String,
+    admin: bool,
+    pin: Option<String>,
+    restrictions: Option<ProfileRestrictions>,
+) -> Result<ProfileInfo, String> {
+    super::require_admin(&app)?;
+    let mut profile = Profile::new(&trimmed_name(&name)?, admin);
+    if let Some(pin) = pin.as_deref() {
+        super::validate_pin(pin)?;
+        profile.pin = Some(PinHash::new(pin)?);
+    }
+    profile.restrictions = restrictions.unwrap_or_default();
+    super::update(&app, |profiles| {
+        let active_id = profiles.active().id.clone();
+        profiles.profiles.push(profile.clone());
+        Ok(profile.info(&active_id))
+    })
+}
+
+/// Rename a profile or change what it may do. Admin only.
+#[tauri::command]
+pub async fn update_profile(
+    app: AppHandle,
+    id: String,
+    name: Option<String>,
+    avatar: Option<String>,
+    admin: Option<bool>,
+    restrictions: Option<ProfileRestrictions>,
+) -> Result<ProfileInfo, String> {
+    super::require_admin(&app)?;
+    let name = name.as_deref().map(trimmed_name).transpose()?;
+    super::update(&app, |profiles| {
+        let active_id = profiles.active().id.clone();
+        let profile = profiles.get_mut(&id)?;
+        if let Some(name) = name {
+            profile.name = name;
+        }
+        if avatar.is_some() {
+            profile.avatar = avatar.filter(|a| !a.is_empty());
+        }
+        if let Some(admin) = admin {
+            profile.admin = admin;
+        }
+        if let Some(restrictions) = restrictions {
+            profile.restrictions = restrictions;
+        }
+        Ok(profile.info(&active_id))
+    })
+}
+
+/// Set or clear a profile's PIN. Admins may change any PIN; others only
+/// their own, by giving the current one.
+#[tauri::command]
+pub async fn set_profile_pin(
+    app: AppHandle,
+    id: String,
+    current_pin: Option<String>,
+    new_pin: Option<String>,
+) -> Result<(), String> {
+    let active = super::active(&app)?;
+    if !active.admin {
+        if active.id != id {
+            return Err("Only an admin profile can change other profiles' PINs".to_string());
+        }
+        super::verify_pin(&app, &active, current_pin.as_deref())?;
+    }
+    let pin = match new_pin.as_deref() {
+        Some(pin) => {
+            super::validate_pin(pin)?;
+            Some(PinHash::new(pin)?)
+        }
+        None => None,
+    };
+    super::update(&app, |profiles| {
+        profiles.get_mut(&id)?.pin = pin;
+        Ok(())
+    })
+}
+
+/// Remove a profile other than the active one, with its stories and
+/// settings. The first profile cannot be removed. Admin only.
+#[tauri::command]
+pub async fn delete_profile(app: AppHandle, id: String) -> Result<(), String> {
+    super::require_admin(&app)?;
+    super::update(&app, |profiles| {
+        if profiles.active().id == id {
+            return Err("Switch to another profile before deleting this one".to_string());
+        }
+        if profiles.is_primary(&id) {
+            return Err("The first profile cannot be deleted".to_string());
+        }
+        profiles.get(&id)?;
+        profiles.profiles.retain(|p| p.id != id);
+        Ok(())
+    })?;
+    super::remove_data(&app, &id)
+}
+
+/// The given stories the active profile may see, in the same order, for
+/// filtering the library. Stories rated above the profile's limits are left
+/// out too.
+#[tauri::command]
+pub async fn filter_visible_stories(
+    app: AppHandle,
+    story_ids: Vec<String>,
+) -> Result<Vec<String>, String> {
+    super::visible_stories(&app, story_ids).await
+}
@@ -0,0 +1,73 @@
+//! Each profile but the first keeps its own copy of the frontend's database.
+//! The SQL plugin only migrates the databases it is given at startup, so a
+//! profile's database is created and brought up to date here before the
+//! frontend opens it.
+
+use sqlx::error::BoxDynError;
+use sqlx::migrate::{Migration, MigrationSource, MigrationType, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// How long a statement waits for another connection's write to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The app's schema migrations, as the SQL plugin runs them
+#[derive(Debug)]
+struct SchemaMigrations;
+
+impl MigrationSource<'static> for SchemaMigrations {
+    fn resolve(
+        self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<Migration>, BoxDynError>> + Send + 'static>> {
+        Box::pin(async move {
+            Ok(crate::migrations()
+                .into_iter()
+                .map(|m| {
+                    Migration::new(
+                        m.version,
+                        m.description.into(),
+                        MigrationType::ReversibleUp,
+                        m.sql.into(),
+                        false,
+                    )
+                })
+                .collect())
+        })
+    }
+}
+
+/// Open a database with a single connection. The frontend may have the same
+/// file open through the SQL plugin, so writers wait for each other rather
+/// than failing straight away.
+pub async fn open(path: &Path, create: bool) -> Result<SqlitePool, String> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(create)
+        .busy_timeout(BUSY_TIMEOUT);
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+        .map_err(|e| format!("Failed to open database: {}", e))
+}
+
+/// Create a profile's database if needed and apply any pending migrations
+pub async fn migrate(path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create profile directory: {}", e))?;
+    }
+    let pool = open(path, true).await?;
+    let result = match Migrator::new(SchemaMigrations).await {
+        Ok(migrator) => migrator
+            .run(&pool)
+            .await
+            .map_err(|e| format!("Failed to migrate profile database: {}", e)),
+        Err(e) => Err(format!("Failed to load migrations: {}", e)),
+    };
+    pool.close().await;
+    result
+}
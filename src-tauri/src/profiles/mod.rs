@@ -0,0 +1,667 @@
+//! Local profiles for a device shared by several people. Each profile can
+//! have a PIN, and restricted profiles only see the stories they are allowed,
+//! only reach the AI providers they are allowed, can be held to a minimum
+//! content filter strictness and can have stories hidden by their rating.
+//! The restrictions are checked by the backend commands themselves, so the
+//! frontend cannot bypass them.
+//!
+//! A device without profiles gets one unrestricted admin profile, so nothing
+//! changes until a second profile is added. That first profile keeps its
+//! data where it always was; each later profile gets its own data directory
+//! and database under `profiles/<id>`, so stories, attachments and settings
+//! never mix between profiles.
+
+pub mod commands;
+pub mod database;
+
+pub use commands::ProfileState;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::ai::filter::Strictness;
+use crate::ai::AiState;
+use crate::audio::AudioState;
+use crate::export;
+use crate::import;
+use crate::location;
+use crate::stats::{focus, StatsState};
+use crate::store;
+use crate::story::rating::{self, AgeRating, ContentRating, ContentWarning};
+use crate::story::StoryState;
+use crate::sync::commands::parse_story_preview;
+use crate::sync::keys::now_ms;
+use crate::sync::{self, SyncState};
+
+/// Profiles and the active one, in the shared app data directory
+pub const PROFILES_FILE: &str = "profiles.json";
+
+/// Database of the first profile, as the SQL plugin resolves it: relative to
+/// the app config directory. Data moved to another folder uses absolute URLs.
+pub const PRIMARY_DATABASE_URL: &str = "sqlite:aventura.db";
+
+/// Holds a directory per profile, in both the app data and config directories
+const PROFILES_DIR: &str = "profiles";
+
+const DATABASE_FILE: &str = "aventura.db";
+
+/// Wrong PINs entered per profile, in the shared app data directory so a
+/// restart does not lift a lockout
+const PIN_ATTEMPTS_FILE: &str = "pin_attempts.json";
+
+/// Wrong PINs allowed before a profile is locked for `PIN_LOCKOUT_MS`
+const MAX_PIN_ATTEMPTS: u32 = 5;
+
+const PIN_LOCKOUT_MS: i64 = 60_000;
+
+/// Which stories a profile sees
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum StoryVisibility {
+    #[default]
+    All,
+    /// Only these stories, plus any the profile creates
+    #[serde(rename_all = "camelCase")]
+    Only { story_ids: Vec<String> },
+    /// Every story but these
+    #[serde(rename_all = "camelCase")]
+    Except { story_ids: Vec<String> },
+}
+
+impl StoryVisibility {
+    pub fn allows(&self, story_id: &str) -> bool {
+        match self {
+            StoryVisibility::All => true,
+            StoryVisibility::Only { story_ids } => story_ids.iter().any(|id| id == story_id),
+            StoryVisibility::Except { story_ids } => !story_ids.iter().any(|id| id == story_id),
+        }
+    }
+}
+
+/// What a profile may do
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProfileRestrictions {
+    pub visibility: StoryVisibility,
+    /// Provider hosts (with the port if not the default) the profile may
+    /// generate with, e.g. "openrouter.ai" or "localhost:5001". None allows any.
+    pub allowed_providers: Option<Vec<String>>,
+    /// The content filter runs at least this strictly
+    pub min_filter_strictness: Option<Strictness>,
+    /// Stories rated above this are hidden
+    pub max_age_rating: Option<AgeRating>,
+    /// Also hide stories without an age rating while `max_age_rating` is set
+    pub hide_unrated: bool,
+    /// Stories carrying any of these warnings are hidden
+    pub blocked_warnings: Vec<ContentWarning>,
+}
+
+impl ProfileRestrictions {
+    /// Whether ratings hide any stories, so they need to be read at all
+    pub fn limits_ratings(&self) -> bool {
+        self.max_age_rating.is_some() || !self.blocked_warnings.is_empty()
+    }
+
+    pub fn allows_rating(&self, rating: &ContentRating) -> bool {
+        let age_allowed = match (self.max_age_rating, rating.age_rating) {
+            (None, _) => true,
+            (Some(_), None) => !self.hide_unrated,
+            (Some(max), Some(age)) => age <= max,
+        };
+        age_allowed
+            && !rating
+                .warnings
+                .iter()
+                .any(|w| self.blocked_warnings.contains(w))
+    }
+}
+
+/// A PIN as an Argon2id PHC string. PINs set before Argon2id was used are
+/// salted SHA-256 and are rehashed the next time they are entered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PinHash {
+    /// Only set for SHA-256 hashes; a PHC string carries its own salt
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    salt: String,
+    hash: String,
+}
+
+impl PinHash {
+    fn new(pin: &str) -> Result<Self, String> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(pin.as_bytes(), &salt)
+            .map_err(|e| format!("Failed to hash PIN: {}", e))?;
+        Ok(Self {
+            salt: String::new(),
+            hash: hash.to_string(),
+        })
+    }
+
+    fn is_legacy(&self) -> bool {
+        !self.salt.is_empty()
+    }
+
+    fn matches(&self, pin: &str) -> bool {
+        if self.is_legacy() {
+            return legacy_hash(&self.salt, pin)
+                .as_bytes()
+                .ct_eq(self.hash.as_bytes())
+                .into();
+        }
+        PasswordHash::new(&self.hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(pin.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+}
+
+fn legacy_hash(salt: &str, pin: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(salt.as_bytes())
+        .chain_update(pin.as_bytes())
+        .finalize();
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// PINs are 4 to 8 digits
+pub fn validate_pin(pin: &str) -> Result<(), String> {
+    if (4..=8).contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_digit()) {
+        Ok(())
+    } else {
+        Err("PINs are 4 to 8 digits".to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    /// Admins manage profiles and are never restricted
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default)]
+    pin: Option<PinHash>,
+    #[serde(default)]
+    pub restrictions: ProfileRestrictions,
+    pub created_at: i64,
+}
+
+impl Profile {
+    fn new(name: &str, admin: bool) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            avatar: None,
+            admin,
+            pin: None,
+            restrictions: ProfileRestrictions::default(),
+            created_at: now_ms(),
+        }
+    }
+
+    fn info(&self, active_id: &str) -> ProfileInfo {
+        ProfileInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            avatar: self.avatar.clone(),
+            admin: self.admin,
+            has_pin: self.pin.is_some(),
+            restrictions: self.restrictions.clone(),
+            active: self.id == active_id,
+        }
+    }
+
+    fn sees_story(&self, story_id: &str) -> bool {
+        self.admin || self.restrictions.visibility.allows(story_id)
+    }
+}
+
+/// A profile as shown to the frontend, without its PIN
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileInfo {
+    pub id: String,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub admin: bool,
+    pub has_pin: bool,
+    pub restrictions: ProfileRestrictions,
+    pub active: bool,
+}
+
+/// The active profile with where its data lives, for the frontend to open
+/// the right database
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveProfileInfo {
+    #[serde(flatten)]
+    pub profile: ProfileInfo,
+    pub database_url: String,
+}
+
+/// The active profile and where its data lives
+#[derive(Debug, Clone)]
+pub struct ActiveProfile {
+    pub profile: Profile,
+    pub data_dir: PathBuf,
+    pub database_url: String,
+    /// The database file `database_url` points at
+    pub database_path: PathBuf,
+}
+
+impl ActiveProfile {
+    pub fn info(&self) -> ActiveProfileInfo {
+        ActiveProfileInfo {
+            profile: self.profile.info(&self.profile.id),
+            database_url: self.database_url.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Profiles {
+    profiles: Vec<Profile>,
+    active_id: Option<String>,
+    /// The profile using the data from before profiles existed
+    primary_id: Option<String>,
+}
+
+impl Profiles {
+    fn get(&self, id: &str) -> Result<&Profile, String> {
+        self.profiles
+            .iter()
+            .find(|p| p.id == id)
+            .ok_or_else(|| format!("Profile not found: {}", id))
+    }
+
+    fn get_mut(&mut self, id: &str) -> Result<&mut Profile, String> {
+        self.profiles
+            .iter_mut()
+            .find(|p| p.id == id)
+            .ok_or_else(|| format!("Profile not found: {}", id))
+    }
+
+    /// The active profile, falling back to the first admin
+    fn active(&self) -> &Profile {
+        self.active_id
+            .as_deref()
+            .and_then(|id| self.get(id).ok())
+            .or_else(|| self.profiles.iter().find(|p| p.admin))
+            .unwrap_or(&self.profiles[0])
+    }
+
+    fn is_primary(&self, id: &str) -> bool {
+        self.primary_id.as_deref() == Some(id)
+    }
+
+    /// Where a profile's data lives
+    fn locate(&self, app: &AppHandle, profile: &Profile) -> Result<ActiveProfile, String> {
+        let shared = store::shared_dir(app)?;
+        let custom = location::roots(app)?.custom;
+        let (data_dir, database_url, database_path) = if self.is_primary(&profile.id) {
+            let path = location::roots(app)?.database_dir.join(DATABASE_FILE);
+            let url = match custom {
+                true => format!("sqlite:{}", path.display()),
+                false => PRIMARY_DATABASE_URL.to_string(),
+            };
+            (shared, url, path)
+        } else {
+            let path = database_dir(app, &profile.id)?.join(DATABASE_FILE);
+            let url = match custom {
+                true => format!("sqlite:{}", path.display()),
+                false => format!("sqlite:{}/{}/{}", PROFILES_DIR, profile.id, DATABASE_FILE),
+            };
+            (shared.join(PROFILES_DIR).join(&profile.id), url, path)
+        };
+        Ok(ActiveProfile {
+            profile: profile.clone(),
+            data_dir,
+            database_url,
+            database_path,
+        })
+    }
+}
+
+/// Profiles on disk, creating the default admin profile on first use
+fn load(app: &AppHandle) -> Result<Profiles, String> {
+    let mut profiles: Profiles = store::load_shared_json(app, PROFILES_FILE)?;
+    if profiles.profiles.is_empty() {
+        let default = Profile::new("Default", true);
+        profiles.active_id = Some(default.id.clone());
+        profiles.primary_id = Some(default.id.clone());
+        profiles.profiles.push(default);
+        store::save_shared_json(app, PROFILES_FILE, &profiles)?;
+    }
+    if profiles.primary_id.is_none() {
+        profiles.primary_id = Some(profiles.profiles[0].id.clone());
+    }
+    Ok(profiles)
+}
+
+/// Where a later profile's database lives
+fn database_dir(app: &AppHandle, profile_id: &str) -> Result<PathBuf, String> {
+    Ok(location::roots(app)?
+        .database_dir
+        .join(PROFILES_DIR)
+        .join(profile_id))
+}
+
+/// Change the profiles on disk, keeping the cached active profile in step
+fn update<T>(
+    app: &AppHandle,
+    change: impl FnOnce(&mut Profiles) -> Result<T, String>,
+) -> Result<T, String> {
+    let state = app.state::<ProfileState>();
+    let _write = state
+        .writes
+        .lock()
+        .map_err(|_| "Profiles are unavailable".to_string())?;
+    let mut profiles = load(app)?;
+    let result = change(&mut profiles)?;
+    if !profiles.profiles.iter().any(|p| p.admin) {
+        return Err("At least one admin profile is needed".to_string());
+    }
+    store::save_shared_json(app, PROFILES_FILE, &profiles)?;
+    state.set_active(profiles.locate(app, profiles.active())?);
+    Ok(result)
+}
+
+/// Make another profile active without a restart. Saves and queued sync
+/// pushes are held off while the data directory changes; the focus session
+/// and ambience end with the old profile, generations in flight are
+/// aborted, and background work starts again on the new profile's data.
+/// The frontend is told with `profile://switched`, on which it closes its
+/// database and reloads onto the new profile.
+async fn switch(app: &AppHandle, id: &str) -> Result<ActiveProfile, String> {
+    let state = app.state::<ProfileState>();
+    let _switching = state.switching.lock().await;
+    let profiles = load(app)?;
+    let target = profiles.locate(app, profiles.get(id)?)?;
+    if profiles.active().id == id {
+        return current(app);
+    }
+    if target.database_url != PRIMARY_DATABASE_URL {
+        database::migrate(&target.database_path).await?;
+    }
+
+    let story_state = app.state::<StoryState>();
+    let _saves = story_state.saves.lock().await;
+    let sync_state = app.state::<SyncState>();
+    let outbox = sync_state.outbox.pause().await;
+    let stats = app.state::<StatsState>();
+    // Not running is fine; a running session is recorded in the old profile
+    let _ = focus::stop(app, &stats).await;
+    let _ = app
+        .state::<AudioState>()
+        .ambience
+        .stop(Duration::from_millis(500));
+
+    update(app, |profiles| {
+        profiles.active_id = Some(id.to_string());
+        Ok(())
+    })?;
+    app.state::<AiState>().reset().await;
+    drop(outbox);
+
+    import::watcher::restart(app).await;
+    sync::outbox::resume(app);
+    export::schedule::resume(app);
+    let active = current(app)?;
+    let _ = app.emit("profile://switched", active.info());
+    Ok(active)
+}
+
+/// Remove a deleted profile's data directory and database
+fn remove_data(app: &AppHandle, id: &str) -> Result<(), String> {
+    let dirs = [
+        store::shared_dir(app)?.join(PROFILES_DIR).join(id),
+        database_dir(app, id)?,
+    ];
+    for dir in dirs {
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to remove profile data: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+/// Bring the active profile's database up to date at launch, unless it is
+/// the one the SQL plugin migrates itself
+pub async fn migrate_active(app: &AppHandle) -> Result<(), String> {
+    let active = current(app)?;
+    if active.database_url == PRIMARY_DATABASE_URL {
+        return Ok(());
+    }
+    database::migrate(&active.database_path).await
+}
+
+/// The profile in use and where its data lives
+pub fn current(app: &AppHandle) -> Result<ActiveProfile, String> {
+    let state = app.state::<ProfileState>();
+    if let Some(active) = state.cached() {
+        return Ok(active);
+    }
+    let profiles = load(app)?;
+    let active = profiles.locate(app, profiles.active())?;
+    state.set_active(active.clone());
+    Ok(active)
+}
+
+/// The profile in use
+pub fn active(app: &AppHandle) -> Result<Profile, String> {
+    current(app).map(|active| active.profile)
+}
+
+pub fn require_admin(app: &AppHandle) -> Result<(), String> {
+    if active(app)?.admin {
+        Ok(())
+    } else {
+        Err("Only an admin profile can manage profiles".to_string())
+    }
+}
+
+/// Fail unless the active profile may see a story, by its ID and by its
+/// rating. A story not saved yet has no rating to hold against it.
+pub async fn check_story(app: &AppHandle, story_id: &str) -> Result<(), String> {
+    let profile = active(app)?;
+    check_story_id(&profile, story_id)?;
+    if profile.admin || !profile.restrictions.limits_ratings() {
+        return Ok(());
+    }
+    match rating::find(app, story_id).await? {
+        Some(rating) if !profile.restrictions.allows_rating(&rating) => {
+            Err("This story is not available in the current profile".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Like `check_story` without the rating, for stories that are not in the
+/// database, such as archived ones and deletions
+pub fn check_story_id(profile: &Profile, story_id: &str) -> Result<(), String> {
+    if profile.sees_story(story_id) {
+        Ok(())
+    } else {
+        Err("This story is not available in the current profile".to_string())
+    }
+}
+
+/// The given stories the active profile may see, in the same order.
+/// Stories rated above the profile's limits are left out too.
+pub async fn visible_stories(
+    app: &AppHandle,
+    story_ids: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let profile = active(app)?;
+    let mut visible: Vec<String> = story_ids
+        .into_iter()
+        .filter(|id| profile.sees_story(id))
+        .collect();
+    if !profile.admin && profile.restrictions.limits_ratings() {
+        let ratings = rating::all(app).await?;
+        visible.retain(|id| {
+            ratings
+                .get(id)
+                .is_none_or(|r| profile.restrictions.allows_rating(r))
+        });
+    }
+    Ok(visible)
+}
+
+/// The given stories in Aventura export format that the active profile may
+/// see, for commands that take the library from the frontend. Exports that
+/// cannot be read are left for the command to report.
+pub async fn visible_exports(
+    app: &AppHandle,
+    stories_json: Vec<String>,
+) -> Result<Vec<String>, String> {
+    if active(app)?.admin {
+        return Ok(stories_json);
+    }
+    let ids: Vec<Option<String>> = stories_json
+        .iter()
+        .map(|json| parse_story_preview(json).ok().map(|p| p.id))
+        .collect();
+    let visible: HashSet<String> = visible_stories(app, ids.iter().flatten().cloned().collect())
+        .await?
+        .into_iter()
+        .collect();
+    Ok(stories_json
+        .into_iter()
+        .zip(ids)
+        .filter(|(_, id)| id.as_ref().is_none_or(|id| visible.contains(id)))
+        .map(|(json, _)| json)
+        .collect())
+}
+
+/// Like `check_story`, for a save. A story saved for the first time by a
+/// profile limited to certain stories becomes one of them.
+pub fn check_story_save(app: &AppHandle, story_id: &str, is_new: bool) -> Result<(), String> {
+    let profile = active(app)?;
+    if profile.sees_story(story_id) {
+        return Ok(());
+    }
+    if !is_new
+        || !matches!(
+            profile.restrictions.visibility,
+            StoryVisibility::Only { .. }
+        )
+    {
+        return Err("This story is not available in the current profile".to_string());
+    }
+    update(app, |profiles| {
+        if let StoryVisibility::Only { story_ids } =
+            &mut profiles.get_mut(&profile.id)?.restrictions.visibility
+        {
+            story_ids.push(story_id.to_string());
+        }
+        Ok(())
+    })
+}
+
+/// Host of a provider URL as profiles list it: the port is kept unless it
+/// is the scheme's default
+fn provider_host(base_url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(base_url.trim()).ok()?;
+    let host = url.host_str()?.to_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    })
+}
+
+/// Fail unless the active profile may generate with a provider, and raise
+/// the filter strictness to the profile's minimum
+pub fn check_generation(
+    app: &AppHandle,
+    base_url: &str,
+    strictness: Strictness,
+) -> Result<Strictness, String> {
+    let profile = active(app)?;
+    if profile.admin {
+        return Ok(strictness);
+    }
+    let restrictions = &profile.restrictions;
+    if let Some(allowed) = restrictions.allowed_providers.as_ref() {
+        let host = provider_host(base_url).ok_or("Invalid provider URL")?;
+        if !allowed.iter().any(|a| a.trim().eq_ignore_ascii_case(&host)) {
+            return Err(format!(
+                "The current profile may not use the provider at {}",
+                host
+            ));
+        }
+    }
+    Ok(restrictions
+        .min_filter_strictness
+        .map_or(strictness, |min| strictness.max(min)))
+}
+
+/// Wrong PINs in a row for a profile and when the last one was entered
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PinAttempts {
+    failures: u32,
+    last_failure_at: i64,
+}
+
+/// Check a PIN for a profile, locking it for a while after repeated
+/// failures. A correct PIN still hashed with SHA-256 is rehashed.
+fn verify_pin(app: &AppHandle, profile: &Profile, pin: Option<&str>) -> Result<(), String> {
+    let Some(hash) = profile.pin.as_ref() else {
+        return Ok(());
+    };
+    let state = app.state::<ProfileState>();
+    let _checking = state
+        .pin_checks
+        .lock()
+        .map_err(|_| "Profiles are unavailable".to_string())?;
+    let mut attempts: HashMap<String, PinAttempts> =
+        store::load_shared_json(app, PIN_ATTEMPTS_FILE)?;
+    let entry = attempts.entry(profile.id.clone()).or_default();
+    let now = now_ms();
+    if entry.failures >= MAX_PIN_ATTEMPTS {
+        // A clock set back does not extend the lockout past its length
+        let waited = (now - entry.last_failure_at).max(0);
+        if waited < PIN_LOCKOUT_MS {
+            return Err(format!(
+                "Too many wrong PINs. Try again in {} seconds.",
+                ((PIN_LOCKOUT_MS - waited) / 1000).max(1)
+            ));
+        }
+        *entry = PinAttempts::default();
+    }
+
+    if pin.is_some_and(|pin| hash.matches(pin)) {
+        if attempts.remove(&profile.id).is_some() {
+            store::save_shared_json(app, PIN_ATTEMPTS_FILE, &attempts)?;
+        }
+        if let Some(pin) = pin.filter(|_| hash.is_legacy()) {
+            let rehashed = PinHash::new(pin)?;
+            update(app, |profiles| {
+                profiles.get_mut(&profile.id)?.pin = Some(rehashed);
+                Ok(())
+            })?;
+        }
+        return Ok(());
+    }
+    entry.failures += 1;
+    entry.last_failure_at = now;
+    store::save_shared_json(app, PIN_ATTEMPTS_FILE, &attempts)?;
+    Err("Wrong PIN".to_string())
+}
@@ -0,0 +1,170 @@
+use spellbook::Dictionary;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+use tokio::sync::Mutex;
+
+use super::grammar;
+use super::spellcheck::{
+    self, detect_language, installed_dictionaries, load_dictionary, resolve_dictionary_name,
+    DICTIONARY_DIR,
+};
+use super::types::{GrammarIssue, SpellcheckResult};
+use super::words;
+use crate::{profiles, store};
+
+/// State managed by Tauri for proofing operations
+pub struct ProofingState {
+    /// Parsed Hunspell dictionaries, keyed by name (parsing is slow)
+    dictionaries: Arc<Mutex<HashMap<String, Arc<Dictionary>>>>,
+}
+
+impl Default for ProofingState {
+    fn default() -> Self {
+        Self {
+            dictionaries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl ProofingState {
+    /// Get a dictionary, loading it on first use
+    async fn dictionary(&self, dir: &Path, name: &str) -> Result<Arc<Dictionary>, String> {
+        let mut dictionaries = self.dictionaries.lock().await;
+        if let Some(dictionary) = dictionaries.get(name) {
+            return Ok(dictionary.clone());
+        }
+
+        let dir = dir.to_path_buf();
+        let owned_name = name.to_string();
+        let dictionary = tokio::task::spawn_blocking(move || load_dictionary(&dir, &owned_name))
+            .await
+            .map_err(|e| format!("Failed to load dictionary: {}", e))??;
+        let dictionary = Arc::new(dictionary);
+        dictionaries.insert(name.to_string(), dictionary.clone());
+        Ok(dictionary)
+    }
+}
+
+/// Check text for grammar and style issues without sending it to a third party
+#[tauri::command]
+pub async fn check_text(text: String, language: String) -> Result<Vec<GrammarIssue>, String> {
+    tokio::task::spawn_blocking(move || grammar::check(&text, &language))
+        .await
+        .map_err(|e| format!("Grammar check failed: {}", e))?
+}
+
+/// Spellcheck text, accepting the story's custom words.
+/// The language is detected from the text when not given.
+#[tauri::command]
+pub async fn spellcheck(
+    app: AppHandle,
+    state: State<'_, ProofingState>,
+    text: String,
+    story_id: Option<String>,
+    language: Option<String>,
+) -> Result<SpellcheckResult, String> {
+    let dir = store::data_file(&app, DICTIONARY_DIR)?;
+    let (language, detected) = match language {
+        Some(language) => (language, false),
+        None => (detect_language(&text).unwrap_or("en").to_string(), true),
+    };
+    let name = resolve_dictionary_name(&dir, &language)?;
+    let dictionary = state.dictionary(&dir, &name).await?;
+
+    let custom_words = match story_id {
+        Some(id) => {
+            profiles::check_story(&app, &id).await?;
+            words::story_words(&app, &id).await?
+        }
+        None => Vec::new(),
+    };
+
+    let issues =
+        tokio::task::spawn_blocking(move || spellcheck::check(&dictionary, &text, &custom_words))
+            .await
+            .map_err(|e| format!("Spellcheck failed: {}", e))?;
+
+    Ok(SpellcheckResult {
+        language: name,
+        detected,
+        issues,
+    })
+}
+
+/// Get the custom words accepted for a story
+#[tauri::command]
+pub async fn get_story_dictionary(app: AppHandle, story_id: String) -> Result<Vec<String>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    words::story_words(&app, &story_id).await
+}
+
+/// Add a word (e.g. an invented name) to a story's custom dictionary
+#[tauri::command]
+pub async fn add_to_dictionary(
+    app: AppHandle,
+    story_id: String,
+    word: String,
+) -> Result<(), String> {
+    let word = word.trim();
+    if word.is_empty() || word.contains(char::is_whitespace) {
+        return Err(format!("Not a single word: '{}'", word));
+    }
+    profiles::check_story(&app, &story_id).await?;
+    words::add(&app, &story_id, word).await
+}
+
+/// Remove a word from a story's custom dictionary
+#[tauri::command]
+pub async fn remove_from_dictionary(
+    app: AppHandle,
+    story_id: String,
+    word: String,
+) -> Result<(), String> {
+    profiles::check_story(&app, &story_id).await?;
+    words::remove(&app, &story_id, word.trim()).await
+}
+
+/// Names of the installed spellcheck dictionaries
+#[tauri::command]
+pub async fn list_dictionaries(app: AppHandle) -> Result<Vec<String>, String> {
+    Ok(installed_dictionaries(&store::data_file(
+        &app,
+        DICTIONARY_DIR,
+    )?))
+}
+
+/// Install a Hunspell dictionary from an `.aff`/`.dic` pair, such as one
+/// downloaded from LibreOffice. The name defaults to the `.aff` file's
+/// (e.g. "en_US") and replaces a dictionary installed under it.
+#[tauri::command]
+pub async fn install_dictionary(
+    app: AppHandle,
+    state: State<'_, ProofingState>,
+    aff_path: String,
+    dic_path: String,
+    name: Option<String>,
+) -> Result<String, String> {
+    let dir = store::data_file(&app, DICTIONARY_DIR)?;
+    let aff_path = PathBuf::from(aff_path);
+    let name = match name {
+        Some(name) => name.trim().to_string(),
+        None => aff_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or("Choose an .aff file")?,
+    };
+    let owned_name = name.clone();
+    let dictionary = tokio::task::spawn_blocking(move || {
+        spellcheck::install_dictionary(&dir, &owned_name, &aff_path, &PathBuf::from(dic_path))
+    })
+    .await
+    .map_err(|e| format!("Failed to install dictionary: {}", e))??;
+    state
+        .dictionaries
+        .lock()
+        .await
+        .insert(name.clone(), Arc::new(dictionary));
+    Ok(name)
+}
@@ -0,0 +1,71 @@
+use harper_core::linting::{LintGroup, Suggestion};
+use harper_core::spell::FstDictionary;
+use harper_core::{Dialect, Document};
+
+use super::types::{GrammarIssue, TextFix};
+use super::utf16_offsets;
+
+/// Rules that are too strict for creative writing, matching the frontend linter
+const DISABLED_RULES: &[&str] = &["SentenceCapitalization", "LongSentences"];
+
+/// Map a language tag to a Harper dialect. Only English is supported.
+fn dialect_for(language: &str) -> Result<Dialect, String> {
+    let tag = language.to_ascii_lowercase().replace('_', "-");
+    match tag.as_str() {
+        "en" | "en-us" => Ok(Dialect::American),
+        "en-gb" | "en-uk" => Ok(Dialect::British),
+        "en-au" => Ok(Dialect::Australian),
+        "en-ca" => Ok(Dialect::Canadian),
+        "en-in" => Ok(Dialect::Indian),
+        _ => Err(format!(
+            "Grammar checking is not available for language: {}",
+            language
+        )),
+    }
+}
+
+fn convert_suggestion(suggestion: &Suggestion) -> TextFix {
+    match suggestion {
+        Suggestion::ReplaceWith(chars) => TextFix::Replace {
+            text: chars.iter().collect(),
+        },
+        Suggestion::InsertAfter(chars) => TextFix::InsertAfter {
+            text: chars.iter().collect(),
+        },
+        Suggestion::Remove => TextFix::Remove,
+    }
+}
+
+/// Check text for grammar and style issues using the bundled Harper linter.
+/// Runs entirely locally; nothing is sent over the network.
+pub fn check(text: &str, language: &str) -> Result<Vec<GrammarIssue>, String> {
+    let dialect = dialect_for(language)?;
+
+    let mut linter = LintGroup::new_curated(FstDictionary::curated(), dialect);
+    for rule in DISABLED_RULES {
+        linter.config.set_rule_enabled(*rule, false);
+    }
+
+    let document = Document::new_plain_english_curated(text);
+    let chars: Vec<char> = text.chars().collect();
+    let offsets = utf16_offsets(&chars);
+
+    let mut issues: Vec<GrammarIssue> = linter
+        .organized_lints(&document)
+        .into_iter()
+        .flat_map(|(rule_id, lints)| lints.into_iter().map(move |lint| (rule_id.clone(), lint)))
+        .filter(|(_, lint)| lint.span.end <= chars.len())
+        .map(|(rule_id, lint)| GrammarIssue {
+            rule_id,
+            kind: lint.lint_kind.to_string_key(),
+            message: lint.message.clone(),
+            start: offsets[lint.span.start],
+            end: offsets[lint.span.end],
+            problem_text: lint.get_str(&chars),
+            suggestions: lint.suggestions.iter().map(convert_suggestion).collect(),
+        })
+        .collect();
+
+    issues.sort_by_key(|issue| (issue.start, issue.end));
+    Ok(issues)
+}
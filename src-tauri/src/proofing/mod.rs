@@ -0,0 +1,19 @@
+pub mod commands;
+pub mod grammar;
+pub mod spellcheck;
+pub mod types;
+pub mod words;
+
+pub use commands::ProofingState;
+
+/// UTF-16 offset of every char boundary, including the end of the text
+pub(crate) fn utf16_offsets(chars: &[char]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(chars.len() + 1);
+    let mut offset = 0;
+    offsets.push(offset);
+    for c in chars {
+        offset += c.len_utf16();
+        offsets.push(offset);
+    }
+    offsets
+}
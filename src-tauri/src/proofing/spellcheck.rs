@@ -0,0 +1,168 @@
+use spellbook::Dictionary;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use super::types::SpellingIssue;
+use super::utf16_offsets;
+
+/// Directory (inside app data) holding Hunspell `.aff`/`.dic` pairs
+pub const DICTIONARY_DIR: &str = "dictionaries";
+
+/// Maximum number of suggestions returned per misspelled word
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Guess a two-letter language code for the text
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    let code = match info.lang() {
+        whatlang::Lang::Eng => "en",
+        whatlang::Lang::Deu => "de",
+        whatlang::Lang::Fra => "fr",
+        whatlang::Lang::Spa => "es",
+        whatlang::Lang::Ita => "it",
+        whatlang::Lang::Por => "pt",
+        whatlang::Lang::Nld => "nl",
+        whatlang::Lang::Swe => "sv",
+        whatlang::Lang::Dan => "da",
+        whatlang::Lang::Pol => "pl",
+        whatlang::Lang::Rus => "ru",
+        whatlang::Lang::Ukr => "uk",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Names of the installed dictionaries, sorted
+pub fn installed_dictionaries(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut installed: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != "aff" || !path.with_extension("dic").exists() {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().to_string())
+        })
+        .collect();
+    installed.sort();
+    installed
+}
+
+/// Find the installed dictionary best matching a language tag.
+/// "en_US" matches exactly; "en" matches the first installed "en_*" dictionary.
+pub fn resolve_dictionary_name(dir: &Path, language: &str) -> Result<String, String> {
+    let wanted = language.replace('-', "_");
+    let installed = installed_dictionaries(dir);
+    if installed.is_empty() {
+        return Err("No spellcheck dictionaries are installed".to_string());
+    }
+
+    installed
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case(&wanted))
+        .or_else(|| {
+            installed.iter().find(|name| {
+                name.to_ascii_lowercase()
+                    .starts_with(&format!("{}_", wanted.to_ascii_lowercase()))
+            })
+        })
+        .cloned()
+        .ok_or_else(|| {
+            format!(
+                "No spellcheck dictionary installed for language: {}",
+                language
+            )
+        })
+}
+
+/// Load a Hunspell dictionary pair from disk
+pub fn load_dictionary(dir: &Path, name: &str) -> Result<Dictionary, String> {
+    let aff = fs::read_to_string(dir.join(format!("{}.aff", name)))
+        .map_err(|e| format!("Failed to read {}.aff: {}", name, e))?;
+    let dic = fs::read_to_string(dir.join(format!("{}.dic", name)))
+        .map_err(|e| format!("Failed to read {}.dic: {}", name, e))?;
+    Dictionary::new(&aff, &dic).map_err(|e| format!("Invalid dictionary {}: {}", name, e))
+}
+
+/// Copy a Hunspell `.aff`/`.dic` pair into the dictionary directory under
+/// `name` (e.g. "en_US"), replacing one installed under that name. The pair
+/// is parsed first so a broken download is never installed.
+pub fn install_dictionary(
+    dir: &Path,
+    name: &str,
+    aff_path: &Path,
+    dic_path: &Path,
+) -> Result<Dictionary, String> {
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid_name {
+        return Err(format!("Invalid dictionary name: '{}'", name));
+    }
+    let aff = fs::read_to_string(aff_path)
+        .map_err(|e| format!("Failed to read {}: {}", aff_path.display(), e))?;
+    let dic = fs::read_to_string(dic_path)
+        .map_err(|e| format!("Failed to read {}: {}", dic_path.display(), e))?;
+    let dictionary =
+        Dictionary::new(&aff, &dic).map_err(|e| format!("Invalid dictionary {}: {}", name, e))?;
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create dictionary folder: {}", e))?;
+    fs::write(dir.join(format!("{}.aff", name)), aff)
+        .map_err(|e| format!("Failed to write {}.aff: {}", name, e))?;
+    fs::write(dir.join(format!("{}.dic", name)), dic)
+        .map_err(|e| format!("Failed to write {}.dic: {}", name, e))?;
+    Ok(dictionary)
+}
+
+/// Split text into words, keeping inner apostrophes and hyphens.
+/// Returns char ranges alongside each word.
+fn words(chars: &[char]) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in chars.iter().enumerate() {
+        let joiner = matches!(c, '\'' | '’' | '-')
+            && start.is_some()
+            && chars.get(i + 1).is_some_and(|n| n.is_alphabetic());
+        if c.is_alphabetic() || joiner {
+            start.get_or_insert(i);
+        } else if let Some(s) = start.take() {
+            spans.push((s, i));
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, chars.len()));
+    }
+    spans
+}
+
+/// Check every word in the text, skipping words in the custom word list
+pub fn check(dictionary: &Dictionary, text: &str, custom_words: &[String]) -> Vec<SpellingIssue> {
+    let custom: HashSet<String> = custom_words.iter().map(|w| w.to_lowercase()).collect();
+    let chars: Vec<char> = text.chars().collect();
+    let offsets = utf16_offsets(&chars);
+
+    let mut issues = Vec::new();
+    for (start, end) in words(&chars) {
+        let word: String = chars[start..end].iter().collect();
+        let normalized = word.replace('’', "'");
+        if custom.contains(&normalized.to_lowercase()) || dictionary.check(&normalized) {
+            continue;
+        }
+
+        let mut suggestions = Vec::new();
+        dictionary.suggest(&normalized, &mut suggestions);
+        suggestions.truncate(MAX_SUGGESTIONS);
+
+        issues.push(SpellingIssue {
+            word,
+            start: offsets[start],
+            end: offsets[end],
+            suggestions,
+        });
+    }
+    issues
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// A suggested edit that would resolve an issue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TextFix {
+    /// Replace the problem text
+    Replace { text: String },
+    /// Insert text after the problem text
+    InsertAfter { text: String },
+    /// Remove the problem text
+    Remove,
+}
+
+/// A grammar or style issue found in text.
+/// Offsets are UTF-16 code units so the editor can use them on JS strings directly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrammarIssue {
+    /// Name of the rule that produced the issue (e.g. "RepeatedWords")
+    pub rule_id: String,
+    /// General category of the issue (e.g. "Spelling", "Style")
+    pub kind: String,
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+    pub problem_text: String,
+    pub suggestions: Vec<TextFix>,
+}
+
+/// A word not found in the dictionary.
+/// Offsets are UTF-16 code units, like [`GrammarIssue`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpellingIssue {
+    pub word: String,
+    pub start: usize,
+    pub end: usize,
+    pub suggestions: Vec<String>,
+}
+
+/// Result of a spellcheck, including the language that was used
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpellcheckResult {
+    /// Dictionary name (e.g. "en_US")
+    pub language: String,
+    /// Whether the language was detected rather than given by the caller
+    pub detected: bool,
+    pub issues: Vec<SpellingIssue>,
+}
@@ -0,0 +1,117 @@
+//! Per-story custom words, one row per word in the `story_dictionary_words`
+//! table, so they go with the story when it is archived, trashed or deleted.
+
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+use crate::profiles::{self, database};
+use crate::store;
+
+/// Custom words from before they were kept in the database, in the app data
+/// directory. A story's words move into the database once it is there.
+const LEGACY_STORY_DICTIONARIES_FILE: &str = "story_dictionaries.json";
+
+/// Custom words per story, keyed by story ID, as the legacy file held them
+type StoryDictionaries = HashMap<String, Vec<String>>;
+
+/// Move words from the old file into the database, keeping those whose
+/// story is not in it yet
+async fn adopt_legacy_file(app: &AppHandle, pool: &SqlitePool) -> Result<(), String> {
+    let path = store::data_file(app, LEGACY_STORY_DICTIONARIES_FILE)?;
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut legacy: StoryDictionaries = store::load_json(app, LEGACY_STORY_DICTIONARIES_FILE)?;
+    let mut adopted = Vec::new();
+    for (story_id, words) in &legacy {
+        let (exists,): (bool,) =
+            sqlx::query_as("SELECT EXISTS (SELECT 1 FROM stories WHERE id = ?)")
+                .bind(story_id)
+                .fetch_one(pool)
+                .await
+                .map_err(|e| format!("Failed to read stories: {}", e))?;
+        if !exists {
+            continue;
+        }
+        for word in words {
+            insert(pool, story_id, word).await?;
+        }
+        adopted.push(story_id.clone());
+    }
+    for story_id in adopted {
+        legacy.remove(&story_id);
+    }
+    match legacy.is_empty() {
+        true => std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", LEGACY_STORY_DICTIONARIES_FILE, e)),
+        false => store::save_json(app, LEGACY_STORY_DICTIONARIES_FILE, &legacy),
+    }
+}
+
+async fn open(app: &AppHandle) -> Result<SqlitePool, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    if let Err(e) = adopt_legacy_file(app, &pool).await {
+        eprintln!("Failed to move story dictionaries into the database: {}", e);
+    }
+    Ok(pool)
+}
+
+/// Add a word unless the story already has it in any case. Returns whether
+/// the story exists.
+async fn insert(pool: &SqlitePool, story_id: &str, word: &str) -> Result<bool, String> {
+    sqlx::query(
+        "INSERT OR IGNORE INTO story_dictionary_words (story_id, word) \
+         SELECT ?, ? WHERE EXISTS (SELECT 1 FROM stories WHERE id = ?)",
+    )
+    .bind(story_id)
+    .bind(word)
+    .bind(story_id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to save custom word: {}", e))?;
+    let (exists,): (bool,) = sqlx::query_as("SELECT EXISTS (SELECT 1 FROM stories WHERE id = ?)")
+        .bind(story_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to read stories: {}", e))?;
+    Ok(exists)
+}
+
+/// A story's custom words, sorted case-insensitively
+pub async fn story_words(app: &AppHandle, story_id: &str) -> Result<Vec<String>, String> {
+    let pool = open(app).await?;
+    let rows: Result<Vec<(String,)>, String> = sqlx::query_as(
+        "SELECT word FROM story_dictionary_words WHERE story_id = ? ORDER BY word COLLATE NOCASE",
+    )
+    .bind(story_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| format!("Failed to read custom words: {}", e));
+    pool.close().await;
+    Ok(rows?.into_iter().map(|(word,)| word).collect())
+}
+
+/// Add a word to a story's custom words
+pub async fn add(app: &AppHandle, story_id: &str, word: &str) -> Result<(), String> {
+    let pool = open(app).await?;
+    let exists = insert(&pool, story_id, word).await;
+    pool.close().await;
+    match exists? {
+        true => Ok(()),
+        false => Err(format!("Story not found: {}", story_id)),
+    }
+}
+
+/// Remove a word from a story's custom words, in any case
+pub async fn remove(app: &AppHandle, story_id: &str, word: &str) -> Result<(), String> {
+    let pool = open(app).await?;
+    let result = sqlx::query("DELETE FROM story_dictionary_words WHERE story_id = ? AND word = ?")
+        .bind(story_id)
+        .bind(word)
+        .execute(&pool)
+        .await
+        .map_err(|e| format!("Failed to remove custom word: {}", e));
+    pool.close().await;
+    result.map(|_| ())
+}
@@ -0,0 +1,53 @@
+use tauri::AppHandle;
+
+use super::{Publication, PublishOptions};
+
+/// Publish a story as it is now to a share server and return where it can
+/// be read. By default private notes are left out and nothing is sanitized.
+/// The token is remembered for the server when given.
+#[tauri::command]
+pub async fn publish_story(
+    app: AppHandle,
+    story_id: String,
+    server_url: String,
+    auth: Option<String>,
+    options: Option<PublishOptions>,
+) -> Result<Publication, String> {
+    super::publish(
+        &app,
+        &story_id,
+        &server_url,
+        auth,
+        options.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Replace a published story with the story as it is now, keeping its
+/// options unless new ones are given
+#[tauri::command]
+pub async fn update_published_story(
+    app: AppHandle,
+    story_id: String,
+    auth: Option<String>,
+    options: Option<PublishOptions>,
+) -> Result<Publication, String> {
+    super::update(&app, &story_id, auth, options).await
+}
+
+#[tauri::command]
+pub async fn unpublish_story(
+    app: AppHandle,
+    story_id: String,
+    auth: Option<String>,
+) -> Result<(), String> {
+    super::unpublish(&app, &story_id, auth).await
+}
+
+#[tauri::command]
+pub async fn get_story_publication(
+    app: AppHandle,
+    story_id: String,
+) -> Result<Option<Publication>, String> {
+    super::publication(&app, &story_id)
+}
@@ -0,0 +1,319 @@
+//! Publishing stories to a self-hosted share server, which hands back a link
+//! anyone can read the story at. The server is expected to offer:
+//!
+//! - `POST {server}/api/stories` with the export as the body, answering
+//!   `{ "id": "...", "url": "https://..." }`
+//! - `PUT {server}/api/stories/{id}` to replace a published story, answering
+//!   the same
+//! - `DELETE {server}/api/stories/{id}` to take it down
+//!
+//! Requests carry the user's token as `Authorization: Bearer`. Tokens are
+//! kept in the OS keychain per server, so updates do not ask for them again.
+
+pub mod commands;
+
+use reqwest::{Method, Url};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::profiles;
+use crate::store;
+use crate::story::private::strip_private_notes;
+use crate::story::sanitize::{self, SanitizeRules, SANITIZE_RULES_FILE};
+use crate::story::{rows, StoryExport};
+use crate::sync::keys::{self, now_ms, ApiKeyEntry};
+use crate::sync::network::is_local_address;
+
+/// Published stories, in the app data directory
+pub const PUBLICATIONS_FILE: &str = "publications.json";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn default_true() -> bool {
+    true
+}
+
+/// How the published copy is prepared
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishOptions {
+    /// Leave private notes out of the published copy
+    #[serde(default = "default_true")]
+    pub strip_private_notes: bool,
+    /// Clean the published copy with the saved sanitize rules
+    #[serde(default)]
+    pub sanitize: bool,
+}
+
+impl Default for PublishOptions {
+    fn default() -> Self {
+        Self {
+            strip_private_notes: true,
+            sanitize: false,
+        }
+    }
+}
+
+/// Where a story is published
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Publication {
+    pub story_id: String,
+    pub server_url: String,
+    /// ID the server gave the story
+    pub remote_id: String,
+    pub share_url: String,
+    pub options: PublishOptions,
+    pub published_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishResponse {
+    id: String,
+    url: String,
+}
+
+/// Server addresses must be HTTPS, or plain HTTP on this machine or the
+/// local network, since requests carry a token
+pub fn parse_server_url(server_url: &str) -> Result<Url, String> {
+    let mut url =
+        Url::parse(server_url.trim()).map_err(|e| format!("Invalid server URL: {}", e))?;
+    let host = url
+        .host_str()
+        .ok_or("The server URL has no host")?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let local = match host.parse::<IpAddr>() {
+        Ok(ip) => is_local_address(ip),
+        Err(_) => host.eq_ignore_ascii_case("localhost"),
+    };
+    match url.scheme() {
+        "https" => {}
+        "http" if local => {}
+        "http" => {
+            return Err("Share servers outside the local network must use https://".to_string())
+        }
+        other => return Err(format!("Unsupported server URL scheme: {}", other)),
+    }
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok(url)
+}
+
+fn keychain_account(server_url: &Url) -> String {
+    format!("publish:{}", server_url)
+}
+
+fn save_auth(server_url: &Url, auth: &str) -> Result<(), String> {
+    keys::store_in_keychain(&[ApiKeyEntry {
+        provider: keychain_account(server_url),
+        api_key: auth.to_string(),
+    }])
+    .map(|_| ())
+}
+
+/// The token given now, or the one remembered for the server
+fn resolve_auth(server_url: &Url, auth: Option<String>) -> Result<String, String> {
+    if let Some(auth) = auth.filter(|a| !a.trim().is_empty()) {
+        save_auth(server_url, auth.trim())?;
+        return Ok(auth.trim().to_string());
+    }
+    keys::read_from_keychain(&keychain_account(server_url))?
+        .ok_or_else(|| "No token is saved for this share server".to_string())
+}
+
+fn stories_url(server_url: &Url, remote_id: Option<&str>) -> Result<Url, String> {
+    let mut url = server_url
+        .join("api/stories")
+        .map_err(|e| format!("Invalid server URL: {}", e))?;
+    if let Some(id) = remote_id {
+        url.path_segments_mut()
+            .map_err(|_| "Invalid server URL".to_string())?
+            .push(id);
+    }
+    Ok(url)
+}
+
+async fn send(
+    method: Method,
+    url: Url,
+    auth: &str,
+    body: Option<String>,
+) -> Result<reqwest::Response, String> {
+    let mut request = reqwest::Client::new()
+        .request(method, url)
+        .bearer_auth(auth)
+        .timeout(REQUEST_TIMEOUT);
+    if let Some(body) = body {
+        request = request
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+    }
+    request
+        .send()
+        .await
+        .map_err(|e| format!("Connection failed: {}", e))
+}
+
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, String> {
+    match response.status() {
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+            Err("The share server rejected the token".to_string())
+        }
+        status if !status.is_success() => {
+            let text = response.text().await.unwrap_or_default();
+            Err(format!("Share server returned {}: {}", status, text))
+        }
+        _ => Ok(response),
+    }
+}
+
+async fn upload(
+    method: Method,
+    url: Url,
+    auth: &str,
+    export: &StoryExport,
+) -> Result<PublishResponse, String> {
+    let response = send(method, url, auth, Some(export.to_json()?)).await?;
+    let published: PublishResponse = check_status(response)
+        .await?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid response from share server: {}", e))?;
+    let share_url =
+        Url::parse(&published.url).map_err(|_| "The share server sent an invalid link")?;
+    if !matches!(share_url.scheme(), "https" | "http") || published.id.is_empty() {
+        return Err("The share server sent an invalid link".to_string());
+    }
+    Ok(published)
+}
+
+/// The story as its database rows have it, as it will be published
+async fn prepare(
+    app: &AppHandle,
+    story_id: &str,
+    options: &PublishOptions,
+) -> Result<StoryExport, String> {
+    profiles::check_story(app, story_id).await?;
+    let mut export = rows::load(app, story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    if options.sanitize {
+        let rules = SanitizeRules {
+            private_notes: options.strip_private_notes,
+            ..store::load_json(app, SANITIZE_RULES_FILE)?
+        };
+        return sanitize::sanitize(export, &rules).map(|(clean, _)| clean);
+    }
+    if options.strip_private_notes {
+        strip_private_notes(&mut export);
+    }
+    Ok(export)
+}
+
+fn load_publications(app: &AppHandle) -> Result<Vec<Publication>, String> {
+    store::load_json(app, PUBLICATIONS_FILE)
+}
+
+fn save_publication(app: &AppHandle, publication: &Publication) -> Result<(), String> {
+    let mut publications = load_publications(app)?;
+    publications.retain(|p| p.story_id != publication.story_id);
+    publications.push(publication.clone());
+    store::save_json(app, PUBLICATIONS_FILE, &publications)
+}
+
+/// Where a story is published, if anywhere
+pub fn publication(app: &AppHandle, story_id: &str) -> Result<Option<Publication>, String> {
+    Ok(load_publications(app)?
+        .into_iter()
+        .find(|p| p.story_id == story_id))
+}
+
+/// Upload a story to a share server. A story already published
+/// to the same server is updated in place; one published elsewhere must be
+/// taken down there first.
+pub async fn publish(
+    app: &AppHandle,
+    story_id: &str,
+    server_url: &str,
+    auth: Option<String>,
+    options: PublishOptions,
+) -> Result<Publication, String> {
+    let server = parse_server_url(server_url)?;
+    if let Some(existing) = publication(app, story_id)? {
+        if existing.server_url != server.as_str() {
+            return Err(format!(
+                "This story is published on {}. Unpublish it there first.",
+                existing.server_url
+            ));
+        }
+        return update(app, story_id, auth, Some(options)).await;
+    }
+    let auth = resolve_auth(&server, auth)?;
+    let export = prepare(app, story_id, &options).await?;
+    let published = upload(Method::POST, stories_url(&server, None)?, &auth, &export).await?;
+    let now = now_ms();
+    let publication = Publication {
+        story_id: story_id.to_string(),
+        server_url: server.to_string(),
+        remote_id: published.id,
+        share_url: published.url,
+        options,
+        published_at: now,
+        updated_at: now,
+    };
+    save_publication(app, &publication)?;
+    Ok(publication)
+}
+
+/// Replace the published copy with the story as it is now
+pub async fn update(
+    app: &AppHandle,
+    story_id: &str,
+    auth: Option<String>,
+    options: Option<PublishOptions>,
+) -> Result<Publication, String> {
+    let mut publication =
+        publication(app, story_id)?.ok_or_else(|| "This story is not published".to_string())?;
+    let server = parse_server_url(&publication.server_url)?;
+    let auth = resolve_auth(&server, auth)?;
+    let options = options.unwrap_or_else(|| publication.options.clone());
+    let export = prepare(app, story_id, &options).await?;
+    let url = stories_url(&server, Some(&publication.remote_id))?;
+    let published = upload(Method::PUT, url, &auth, &export).await?;
+    publication.share_url = published.url;
+    publication.options = options;
+    publication.updated_at = now_ms();
+    save_publication(app, &publication)?;
+    Ok(publication)
+}
+
+/// Take a story down from its share server. A story the server no longer
+/// has counts as taken down.
+pub async fn unpublish(
+    app: &AppHandle,
+    story_id: &str,
+    auth: Option<String>,
+) -> Result<(), String> {
+    profiles::check_story(app, story_id).await?;
+    let Some(publication) = publication(app, story_id)? else {
+        return Ok(());
+    };
+    let server = parse_server_url(&publication.server_url)?;
+    let auth = resolve_auth(&server, auth)?;
+    let url = stories_url(&server, Some(&publication.remote_id))?;
+    let response = send(Method::DELETE, url, &auth, None).await?;
+    if response.status() != reqwest::StatusCode::NOT_FOUND {
+        check_status(response).await?;
+    }
+    let mut publications = load_publications(app)?;
+    publications.retain(|p| p.story_id != story_id);
+    store::save_json(app, PUBLICATIONS_FILE, &publications)
+}
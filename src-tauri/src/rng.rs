@@ -0,0 +1,25 @@
+//! Seeded random numbers for features that must come out the same for the
+//! same seed on every platform, like playthrough simulation and dice.
+
+/// SplitMix64: small, fast and the same everywhere
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`; `bound` must not be zero
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    /// A roll of a die with `sides` sides, from 1
+    pub fn roll(&mut self, sides: u32) -> u32 {
+        self.below(sides.max(1) as usize) as u32 + 1
+    }
+}
@@ -0,0 +1,83 @@
+use tauri::{AppHandle, State};
+use tokio::sync::Mutex;
+
+use super::focus::{self, ActiveFocus, FocusSession, FocusSessionResult};
+use super::goals::{self, GoalProgress, WritingGoals};
+use super::{WritingStats, WRITING_STATS_FILE};
+use crate::store;
+
+/// State managed by Tauri for writing statistics
+#[derive(Default)]
+pub struct StatsState {
+    /// The running focus session
+    pub(crate) focus: Mutex<Option<ActiveFocus>>,
+    /// Serializes changes to the stats file
+    pub(crate) writes: std::sync::Mutex<()>,
+}
+
+/// Start a focus session of the given length for a story. Words saved to the
+/// story during the session are counted; progress is reported with
+/// `focus://progress` events and the result with `focus://ended`.
+#[tauri::command]
+pub async fn start_focus_session(
+    app: AppHandle,
+    state: State<'_, StatsState>,
+    minutes: u32,
+    story_id: String,
+) -> Result<FocusSession, String> {
+    focus::start(&app, &state, minutes, story_id).await
+}
+
+/// End the running focus session early; it is recorded as not completed
+#[tauri::command]
+pub async fn stop_focus_session(
+    app: AppHandle,
+    state: State<'_, StatsState>,
+) -> Result<FocusSessionResult, String> {
+    focus::stop(&app, &state).await
+}
+
+#[tauri::command]
+pub async fn get_focus_session(
+    state: State<'_, StatsState>,
+) -> Result<Option<FocusSession>, String> {
+    Ok(focus::current(&state).await)
+}
+
+/// Finished focus sessions, newest first, optionally for one story
+#[tauri::command]
+pub async fn list_focus_sessions(
+    app: AppHandle,
+    story_id: Option<String>,
+) -> Result<Vec<FocusSessionResult>, String> {
+    let stats: WritingStats = store::load_json(&app, WRITING_STATS_FILE)?;
+    Ok(stats
+        .focus_sessions
+        .into_iter()
+        .rev()
+        .filter(|s| story_id.as_ref().is_none_or(|id| &s.story_id == id))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn get_writing_goals(app: AppHandle) -> Result<WritingGoals, String> {
+    goals::load(&app).await
+}
+
+/// Save word goals; unset goals are not tracked
+#[tauri::command]
+pub async fn set_writing_goals(app: AppHandle, goals: WritingGoals) -> Result<(), String> {
+    if goals.daily_words == Some(0) || goals.weekly_words == Some(0) {
+        return Err("Word goals must be at least 1 word".to_string());
+    }
+    if goals.reminder_hour > 23 {
+        return Err("Reminder hour must be between 0 and 23".to_string());
+    }
+    goals::save(&app, &goals).await
+}
+
+/// Words written today and this week against the goals, and the current streak
+#[tauri::command]
+pub async fn get_goal_progress(app: AppHandle) -> Result<GoalProgress, String> {
+    goals::current_progress(&app).await
+}
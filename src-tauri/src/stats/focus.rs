@@ -0,0 +1,160 @@
+//! Focus sessions: a countdown for one story that counts the words added by
+//! its saves until the time runs out, announced with `focus://progress` every
+//! second and `focus://ended` when the session finishes or is stopped.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::task::JoinHandle;
+
+use super::StatsState;
+use crate::sync::keys::now_ms;
+
+/// Longest session accepted, in minutes
+const MAX_MINUTES: u32 = 240;
+
+/// A running focus session
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusSession {
+    pub id: String,
+    pub story_id: String,
+    pub minutes: u32,
+    pub started_at: i64,
+    pub ends_at: i64,
+    pub remaining_secs: u64,
+    /// Words added by saves since the session started
+    pub words_written: usize,
+}
+
+/// A session as stored in the writing stats
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusSessionResult {
+    pub id: String,
+    pub story_id: String,
+    pub minutes: u32,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub words_written: usize,
+    /// False if the session was stopped before its time was up
+    pub completed: bool,
+}
+
+pub(crate) struct ActiveFocus {
+    session: FocusSession,
+    timer: JoinHandle<()>,
+}
+
+impl ActiveFocus {
+    fn snapshot(&self) -> FocusSession {
+        FocusSession {
+            remaining_secs: ((self.session.ends_at - now_ms()).max(0) as u64).div_ceil(1000),
+            ..self.session.clone()
+        }
+    }
+}
+
+/// Start a session for a story. Only one session runs at a time.
+pub async fn start(
+    app: &AppHandle,
+    state: &StatsState,
+    minutes: u32,
+    story_id: String,
+) -> Result<FocusSession, String> {
+    if minutes == 0 || minutes > MAX_MINUTES {
+        return Err(format!("Focus sessions last 1 to {} minutes", MAX_MINUTES));
+    }
+    let mut slot = state.focus.lock().await;
+    if let Some(active) = slot.as_ref() {
+        return Err(format!(
+            "A focus session is already running for story {}",
+            active.session.story_id
+        ));
+    }
+    let started_at = now_ms();
+    let session = FocusSession {
+        id: uuid::Uuid::new_v4().to_string(),
+        story_id,
+        minutes,
+        started_at,
+        ends_at: started_at + i64::from(minutes) * 60_000,
+        remaining_secs: u64::from(minutes) * 60,
+        words_written: 0,
+    };
+    *slot = Some(ActiveFocus {
+        session: session.clone(),
+        timer: tokio::spawn(run_timer(app.clone(), session.id.clone())),
+    });
+    Ok(session)
+}
+
+/// The running session, if any
+pub async fn current(state: &StatsState) -> Option<FocusSession> {
+    state.focus.lock().await.as_ref().map(ActiveFocus::snapshot)
+}
+
+/// Stop the running session early and record it
+pub async fn stop(app: &AppHandle, state: &StatsState) -> Result<FocusSessionResult, String> {
+    let active = state
+        .focus
+        .lock()
+        .await
+        .take()
+        .ok_or("No focus session is running")?;
+    active.timer.abort();
+    finish(app, active, false)
+}
+
+/// Count words a save added to a story toward the running session
+pub async fn record_words(app: &AppHandle, state: &StatsState, story_id: &str, added: usize) {
+    let mut slot = state.focus.lock().await;
+    let Some(active) = slot.as_mut().filter(|a| a.session.story_id == story_id) else {
+        return;
+    };
+    active.session.words_written += added;
+    let _ = app.emit("focus://progress", active.snapshot());
+}
+
+fn finish(
+    app: &AppHandle,
+    active: ActiveFocus,
+    completed: bool,
+) -> Result<FocusSessionResult, String> {
+    let session = active.session;
+    let result = FocusSessionResult {
+        id: session.id,
+        story_id: session.story_id,
+        minutes: session.minutes,
+        started_at: session.started_at,
+        ended_at: now_ms(),
+        words_written: session.words_written,
+        completed,
+    };
+    super::update(app, |stats| stats.focus_sessions.push(result.clone()))?;
+    let _ = app.emit("focus://ended", &result);
+    Ok(result)
+}
+
+async fn run_timer(app: AppHandle, session_id: String) {
+    let mut ticks = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticks.tick().await;
+        let state = app.state::<StatsState>();
+        let mut slot = state.focus.lock().await;
+        let Some(active) = slot.as_ref().filter(|a| a.session.id == session_id) else {
+            return;
+        };
+        let snapshot = active.snapshot();
+        if snapshot.remaining_secs > 0 {
+            let _ = app.emit("focus://progress", snapshot);
+            continue;
+        }
+        if let Some(active) = slot.take() {
+            if let Err(e) = finish(&app, active, true) {
+                eprintln!("Failed to record focus session: {}", e);
+            }
+        }
+        return;
+    }
+}
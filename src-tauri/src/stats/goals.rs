@@ -0,0 +1,262 @@
+//! Daily and weekly word goals with a streak of days the daily goal was met.
+//! The goals are kept in the database's settings table, next to the
+//! frontend's settings. They are checked after every save and every quarter
+//! hour, and the user gets an OS notification when a goal is reached or,
+//! late in the day, when the streak will break unless they write.
+
+use chrono::{Datelike, Days, Local, NaiveDate, Timelike};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+use super::{day_key, today, WritingStats, WRITING_STATS_FILE};
+use crate::annotations;
+use crate::profiles::{self, database};
+use crate::store;
+
+/// Key of the goals in the settings table
+const WRITING_GOALS_SETTING: &str = "writing_goals";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Streaks are counted back at most this far
+const MAX_STREAK_DAYS: u32 = 3660;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WritingGoals {
+    pub daily_words: Option<u64>,
+    pub weekly_words: Option<u64>,
+    pub notifications: bool,
+    /// Local hour (0-23) after which an unmet daily goal warns of a broken streak
+    pub reminder_hour: u32,
+}
+
+impl Default for WritingGoals {
+    fn default() -> Self {
+        Self {
+            daily_words: None,
+            weekly_words: None,
+            notifications: true,
+            reminder_hour: 20,
+        }
+    }
+}
+
+/// Days and weeks that have already been announced, so each goal is
+/// announced once
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GoalNotices {
+    pub daily_met: Option<String>,
+    /// Monday of the week, "YYYY-MM-DD"
+    pub weekly_met: Option<String>,
+    pub streak_warned: Option<String>,
+}
+
+/// Where the user stands against their goals today
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoalProgress {
+    pub today: String,
+    pub words_today: u64,
+    pub daily_goal: Option<u64>,
+    pub daily_met: bool,
+    /// Monday of the current week
+    pub week_start: String,
+    pub words_this_week: u64,
+    pub weekly_goal: Option<u64>,
+    pub weekly_met: bool,
+    /// Days in a row the daily goal was met (any writing counts without a
+    /// daily goal), including today once it is met
+    pub streak_days: u32,
+    /// The streak ends tonight unless the daily goal is met
+    pub streak_at_risk: bool,
+    /// Unresolved TODO annotations across the library
+    pub open_todos: usize,
+}
+
+fn week_start(day: NaiveDate) -> NaiveDate {
+    day - Days::new(u64::from(day.weekday().num_days_from_monday()))
+}
+
+/// Measure progress on `today`, given the current local hour
+pub fn progress(
+    goals: &WritingGoals,
+    stats: &WritingStats,
+    today: NaiveDate,
+    hour: u32,
+) -> GoalProgress {
+    let met = |day: NaiveDate| {
+        let words = stats.words_on(day);
+        goals.daily_words.map_or(words > 0, |goal| words >= goal)
+    };
+    let monday = week_start(today);
+    let words_this_week = (0..=today.weekday().num_days_from_monday())
+        .map(|offset| stats.words_on(monday + Days::new(u64::from(offset))))
+        .sum();
+
+    let daily_met = met(today);
+    let mut day = if daily_met {
+        today
+    } else {
+        today - Days::new(1)
+    };
+    let mut streak_days = 0;
+    while streak_days < MAX_STREAK_DAYS && met(day) {
+        streak_days += 1;
+        day = day - Days::new(1);
+    }
+
+    let words_today = stats.words_on(today);
+    GoalProgress {
+        today: day_key(today),
+        words_today,
+        daily_goal: goals.daily_words,
+        daily_met: goals.daily_words.is_some_and(|goal| words_today >= goal),
+        week_start: day_key(monday),
+        words_this_week,
+        weekly_goal: goals.weekly_words,
+        weekly_met: goals
+            .weekly_words
+            .is_some_and(|goal| words_this_week >= goal),
+        streak_days,
+        streak_at_risk: !daily_met && streak_days > 0 && hour >= goals.reminder_hour,
+        open_todos: 0,
+    }
+}
+
+/// The goals as set, or the defaults before they are first saved
+pub async fn load(app: &AppHandle) -> Result<WritingGoals, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let value: Result<Option<String>, String> =
+        sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
+            .bind(WRITING_GOALS_SETTING)
+            .fetch_optional(&pool)
+            .await
+            .map_err(|e| format!("Failed to read writing goals: {}", e));
+    pool.close().await;
+    match value? {
+        Some(json) => {
+            serde_json::from_str(&json).map_err(|e| format!("Invalid writing goals: {}", e))
+        }
+        None => Ok(WritingGoals::default()),
+    }
+}
+
+pub async fn save(app: &AppHandle, goals: &WritingGoals) -> Result<(), String> {
+    let json = serde_json::to_string(goals)
+        .map_err(|e| format!("Failed to serialize writing goals: {}", e))?;
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let result = sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)")
+        .bind(WRITING_GOALS_SETTING)
+        .bind(json)
+        .execute(&pool)
+        .await
+        .map_err(|e| format!("Failed to save writing goals: {}", e));
+    pool.close().await;
+    result.map(|_| ())
+}
+
+/// Progress right now
+pub async fn current_progress(app: &AppHandle) -> Result<GoalProgress, String> {
+    let goals = load(app).await?;
+    let stats: WritingStats = store::load_json(app, WRITING_STATS_FILE)?;
+    Ok(GoalProgress {
+        open_todos: annotations::open_todos(app).await?.len(),
+        ..progress(&goals, &stats, today(), Local::now().hour())
+    })
+}
+
+fn notify(app: &AppHandle, title: &str, body: &str) {
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        eprintln!("Failed to show notification: {}", e);
+    }
+}
+
+/// Announce goals reached and streaks at risk that have not been announced yet
+pub async fn check(app: &AppHandle, stats: WritingStats) {
+    let goals = match load(app).await {
+        Ok(goals) => goals,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    if !goals.notifications {
+        return;
+    }
+    let progress = progress(&goals, &stats, today(), Local::now().hour());
+    let notices = &stats.notices;
+    let mut announced = notices.clone();
+
+    if progress.daily_met && notices.daily_met.as_ref() != Some(&progress.today) {
+        notify(
+            app,
+            "Daily goal reached",
+            &format!(
+                "You wrote {} words today. Streak: {} {}.",
+                progress.words_today,
+                progress.streak_days,
+                if progress.streak_days == 1 {
+                    "day"
+                } else {
+                    "days"
+                }
+            ),
+        );
+        announced.daily_met = Some(progress.today.clone());
+    }
+    if progress.weekly_met && notices.weekly_met.as_ref() != Some(&progress.week_start) {
+        notify(
+            app,
+            "Weekly goal reached",
+            &format!("You wrote {} words this week.", progress.words_this_week),
+        );
+        announced.weekly_met = Some(progress.week_start.clone());
+    }
+    if progress.streak_at_risk && notices.streak_warned.as_ref() != Some(&progress.today) {
+        let needed = match progress.daily_goal {
+            Some(goal) => {
+                let remaining = goal.saturating_sub(progress.words_today);
+                format!(
+                    "Write {} more {}",
+                    remaining,
+                    if remaining == 1 { "word" } else { "words" }
+                )
+            }
+            None => "Write something".to_string(),
+        };
+        notify(
+            app,
+            "Keep your streak going",
+            &format!(
+                "{} today to keep your {}-day streak.",
+                needed, progress.streak_days
+            ),
+        );
+        announced.streak_warned = Some(progress.today.clone());
+    }
+
+    if announced != *notices {
+        if let Err(e) = super::update(app, |stats| stats.notices = announced) {
+            eprintln!("Failed to record goal notifications: {}", e);
+        }
+    }
+}
+
+/// Check goals periodically so streak reminders arrive without a save
+pub fn resume(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut ticks = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticks.tick().await;
+            match store::load_json(&app, WRITING_STATS_FILE) {
+                Ok(stats) => check(&app, stats).await,
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    });
+}
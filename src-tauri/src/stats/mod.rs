@@ -0,0 +1,102 @@
+//! Writing statistics: how much was written and when, gathered from story
+//! saves and focus sessions and kept in the app data directory. Words
+//! written are the growth of a story's word count from one save to the
+//! next, whether the frontend autosaved it or `save_story` was called.
+
+pub mod commands;
+pub mod focus;
+pub mod goals;
+
+pub use commands::StatsState;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tauri::{AppHandle, Manager};
+
+use crate::store;
+use crate::story::text::plain_text;
+use crate::story::StoryExport;
+
+/// Writing statistics in the app data directory
+pub const WRITING_STATS_FILE: &str = "writing_stats.json";
+
+/// Everything the stats subsystem remembers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WritingStats {
+    /// Finished focus sessions, oldest first
+    pub focus_sessions: Vec<focus::FocusSessionResult>,
+    /// Words added per local day, keyed "YYYY-MM-DD"
+    pub daily_words: BTreeMap<String, u64>,
+    /// Word count of each story at its last save, to measure what a save added
+    pub story_words: BTreeMap<String, u64>,
+    /// Goal notifications already shown
+    pub notices: goals::GoalNotices,
+}
+
+impl WritingStats {
+    pub fn words_on(&self, day: NaiveDate) -> u64 {
+        self.daily_words.get(&day_key(day)).copied().unwrap_or(0)
+    }
+}
+
+pub fn day_key(day: NaiveDate) -> String {
+    day.format("%Y-%m-%d").to_string()
+}
+
+/// Today in the user's time zone
+pub fn today() -> NaiveDate {
+    chrono::Local::now().date_naive()
+}
+
+/// Read, change and write the stats file
+pub fn update(
+    app: &AppHandle,
+    change: impl FnOnce(&mut WritingStats),
+) -> Result<WritingStats, String> {
+    let state = app.state::<StatsState>();
+    let _write = state
+        .writes
+        .lock()
+        .map_err(|_| "Writing stats are unavailable".to_string())?;
+    let mut stats: WritingStats = store::load_json(app, WRITING_STATS_FILE)?;
+    change(&mut stats);
+    store::save_json(app, WRITING_STATS_FILE, &stats)?;
+    Ok(stats)
+}
+
+/// Words in the story's main branch, as a reader would count them
+pub fn word_count(export: &StoryExport) -> usize {
+    export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none() && e.entry_type != "system")
+        .map(|e| plain_text(&e.content).split_whitespace().count())
+        .sum()
+}
+
+/// Credit the words a save added to today's total and the running focus
+/// session. Deletions do not take back words written earlier, and the first
+/// save of a story, new or imported, only sets its starting count.
+pub async fn record_save(app: &AppHandle, export: &StoryExport) {
+    let words = word_count(export) as u64;
+    let mut added = 0;
+    let recorded = update(app, |stats| {
+        let previous = stats.story_words.insert(export.story.id.clone(), words);
+        added = previous.map_or(0, |previous| words.saturating_sub(previous));
+        if added > 0 {
+            *stats.daily_words.entry(day_key(today())).or_default() += added;
+        }
+    });
+    match recorded {
+        Ok(stats) if added > 0 => goals::check(app, stats).await,
+        Ok(_) => return,
+        Err(e) => {
+            eprintln!("Failed to record words written: {}", e);
+            return;
+        }
+    }
+    let state = app.state::<StatsState>();
+    focus::record_words(app, &state, &export.story.id, added as usize).await;
+}
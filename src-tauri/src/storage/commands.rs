@@ -0,0 +1,19 @@
+use tauri::AppHandle;
+
+use super::{ReclaimReport, ReclaimTarget, StorageReport};
+
+/// Disk usage of the active profile, by category
+#[tauri::command]
+pub async fn get_storage_report(app: AppHandle) -> Result<StorageReport, String> {
+    super::report(&app).await
+}
+
+/// Vacuum the database, prune old story versions or clear caches and logs,
+/// as chosen by the user
+#[tauri::command]
+pub async fn reclaim_space(
+    app: AppHandle,
+    targets: Vec<ReclaimTarget>,
+) -> Result<ReclaimReport, String> {
+    super::reclaim(&app, &targets).await
+}
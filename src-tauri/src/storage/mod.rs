@@ -0,0 +1,270 @@
+//! Disk usage of the active profile's data, and ways to get some of it back:
+//! compacting the database, pruning old story versions and clearing caches
+//! and logs.
+
+pub mod commands;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+use crate::ai::translate::TRANSLATION_CACHE_FILE;
+use crate::attachments::ATTACHMENTS_DIR;
+use crate::gallery::GALLERY_CACHE_FILE;
+use crate::history::DEFAULT_REPO_DIR;
+use crate::portable;
+use crate::profiles::{self, database};
+use crate::store;
+use crate::story::archive::ARCHIVE_DIR;
+use crate::story::versions::{self, STORY_VERSIONS_DIR};
+
+/// Files that are rebuilt on demand and safe to delete
+const CACHE_FILES: [&str; 2] = [TRANSLATION_CACHE_FILE, GALLERY_CACHE_FILE];
+
+/// Log folder inside a portable data folder
+const LOG_DIR: &str = "logs";
+
+/// Versions kept per story when pruning and the caller does not say
+const DEFAULT_VERSIONS_KEPT: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageCategory {
+    /// The frontend's database, with its journal files
+    Database,
+    /// Archived stories
+    Archives,
+    Attachments,
+    /// Story versions kept before merges, restores and other replacements
+    Versions,
+    /// The built-in git history repository
+    History,
+    Caches,
+    Logs,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageUsage {
+    pub category: StorageCategory,
+    pub bytes: u64,
+    pub files: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageReport {
+    pub categories: Vec<StorageUsage>,
+    pub total_bytes: u64,
+    /// Unused pages in the database that a vacuum would give back
+    pub database_free_bytes: u64,
+}
+
+/// What `reclaim_space` should clean up
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ReclaimTarget {
+    /// Rebuild the database without its unused pages
+    Vacuum,
+    /// Keep only the newest versions of each story
+    #[serde(rename_all = "camelCase")]
+    OldVersions {
+        keep: Option<usize>,
+    },
+    Caches,
+    Logs,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReclaimedSpace {
+    pub category: StorageCategory,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReclaimReport {
+    pub reclaimed: Vec<ReclaimedSpace>,
+    pub total_bytes: u64,
+}
+
+/// Size and file count of a file or a directory tree. Symlinks are not
+/// followed, and a missing path is empty.
+fn usage_of(path: &Path) -> (u64, u64) {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return (0, 0);
+    };
+    if !meta.is_dir() {
+        return (meta.len(), 1);
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        return (0, 0);
+    };
+    entries.flatten().fold((0, 0), |(bytes, files), entry| {
+        let (b, f) = usage_of(&entry.path());
+        (bytes + b, files + f)
+    })
+}
+
+/// The database file with its `-wal`, `-shm` and `-journal` companions
+fn database_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![path.to_path_buf()];
+    for suffix in ["-wal", "-shm", "-journal"] {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        files.push(PathBuf::from(name));
+    }
+    files
+}
+
+fn log_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    if let Some(root) = portable::root() {
+        return Ok(root.join(LOG_DIR));
+    }
+    app.path()
+        .app_log_dir()
+        .map_err(|e| format!("Failed to resolve log directory: {}", e))
+}
+
+/// Paths making up each category
+fn category_paths(app: &AppHandle, category: StorageCategory) -> Result<Vec<PathBuf>, String> {
+    let data_file = |name: &str| store::data_file(app, name);
+    Ok(match category {
+        StorageCategory::Database => database_files(&profiles::current(app)?.database_path),
+        StorageCategory::Archives => vec![data_file(ARCHIVE_DIR)?],
+        StorageCategory::Attachments => vec![data_file(ATTACHMENTS_DIR)?],
+        StorageCategory::Versions => vec![data_file(STORY_VERSIONS_DIR)?],
+        StorageCategory::History => vec![data_file(DEFAULT_REPO_DIR)?],
+        StorageCategory::Caches => CACHE_FILES
+            .iter()
+            .map(|name| data_file(name))
+            .collect::<Result<_, _>>()?,
+        StorageCategory::Logs => vec![log_dir(app)?],
+    })
+}
+
+fn category_bytes(app: &AppHandle, category: StorageCategory) -> Result<u64, String> {
+    Ok(category_paths(app, category)?
+        .iter()
+        .map(|path| usage_of(path).0)
+        .sum())
+}
+
+/// Bytes held by unused database pages
+async fn database_free_bytes(path: &Path) -> Result<u64, String> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let pool = database::open(path, false).await?;
+    let pragma = |name: &'static str| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_scalar::<_, i64>(&format!("PRAGMA {}", name))
+                .fetch_one(&pool)
+                .await
+                .map_err(|e| format!("Failed to read database size: {}", e))
+        }
+    };
+    let free = pragma("freelist_count").await;
+    let page_size = pragma("page_size").await;
+    pool.close().await;
+    Ok((free? * page_size?).max(0) as u64)
+}
+
+/// Disk usage of the active profile by category
+pub async fn report(app: &AppHandle) -> Result<StorageReport, String> {
+    let all = [
+        StorageCategory::Database,
+        StorageCategory::Archives,
+        StorageCategory::Attachments,
+        StorageCategory::Versions,
+        StorageCategory::History,
+        StorageCategory::Caches,
+        StorageCategory::Logs,
+    ];
+    let mut categories = Vec::with_capacity(all.len());
+    for category in all {
+        let (bytes, files) = category_paths(app, category)?
+            .iter()
+            .map(|path| usage_of(path))
+            .fold((0, 0), |(bytes, files), (b, f)| (bytes + b, files + f));
+        categories.push(StorageUsage {
+            category,
+            bytes,
+            files,
+        });
+    }
+    Ok(StorageReport {
+        total_bytes: categories.iter().map(|c| c.bytes).sum(),
+        categories,
+        database_free_bytes: database_free_bytes(&profiles::current(app)?.database_path).await?,
+    })
+}
+
+async fn vacuum(app: &AppHandle) -> Result<u64, String> {
+    let path = profiles::current(app)?.database_path;
+    if !path.exists() {
+        return Ok(0);
+    }
+    let before = category_bytes(app, StorageCategory::Database)?;
+    let pool = database::open(&path, false).await?;
+    let result = sqlx::query("VACUUM").execute(&pool).await;
+    pool.close().await;
+    result.map_err(|e| format!("Failed to vacuum database: {}", e))?;
+    Ok(before.saturating_sub(category_bytes(app, StorageCategory::Database)?))
+}
+
+/// Delete the files in a directory, leaving the directory itself
+fn clear_dir(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| {
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            if fs::remove_file(entry.path()).is_ok() {
+                size
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+fn clear_caches(app: &AppHandle) -> Result<u64, String> {
+    let mut freed = 0;
+    for path in category_paths(app, StorageCategory::Caches)? {
+        let size = usage_of(&path).0;
+        match fs::remove_file(&path) {
+            Ok(()) => freed += size,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to clear cache: {}", e)),
+        }
+    }
+    Ok(freed)
+}
+
+/// Clean up the chosen targets, in order. A failing target stops the rest.
+pub async fn reclaim(app: &AppHandle, targets: &[ReclaimTarget]) -> Result<ReclaimReport, String> {
+    let mut reclaimed = Vec::with_capacity(targets.len());
+    for target in targets {
+        let (category, bytes) = match target {
+            ReclaimTarget::Vacuum => (StorageCategory::Database, vacuum(app).await?),
+            ReclaimTarget::OldVersions { keep } => (
+                StorageCategory::Versions,
+                versions::prune(app, keep.unwrap_or(DEFAULT_VERSIONS_KEPT))?,
+            ),
+            ReclaimTarget::Caches => (StorageCategory::Caches, clear_caches(app)?),
+            ReclaimTarget::Logs => (StorageCategory::Logs, clear_dir(&log_dir(app)?)),
+        };
+        reclaimed.push(ReclaimedSpace { category, bytes });
+    }
+    Ok(ReclaimReport {
+        total_bytes: reclaimed.iter().map(|r| r.bytes).sum(),
+        reclaimed,
+    })
+}
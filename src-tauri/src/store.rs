@@ -0,0 +1,74 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::location;
+use crate::profiles;
+
+/// Resolve the app data directory shared by all profiles, creating it if
+/// needed. This is the folder the user chose, if they moved their data.
+pub fn shared_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = location::roots(app)?.data_dir;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Resolve a file in the active profile's data directory, creating the
+/// directory if needed
+pub fn data_file(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    let dir = profiles::current(app)?.data_dir;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create profile directory: {}", e))?;
+    Ok(dir.join(name))
+}
+
+/// Resolve a file in the shared app data directory
+pub fn shared_file(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    Ok(shared_dir(app)?.join(name))
+}
+
+fn read_json<T: DeserializeOwned + Default>(path: &Path, name: &str) -> Result<T, String> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("Invalid JSON in {}: {}", name, e))
+}
+
+fn write_json<T: Serialize>(path: &Path, name: &str, value: &T) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {}: {}", name, e))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to save {}: {}", name, e))
+}
+
+/// Load a JSON document from the active profile's data directory.
+/// Returns the default value if the document has not been saved yet.
+pub fn load_json<T: DeserializeOwned + Default>(app: &AppHandle, name: &str) -> Result<T, String> {
+    read_json(&data_file(app, name)?, name)
+}
+
+/// Write a JSON document to the active profile's data directory.
+/// Writes to a temporary file first so a crash never leaves a truncated document.
+pub fn save_json<T: Serialize>(app: &AppHandle, name: &str, value: &T) -> Result<(), String> {
+    write_json(&data_file(app, name)?, name, value)
+}
+
+/// Like `load_json`, for a document shared by all profiles
+pub fn load_shared_json<T: DeserializeOwned + Default>(
+    app: &AppHandle,
+    name: &str,
+) -> Result<T, String> {
+    read_json(&shared_file(app, name)?, name)
+}
+
+/// Like `save_json`, for a document shared by all profiles
+pub fn save_shared_json<T: Serialize>(
+    app: &AppHandle,
+    name: &str,
+    value: &T,
+) -> Result<(), String> {
+    write_json(&shared_file(app, name)?, name, value)
+}
@@ -0,0 +1,452 @@
+//! Archival of stories nobody is playing. Every row a story has in the
+//! frontend's database is packed into one zstd-compressed file and removed
+//! from the working tables, so the database stays small for libraries of
+//! hundreds of adventures. A small index keeps archived stories listed and
+//! searchable by title, and opening one restores its rows exactly as they
+//! were. Stories deleted by another device are archived the same way into a
+//! trash, and removed for good once their grace period ends.
+
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteRow, SqliteValueRef};
+use sqlx::{Column, Row, Sqlite, Transaction, TypeInfo, ValueRef};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use crate::profiles::{self, database};
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Directory in the app data directory holding the compressed stories
+pub const ARCHIVE_DIR: &str = "story_archive";
+
+/// Index of archived stories, in the app data directory
+pub const ARCHIVE_INDEX_FILE: &str = "story_archive.json";
+
+/// Archives are written once and read rarely, so a high level is worth it
+const COMPRESSION_LEVEL: i32 = 15;
+
+/// Bumped if the archive layout changes
+const ARCHIVE_FORMAT: u32 = 1;
+
+/// Tables holding a story's rows, in the order they are restored. Rows are
+/// removed in the reverse order.
+const STORY_TABLES: [&str; 13] = [
+    "stories",
+    "ai_profiles",
+    "branches",
+    "chapters",
+    "checkpoints",
+    "story_entries",
+    "characters",
+    "locations",
+    "items",
+    "story_beats",
+    "entries",
+    "embedded_images",
+    "story_dictionary_words",
+];
+
+/// An archived story as listed in the library
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedStory {
+    pub story_id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
+    pub entry_count: usize,
+    /// When the story was last changed before it was archived
+    #[serde(default)]
+    pub updated_at: Option<i64>,
+    pub archived_at: i64,
+    /// Bytes of row data before and after compression
+    pub size: u64,
+    pub compressed_size: u64,
+    /// Set for stories in the trash: the archive is removed after this time
+    /// unless the story is restored
+    #[serde(default)]
+    pub purge_at: Option<i64>,
+}
+
+/// One value as SQLite stores it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Cell {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ArchivedTable {
+    pub(crate) name: String,
+    pub(crate) columns: Vec<String>,
+    pub(crate) rows: Vec<Vec<Cell>>,
+}
+
+/// The contents of an archive file
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ArchivedRows {
+    format: u32,
+    pub(crate) tables: Vec<ArchivedTable>,
+}
+
+impl ArchivedRows {
+    fn cell(&self, table: &str, column: &str) -> Option<&Cell> {
+        let table = self.tables.iter().find(|t| t.name == table)?;
+        let index = table.columns.iter().position(|c| c == column)?;
+        table.rows.first()?.get(index)
+    }
+
+    pub(crate) fn text(&self, column: &str) -> Option<String> {
+        match self.cell("stories", column)? {
+            Cell::Text(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn row_count(&self, table: &str) -> usize {
+        self.tables
+            .iter()
+            .find(|t| t.name == table)
+            .map_or(0, |t| t.rows.len())
+    }
+}
+
+fn archive_path(app: &AppHandle, story_id: &str) -> Result<PathBuf, String> {
+    if story_id.is_empty() || story_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid story ID: {}", story_id));
+    }
+    let dir = store::data_file(app, ARCHIVE_DIR)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create archive directory: {}", e))?;
+    Ok(dir.join(format!("{}.json.zst", story_id)))
+}
+
+fn key_column(table: &str) -> &'static str {
+    if table == "stories" {
+        "id"
+    } else {
+        "story_id"
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn read_cell(value: SqliteValueRef<'_>, row: &SqliteRow, index: usize) -> Result<Cell, String> {
+    if value.is_null() {
+        return Ok(Cell::Null);
+    }
+    let kind = value.type_info().name().to_string();
+    let cell = match kind.as_str() {
+        "INTEGER" => row.try_get(index).map(Cell::Integer),
+        "REAL" => row.try_get(index).map(Cell::Real),
+        "BLOB" => row.try_get(index).map(Cell::Blob),
+        _ => row.try_get(index).map(Cell::Text),
+    };
+    cell.map_err(|e| format!("Failed to read story data: {}", e))
+}
+
+pub(crate) async fn read_rows(
+    tx: &mut Transaction<'_, Sqlite>,
+    story_id: &str,
+) -> Result<ArchivedRows, String> {
+    let mut tables = Vec::new();
+    for table in STORY_TABLES {
+        let sql = format!("SELECT * FROM {} WHERE {} = ?", table, key_column(table));
+        let rows = sqlx::query(&sql)
+            .bind(story_id)
+            .fetch_all(&mut **tx)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", table, e))?;
+        let Some(first) = rows.first() else {
+            continue;
+        };
+        let columns = first
+            .columns()
+            .iter()
+            .map(|c| c.name().to_string())
+            .collect::<Vec<_>>();
+        let mut cells = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let mut values = Vec::with_capacity(columns.len());
+            for index in 0..columns.len() {
+                let raw = row
+                    .try_get_raw(index)
+                    .map_err(|e| format!("Failed to read {}: {}", table, e))?;
+                values.push(read_cell(raw, row, index)?);
+            }
+            cells.push(values);
+        }
+        tables.push(ArchivedTable {
+            name: table.to_string(),
+            columns,
+            rows: cells,
+        });
+    }
+    Ok(ArchivedRows {
+        format: ARCHIVE_FORMAT,
+        tables,
+    })
+}
+
+pub(crate) async fn delete_rows(
+    tx: &mut Transaction<'_, Sqlite>,
+    story_id: &str,
+) -> Result<(), String> {
+    for table in STORY_TABLES.iter().rev() {
+        let sql = format!("DELETE FROM {} WHERE {} = ?", table, key_column(table));
+        sqlx::query(&sql)
+            .bind(story_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| format!("Failed to remove story from {}: {}", table, e))?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn insert_rows(
+    tx: &mut Transaction<'_, Sqlite>,
+    archived: &ArchivedRows,
+) -> Result<(), String> {
+    for table in &archived.tables {
+        if !STORY_TABLES.contains(&table.name.as_str()) {
+            return Err(format!("Unknown table in archive: {}", table.name));
+        }
+        let columns = table
+            .columns
+            .iter()
+            .map(|c| quote(c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let placeholders = vec!["?"; table.columns.len()].join(", ");
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table.name, columns, placeholders
+        );
+        for row in &table.rows {
+            let mut query = sqlx::query(&sql);
+            for cell in row {
+                query = match cell {
+                    Cell::Null => query.bind(None::<String>),
+                    Cell::Integer(value) => query.bind(*value),
+                    Cell::Real(value) => query.bind(*value),
+                    Cell::Text(value) => query.bind(value.as_str()),
+                    Cell::Blob(value) => query.bind(value.as_slice()),
+                };
+            }
+            query
+                .execute(&mut **tx)
+                .await
+                .map_err(|e| format!("Failed to restore {}: {}", table.name, e))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn list(app: &AppHandle) -> Result<Vec<ArchivedStory>, String> {
+    store::load_json(app, ARCHIVE_INDEX_FILE)
+}
+
+fn save_index(app: &AppHandle, index: &[ArchivedStory]) -> Result<(), String> {
+    store::save_json(app, ARCHIVE_INDEX_FILE, &index)
+}
+
+/// Archived stories whose title or description holds every word of the
+/// query, most recently archived first. Stories in the trash are left out.
+pub fn search(app: &AppHandle, query: Option<&str>) -> Result<Vec<ArchivedStory>, String> {
+    let words: Vec<String> = query
+        .unwrap_or_default()
+        .to_lowercase()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    let profile = profiles::active(app)?;
+    let mut found: Vec<ArchivedStory> = list(app)?
+        .into_iter()
+        .filter(|story| story.purge_at.is_none())
+        .filter(|story| profiles::check_story_id(&profile, &story.story_id).is_ok())
+        .filter(|story| {
+            let haystack = format!(
+                "{} {}",
+                story.title,
+                story.description.as_deref().unwrap_or_default()
+            )
+            .to_lowercase();
+            words.iter().all(|word| haystack.contains(word.as_str()))
+        })
+        .collect();
+    found.sort_by_key(|story| std::cmp::Reverse(story.archived_at));
+    Ok(found)
+}
+
+/// Compress a story's rows into its archive file and remove them from the
+/// database. The file is written before the rows are removed, so a failure
+/// at any point leaves the story where it was.
+pub async fn archive(app: &AppHandle, story_id: &str) -> Result<ArchivedStory, String> {
+    if list(app)?.iter().any(|s| s.story_id == story_id) {
+        return Err("This story is already archived".to_string());
+    }
+    let path = archive_path(app, story_id)?;
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to open database: {}", e))?;
+    let rows = read_rows(&mut tx, story_id).await?;
+    if rows.row_count("stories") == 0 {
+        return Err(format!("Story not found: {}", story_id));
+    }
+
+    let json =
+        serde_json::to_vec(&rows).map_err(|e| format!("Failed to serialize story: {}", e))?;
+    let compressed = zstd::encode_all(json.as_slice(), COMPRESSION_LEVEL)
+        .map_err(|e| format!("Failed to compress story: {}", e))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, &compressed).map_err(|e| format!("Failed to write archive: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to save archive: {}", e))?;
+
+    let removed = async {
+        sqlx::query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to prepare database: {}", e))?;
+        delete_rows(&mut tx, story_id).await?;
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to remove story from database: {}", e))
+    }
+    .await;
+    pool.close().await;
+    if let Err(e) = removed {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+
+    let archived = ArchivedStory {
+        story_id: story_id.to_string(),
+        title: rows.text("title").unwrap_or_default(),
+        description: rows.text("description"),
+        genre: rows.text("genre"),
+        mode: rows.text("mode"),
+        entry_count: rows.row_count("story_entries"),
+        updated_at: match rows.cell("stories", "updated_at") {
+            Some(Cell::Integer(at)) => Some(*at),
+            _ => None,
+        },
+        archived_at: now_ms(),
+        size: json.len() as u64,
+        compressed_size: compressed.len() as u64,
+        purge_at: None,
+    };
+    let mut index = list(app)?;
+    index.push(archived.clone());
+    save_index(app, &index)?;
+    Ok(archived)
+}
+
+/// Put an archived story's rows back into the database and drop its archive
+pub async fn unarchive(app: &AppHandle, story_id: &str) -> Result<ArchivedStory, String> {
+    let mut index = list(app)?;
+    let position = index
+        .iter()
+        .position(|s| s.story_id == story_id)
+        .ok_or_else(|| format!("Story {} is not archived", story_id))?;
+    let path = archive_path(app, story_id)?;
+    let compressed = fs::read(&path).map_err(|e| format!("Failed to read archive: {}", e))?;
+    let json = zstd::decode_all(compressed.as_slice())
+        .map_err(|e| format!("Failed to decompress archive: {}", e))?;
+    let rows: ArchivedRows =
+        serde_json::from_slice(&json).map_err(|e| format!("Invalid archive: {}", e))?;
+    if rows.format > ARCHIVE_FORMAT {
+        return Err("This archive was made by a newer version of Aventura".to_string());
+    }
+
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let restored = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        let existing = sqlx::query("SELECT 1 FROM stories WHERE id = ?")
+            .bind(story_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to read stories: {}", e))?;
+        if existing.is_some() {
+            return Err("A story with this ID is already in the library".to_string());
+        }
+        // Stories and branches point at each other, so keys are checked once
+        // every row is back
+        sqlx::query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to prepare database: {}", e))?;
+        insert_rows(&mut tx, &rows).await?;
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to restore story: {}", e))
+    }
+    .await;
+    pool.close().await;
+    restored?;
+
+    let archived = index.remove(position);
+    save_index(app, &index)?;
+    let _ = fs::remove_file(&path);
+    Ok(archived)
+}
+
+/// Archive a story into the trash, to be removed for good at `purge_at`
+pub async fn trash(
+    app: &AppHandle,
+    story_id: &str,
+    purge_at: i64,
+) -> Result<ArchivedStory, String> {
+    let mut trashed = archive(app, story_id).await?;
+    trashed.purge_at = Some(purge_at);
+    let mut index = list(app)?;
+    if let Some(entry) = index.iter_mut().find(|s| s.story_id == story_id) {
+        entry.purge_at = Some(purge_at);
+    }
+    save_index(app, &index)?;
+    Ok(trashed)
+}
+
+/// Stories in the trash, soonest to be removed first. Any past their grace
+/// period are removed before listing.
+pub fn trashed(app: &AppHandle) -> Result<Vec<ArchivedStory>, String> {
+    purge_expired(app)?;
+    let profile = profiles::active(app)?;
+    let mut found: Vec<ArchivedStory> = list(app)?
+        .into_iter()
+        .filter(|story| story.purge_at.is_some())
+        .filter(|story| profiles::check_story_id(&profile, &story.story_id).is_ok())
+        .collect();
+    found.sort_by_key(|story| story.purge_at);
+    Ok(found)
+}
+
+/// Remove trashed stories whose grace period has ended
+pub fn purge_expired(app: &AppHandle) -> Result<(), String> {
+    let now = now_ms();
+    let (expired, kept): (Vec<ArchivedStory>, Vec<ArchivedStory>) = list(app)?
+        .into_iter()
+        .partition(|story| story.purge_at.is_some_and(|at| at <= now));
+    if expired.is_empty() {
+        return Ok(());
+    }
+    save_index(app, &kept)?;
+    for story in expired {
+        let _ = fs::remove_file(archive_path(app, &story.story_id)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,604 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+use super::archive::{self, ArchivedStory};
+use super::consistency::{self, ConsistencyReport};
+use super::graph::{self, StoryGraph};
+use super::interview::{self, Interview, InterviewProgress, Interviews};
+use super::journal::{self, OperationKind, UndoConfig, UndoableOperation, UNDO_CONFIG_FILE};
+use super::lock::{LockReason, StoryLockInfo, StoryLocks};
+use super::merge::{self, MergeReport, MergeSide, MergeStrategy};
+use super::rating::{self, ContentRating};
+use super::relationships::RelationshipGraph;
+use super::revisions::{self, StoryRevision};
+use super::sanitize::{self, SanitizeRules, SanitizedStory, SANITIZE_RULES_FILE};
+use super::simulate::{simulate, SimulationMode, SimulationReport};
+use super::split;
+use super::timeline::{self, Timeline};
+use super::versions::{self, StoryVersion};
+use super::{rows, StoryExport};
+use crate::ai::filter::Strictness;
+use crate::ai::profile::story_provider;
+use crate::ai::types::ProviderConfig;
+use crate::game::recaps;
+use crate::profiles;
+use crate::stats;
+use crate::store;
+use crate::sync::commands::SyncState;
+use crate::sync::tombstones;
+
+/// How long a lock taken by the frontend lasts unless released sooner
+const DEFAULT_LOCK_TTL: Duration = Duration::from_secs(300);
+
+/// State managed by Tauri for story operations
+#[derive(Default)]
+pub struct StoryState {
+    /// Advisory locks held while stories are synced, merged or co-edited
+    pub(crate) locks: StoryLocks,
+    /// Held while a save compares and bumps a revision
+    pub(crate) saves: tokio::sync::Mutex<()>,
+    /// Story setup interviews in progress
+    pub(crate) interviews: std::sync::Mutex<Interviews>,
+}
+
+/// Rebuild and store the branch graph of a story, including cycle and
+/// reachability checks
+#[tauri::command]
+pub async fn get_story_graph(app: AppHandle, story_id: String) -> Result<StoryGraph, String> {
+    profiles::check_story(&app, &story_id).await?;
+    graph::refresh(&app, &story_id).await
+}
+
+/// Flag names spelled several ways and eye or hair colours that change along
+/// the main branch. With `verify`, the story's AI profile provider judges
+/// the most referenced issues.
+#[tauri::command]
+pub async fn check_consistency(
+    app: AppHandle,
+    story_id: String,
+    verify: Option<bool>,
+) -> Result<ConsistencyReport, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let mut report = consistency::check(&export);
+    if verify.unwrap_or(false) && !report.issues.is_empty() {
+        let provider = story_provider(&app, &story_id).await?;
+        consistency::verify(&mut report, &provider).await?;
+    }
+    Ok(report)
+}
+
+/// Weighted graph of which characters appear and interact with each other
+/// along the main branch, with the strongest ties written out for prompts
+#[tauri::command]
+pub async fn get_relationship_graph(
+    app: AppHandle,
+    story_id: String,
+) -> Result<RelationshipGraph, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    Ok(RelationshipGraph::build(&export))
+}
+
+/// Place the main branch's entries on an in-story clock from the time
+/// tracker and phrases like "the next morning". With `use_model`, the
+/// story's AI profile provider also reads and summarises new or changed
+/// entries. Only entries that changed since the last run are read again.
+#[tauri::command]
+pub async fn extract_timeline(
+    app: AppHandle,
+    story_id: String,
+    use_model: Option<bool>,
+) -> Result<Timeline, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let provider = match use_model.unwrap_or(false) {
+        true => Some(story_provider(&app, &story_id).await?),
+        false => None,
+    };
+    timeline::extract(&app, &export, provider.as_ref()).await
+}
+
+/// The timeline last extracted for a story, brought up to date with its
+/// rows, so entries written since the last save are placed too
+#[tauri::command]
+pub async fn get_timeline(app: AppHandle, story_id: String) -> Result<Option<Timeline>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    timeline::refresh(&app, &export)
+}
+
+/// Walk a saved story's branch graph `n` times to find dead ends and content players never see.
+/// Random walks are reproducible for a given seed; exhaustive mode lists up to `n` distinct paths.
+#[tauri::command]
+pub async fn simulate_playthroughs(
+    app: AppHandle,
+    story_id: String,
+    n: usize,
+    seed: Option<u64>,
+    mode: Option<SimulationMode>,
+) -> Result<SimulationReport, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let graph = StoryGraph::build(&export);
+    Ok(simulate(
+        &graph,
+        n,
+        seed.unwrap_or_default(),
+        mode.unwrap_or_default(),
+    ))
+}
+
+/// Combine two saved copies of a story entry-by-entry into one that keeps
+/// the primary's ID. Interactive merges return conflicts until every one has a
+/// resolution; once the merge completes both originals are saved as versions.
+/// Records the copies know under different IDs are matched first. A
+/// secondary pulled in a sync session names the session, so its times are
+/// then moved onto this device's clock.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn merge_stories(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+    sync: State<'_, SyncState>,
+    primary_id: String,
+    secondary_id: String,
+    strategy: Option<MergeStrategy>,
+    resolutions: Option<HashMap<String, MergeSide>>,
+    secondary_session_id: Option<String>,
+) -> Result<MergeReport, String> {
+    if primary_id == secondary_id {
+        return Err("Choose two different stories to merge".to_string());
+    }
+    profiles::check_story(&app, &primary_id).await?;
+    profiles::check_story(&app, &secondary_id).await?;
+    let primary = rows::load(&app, &primary_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", primary_id))?;
+    let secondary = rows::load(&app, &secondary_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", secondary_id))?;
+    let mut secondary = merge::match_ids(&primary, &secondary)?;
+    if let Some(session_id) = secondary_session_id {
+        if let Some(clock) = sync.session_clock(&session_id).await? {
+            merge::adjust_clock(&mut secondary, &clock);
+        }
+    }
+    let _locks = state
+        .locks
+        .try_acquire_all(&[&primary_id, &secondary_id], LockReason::Merge)?;
+    let mut report = merge::merge(
+        &primary,
+        &secondary,
+        strategy.unwrap_or_default(),
+        &resolutions.unwrap_or_default(),
+    )?;
+    if report.merged_json.is_some() {
+        journal::record(&app, OperationKind::Merge, &[&primary_id, &secondary_id]).await?;
+        report.versions = vec![
+            versions::save_version(&app, &primary, "Before merge")?,
+            versions::save_version(&app, &secondary, "Merged into another story")?,
+        ];
+    }
+    Ok(report)
+}
+
+/// Lock a story for an operation the frontend runs, such as an import.
+/// Waits up to `wait_secs` for a current holder; the lock expires after
+/// `ttl_secs` (five minutes by default) unless released first.
+#[tauri::command]
+pub async fn acquire_story_lock(
+    state: State<'_, StoryState>,
+    story_id: String,
+    reason: Option<LockReason>,
+    ttl_secs: Option<u64>,
+    wait_secs: Option<u64>,
+) -> Result<StoryLockInfo, String> {
+    let ttl = ttl_secs.map_or(DEFAULT_LOCK_TTL, Duration::from_secs);
+    let guard = state
+        .locks
+        .acquire(
+            &story_id,
+            reason.unwrap_or(LockReason::Edit),
+            Some(ttl),
+            Duration::from_secs(wait_secs.unwrap_or_default()),
+        )
+        .await?;
+    Ok(guard.detach())
+}
+
+/// Release a lock taken with `acquire_story_lock`
+#[tauri::command]
+pub async fn release_story_lock(
+    state: State<'_, StoryState>,
+    lock_id: String,
+) -> Result<bool, String> {
+    Ok(state.locks.release(&lock_id))
+}
+
+/// Who holds a story's lock, if anyone. Check before saving a story.
+#[tauri::command]
+pub async fn get_story_lock(
+    state: State<'_, StoryState>,
+    story_id: String,
+) -> Result<Option<StoryLockInfo>, String> {
+    Ok(state.locks.holder(&story_id))
+}
+
+/// Fail with a `StoryLocked` error if the story may not be written now.
+/// The frontend checks before each change it writes to the database.
+#[tauri::command]
+pub async fn check_story_writable(
+    state: State<'_, StoryState>,
+    story_id: String,
+    lock_id: Option<String>,
+) -> Result<(), String> {
+    state.locks.check_write(&story_id, lock_id.as_deref())
+}
+
+#[tauri::command]
+pub async fn list_story_locks(state: State<'_, StoryState>) -> Result<Vec<StoryLockInfo>, String> {
+    Ok(state.locks.list())
+}
+
+/// Save a story the frontend has written to the database, based on
+/// `expected_revision` (0 for a story never saved). Called once the story's
+/// writes have settled: the story is read back from its rows, the words
+/// written are counted and its timeline is brought up to date. Fails with a
+/// `RevisionConflict` error carrying the newer copy if someone saved in
+/// between, or `StoryLocked` if another operation holds the story and
+/// `lock_id` is not its lock.
+#[tauri::command]
+pub async fn save_story(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+    story_id: String,
+    expected_revision: u64,
+    lock_id: Option<String>,
+) -> Result<StoryRevision, String> {
+    state.locks.check_write(&story_id, lock_id.as_deref())?;
+    let _save = state.saves.lock().await;
+    let is_new = revisions::current(&app, &story_id)
+        .await?
+        .is_none_or(|r| r.revision == 0);
+    profiles::check_story_save(&app, &story_id, is_new)?;
+    let revision = revisions::save(&app, &story_id, expected_revision).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    stats::record_save(&app, &export).await;
+    if let Err(e) = recaps::touch(&app, &story_id).await {
+        eprintln!("Failed to track play session: {}", e);
+    }
+    if let Err(e) = timeline::refresh(&app, &export) {
+        eprintln!("Failed to update timeline: {}", e);
+    }
+    Ok(revision)
+}
+
+/// Revision a story was last saved at, if it has been saved
+#[tauri::command]
+pub async fn get_story_revision(
+    app: AppHandle,
+    story_id: String,
+) -> Result<Option<StoryRevision>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    revisions::current(&app, &story_id).await
+}
+
+/// Saved versions of a story, newest first
+#[tauri::command]
+pub async fn list_story_versions(
+    app: AppHandle,
+    story_id: String,
+) -> Result<Vec<StoryVersion>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    versions::list_versions(&app, &story_id)
+}
+
+/// Story JSON of a saved version
+#[tauri::command]
+pub async fn get_story_version(
+    app: AppHandle,
+    story_id: String,
+    version_id: String,
+) -> Result<String, String> {
+    profiles::check_story(&app, &story_id).await?;
+    versions::load_version(&app, &story_id, &version_id)
+}
+
+/// Break a saved story into linked parts starting at the given main-branch
+/// entries. Returns each part in Aventura export format, in order.
+#[tauri::command]
+pub async fn split_story(
+    app: AppHandle,
+    story_id: String,
+    at_entry_ids: Vec<String>,
+) -> Result<Vec<String>, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    split::split(&export, &at_entry_ids)?
+        .iter()
+        .map(StoryExport::to_json)
+        .collect()
+}
+
+/// Join saved stories end to end into one new story. `order` lists story
+/// IDs; stories not listed follow in the order given.
+#[tauri::command]
+pub async fn combine_stories(
+    app: AppHandle,
+    story_ids: Vec<String>,
+    order: Option<Vec<String>>,
+) -> Result<String, String> {
+    let mut stories = Vec::with_capacity(story_ids.len());
+    for story_id in &story_ids {
+        profiles::check_story(&app, story_id).await?;
+        let export = rows::load(&app, story_id)
+            .await?
+            .ok_or_else(|| format!("Story not found: {}", story_id))?;
+        stories.push(export);
+    }
+    split::combine(split::order_stories(stories, &order.unwrap_or_default()))?.to_json()
+}
+
+#[tauri::command]
+pub async fn get_sanitize_rules(app: AppHandle) -> Result<SanitizeRules, String> {
+    store::load_json(&app, SANITIZE_RULES_FILE)
+}
+
+#[tauri::command]
+pub async fn set_sanitize_rules(app: AppHandle, rules: SanitizeRules) -> Result<(), String> {
+    sanitize::validate(&rules)?;
+    store::save_json(&app, SANITIZE_RULES_FILE, &rules)
+}
+
+/// Clean a copy of a story for sharing, using the given rules or the saved
+/// ones. The story itself is not changed.
+#[tauri::command]
+pub async fn sanitize_story(
+    app: AppHandle,
+    story_id: String,
+    rules: Option<SanitizeRules>,
+) -> Result<SanitizedStory, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let rules = match rules {
+        Some(rules) => rules,
+        None => store::load_json(&app, SANITIZE_RULES_FILE)?,
+    };
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let (clean, report) = sanitize::sanitize(export, &rules)?;
+    Ok(SanitizedStory {
+        story_json: clean.to_json()?,
+        report,
+    })
+}
+
+/// A story's age rating and content warnings
+#[tauri::command]
+pub async fn get_story_rating(app: AppHandle, story_id: String) -> Result<ContentRating, String> {
+    profiles::check_story(&app, &story_id).await?;
+    rating::get(&app, &story_id).await
+}
+
+/// Change a story's age rating and content warnings. A profile that ratings
+/// are hidden from cannot change them.
+#[tauri::command]
+pub async fn set_story_rating(
+    app: AppHandle,
+    story_id: String,
+    rating: ContentRating,
+) -> Result<ContentRating, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let profile = profiles::active(&app)?;
+    if !profile.admin && profile.restrictions.limits_ratings() {
+        return Err("The current profile may not change content ratings".to_string());
+    }
+    rating::set(&app, &story_id, rating).await
+}
+
+/// Compress a story out of the database. It stays listed by
+/// `list_archived_stories` and comes back with `unarchive_story`.
+#[tauri::command]
+pub async fn archive_story(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+    story_id: String,
+) -> Result<ArchivedStory, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let _lock = state
+        .locks
+        .try_acquire(&story_id, LockReason::Archive, None)?;
+    let _save = state.saves.lock().await;
+    archive::archive(&app, &story_id).await
+}
+
+/// Restore an archived story's rows, for example when it is opened, or
+/// bring a story back from the trash
+#[tauri::command]
+pub async fn unarchive_story(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+    story_id: String,
+) -> Result<ArchivedStory, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let _lock = state
+        .locks
+        .try_acquire(&story_id, LockReason::Archive, None)?;
+    let _save = state.saves.lock().await;
+    let story = archive::unarchive(&app, &story_id).await?;
+    if story.purge_at.is_some() {
+        // Back from the trash, so other devices should keep it too
+        tombstones::forget(&app, &[&story_id])?;
+    }
+    Ok(story)
+}
+
+/// Archived stories, optionally only those whose title or description
+/// contains every word of `query`
+#[tauri::command]
+pub async fn list_archived_stories(
+    app: AppHandle,
+    query: Option<String>,
+) -> Result<Vec<ArchivedStory>, String> {
+    archive::search(&app, query.as_deref())
+}
+
+/// Stories deleted by another device that can still be brought back with
+/// `unarchive_story`
+#[tauri::command]
+pub async fn list_trashed_stories(app: AppHandle) -> Result<Vec<ArchivedStory>, String> {
+    archive::trashed(&app)
+}
+
+/// Delete a story from the database. It can be brought back with
+/// `undo_last_operation` within the undo window. The deletion is passed on
+/// to other devices when they sync, unless the story is `replacing`: about
+/// to be written again, such as by a sync pull.
+#[tauri::command]
+pub async fn delete_story(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+    story_id: String,
+    replacing: Option<bool>,
+) -> Result<UndoableOperation, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let _lock = state
+        .locks
+        .try_acquire(&story_id, LockReason::Delete, None)?;
+    let _save = state.saves.lock().await;
+    let operation = journal::delete(&app, &[&story_id]).await?;
+    if !replacing.unwrap_or(false) {
+        tombstones::record(&app, &[&story_id])?;
+    }
+    Ok(operation)
+}
+
+/// Snapshot stories an import is about to overwrite, so the import can be
+/// undone. Call right before writing the imported copies.
+#[tauri::command]
+pub async fn record_import_overwrite(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+    story_ids: Vec<String>,
+) -> Result<UndoableOperation, String> {
+    for story_id in &story_ids {
+        profiles::check_story(&app, story_id).await?;
+    }
+    let ids: Vec<&str> = story_ids.iter().map(String::as_str).collect();
+    let _save = state.saves.lock().await;
+    journal::record(&app, OperationKind::ImportOverwrite, &ids).await
+}
+
+/// Deletes, merges and import overwrites that can still be undone, newest first
+#[tauri::command]
+pub async fn list_undoable_operations(app: AppHandle) -> Result<Vec<UndoableOperation>, String> {
+    journal::list(&app)
+}
+
+/// Reverse the newest operation still in the undo window, restoring every
+/// story it touched. Returns the operation undone.
+#[tauri::command]
+pub async fn undo_last_operation(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+) -> Result<UndoableOperation, String> {
+    let operation = journal::last(&app)?.ok_or("There is nothing to undo")?;
+    for story_id in &operation.story_ids {
+        profiles::check_story(&app, story_id).await?;
+    }
+    let ids: Vec<&str> = operation.story_ids.iter().map(String::as_str).collect();
+    let _locks = state.locks.try_acquire_all(&ids, LockReason::Undo)?;
+    let _save = state.saves.lock().await;
+    journal::undo(&app, &operation).await?;
+    if operation.kind == OperationKind::Delete {
+        tombstones::forget(&app, &ids)?;
+    }
+    Ok(operation)
+}
+
+#[tauri::command]
+pub async fn get_undo_config(app: AppHandle) -> Result<UndoConfig, String> {
+    store::load_json(&app, UNDO_CONFIG_FILE)
+}
+
+#[tauri::command]
+pub async fn set_undo_config(app: AppHandle, config: UndoConfig) -> Result<(), String> {
+    store::save_json(&app, UNDO_CONFIG_FILE, &config)
+}
+
+/// Start a guided story setup for a genre. With a provider, the model
+/// fleshes out the answers and writes the opening scene.
+#[tauri::command]
+pub async fn start_story_interview(
+    app: AppHandle,
+    state: State<'_, StoryState>,
+    genre: String,
+    provider: Option<ProviderConfig>,
+) -> Result<InterviewProgress, String> {
+    if let Some(provider) = &provider {
+        profiles::check_generation(&app, &provider.base_url, Strictness::Off)?;
+    }
+    let interview = Interview::new(&genre, provider)?;
+    let progress = interview.progress(None);
+    let mut interviews = state
+        .interviews
+        .lock()
+        .map_err(|_| "Interviews are unavailable".to_string())?;
+    interview::prune(&mut interviews);
+    interviews.insert(interview.id().to_string(), interview);
+    Ok(progress)
+}
+
+/// Answer the interview's current question. Returns the next question, or
+/// once every question is answered the new story, which ends the interview.
+#[tauri::command]
+pub async fn answer_story_interview(
+    state: State<'_, StoryState>,
+    interview_id: String,
+    answer: String,
+) -> Result<InterviewProgress, String> {
+    let take = || {
+        state
+            .interviews
+            .lock()
+            .map_err(|_| "Interviews are unavailable".to_string())
+    };
+    let mut interview = take()?
+        .remove(&interview_id)
+        .ok_or_else(|| format!("Interview not found: {}", interview_id))?;
+    let progress = interview.answer(&answer).await;
+    if !matches!(progress, Ok(InterviewProgress::Complete { .. })) {
+        take()?.insert(interview_id, interview);
+    }
+    progress
+}
+
+/// Abandon an interview. Returns false if there was none.
+#[tauri::command]
+pub async fn cancel_story_interview(
+    state: State<'_, StoryState>,
+    interview_id: String,
+) -> Result<bool, String> {
+    Ok(state
+        .interviews
+        .lock()
+        .map_err(|_| "Interviews are unavailable".to_string())?
+        .remove(&interview_id)
+        .is_some())
+}
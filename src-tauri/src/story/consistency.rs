@@ -0,0 +1,404 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::OnceLock;
+
+use super::entities::{EntityIndex, EntityKind};
+use super::StoryExport;
+use crate::ai::proxy::{complete_chat, json_object};
+use crate::ai::types::{ChatMessage, ProviderConfig, SamplingParams};
+
+/// Length of the sentence excerpt included with each reference
+const EXCERPT_CHARS: usize = 200;
+
+/// References listed per issue
+const MAX_REFERENCES: usize = 20;
+
+/// Issues sent to the model when verification is asked for, most
+/// referenced first
+const MAX_VERIFIED: usize = 10;
+
+/// Shortest name checked for misspellings; shorter names have too many
+/// lookalikes
+const MIN_NAME_CHARS: usize = 4;
+
+/// Colours recognised in eye and hair descriptions
+const COLOURS: [&str; 18] = [
+    "blue", "green", "brown", "hazel", "grey", "gray", "amber", "black", "violet", "red", "golden",
+    "silver", "blonde", "blond", "auburn", "white", "copper", "purple",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConsistencyIssueKind {
+    /// A name written more than one way
+    Spelling,
+    /// A physical trait that changes between entries
+    Attribute,
+}
+
+/// Where an issue shows up
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryReference {
+    pub entry_id: String,
+    pub chapter: Option<i64>,
+    /// The spelling or trait value found here
+    pub value: String,
+    pub excerpt: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyIssue {
+    pub kind: ConsistencyIssueKind,
+    pub entity_id: String,
+    pub entity_name: String,
+    pub message: String,
+    /// Up to `MAX_REFERENCES`, the least common values first
+    pub references: Vec<EntryReference>,
+    /// Whether the model agreed it is a mistake, when verification ran
+    pub verified: Option<bool>,
+    pub explanation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyReport {
+    pub story_id: String,
+    pub entities_checked: usize,
+    pub issues: Vec<ConsistencyIssue>,
+}
+
+/// The model's verdict on one issue
+#[derive(Debug, Deserialize)]
+struct Verdict {
+    inconsistent: bool,
+    #[serde(default)]
+    explanation: String,
+}
+
+fn excerpt(sentence: &str) -> String {
+    sentence.chars().take(EXCERPT_CHARS).collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Capitalised words that appear somewhere other than the start of a
+/// sentence, so ordinary words are not mistaken for names, with the
+/// sentences they appear in
+fn proper_words(index: &EntityIndex) -> HashMap<String, Vec<(usize, usize)>> {
+    static WORD: OnceLock<Regex> = OnceLock::new();
+    let word = WORD.get_or_init(|| Regex::new(r"\b\p{Lu}\p{Ll}{2,}\b").unwrap());
+    let mut found: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+    let mut mid_sentence = HashSet::new();
+    for (entry, indexed) in index.entries.iter().enumerate() {
+        for (sentence, text) in indexed.sentences.iter().enumerate() {
+            for m in word.find_iter(text) {
+                let before = text[..m.start()].trim_end();
+                if before
+                    .chars()
+                    .last()
+                    .is_some_and(|c| c.is_alphanumeric() || c == ',')
+                {
+                    mid_sentence.insert(m.as_str().to_string());
+                }
+                found
+                    .entry(m.as_str().to_string())
+                    .or_default()
+                    .push((entry, sentence));
+            }
+        }
+    }
+    found.retain(|word, _| mid_sentence.contains(word));
+    found
+}
+
+/// Names of characters and places that also appear with a letter or two
+/// different, like "Elara", "Elarra" and "Ellara"
+fn spelling_issues(index: &EntityIndex) -> Vec<ConsistencyIssue> {
+    let words = proper_words(index);
+    let known: HashSet<String> = index
+        .entities
+        .iter()
+        .flat_map(|e| e.names())
+        .flat_map(str::split_whitespace)
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut issues = Vec::new();
+    for entity in &index.entities {
+        if !matches!(entity.kind, EntityKind::Character | EntityKind::Location) {
+            continue;
+        }
+        for name in entity.names().flat_map(str::split_whitespace) {
+            let name_lower = name.to_lowercase();
+            let length = name.chars().count();
+            if length < MIN_NAME_CHARS {
+                continue;
+            }
+            let max_edits = if length >= 8 { 2 } else { 1 };
+            let first = name_lower.chars().next();
+            let mut variants: Vec<(&String, &Vec<(usize, usize)>)> = words
+                .iter()
+                .filter(|(word, _)| {
+                    let lower = word.to_lowercase();
+                    !known.contains(&lower)
+                        && lower.chars().next() == first
+                        && edit_distance(&lower, &name_lower) <= max_edits
+                })
+                .collect();
+            if variants.is_empty() {
+                continue;
+            }
+            variants.sort_by_key(|(word, places)| (places.len(), word.as_str()));
+
+            let uses = words.get(name).map_or(0, Vec::len);
+            let spellings: Vec<String> = std::iter::once(format!("{} ({})", name, uses))
+                .chain(
+                    variants
+                        .iter()
+                        .rev()
+                        .map(|(word, places)| format!("{} ({})", word, places.len())),
+                )
+                .collect();
+            let mut references = Vec::new();
+            for (word, places) in &variants {
+                let mut entries_seen = HashSet::new();
+                for &(entry, sentence) in places.iter() {
+                    if entries_seen.insert(entry) {
+                        let indexed = &index.entries[entry];
+                        references.push(EntryReference {
+                            entry_id: indexed.entry_id.clone(),
+                            chapter: indexed.chapter,
+                            value: word.to_string(),
+                            excerpt: excerpt(&indexed.sentences[sentence]),
+                        });
+                    }
+                }
+            }
+            references.truncate(MAX_REFERENCES);
+            issues.push(ConsistencyIssue {
+                kind: ConsistencyIssueKind::Spelling,
+                entity_id: entity.id.clone(),
+                entity_name: entity.name.clone(),
+                message: format!(
+                    "{} is spelled {} ways: {}",
+                    name,
+                    spellings.len(),
+                    spellings.join(", ")
+                ),
+                references,
+                verified: None,
+                explanation: None,
+            });
+        }
+    }
+    issues
+}
+
+/// Eye and hair colours given in a sentence
+fn traits_in(sentence: &str) -> Vec<(&'static str, String)> {
+    static PATTERNS: OnceLock<Vec<(&'static str, Regex)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        let colours = COLOURS.join("|");
+        [("eyes", "eyes|eyed"), ("hair", "hair|haired")]
+            .into_iter()
+            .flat_map(|(attribute, nouns)| {
+                [
+                    format!(r"(?i)\b({})[- ](?:{})\b", colours, nouns),
+                    format!(
+                        r"(?i)\b{}\s+(?:was|were|is|are|of|shone|glowed|gleamed|flashed)\s+(?:an?\s+)?({})\b",
+                        attribute, colours
+                    ),
+                ]
+                .map(|pattern| (attribute, Regex::new(&pattern).unwrap()))
+            })
+        .collect()
+    });
+    let mut found = Vec::new();
+    for (attribute, regex) in patterns {
+        for captures in regex.captures_iter(sentence) {
+            let value = match captures[1].to_lowercase().as_str() {
+                "gray" => "grey".to_string(),
+                "blond" => "blonde".to_string(),
+                other => other.to_string(),
+            };
+            if !found.contains(&(*attribute, value.clone())) {
+                found.push((*attribute, value));
+            }
+        }
+    }
+    found
+}
+
+/// Eye and hair colours that change for a character. Only sentences naming
+/// exactly one character are read, so a trait is not pinned on the wrong
+/// person.
+fn attribute_issues(export: &StoryExport, index: &EntityIndex) -> Vec<ConsistencyIssue> {
+    // (character, attribute) -> value -> references
+    let mut seen: BTreeMap<(usize, &str), BTreeMap<String, Vec<EntryReference>>> = BTreeMap::new();
+    for (entry, sentence, entities) in index.sentences_with_mentions() {
+        let mut characters = entities
+            .iter()
+            .filter(|e| index.entities[**e].kind == EntityKind::Character);
+        let (Some(&character), None) = (characters.next(), characters.next()) else {
+            continue;
+        };
+        let indexed = &index.entries[entry];
+        let text = &indexed.sentences[sentence];
+        for (attribute, value) in traits_in(text) {
+            seen.entry((character, attribute))
+                .or_default()
+                .entry(value.clone())
+                .or_default()
+                .push(EntryReference {
+                    entry_id: indexed.entry_id.clone(),
+                    chapter: indexed.chapter,
+                    value,
+                    excerpt: excerpt(text),
+                });
+        }
+    }
+
+    let mut issues = Vec::new();
+    for ((character, attribute), values) in seen {
+        let entity = &index.entities[character];
+        let described: Vec<String> = export
+            .characters
+            .iter()
+            .find(|c| c.id == entity.id)
+            .and_then(|c| c.description.as_deref())
+            .map(traits_in)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(a, _)| *a == attribute)
+            .map(|(_, value)| value)
+            .collect();
+        let differs_from_description =
+            !described.is_empty() && values.keys().any(|value| !described.contains(value));
+        if values.len() < 2 && !differs_from_description {
+            continue;
+        }
+
+        let mut by_count: Vec<(String, Vec<EntryReference>)> = values.into_iter().collect();
+        by_count.sort_by_key(|(_, refs)| std::cmp::Reverse(refs.len()));
+        let counts: Vec<String> = by_count
+            .iter()
+            .map(|(value, refs)| {
+                let places = if refs.len() == 1 { "entry" } else { "entries" };
+                format!("{} in {} {}", value, refs.len(), places)
+            })
+            .collect();
+        let verb = if attribute == "eyes" { "are" } else { "is" };
+        let mut message = format!(
+            "{}'s {} {} described as {}",
+            entity.name,
+            attribute,
+            verb,
+            counts.join(" and ")
+        );
+        if differs_from_description {
+            message.push_str(&format!(
+                "; the character description says {}",
+                described.join(" and ")
+            ));
+        }
+        let mut references: Vec<EntryReference> = by_count
+            .into_iter()
+            .rev()
+            .flat_map(|(_, refs)| refs)
+            .collect();
+        references.truncate(MAX_REFERENCES);
+        issues.push(ConsistencyIssue {
+            kind: ConsistencyIssueKind::Attribute,
+            entity_id: entity.id.clone(),
+            entity_name: entity.name.clone(),
+            message,
+            references,
+            verified: None,
+            explanation: None,
+        });
+    }
+    issues
+}
+
+/// Find likely continuity mistakes in the main branch
+pub fn check(export: &StoryExport) -> ConsistencyReport {
+    let index = EntityIndex::build(export);
+    let mut issues = spelling_issues(&index);
+    issues.extend(attribute_issues(export, &index));
+    issues.sort_by_key(|i| std::cmp::Reverse(i.references.len()));
+    ConsistencyReport {
+        story_id: export.story.id.clone(),
+        entities_checked: index.entities.len(),
+        issues,
+    }
+}
+
+/// Ask the model whether each of the most referenced issues is a real
+/// mistake. Changes can be deliberate (a disguise, a nickname, magic), so
+/// issues it rejects are kept and marked rather than dropped.
+pub async fn verify(
+    report: &mut ConsistencyReport,
+    provider: &ProviderConfig,
+) -> Result<(), String> {
+    let sampling = SamplingParams {
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+    for issue in report.issues.iter_mut().take(MAX_VERIFIED) {
+        let mut evidence = format!("{}\n", issue.message);
+        for reference in &issue.references {
+            let chapter = reference
+                .chapter
+                .map(|c| format!("chapter {}, ", c))
+                .unwrap_or_default();
+            evidence.push_str(&format!(
+                "- ({}{}) {}\n",
+                chapter, reference.value, reference.excerpt
+            ));
+        }
+        let messages = vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: "You check fiction for continuity mistakes. Given a possible \
+                          inconsistency and the sentences it was found in, decide whether it \
+                          is an error the author would want to fix rather than something \
+                          intended, such as a different person, a nickname, a disguise or a \
+                          change the story explains. Reply with JSON only, in the form \
+                          {\"inconsistent\": boolean, \"explanation\": string}, the \
+                          explanation one sentence."
+                    .to_string(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: evidence,
+            },
+        ];
+        let reply = complete_chat(provider, &messages, &sampling).await?;
+        let json = json_object(&reply).ok_or("Verification response contained no JSON")?;
+        let verdict: Verdict = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid verification response: {}", e))?;
+        issue.verified = Some(verdict.inconsistent);
+        issue.explanation = Some(verdict.explanation.trim().to_string()).filter(|e| !e.is_empty());
+    }
+    Ok(())
+}
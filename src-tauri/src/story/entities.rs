@@ -0,0 +1,228 @@
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::text::plain_text;
+use super::StoryExport;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EntityKind {
+    Character,
+    Location,
+    Item,
+    /// A lorebook entry that is not also a character, location or item
+    Lore,
+}
+
+/// Something in the story that entries can mention by name
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entity {
+    pub id: String,
+    pub kind: EntityKind,
+    pub name: String,
+    /// Other names it goes by, from the lorebook
+    pub aliases: Vec<String>,
+}
+
+impl Entity {
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+}
+
+/// A main branch entry, split into sentences
+#[derive(Debug, Clone)]
+pub struct IndexedEntry {
+    pub entry_id: String,
+    pub chapter: Option<i64>,
+    pub sentences: Vec<String>,
+}
+
+/// One sentence naming an entity
+#[derive(Debug, Clone, Copy)]
+pub struct Mention {
+    /// Index into `EntityIndex::entities`
+    pub entity: usize,
+    /// Index into `EntityIndex::entries`
+    pub entry: usize,
+    /// Index into the entry's sentences
+    pub sentence: usize,
+}
+
+/// Where the story's characters, locations, items and lore are named along
+/// the main branch, sentence by sentence
+#[derive(Debug, Clone)]
+pub struct EntityIndex {
+    pub entities: Vec<Entity>,
+    pub entries: Vec<IndexedEntry>,
+    /// In story order
+    pub mentions: Vec<Mention>,
+}
+
+fn sentences(text: &str) -> Vec<String> {
+    text.split_inclusive(['.', '!', '?', '\n'])
+        .map(str::trim)
+        .filter(|s| s.chars().any(char::is_alphanumeric))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Characters, locations and items, with lorebook aliases for the ones the
+/// lorebook also describes, then the rest of the lorebook
+fn entities(export: &StoryExport) -> Vec<Entity> {
+    let lore_aliases = |name: &str| -> Vec<String> {
+        export
+            .lorebook_entries
+            .iter()
+            .filter(|l| l.name.eq_ignore_ascii_case(name))
+            .flat_map(|l| l.aliases.iter().cloned())
+            .collect()
+    };
+    let mut entities: Vec<Entity> = Vec::new();
+    let named = export
+        .characters
+        .iter()
+        .map(|c| (&c.id, &c.name, EntityKind::Character))
+        .chain(
+            export
+                .locations
+                .iter()
+                .map(|l| (&l.id, &l.name, EntityKind::Location)),
+        )
+        .chain(
+            export
+                .items
+                .iter()
+                .map(|i| (&i.id, &i.name, EntityKind::Item)),
+        );
+    for (id, name, kind) in named {
+        entities.push(Entity {
+            id: id.clone(),
+            kind,
+            name: name.clone(),
+            aliases: lore_aliases(name),
+        });
+    }
+    for entry in &export.lorebook_entries {
+        if entities
+            .iter()
+            .any(|e| e.name.eq_ignore_ascii_case(&entry.name))
+        {
+            continue;
+        }
+        entities.push(Entity {
+            id: entry.id.clone(),
+            kind: EntityKind::Lore,
+            name: entry.name.clone(),
+            aliases: entry.aliases.clone(),
+        });
+    }
+    entities
+}
+
+impl EntityIndex {
+    pub fn build(export: &StoryExport) -> Self {
+        let entities = entities(export);
+
+        let mut main: Vec<_> = export
+            .entries
+            .iter()
+            .filter(|e| e.branch_id.is_none() && e.entry_type != "system")
+            .collect();
+        main.sort_by_key(|e| e.position);
+        let position: HashMap<&str, i64> =
+            main.iter().map(|e| (e.id.as_str(), e.position)).collect();
+        let chapters: Vec<(i64, i64, i64)> = export
+            .chapters
+            .iter()
+            .filter_map(|c| {
+                let start = *position.get(c.start_entry_id.as_str())?;
+                let end = *position.get(c.end_entry_id.as_str())?;
+                Some((start, end, c.number))
+            })
+            .collect();
+        let entries: Vec<IndexedEntry> = main
+            .iter()
+            .map(|e| IndexedEntry {
+                entry_id: e.id.clone(),
+                chapter: chapters
+                    .iter()
+                    .find(|(start, end, _)| (*start..=*end).contains(&e.position))
+                    .map(|(_, _, number)| *number),
+                sentences: sentences(&plain_text(&e.content)),
+            })
+            .collect();
+
+        // Longest names first, so "Mira Vance" is matched before "Mira"
+        let mut names: Vec<(String, usize)> = Vec::new();
+        for (index, entity) in entities.iter().enumerate() {
+            for name in entity.names() {
+                let name = name.trim().to_lowercase();
+                if !name.is_empty() && !names.iter().any(|(n, _)| *n == name) {
+                    names.push((name, index));
+                }
+            }
+        }
+        names.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        let mut mentions = Vec::new();
+        if let Some(pattern) = name_pattern(names.iter().map(|(n, _)| n.as_str())) {
+            let by_name: HashMap<&str, usize> =
+                names.iter().map(|(n, i)| (n.as_str(), *i)).collect();
+            for (entry, indexed) in entries.iter().enumerate() {
+                for (sentence, text) in indexed.sentences.iter().enumerate() {
+                    let mut seen = Vec::new();
+                    for found in pattern.find_iter(text) {
+                        let Some(&entity) = by_name.get(found.as_str().to_lowercase().as_str())
+                        else {
+                            continue;
+                        };
+                        if !seen.contains(&entity) {
+                            seen.push(entity);
+                            mentions.push(Mention {
+                                entity,
+                                entry,
+                                sentence,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        EntityIndex {
+            entities,
+            entries,
+            mentions,
+        }
+    }
+
+    /// Entities named in each sentence that names any, in story order
+    pub fn sentences_with_mentions(&self) -> Vec<(usize, usize, Vec<usize>)> {
+        let mut grouped: Vec<(usize, usize, Vec<usize>)> = Vec::new();
+        for mention in &self.mentions {
+            match grouped.last_mut() {
+                Some((entry, sentence, entities))
+                    if *entry == mention.entry && *sentence == mention.sentence =>
+                {
+                    entities.push(mention.entity);
+                }
+                _ => grouped.push((mention.entry, mention.sentence, vec![mention.entity])),
+            }
+        }
+        grouped
+    }
+}
+
+/// Case-insensitive whole-word match of any of the names
+fn name_pattern<'a>(names: impl Iterator<Item = &'a str>) -> Option<Regex> {
+    let alternatives: Vec<String> = names.map(regex::escape).collect();
+    if alternatives.is_empty() {
+        return None;
+    }
+    RegexBuilder::new(&format!(r"\b(?:{})\b", alternatives.join("|")))
+        .case_insensitive(true)
+        .build()
+        .ok()
+}
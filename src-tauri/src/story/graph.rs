@@ -0,0 +1,306 @@
+//! The branch graph of a story: entries as nodes, linked to the entry that
+//! follows them and to the first entry of each branch forking off them. The
+//! graph is rebuilt from the story's rows when requested and stored in the
+//! `story_graph_nodes` and `story_graph_edges` tables.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tauri::AppHandle;
+
+use super::text::plain_text;
+use super::{rows, StoryExport};
+use crate::profiles::{self, database};
+
+/// Length of the text excerpt included with each node
+const EXCERPT_CHARS: usize = 120;
+
+/// An entry in the story graph
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphNode {
+    pub id: String,
+    pub branch_id: Option<String>,
+    pub entry_type: String,
+    pub position: i64,
+    pub excerpt: String,
+}
+
+/// How one entry leads to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EdgeKind {
+    /// The following entry on the same branch
+    Next,
+    /// The first entry of a branch forking off here
+    Choice,
+}
+
+impl EdgeKind {
+    fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Next => "next",
+            EdgeKind::Choice => "choice",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: EdgeKind,
+    /// Branch name for choice edges
+    pub label: Option<String>,
+}
+
+/// Problems found while analysing the graph
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphIssues {
+    /// Entry IDs forming each detected cycle
+    pub cycles: Vec<Vec<String>>,
+    /// Entries that cannot be reached from the start of the story
+    pub unreachable: Vec<String>,
+    /// Branches whose fork entry does not exist
+    pub orphaned_branches: Vec<String>,
+    /// Branches that contain no entries
+    pub empty_branches: Vec<String>,
+}
+
+/// Explicit graph of a branching story
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryGraph {
+    pub story_id: String,
+    /// First entry of the main branch
+    pub root: Option<String>,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    pub issues: GraphIssues,
+}
+
+impl StoryGraph {
+    /// Build the graph from an export: entries on a branch are chained in
+    /// position order, and each branch hangs off its fork entry with a choice edge
+    pub fn build(export: &StoryExport) -> Self {
+        let mut by_branch: HashMap<Option<&str>, Vec<_>> = HashMap::new();
+        for entry in &export.entries {
+            by_branch
+                .entry(entry.branch_id.as_deref())
+                .or_default()
+                .push(entry);
+        }
+        for entries in by_branch.values_mut() {
+            entries.sort_by_key(|e| e.position);
+        }
+
+        let mut nodes: Vec<GraphNode> = export
+            .entries
+            .iter()
+            .map(|e| GraphNode {
+                id: e.id.clone(),
+                branch_id: e.branch_id.clone(),
+                entry_type: e.entry_type.clone(),
+                position: e.position,
+                excerpt: plain_text(&e.content).chars().take(EXCERPT_CHARS).collect(),
+            })
+            .collect();
+        nodes.sort_by_key(|n| (n.branch_id.is_some(), n.position));
+
+        let mut edges = Vec::new();
+        for entries in by_branch.values() {
+            for pair in entries.windows(2) {
+                edges.push(GraphEdge {
+                    from: pair[0].id.clone(),
+                    to: pair[1].id.clone(),
+                    kind: EdgeKind::Next,
+                    label: None,
+                });
+            }
+        }
+
+        let node_ids: HashSet<&str> = export.entries.iter().map(|e| e.id.as_str()).collect();
+        let mut issues = GraphIssues::default();
+        for branch in &export.branches {
+            let Some(first) = by_branch
+                .get(&Some(branch.id.as_str()))
+                .and_then(|e| e.first())
+            else {
+                issues.empty_branches.push(branch.id.clone());
+                continue;
+            };
+            if !node_ids.contains(branch.fork_entry_id.as_str()) {
+                issues.orphaned_branches.push(branch.id.clone());
+                continue;
+            }
+            edges.push(GraphEdge {
+                from: branch.fork_entry_id.clone(),
+                to: first.id.clone(),
+                kind: EdgeKind::Choice,
+                label: Some(branch.name.clone()),
+            });
+        }
+
+        let root = by_branch
+            .get(&None)
+            .and_then(|e| e.first())
+            .map(|e| e.id.clone());
+
+        let mut graph = StoryGraph {
+            story_id: export.story.id.clone(),
+            root,
+            nodes,
+            edges,
+            issues,
+        };
+        graph.issues.cycles = graph.find_cycles();
+        graph.issues.unreachable = graph.find_unreachable();
+        graph
+    }
+
+    /// Outgoing edges for every node
+    pub fn adjacency(&self) -> HashMap<&str, Vec<&GraphEdge>> {
+        let mut adjacency: HashMap<&str, Vec<&GraphEdge>> = HashMap::new();
+        for edge in &self.edges {
+            adjacency.entry(edge.from.as_str()).or_default().push(edge);
+        }
+        adjacency
+    }
+
+    fn find_unreachable(&self) -> Vec<String> {
+        let adjacency = self.adjacency();
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut stack: Vec<&str> = self.root.iter().map(|r| r.as_str()).collect();
+        while let Some(id) = stack.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            for edge in adjacency.get(id).into_iter().flatten() {
+                stack.push(edge.to.as_str());
+            }
+        }
+
+        self.nodes
+            .iter()
+            .filter(|n| !seen.contains(n.id.as_str()))
+            .map(|n| n.id.clone())
+            .collect()
+    }
+
+    /// Iterative DFS reporting each back edge as a cycle
+    fn find_cycles(&self) -> Vec<Vec<String>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Unvisited,
+            InProgress,
+            Done,
+        }
+
+        let adjacency = self.adjacency();
+        let mut marks: HashMap<&str, Mark> = self
+            .nodes
+            .iter()
+            .map(|n| (n.id.as_str(), Mark::Unvisited))
+            .collect();
+        let mut cycles = Vec::new();
+
+        for start in &self.nodes {
+            if marks.get(start.id.as_str()) != Some(&Mark::Unvisited) {
+                continue;
+            }
+            // (node, index of next edge to explore)
+            let mut path: Vec<(&str, usize)> = vec![(start.id.as_str(), 0)];
+            marks.insert(start.id.as_str(), Mark::InProgress);
+
+            while let Some((node, next)) = path.last_mut() {
+                let edges = adjacency.get(*node).map(Vec::as_slice).unwrap_or(&[]);
+                if let Some(edge) = edges.get(*next) {
+                    *next += 1;
+                    let target = edge.to.as_str();
+                    match marks.get(target).copied().unwrap_or(Mark::Done) {
+                        Mark::Unvisited => {
+                            marks.insert(target, Mark::InProgress);
+                            path.push((target, 0));
+                        }
+                        Mark::InProgress => {
+                            let from = path.iter().position(|(id, _)| *id == target).unwrap_or(0);
+                            cycles
+                                .push(path[from..].iter().map(|(id, _)| id.to_string()).collect());
+                        }
+                        Mark::Done => {}
+                    }
+                } else {
+                    marks.insert(*node, Mark::Done);
+                    path.pop();
+                }
+            }
+        }
+        cycles
+    }
+}
+
+/// Build a story's graph from its rows and store it in place of the
+/// previous one
+pub async fn refresh(app: &AppHandle, story_id: &str) -> Result<StoryGraph, String> {
+    let export = rows::load(app, story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let graph = StoryGraph::build(&export);
+
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let stored = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        for table in ["story_graph_edges", "story_graph_nodes"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE story_id = ?", table))
+                .bind(story_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to clear story graph: {}", e))?;
+        }
+        let unreachable: HashSet<&str> = graph
+            .issues
+            .unreachable
+            .iter()
+            .map(String::as_str)
+            .collect();
+        for node in &graph.nodes {
+            sqlx::query(
+                "INSERT INTO story_graph_nodes (story_id, entry_id, branch_id, position, reachable) \
+                 VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(story_id)
+            .bind(&node.id)
+            .bind(&node.branch_id)
+            .bind(node.position)
+            .bind(!unreachable.contains(node.id.as_str()))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to store story graph: {}", e))?;
+        }
+        for edge in &graph.edges {
+            sqlx::query(
+                "INSERT OR IGNORE INTO story_graph_edges \
+                 (story_id, from_entry_id, to_entry_id, kind, label) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(story_id)
+            .bind(&edge.from)
+            .bind(&edge.to)
+            .bind(edge.kind.name())
+            .bind(&edge.label)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to store story graph: {}", e))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to store story graph: {}", e))
+    }
+    .await;
+    pool.close().await;
+    stored?;
+    Ok(graph)
+}
@@ -0,0 +1,778 @@
+//! Guided story setup. An interview asks for the setting, the protagonist
+//! and the tone one question at a time, with suggestions for the chosen
+//! genre. When it was started with a provider, the model fleshes out the
+//! answers and writes the opening; otherwise the answers are used as given.
+//! The finished interview is a new story in Aventura export format, seeded
+//! with lorebook entries, and an AI profile for it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::ai::overflow::OverflowRules;
+use crate::ai::profile::AiProfile;
+use crate::ai::proxy::{complete_structured, json_object};
+use crate::ai::types::{ChatMessage, ProviderConfig, SamplingParams};
+use crate::lorebook::{EntryInjection, LorebookEntryData};
+use crate::sync::keys::now_ms;
+
+/// Export format version written into the finished story
+const EXPORT_VERSION: &str = "1.7.0";
+
+/// Interviews left unanswered this long are dropped
+const INTERVIEW_TTL_MS: i64 = 60 * 60 * 1000;
+
+/// Longest answer accepted, in characters
+const MAX_ANSWER_CHARS: usize = 2_000;
+
+/// Locations the model may add to the lorebook from the setting
+const MAX_LOCATIONS: usize = 4;
+
+const MAX_TRAITS: usize = 6;
+
+/// Temperature used when the tone is not one of the suggestions
+const DEFAULT_TEMPERATURE: f32 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InterviewStep {
+    Setting,
+    ProtagonistName,
+    Protagonist,
+    Tone,
+}
+
+impl InterviewStep {
+    fn next(self) -> Option<InterviewStep> {
+        match self {
+            InterviewStep::Setting => Some(InterviewStep::ProtagonistName),
+            InterviewStep::ProtagonistName => Some(InterviewStep::Protagonist),
+            InterviewStep::Protagonist => Some(InterviewStep::Tone),
+            InterviewStep::Tone => None,
+        }
+    }
+}
+
+/// Suggestions and wording for one genre
+struct GenrePreset {
+    key: &'static str,
+    name: &'static str,
+    settings: &'static [&'static str],
+    protagonists: &'static [&'static str],
+    /// Tones with the temperature each is generated at
+    tones: &'static [(&'static str, f32)],
+}
+
+const GENRES: &[GenrePreset] = &[
+    GenrePreset {
+        key: "fantasy",
+        name: "fantasy",
+        settings: &[
+            "A walled city at the edge of a haunted forest",
+            "A sky archipelago linked by airships",
+            "A dying empire where magic is outlawed",
+        ],
+        protagonists: &[
+            "A disgraced knight looking for redemption",
+            "An apprentice mage who broke their master's staff",
+            "A smuggler with a map nobody should have",
+        ],
+        tones: &[("epic", 0.9), ("whimsical", 1.0), ("grim", 0.8)],
+    },
+    GenrePreset {
+        key: "sci-fi",
+        name: "science fiction",
+        settings: &[
+            "A generation ship three centuries into its voyage",
+            "A mining colony on a tidally locked moon",
+            "A megacity ruled by competing corporations",
+        ],
+        protagonists: &[
+            "A salvage pilot in debt to the wrong people",
+            "An android who has started to dream",
+            "A diplomat sent to a species nobody understands",
+        ],
+        tones: &[("hopeful", 0.9), ("hard and technical", 0.7), ("noir", 0.8)],
+    },
+    GenrePreset {
+        key: "mystery",
+        name: "mystery",
+        settings: &[
+            "A snowed-in country house in the 1920s",
+            "A small coastal town where everyone has a secret",
+            "A rain-soaked city precinct",
+        ],
+        protagonists: &[
+            "A retired detective pulled back for one last case",
+            "A journalist who got too close to a story",
+            "A guest who was the last to see the victim alive",
+        ],
+        tones: &[("cozy", 0.9), ("hardboiled", 0.8), ("tense", 0.8)],
+    },
+    GenrePreset {
+        key: "horror",
+        name: "horror",
+        settings: &[
+            "A farmhouse miles from the nearest neighbour",
+            "An abandoned hospital scheduled for demolition",
+            "A village that holds a festival no outsider may see",
+        ],
+        protagonists: &[
+            "A caretaker on their first night shift",
+            "A folklorist chasing a local legend",
+            "A child who can see what the adults cannot",
+        ],
+        tones: &[("creeping dread", 0.8), ("visceral", 0.9), ("gothic", 0.9)],
+    },
+    GenrePreset {
+        key: "romance",
+        name: "romance",
+        settings: &[
+            "A family vineyard that is about to be sold",
+            "A royal court during a season of balls",
+            "A small bookshop in a busy city",
+        ],
+        protagonists: &[
+            "A chef who just inherited a failing restaurant",
+            "A spy whose cover is an arranged engagement",
+            "A musician back in their hometown for the summer",
+        ],
+        tones: &[("sweet", 1.0), ("dramatic", 0.9), ("slow burn", 0.9)],
+    },
+];
+
+/// Preset for genres without one of their own
+const OTHER_GENRE: GenrePreset = GenrePreset {
+    key: "",
+    name: "",
+    settings: &[],
+    protagonists: &[],
+    tones: &[
+        ("lighthearted", 1.0),
+        ("serious", 0.8),
+        ("dark", 0.8),
+        ("adventurous", 0.9),
+    ],
+};
+
+fn preset(genre: &str) -> &'static GenrePreset {
+    let key = genre.trim().to_lowercase();
+    GENRES
+        .iter()
+        .find(|p| p.key == key || p.name == key)
+        .unwrap_or(&OTHER_GENRE)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterviewQuestion {
+    pub step: InterviewStep,
+    pub prompt: String,
+    pub suggestions: Vec<String>,
+}
+
+/// A place the setting names, seeded into the lorebook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedLocation {
+    pub name: String,
+    pub description: String,
+}
+
+/// The answers so far, with whatever the model added to them
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterviewAnswers {
+    pub setting: Option<String>,
+    pub locations: Vec<SeedLocation>,
+    pub protagonist_name: Option<String>,
+    pub protagonist: Option<String>,
+    pub traits: Vec<String>,
+    pub tone: Option<String>,
+}
+
+pub struct Interview {
+    id: String,
+    genre: String,
+    provider: Option<ProviderConfig>,
+    step: InterviewStep,
+    answers: InterviewAnswers,
+    updated_at: i64,
+}
+
+/// Interviews in progress, by ID
+pub type Interviews = HashMap<String, Interview>;
+
+/// The new story an interview produced
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterviewResult {
+    /// The story in Aventura export format, ready to import
+    pub story_json: String,
+    /// The story's system prompt and temperature, when the interview had a
+    /// provider. Its story ID is the export's, so it should be saved under
+    /// the ID the story gets on import.
+    pub ai_profile: Option<AiProfile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum InterviewProgress {
+    #[serde(rename_all = "camelCase")]
+    Question {
+        interview_id: String,
+        question: InterviewQuestion,
+        answers: InterviewAnswers,
+        /// Why the model could not enrich the last answer, which was kept as given
+        notice: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Complete {
+        interview_id: String,
+        result: InterviewResult,
+        notice: Option<String>,
+    },
+}
+
+/// Drop interviews nobody has answered for a while
+pub fn prune(interviews: &mut Interviews) {
+    let now = now_ms();
+    interviews.retain(|_, i| now - i.updated_at < INTERVIEW_TTL_MS);
+}
+
+impl Interview {
+    pub fn new(genre: &str, provider: Option<ProviderConfig>) -> Result<Self, String> {
+        let genre = genre.trim();
+        if genre.is_empty() {
+            return Err("Choose a genre to start the interview".to_string());
+        }
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            genre: genre.to_string(),
+            provider,
+            step: InterviewStep::Setting,
+            answers: InterviewAnswers::default(),
+            updated_at: now_ms(),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn genre_name(&self) -> &str {
+        match preset(&self.genre).name {
+            "" => &self.genre,
+            name => name,
+        }
+    }
+
+    fn protagonist_name(&self) -> &str {
+        self.answers
+            .protagonist_name
+            .as_deref()
+            .unwrap_or("the protagonist")
+    }
+
+    pub fn question(&self) -> InterviewQuestion {
+        let preset = preset(&self.genre);
+        let (prompt, suggestions): (String, &[&str]) = match self.step {
+            InterviewStep::Setting => (
+                format!(
+                    "Where and when does your {} story take place?",
+                    self.genre_name()
+                ),
+                preset.settings,
+            ),
+            InterviewStep::ProtagonistName => ("What is the protagonist called?".to_string(), &[]),
+            InterviewStep::Protagonist => (
+                format!(
+                    "Who is {}? Describe them in a sentence or two.",
+                    self.protagonist_name()
+                ),
+                preset.protagonists,
+            ),
+            InterviewStep::Tone => ("What tone should the story have?".to_string(), &[]),
+        };
+        let suggestions = match self.step {
+            InterviewStep::Tone => preset.tones.iter().map(|(t, _)| t.to_string()).collect(),
+            _ => suggestions.iter().map(|s| s.to_string()).collect(),
+        };
+        InterviewQuestion {
+            step: self.step,
+            prompt,
+            suggestions,
+        }
+    }
+
+    pub fn progress(&self, notice: Option<String>) -> InterviewProgress {
+        InterviewProgress::Question {
+            interview_id: self.id.clone(),
+            question: self.question(),
+            answers: self.answers.clone(),
+            notice,
+        }
+    }
+
+    /// Record the answer to the current question and move on. Returns the
+    /// next question, or the new story after the last one.
+    pub async fn answer(&mut self, answer: &str) -> Result<InterviewProgress, String> {
+        let answer = answer.split_whitespace().collect::<Vec<_>>().join(" ");
+        if answer.is_empty() {
+            return Err("Answer the question to continue".to_string());
+        }
+        if answer.chars().count() > MAX_ANSWER_CHARS {
+            return Err(format!(
+                "Answers can be at most {} characters",
+                MAX_ANSWER_CHARS
+            ));
+        }
+        self.updated_at = now_ms();
+
+        let mut notice = None;
+        match self.step {
+            InterviewStep::Setting => {
+                self.answers.setting = Some(answer.clone());
+                if let Some(provider) = &self.provider {
+                    match enrich_setting(provider, self.genre_name(), &answer).await {
+                        Ok((setting, locations)) => {
+                            self.answers.setting = Some(setting);
+                            self.answers.locations = locations;
+                        }
+                        Err(e) => notice = Some(e),
+                    }
+                }
+            }
+            InterviewStep::ProtagonistName => self.answers.protagonist_name = Some(answer),
+            InterviewStep::Protagonist => {
+                self.answers.protagonist = Some(answer.clone());
+                if let Some(provider) = &self.provider {
+                    match enrich_protagonist(provider, self, &answer).await {
+                        Ok((description, traits)) => {
+                            self.answers.protagonist = Some(description);
+                            self.answers.traits = traits;
+                        }
+                        Err(e) => notice = Some(e),
+                    }
+                }
+            }
+            InterviewStep::Tone => self.answers.tone = Some(answer),
+        }
+
+        match self.step.next() {
+            Some(step) => {
+                self.step = step;
+                Ok(self.progress(notice))
+            }
+            None => {
+                let (result, finish_notice) = self.finish().await?;
+                Ok(InterviewProgress::Complete {
+                    interview_id: self.id.clone(),
+                    result,
+                    notice: notice.or(finish_notice),
+                })
+            }
+        }
+    }
+
+    fn system_prompt(&self) -> String {
+        let name = self.protagonist_name();
+        let mut prompt = format!(
+            "You are the narrator of an interactive {} story.\n\nSetting: {}\n\n\
+             The protagonist is {}: {}",
+            self.genre_name(),
+            self.answers.setting.as_deref().unwrap_or_default(),
+            name,
+            self.answers.protagonist.as_deref().unwrap_or_default(),
+        );
+        if !self.answers.traits.is_empty() {
+            prompt.push_str(&format!(" ({})", self.answers.traits.join(", ")));
+        }
+        prompt.push_str(&format!(
+            "\n\nKeep the tone {}. Write in the second person and present tense, \
+             and end each reply at a moment where {} can act.",
+            self.answers.tone.as_deref().unwrap_or("consistent"),
+            name
+        ));
+        prompt
+    }
+
+    fn temperature(&self) -> f32 {
+        let tone = self
+            .answers
+            .tone
+            .as_deref()
+            .unwrap_or_default()
+            .to_lowercase();
+        preset(&self.genre)
+            .tones
+            .iter()
+            .chain(OTHER_GENRE.tones)
+            .find(|(t, _)| *t == tone)
+            .map_or(DEFAULT_TEMPERATURE, |(_, temperature)| *temperature)
+    }
+
+    /// The opening title and scene, from the model when it can write them
+    async fn opening(&self) -> (String, String, Option<String>) {
+        let name = self.protagonist_name();
+        let title = format!("{}'s Story", name);
+        let opening = format!(
+            "{}\n\nThis is where {}'s story begins.",
+            self.answers.setting.as_deref().unwrap_or_default(),
+            name
+        );
+        let Some(provider) = &self.provider else {
+            return (title, opening, None);
+        };
+        match write_opening(provider, self).await {
+            Ok((title, opening)) => (title, opening, None),
+            Err(e) => (title, opening, Some(e)),
+        }
+    }
+
+    fn lorebook_seeds(&self) -> Vec<LorebookEntryData> {
+        let seed =
+            |name: &str, entry_type: &str, description: &str, mode: &str| LorebookEntryData {
+                name: name.to_string(),
+                entry_type: entry_type.to_string(),
+                description: description.to_string(),
+                hidden_info: None,
+                aliases: Vec::new(),
+                state: None,
+                injection: EntryInjection {
+                    mode: mode.to_string(),
+                    keywords: vec![name.to_string()],
+                    priority: None,
+                },
+            };
+        let mut seeds = vec![
+            seed(
+                self.protagonist_name(),
+                "character",
+                self.answers.protagonist.as_deref().unwrap_or_default(),
+                "always",
+            ),
+            seed(
+                "The setting",
+                "concept",
+                self.answers.setting.as_deref().unwrap_or_default(),
+                "always",
+            ),
+        ];
+        seeds.extend(
+            self.answers
+                .locations
+                .iter()
+                .map(|l| seed(&l.name, "location", &l.description, "keyword")),
+        );
+        seeds
+    }
+
+    async fn finish(&self) -> Result<(InterviewResult, Option<String>), String> {
+        let (title, opening, notice) = self.opening().await;
+        let story_id = Uuid::new_v4().to_string();
+        let now = now_ms();
+        let tone = self.answers.tone.clone().unwrap_or_default();
+
+        let locations: Vec<Value> = self
+            .answers
+            .locations
+            .iter()
+            .enumerate()
+            .map(|(i, location)| {
+                json!({
+                    "id": Uuid::new_v4().to_string(),
+                    "storyId": story_id,
+                    "name": location.name,
+                    "description": location.description,
+                    "visited": i == 0,
+                    "current": i == 0,
+                    "connections": [],
+                    "metadata": null,
+                    "branchId": null,
+                })
+            })
+            .collect();
+        let lorebook_entries: Vec<Value> = self
+            .lorebook_seeds()
+            .into_iter()
+            .map(|entry| {
+                json!({
+                    "id": Uuid::new_v4().to_string(),
+                    "storyId": story_id,
+                    "name": entry.name,
+                    "type": entry.entry_type,
+                    "description": entry.description,
+                    "hiddenInfo": null,
+                    "aliases": entry.aliases,
+                    "state": { "type": entry.entry_type },
+                    "adventureState": null,
+                    "creativeState": null,
+                    "injection": {
+                        "mode": entry.injection.mode,
+                        "keywords": entry.injection.keywords,
+                        "priority": 0,
+                    },
+                    "firstMentioned": null,
+                    "lastMentioned": null,
+                    "mentionCount": 0,
+                    "createdBy": "import",
+                    "createdAt": now,
+                    "updatedAt": now,
+                    "loreManagementBlacklisted": false,
+                    "branchId": null,
+                })
+            })
+            .collect();
+
+        let export = json!({
+            "version": EXPORT_VERSION,
+            "exportedAt": now,
+            "story": {
+                "id": story_id,
+                "title": title,
+                "description": self.answers.setting,
+                "genre": self.genre,
+                "templateId": null,
+                "mode": "adventure",
+                "createdAt": now,
+                "updatedAt": now,
+                "settings": { "tone": tone, "pov": "second", "tense": "present" },
+                "memoryConfig": null,
+                "timeTracker": null,
+            },
+            "entries": [{
+                "id": Uuid::new_v4().to_string(),
+                "storyId": story_id,
+                "type": "narration",
+                "content": opening,
+                "parentId": null,
+                "position": 0,
+                "createdAt": now,
+                "metadata": { "source": "interview" },
+                "branchId": null,
+            }],
+            "characters": [{
+                "id": Uuid::new_v4().to_string(),
+                "storyId": story_id,
+                "name": self.protagonist_name(),
+                "description": self.answers.protagonist,
+                "relationship": "self",
+                "traits": self.answers.traits,
+                "visualDescriptors": [],
+                "portrait": null,
+                "status": "active",
+                "metadata": null,
+                "branchId": null,
+            }],
+            "locations": locations,
+            "items": [],
+            "storyBeats": [],
+            "lorebookEntries": lorebook_entries,
+        });
+        let story_json = serde_json::to_string(&export)
+            .map_err(|e| format!("Failed to serialize story: {}", e))?;
+
+        let ai_profile = self.provider.as_ref().map(|provider| AiProfile {
+            story_id: story_id.clone(),
+            name: title.clone(),
+            provider: provider.clone(),
+            temperature: Some(self.temperature()),
+            system_prompt: self.system_prompt(),
+            context_strategy: None,
+            sampling_preset: None,
+            style_reference: None,
+            overflow: OverflowRules::default(),
+            updated_at: now,
+        });
+        Ok((
+            InterviewResult {
+                story_json,
+                ai_profile,
+            },
+            notice,
+        ))
+    }
+}
+
+/// Send one request for a JSON object matching `schema`
+async fn ask(
+    provider: &ProviderConfig,
+    system: String,
+    user: String,
+    name: &str,
+    schema: Value,
+) -> Result<Value, String> {
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: system,
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: user,
+        },
+    ];
+    let sampling = SamplingParams {
+        temperature: Some(0.9),
+        ..Default::default()
+    };
+    let reply = complete_structured(provider, &messages, &sampling, name, &schema).await?;
+    let json = json_object(&reply).ok_or("Interview response contained no JSON")?;
+    serde_json::from_str(json).map_err(|e| format!("Invalid interview response: {}", e))
+}
+
+fn text_field(value: &Value, field: &str) -> Option<String> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|s| !s.is_empty())
+}
+
+/// A fuller setting and the places in it
+async fn enrich_setting(
+    provider: &ProviderConfig,
+    genre: &str,
+    answer: &str,
+) -> Result<(String, Vec<SeedLocation>), String> {
+    let location = json!({
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "description": { "type": "string" },
+        },
+        "required": ["name", "description"],
+        "additionalProperties": false,
+    });
+    let reply = ask(
+        provider,
+        format!(
+            "You help set up interactive {} stories. Expand the user's setting into \
+             three or four vivid sentences that keep everything they said. Then name \
+             up to {} places in it where the story could go, each with one sentence. \
+             Reply with JSON only, in the form {{\"setting\": string, \"locations\": \
+             [{{\"name\": string, \"description\": string}}]}}.",
+            genre, MAX_LOCATIONS
+        ),
+        answer.to_string(),
+        "story_setting",
+        json!({
+            "type": "object",
+            "properties": {
+                "setting": { "type": "string" },
+                "locations": { "type": "array", "items": location },
+            },
+            "required": ["setting", "locations"],
+            "additionalProperties": false,
+        }),
+    )
+    .await?;
+    let setting = text_field(&reply, "setting").ok_or("The model did not describe the setting")?;
+    let locations = reply
+        .get("locations")
+        .and_then(Value::as_array)
+        .map(|list| {
+            list.iter()
+                .filter_map(|l| {
+                    Some(SeedLocation {
+                        name: text_field(l, "name")?,
+                        description: text_field(l, "description").unwrap_or_default(),
+                    })
+                })
+                .take(MAX_LOCATIONS)
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok((setting, locations))
+}
+
+/// A fuller description of the protagonist and their traits
+async fn enrich_protagonist(
+    provider: &ProviderConfig,
+    interview: &Interview,
+    answer: &str,
+) -> Result<(String, Vec<String>), String> {
+    let reply = ask(
+        provider,
+        format!(
+            "You help set up interactive {} stories. The setting is: {}\n\nExpand the \
+             user's description of the protagonist, {}, into two or three sentences \
+             that keep everything they said, and list up to {} short personality \
+             traits. Reply with JSON only, in the form {{\"description\": string, \
+             \"traits\": [string]}}.",
+            interview.genre_name(),
+            interview.answers.setting.as_deref().unwrap_or_default(),
+            interview.protagonist_name(),
+            MAX_TRAITS
+        ),
+        answer.to_string(),
+        "story_protagonist",
+        json!({
+            "type": "object",
+            "properties": {
+                "description": { "type": "string" },
+                "traits": { "type": "array", "items": { "type": "string" } },
+            },
+            "required": ["description", "traits"],
+            "additionalProperties": false,
+        }),
+    )
+    .await?;
+    let description =
+        text_field(&reply, "description").ok_or("The model did not describe the protagonist")?;
+    let mut traits: Vec<String> = Vec::new();
+    for t in reply
+        .get("traits")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        let t = t.trim().to_lowercase();
+        if !t.is_empty() && !traits.contains(&t) && traits.len() < MAX_TRAITS {
+            traits.push(t);
+        }
+    }
+    Ok((description, traits))
+}
+
+/// A title and opening scene for the finished interview
+async fn write_opening(
+    provider: &ProviderConfig,
+    interview: &Interview,
+) -> Result<(String, String), String> {
+    let reply = ask(
+        provider,
+        format!(
+            "{}\n\nWrite the story's title, a few words long, and its opening scene in \
+             two or three paragraphs. Reply with JSON only, in the form \
+             {{\"title\": string, \"opening\": string}}.",
+            interview.system_prompt()
+        ),
+        "Begin the story.".to_string(),
+        "story_opening",
+        json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string" },
+                "opening": { "type": "string" },
+            },
+            "required": ["title", "opening"],
+            "additionalProperties": false,
+        }),
+    )
+    .await?;
+    let title = text_field(&reply, "title")
+        .map(|t| t.trim_matches('"').to_string())
+        .ok_or("The model did not title the story")?;
+    // Paragraph breaks are kept in the opening
+    let opening = reply
+        .get("opening")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .ok_or("The model did not write an opening")?;
+    Ok((title, opening.to_string()))
+}
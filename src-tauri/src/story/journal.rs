@@ -0,0 +1,288 @@
+//! Undo journal for destructive operations. Before a story is deleted,
+//! merged or overwritten by an import, every row it has in the frontend's
+//! database is packed into a compressed snapshot, the same way an archive
+//! is. Undoing puts those rows back, removing whatever replaced them.
+//! Operations can be undone for a configurable time, newest first.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, Transaction};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use super::archive::{self, ArchivedRows};
+use crate::profiles::{self, database};
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Directory in the app data directory holding operation snapshots
+pub const JOURNAL_DIR: &str = "undo_journal";
+
+/// Index of recorded operations, in the app data directory
+const JOURNAL_FILE: &str = "undo_journal.json";
+
+pub const UNDO_CONFIG_FILE: &str = "undo_config.json";
+
+/// Snapshots are written on every destructive operation, so speed matters
+/// more than size
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Operations kept at most, however recent
+const MAX_OPERATIONS: usize = 50;
+
+const DEFAULT_WINDOW_MINUTES: u64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UndoConfig {
+    /// How long an operation can be undone
+    pub window_minutes: u64,
+}
+
+impl Default for UndoConfig {
+    fn default() -> Self {
+        Self {
+            window_minutes: DEFAULT_WINDOW_MINUTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OperationKind {
+    Delete,
+    Merge,
+    /// An import replaced stories that were already in the library
+    ImportOverwrite,
+}
+
+/// A recorded operation that can still be undone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoableOperation {
+    pub id: String,
+    pub kind: OperationKind,
+    pub story_ids: Vec<String>,
+    /// Titles of the stories as they were, for showing what undo brings back
+    pub titles: Vec<String>,
+    pub created_at: i64,
+    /// Set from the current undo window when listed
+    #[serde(default)]
+    pub expires_at: i64,
+}
+
+/// One story's rows before the operation. A story that was not in the
+/// database has no rows and is removed again on undo.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StorySnapshot {
+    story_id: String,
+    rows: ArchivedRows,
+}
+
+fn snapshot_path(app: &AppHandle, operation_id: &str) -> Result<PathBuf, String> {
+    if operation_id.is_empty() || operation_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid operation ID: {}", operation_id));
+    }
+    let dir = store::data_file(app, JOURNAL_DIR)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create journal directory: {}", e))?;
+    Ok(dir.join(format!("{}.json.zst", operation_id)))
+}
+
+fn window_ms(app: &AppHandle) -> Result<i64, String> {
+    let config: UndoConfig = store::load_json(app, UNDO_CONFIG_FILE)?;
+    Ok(config
+        .window_minutes
+        .saturating_mul(60_000)
+        .min(i64::MAX as u64) as i64)
+}
+
+/// Drop operations past the undo window or beyond `MAX_OPERATIONS`, with
+/// their snapshots. Returns the rest, oldest first.
+fn prune(app: &AppHandle) -> Result<Vec<UndoableOperation>, String> {
+    let mut journal: Vec<UndoableOperation> = store::load_json(app, JOURNAL_FILE)?;
+    let window = window_ms(app)?;
+    let now = now_ms();
+    let before = journal.len();
+    let mut expired = Vec::new();
+    journal.retain(|op| {
+        let keep = op.created_at.saturating_add(window) > now;
+        if !keep {
+            expired.push(op.id.clone());
+        }
+        keep
+    });
+    let excess = journal.len().saturating_sub(MAX_OPERATIONS);
+    expired.extend(journal.drain(..excess).map(|op| op.id));
+    for id in &expired {
+        if let Ok(path) = snapshot_path(app, id) {
+            let _ = fs::remove_file(path);
+        }
+    }
+    if journal.len() != before {
+        store::save_json(app, JOURNAL_FILE, &journal)?;
+    }
+    for op in &mut journal {
+        op.expires_at = op.created_at.saturating_add(window);
+    }
+    Ok(journal)
+}
+
+/// Operations that can still be undone, newest first
+pub fn list(app: &AppHandle) -> Result<Vec<UndoableOperation>, String> {
+    let mut journal = prune(app)?;
+    journal.reverse();
+    Ok(journal)
+}
+
+async fn read_snapshots(
+    tx: &mut Transaction<'_, Sqlite>,
+    story_ids: &[&str],
+) -> Result<Vec<StorySnapshot>, String> {
+    let mut snapshots = Vec::with_capacity(story_ids.len());
+    for story_id in story_ids {
+        snapshots.push(StorySnapshot {
+            story_id: story_id.to_string(),
+            rows: archive::read_rows(tx, story_id).await?,
+        });
+    }
+    Ok(snapshots)
+}
+
+/// Write the snapshots and add the operation to the journal
+fn save(
+    app: &AppHandle,
+    kind: OperationKind,
+    snapshots: &[StorySnapshot],
+) -> Result<UndoableOperation, String> {
+    let created_at = now_ms();
+    let operation = UndoableOperation {
+        id: format!(
+            "{}-{}",
+            created_at,
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        ),
+        kind,
+        story_ids: snapshots.iter().map(|s| s.story_id.clone()).collect(),
+        titles: snapshots
+            .iter()
+            .filter_map(|s| s.rows.text("title"))
+            .collect(),
+        created_at,
+        expires_at: created_at.saturating_add(window_ms(app)?),
+    };
+    let json = serde_json::to_vec(snapshots)
+        .map_err(|e| format!("Failed to serialize snapshot: {}", e))?;
+    let compressed = zstd::encode_all(json.as_slice(), COMPRESSION_LEVEL)
+        .map_err(|e| format!("Failed to compress snapshot: {}", e))?;
+    let path = snapshot_path(app, &operation.id)?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, compressed).map_err(|e| format!("Failed to write snapshot: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to save snapshot: {}", e))?;
+
+    let mut journal = prune(app)?;
+    journal.push(operation.clone());
+    store::save_json(app, JOURNAL_FILE, &journal)?;
+    Ok(operation)
+}
+
+/// Snapshot the stories' rows and add the operation to the journal. Call
+/// before the operation changes anything.
+pub async fn record(
+    app: &AppHandle,
+    kind: OperationKind,
+    story_ids: &[&str],
+) -> Result<UndoableOperation, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let read = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        read_snapshots(&mut tx, story_ids).await
+    }
+    .await;
+    pool.close().await;
+    let snapshots = read?;
+    if let Some(missing) = snapshots.iter().find(|s| s.rows.row_count("stories") == 0) {
+        return Err(format!("Story not found: {}", missing.story_id));
+    }
+    save(app, kind, &snapshots)
+}
+
+/// Remove stories' rows from the database in one transaction, recording
+/// them first so the whole delete can be undone at once
+pub async fn delete(app: &AppHandle, story_ids: &[&str]) -> Result<UndoableOperation, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let removed = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        let snapshots = read_snapshots(&mut tx, story_ids).await?;
+        if let Some(missing) = snapshots.iter().find(|s| s.rows.row_count("stories") == 0) {
+            return Err(format!("Story not found: {}", missing.story_id));
+        }
+        let operation = save(app, OperationKind::Delete, &snapshots)?;
+        sqlx::query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to prepare database: {}", e))?;
+        for story_id in story_ids {
+            archive::delete_rows(&mut tx, story_id).await?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to delete story: {}", e))?;
+        Ok(operation)
+    }
+    .await;
+    pool.close().await;
+    removed
+}
+
+/// The newest operation that can still be undone
+pub fn last(app: &AppHandle) -> Result<Option<UndoableOperation>, String> {
+    Ok(prune(app)?.pop())
+}
+
+/// Put every story the operation touched back as it was before it ran and
+/// drop the operation from the journal
+pub async fn undo(app: &AppHandle, operation: &UndoableOperation) -> Result<(), String> {
+    let path = snapshot_path(app, &operation.id)?;
+    let compressed = fs::read(&path).map_err(|e| format!("Failed to read snapshot: {}", e))?;
+    let json = zstd::decode_all(compressed.as_slice())
+        .map_err(|e| format!("Failed to decompress snapshot: {}", e))?;
+    let snapshots: Vec<StorySnapshot> =
+        serde_json::from_slice(&json).map_err(|e| format!("Invalid snapshot: {}", e))?;
+
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let restored = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        sqlx::query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to prepare database: {}", e))?;
+        for snapshot in &snapshots {
+            archive::delete_rows(&mut tx, &snapshot.story_id).await?;
+        }
+        for snapshot in &snapshots {
+            archive::insert_rows(&mut tx, &snapshot.rows).await?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to undo operation: {}", e))
+    }
+    .await;
+    pool.close().await;
+    restored?;
+
+    let mut journal: Vec<UndoableOperation> = store::load_json(app, JOURNAL_FILE)?;
+    journal.retain(|op| op.id != operation.id);
+    store::save_json(app, JOURNAL_FILE, &journal)?;
+    let _ = fs::remove_file(path);
+    Ok(())
+}
@@ -0,0 +1,215 @@
+//! Advisory story locks. While a story is being pulled, merged or co-edited,
+//! other writers get a `StoryLocked` error instead of mixing their changes in.
+//! The frontend writes stories through the SQL plugin, so it asks before
+//! each change with `check_story_writable`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::sync::keys::now_ms;
+
+/// Prefix of lock errors, so the frontend can tell them apart from failures
+pub const STORY_LOCKED: &str = "StoryLocked";
+
+/// How often a queued acquire checks whether the lock was released
+const WAIT_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LockReason {
+    Sync,
+    Merge,
+    Collaboration,
+    Edit,
+    Archive,
+    Delete,
+    Undo,
+    Bulk,
+}
+
+impl LockReason {
+    fn describe(self) -> &'static str {
+        match self {
+            LockReason::Sync => "synced",
+            LockReason::Merge => "merged",
+            LockReason::Collaboration => "co-edited in a multiplayer session",
+            LockReason::Edit => "edited elsewhere",
+            LockReason::Archive => "archived or restored",
+            LockReason::Delete => "deleted",
+            LockReason::Undo => "restored to an earlier state",
+            LockReason::Bulk => "updated with other stories",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryLockInfo {
+    pub lock_id: String,
+    pub story_id: String,
+    pub reason: LockReason,
+    pub acquired_at: i64,
+    /// Locks handed to the frontend expire in case it never releases them
+    pub expires_at: Option<i64>,
+}
+
+impl StoryLockInfo {
+    fn expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+/// Error returned when a story is held by another operation
+pub fn locked_error(info: &StoryLockInfo) -> String {
+    format!(
+        "{}: This story is being {}. Try again when that finishes.",
+        STORY_LOCKED,
+        info.reason.describe()
+    )
+}
+
+/// The lock table, shared by every command that writes stories
+#[derive(Clone, Default)]
+pub struct StoryLocks {
+    table: Arc<Mutex<HashMap<String, StoryLockInfo>>>,
+}
+
+/// Releases its lock when dropped
+pub struct StoryLockGuard {
+    locks: StoryLocks,
+    info: StoryLockInfo,
+}
+
+impl StoryLockGuard {
+    /// Keep the lock after the guard is dropped; it must then be released by ID
+    pub fn detach(self) -> StoryLockInfo {
+        let info = self.info.clone();
+        std::mem::forget(self);
+        info
+    }
+}
+
+impl Drop for StoryLockGuard {
+    fn drop(&mut self) {
+        self.locks.release(&self.info.lock_id);
+    }
+}
+
+impl StoryLocks {
+    /// Lock a story, failing with a `StoryLocked` error if it is already held
+    pub fn try_acquire(
+        &self,
+        story_id: &str,
+        reason: LockReason,
+        ttl: Option<Duration>,
+    ) -> Result<StoryLockGuard, String> {
+        let mut table = self.table.lock().map_err(|_| "Story lock table poisoned")?;
+        let now = now_ms();
+        if let Some(held) = table.get(story_id).filter(|l| !l.expired(now)) {
+            return Err(locked_error(held));
+        }
+        let info = StoryLockInfo {
+            lock_id: uuid::Uuid::new_v4().to_string(),
+            story_id: story_id.to_string(),
+            reason,
+            acquired_at: now,
+            expires_at: ttl.map(|ttl| now + ttl.as_millis() as i64),
+        };
+        table.insert(story_id.to_string(), info.clone());
+        Ok(StoryLockGuard {
+            locks: self.clone(),
+            info,
+        })
+    }
+
+    /// Lock several stories at once, or none of them
+    pub fn try_acquire_all(
+        &self,
+        story_ids: &[&str],
+        reason: LockReason,
+    ) -> Result<Vec<StoryLockGuard>, String> {
+        let mut guards = Vec::with_capacity(story_ids.len());
+        for story_id in story_ids {
+            if guards
+                .iter()
+                .any(|g: &StoryLockGuard| g.info.story_id == *story_id)
+            {
+                continue;
+            }
+            // Guards acquired so far are released if a later one fails
+            guards.push(self.try_acquire(story_id, reason, None)?);
+        }
+        Ok(guards)
+    }
+
+    /// Lock a story, waiting up to `wait` for the current holder to finish
+    pub async fn acquire(
+        &self,
+        story_id: &str,
+        reason: LockReason,
+        ttl: Option<Duration>,
+        wait: Duration,
+    ) -> Result<StoryLockGuard, String> {
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            match self.try_acquire(story_id, reason, ttl) {
+                Ok(guard) => return Ok(guard),
+                Err(e) if tokio::time::Instant::now() >= deadline => return Err(e),
+                Err(_) => tokio::time::sleep(WAIT_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Release a lock by ID. Returns false if it was not held.
+    pub fn release(&self, lock_id: &str) -> bool {
+        let Ok(mut table) = self.table.lock() else {
+            return false;
+        };
+        let before = table.len();
+        table.retain(|_, l| l.lock_id != lock_id);
+        table.len() != before
+    }
+
+    /// Fail with a `StoryLocked` error if another operation holds the story.
+    /// `lock_id` names a lock the writer holds itself. A collaboration lock
+    /// is held on behalf of the host, whose frontend writes the story for
+    /// the session, so it does not stop writes.
+    pub fn check_write(&self, story_id: &str, lock_id: Option<&str>) -> Result<(), String> {
+        match self.holder(story_id) {
+            Some(held)
+                if held.reason != LockReason::Collaboration
+                    && lock_id != Some(held.lock_id.as_str()) =>
+            {
+                Err(locked_error(&held))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Current holder of a story's lock, if any
+    pub fn holder(&self, story_id: &str) -> Option<StoryLockInfo> {
+        let now = now_ms();
+        self.table
+            .lock()
+            .ok()?
+            .get(story_id)
+            .filter(|l| !l.expired(now))
+            .cloned()
+    }
+
+    pub fn list(&self) -> Vec<StoryLockInfo> {
+        let now = now_ms();
+        self.table
+            .lock()
+            .map(|table| {
+                table
+                    .values()
+                    .filter(|l| !l.expired(now))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,472 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use super::text::plain_text;
+use super::types::StoryEntry;
+use super::versions::StoryVersion;
+use super::StoryExport;
+use crate::sync::clock::ClockSkew;
+
+/// Characters of each side shown for a conflicting entry
+const CONFLICT_EXCERPT_CHARS: usize = 240;
+
+/// How conflicting copies of the same entry are resolved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeStrategy {
+    /// Take every entry from both copies; the primary wins conflicts
+    #[default]
+    Union,
+    /// The more recently edited copy of an entry wins
+    NewestWins,
+    /// Conflicts are returned for the user to decide
+    Interactive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeSide {
+    Primary,
+    Secondary,
+}
+
+/// An entry that differs between the two copies
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeConflict {
+    pub entry_id: String,
+    pub primary_excerpt: String,
+    pub secondary_excerpt: String,
+    pub primary_updated_at: i64,
+    pub secondary_updated_at: i64,
+}
+
+/// Outcome of a merge
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeReport {
+    /// Merged story in Aventura export format, keeping the primary's ID.
+    /// None when an interactive merge still has undecided conflicts.
+    pub merged_json: Option<String>,
+    pub identical: usize,
+    pub only_primary: usize,
+    pub only_secondary: usize,
+    pub taken_from_primary: usize,
+    pub taken_from_secondary: usize,
+    /// Conflicts still waiting for a decision
+    pub conflicts: Vec<MergeConflict>,
+    /// Versions of both originals saved when the merge completed
+    pub versions: Vec<StoryVersion>,
+}
+
+/// When an entry was last edited; entries without `updatedAt` were never edited
+fn edited_at(entry: &StoryEntry) -> i64 {
+    entry
+        .extra
+        .get("updatedAt")
+        .and_then(Value::as_i64)
+        .unwrap_or(entry.created_at)
+}
+
+/// Move the times of a copy pulled from another device onto this device's
+/// clock, so its edits compare fairly with the local copy's
+pub fn adjust_clock(export: &mut StoryExport, clock: &ClockSkew) {
+    let local = |time: i64| {
+        if time > 0 {
+            clock.local_time(time)
+        } else {
+            time
+        }
+    };
+    export.story.updated_at = local(export.story.updated_at);
+    for entry in &mut export.entries {
+        entry.created_at = local(entry.created_at);
+        if let Some(updated_at) = entry.extra.get("updatedAt").and_then(Value::as_i64) {
+            entry
+                .extra
+                .insert("updatedAt".to_string(), Value::from(local(updated_at)));
+        }
+    }
+}
+
+/// Pair records the other copy knows under a different ID by a key that
+/// survives import, recording secondary ID -> primary ID. Records whose ID
+/// is on both sides, or already paired, are left out.
+fn pair_by<T>(
+    primary: &[T],
+    secondary: &[T],
+    id: impl Fn(&T) -> &str,
+    key: impl Fn(&T) -> Option<String>,
+    pairs: &mut HashMap<String, String>,
+) {
+    let primary_ids: HashSet<&str> = primary.iter().map(&id).collect();
+    let secondary_ids: HashSet<&str> = secondary.iter().map(&id).collect();
+    let taken: HashSet<&str> = pairs.values().map(String::as_str).collect();
+    let mut free: HashMap<String, &str> = HashMap::new();
+    for record in primary {
+        let record_id = id(record);
+        if secondary_ids.contains(record_id) || taken.contains(record_id) {
+            continue;
+        }
+        if let Some(key) = key(record) {
+            free.entry(key).or_insert(record_id);
+        }
+    }
+    let mut found = Vec::new();
+    for record in secondary {
+        let record_id = id(record);
+        if primary_ids.contains(record_id) || pairs.contains_key(record_id) {
+            continue;
+        }
+        if let Some(primary_id) = key(record).and_then(|key| free.remove(&key)) {
+            found.push((record_id.to_string(), primary_id.to_string()));
+        }
+    }
+    pairs.extend(found);
+}
+
+/// Replace every string in a JSON value that is a paired ID
+fn rename_ids(value: &mut Value, pairs: &HashMap<String, String>) {
+    match value {
+        Value::String(text) => {
+            if let Some(id) = pairs.get(text.as_str()) {
+                *text = id.clone();
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| rename_ids(v, pairs)),
+        Value::Object(fields) => fields.values_mut().for_each(|v| rename_ids(v, pairs)),
+        _ => {}
+    }
+}
+
+fn name_key(name: &str) -> Option<String> {
+    let name = name.trim().to_lowercase();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Give the secondary the primary's IDs for records both copies have. A
+/// copy imported before imports kept IDs knows every record under a new
+/// ID, so entries are matched by type and creation time, then by text,
+/// and other records by name. Run it before moving the secondary's clock,
+/// while creation times still agree.
+pub fn match_ids(primary: &StoryExport, secondary: &StoryExport) -> Result<StoryExport, String> {
+    let mut pairs = HashMap::new();
+    pair_by(
+        &primary.entries,
+        &secondary.entries,
+        |e| &e.id,
+        |e| (e.created_at > 0).then(|| format!("{}:{}", e.entry_type, e.created_at)),
+        &mut pairs,
+    );
+    pair_by(
+        &primary.entries,
+        &secondary.entries,
+        |e| &e.id,
+        |e| {
+            let text = plain_text(&e.content);
+            let text = text.trim();
+            (!text.is_empty()).then(|| format!("{}:{}", e.entry_type, text))
+        },
+        &mut pairs,
+    );
+    pair_by(
+        &primary.characters,
+        &secondary.characters,
+        |c| &c.id,
+        |c| name_key(&c.name),
+        &mut pairs,
+    );
+    pair_by(
+        &primary.locations,
+        &secondary.locations,
+        |l| &l.id,
+        |l| name_key(&l.name),
+        &mut pairs,
+    );
+    pair_by(
+        &primary.items,
+        &secondary.items,
+        |i| &i.id,
+        |i| name_key(&i.name),
+        &mut pairs,
+    );
+    pair_by(
+        &primary.story_beats,
+        &secondary.story_beats,
+        |b| &b.id,
+        |b| name_key(&b.title),
+        &mut pairs,
+    );
+    pair_by(
+        &primary.lorebook_entries,
+        &secondary.lorebook_entries,
+        |l| &l.id,
+        |l| name_key(&l.name),
+        &mut pairs,
+    );
+    pair_by(
+        &primary.chapters,
+        &secondary.chapters,
+        |c| &c.id,
+        |c| Some(c.number.to_string()),
+        &mut pairs,
+    );
+    pair_by(
+        &primary.branches,
+        &secondary.branches,
+        |b| &b.id,
+        |b| name_key(&b.name),
+        &mut pairs,
+    );
+    let entry_ids = pairs.clone();
+    pair_by(
+        &primary.embedded_images,
+        &secondary.embedded_images,
+        |i| &i.id,
+        |i| {
+            let entry_id = entry_ids.get(&i.entry_id).unwrap_or(&i.entry_id);
+            Some(format!("{}:{}", entry_id, i.source_text))
+        },
+        &mut pairs,
+    );
+    if pairs.is_empty() {
+        return Ok(secondary.clone());
+    }
+
+    let mut value =
+        serde_json::to_value(secondary).map_err(|e| format!("Failed to serialize story: {}", e))?;
+    rename_ids(&mut value, &pairs);
+    serde_json::from_value(value).map_err(|e| format!("Invalid story export: {}", e))
+}
+
+/// Start of an entry's text, for showing it next to another copy
+pub fn excerpt(entry: &StoryEntry) -> String {
+    plain_text(&entry.content)
+        .chars()
+        .take(CONFLICT_EXCERPT_CHARS)
+        .collect()
+}
+
+/// Union two lists by ID. Items present in both come from `secondary` only
+/// when `prefer_secondary` is set.
+fn union_by_id<T: Clone>(
+    primary: &[T],
+    secondary: &[T],
+    id: impl Fn(&T) -> &str,
+    prefer_secondary: bool,
+) -> Vec<T> {
+    let by_id: HashMap<&str, &T> = secondary.iter().map(|s| (id(s), s)).collect();
+    let mut seen = HashSet::new();
+    let mut merged: Vec<T> = primary
+        .iter()
+        .map(|p| {
+            seen.insert(id(p));
+            match by_id.get(id(p)) {
+                Some(s) if prefer_secondary => (*s).clone(),
+                _ => p.clone(),
+            }
+        })
+        .collect();
+    merged.extend(secondary.iter().filter(|s| !seen.contains(id(s))).cloned());
+    merged
+}
+
+/// Interleave entries per branch: secondary-only entries are placed after the
+/// entry that precedes them in the secondary copy, then positions are renumbered
+fn order_entries(
+    primary: &StoryExport,
+    secondary: &StoryExport,
+    chosen: HashMap<String, StoryEntry>,
+) -> Vec<StoryEntry> {
+    let mut by_branch: HashMap<Option<&str>, Vec<&StoryEntry>> = HashMap::new();
+    for entry in &primary.entries {
+        by_branch
+            .entry(entry.branch_id.as_deref())
+            .or_default()
+            .push(entry);
+    }
+    let mut secondary_by_branch: HashMap<Option<&str>, Vec<&StoryEntry>> = HashMap::new();
+    for entry in &secondary.entries {
+        secondary_by_branch
+            .entry(entry.branch_id.as_deref())
+            .or_default()
+            .push(entry);
+    }
+
+    let primary_ids: HashSet<&str> = primary.entries.iter().map(|e| e.id.as_str()).collect();
+    let mut branches: Vec<Option<&str>> = by_branch.keys().copied().collect();
+    branches.extend(
+        secondary_by_branch
+            .keys()
+            .filter(|b| !by_branch.contains_key(*b))
+            .copied(),
+    );
+    branches.sort();
+
+    let mut ordered = Vec::with_capacity(chosen.len());
+    for branch in branches {
+        let mut ids: Vec<&str> = by_branch
+            .remove(&branch)
+            .map(|mut entries| {
+                entries.sort_by_key(|e| e.position);
+                entries.iter().map(|e| e.id.as_str()).collect()
+            })
+            .unwrap_or_default();
+
+        let mut theirs = secondary_by_branch.remove(&branch).unwrap_or_default();
+        theirs.sort_by_key(|e| e.position);
+        let mut previous: Option<&str> = None;
+        for entry in theirs {
+            if !primary_ids.contains(entry.id.as_str()) {
+                let at = previous
+                    .and_then(|p| ids.iter().position(|id| *id == p))
+                    .map_or(0, |i| i + 1);
+                ids.insert(at, entry.id.as_str());
+            }
+            previous = Some(entry.id.as_str());
+        }
+
+        for (position, id) in ids.into_iter().enumerate() {
+            if let Some(mut entry) = chosen.get(id).cloned() {
+                entry.position = position as i64;
+                ordered.push(entry);
+            }
+        }
+    }
+    ordered
+}
+
+/// Point every `storyId` field in a list of records at the merged story
+fn retarget(value: &mut Value, story_id: &str) {
+    if let Some(items) = value.as_array_mut() {
+        for item in items {
+            if let Some(field) = item.get_mut("storyId") {
+                *field = Value::String(story_id.to_string());
+            }
+        }
+    }
+}
+
+/// Merge two copies of a story entry-by-entry. Lore, characters and other
+/// records are unioned by ID, taking the newer story's copy on conflict.
+pub fn merge(
+    primary: &StoryExport,
+    secondary: &StoryExport,
+    strategy: MergeStrategy,
+    resolutions: &HashMap<String, MergeSide>,
+) -> Result<MergeReport, String> {
+    let theirs: HashMap<&str, &StoryEntry> = secondary
+        .entries
+        .iter()
+        .map(|e| (e.id.as_str(), e))
+        .collect();
+
+    let mut report = MergeReport {
+        merged_json: None,
+        identical: 0,
+        only_primary: 0,
+        only_secondary: 0,
+        taken_from_primary: 0,
+        taken_from_secondary: 0,
+        conflicts: Vec::new(),
+        versions: Vec::new(),
+    };
+    let mut chosen: HashMap<String, StoryEntry> = HashMap::new();
+
+    for ours in &primary.entries {
+        let Some(other) = theirs.get(ours.id.as_str()) else {
+            report.only_primary += 1;
+            chosen.insert(ours.id.clone(), ours.clone());
+            continue;
+        };
+        if ours.content == other.content && ours.entry_type == other.entry_type {
+            report.identical += 1;
+            chosen.insert(ours.id.clone(), ours.clone());
+            continue;
+        }
+
+        let side = match (resolutions.get(&ours.id), strategy) {
+            (Some(side), _) => Some(*side),
+            (None, MergeStrategy::Union) => Some(MergeSide::Primary),
+            (None, MergeStrategy::NewestWins) if edited_at(other) > edited_at(ours) => {
+                Some(MergeSide::Secondary)
+            }
+            (None, MergeStrategy::NewestWins) => Some(MergeSide::Primary),
+            (None, MergeStrategy::Interactive) => None,
+        };
+        match side {
+            Some(MergeSide::Primary) => {
+                report.taken_from_primary += 1;
+                chosen.insert(ours.id.clone(), ours.clone());
+            }
+            Some(MergeSide::Secondary) => {
+                report.taken_from_secondary += 1;
+                chosen.insert(ours.id.clone(), (*other).clone());
+            }
+            None => report.conflicts.push(MergeConflict {
+                entry_id: ours.id.clone(),
+                primary_excerpt: excerpt(ours),
+                secondary_excerpt: excerpt(other),
+                primary_updated_at: edited_at(ours),
+                secondary_updated_at: edited_at(other),
+            }),
+        }
+    }
+    let primary_ids: HashSet<&str> = primary.entries.iter().map(|e| e.id.as_str()).collect();
+    for entry in &secondary.entries {
+        if !primary_ids.contains(entry.id.as_str()) {
+            report.only_secondary += 1;
+            chosen.insert(entry.id.clone(), entry.clone());
+        }
+    }
+    if !report.conflicts.is_empty() {
+        return Ok(report);
+    }
+
+    let story_id = primary.story.id.clone();
+    let newer = secondary.story.updated_at > primary.story.updated_at;
+    let mut merged = primary.clone();
+    merged.entries = order_entries(primary, secondary, chosen);
+    for entry in &mut merged.entries {
+        entry.story_id = story_id.clone();
+    }
+    merged.characters = union_by_id(&primary.characters, &secondary.characters, |c| &c.id, newer);
+    merged.locations = union_by_id(&primary.locations, &secondary.locations, |l| &l.id, newer);
+    merged.items = union_by_id(&primary.items, &secondary.items, |i| &i.id, newer);
+    merged.story_beats = union_by_id(
+        &primary.story_beats,
+        &secondary.story_beats,
+        |b| &b.id,
+        newer,
+    );
+    merged.lorebook_entries = union_by_id(
+        &primary.lorebook_entries,
+        &secondary.lorebook_entries,
+        |l| &l.id,
+        newer,
+    );
+    merged.chapters = union_by_id(&primary.chapters, &secondary.chapters, |c| &c.id, newer);
+    merged.branches = union_by_id(&primary.branches, &secondary.branches, |b| &b.id, newer);
+    merged.embedded_images = union_by_id(
+        &primary.embedded_images,
+        &secondary.embedded_images,
+        |i| &i.id,
+        newer,
+    );
+    merged.story.updated_at = primary.story.updated_at.max(secondary.story.updated_at);
+
+    let mut value =
+        serde_json::to_value(&merged).map_err(|e| format!("Failed to serialize story: {}", e))?;
+    if let Some(fields) = value.as_object_mut() {
+        for (key, field) in fields.iter_mut() {
+            if key != "story" {
+                retarget(field, &story_id);
+            }
+        }
+    }
+    report.merged_json = Some(
+        serde_json::to_string(&value).map_err(|e| format!("Failed to serialize story: {}", e))?,
+    );
+    Ok(report)
+}
@@ -0,0 +1,24 @@
+pub mod archive;
+pub mod commands;
+pub mod consistency;
+pub mod entities;
+pub mod graph;
+pub mod interview;
+pub mod journal;
+pub mod lock;
+pub mod merge;
+pub mod private;
+pub mod rating;
+pub mod relationships;
+pub mod revisions;
+pub mod rows;
+pub mod sanitize;
+pub mod simulate;
+pub mod split;
+pub mod text;
+pub mod timeline;
+pub mod types;
+pub mod versions;
+
+pub use commands::StoryState;
+pub use types::StoryExport;
@@ -0,0 +1,37 @@
+//! Parts of a story meant only for its author: player notes on lorebook
+//! entries and the working state the editor keeps alongside the story.
+//! They are left out when a story is shared with other people.
+
+use serde_json::Value;
+
+use super::StoryExport;
+
+/// Story fields holding editor state or notes rather than story content
+const PRIVATE_STORY_FIELDS: [&str; 4] = ["retryState", "styleReviewState", "authorNotes", "notes"];
+
+/// Remove private notes and state from a story, returning how many were removed
+pub fn strip_private_notes(export: &mut StoryExport) -> usize {
+    let mut removed = 0;
+    for field in PRIVATE_STORY_FIELDS {
+        if export
+            .story
+            .extra
+            .remove(field)
+            .is_some_and(|value| !value.is_null())
+        {
+            removed += 1;
+        }
+    }
+    for entry in &mut export.lorebook_entries {
+        let notes = entry
+            .extra
+            .get_mut("adventureState")
+            .and_then(|state| state.get_mut("notes"))
+            .and_then(Value::as_array_mut);
+        if let Some(notes) = notes {
+            removed += notes.len();
+            notes.clear();
+        }
+    }
+    removed
+}
@@ -0,0 +1,149 @@
+//! Content ratings: an age rating and the warnings a story carries. They are
+//! kept with the story, travel in its export and sync preview, and let a
+//! restricted profile hide stories rated above what it may see.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+use crate::profiles::{self, database};
+use crate::sync::keys::now_ms;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AgeRating {
+    Everyone,
+    Teen,
+    Mature,
+    Adult,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContentWarning {
+    Violence,
+    Gore,
+    SexualContent,
+    SelfHarm,
+    SubstanceUse,
+    StrongLanguage,
+    Abuse,
+    Horror,
+    Discrimination,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ContentRating {
+    pub age_rating: Option<AgeRating>,
+    /// Sorted, without duplicates
+    pub warnings: Vec<ContentWarning>,
+}
+
+/// A value as the story stores it, or None if this version does not know it
+fn parse<T: serde::de::DeserializeOwned>(value: &str) -> Option<T> {
+    serde_json::from_value(Value::String(value.to_string())).ok()
+}
+
+fn name<T: Serialize>(value: T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+impl ContentRating {
+    /// Read from a story's fields. Ratings and warnings this version does
+    /// not know are dropped rather than failing the story.
+    pub fn from_fields(age_rating: Option<&str>, warnings: &[String]) -> Self {
+        let mut rating = Self {
+            age_rating: age_rating.and_then(parse),
+            warnings: warnings.iter().filter_map(|w| parse(w)).collect(),
+        };
+        rating.normalize();
+        rating
+    }
+
+    /// Read from the `age_rating` and `content_warnings` columns
+    fn from_columns(age_rating: Option<String>, warnings: Option<String>) -> Self {
+        let warnings: Vec<String> = warnings
+            .and_then(|w| serde_json::from_str(&w).ok())
+            .unwrap_or_default();
+        Self::from_fields(age_rating.as_deref(), &warnings)
+    }
+
+    fn normalize(&mut self) {
+        self.warnings.sort();
+        self.warnings.dedup();
+    }
+}
+
+/// The `age_rating` and `content_warnings` columns
+type RatingColumns = (Option<String>, Option<String>);
+
+/// Story ID with its rating columns
+type RatingRow = (String, Option<String>, Option<String>);
+
+/// A story's rating
+pub async fn get(app: &AppHandle, story_id: &str) -> Result<ContentRating, String> {
+    find(app, story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))
+}
+
+/// A story's rating, or None if the story is not in the database
+pub async fn find(app: &AppHandle, story_id: &str) -> Result<Option<ContentRating>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let row: Result<Option<RatingColumns>, String> =
+        sqlx::query_as("SELECT age_rating, content_warnings FROM stories WHERE id = ?")
+            .bind(story_id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(|e| format!("Failed to read story: {}", e));
+    pool.close().await;
+    Ok(row?.map(|(age_rating, warnings)| ContentRating::from_columns(age_rating, warnings)))
+}
+
+/// Change a story's rating, returning it as stored
+pub async fn set(
+    app: &AppHandle,
+    story_id: &str,
+    mut rating: ContentRating,
+) -> Result<ContentRating, String> {
+    rating.normalize();
+    let warnings: Vec<String> = rating.warnings.iter().copied().map(name).collect();
+    let warnings = serde_json::to_string(&warnings)
+        .map_err(|e| format!("Failed to serialize warnings: {}", e))?;
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let result = sqlx::query(
+        "UPDATE stories SET age_rating = ?, content_warnings = ?, updated_at = ? WHERE id = ?",
+    )
+    .bind(rating.age_rating.map(name))
+    .bind(warnings)
+    .bind(now_ms())
+    .bind(story_id)
+    .execute(&pool)
+    .await
+    .map_err(|e| format!("Failed to update story: {}", e));
+    pool.close().await;
+    if result?.rows_affected() == 0 {
+        return Err(format!("Story not found: {}", story_id));
+    }
+    Ok(rating)
+}
+
+/// Every story's rating, by story ID
+pub async fn all(app: &AppHandle) -> Result<HashMap<String, ContentRating>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let rows: Result<Vec<RatingRow>, String> =
+        sqlx::query_as("SELECT id, age_rating, content_warnings FROM stories")
+            .fetch_all(&pool)
+            .await
+            .map_err(|e| format!("Failed to read stories: {}", e));
+    pool.close().await;
+    Ok(rows?
+        .into_iter()
+        .map(|(id, age_rating, warnings)| (id, ContentRating::from_columns(age_rating, warnings)))
+        .collect())
+}
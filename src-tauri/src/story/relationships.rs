@@ -0,0 +1,209 @@
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::OnceLock;
+
+use super::entities::{EntityIndex, EntityKind};
+use super::StoryExport;
+
+/// Sentences kept as examples of each pair interacting
+const MAX_EXAMPLES: usize = 3;
+
+const EXAMPLE_CHARS: usize = 200;
+
+/// Strongest relationships described in `RelationshipGraph::context`
+const MAX_CONTEXT_EDGES: usize = 15;
+
+/// An interaction counts for this many shared entries in an edge's weight
+const INTERACTION_WEIGHT: f64 = 2.0;
+
+/// Verbs that suggest two characters named in a sentence are dealing with
+/// each other rather than just both present
+const INTERACTION_VERBS: &str = "said|says|asked|asks|told|tells|replied|replies|answered|\
+    whispered|shouted|called|greeted|thanked|warned|promised|hugged|hugs|embraced|kissed|kisses|\
+    held|helped|helps|saved|rescued|protected|attacked|attacks|struck|fought|fights|betrayed|\
+    killed|hit|pushed|grabbed|followed|met|meets|joined|gave|gives|handed|showed|taught|\
+    looked at|glanced at|smiled at|nodded to|turned to|argued|agreed|trusted|loved|hated";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelationshipNode {
+    pub character_id: String,
+    pub name: String,
+    /// How the character relates to the player, from the character sheet
+    pub relationship: Option<String>,
+    /// Entries naming the character
+    pub entries: usize,
+    pub first_entry_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelationshipEdge {
+    pub source: String,
+    pub target: String,
+    /// Entries naming both
+    pub shared_entries: usize,
+    /// Sentences naming both with one acting on or speaking to the other
+    pub interactions: usize,
+    /// Between 0 and 1, relative to the strongest edge in the story
+    pub weight: f64,
+    pub first_entry_id: String,
+    pub last_entry_id: String,
+    pub examples: Vec<String>,
+}
+
+/// Who appears with whom across the main branch
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelationshipGraph {
+    pub story_id: String,
+    pub nodes: Vec<RelationshipNode>,
+    /// Strongest first
+    pub edges: Vec<RelationshipEdge>,
+    /// The strongest relationships as plain sentences, for adding to a
+    /// prompt
+    pub context: String,
+}
+
+#[derive(Default)]
+struct Pair {
+    entries: Vec<usize>,
+    interactions: usize,
+    examples: Vec<String>,
+}
+
+fn interaction() -> &'static Regex {
+    static INTERACTION: OnceLock<Regex> = OnceLock::new();
+    INTERACTION.get_or_init(|| Regex::new(&format!(r"(?i)\b(?:{})\b", INTERACTION_VERBS)).unwrap())
+}
+
+fn describe(edge: &RelationshipEdge, names: &HashMap<&str, &str>) -> String {
+    let closeness = if edge.weight >= 0.66 {
+        "know each other well"
+    } else if edge.weight >= 0.33 {
+        "know each other"
+    } else {
+        "have met"
+    };
+    format!(
+        "{} and {} {} (together in {} entries, interacting in {}).",
+        names.get(edge.source.as_str()).unwrap_or(&""),
+        names.get(edge.target.as_str()).unwrap_or(&""),
+        closeness,
+        edge.shared_entries,
+        edge.interactions
+    )
+}
+
+impl RelationshipGraph {
+    pub fn build(export: &StoryExport) -> Self {
+        let index = EntityIndex::build(export);
+        let is_character = |entity: &usize| index.entities[*entity].kind == EntityKind::Character;
+
+        let mut entries_of: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for mention in index.mentions.iter().filter(|m| is_character(&m.entity)) {
+            let entries = entries_of.entry(mention.entity).or_default();
+            if entries.last() != Some(&mention.entry) {
+                entries.push(mention.entry);
+            }
+        }
+
+        let mut pairs: BTreeMap<(usize, usize), Pair> = BTreeMap::new();
+        let mut by_entry: BTreeMap<usize, HashSet<usize>> = BTreeMap::new();
+        for (entry, sentence, entities) in index.sentences_with_mentions() {
+            let characters: Vec<usize> = entities.into_iter().filter(is_character).collect();
+            by_entry
+                .entry(entry)
+                .or_default()
+                .extend(characters.iter().copied());
+            if characters.len() < 2 {
+                continue;
+            }
+            let text = &index.entries[entry].sentences[sentence];
+            if !interaction().is_match(text) {
+                continue;
+            }
+            for (i, a) in characters.iter().enumerate() {
+                for b in &characters[i + 1..] {
+                    let pair = pairs.entry((*a.min(b), *a.max(b))).or_default();
+                    pair.interactions += 1;
+                    if pair.examples.len() < MAX_EXAMPLES {
+                        pair.examples
+                            .push(text.chars().take(EXAMPLE_CHARS).collect());
+                    }
+                }
+            }
+        }
+        for (entry, characters) in by_entry {
+            let mut characters: Vec<usize> = characters.into_iter().collect();
+            characters.sort_unstable();
+            for (i, a) in characters.iter().enumerate() {
+                for b in &characters[i + 1..] {
+                    pairs.entry((*a, *b)).or_default().entries.push(entry);
+                }
+            }
+        }
+
+        let score =
+            |pair: &Pair| pair.entries.len() as f64 + INTERACTION_WEIGHT * pair.interactions as f64;
+        let strongest = pairs.values().map(score).fold(0.0, f64::max);
+        let entry_id = |entry: usize| index.entries[entry].entry_id.clone();
+        let mut edges: Vec<RelationshipEdge> = pairs
+            .iter()
+            .filter(|(_, pair)| !pair.entries.is_empty())
+            .map(|((a, b), pair)| RelationshipEdge {
+                source: index.entities[*a].id.clone(),
+                target: index.entities[*b].id.clone(),
+                shared_entries: pair.entries.len(),
+                interactions: pair.interactions,
+                weight: if strongest > 0.0 {
+                    score(pair) / strongest
+                } else {
+                    0.0
+                },
+                first_entry_id: entry_id(pair.entries[0]),
+                last_entry_id: entry_id(pair.entries[pair.entries.len() - 1]),
+                examples: pair.examples.clone(),
+            })
+            .collect();
+        edges.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+        let nodes: Vec<RelationshipNode> = export
+            .characters
+            .iter()
+            .map(|character| {
+                let entity = index
+                    .entities
+                    .iter()
+                    .position(|e| e.kind == EntityKind::Character && e.id == character.id);
+                let entries = entity.and_then(|e| entries_of.get(&e));
+                RelationshipNode {
+                    character_id: character.id.clone(),
+                    name: character.name.clone(),
+                    relationship: character.relationship.clone(),
+                    entries: entries.map_or(0, Vec::len),
+                    first_entry_id: entries.and_then(|e| e.first()).map(|e| entry_id(*e)),
+                }
+            })
+            .collect();
+
+        let names: HashMap<&str, &str> = nodes
+            .iter()
+            .map(|n| (n.character_id.as_str(), n.name.as_str()))
+            .collect();
+        let context = edges
+            .iter()
+            .take(MAX_CONTEXT_EDGES)
+            .map(|edge| describe(edge, &names))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        RelationshipGraph {
+            story_id: export.story.id.clone(),
+            nodes,
+            edges,
+            context,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Revision numbers for optimistic concurrency, kept in the stories table.
+//! The frontend writes a story's rows itself and then saves it, naming the
+//! revision it loaded or last saved; if another window or device saved in
+//! between, the save is rejected with a `RevisionConflict` carrying the
+//! newer copy.
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use super::rows;
+use crate::profiles::{self, database};
+use crate::sync::keys::now_ms;
+
+/// Prefix of conflict errors; the rest of the message is a `RevisionConflict` as JSON
+pub const REVISION_CONFLICT: &str = "RevisionConflict";
+
+/// The revision a story was last saved at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryRevision {
+    pub story_id: String,
+    /// Starts at 0 and goes up by one with every save
+    pub revision: u64,
+    pub saved_at: i64,
+}
+
+/// A save was based on an older revision than the one in the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionConflict {
+    pub story_id: String,
+    pub expected_revision: u64,
+    pub current_revision: u64,
+    pub saved_at: i64,
+    /// The newer copy in Aventura export format
+    pub story_json: String,
+}
+
+impl From<RevisionConflict> for String {
+    fn from(conflict: RevisionConflict) -> String {
+        match serde_json::to_string(&conflict) {
+            Ok(json) => format!("{}: {}", REVISION_CONFLICT, json),
+            Err(_) => format!(
+                "{}: story {} is at revision {}",
+                REVISION_CONFLICT, conflict.story_id, conflict.current_revision
+            ),
+        }
+    }
+}
+
+/// Current revision of a story, or `None` if it is not in the database.
+/// Stories start at revision 0 until their first save.
+pub async fn current(app: &AppHandle, story_id: &str) -> Result<Option<StoryRevision>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let row: Result<Option<(i64, i64)>, String> =
+        sqlx::query_as("SELECT revision, updated_at FROM stories WHERE id = ?")
+            .bind(story_id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(|e| format!("Failed to read revision: {}", e));
+    pool.close().await;
+    Ok(row?.map(|(revision, saved_at)| StoryRevision {
+        story_id: story_id.to_string(),
+        revision: revision.max(0) as u64,
+        saved_at,
+    }))
+}
+
+/// Record a save of a story that was based on `expected`. The comparison
+/// and the bump are one statement, so two saves from the same revision
+/// cannot both succeed.
+pub async fn save(app: &AppHandle, story_id: &str, expected: u64) -> Result<StoryRevision, String> {
+    let saved_at = now_ms();
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let result =
+        sqlx::query("UPDATE stories SET revision = revision + 1 WHERE id = ? AND revision = ?")
+            .bind(story_id)
+            .bind(expected as i64)
+            .execute(&pool)
+            .await
+            .map_err(|e| format!("Failed to save revision: {}", e));
+    pool.close().await;
+    if result?.rows_affected() == 1 {
+        return Ok(StoryRevision {
+            story_id: story_id.to_string(),
+            revision: expected + 1,
+            saved_at,
+        });
+    }
+
+    let current = current(app, story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let story_json = rows::load(app, story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?
+        .to_json()?;
+    Err(RevisionConflict {
+        story_id: story_id.to_string(),
+        expected_revision: expected,
+        current_revision: current.revision,
+        saved_at: current.saved_at,
+        story_json,
+    }
+    .into())
+}
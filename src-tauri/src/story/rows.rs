@@ -0,0 +1,225 @@
+//! Building an Aventura export straight from a story's database rows, for
+//! backend operations that export stories the frontend has not loaded. The
+//! conversion follows the frontend's row mappers: columns become camelCase
+//! fields, JSON columns are parsed and 0/1 flags become booleans.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{Map, Number, Value};
+use tauri::AppHandle;
+
+use super::archive::{self, ArchivedRows, ArchivedTable, Cell};
+use super::StoryExport;
+use crate::profiles::{self, database};
+use crate::sync::keys::now_ms;
+
+/// Export format written, matching the frontend's exporter
+const EXPORT_VERSION: &str = "1.7.0";
+
+/// JSON columns read as an empty list when unset
+const LIST_COLUMNS: [&str; 15] = [
+    "traits",
+    "visual_descriptors",
+    "connections",
+    "keywords",
+    "characters",
+    "locations",
+    "plot_threads",
+    "aliases",
+    "tags",
+    "entries_snapshot",
+    "characters_snapshot",
+    "locations_snapshot",
+    "items_snapshot",
+    "story_beats_snapshot",
+    "chapters_snapshot",
+];
+
+/// JSON columns read as null when unset
+const JSON_COLUMNS: [&str; 18] = [
+    "settings",
+    "memory_config",
+    "retry_state",
+    "style_review_state",
+    "time_tracker",
+    "metadata",
+    "start_time",
+    "end_time",
+    "time_tracker_snapshot",
+    "lorebook_entries_snapshot",
+    "state",
+    "adventure_state",
+    "creative_state",
+    "injection",
+    "content_warnings",
+    "series",
+    "ambience",
+    "variant_of",
+];
+
+const FLAG_COLUMNS: [&str; 6] = [
+    "visited",
+    "current",
+    "equipped",
+    "lore_management_blacklisted",
+    "favorite",
+    "pinned",
+];
+
+/// Export field for each table, with the column its rows are ordered by
+const EXPORT_TABLES: [(&str, &str, &str); 10] = [
+    ("story_entries", "entries", "position"),
+    ("characters", "characters", ""),
+    ("locations", "locations", ""),
+    ("items", "items", ""),
+    ("story_beats", "storyBeats", ""),
+    ("entries", "lorebookEntries", "created_at"),
+    ("embedded_images", "embeddedImages", "created_at"),
+    ("checkpoints", "checkpoints", "created_at"),
+    ("branches", "branches", "created_at"),
+    ("chapters", "chapters", "number"),
+];
+
+fn camel_case(column: &str) -> String {
+    let mut out = String::with_capacity(column.len());
+    let mut upper = false;
+    for c in column.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn value(column: &str, cell: &Cell) -> Value {
+    let is_list = LIST_COLUMNS.contains(&column);
+    match cell {
+        Cell::Null if is_list => Value::Array(Vec::new()),
+        Cell::Null => Value::Null,
+        Cell::Integer(n) if FLAG_COLUMNS.contains(&column) => Value::Bool(*n == 1),
+        Cell::Integer(n) => Value::from(*n),
+        Cell::Real(n) => Number::from_f64(*n).map_or(Value::Null, Value::Number),
+        Cell::Text(text) if is_list || JSON_COLUMNS.contains(&column) => {
+            serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.clone()))
+        }
+        Cell::Text(text) => Value::String(text.clone()),
+        Cell::Blob(bytes) => Value::String(STANDARD.encode(bytes)),
+    }
+}
+
+fn objects(table: &ArchivedTable, order_by: &str) -> Vec<Value> {
+    let mut rows: Vec<&Vec<Cell>> = table.rows.iter().collect();
+    if let Some(index) = table.columns.iter().position(|c| c == order_by) {
+        rows.sort_by_key(|row| match row.get(index) {
+            Some(Cell::Integer(n)) => *n,
+            _ => i64::MAX,
+        });
+    }
+    rows.into_iter()
+        .map(|row| {
+            let object: Map<String, Value> = table
+                .columns
+                .iter()
+                .zip(row)
+                .map(|(column, cell)| (camel_case(column), value(column, cell)))
+                .collect();
+            Value::Object(object)
+        })
+        .collect()
+}
+
+/// The story as Aventura export JSON, or None if it has no story row
+pub fn to_export_json(rows: &ArchivedRows) -> Result<Option<String>, String> {
+    let Some(mut story) = rows
+        .tables
+        .iter()
+        .find(|t| t.name == "stories")
+        .and_then(|t| objects(t, "").into_iter().next())
+    else {
+        return Ok(None);
+    };
+    if let Some(fields) = story.as_object_mut() {
+        if fields.get("mode").is_none_or(Value::is_null) {
+            fields.insert("mode".into(), Value::from("adventure"));
+        }
+    }
+
+    let mut export = Map::new();
+    export.insert("version".into(), Value::from(EXPORT_VERSION));
+    export.insert("exportedAt".into(), Value::from(now_ms()));
+    export.insert(
+        "styleReviewState".into(),
+        story
+            .get("styleReviewState")
+            .cloned()
+            .unwrap_or(Value::Null),
+    );
+    export.insert("story".into(), story);
+    for (table, field, order_by) in EXPORT_TABLES {
+        let list = rows
+            .tables
+            .iter()
+            .find(|t| t.name == table)
+            .map(|t| objects(t, order_by))
+            .unwrap_or_default();
+        export.insert(field.into(), Value::Array(list));
+    }
+    serde_json::to_string(&export)
+        .map(Some)
+        .map_err(|e| format!("Failed to serialize story: {}", e))
+}
+
+/// Read a story from the current profile's database, or None if it is not
+/// there
+pub async fn load(app: &AppHandle, story_id: &str) -> Result<Option<StoryExport>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let read = async {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        archive::read_rows(&mut tx, story_id).await
+    }
+    .await;
+    pool.close().await;
+    to_export_json(&read?)?
+        .map(|json| StoryExport::from_json(&json))
+        .transpose()
+}
+
+/// ID of the local copy of a story: the story itself, or else the most
+/// recently edited story with its title
+pub async fn find_story(
+    app: &AppHandle,
+    story_id: &str,
+    title: &str,
+) -> Result<Option<String>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let id: Result<Option<(String,)>, _> = sqlx::query_as(
+        "SELECT id FROM stories WHERE id = ?1 OR title = ?2 \
+         ORDER BY id = ?1 DESC, updated_at DESC LIMIT 1",
+    )
+    .bind(story_id)
+    .bind(title)
+    .fetch_optional(&pool)
+    .await;
+    pool.close().await;
+    id.map(|id| id.map(|(id,)| id))
+        .map_err(|e| format!("Failed to read stories: {}", e))
+}
+
+/// IDs of every story in the current profile's database
+pub async fn story_ids(app: &AppHandle) -> Result<Vec<String>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let ids: Result<Vec<(String,)>, _> =
+        sqlx::query_as("SELECT id FROM stories ORDER BY created_at")
+            .fetch_all(&pool)
+            .await;
+    pool.close().await;
+    ids.map(|ids| ids.into_iter().map(|(id,)| id).collect())
+        .map_err(|e| format!("Failed to read stories: {}", e))
+}
@@ -0,0 +1,303 @@
+//! Cleaning a story before it is shared: text matching the user's rules is
+//! redacted or replaced with a consistent pseudonym, email addresses and API
+//! keys pasted by accident are redacted, and private notes are removed. Only
+//! prose fields are rewritten; IDs, settings such as the mode and genre,
+//! timestamps and image data are left alone so the cleaned copy still loads.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::private::strip_private_notes;
+use super::StoryExport;
+use crate::ai::filter::{self, FilterMatcher};
+
+/// Sanitize rules in the app data directory
+pub const SANITIZE_RULES_FILE: &str = "sanitize_rules.json";
+
+const EMAIL_PATTERN: &str = r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b";
+
+/// Key formats of common AI and hosting providers
+const API_KEY_PATTERN: &str = concat!(
+    r"\b(?:sk-[A-Za-z0-9_-]{20,}",
+    r"|AIza[0-9A-Za-z_-]{35}",
+    r"|gh[pousr]_[A-Za-z0-9]{36,}",
+    r"|xox[abprs]-[A-Za-z0-9-]{10,}",
+    r"|hf_[A-Za-z0-9]{30,}",
+    r"|AKIA[0-9A-Z]{16})\b"
+);
+
+/// What happens to text matched by a rule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SanitizeAction {
+    /// Replace every match with the same placeholder
+    #[default]
+    Redact,
+    /// Replace each distinct match with its own stand-in, the same one
+    /// everywhere it appears
+    Pseudonymize,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A user-authored sanitize rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitizeRule {
+    pub name: String,
+    pub matcher: FilterMatcher,
+    #[serde(default)]
+    pub action: SanitizeAction,
+    /// Replacement text. For pseudonyms, `{n}` is replaced by the number of
+    /// the distinct match. Defaults to "[redacted]" or "Person {n}".
+    #[serde(default)]
+    pub replacement: Option<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+/// Everything a sanitize pass does
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitizeRules {
+    #[serde(default)]
+    pub rules: Vec<SanitizeRule>,
+    #[serde(default = "default_true")]
+    pub emails: bool,
+    #[serde(default = "default_true")]
+    pub api_keys: bool,
+    #[serde(default = "default_true")]
+    pub private_notes: bool,
+}
+
+impl Default for SanitizeRules {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            emails: true,
+            api_keys: true,
+            private_notes: true,
+        }
+    }
+}
+
+/// How often one rule matched
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleReplacements {
+    pub rule: String,
+    pub action: SanitizeAction,
+    pub count: usize,
+    /// Distinct texts replaced, for pseudonyms only
+    pub distinct: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitizeReport {
+    /// Rules that matched, in rule order
+    pub replacements: Vec<RuleReplacements>,
+    pub total_replacements: usize,
+    /// Text fields that changed
+    pub fields_changed: usize,
+    pub private_notes_removed: usize,
+}
+
+/// A cleaned copy of a story
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitizedStory {
+    pub story_json: String,
+    pub report: SanitizeReport,
+}
+
+struct CompiledRule {
+    name: String,
+    regex: Regex,
+    action: SanitizeAction,
+    replacement: String,
+    count: usize,
+    /// Pseudonyms given so far, keyed by the lowercased match
+    pseudonyms: HashMap<String, String>,
+}
+
+impl CompiledRule {
+    fn replace(&mut self, matched: &str) -> String {
+        self.count += 1;
+        match self.action {
+            SanitizeAction::Redact => self.replacement.clone(),
+            SanitizeAction::Pseudonymize => {
+                let next = self.pseudonyms.len() + 1;
+                self.pseudonyms
+                    .entry(matched.to_lowercase())
+                    .or_insert_with(|| self.replacement.replace("{n}", &next.to_string()))
+                    .clone()
+            }
+        }
+    }
+}
+
+fn compile_rules(rules: &SanitizeRules) -> Result<Vec<CompiledRule>, String> {
+    let mut compiled = Vec::new();
+    for rule in rules.rules.iter().filter(|r| r.enabled) {
+        let replacement = rule.replacement.clone().unwrap_or_else(|| {
+            match rule.action {
+                SanitizeAction::Redact => "[redacted]",
+                SanitizeAction::Pseudonymize => "Person {n}",
+            }
+            .to_string()
+        });
+        compiled.push(CompiledRule {
+            name: rule.name.clone(),
+            regex: filter::compile(&rule.name, &rule.matcher)?,
+            action: rule.action,
+            replacement,
+            count: 0,
+            pseudonyms: HashMap::new(),
+        });
+    }
+    let builtin = [
+        (rules.emails, "Email addresses", EMAIL_PATTERN, "[email]"),
+        (rules.api_keys, "API keys", API_KEY_PATTERN, "[api key]"),
+    ];
+    for (enabled, name, pattern, replacement) in builtin {
+        if enabled {
+            compiled.push(CompiledRule {
+                name: name.to_string(),
+                regex: Regex::new(pattern).map_err(|e| e.to_string())?,
+                action: SanitizeAction::Redact,
+                replacement: replacement.to_string(),
+                count: 0,
+                pseudonyms: HashMap::new(),
+            });
+        }
+    }
+    Ok(compiled)
+}
+
+/// Check that every rule compiles
+pub fn validate(rules: &SanitizeRules) -> Result<(), String> {
+    compile_rules(rules).map(|_| ())
+}
+
+/// Fields holding text the user or the model wrote, or lists of it. Any
+/// other string is a setting, an ID or data the app reads back.
+const PROSE_FIELDS: [&str; 21] = [
+    "title",
+    "description",
+    "content",
+    "name",
+    "summary",
+    "relationship",
+    "traits",
+    "visualDescriptors",
+    "aliases",
+    "hiddenInfo",
+    "keywords",
+    "characters",
+    "locations",
+    "plotThreads",
+    "emotionalTone",
+    "lastEntryPreview",
+    "openingScene",
+    "location",
+    "tags",
+    "notes",
+    "text",
+];
+
+/// Replace matches in one string. Where matches overlap the one starting
+/// first wins, then the longer one, then the one from the earlier rule.
+fn sanitize_text(text: &str, rules: &mut [CompiledRule]) -> Option<String> {
+    let mut matches: Vec<(usize, usize, usize)> = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        for m in rule.regex.find_iter(text) {
+            if m.start() < m.end() {
+                matches.push((m.start(), m.end(), index));
+            }
+        }
+    }
+    if matches.is_empty() {
+        return None;
+    }
+    matches.sort_by_key(|&(start, end, index)| (start, std::cmp::Reverse(end), index));
+
+    let mut output = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end, index) in matches {
+        if start < cursor {
+            continue;
+        }
+        output.push_str(&text[cursor..start]);
+        output.push_str(&rules[index].replace(&text[start..end]));
+        cursor = end;
+    }
+    output.push_str(&text[cursor..]);
+    Some(output)
+}
+
+/// Sanitize the strings of prose fields, `key` being the field `value` is
+/// in, or the list it is an item of
+fn walk(value: &mut Value, key: &str, rules: &mut [CompiledRule], changed: &mut usize) {
+    match value {
+        Value::String(text) if PROSE_FIELDS.contains(&key) => {
+            if let Some(clean) = sanitize_text(text, rules) {
+                *text = clean;
+                *changed += 1;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                walk(item, key, rules, changed);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                walk(field, key, rules, changed);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Produce a cleaned copy of a story and a report of what was replaced
+pub fn sanitize(
+    mut export: StoryExport,
+    rules: &SanitizeRules,
+) -> Result<(StoryExport, SanitizeReport), String> {
+    let mut compiled = compile_rules(rules)?;
+    let private_notes_removed = if rules.private_notes {
+        strip_private_notes(&mut export)
+    } else {
+        0
+    };
+
+    let mut value =
+        serde_json::to_value(&export).map_err(|e| format!("Failed to serialize story: {}", e))?;
+    let mut fields_changed = 0;
+    walk(&mut value, "", &mut compiled, &mut fields_changed);
+    let export: StoryExport =
+        serde_json::from_value(value).map_err(|e| format!("Sanitized story is invalid: {}", e))?;
+
+    let replacements: Vec<RuleReplacements> = compiled
+        .into_iter()
+        .filter(|rule| rule.count > 0)
+        .map(|rule| RuleReplacements {
+            rule: rule.name,
+            action: rule.action,
+            count: rule.count,
+            distinct: rule.pseudonyms.len(),
+        })
+        .collect();
+    let report = SanitizeReport {
+        total_replacements: replacements.iter().map(|r| r.count).sum(),
+        replacements,
+        fields_changed,
+        private_notes_removed,
+    };
+    Ok((export, report))
+}
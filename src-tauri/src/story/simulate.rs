@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use super::graph::{GraphEdge, StoryGraph};
+use crate::rng::SplitMix64;
+
+/// Upper bound on playthroughs in a single simulation
+const MAX_PLAYTHROUGHS: usize = 10_000;
+
+/// How playthroughs choose between branches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SimulationMode {
+    /// Pick a random outgoing edge at every fork
+    #[default]
+    Random,
+    /// Enumerate distinct paths depth-first
+    Exhaustive,
+}
+
+/// Where playthroughs finished
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndingReport {
+    pub entry_id: String,
+    pub branch_id: Option<String>,
+    pub entry_type: String,
+    pub count: usize,
+    /// True when the story stops without a narration reply
+    pub dead_end: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationReport {
+    pub playthroughs: usize,
+    pub average_path_length: f64,
+    pub shortest_path: usize,
+    pub longest_path: usize,
+    pub endings: Vec<EndingReport>,
+    pub dead_ends: Vec<String>,
+    /// Entries no playthrough visited
+    pub unvisited: Vec<String>,
+    /// Entries the graph cannot reach at all
+    pub unreachable: Vec<String>,
+    /// True when exhaustive mode stopped before covering every path
+    pub truncated: bool,
+}
+
+/// Walk the story graph from its root, collecting each path as a list of entry IDs.
+/// Paths never revisit an entry, so cyclic graphs still terminate.
+pub fn simulate(
+    graph: &StoryGraph,
+    runs: usize,
+    seed: u64,
+    mode: SimulationMode,
+) -> SimulationReport {
+    let runs = runs.clamp(1, MAX_PLAYTHROUGHS);
+    let adjacency = graph.adjacency();
+    let mut paths = Vec::new();
+    let mut truncated = false;
+
+    if let Some(root) = graph.root.as_deref() {
+        match mode {
+            SimulationMode::Random => {
+                let mut rng = SplitMix64(seed);
+                for _ in 0..runs {
+                    paths.push(random_walk(root, &adjacency, &mut rng));
+                }
+            }
+            SimulationMode::Exhaustive => {
+                truncated = enumerate_paths(root, &adjacency, runs, &mut paths);
+            }
+        }
+    }
+
+    summarize(graph, &paths, truncated)
+}
+
+fn random_walk<'a>(
+    root: &'a str,
+    adjacency: &HashMap<&'a str, Vec<&'a GraphEdge>>,
+    rng: &mut SplitMix64,
+) -> Vec<&'a str> {
+    let mut path = vec![root];
+    let mut visited: HashSet<&str> = HashSet::from([root]);
+    let mut current = root;
+    loop {
+        let options: Vec<&str> = adjacency
+            .get(current)
+            .into_iter()
+            .flatten()
+            .map(|e| e.to.as_str())
+            .filter(|id| !visited.contains(id))
+            .collect();
+        if options.is_empty() {
+            return path;
+        }
+        current = options[rng.below(options.len())];
+        visited.insert(current);
+        path.push(current);
+    }
+}
+
+/// Depth-first enumeration of paths; returns true if the limit cut it short
+fn enumerate_paths<'a>(
+    root: &'a str,
+    adjacency: &HashMap<&'a str, Vec<&'a GraphEdge>>,
+    limit: usize,
+    paths: &mut Vec<Vec<&'a str>>,
+) -> bool {
+    let mut stack: Vec<Vec<&str>> = vec![vec![root]];
+    while let Some(path) = stack.pop() {
+        let Some(&last) = path.last() else {
+            continue;
+        };
+        let next: Vec<&str> = adjacency
+            .get(last)
+            .into_iter()
+            .flatten()
+            .map(|e| e.to.as_str())
+            .filter(|id| !path.contains(id))
+            .collect();
+        if next.is_empty() {
+            if paths.len() == limit {
+                return true;
+            }
+            paths.push(path);
+            continue;
+        }
+        for id in next.into_iter().rev() {
+            let mut extended = path.clone();
+            extended.push(id);
+            stack.push(extended);
+        }
+    }
+    false
+}
+
+fn summarize(graph: &StoryGraph, paths: &[Vec<&str>], truncated: bool) -> SimulationReport {
+    let nodes: HashMap<&str, _> = graph.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut visited: HashSet<&str> = HashSet::new();
+    let mut ending_counts: HashMap<&str, usize> = HashMap::new();
+
+    for path in paths {
+        visited.extend(path.iter().copied());
+        if let Some(&last) = path.last() {
+            *ending_counts.entry(last).or_default() += 1;
+        }
+    }
+
+    let mut endings: Vec<EndingReport> = ending_counts
+        .into_iter()
+        .filter_map(|(id, count)| {
+            let node = nodes.get(id)?;
+            Some(EndingReport {
+                entry_id: node.id.clone(),
+                branch_id: node.branch_id.clone(),
+                entry_type: node.entry_type.clone(),
+                count,
+                dead_end: node.entry_type != "narration",
+            })
+        })
+        .collect();
+    endings.sort_by(|a, b| b.count.cmp(&a.count).then(a.entry_id.cmp(&b.entry_id)));
+
+    let lengths: Vec<usize> = paths.iter().map(Vec::len).collect();
+    let average_path_length = if lengths.is_empty() {
+        0.0
+    } else {
+        lengths.iter().sum::<usize>() as f64 / lengths.len() as f64
+    };
+
+    SimulationReport {
+        playthroughs: paths.len(),
+        average_path_length,
+        shortest_path: lengths.iter().copied().min().unwrap_or(0),
+        longest_path: lengths.iter().copied().max().unwrap_or(0),
+        dead_ends: endings
+            .iter()
+            .filter(|e| e.dead_end)
+            .map(|e| e.entry_id.clone())
+            .collect(),
+        endings,
+        unvisited: graph
+            .nodes
+            .iter()
+            .filter(|n| !visited.contains(n.id.as_str()))
+            .map(|n| n.id.clone())
+            .collect(),
+        unreachable: graph.issues.unreachable.clone(),
+        truncated,
+    }
+}
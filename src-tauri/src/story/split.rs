@@ -0,0 +1,367 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+use super::text::plain_text;
+use super::types::StoryEntry;
+use super::StoryExport;
+
+/// Key in `story` linking the parts of a split story, kept in the stories
+/// table's `series` column
+const SERIES_KEY: &str = "series";
+
+/// Where a part sits in a split story, stored on each part's story record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesLink {
+    pub id: String,
+    pub title: String,
+    pub part: usize,
+    pub previous_story_id: Option<String>,
+    pub next_story_id: Option<String>,
+}
+
+fn mentions(text: &str, names: impl IntoIterator<Item = impl AsRef<str>>) -> bool {
+    names.into_iter().any(|name| {
+        let name = name.as_ref().trim().to_lowercase();
+        !name.is_empty() && text.contains(&name)
+    })
+}
+
+fn new_story_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Break a story into parts, each starting at one of `at_entry_ids` on the main
+/// branch. Branches go with the part containing their fork entry. Characters,
+/// locations, items and lorebook entries are copied into every part that
+/// mentions them; images go with their entries. Any no part takes go with the
+/// first part.
+pub fn split(export: &StoryExport, at_entry_ids: &[String]) -> Result<Vec<StoryExport>, String> {
+    let mut main: Vec<&StoryEntry> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none())
+        .collect();
+    main.sort_by_key(|e| e.position);
+
+    let cuts: HashSet<&str> = at_entry_ids.iter().map(String::as_str).collect();
+    if let Some(missing) = cuts.iter().find(|id| !main.iter().any(|e| e.id == **id)) {
+        return Err(format!("Entry {} is not on the main branch", missing));
+    }
+
+    let mut parts: Vec<Vec<&StoryEntry>> = vec![Vec::new()];
+    for entry in main {
+        if cuts.contains(entry.id.as_str()) && !parts.last().is_some_and(Vec::is_empty) {
+            parts.push(Vec::new());
+        }
+        if let Some(part) = parts.last_mut() {
+            part.push(entry);
+        }
+    }
+    if parts.len() < 2 {
+        return Err("Split points must leave at least two non-empty parts".to_string());
+    }
+
+    let part_of: HashMap<&str, usize> = parts
+        .iter()
+        .enumerate()
+        .flat_map(|(i, entries)| entries.iter().map(move |e| (e.id.as_str(), i)))
+        .collect();
+
+    // A branch belongs where it forks; nested branches follow their parent
+    let mut branch_part: HashMap<&str, usize> = HashMap::new();
+    let mut pending: Vec<_> = export.branches.iter().collect();
+    while !pending.is_empty() {
+        let before = pending.len();
+        pending.retain(|branch| {
+            let part = part_of
+                .get(branch.fork_entry_id.as_str())
+                .copied()
+                .or_else(|| {
+                    let fork = export
+                        .entries
+                        .iter()
+                        .find(|e| e.id == branch.fork_entry_id)?;
+                    branch_part.get(fork.branch_id.as_deref()?).copied()
+                });
+            match part {
+                Some(part) => {
+                    branch_part.insert(branch.id.as_str(), part);
+                    false
+                }
+                None => true,
+            }
+        });
+        if pending.len() == before {
+            break;
+        }
+    }
+
+    let series_id = new_story_id();
+    let ids: Vec<String> = parts.iter().map(|_| new_story_id()).collect();
+    let total = parts.len();
+
+    let mut result = Vec::with_capacity(total);
+    for (index, main_entries) in parts.into_iter().enumerate() {
+        let story_id = &ids[index];
+        let mut entries: Vec<StoryEntry> = main_entries.into_iter().cloned().collect();
+        entries.extend(
+            export
+                .entries
+                .iter()
+                .filter(|e| {
+                    e.branch_id
+                        .as_deref()
+                        .and_then(|b| branch_part.get(b))
+                        .is_some_and(|p| *p == index)
+                })
+                .cloned(),
+        );
+        for entry in &mut entries {
+            entry.story_id = story_id.clone();
+        }
+        let entry_ids: HashSet<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+        let text = entries
+            .iter()
+            .map(|e| plain_text(&e.content).to_lowercase())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let chapters: Vec<_> = export
+            .chapters
+            .iter()
+            .filter(|c| entry_ids.contains(c.start_entry_id.as_str()))
+            .cloned()
+            .collect();
+        let title = match chapters.iter().min_by_key(|c| c.number) {
+            Some(first) if first.title.is_some() => format!(
+                "{}: {}",
+                export.story.title,
+                first.title.as_deref().unwrap_or_default()
+            ),
+            _ => format!("{} (Part {})", export.story.title, index + 1),
+        };
+
+        let mut part = StoryExport {
+            version: export.version.clone(),
+            exported_at: export.exported_at,
+            story: export.story.clone(),
+            characters: export
+                .characters
+                .iter()
+                .filter(|c| mentions(&text, [&c.name]))
+                .cloned()
+                .collect(),
+            locations: export
+                .locations
+                .iter()
+                .filter(|l| mentions(&text, [&l.name]))
+                .cloned()
+                .collect(),
+            items: export
+                .items
+                .iter()
+                .filter(|i| mentions(&text, [&i.name]))
+                .cloned()
+                .collect(),
+            story_beats: export.story_beats.clone(),
+            lorebook_entries: export
+                .lorebook_entries
+                .iter()
+                .filter(|l| mentions(&text, std::iter::once(&l.name).chain(&l.aliases)))
+                .cloned()
+                .collect(),
+            chapters,
+            branches: export
+                .branches
+                .iter()
+                .filter(|b| branch_part.get(b.id.as_str()) == Some(&index))
+                .cloned()
+                .collect(),
+            embedded_images: export
+                .embedded_images
+                .iter()
+                .filter(|i| entry_ids.contains(i.entry_id.as_str()))
+                .cloned()
+                .collect(),
+            entries,
+            extra: export.extra.clone(),
+        };
+        part.story.id = story_id.clone();
+        part.story.title = title;
+        part.story.extra.insert(
+            SERIES_KEY.to_string(),
+            json!(SeriesLink {
+                id: series_id.clone(),
+                title: export.story.title.clone(),
+                part: index + 1,
+                previous_story_id: index.checked_sub(1).map(|i| ids[i].clone()),
+                next_story_id: ids.get(index + 1).cloned(),
+            }),
+        );
+        result.push(part);
+    }
+
+    keep_unplaced(
+        &export.characters,
+        &mut result,
+        |c| &c.id,
+        |p| &mut p.characters,
+    );
+    keep_unplaced(
+        &export.locations,
+        &mut result,
+        |l| &l.id,
+        |p| &mut p.locations,
+    );
+    keep_unplaced(&export.items, &mut result, |i| &i.id, |p| &mut p.items);
+    keep_unplaced(
+        &export.lorebook_entries,
+        &mut result,
+        |l| &l.id,
+        |p| &mut p.lorebook_entries,
+    );
+    keep_unplaced(
+        &export.embedded_images,
+        &mut result,
+        |i| &i.id,
+        |p| &mut p.embedded_images,
+    );
+    Ok(result)
+}
+
+/// Give the first part the records no part took, such as lorebook entries
+/// no part mentions, so a split never loses any
+fn keep_unplaced<T: Clone>(
+    records: &[T],
+    parts: &mut [StoryExport],
+    id: impl Fn(&T) -> &String,
+    field: impl Fn(&mut StoryExport) -> &mut Vec<T>,
+) {
+    let placed: HashSet<String> = parts
+        .iter_mut()
+        .flat_map(|part| field(part).iter().map(&id).cloned().collect::<Vec<_>>())
+        .collect();
+    let unplaced = records.iter().filter(|r| !placed.contains(id(r))).cloned();
+    if let Some(first) = parts.first_mut() {
+        field(first).extend(unplaced);
+    }
+}
+
+/// Keep the first record per ID, then per case-insensitive name, so entities
+/// copied into several parts come back once
+fn dedupe<T>(items: Vec<T>, id: impl Fn(&T) -> &str, name: impl Fn(&T) -> &str) -> Vec<T> {
+    let mut ids = HashSet::new();
+    let mut names = HashSet::new();
+    items
+        .into_iter()
+        .filter(|item| {
+            let fresh_id = ids.insert(id(item).to_string());
+            let fresh_name = names.insert(name(item).trim().to_lowercase());
+            fresh_id && fresh_name
+        })
+        .collect()
+}
+
+/// Join stories end to end into a new story, in the given order. Chapters are
+/// renumbered and entities shared between parts are merged.
+pub fn combine(stories: Vec<StoryExport>) -> Result<StoryExport, String> {
+    let mut stories = stories.into_iter();
+    let mut combined = stories
+        .next()
+        .ok_or_else(|| "No stories to combine".to_string())?;
+    let series = series_link(&combined);
+    combined.story.extra.remove(SERIES_KEY);
+
+    let mut same_series = series.is_some();
+    for story in stories {
+        same_series &= series_link(&story).map(|l| l.id) == series.as_ref().map(|s| s.id.clone());
+
+        let offset = combined
+            .entries
+            .iter()
+            .filter(|e| e.branch_id.is_none())
+            .map(|e| e.position + 1)
+            .max()
+            .unwrap_or(0);
+        let base = story
+            .entries
+            .iter()
+            .filter(|e| e.branch_id.is_none())
+            .map(|e| e.position)
+            .min()
+            .unwrap_or(0);
+        let chapter_offset = combined
+            .chapters
+            .iter()
+            .map(|c| c.number)
+            .max()
+            .unwrap_or(0);
+        let first_chapter = story.chapters.iter().map(|c| c.number).min().unwrap_or(1);
+
+        combined
+            .entries
+            .extend(story.entries.into_iter().map(|mut e| {
+                if e.branch_id.is_none() {
+                    e.position += offset - base;
+                }
+                e
+            }));
+        combined
+            .chapters
+            .extend(story.chapters.into_iter().map(|mut c| {
+                c.number = chapter_offset + c.number - first_chapter + 1;
+                c
+            }));
+        combined.characters.extend(story.characters);
+        combined.locations.extend(story.locations);
+        combined.items.extend(story.items);
+        combined.story_beats.extend(story.story_beats);
+        combined.lorebook_entries.extend(story.lorebook_entries);
+        combined.branches.extend(story.branches);
+        combined.embedded_images.extend(story.embedded_images);
+        combined.story.updated_at = combined.story.updated_at.max(story.story.updated_at);
+    }
+
+    combined.characters = dedupe(combined.characters, |c| &c.id, |c| &c.name);
+    combined.locations = dedupe(combined.locations, |l| &l.id, |l| &l.name);
+    combined.items = dedupe(combined.items, |i| &i.id, |i| &i.name);
+    combined.story_beats = dedupe(combined.story_beats, |b| &b.id, |b| &b.id);
+    combined.lorebook_entries = dedupe(combined.lorebook_entries, |l| &l.id, |l| &l.name);
+    combined.branches = dedupe(combined.branches, |b| &b.id, |b| &b.id);
+    combined.embedded_images = dedupe(combined.embedded_images, |i| &i.id, |i| &i.id);
+
+    // The frontend importer assigns fresh IDs to everything else
+    let story_id = new_story_id();
+    for entry in &mut combined.entries {
+        entry.story_id = story_id.clone();
+    }
+    combined.story.id = story_id;
+    if let Some(series) = series.filter(|_| same_series) {
+        combined.story.title = series.title;
+    }
+    Ok(combined)
+}
+
+/// Order parsed stories by the given story IDs; stories not listed keep their
+/// relative order at the end
+pub fn order_stories(mut stories: Vec<StoryExport>, order: &[String]) -> Vec<StoryExport> {
+    let rank: HashMap<&str, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_str(), i))
+        .collect();
+    stories.sort_by_key(|s| rank.get(s.story.id.as_str()).copied().unwrap_or(usize::MAX));
+    stories
+}
+
+/// The series link of a part produced by `split`
+pub fn series_link(export: &StoryExport) -> Option<SeriesLink> {
+    export
+        .story
+        .extra
+        .get(SERIES_KEY)
+        .cloned()
+        .and_then(|v: Value| serde_json::from_value(v).ok())
+}
@@ -0,0 +1,21 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Strip HTML tags and Markdown emphasis from entry content.
+/// Entries written in visual prose mode contain HTML; regular entries use Markdown.
+pub fn plain_text(content: &str) -> String {
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    static EMPHASIS: OnceLock<Regex> = OnceLock::new();
+    let tags = TAGS.get_or_init(|| Regex::new(r"(?s)<style.*?</style>|<[^>]+>").unwrap());
+    let emphasis = EMPHASIS.get_or_init(|| Regex::new(r"(?m)^#+\s*|\*{1,3}|_{2,3}").unwrap());
+
+    let without_tags = tags.replace_all(content, "");
+    let text = emphasis.replace_all(&without_tags, "");
+    text.replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,636 @@
+//! Story chronology. Each main branch entry is placed on an in-story clock,
+//! from the time tracker when the entry recorded it, otherwise from phrases
+//! like "three days later" or "the next morning", or from the model when
+//! asked. What was read from each entry is saved with a hash of the entry,
+//! so later runs only look again at entries that changed.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tauri::AppHandle;
+
+use super::entities::EntityIndex;
+use super::text::plain_text;
+use super::types::StoryEntry;
+use super::StoryExport;
+use crate::ai::proxy::{complete_chat, json_object};
+use crate::ai::types::{ChatMessage, ProviderConfig, SamplingParams};
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Directory in the app data directory holding each story's timeline
+pub const TIMELINES_DIR: &str = "timelines";
+
+/// Bumped when marker extraction changes, so saved readings are redone
+const TIMELINE_FORMAT: u32 = 1;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// Length of the summary of entries the model did not summarise
+const SUMMARY_CHARS: usize = 160;
+
+/// Entries sent to the model per request
+const MODEL_BATCH: usize = 10;
+
+/// Text of each entry sent to the model
+const MODEL_ENTRY_CHARS: usize = 1_500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkerKind {
+    /// Time passing, like "three days later"
+    Elapsed,
+    /// A move to the next day, like "the next morning" or "overnight"
+    NextDay,
+    /// A time of day, like "that evening"
+    TimeOfDay,
+    Weekday,
+    Date,
+    /// Events from before the story's present, like "years ago"
+    Flashback,
+    /// Events alongside the previous ones, like "meanwhile"
+    Simultaneous,
+}
+
+/// A phrase placing an entry in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemporalMarker {
+    pub text: String,
+    pub kind: MarkerKind,
+    /// Time passed for elapsed markers; minute of the day for times of day
+    pub minutes: Option<i64>,
+}
+
+/// Where an event's time came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimeSource {
+    /// The story's time tracker, recorded on the entry
+    Tracked,
+    Markers,
+    Model,
+    /// Nothing placed it; it follows the entry before
+    Inferred,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineEvent {
+    pub entry_id: String,
+    pub position: i64,
+    pub chapter: Option<i64>,
+    /// In-story minutes since the start of the story
+    pub elapsed_minutes: i64,
+    /// Starts at 1
+    pub day: i64,
+    pub time_of_day: Option<String>,
+    pub source: TimeSource,
+    pub markers: Vec<TemporalMarker>,
+    /// Told out of order, before the story's present
+    pub flashback: bool,
+    /// Names of characters and places in the entry
+    pub entities: Vec<String>,
+    pub summary: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timeline {
+    pub story_id: String,
+    pub events: Vec<TimelineEvent>,
+    pub days: i64,
+    pub updated_at: i64,
+    /// Entries read again in the last update because they were new or changed
+    pub refreshed: usize,
+}
+
+/// The model's reading of one entry
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ModelReading {
+    summary: String,
+    /// In-story minutes since the entry before
+    elapsed_minutes: Option<i64>,
+    time_of_day: Option<String>,
+    flashback: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelReply {
+    /// Keyed by entry ID
+    entries: HashMap<String, ModelReading>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EntryReading {
+    hash: String,
+    markers: Vec<TemporalMarker>,
+    #[serde(default)]
+    model: Option<ModelReading>,
+}
+
+/// A timeline as saved, with what was read from each entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SavedTimeline {
+    format: u32,
+    readings: HashMap<String, EntryReading>,
+    timeline: Timeline,
+}
+
+fn timeline_path(app: &AppHandle, story_id: &str) -> Result<PathBuf, String> {
+    if story_id.is_empty() || story_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid story ID: {}", story_id));
+    }
+    let dir = store::data_file(app, TIMELINES_DIR)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create timelines directory: {}", e))?;
+    Ok(dir.join(format!("{}.json", story_id)))
+}
+
+fn load_saved(app: &AppHandle, story_id: &str) -> Result<Option<SavedTimeline>, String> {
+    let path = timeline_path(app, story_id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = fs::read_to_string(&path).map_err(|e| format!("Failed to read timeline: {}", e))?;
+    Ok(serde_json::from_str::<SavedTimeline>(&json)
+        .ok()
+        .filter(|saved| saved.format == TIMELINE_FORMAT))
+}
+
+fn save(app: &AppHandle, saved: &SavedTimeline) -> Result<(), String> {
+    let path = timeline_path(app, &saved.timeline.story_id)?;
+    let json =
+        serde_json::to_string(saved).map_err(|e| format!("Failed to serialize timeline: {}", e))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write timeline: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to save timeline: {}", e))
+}
+
+/// Time tracker values recorded on an entry by the frontend
+fn tracked(entry: &StoryEntry, field: &str) -> Option<i64> {
+    let time = entry.extra.get("metadata")?.get(field)?;
+    let part = |name: &str| time.get(name).and_then(Value::as_i64).unwrap_or(0);
+    Some(((part("years") * 365 + part("days")) * 24 + part("hours")) * 60 + part("minutes"))
+}
+
+fn entry_hash(entry: &StoryEntry) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(entry.content.as_bytes());
+    hasher.update([0]);
+    for field in ["timeStart", "timeEnd"] {
+        hasher.update(tracked(entry, field).unwrap_or(-1).to_le_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+const NUMBER: &str =
+    r"\d+|an?|one|two|three|four|five|six|seven|eight|nine|ten|eleven|twelve|a few|several";
+
+fn number_value(word: &str) -> i64 {
+    match word.to_lowercase().as_str() {
+        "a" | "an" | "one" => 1,
+        "two" => 2,
+        "a few" | "three" => 3,
+        "four" => 4,
+        "five" => 5,
+        "several" | "six" => 6,
+        "seven" => 7,
+        "eight" => 8,
+        "nine" => 9,
+        "ten" => 10,
+        "eleven" => 11,
+        "twelve" => 12,
+        digits => digits.parse::<i64>().map_or(1, |n| n.min(10_000)),
+    }
+}
+
+fn unit_minutes(unit: &str) -> i64 {
+    match unit.to_lowercase().trim_end_matches('s') {
+        "moment" => 1,
+        "minute" => 1,
+        "hour" => 60,
+        "day" => MINUTES_PER_DAY,
+        "week" => 7 * MINUTES_PER_DAY,
+        "month" => 30 * MINUTES_PER_DAY,
+        _ => 365 * MINUTES_PER_DAY,
+    }
+}
+
+fn time_of_day_minute(word: &str) -> i64 {
+    match word.to_lowercase().as_str() {
+        "dawn" | "daybreak" | "sunrise" => 6 * 60,
+        "morning" => 8 * 60,
+        "noon" | "midday" => 12 * 60,
+        "afternoon" => 15 * 60,
+        "dusk" | "sunset" | "evening" => 19 * 60,
+        "midnight" => 24 * 60 - 1,
+        _ => 22 * 60,
+    }
+}
+
+fn time_of_day_label(minute: i64) -> &'static str {
+    match minute.rem_euclid(MINUTES_PER_DAY) / 60 {
+        5..=6 => "dawn",
+        7..=11 => "morning",
+        12..=13 => "midday",
+        14..=17 => "afternoon",
+        18..=20 => "evening",
+        _ => "night",
+    }
+}
+
+struct Patterns {
+    counted: Regex,
+    vague: Regex,
+    next_day: Regex,
+    time_of_day: Regex,
+    weekday: Regex,
+    date: Regex,
+    flashback: Regex,
+    simultaneous: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let months = "January|February|March|April|May|June|July|August|September|October|November|December";
+        Patterns {
+            counted: Regex::new(&format!(
+                r"(?i)\b({})\s+(minute|hour|day|week|month|year)s?\s+(?:later|passed|went by|had passed|afterwards?)\b",
+                NUMBER
+            ))
+            .unwrap(),
+            vague: Regex::new(r"(?i)\b(moments|minutes|hours|days|weeks|months|years)\s+later\b")
+                .unwrap(),
+            next_day: Regex::new(r"(?i)\b(?:the next day|the following day|overnight|the day after)\b")
+                .unwrap(),
+            time_of_day: Regex::new(
+                r"(?i)\b(that|this|the next|the following|next|in the|at|by|until|later that)\s+(dawn|daybreak|sunrise|morning|noon|midday|afternoon|dusk|sunset|evening|night|midnight)\b",
+            )
+            .unwrap(),
+            weekday: Regex::new(r"\b(?:Monday|Tuesday|Wednesday|Thursday|Friday|Saturday|Sunday)\b")
+                .unwrap(),
+            date: Regex::new(&format!(
+                r"\b(?:(?:{m})\s+\d{{1,2}}(?:st|nd|rd|th)?|\d{{1,2}}(?:st|nd|rd|th)?\s+of\s+(?:{m}))\b",
+                m = months
+            ))
+            .unwrap(),
+            flashback: Regex::new(&format!(
+                r"(?i)\b(?:(?:{})\s+(?:day|week|month|year)s?\s+(?:ago|earlier|before)|years ago|back when|remembered when|in (?:his|her|their|your|my) (?:childhood|youth))\b",
+                NUMBER
+            ))
+            .unwrap(),
+            simultaneous: Regex::new(r"(?i)\b(?:meanwhile|at the same time|elsewhere)\b").unwrap(),
+        }
+    })
+}
+
+/// Temporal phrases in an entry, in the order they appear
+fn markers(text: &str) -> Vec<TemporalMarker> {
+    let p = patterns();
+    let mut found: Vec<(usize, TemporalMarker)> = Vec::new();
+    let mut push = |start: usize, text: &str, kind: MarkerKind, minutes: Option<i64>| {
+        found.push((
+            start,
+            TemporalMarker {
+                text: text.to_string(),
+                kind,
+                minutes,
+            },
+        ));
+    };
+    // Spans already read, so "three days later" is not also "days later"
+    let mut taken: Vec<(usize, usize)> = Vec::new();
+    let overlaps = |taken: &[(usize, usize)], start: usize, end: usize| {
+        taken.iter().any(|(s, e)| start < *e && *s < end)
+    };
+    for m in p.flashback.find_iter(text) {
+        taken.push((m.start(), m.end()));
+        push(m.start(), m.as_str(), MarkerKind::Flashback, None);
+    }
+    for c in p.counted.captures_iter(text) {
+        let whole = c.get(0).unwrap();
+        if overlaps(&taken, whole.start(), whole.end()) {
+            continue;
+        }
+        taken.push((whole.start(), whole.end()));
+        let minutes = number_value(&c[1]) * unit_minutes(&c[2]);
+        push(
+            whole.start(),
+            whole.as_str(),
+            MarkerKind::Elapsed,
+            Some(minutes),
+        );
+    }
+    for c in p.vague.captures_iter(text) {
+        let whole = c.get(0).unwrap();
+        if overlaps(&taken, whole.start(), whole.end()) {
+            continue;
+        }
+        // "Moments later" is a moment; "days later" is taken as a few days
+        let count = if c[1].eq_ignore_ascii_case("moments") {
+            1
+        } else {
+            3
+        };
+        push(
+            whole.start(),
+            whole.as_str(),
+            MarkerKind::Elapsed,
+            Some(count * unit_minutes(&c[1])),
+        );
+    }
+    for m in p.next_day.find_iter(text) {
+        push(m.start(), m.as_str(), MarkerKind::NextDay, None);
+    }
+    for c in p.time_of_day.captures_iter(text) {
+        let whole = c.get(0).unwrap();
+        let prefix = c[1].to_lowercase();
+        if matches!(prefix.as_str(), "the next" | "the following" | "next") {
+            push(whole.start(), whole.as_str(), MarkerKind::NextDay, None);
+        }
+        push(
+            whole.start(),
+            whole.as_str(),
+            MarkerKind::TimeOfDay,
+            Some(time_of_day_minute(&c[2])),
+        );
+    }
+    for m in p.weekday.find_iter(text) {
+        push(m.start(), m.as_str(), MarkerKind::Weekday, None);
+    }
+    for m in p.date.find_iter(text) {
+        push(m.start(), m.as_str(), MarkerKind::Date, None);
+    }
+    for m in p.simultaneous.find_iter(text) {
+        push(m.start(), m.as_str(), MarkerKind::Simultaneous, None);
+    }
+    found.sort_by_key(|(start, _)| *start);
+    found.into_iter().map(|(_, marker)| marker).collect()
+}
+
+/// Move the clock forward to the next time the day reaches `minute`
+fn advance_to(clock: i64, minute: i64, next_day: bool) -> i64 {
+    let day_start = clock.div_euclid(MINUTES_PER_DAY) * MINUTES_PER_DAY;
+    let mut target = day_start + minute;
+    if next_day {
+        target += MINUTES_PER_DAY;
+    }
+    if target < clock {
+        target += MINUTES_PER_DAY;
+    }
+    target
+}
+
+/// Place every entry on the clock, in story order
+fn build(
+    export: &StoryExport,
+    readings: &HashMap<String, EntryReading>,
+    refreshed: usize,
+) -> Timeline {
+    let index = EntityIndex::build(export);
+    let by_id: HashMap<&str, &StoryEntry> =
+        export.entries.iter().map(|e| (e.id.as_str(), e)).collect();
+    let mut named: HashMap<usize, Vec<String>> = HashMap::new();
+    for mention in &index.mentions {
+        let names = named.entry(mention.entry).or_default();
+        let name = &index.entities[mention.entity].name;
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+
+    let mut clock = 0;
+    let mut events = Vec::with_capacity(index.entries.len());
+    for (number, indexed) in index.entries.iter().enumerate() {
+        let Some(entry) = by_id.get(indexed.entry_id.as_str()) else {
+            continue;
+        };
+        let reading = readings.get(&entry.id);
+        let markers = reading.map(|r| r.markers.clone()).unwrap_or_default();
+        let model = reading.and_then(|r| r.model.as_ref());
+        let flashback = model.map_or_else(
+            || markers.iter().any(|m| m.kind == MarkerKind::Flashback),
+            |m| m.flashback,
+        );
+
+        let mut time_of_day = None;
+        let source;
+        let mut at = clock;
+        if let Some(start) = tracked(entry, "timeStart") {
+            source = TimeSource::Tracked;
+            at = start;
+            time_of_day = Some(time_of_day_label(start).to_string());
+            clock = tracked(entry, "timeEnd").unwrap_or(start).max(start);
+        } else if flashback {
+            source = if model.is_some() {
+                TimeSource::Model
+            } else {
+                TimeSource::Markers
+            };
+        } else if let Some(model) = model {
+            source = TimeSource::Model;
+            clock += model.elapsed_minutes.unwrap_or(0).max(0);
+            at = clock;
+            time_of_day = model.time_of_day.clone();
+        } else {
+            let mut next_day = false;
+            for marker in &markers {
+                match marker.kind {
+                    MarkerKind::Elapsed => clock += marker.minutes.unwrap_or(0),
+                    MarkerKind::NextDay => next_day = true,
+                    MarkerKind::TimeOfDay => {
+                        let minute = marker.minutes.unwrap_or(0);
+                        clock = advance_to(clock, minute, next_day);
+                        next_day = false;
+                        time_of_day = Some(time_of_day_label(minute).to_string());
+                    }
+                    _ => {}
+                }
+            }
+            if next_day {
+                clock = advance_to(clock, 8 * 60, true);
+            }
+            at = clock;
+            source = if markers.is_empty() {
+                TimeSource::Inferred
+            } else {
+                TimeSource::Markers
+            };
+        }
+
+        let summary = model
+            .map(|m| m.summary.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| {
+                plain_text(&entry.content)
+                    .chars()
+                    .take(SUMMARY_CHARS)
+                    .collect()
+            });
+        events.push(TimelineEvent {
+            entry_id: entry.id.clone(),
+            position: entry.position,
+            chapter: indexed.chapter,
+            elapsed_minutes: at,
+            day: at.div_euclid(MINUTES_PER_DAY) + 1,
+            time_of_day,
+            source,
+            markers,
+            flashback,
+            entities: named.remove(&number).unwrap_or_default(),
+            summary,
+        });
+    }
+
+    Timeline {
+        story_id: export.story.id.clone(),
+        days: clock.div_euclid(MINUTES_PER_DAY) + 1,
+        events,
+        updated_at: now_ms(),
+        refreshed,
+    }
+}
+
+/// Markers for every main branch entry, reusing readings of unchanged ones.
+/// Returns the readings and how many entries were read again.
+fn read_entries(
+    export: &StoryExport,
+    previous: Option<SavedTimeline>,
+) -> (HashMap<String, EntryReading>, usize) {
+    let mut previous = previous.map(|saved| saved.readings).unwrap_or_default();
+    let mut readings = HashMap::new();
+    let mut refreshed = 0;
+    for entry in &export.entries {
+        if entry.branch_id.is_some() || entry.entry_type == "system" {
+            continue;
+        }
+        let hash = entry_hash(entry);
+        let reading = match previous.remove(&entry.id) {
+            Some(reading) if reading.hash == hash => reading,
+            _ => {
+                refreshed += 1;
+                EntryReading {
+                    hash,
+                    markers: markers(&plain_text(&entry.content)),
+                    model: None,
+                }
+            }
+        };
+        readings.insert(entry.id.clone(), reading);
+    }
+    (readings, refreshed)
+}
+
+/// Have the model read entries it has not read yet, a batch at a time
+async fn read_with_model(
+    export: &StoryExport,
+    readings: &mut HashMap<String, EntryReading>,
+    provider: &ProviderConfig,
+) -> Result<(), String> {
+    let mut unread: Vec<&StoryEntry> = export
+        .entries
+        .iter()
+        .filter(|e| readings.get(&e.id).is_some_and(|r| r.model.is_none()))
+        .collect();
+    unread.sort_by_key(|e| e.position);
+    let sampling = SamplingParams {
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+    for batch in unread.chunks(MODEL_BATCH) {
+        let mut passages = String::new();
+        for entry in batch {
+            let text: String = plain_text(&entry.content)
+                .chars()
+                .take(MODEL_ENTRY_CHARS)
+                .collect();
+            passages.push_str(&format!("[{}]\n{}\n\n", entry.id, text));
+        }
+        let messages = vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: "You build timelines of stories. For each passage, given in story \
+                          order with its ID in brackets, reply with JSON only, in the form \
+                          {\"entries\": {\"<id>\": {\"summary\": string, \"elapsedMinutes\": \
+                          number or null, \"timeOfDay\": string or null, \"flashback\": \
+                          boolean}}}. The summary is one short sentence of what happens. \
+                          elapsedMinutes is in-story time passed since the passage before, \
+                          or null if the text gives no hint. timeOfDay is dawn, morning, \
+                          midday, afternoon, evening or night when the text says so. \
+                          flashback is true for passages set before the story's present."
+                    .to_string(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: passages,
+            },
+        ];
+        let reply = complete_chat(provider, &messages, &sampling).await?;
+        let json = json_object(&reply).ok_or("Timeline response contained no JSON")?;
+        let reply: ModelReply =
+            serde_json::from_str(json).map_err(|e| format!("Invalid timeline response: {}", e))?;
+        for (id, model) in reply.entries {
+            if let Some(reading) = readings.get_mut(&id) {
+                reading.model = Some(model);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Extract or update a story's timeline and save it. With a provider, the
+/// model reads the entries the rules leave unplaced and summarises each.
+pub async fn extract(
+    app: &AppHandle,
+    export: &StoryExport,
+    provider: Option<&ProviderConfig>,
+) -> Result<Timeline, String> {
+    let (mut readings, refreshed) = read_entries(export, load_saved(app, &export.story.id)?);
+    if let Some(provider) = provider {
+        read_with_model(export, &mut readings, provider).await?;
+    }
+    let timeline = build(export, &readings, refreshed);
+    save(
+        app,
+        &SavedTimeline {
+            format: TIMELINE_FORMAT,
+            readings,
+            timeline: timeline.clone(),
+        },
+    )?;
+    Ok(timeline)
+}
+
+/// Bring an already extracted timeline up to date with the story as it is
+/// now, reading only entries that changed. None for stories without a
+/// timeline, which are left alone.
+pub fn refresh(app: &AppHandle, export: &StoryExport) -> Result<Option<Timeline>, String> {
+    let Some(saved) = load_saved(app, &export.story.id)? else {
+        return Ok(None);
+    };
+    let before = saved.readings.len();
+    let unchanged = saved.timeline.clone();
+    let (readings, refreshed) = read_entries(export, Some(saved));
+    if refreshed == 0 && readings.len() == before {
+        return Ok(Some(unchanged));
+    }
+    let timeline = build(export, &readings, refreshed);
+    save(
+        app,
+        &SavedTimeline {
+            format: TIMELINE_FORMAT,
+            readings,
+            timeline: timeline.clone(),
+        },
+    )?;
+    Ok(Some(timeline))
+}
@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A story in Aventura export format (the same JSON the frontend writes to `.avt` files).
+/// Only the fields the backend works with are typed; everything else is kept in `extra`
+/// so a parsed export serializes back without losing data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryExport {
+    pub version: String,
+    #[serde(default)]
+    pub exported_at: i64,
+    pub story: Story,
+    #[serde(default)]
+    pub entries: Vec<StoryEntry>,
+    #[serde(default)]
+    pub characters: Vec<Character>,
+    #[serde(default)]
+    pub locations: Vec<Location>,
+    #[serde(default)]
+    pub items: Vec<Item>,
+    #[serde(default)]
+    pub story_beats: Vec<StoryBeat>,
+    #[serde(default)]
+    pub lorebook_entries: Vec<LorebookEntry>,
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+    #[serde(default)]
+    pub branches: Vec<Branch>,
+    #[serde(default)]
+    pub embedded_images: Vec<EmbeddedImage>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl StoryExport {
+    /// Parse an export from its JSON representation
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid story export: {}", e))
+    }
+
+    /// Serialize the export back to JSON
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| format!("Failed to serialize story: {}", e))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Story {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub created_at: i64,
+    #[serde(default)]
+    pub updated_at: i64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryEntry {
+    pub id: String,
+    #[serde(default)]
+    pub story_id: String,
+    /// "user_action", "narration", "system" or "retry"
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    pub content: String,
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub created_at: i64,
+    #[serde(default)]
+    pub branch_id: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Character {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub relationship: Option<String>,
+    #[serde(default)]
+    pub traits: Vec<String>,
+    #[serde(default)]
+    pub portrait: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub visited: bool,
+    #[serde(default)]
+    pub current: bool,
+    #[serde(default)]
+    pub connections: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub quantity: i64,
+    #[serde(default)]
+    pub equipped: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryBeat {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// "pending", "active", "completed" or "failed"
+    #[serde(default)]
+    pub status: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LorebookEntry {
+    pub id: String,
+    pub name: String,
+    /// "character", "location", "item", "faction", "concept" or "event"
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chapter {
+    pub id: String,
+    pub number: i64,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub start_entry_id: String,
+    pub end_entry_id: String,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Branch {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub parent_branch_id: Option<String>,
+    pub fork_entry_id: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddedImage {
+    pub id: String,
+    pub entry_id: String,
+    /// Text in the entry the image illustrates
+    #[serde(default)]
+    pub source_text: String,
+    /// Base64-encoded image data, empty until generation completes
+    #[serde(default)]
+    pub image_data: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use super::StoryExport;
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Directory in the app data directory holding saved story versions
+pub const STORY_VERSIONS_DIR: &str = "story_versions";
+
+/// A snapshot of a story kept before an operation replaced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryVersion {
+    pub id: String,
+    pub story_id: String,
+    pub title: String,
+    /// Why the version was kept (e.g. "Before merge")
+    pub label: String,
+    pub created_at: i64,
+}
+
+fn story_dir(app: &AppHandle, story_id: &str) -> Result<PathBuf, String> {
+    if story_id.is_empty() || story_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid story ID: {}", story_id));
+    }
+    let dir = store::data_file(app, STORY_VERSIONS_DIR)?.join(story_id);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create versions directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Save a copy of a story. The metadata sits next to the export so listing
+/// versions does not parse whole stories.
+pub fn save_version(
+    app: &AppHandle,
+    export: &StoryExport,
+    label: &str,
+) -> Result<StoryVersion, String> {
+    let created_at = now_ms();
+    let version = StoryVersion {
+        id: format!(
+            "{}-{}",
+            created_at,
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        ),
+        story_id: export.story.id.clone(),
+        title: export.story.title.clone(),
+        label: label.to_string(),
+        created_at,
+    };
+    let dir = story_dir(app, &version.story_id)?;
+    fs::write(dir.join(format!("{}.avt", version.id)), export.to_json()?)
+        .map_err(|e| format!("Failed to save story version: {}", e))?;
+    let meta = serde_json::to_string_pretty(&version)
+        .map_err(|e| format!("Failed to serialize story version: {}", e))?;
+    fs::write(dir.join(format!("{}.json", version.id)), meta)
+        .map_err(|e| format!("Failed to save story version: {}", e))?;
+    Ok(version)
+}
+
+/// Saved versions of a story, newest first
+pub fn list_versions(app: &AppHandle, story_id: &str) -> Result<Vec<StoryVersion>, String> {
+    let dir = story_dir(app, story_id)?;
+    let read = fs::read_dir(&dir).map_err(|e| format!("Failed to read versions: {}", e))?;
+    let mut versions: Vec<StoryVersion> = read
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| fs::read_to_string(e.path()).ok())
+        .filter_map(|json| serde_json::from_str(&json).ok())
+        .collect();
+    versions.sort_by_key(|v| std::cmp::Reverse(v.created_at));
+    Ok(versions)
+}
+
+/// Story JSON of a saved version
+pub fn load_version(app: &AppHandle, story_id: &str, version_id: &str) -> Result<String, String> {
+    if version_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid version ID: {}", version_id));
+    }
+    fs::read_to_string(story_dir(app, story_id)?.join(format!("{}.avt", version_id)))
+        .map_err(|e| format!("Story version not found: {}", e))
+}
+
+/// Delete all but the newest `keep` versions of every story, returning how
+/// many bytes were freed. At least one version is always kept.
+pub fn prune(app: &AppHandle, keep: usize) -> Result<u64, String> {
+    if keep == 0 {
+        return Err("At least one version of each story must be kept".to_string());
+    }
+    let root = store::data_file(app, STORY_VERSIONS_DIR)?;
+    let Ok(stories) = fs::read_dir(&root) else {
+        return Ok(0);
+    };
+    let mut freed = 0;
+    for story in stories.flatten().filter(|e| e.path().is_dir()) {
+        let story_id = story.file_name().to_string_lossy().to_string();
+        for version in list_versions(app, &story_id)?.into_iter().skip(keep) {
+            for extension in ["avt", "json"] {
+                let path = story.path().join(format!("{}.{}", version.id, extension));
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                if fs::remove_file(&path).is_ok() {
+                    freed += size;
+                }
+            }
+        }
+    }
+    Ok(freed)
+}
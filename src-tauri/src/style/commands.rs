@@ -0,0 +1,75 @@
+use tauri::AppHandle;
+
+use super::{StyleGuide, StyleLibrary, StyleReference, STYLE_REFERENCES_FILE};
+use crate::ai::filter::Strictness;
+use crate::ai::profile;
+use crate::ai::types::ProviderConfig;
+use crate::profiles;
+use crate::store;
+
+/// Every style reference and which one is the default
+#[tauri::command]
+pub async fn list_style_references(app: AppHandle) -> Result<StyleLibrary, String> {
+    store::load_json(&app, STYLE_REFERENCES_FILE)
+}
+
+/// Create a style reference, or replace the name and passages of one.
+/// Its guide is kept until it is built again.
+#[tauri::command]
+pub async fn save_style_reference(
+    app: AppHandle,
+    style: StyleReference,
+) -> Result<StyleReference, String> {
+    let mut library: StyleLibrary = store::load_json(&app, STYLE_REFERENCES_FILE)?;
+    let saved = library.save(style)?;
+    store::save_json(&app, STYLE_REFERENCES_FILE, &library)?;
+    Ok(saved)
+}
+
+/// Delete a style reference no AI profile uses. Returns false if there was
+/// none.
+#[tauri::command]
+pub async fn delete_style_reference(app: AppHandle, id: String) -> Result<bool, String> {
+    let profiles = profile::load_profiles(&app).await?;
+    let users: Vec<&str> = profiles
+        .values()
+        .filter(|p| p.style_reference.as_deref() == Some(id.as_str()))
+        .map(|p| p.name.as_str())
+        .collect();
+    if !users.is_empty() {
+        return Err(format!("Style reference is used by {}", users.join(", ")));
+    }
+    let mut library: StyleLibrary = store::load_json(&app, STYLE_REFERENCES_FILE)?;
+    let removed = library.remove(&id);
+    if removed {
+        store::save_json(&app, STYLE_REFERENCES_FILE, &library)?;
+    }
+    Ok(removed)
+}
+
+/// Set the style used by stories whose AI profile names none, or clear it
+#[tauri::command]
+pub async fn set_default_style_reference(app: AppHandle, id: Option<String>) -> Result<(), String> {
+    let mut library: StyleLibrary = store::load_json(&app, STYLE_REFERENCES_FILE)?;
+    if let Some(ref id) = id {
+        library
+            .get(id)
+            .ok_or_else(|| format!("Style reference not found: {}", id))?;
+    }
+    library.default_style_id = id;
+    store::save_json(&app, STYLE_REFERENCES_FILE, &library)
+}
+
+/// Distill a style reference into a guide with the given provider. The
+/// cached guide is returned while the passages are unchanged, unless
+/// `force` is set.
+#[tauri::command]
+pub async fn build_style_guide(
+    app: AppHandle,
+    id: String,
+    provider: ProviderConfig,
+    force: Option<bool>,
+) -> Result<StyleGuide, String> {
+    profiles::check_generation(&app, &provider.base_url, Strictness::Off)?;
+    super::build_guide(&app, &id, &provider, force.unwrap_or(false)).await
+}
@@ -0,0 +1,300 @@
+//! Style references: sample passages of the voice a user wants stories
+//! written in. The model distills each reference into a short style guide,
+//! which is cached until the passages change and added to the system
+//! prompt of story requests. References travel with the AI profiles when
+//! settings are synced, guides included, so other devices need not build
+//! them again.
+
+pub mod commands;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+use crate::ai::profile;
+use crate::ai::proxy::complete_chat;
+use crate::ai::types::{ChatMessage, ProviderConfig, SamplingParams};
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Style references and the default one, in the app data directory
+pub const STYLE_REFERENCES_FILE: &str = "style_references.json";
+
+const MAX_STYLES: usize = 50;
+
+const MAX_PASSAGES: usize = 20;
+
+const MAX_PASSAGE_CHARS: usize = 5_000;
+
+/// Passage text sent when building a guide; later passages are left out
+const MAX_SOURCE_CHARS: usize = 30_000;
+
+/// Length the guide is asked to stay under
+const MAX_GUIDE_WORDS: usize = 250;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StylePassage {
+    #[serde(default)]
+    pub id: String,
+    pub text: String,
+    /// Where the passage is from, such as a book or an earlier story
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// What the model distilled from the passages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StyleGuide {
+    pub text: String,
+    /// Hash of the passages the guide was built from
+    pub source_hash: String,
+    pub model: String,
+    pub built_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StyleReference {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub passages: Vec<StylePassage>,
+    /// Kept after the passages change, until a new one is built
+    #[serde(default)]
+    pub guide: Option<StyleGuide>,
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+impl StyleReference {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Style reference needs a name".to_string());
+        }
+        if self.passages.len() > MAX_PASSAGES {
+            return Err(format!(
+                "A style reference takes at most {} passages",
+                MAX_PASSAGES
+            ));
+        }
+        for passage in &self.passages {
+            if passage.text.trim().is_empty() {
+                return Err("Style passages cannot be empty".to_string());
+            }
+            if passage.text.chars().count() > MAX_PASSAGE_CHARS {
+                return Err(format!(
+                    "Style passages are limited to {} characters",
+                    MAX_PASSAGE_CHARS
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn source_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for passage in &self.passages {
+            hasher.update(passage.text.trim().as_bytes());
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// The guide was built from the passages as they are now
+    pub fn guide_is_current(&self) -> bool {
+        self.guide
+            .as_ref()
+            .is_some_and(|g| g.source_hash == self.source_hash())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StyleLibrary {
+    #[serde(default)]
+    pub styles: Vec<StyleReference>,
+    /// Used by stories whose AI profile names no style
+    #[serde(default)]
+    pub default_style_id: Option<String>,
+}
+
+impl StyleLibrary {
+    pub fn get(&self, id: &str) -> Option<&StyleReference> {
+        self.styles.iter().find(|s| s.id == id)
+    }
+
+    /// Add or replace a reference. A guide is only ever written by
+    /// building one, so the stored guide is kept.
+    pub fn save(&mut self, mut style: StyleReference) -> Result<StyleReference, String> {
+        style.validate()?;
+        style.name = style.name.trim().to_string();
+        if style.id.is_empty() {
+            style.id = Uuid::new_v4().to_string();
+        }
+        for passage in &mut style.passages {
+            if passage.id.is_empty() {
+                passage.id = Uuid::new_v4().to_string();
+            }
+        }
+        style.updated_at = now_ms();
+        match self.styles.iter_mut().find(|s| s.id == style.id) {
+            Some(existing) => {
+                style.guide = existing.guide.take();
+                *existing = style.clone();
+            }
+            None => {
+                if self.styles.len() >= MAX_STYLES {
+                    return Err(format!("At most {} style references are kept", MAX_STYLES));
+                }
+                style.guide = None;
+                self.styles.push(style.clone());
+            }
+        }
+        Ok(style)
+    }
+
+    pub fn remove(&mut self, id: &str) -> bool {
+        let before = self.styles.len();
+        self.styles.retain(|s| s.id != id);
+        if self.default_style_id.as_deref() == Some(id) {
+            self.default_style_id = None;
+        }
+        self.styles.len() != before
+    }
+
+    /// Take references from another device, the more recently edited copy
+    /// winning. Returns how many were written.
+    pub fn merge(&mut self, incoming: StyleLibrary) -> usize {
+        let mut written = 0;
+        for style in incoming.styles {
+            match self.styles.iter_mut().find(|s| s.id == style.id) {
+                Some(existing) if existing.updated_at > style.updated_at => {}
+                Some(existing) => {
+                    *existing = style;
+                    written += 1;
+                }
+                None => {
+                    self.styles.push(style);
+                    written += 1;
+                }
+            }
+        }
+        if let Some(id) = incoming.default_style_id {
+            if self.get(&id).is_some() {
+                self.default_style_id = Some(id);
+            }
+        }
+        written
+    }
+}
+
+/// Style reference a story writes in: its AI profile's, or the default
+async fn story_style<'a>(
+    app: &AppHandle,
+    library: &'a StyleLibrary,
+    story_id: &str,
+) -> Result<Option<&'a StyleReference>, String> {
+    let id = profile::load_profile(app, story_id)
+        .await?
+        .and_then(|p| p.style_reference)
+        .or_else(|| library.default_style_id.clone());
+    Ok(id.and_then(|id| library.get(&id)))
+}
+
+/// The story's style guide for the system prompt, or empty when its style
+/// has no guide yet
+pub async fn context_text(app: &AppHandle, story_id: &str) -> Result<String, String> {
+    let library: StyleLibrary = store::load_json(app, STYLE_REFERENCES_FILE)?;
+    Ok(story_style(app, &library, story_id)
+        .await?
+        .and_then(|style| style.guide.as_ref())
+        .map(|guide| format!("[STYLE GUIDE]\n{}", guide.text.trim()))
+        .unwrap_or_default())
+}
+
+fn guide_messages(style: &StyleReference) -> Vec<ChatMessage> {
+    let mut source = String::new();
+    for (index, passage) in style.passages.iter().enumerate() {
+        let text = passage.text.trim();
+        if index > 0 && source.chars().count() + text.chars().count() > MAX_SOURCE_CHARS {
+            break;
+        }
+        source.push_str(&format!("--- Passage {} ---\n{}\n\n", index + 1, text));
+    }
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: format!(
+                "You describe an author's writing style so another writer can imitate it. \
+                 From the passages, write a style guide of at most {} words as short \
+                 imperative bullet points covering narrative voice and point of view, \
+                 tense, sentence length and rhythm, vocabulary and register, dialogue, \
+                 imagery and pacing. Describe how the author writes, never what happens \
+                 in the passages, and do not quote them. Reply with the bullet points only.",
+                MAX_GUIDE_WORDS
+            ),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: source.trim_end().to_string(),
+        },
+    ]
+}
+
+/// Distill a reference's passages into a style guide and keep it. The
+/// cached guide is returned while the passages are unchanged, unless a
+/// rebuild is forced.
+pub async fn build_guide(
+    app: &AppHandle,
+    style_id: &str,
+    provider: &ProviderConfig,
+    force: bool,
+) -> Result<StyleGuide, String> {
+    let library: StyleLibrary = store::load_json(app, STYLE_REFERENCES_FILE)?;
+    let style = library
+        .get(style_id)
+        .ok_or_else(|| format!("Style reference not found: {}", style_id))?;
+    if style.passages.is_empty() {
+        return Err("Add at least one passage before building a style guide".to_string());
+    }
+    if let Some(guide) = style.guide.as_ref().filter(|_| !force) {
+        if style.guide_is_current() {
+            return Ok(guide.clone());
+        }
+    }
+
+    let sampling = SamplingParams {
+        temperature: Some(0.3),
+        ..Default::default()
+    };
+    let text = complete_chat(provider, &guide_messages(style), &sampling).await?;
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("The model returned an empty style guide".to_string());
+    }
+    let guide = StyleGuide {
+        text: text.to_string(),
+        source_hash: style.source_hash(),
+        model: provider.model.clone(),
+        built_at: now_ms(),
+    };
+
+    // Passages edited while the model was writing make the guide stale
+    // before it is saved, so it is only kept for the passages it describes
+    let mut library: StyleLibrary = store::load_json(app, STYLE_REFERENCES_FILE)?;
+    let style = library
+        .styles
+        .iter_mut()
+        .find(|s| s.id == style_id)
+        .ok_or("Style reference was deleted while its guide was built")?;
+    if style.source_hash() != guide.source_hash {
+        return Err("Passages changed while the guide was built; build it again".to_string());
+    }
+    style.guide = Some(guide.clone());
+    store::save_json(app, STYLE_REFERENCES_FILE, &library)?;
+    Ok(guide)
+}
@@ -0,0 +1,277 @@
+//! Every HTTP request the sync server answered, so hosts can see exactly what
+//! other devices accessed. Recent requests are kept in memory while the
+//! server runs and all of them are appended to a log file by a writer
+//! thread, so requests never wait on the disk.
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use http_body::{Frame, SizeHint};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tauri::AppHandle;
+
+use super::keys::now_ms;
+use super::server::ServerState;
+use crate::store;
+
+/// Log file in the app data directory, one JSON entry per line
+pub const ACCESS_LOG_FILE: &str = "sync_access.log";
+
+/// Requests kept in memory while the server runs
+const RECENT_REQUESTS: usize = 1000;
+
+/// Size at which the log file is moved aside and a new one started
+const MAX_LOG_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Characters of a link token kept in logged paths
+const TOKEN_PREFIX_CHARS: usize = 6;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessLogEntry {
+    pub at: i64,
+    pub ip: String,
+    pub method: String,
+    /// Request path, with link tokens shortened
+    pub path: String,
+    /// Sync action, or the kind of page for other routes
+    pub action: String,
+    #[serde(default)]
+    pub story_id: Option<String>,
+    pub status: u16,
+    /// "ok", or why the request failed
+    pub result: String,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub duration_ms: u64,
+}
+
+/// What a handler knows about a request that the path does not say, passed
+/// to the log as a response extension
+#[derive(Debug, Clone, Default)]
+pub struct AccessNote {
+    pub action: Option<&'static str>,
+    pub story_id: Option<String>,
+    pub error: Option<String>,
+}
+
+impl AccessNote {
+    /// Attach the note to a response
+    pub fn on(self, mut response: Response) -> Response {
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    recent: Mutex<VecDeque<AccessLogEntry>>,
+    /// Entries for the writer thread, which stops when the log is dropped
+    writer: Option<Sender<AccessLogEntry>>,
+}
+
+impl AccessLog {
+    pub fn new(app: &AppHandle) -> Self {
+        let writer = store::data_file(app, ACCESS_LOG_FILE).ok().map(|file| {
+            let (sender, receiver) = mpsc::channel::<AccessLogEntry>();
+            std::thread::spawn(move || {
+                for entry in receiver {
+                    // A full disk should not take the server down with it
+                    let _ = append(&file, &entry);
+                }
+            });
+            sender
+        });
+        Self {
+            recent: Mutex::default(),
+            writer,
+        }
+    }
+
+    fn push(&self, entry: AccessLogEntry) {
+        if let Some(writer) = &self.writer {
+            let _ = writer.send(entry.clone());
+        }
+        if let Ok(mut recent) = self.recent.lock() {
+            if recent.len() == RECENT_REQUESTS {
+                recent.pop_front();
+            }
+            recent.push_back(entry);
+        }
+    }
+
+    /// Up to `limit` requests, newest first
+    pub fn recent(&self, limit: usize) -> Vec<AccessLogEntry> {
+        self.recent
+            .lock()
+            .map(|recent| recent.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+fn append(file: &Path, entry: &AccessLogEntry) -> Result<(), String> {
+    if fs::metadata(file).is_ok_and(|m| m.len() > MAX_LOG_BYTES) {
+        fs::rename(file, file.with_extension("log.1"))
+            .map_err(|e| format!("Failed to rotate access log: {}", e))?;
+    }
+    let mut line = serde_json::to_string(entry)
+        .map_err(|e| format!("Failed to serialize access log entry: {}", e))?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)
+        .and_then(|mut f| f.write_all(line.as_bytes()))
+        .map_err(|e| format!("Failed to write access log: {}", e))
+}
+
+/// Up to `limit` logged requests from the log file, newest first
+pub fn read_file(app: &AppHandle, limit: usize) -> Result<Vec<AccessLogEntry>, String> {
+    let file = store::data_file(app, ACCESS_LOG_FILE)?;
+    let Ok(text) = fs::read_to_string(&file) else {
+        return Ok(Vec::new());
+    };
+    Ok(text
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .take(limit)
+        .collect())
+}
+
+/// Shorten the secret token in share and catalog links
+fn redact(path: &str) -> String {
+    let mut parts: Vec<String> = path.split('/').map(str::to_string).collect();
+    if matches!(parts.get(1).map(String::as_str), Some("read" | "opds")) {
+        if let Some(token) = parts.get_mut(2) {
+            if token.chars().count() > TOKEN_PREFIX_CHARS {
+                *token = format!(
+                    "{}…",
+                    token.chars().take(TOKEN_PREFIX_CHARS).collect::<String>()
+                );
+            }
+        }
+    }
+    parts.join("/")
+}
+
+/// Kind of request, from the first segment of its path
+fn route_action(path: &str) -> String {
+    path.trim_start_matches('/')
+        .split('/')
+        .next()
+        .filter(|segment| !segment.is_empty())
+        .unwrap_or("root")
+        .to_string()
+}
+
+fn content_length(headers: &HeaderMap) -> u64 {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Request body counting the bytes the server read from it
+struct CountedBody {
+    inner: Body,
+    read: Arc<AtomicU64>,
+}
+
+impl HttpBody for CountedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &polled {
+            if let Some(data) = frame.data_ref() {
+                self.read.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Middleware logging each request after it is answered. `bytes_in` counts
+/// the body bytes actually read, so chunked uploads are measured too.
+pub async fn record(
+    State(state): State<ServerState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let read = Arc::new(AtomicU64::new(0));
+    let counter = read.clone();
+    let request = request.map(|inner| {
+        Body::new(CountedBody {
+            inner,
+            read: counter,
+        })
+    });
+    let response = next.run(request).await;
+
+    let note = response
+        .extensions()
+        .get::<AccessNote>()
+        .cloned()
+        .unwrap_or_default();
+    let status = response.status();
+    let result = match note.error {
+        Some(error) => error,
+        None if status.is_client_error() || status.is_server_error() => {
+            status.canonical_reason().unwrap_or("Error").to_string()
+        }
+        None => "ok".to_string(),
+    };
+    state.access_log.push(AccessLogEntry {
+        at: now_ms(),
+        ip: peer.ip().to_string(),
+        method,
+        action: note
+            .action
+            .map_or_else(|| route_action(&path), str::to_string),
+        path: redact(&path),
+        story_id: note.story_id,
+        status: status.as_u16(),
+        result,
+        bytes_in: read.load(Ordering::Relaxed),
+        bytes_out: response
+            .body()
+            .size_hint()
+            .exact()
+            .unwrap_or_else(|| content_length(response.headers())),
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+    response
+}
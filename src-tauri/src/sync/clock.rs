@@ -0,0 +1,91 @@
+//! How far a peer's clock is from ours. Which copy of a story wins is decided
+//! by `updatedAt`, so a peer whose clock runs ahead would always look newer.
+//! The offset is measured when a session opens and story times from the peer
+//! are moved onto this device's clock before they are compared.
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use super::health;
+use super::keys::now_ms;
+use super::types::SyncStoryPreview;
+
+/// Emitted with a `ClockSkewWarning` when a peer's clock is too far off
+pub const CLOCK_SKEW_EVENT: &str = "sync://clock-skew";
+
+/// Offset past which the user is warned that merges may pick the wrong copy
+pub const CLOCK_SKEW_WARNING_MS: i64 = 60_000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockSkew {
+    /// Peer clock minus ours; positive when the peer is ahead
+    pub skew_ms: i64,
+    /// The measurement is only good to within half of this
+    pub round_trip_ms: i64,
+}
+
+impl ClockSkew {
+    /// Offsets within the measurement error are treated as none
+    fn correction(&self) -> i64 {
+        if self.skew_ms.abs() * 2 > self.round_trip_ms {
+            self.skew_ms
+        } else {
+            0
+        }
+    }
+
+    pub fn exceeds_warning(&self) -> bool {
+        self.skew_ms.abs() > CLOCK_SKEW_WARNING_MS
+    }
+
+    /// A peer time on this device's clock
+    pub fn local_time(&self, peer_time: i64) -> i64 {
+        peer_time.saturating_sub(self.correction())
+    }
+
+    /// A time on this device's clock as the peer's clock reads it
+    pub fn peer_time(&self, local_time: i64) -> i64 {
+        local_time.saturating_add(self.correction())
+    }
+
+    /// Move the update times of a peer's stories onto this device's clock
+    pub fn adjust(&self, stories: &mut [SyncStoryPreview]) {
+        for story in stories.iter_mut().filter(|s| s.updated_at > 0) {
+            story.updated_at = self.local_time(story.updated_at);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockSkewWarning {
+    pub ip: String,
+    pub port: u16,
+    #[serde(flatten)]
+    pub skew: ClockSkew,
+}
+
+/// Measure a peer's clock against ours from its health endpoint. `None` when
+/// the peer is too old to report its time.
+pub async fn probe(app: &AppHandle, ip: &str, port: u16) -> Result<Option<ClockSkew>, String> {
+    let sent = now_ms();
+    let info = health::fetch(app, ip, port).await?;
+    let received = now_ms();
+    let Some(server_time) = info.server_time else {
+        return Ok(None);
+    };
+    let skew = ClockSkew {
+        skew_ms: server_time - (sent + received) / 2,
+        round_trip_ms: received - sent,
+    };
+    if skew.exceeds_warning() {
+        let warning = ClockSkewWarning {
+            ip: ip.to_string(),
+            port,
+            skew,
+        };
+        let _ = app.emit(CLOCK_SKEW_EVENT, &warning);
+    }
+    Ok(Some(skew))
+}
@@ -0,0 +1,97 @@
+//! Wire encodings for sync requests and responses. Peers negotiate with the
+//! usual `Accept` and `Content-Type` headers: a client lists the formats it
+//! reads, the server answers in the best one it knows, and the client sends
+//! later requests in whatever the server answered with. JSON is the fallback
+//! every version understands.
+//!
+//! Story payloads travel as JSON strings inside the envelope. Binary formats
+//! carry them as raw bytes, so the multi-megabyte string is neither escaped
+//! on the way out nor unescaped on the way in.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl WireFormat {
+    /// Value of the `Accept` header sent by clients, most preferred first
+    pub const ACCEPT: &'static str =
+        "application/msgpack, application/cbor;q=0.9, application/json;q=0.8";
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            WireFormat::Json => "application/json",
+            WireFormat::MessagePack => "application/msgpack",
+            WireFormat::Cbor => "application/cbor",
+        }
+    }
+
+    /// Format named by a `Content-Type` value, ignoring parameters
+    pub fn from_mime(value: &str) -> Option<Self> {
+        match value
+            .split(';')
+            .next()?
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "application/json" => Some(WireFormat::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(WireFormat::MessagePack)
+            }
+            "application/cbor" => Some(WireFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Best format a client accepts, honouring `q` weights. Missing or
+    /// unrecognised `Accept` headers get JSON, as older clients expect.
+    pub fn negotiate(accept: Option<&str>) -> Self {
+        let Some(accept) = accept else {
+            return WireFormat::Json;
+        };
+        let mut offered: Vec<(Self, f32)> = accept
+            .split(',')
+            .filter_map(|item| {
+                let format = Self::from_mime(item)?;
+                let weight = item
+                    .split(';')
+                    .skip(1)
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (weight > 0.0).then_some((format, weight))
+            })
+            .collect();
+        // Stable, so equal weights keep the client's order
+        offered.sort_by(|a, b| b.1.total_cmp(&a.1));
+        offered
+            .first()
+            .map_or(WireFormat::Json, |(format, _)| *format)
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            WireFormat::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            // Field names are kept so tagged enums decode the same way as from JSON
+            WireFormat::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            WireFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            WireFormat::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            WireFormat::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            WireFormat::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
@@ -1,35 +1,110 @@
 use base64::{engine::general_purpose::STANDARD, Engine};
 use image::Luma;
 use qrcode::QrCode;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::io::Cursor;
 use std::sync::Arc;
+use std::time::Duration;
 use tauri::{AppHandle, State};
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
-use super::server::{bind_listener, build_router, spawn_server, ServerState, StoriesData};
-use super::types::{QrCodeData, SyncAction, SyncRequest, SyncResponse, SyncServerInfo, SyncStoryPreview};
+use crate::ai::profile::{import_profiles, load_profiles};
+use crate::export::cover;
+use crate::export::site::SiteTheme;
+use crate::lorebook::{self, LorebookInfo};
+use crate::profiles;
+use crate::store;
+use crate::story::archive::ArchivedStory;
+use crate::story::lock::LockReason;
+use crate::story::rating::ContentRating;
+use crate::story::{rows, StoryExport, StoryState};
+use crate::style::{StyleLibrary, STYLE_REFERENCES_FILE};
+
+use super::access_log::{self, AccessLogEntry};
+use super::clock::{self, ClockSkew};
+use super::device::{self, DeviceIdentity, DeviceProfile, DEVICE_PROFILE_FILE};
+use super::folder::{self, FolderSyncReport};
+use super::health::{self, HealthInfo};
+use super::http::{self, HttpTuning, HTTP_TUNING_FILE};
+use super::keys::{self, ApiKeyEntry, ExchangeSecret, KeyExchangeHandshake, PendingKeyExchange};
+use super::metrics::{self, SyncMetricsSnapshot};
+use super::network::{self, NetworkBinding, NetworkInterfaceInfo};
+use super::opds::OpdsCatalog;
+use super::outbox::{Outbox, PendingSyncOpInfo};
+use super::partial::{self, EntryRange, PartialStoryInfo};
+use super::payload::{SpillConfig, StoryPayload, SPILL_CONFIG_FILE};
+use super::plan::{self, FavoritesConfig, SyncPlan, SyncPlanOptions, FAVORITES_CONFIG_FILE};
+use super::preview;
+use super::server::{bind_listener, ServerState, StoriesData};
+use super::session::{PeerSession, PeerSessionInfo, Sessions};
+use super::settings::{SettingsBundle, SettingsScope};
+use super::share::ReadShare;
+use super::supervisor::{self, SharedStatus, SyncServerStatus};
+use super::tombstones::{self, PendingDeletion};
+use super::transport::{
+    open_device, serve_stream, HttpTransport, StreamTransport, SyncTransport, TransportError,
+};
+use super::types::{
+    QrCodeData, StoryTombstone, SyncAction, SyncRequest, SyncResponse, SyncServerInfo,
+    SyncStoryPreview,
+};
+
+/// Requests returned by `get_server_access_log` when no limit is given
+const DEFAULT_ACCESS_LOG_LIMIT: usize = 200;
 
 /// State managed by Tauri for sync operations
 pub struct SyncState {
-    /// Handle to the running server task
+    /// Handle to the task supervising the running server
     server_handle: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
+    /// Whether the server is up, and why it last stopped
+    status: SharedStatus,
     /// Current server state (for accessing received stories)
     server_state: Arc<Mutex<Option<ServerState>>>,
+    /// Pushes queued while their peer was unreachable
+    pub(crate) outbox: Outbox,
+    /// Open sessions with remote servers
+    sessions: Sessions,
+    /// Tasks answering requests on serial devices
+    device_tasks: Arc<Mutex<Vec<tokio::task::JoinHandle<()>>>>,
+    /// Secrets of key exchanges this device started, by exchange ID
+    key_secrets: Arc<Mutex<HashMap<String, ExchangeSecret>>>,
 }
 
 impl Default for SyncState {
     fn default() -> Self {
         Self {
             server_handle: Arc::new(Mutex::new(None)),
+            status: SharedStatus::default(),
             server_state: Arc::new(Mutex::new(None)),
+            outbox: Outbox::default(),
+            sessions: Sessions::default(),
+            device_tasks: Arc::new(Mutex::new(Vec::new())),
+            key_secrets: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
 
+impl SyncState {
+    /// State of the running server, if there is one
+    pub(crate) async fn server_state(&self) -> Option<ServerState> {
+        self.server_state.lock().await.clone()
+    }
+
+    /// How far a session's peer clock is off, when it was measured
+    pub(crate) async fn session_clock(
+        &self,
+        session_id: &str,
+    ) -> Result<Option<ClockSkew>, String> {
+        Ok(self.sessions.get(session_id).await?.clock)
+    }
+}
+
 /// Generate a QR code as base64-encoded PNG
-fn generate_qr_code(data: &str) -> Result<String, String> {
-    let code = QrCode::new(data.as_bytes()).map_err(|e| format!("Failed to create QR code: {}", e))?;
+pub(crate) fn generate_qr_code(data: &str) -> Result<String, String> {
+    let code =
+        QrCode::new(data.as_bytes()).map_err(|e| format!("Failed to create QR code: {}", e))?;
 
     let image = code.render::<Luma<u8>>().min_dimensions(256, 256).build();
 
@@ -42,52 +117,63 @@ fn generate_qr_code(data: &str) -> Result<String, String> {
 }
 
 /// Get the local IP address
-fn get_local_ip() -> Result<String, String> {
+pub(crate) fn get_local_ip() -> Result<String, String> {
     local_ip_address::local_ip()
         .map(|ip| ip.to_string())
         .map_err(|e| format!("Failed to get local IP: {}", e))
 }
 
+/// Add cover thumbnails to the previews of stories about to be offered
+async fn with_thumbnails(
+    app: &AppHandle,
+    mut stories: Vec<(SyncStoryPreview, String)>,
+) -> Result<Vec<(SyncStoryPreview, String)>, String> {
+    let app = app.clone();
+    tokio::task::spawn_blocking(move || {
+        for (preview, _) in &mut stories {
+            preview.thumbnail = cover::thumbnail_for(&app, &preview.id);
+        }
+        stories
+    })
+    .await
+    .map_err(|e| format!("Failed to make cover thumbnails: {}", e))
+}
+
 /// Parse story preview from Aventura export JSON
-fn parse_story_preview(json: &str) -> Result<SyncStoryPreview, String> {
-    let data: serde_json::Value =
-        serde_json::from_str(json).map_err(|e| format!("Invalid JSON: {}", e))?;
-
-    let story = data
-        .get("story")
-        .ok_or("Missing 'story' field in export")?;
-    let entries = data
-        .get("entries")
-        .and_then(|e| e.as_array())
-        .map(|a| a.len())
-        .unwrap_or(0);
+pub(crate) fn parse_story_preview(json: &str) -> Result<SyncStoryPreview, String> {
+    let preview = preview::read(json)?;
+    let story = preview.story.ok_or("Missing 'story' field in export")?;
 
     Ok(SyncStoryPreview {
-        id: story
-            .get("id")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string(),
-        title: story
-            .get("title")
-            .and_then(|v| v.as_str())
-            .unwrap_or("Untitled")
-            .to_string(),
-        genre: story.get("genre").and_then(|v| v.as_str()).map(String::from),
-        updated_at: story
-            .get("updatedAt")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(0),
-        entry_count: entries,
+        id: story.id.unwrap_or_default(),
+        title: story.title.unwrap_or_else(|| "Untitled".to_string()),
+        genre: story.genre,
+        updated_at: story.updated_at.unwrap_or(0),
+        entry_count: preview.entries.0,
+        size: Some(json.len() as u64),
+        favorite: story.favorite.unwrap_or(false),
+        pinned: story.pinned.unwrap_or(false),
+        rating: ContentRating::from_fields(
+            story.age_rating.as_deref(),
+            story.content_warnings.as_deref().unwrap_or_default(),
+        ),
+        thumbnail: None,
     })
 }
 
-/// Start the sync server with available stories
+/// Start the sync server with available stories.
+/// Listens on every interface unless the binding names one, and serves
+/// Prometheus metrics at `/metrics` only when `expose_metrics` is set.
+/// With `auto_restart`, a server that stops on its own is started again
+/// with the same port and token.
 #[tauri::command]
 pub async fn start_sync_server(
     app: AppHandle,
     state: State<'_, SyncState>,
     stories_json: Option<Vec<String>>,
+    binding: Option<NetworkBinding>,
+    expose_metrics: Option<bool>,
+    auto_restart: Option<bool>,
 ) -> Result<SyncServerInfo, String> {
     // Stop any existing server first
     stop_sync_server(state.clone()).await?;
@@ -96,35 +182,52 @@ pub async fn start_sync_server(
     let token = Uuid::new_v4().to_string();
 
     // Create server state
-    let server_state = ServerState::new(token.clone());
+    let mut server_state = ServerState::new(app.clone(), token.clone());
+    server_state.device = device::identity(&app)?;
+    server_state.spill = store::load_json(&app, SPILL_CONFIG_FILE)?;
+    let favorites: FavoritesConfig = store::load_json(&app, FAVORITES_CONFIG_FILE)?;
 
     // Add stories if provided
     if let Some(stories) = stories_json {
-        let mut stories_data = server_state.stories.lock().await;
+        let stories = profiles::visible_exports(&app, stories).await?;
+        let mut offered = Vec::new();
         for story_json in stories {
             match parse_story_preview(&story_json) {
-                Ok(preview) => {
-                    stories_data.push(StoriesData {
-                        preview,
-                        full_data: story_json,
-                    });
-                }
+                Ok(preview) if favorites.offer_only_favorites && !preview.favorite => {}
+                Ok(preview) => offered.push((preview, story_json)),
                 Err(e) => {
                     eprintln!("Failed to parse story: {}", e);
                 }
             }
         }
+        let offered = with_thumbnails(&app, offered).await?;
+        let mut stories_data = server_state.stories.lock().await;
+        for (preview, story_json) in offered {
+            stories_data.push(StoriesData {
+                preview,
+                full_data: StoryPayload::new(story_json, &server_state.spill),
+            });
+        }
     }
 
     // Bind listener before starting the server task
-    let listener = bind_listener().await?;
+    let binding = binding.unwrap_or_default();
+    let (bind_ip, visible_on) = network::resolve_binding(&binding)?;
+    let listener = bind_listener(bind_ip).await?;
     let addr = listener
         .local_addr()
         .map_err(|e| format!("Failed to get local address: {}", e))?;
 
     // Get local IP for QR data
-    let ip = get_local_ip()?;
+    let ip = if bind_ip.is_unspecified() {
+        get_local_ip()?
+    } else {
+        bind_ip.to_string()
+    };
     let port = addr.port();
+    server_state.port = port;
+    server_state.lan_only = binding.lan_only;
+    server_state.expose_metrics = expose_metrics.unwrap_or(false);
 
     // Generate QR code with connection data
     let qr_data = QrCodeData {
@@ -132,13 +235,21 @@ pub async fn start_sync_server(
         port,
         token: token.clone(),
         version: app.package_info().version.to_string(),
+        device: Some(server_state.device.clone()),
     };
-    let qr_json = serde_json::to_string(&qr_data).map_err(|e| format!("Failed to serialize QR data: {}", e))?;
+    let device = server_state.device.clone();
+    let qr_json = serde_json::to_string(&qr_data)
+        .map_err(|e| format!("Failed to serialize QR data: {}", e))?;
     let qr_code_base64 = generate_qr_code(&qr_json)?;
 
     // Start the server after QR data is ready
-    let app = build_router(server_state.clone());
-    let handle = spawn_server(listener, app);
+    let handle = supervisor::supervise(
+        server_state.clone(),
+        listener,
+        addr,
+        auto_restart.unwrap_or(false),
+        state.status.clone(),
+    );
 
     // Store handles
     *state.server_handle.lock().await = Some(handle);
@@ -149,9 +260,76 @@ pub async fn start_sync_server(
         port,
         token,
         qr_code_base64,
+        device,
+        visible_on,
+        lan_only: binding.lan_only,
     })
 }
 
+/// Request counts, transfer totals and active sessions of the running server
+#[tauri::command]
+pub async fn get_sync_metrics(
+    state: State<'_, SyncState>,
+) -> Result<Option<SyncMetricsSnapshot>, String> {
+    Ok(match state.server_state().await {
+        Some(server) => Some(metrics::snapshot(&server).await),
+        None => None,
+    })
+}
+
+/// Requests other devices made to the sync server, newest first. While the
+/// server runs these are its recent requests; otherwise they are read from
+/// the log file, which also covers earlier runs.
+#[tauri::command]
+pub async fn get_server_access_log(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    limit: Option<usize>,
+) -> Result<Vec<AccessLogEntry>, String> {
+    let limit = limit.unwrap_or(DEFAULT_ACCESS_LOG_LIMIT);
+    match state.server_state().await {
+        Some(server) => Ok(server.access_log.recent(limit)),
+        None => tokio::task::spawn_blocking(move || access_log::read_file(&app, limit))
+            .await
+            .map_err(|e| format!("Failed to read access log: {}", e))?,
+    }
+}
+
+/// Name and avatar other devices see; unset fields use defaults
+#[tauri::command]
+pub async fn get_device_profile(app: AppHandle) -> Result<DeviceProfile, String> {
+    store::load_shared_json(&app, DEVICE_PROFILE_FILE)
+}
+
+/// Save the device name and avatar. Shown by servers started afterwards.
+#[tauri::command]
+pub async fn set_device_profile(
+    app: AppHandle,
+    profile: DeviceProfile,
+) -> Result<DeviceIdentity, String> {
+    store::save_shared_json(&app, DEVICE_PROFILE_FILE, &profile.validated()?)?;
+    device::identity(&app)
+}
+
+/// Network interfaces the sync server can be bound to
+#[tauri::command]
+pub async fn list_network_interfaces() -> Result<Vec<NetworkInterfaceInfo>, String> {
+    network::list_interfaces()
+}
+
+/// Whether the sync server is running, how often it was restarted and why
+/// it last stopped on its own
+#[tauri::command]
+pub async fn get_sync_server_status(
+    state: State<'_, SyncState>,
+) -> Result<SyncServerStatus, String> {
+    state
+        .status
+        .lock()
+        .map(|status| status.clone())
+        .map_err(|_| "Server status is unavailable".to_string())
+}
+
 /// Stop the sync server
 #[tauri::command]
 pub async fn stop_sync_server(state: State<'_, SyncState>) -> Result<(), String> {
@@ -159,17 +337,67 @@ pub async fn stop_sync_server(state: State<'_, SyncState>) -> Result<(), String>
     if let Some(h) = handle.take() {
         h.abort();
     }
-    *state.server_state.lock().await = None;
+    for task in state.device_tasks.lock().await.drain(..) {
+        task.abort();
+    }
+    if let Ok(mut status) = state.status.lock() {
+        status.running = false;
+    }
+    // Game and spectator sockets outlive the server task, so close them explicitly
+    if let Some(server) = state.server_state.lock().await.take() {
+        if let Some(session) = server.game.lock().await.take() {
+            session.broadcast(crate::game::types::GameEvent::Ended);
+        }
+        server.spectators.stop().await;
+    }
     Ok(())
 }
 
+#[tauri::command]
+pub async fn get_sync_spill_config(app: AppHandle) -> Result<SpillConfig, String> {
+    store::load_json(&app, SPILL_CONFIG_FILE)
+}
+
+/// Change when large stories are kept on disk. Applies to servers started afterwards.
+#[tauri::command]
+pub async fn set_sync_spill_config(app: AppHandle, config: SpillConfig) -> Result<(), String> {
+    store::save_json(&app, SPILL_CONFIG_FILE, &config)
+}
+
+/// Get the timeouts, retries and keep-alive used when talking to peers
+#[tauri::command]
+pub async fn get_sync_http_tuning(app: AppHandle) -> Result<HttpTuning, String> {
+    store::load_json(&app, HTTP_TUNING_FILE)
+}
+
+/// Change the timeouts, retries and keep-alive used when talking to peers
+#[tauri::command]
+pub async fn set_sync_http_tuning(app: AppHandle, tuning: HttpTuning) -> Result<(), String> {
+    store::save_json(&app, HTTP_TUNING_FILE, &tuning)
+}
+
+/// Get whether syncing is limited to favorite stories
+#[tauri::command]
+pub async fn get_sync_favorites_config(app: AppHandle) -> Result<FavoritesConfig, String> {
+    store::load_json(&app, FAVORITES_CONFIG_FILE)
+}
+
+/// Limit syncing to favorite stories. Offering applies to servers started afterwards.
+#[tauri::command]
+pub async fn set_sync_favorites_config(
+    app: AppHandle,
+    config: FavoritesConfig,
+) -> Result<(), String> {
+    store::save_json(&app, FAVORITES_CONFIG_FILE, &config)
+}
+
 /// Get stories that were pushed to this server
 #[tauri::command]
 pub async fn get_received_stories(state: State<'_, SyncState>) -> Result<Vec<String>, String> {
     let server_state = state.server_state.lock().await;
     if let Some(ref ss) = *server_state {
-        let received = ss.received_stories.lock().await;
-        Ok(received.clone())
+        let received = ss.received_stories.lock().await.clone();
+        received.iter().map(StoryPayload::read).collect()
     } else {
         Ok(Vec::new())
     }
@@ -186,32 +414,45 @@ pub async fn clear_received_stories(state: State<'_, SyncState>) -> Result<(), S
     Ok(())
 }
 
-/// Connect to a remote sync server and list available stories
+/// Name, version and features of a sync server, without pairing first
 #[tauri::command]
-pub async fn sync_connect(ip: String, port: u16, token: String) -> Result<Vec<SyncStoryPreview>, String> {
-    let url = format!("http://{}:{}/sync", ip, port);
+pub async fn sync_check_health(
+    app: AppHandle,
+    ip: String,
+    port: u16,
+) -> Result<HealthInfo, String> {
+    health::fetch(&app, &ip, port).await
+}
 
+/// Connect to a remote sync server and list available stories.
+/// Update times are moved onto this device's clock.
+#[tauri::command]
+pub async fn sync_connect(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    ip: String,
+    port: u16,
+    token: String,
+) -> Result<Vec<SyncStoryPreview>, String> {
     let request = SyncRequest {
-        token,
+        token: token.clone(),
         action: SyncAction::ListStories,
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(10))
-        .send()
-        .await
-        .map_err(|e| format!("Connection failed: {}", e))?;
-
-    let sync_response: SyncResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("Invalid response: {}", e))?;
+    let tuning = http::load(&app);
+    let sync_response = HttpTransport::new(&ip, port, &tuning)
+        .send(&request, tuning.read_timeout())
+        .await?;
 
     match sync_response {
-        SyncResponse::StoriesList { stories } => Ok(stories),
+        SyncResponse::StoriesList { mut stories } => {
+            // The peer is reachable again, so deliver anything queued for it
+            state.outbox.peer_seen(&app, &ip, port, &token).await?;
+            if let Some(skew) = clock::probe(&app, &ip, port).await.ok().flatten() {
+                skew.adjust(&mut stories);
+            }
+            Ok(stories)
+        }
         SyncResponse::Error { message } => Err(message),
         _ => Err("Unexpected response type".to_string()),
     }
@@ -220,31 +461,27 @@ pub async fn sync_connect(ip: String, port: u16, token: String) -> Result<Vec<Sy
 /// Pull a story from a remote server
 #[tauri::command]
 pub async fn sync_pull_story(
+    app: AppHandle,
+    stories: State<'_, StoryState>,
     ip: String,
     port: u16,
     token: String,
     story_id: String,
 ) -> Result<String, String> {
-    let url = format!("http://{}:{}/sync", ip, port);
-
     let request = SyncRequest {
         token,
-        action: SyncAction::PullStory { story_id },
+        action: SyncAction::PullStory {
+            story_id: story_id.clone(),
+        },
     };
+    let _lock = stories
+        .locks
+        .try_acquire(&story_id, LockReason::Sync, None)?;
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(30))
-        .send()
-        .await
-        .map_err(|e| format!("Connection failed: {}", e))?;
-
-    let sync_response: SyncResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("Invalid response: {}", e))?;
+    let tuning = http::load(&app);
+    let sync_response = HttpTransport::new(&ip, port, &tuning)
+        .send(&request, tuning.read_timeout())
+        .await?;
 
     match sync_response {
         SyncResponse::StoryData { data } => Ok(data),
@@ -253,36 +490,738 @@ pub async fn sync_pull_story(
     }
 }
 
-/// Push a story to a remote server
+/// Why a push did not reach the remote server
+pub(crate) enum PushError {
+    /// The peer could not be reached
+    Unreachable(String),
+    /// The peer refused the story, or may have taken it before the
+    /// connection dropped
+    Rejected(String),
+}
+
+impl std::fmt::Display for PushError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PushError::Unreachable(message) | PushError::Rejected(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Send a story to a remote server
+pub(crate) async fn push_story_to(
+    ip: &str,
+    port: u16,
+    token: &str,
+    story_json: String,
+    tuning: &HttpTuning,
+) -> Result<(), PushError> {
+    let request = SyncRequest {
+        token: token.to_string(),
+        action: SyncAction::PushStory {
+            story_data: story_json,
+        },
+    };
+
+    push_result(
+        HttpTransport::new(ip, port, tuning)
+            .send(&request, tuning.read_timeout())
+            .await,
+    )
+}
+
+/// Whether a push reached the peer and was accepted
+fn push_result(result: Result<SyncResponse, TransportError>) -> Result<(), PushError> {
+    let sync_response = result.map_err(|e| match e {
+        TransportError::Unreachable(message) => PushError::Unreachable(message),
+        // Queuing it again could deliver the story twice
+        TransportError::Interrupted(message) => PushError::Rejected(format!(
+            "{}. The story may have arrived; check the other device before pushing again.",
+            message
+        )),
+        TransportError::Invalid(message) => PushError::Rejected(message),
+        TransportError::Cancelled => PushError::Rejected(e.to_string()),
+    })?;
+
+    match sync_response {
+        SyncResponse::Success { .. } => Ok(()),
+        SyncResponse::Error { message } => Err(PushError::Rejected(message)),
+        _ => Err(PushError::Rejected("Unexpected response type".to_string())),
+    }
+}
+
+/// Result of pushing a story
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum PushOutcome {
+    Delivered,
+    /// The peer was unreachable; the push will be retried from the outbox
+    #[serde(rename_all = "camelCase")]
+    Queued {
+        op_id: String,
+    },
+}
+
+/// Push a story to a remote server.
+/// If the peer cannot be reached the push is queued and retried in the background.
 #[tauri::command]
 pub async fn sync_push_story(
+    app: AppHandle,
+    state: State<'_, SyncState>,
     ip: String,
     port: u16,
     token: String,
     story_json: String,
+) -> Result<PushOutcome, String> {
+    profiles::check_story(&app, &parse_story_preview(&story_json)?.id).await?;
+    let tuning = http::load(&app);
+    match push_story_to(&ip, port, &token, story_json.clone(), &tuning).await {
+        Ok(()) => Ok(PushOutcome::Delivered),
+        Err(PushError::Unreachable(message)) => {
+            let op_id = state
+                .outbox
+                .enqueue(&app, &ip, port, &token, &story_json, message)
+                .await?;
+            Ok(PushOutcome::Queued { op_id })
+        }
+        Err(PushError::Rejected(message)) => Err(message),
+    }
+}
+
+/// Connect to a remote sync server and keep the connection details for
+/// later session commands. Fails if the token is not accepted. The peer's
+/// clock is measured once here and `sync://clock-skew` is emitted when it is
+/// far off.
+#[tauri::command]
+pub async fn sync_open_session(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    ip: String,
+    port: u16,
+    token: String,
+) -> Result<String, String> {
+    // Older peers cannot report their time; their clocks are taken as right
+    let clock = clock::probe(&app, &ip, port).await.ok().flatten();
+    let session = PeerSession::new(&ip, port, &token, clock);
+    match session.open(&app).await? {
+        SyncResponse::StoriesList { .. } => {
+            state.outbox.peer_seen(&app, &ip, port, &token).await?;
+            Ok(state.sessions.insert(session).await)
+        }
+        SyncResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response type".to_string()),
+    }
+}
+
+/// List the stories available in a session, with update times on this
+/// device's clock
+#[tauri::command]
+pub async fn sync_session_list(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    session_id: String,
+) -> Result<Vec<SyncStoryPreview>, String> {
+    let session = state.sessions.get(&session_id).await?;
+    match session.send(&app, SyncAction::ListStories).await? {
+        SyncResponse::StoriesList { mut stories } => {
+            if let Some(skew) = session.clock {
+                skew.adjust(&mut stories);
+            }
+            Ok(stories)
+        }
+        SyncResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response type".to_string()),
+    }
+}
+
+/// Work out what syncing with the peer of a session would do, without
+/// transferring any story
+#[tauri::command]
+pub async fn sync_plan(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    session_id: String,
+    options: Option<SyncPlanOptions>,
+) -> Result<SyncPlan, String> {
+    let session = state.sessions.get(&session_id).await?;
+    let mut remote = match session.send(&app, SyncAction::ListStories).await? {
+        SyncResponse::StoriesList { stories } => stories,
+        SyncResponse::Error { message } => return Err(message),
+        _ => return Err("Unexpected response type".to_string()),
+    };
+    if let Some(skew) = session.clock {
+        skew.adjust(&mut remote);
+    }
+    let mut local = plan::local_stories(&app).await?;
+    let visible: HashSet<String> =
+        profiles::visible_stories(&app, local.iter().map(|s| s.id.clone()).collect())
+            .await?
+            .into_iter()
+            .collect();
+    local.retain(|s| visible.contains(&s.id));
+    let mut options = options.unwrap_or_default();
+    let favorites: FavoritesConfig = store::load_json(&app, FAVORITES_CONFIG_FILE)?;
+    options.favorites_only |= favorites.sync_only_favorites;
+    Ok(plan::plan(
+        &local,
+        &remote,
+        &tombstones::list(&app)?,
+        &options,
+    ))
+}
+
+/// Pull a story in a session
+#[tauri::command]
+pub async fn sync_session_pull(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    stories: State<'_, StoryState>,
+    session_id: String,
+    story_id: String,
+) -> Result<String, String> {
+    let session = state.sessions.get(&session_id).await?;
+    let _lock = stories
+        .locks
+        .try_acquire(&story_id, LockReason::Sync, None)?;
+
+    let action = SyncAction::PullStory {
+        story_id: story_id.clone(),
+    };
+    match session.send(&app, action).await? {
+        SyncResponse::StoryData { data } => Ok(data),
+        SyncResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response type".to_string()),
+    }
+}
+
+/// Pull part of a story's entries in a session and stitch them into the
+/// local partial copy, from `from_entry` up to, not including, `to_entry`
+#[tauri::command]
+pub async fn sync_session_pull_entries(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    stories: State<'_, StoryState>,
+    session_id: String,
+    story_id: String,
+    from_entry: usize,
+    to_entry: usize,
+) -> Result<PartialStoryInfo, String> {
+    let session = state.sessions.get(&session_id).await?;
+    let _lock = stories
+        .locks
+        .try_acquire(&story_id, LockReason::Sync, None)?;
+
+    let action = SyncAction::PullEntries {
+        story_id: story_id.clone(),
+        from_entry,
+        to_entry,
+    };
+    match session.send(&app, action).await? {
+        SyncResponse::StoryEntries {
+            data,
+            from_entry,
+            to_entry,
+            total_entries,
+        } => {
+            let pulled = StoryExport::from_json(&data)?;
+            let range = EntryRange {
+                from: from_entry,
+                to: to_entry,
+            };
+            if pulled.story.id != story_id
+                || to_entry > total_entries
+                || pulled.entries.len() != to_entry.saturating_sub(from_entry)
+            {
+                return Err("The peer sent entries that do not match the request".to_string());
+            }
+            partial::stitch(&app, pulled, range, total_entries)
+        }
+        SyncResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response type".to_string()),
+    }
+}
+
+/// Stories pulled in part, with the entry ranges each one has
+#[tauri::command]
+pub async fn list_partial_stories(app: AppHandle) -> Result<Vec<PartialStoryInfo>, String> {
+    partial::list(&app)
+}
+
+/// A partial copy as Aventura export JSON, holding the entries pulled so far
+#[tauri::command]
+pub async fn get_partial_story(app: AppHandle, story_id: String) -> Result<String, String> {
+    partial::story_json(&app, &story_id)
+}
+
+/// Remove a partial copy, for example once the story was imported
+#[tauri::command]
+pub async fn discard_partial_story(app: AppHandle, story_id: String) -> Result<(), String> {
+    partial::discard(&app, &story_id)
+}
+
+/// Push a story in a session, queueing it like `sync_push_story` when the
+/// peer cannot be reached
+#[tauri::command]
+pub async fn sync_session_push(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    session_id: String,
+    story_json: String,
+) -> Result<PushOutcome, String> {
+    profiles::check_story(&app, &parse_story_preview(&story_json)?.id).await?;
+    let session = state.sessions.get(&session_id).await?;
+    let action = SyncAction::PushStory {
+        story_data: story_json.clone(),
+    };
+    match push_result(session.send(&app, action).await) {
+        Ok(()) => Ok(PushOutcome::Delivered),
+        Err(PushError::Unreachable(message)) => {
+            let op_id = state
+                .outbox
+                .enqueue(
+                    &app,
+                    &session.ip,
+                    session.port,
+                    session.token(),
+                    &story_json,
+                    message,
+                )
+                .await?;
+            Ok(PushOutcome::Queued { op_id })
+        }
+        Err(PushError::Rejected(message)) => Err(message),
+    }
+}
+
+/// Swap deletions with the peer of a session. The peer's deletions of
+/// stories still on this device are queued for the user to confirm; all
+/// pending deletions are returned.
+#[tauri::command]
+pub async fn sync_session_deletions(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    session_id: String,
+) -> Result<Vec<PendingDeletion>, String> {
+    let session = state.sessions.get(&session_id).await?;
+    // Sent on the host's clock, so it compares them with its own times
+    let mut own = tombstones::list(&app)?;
+    if let Some(clock) = session.clock {
+        for tombstone in &mut own {
+            tombstone.deleted_at = clock.peer_time(tombstone.deleted_at);
+        }
+    }
+    let action = SyncAction::SyncDeletions { tombstones: own };
+    match session.send(&app, action).await? {
+        SyncResponse::Deletions { tombstones } => {
+            let from = format!("{}:{}", session.ip, session.port);
+            tombstones::receive(&app, &tombstones, session.clock, Some(from)).await
+        }
+        SyncResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response type".to_string()),
+    }
+}
+
+/// Lorebooks a peer shares
+#[tauri::command]
+pub async fn sync_session_list_lorebooks(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    session_id: String,
+) -> Result<Vec<LorebookInfo>, String> {
+    let session = state.sessions.get(&session_id).await?;
+    match session.send(&app, SyncAction::ListLorebooks).await? {
+        SyncResponse::LorebooksList { lorebooks } => Ok(lorebooks),
+        SyncResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response type".to_string()),
+    }
+}
+
+/// Pull a lorebook a peer shares into the library, replacing an older copy
+#[tauri::command]
+pub async fn sync_session_pull_lorebook(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    session_id: String,
+    lorebook_id: String,
+) -> Result<LorebookInfo, String> {
+    let session = state.sessions.get(&session_id).await?;
+    let action = SyncAction::PullLorebook {
+        lorebook_id: lorebook_id.clone(),
+    };
+    let pulled = match session.send(&app, action).await? {
+        SyncResponse::LorebookData { lorebook } => lorebook,
+        SyncResponse::Error { message } => return Err(message),
+        _ => return Err("Unexpected response type".to_string()),
+    };
+    pulled.validate()?;
+    if pulled.id != lorebook_id {
+        return Err("The peer sent a different lorebook".to_string());
+    }
+    let shared = lorebook::list(&app)?
+        .into_iter()
+        .find(|l| l.id == pulled.id)
+        .is_some_and(|l| l.shared);
+    lorebook::save(&app, &pulled)?;
+    Ok(pulled.info(shared))
+}
+
+/// Deletions from other devices waiting for confirmation
+#[tauri::command]
+pub async fn list_pending_deletions(app: AppHandle) -> Result<Vec<PendingDeletion>, String> {
+    tombstones::pending(&app)
+}
+
+/// Apply confirmed deletions from other devices. The stories go to the
+/// trash, where `unarchive_story` brings them back until the grace period
+/// ends.
+#[tauri::command]
+pub async fn apply_remote_deletions(
+    app: AppHandle,
+    stories: State<'_, StoryState>,
+    story_ids: Vec<String>,
+) -> Result<Vec<ArchivedStory>, String> {
+    for story_id in &story_ids {
+        profiles::check_story(&app, story_id).await?;
+    }
+    let ids: Vec<&str> = story_ids.iter().map(String::as_str).collect();
+    let _locks = stories.locks.try_acquire_all(&ids, LockReason::Delete)?;
+    let _save = stories.saves.lock().await;
+    tombstones::apply(&app, &story_ids).await
+}
+
+/// Keep stories another device deleted, without asking again
+#[tauri::command]
+pub async fn decline_remote_deletions(
+    app: AppHandle,
+    story_ids: Vec<String>,
 ) -> Result<(), String> {
-    let url = format!("http://{}:{}/sync", ip, port);
+    tombstones::decline(&app, &story_ids)
+}
 
+/// Close a session, cancelling any of its requests still in flight
+#[tauri::command]
+pub async fn sync_close_session(
+    state: State<'_, SyncState>,
+    session_id: String,
+) -> Result<(), String> {
+    state.sessions.close(&session_id).await
+}
+
+/// Open sessions with their request counts
+#[tauri::command]
+pub async fn list_sync_sessions(
+    state: State<'_, SyncState>,
+) -> Result<Vec<PeerSessionInfo>, String> {
+    Ok(state.sessions.list().await)
+}
+
+/// List pushes waiting in the outbox
+#[tauri::command]
+pub async fn list_pending_sync_ops(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+) -> Result<Vec<PendingSyncOpInfo>, String> {
+    state.outbox.list(&app).await
+}
+
+/// Remove a queued push. Returns false if it was already delivered or cancelled.
+#[tauri::command]
+pub async fn cancel_pending_sync_op(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    op_id: String,
+) -> Result<bool, String> {
+    state.outbox.cancel(&app, &op_id).await
+}
+
+/// Fill in the backend-owned scopes of a bundle from local storage
+async fn with_local_profiles(
+    app: &AppHandle,
+    mut settings: SettingsBundle,
+    scopes: &[SettingsScope],
+) -> Result<SettingsBundle, String> {
+    if scopes.contains(&SettingsScope::AiProfiles) {
+        let profiles = load_profiles(app).await?;
+        settings.ai_profiles = Some(profiles.into_values().collect());
+    }
+    if scopes.contains(&SettingsScope::StyleReferences) {
+        settings.style_references = Some(store::load_json(app, STYLE_REFERENCES_FILE)?);
+    }
+    Ok(settings.restricted_to(scopes))
+}
+
+/// Save AI profiles and style references from a received bundle, keeping
+/// local API keys and style references edited here more recently. Profiles
+/// of stories not on this device are skipped.
+async fn save_received_profiles(
+    app: &AppHandle,
+    settings: &SettingsBundle,
+) -> Result<(), String> {
+    if let Some(ref incoming) = settings.style_references {
+        let mut library: StyleLibrary = store::load_json(app, STYLE_REFERENCES_FILE)?;
+        library.merge(incoming.clone());
+        store::save_json(app, STYLE_REFERENCES_FILE, &library)?;
+    }
+    let Some(ref incoming) = settings.ai_profiles else {
+        return Ok(());
+    };
+    import_profiles(app, incoming.clone(), true).await.map(|_| ())
+}
+
+/// Offer settings to devices that connect to this server.
+/// Only the selected scopes are shared; AI profiles and style references
+/// are read from local storage.
+#[tauri::command]
+pub async fn share_sync_settings(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    settings: SettingsBundle,
+    scopes: Vec<SettingsScope>,
+) -> Result<(), String> {
+    let server_state = state.server_state.lock().await;
+    let ss = server_state.as_ref().ok_or("Sync server is not running")?;
+    let settings = with_local_profiles(&app, settings, &scopes).await?;
+    *ss.shared_settings.lock().await = Some(settings);
+    Ok(())
+}
+
+/// Take settings pushed to this server. AI profiles and style references
+/// are saved right away; the remaining scopes are returned for the frontend
+/// to apply.
+#[tauri::command]
+pub async fn apply_received_settings(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+) -> Result<Vec<SettingsBundle>, String> {
+    let received: Vec<SettingsBundle> = match state.server_state().await {
+        Some(ss) => std::mem::take(&mut *ss.received_settings.lock().await),
+        None => Vec::new(),
+    };
+    for settings in &received {
+        save_received_profiles(&app, settings).await?;
+    }
+    Ok(received)
+}
+
+/// The scopes the peer can take, dropping those it is too old to know
+async fn scopes_for_peer(
+    app: &AppHandle,
+    ip: &str,
+    port: u16,
+    scopes: Vec<SettingsScope>,
+) -> Vec<SettingsScope> {
+    if scopes.iter().all(|s| s.capability().is_none()) {
+        return scopes;
+    }
+    let capabilities = match health::fetch(app, ip, port).await {
+        Ok(info) => info.capabilities,
+        Err(_) => Vec::new(),
+    };
+    scopes
+        .into_iter()
+        .filter(|s| s.capability().is_none_or(|c| capabilities.iter().any(|have| have == c)))
+        .collect()
+}
+
+/// Pull shared settings from a remote server. Scopes the peer does not
+/// offer are left out. AI profiles and style references are saved right
+/// away; the bundle is returned for the frontend to apply.
+#[tauri::command]
+pub async fn sync_pull_settings(
+    app: AppHandle,
+    ip: String,
+    port: u16,
+    token: String,
+    scopes: Vec<SettingsScope>,
+) -> Result<SettingsBundle, String> {
+    let scopes = scopes_for_peer(&app, &ip, port, scopes).await;
     let request = SyncRequest {
         token,
-        action: SyncAction::PushStory {
-            story_data: story_json,
+        action: SyncAction::PullSettings {
+            scopes: scopes.clone(),
+        },
+    };
+
+    let tuning = http::load(&app);
+    let sync_response = HttpTransport::new(&ip, port, &tuning)
+        .send(&request, tuning.read_timeout())
+        .await?;
+
+    match sync_response {
+        SyncResponse::SettingsData { settings } => {
+            let settings = settings.restricted_to(&scopes);
+            save_received_profiles(&app, &settings).await?;
+            Ok(settings)
+        }
+        SyncResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response type".to_string()),
+    }
+}
+
+/// Push settings to a remote server, limited to the selected scopes the
+/// peer can take
+#[tauri::command]
+pub async fn sync_push_settings(
+    app: AppHandle,
+    ip: String,
+    port: u16,
+    token: String,
+    settings: SettingsBundle,
+    scopes: Vec<SettingsScope>,
+) -> Result<(), String> {
+    let scopes = scopes_for_peer(&app, &ip, port, scopes).await;
+    let request = SyncRequest {
+        token,
+        action: SyncAction::PushSettings {
+            settings: with_local_profiles(&app, settings, &scopes).await?,
         },
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(30))
-        .send()
+    let tuning = http::load(&app);
+    let sync_response = HttpTransport::new(&ip, port, &tuning)
+        .send(&request, tuning.read_timeout())
+        .await?;
+
+    match sync_response {
+        SyncResponse::Success { .. } => Ok(()),
+        SyncResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response type".to_string()),
+    }
+}
+
+/// Get the key exchange waiting for the host to compare codes, if any
+#[tauri::command]
+pub async fn get_pending_key_exchange(
+    state: State<'_, SyncState>,
+) -> Result<Option<PendingKeyExchange>, String> {
+    let Some(ss) = state.server_state().await else {
+        return Ok(None);
+    };
+    let pending = ss.key_exchange.lock().await.clone();
+    // There is no code to compare until the client has revealed its key
+    Ok(pending.filter(|p| p.expires_at >= keys::now_ms() && !p.handshake.code.is_empty()))
+}
+
+/// Accept or reject a key exchange after comparing the codes on both screens
+#[tauri::command]
+pub async fn confirm_key_exchange(
+    state: State<'_, SyncState>,
+    exchange_id: String,
+    accept: bool,
+) -> Result<(), String> {
+    let ss = state
+        .server_state()
         .await
-        .map_err(|e| format!("Connection failed: {}", e))?;
+        .ok_or("Sync server is not running")?;
+    let mut pending = ss.key_exchange.lock().await;
+    match pending.as_mut() {
+        Some(exchange)
+            if exchange.handshake.exchange_id == exchange_id
+                && !exchange.handshake.code.is_empty() =>
+        {
+            if accept {
+                exchange.confirmed = true;
+            } else {
+                *pending = None;
+            }
+            Ok(())
+        }
+        _ => Err("No matching key exchange".to_string()),
+    }
+}
+
+/// Start sending API keys to a remote device.
+/// Returns the code to show; the user must check it matches the other screen.
+#[tauri::command]
+pub async fn sync_begin_key_exchange(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    ip: String,
+    port: u16,
+    token: String,
+) -> Result<KeyExchangeHandshake, String> {
+    let secret = ExchangeSecret::generate();
+    let client_public = secret.public_key();
+    let tuning = http::load(&app);
+    let transport = HttpTransport::new(&ip, port, &tuning);
+
+    let request = SyncRequest {
+        token: token.clone(),
+        action: SyncAction::BeginKeyExchange {
+            client_commitment: keys::commitment(&client_public)?,
+            device: Some(device::identity(&app)?),
+        },
+    };
+    let (exchange_id, server_public, peer) =
+        match transport.send(&request, tuning.read_timeout()).await? {
+            SyncResponse::KeyExchangeStarted {
+                exchange_id,
+                server_public,
+                device,
+            } => (exchange_id, server_public, device),
+            SyncResponse::Error { message } => return Err(message),
+            _ => return Err("Unexpected response type".to_string()),
+        };
+
+    let mut handshake = KeyExchangeHandshake {
+        exchange_id: exchange_id.clone(),
+        client_public: client_public.clone(),
+        server_public,
+        code: String::new(),
+        peer,
+    };
+    handshake.code =
+        keys::confirmation_code(&secret, &handshake.server_public, &token, &handshake)?;
+
+    let request = SyncRequest {
+        token,
+        action: SyncAction::RevealKeyExchange {
+            exchange_id: exchange_id.clone(),
+            client_public,
+        },
+    };
+    match transport.send(&request, tuning.read_timeout()).await? {
+        SyncResponse::Success { .. } => {}
+        SyncResponse::Error { message } => return Err(message),
+        _ => return Err("Unexpected response type".to_string()),
+    }
+    state.key_secrets.lock().await.insert(exchange_id, secret);
+    Ok(handshake)
+}
 
-    let sync_response: SyncResponse = response
-        .json()
+/// Encrypt API keys for a confirmed exchange and send them to the remote device,
+/// which stores them in its keychain
+#[tauri::command]
+pub async fn sync_send_api_keys(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    ip: String,
+    port: u16,
+    token: String,
+    handshake: KeyExchangeHandshake,
+    keys: Vec<ApiKeyEntry>,
+) -> Result<(), String> {
+    // The secret only lives here, so a handshake passed back in cannot
+    // swap the keys it is encrypted to
+    let secret = state
+        .key_secrets
+        .lock()
         .await
-        .map_err(|e| format!("Invalid response: {}", e))?;
+        .remove(&handshake.exchange_id)
+        .ok_or("Start the key exchange again")?;
+    let payload = keys::encrypt_keys(&secret, &token, &handshake, &keys)?;
+
+    let request = SyncRequest {
+        token,
+        action: SyncAction::DeliverKeys {
+            exchange_id: handshake.exchange_id,
+            payload,
+        },
+    };
+
+    let tuning = http::load(&app);
+    let sync_response = HttpTransport::new(&ip, port, &tuning)
+        .send(&request, tuning.read_timeout())
+        .await?;
 
     match sync_response {
         SyncResponse::Success { .. } => Ok(()),
@@ -290,3 +1229,193 @@ pub async fn sync_push_story(
         _ => Err("Unexpected response type".to_string()),
     }
 }
+
+/// Answer sync requests on a serial device, such as a Bluetooth RFCOMM channel,
+/// using the running server's stories and token. Runs until the link closes or
+/// the server is stopped.
+#[tauri::command]
+pub async fn serve_sync_on_device(
+    state: State<'_, SyncState>,
+    device_path: String,
+) -> Result<(), String> {
+    let ss = state
+        .server_state()
+        .await
+        .ok_or("Start the sync server before serving a device")?;
+    let device = open_device(&device_path).await?;
+    let task = tokio::spawn(async move {
+        if let Err(e) = serve_stream(ss, device).await {
+            eprintln!("Device sync on {} stopped: {}", device_path, e);
+        }
+    });
+    let mut tasks = state.device_tasks.lock().await;
+    tasks.retain(|t| !t.is_finished());
+    tasks.push(task);
+    Ok(())
+}
+
+/// Send one sync request to a peer over a serial device instead of the network
+#[tauri::command]
+pub async fn sync_device_request(
+    device_path: String,
+    token: String,
+    action: SyncAction,
+) -> Result<SyncResponse, String> {
+    let transport = StreamTransport::new(open_device(&device_path).await?);
+    let request = SyncRequest { token, action };
+    Ok(transport.send(&request, Duration::from_secs(60)).await?)
+}
+
+/// Write local stories and deletions to a sync folder (USB stick or shared drive).
+/// Stories whose folder copy is newer are left alone. Without `tombstones`,
+/// the deletions recorded on this device are written.
+#[tauri::command]
+pub async fn sync_to_folder(
+    app: AppHandle,
+    path: String,
+    stories_json: Vec<String>,
+    tombstones: Option<Vec<StoryTombstone>>,
+) -> Result<FolderSyncReport, String> {
+    let stories_json = profiles::visible_exports(&app, stories_json).await?;
+    let tombstones = match tombstones {
+        Some(tombstones) => tombstones,
+        None => tombstones::list(&app)?,
+    };
+    tokio::task::spawn_blocking(move || folder::sync_to_folder(&path, &stories_json, &tombstones))
+        .await
+        .map_err(|e| format!("Folder sync failed: {}", e))?
+}
+
+/// Read stories from a sync folder that are newer than the local copies given,
+/// and deletions that apply to them. Nothing is imported or deleted here.
+#[tauri::command]
+pub async fn sync_from_folder(
+    path: String,
+    stories_json: Vec<String>,
+) -> Result<FolderSyncReport, String> {
+    tokio::task::spawn_blocking(move || folder::sync_from_folder(&path, &stories_json))
+        .await
+        .map_err(|e| format!("Folder sync failed: {}", e))?
+}
+
+/// Read a provider's API key from the OS keychain
+#[tauri::command]
+pub async fn get_keychain_api_key(provider: String) -> Result<Option<String>, String> {
+    keys::read_from_keychain(&provider)
+}
+
+/// Address e-reader apps use to browse the published catalog
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsCatalogInfo {
+    pub url: String,
+    pub story_count: usize,
+}
+
+/// Publish stories already offered by the sync server as an OPDS catalog.
+/// EPUBs are generated when an e-reader downloads them.
+#[tauri::command]
+pub async fn publish_opds_catalog(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    story_ids: Vec<String>,
+) -> Result<OpdsCatalogInfo, String> {
+    let story_ids = profiles::visible_stories(&app, story_ids).await?;
+    let ss = state
+        .server_state()
+        .await
+        .ok_or("Start the sync server before publishing a catalog")?;
+    let token = Uuid::new_v4().simple().to_string();
+    let url = format!("http://{}:{}/opds/{}", get_local_ip()?, ss.port, token);
+    let story_count = story_ids.len();
+    *ss.opds.lock().await = Some(OpdsCatalog {
+        token,
+        story_ids: story_ids.into_iter().collect(),
+    });
+    Ok(OpdsCatalogInfo { url, story_count })
+}
+
+/// Stop serving the OPDS catalog
+#[tauri::command]
+pub async fn unpublish_opds_catalog(state: State<'_, SyncState>) -> Result<(), String> {
+    if let Some(ss) = state.server_state().await {
+        *ss.opds.lock().await = None;
+    }
+    Ok(())
+}
+
+/// Address of a story shared as a read-only page
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadShareInfo {
+    pub url: String,
+    pub qr_code_base64: String,
+    pub share: ReadShare,
+}
+
+/// Share a story as a read-only web page on the running sync server, so
+/// someone on the same network can read it in a browser. The page always
+/// shows the latest saved text; the link can expire after some minutes.
+#[tauri::command]
+pub async fn share_story_read_only(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    story_id: String,
+    expires_in_minutes: Option<u32>,
+    theme: Option<SiteTheme>,
+) -> Result<ReadShareInfo, String> {
+    profiles::check_story(&app, &story_id).await?;
+    let ss = state
+        .server_state()
+        .await
+        .ok_or("Start the sync server before sharing a story")?;
+    let export = rows::load(&app, &story_id)
+        .await?
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let now = keys::now_ms();
+    let share = ReadShare {
+        token: Uuid::new_v4().simple().to_string(),
+        story_id,
+        title: export.story.title,
+        theme: theme.unwrap_or_default(),
+        created_at: now,
+        expires_at: expires_in_minutes.map(|minutes| now + i64::from(minutes) * 60_000),
+        views: 0,
+    };
+    let url = format!(
+        "http://{}:{}/read/{}",
+        get_local_ip()?,
+        ss.port,
+        share.token
+    );
+    let qr_code_base64 = generate_qr_code(&url)?;
+    ss.shares
+        .lock()
+        .await
+        .insert(share.token.clone(), share.clone());
+    Ok(ReadShareInfo {
+        url,
+        qr_code_base64,
+        share,
+    })
+}
+
+/// Stories shared from the running server, with their view counts
+#[tauri::command]
+pub async fn list_read_shares(state: State<'_, SyncState>) -> Result<Vec<ReadShare>, String> {
+    let Some(ss) = state.server_state().await else {
+        return Ok(Vec::new());
+    };
+    let mut shares: Vec<ReadShare> = ss.shares.lock().await.values().cloned().collect();
+    shares.sort_by_key(|s| s.created_at);
+    Ok(shares)
+}
+
+/// Stop serving a shared story. Returns whether the link existed.
+#[tauri::command]
+pub async fn revoke_read_share(state: State<'_, SyncState>, token: String) -> Result<bool, String> {
+    Ok(match state.server_state().await {
+        Some(ss) => ss.shares.lock().await.remove(&token).is_some(),
+        None => false,
+    })
+}
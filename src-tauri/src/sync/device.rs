@@ -0,0 +1,115 @@
+//! How this device introduces itself to others: a name such as
+//! "Sarah's Laptop" and an optional emoji and color, shown in pairing
+//! screens instead of a bare IP address.
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::store;
+
+/// Device profile in the shared app data directory, the same for every
+/// local profile
+pub const DEVICE_PROFILE_FILE: &str = "device_profile.json";
+
+const MAX_NAME_CHARS: usize = 64;
+
+/// Emoji sequences (flags, skin tones, families) run to several code points
+const MAX_EMOJI_CHARS: usize = 8;
+
+/// The user's choices; unset fields fall back to defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DeviceProfile {
+    pub name: Option<String>,
+    pub avatar_emoji: Option<String>,
+    /// Hex color such as "#3b82f6"
+    pub avatar_color: Option<String>,
+}
+
+/// What other devices are shown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceIdentity {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_emoji: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_color: Option<String>,
+}
+
+/// Name of this computer, used until the user picks one
+pub fn default_device_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "Aventura".to_string())
+}
+
+impl Default for DeviceIdentity {
+    fn default() -> Self {
+        Self {
+            name: default_device_name(),
+            avatar_emoji: None,
+            avatar_color: None,
+        }
+    }
+}
+
+fn is_hex_color(value: &str) -> bool {
+    value
+        .strip_prefix('#')
+        .is_some_and(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+impl DeviceProfile {
+    /// Trim the profile and reject values other devices could not display
+    pub fn validated(self) -> Result<Self, String> {
+        let trimmed = |value: Option<String>| {
+            value
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let profile = Self {
+            name: trimmed(self.name),
+            avatar_emoji: trimmed(self.avatar_emoji),
+            avatar_color: trimmed(self.avatar_color),
+        };
+        if profile
+            .name
+            .as_ref()
+            .is_some_and(|n| n.chars().count() > MAX_NAME_CHARS)
+        {
+            return Err(format!(
+                "Device name must be at most {} characters",
+                MAX_NAME_CHARS
+            ));
+        }
+        if profile
+            .avatar_emoji
+            .as_ref()
+            .is_some_and(|e| e.chars().count() > MAX_EMOJI_CHARS)
+        {
+            return Err("Avatar must be a single emoji".to_string());
+        }
+        if profile
+            .avatar_color
+            .as_ref()
+            .is_some_and(|c| !is_hex_color(c))
+        {
+            return Err("Avatar color must look like #3b82f6".to_string());
+        }
+        Ok(profile)
+    }
+}
+
+/// This device's identity from the saved profile
+pub fn identity(app: &AppHandle) -> Result<DeviceIdentity, String> {
+    let profile: DeviceProfile = store::load_shared_json(app, DEVICE_PROFILE_FILE)?;
+    Ok(DeviceIdentity {
+        name: profile.name.unwrap_or_else(default_device_name),
+        avatar_emoji: profile.avatar_emoji,
+        avatar_color: profile.avatar_color,
+    })
+}
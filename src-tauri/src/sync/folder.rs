@@ -0,0 +1,243 @@
+//! File-based sync for USB sticks and shared drives. A sync folder holds a
+//! manifest, one file per story and deletion tombstones. Conflicts follow the
+//! network rule: the copy with the newer `updatedAt` wins. Stories are
+//! matched by ID, falling back to the title for copies synced before IDs
+//! were kept on import.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::commands::parse_story_preview;
+use super::types::{StoryTombstone, SyncStoryPreview};
+
+/// Directory created inside the chosen folder
+const SYNC_DIR: &str = "aventura-sync";
+const MANIFEST_FILE: &str = "manifest.json";
+const STORIES_DIR: &str = "stories";
+const FORMAT_VERSION: u32 = 1;
+
+/// Index of the folder's contents, so reading it does not parse every story
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FolderManifest {
+    version: u32,
+    #[serde(default)]
+    stories: HashMap<String, SyncStoryPreview>,
+    #[serde(default)]
+    tombstones: HashMap<String, StoryTombstone>,
+}
+
+impl Default for FolderManifest {
+    fn default() -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            stories: HashMap::new(),
+            tombstones: HashMap::new(),
+        }
+    }
+}
+
+/// Outcome of writing to or reading from a sync folder
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderSyncReport {
+    /// Stories written to the folder
+    pub written: Vec<String>,
+    /// Stories skipped because the other side had a newer or identical copy
+    pub skipped: Vec<String>,
+    /// Stories to import (Aventura export JSON), newer in the folder than locally
+    pub stories: Vec<String>,
+    /// Deletions recorded in the folder that apply to local stories
+    pub deletions: Vec<StoryTombstone>,
+    /// Stories that could not be written or read, with the reason; the rest
+    /// are still synced
+    pub errors: Vec<String>,
+}
+
+fn sync_dir(path: &str) -> PathBuf {
+    Path::new(path).join(SYNC_DIR)
+}
+
+fn story_file(dir: &Path, story_id: &str) -> Result<PathBuf, String> {
+    if story_id.is_empty() || story_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid story ID: {}", story_id));
+    }
+    Ok(dir.join(STORIES_DIR).join(format!("{}.avt", story_id)))
+}
+
+fn load_manifest(dir: &Path) -> Result<FolderManifest, String> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(FolderManifest::default());
+    }
+    let contents =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read sync manifest: {}", e))?;
+    let manifest: FolderManifest =
+        serde_json::from_str(&contents).map_err(|e| format!("Invalid sync manifest: {}", e))?;
+    if manifest.version > FORMAT_VERSION {
+        return Err("This sync folder was written by a newer version of Aventura".to_string());
+    }
+    Ok(manifest)
+}
+
+/// Write through a temporary file so a pulled USB stick never holds half a file
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// ID of the folder's copy of a story: the story's own ID, or a story with
+/// the same title that the local library does not have under its ID
+fn folder_copy<'a>(
+    manifest: &'a FolderManifest,
+    preview: &SyncStoryPreview,
+    local_ids: &HashSet<&str>,
+) -> Option<&'a SyncStoryPreview> {
+    manifest.stories.get(&preview.id).or_else(|| {
+        manifest
+            .stories
+            .values()
+            .find(|s| s.title == preview.title && !local_ids.contains(s.id.as_str()))
+    })
+}
+
+fn save_manifest(dir: &Path, manifest: &FolderManifest) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize sync manifest: {}", e))?;
+    write_atomic(&dir.join(MANIFEST_FILE), &json)
+}
+
+/// Copy local stories into the folder where they are newer than the folder's
+/// copy, and record local deletions
+pub fn sync_to_folder(
+    path: &str,
+    stories_json: &[String],
+    tombstones: &[StoryTombstone],
+) -> Result<FolderSyncReport, String> {
+    let dir = sync_dir(path);
+    fs::create_dir_all(dir.join(STORIES_DIR))
+        .map_err(|e| format!("Failed to create sync folder: {}", e))?;
+    let mut manifest = load_manifest(&dir)?;
+    let mut report = FolderSyncReport::default();
+
+    let previews: Vec<(&String, SyncStoryPreview)> = stories_json
+        .iter()
+        .filter_map(|json| match parse_story_preview(json) {
+            Ok(preview) => Some((json, preview)),
+            Err(e) => {
+                report.errors.push(e);
+                None
+            }
+        })
+        .collect();
+    let local_ids: HashSet<&str> = previews.iter().map(|(_, p)| p.id.as_str()).collect();
+    for (json, preview) in &previews {
+        let copy = folder_copy(&manifest, preview, &local_ids);
+        let newer = copy.is_none_or(|existing| preview.updated_at > existing.updated_at);
+        let replaced = copy.map(|existing| existing.id.clone());
+        // A story edited after it was deleted elsewhere comes back to life
+        let deleted = manifest
+            .tombstones
+            .get(&preview.id)
+            .is_some_and(|t| t.deleted_at >= preview.updated_at);
+        if !newer || deleted {
+            report.skipped.push(preview.id.clone());
+            continue;
+        }
+
+        let written =
+            story_file(&dir, &preview.id).and_then(|file| write_atomic(&file, json.as_bytes()));
+        if let Err(e) = written {
+            report.errors.push(format!("{}: {}", preview.title, e));
+            continue;
+        }
+        // A copy found by title is the same story under its old ID
+        if let Some(old_id) = replaced.filter(|id| *id != preview.id) {
+            manifest.stories.remove(&old_id);
+            if let Ok(file) = story_file(&dir, &old_id) {
+                let _ = fs::remove_file(file);
+            }
+        }
+        manifest.tombstones.remove(&preview.id);
+        report.written.push(preview.id.clone());
+        manifest.stories.insert(preview.id.clone(), preview.clone());
+    }
+
+    for tombstone in tombstones {
+        let outdated = manifest
+            .stories
+            .get(&tombstone.story_id)
+            .is_some_and(|s| s.updated_at > tombstone.deleted_at);
+        if outdated {
+            continue;
+        }
+        if let Some(story) = manifest.stories.remove(&tombstone.story_id) {
+            if let Ok(file) = story_file(&dir, &story.id) {
+                let _ = fs::remove_file(file);
+            }
+        }
+        manifest
+            .tombstones
+            .insert(tombstone.story_id.clone(), tombstone.clone());
+    }
+
+    save_manifest(&dir, &manifest)?;
+    Ok(report)
+}
+
+/// Read stories from the folder that are newer than the local copies, plus
+/// deletions of stories that were not edited locally since
+pub fn sync_from_folder(path: &str, stories_json: &[String]) -> Result<FolderSyncReport, String> {
+    let dir = sync_dir(path);
+    let manifest = load_manifest(&dir)?;
+    let local_previews: Vec<SyncStoryPreview> = stories_json
+        .iter()
+        .filter_map(|json| parse_story_preview(json).ok())
+        .collect();
+    let local: HashMap<&str, i64> = local_previews
+        .iter()
+        .map(|p| (p.id.as_str(), p.updated_at))
+        .collect();
+    let folder_ids: HashSet<&str> = manifest.stories.keys().map(String::as_str).collect();
+    let mut report = FolderSyncReport::default();
+
+    let mut previews: Vec<_> = manifest.stories.values().collect();
+    previews.sort_by(|a, b| a.title.cmp(&b.title));
+    for preview in previews {
+        // Same fallback as writing: a local story with the title and an ID
+        // the folder does not know is this story under its old ID
+        let local_updated_at = local.get(preview.id.as_str()).copied().or_else(|| {
+            local_previews
+                .iter()
+                .find(|l| l.title == preview.title && !folder_ids.contains(l.id.as_str()))
+                .map(|l| l.updated_at)
+        });
+        if local_updated_at.is_some_and(|updated_at| preview.updated_at <= updated_at) {
+            report.skipped.push(preview.id.clone());
+            continue;
+        }
+        let read = story_file(&dir, &preview.id).and_then(|file| {
+            fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read {}: {}", file.display(), e))
+        });
+        match read {
+            Ok(json) => report.stories.push(json),
+            Err(e) => report.errors.push(format!("{}: {}", preview.title, e)),
+        }
+    }
+
+    report.deletions = manifest
+        .tombstones
+        .values()
+        .filter(|t| {
+            local
+                .get(t.story_id.as_str())
+                .is_some_and(|updated_at| t.deleted_at >= *updated_at)
+        })
+        .cloned()
+        .collect();
+    Ok(report)
+}
@@ -0,0 +1,93 @@
+//! `GET /health` lets a client check who it is talking to and whether the two
+//! apps can sync before it has the pairing token.
+
+use axum::{extract::State, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::AppHandle;
+
+use super::device::DeviceIdentity;
+use super::http;
+use super::keys::now_ms;
+use super::server::ServerState;
+
+/// Version of the `/sync` request protocol. Raised when a change would
+/// confuse older peers; additions they can ignore do not count.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Identity and abilities of a sync server, public without a token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthInfo {
+    /// Name and avatar to show in the pairing UI
+    #[serde(flatten)]
+    pub device: DeviceIdentity,
+    pub app_version: String,
+    pub protocol_version: u32,
+    /// Optional features this server offers, e.g. "msgpack" or "game"
+    pub capabilities: Vec<String>,
+    /// The server's clock when it answered, for measuring skew. Missing
+    /// from older servers.
+    #[serde(default)]
+    pub server_time: Option<i64>,
+}
+
+async fn capabilities(state: &ServerState) -> Vec<String> {
+    let mut capabilities = vec![
+        "msgpack",
+        "cbor",
+        "settings",
+        "keyExchange",
+        "deletions",
+        "thumbnails",
+        "lorebooks",
+        "styleReferences",
+    ];
+    if state.game.lock().await.is_some() {
+        capabilities.push("game");
+    }
+    if state.spectators.is_running().await {
+        capabilities.push("spectate");
+    }
+    if state.opds.lock().await.is_some() {
+        capabilities.push("opds");
+    }
+    if state.expose_metrics {
+        capabilities.push("metrics");
+    }
+    capabilities.into_iter().map(String::from).collect()
+}
+
+async fn handle_health(State(state): State<ServerState>) -> Json<HealthInfo> {
+    Json(HealthInfo {
+        device: state.device.clone(),
+        app_version: state.app.package_info().version.to_string(),
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: capabilities(&state).await,
+        server_time: Some(now_ms()),
+    })
+}
+
+/// Ask a server for its health info. Servers from before this endpoint
+/// answer 404, which is reported as an unknown version.
+pub async fn fetch(app: &AppHandle, ip: &str, port: u16) -> Result<HealthInfo, String> {
+    let response = http::client(&http::load(app))
+        .get(format!("http://{}:{}/health", ip, port))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .map_err(|e| format!("Connection failed: {}", e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err("This device runs a version of Aventura that is too old to check".to_string());
+    }
+    response
+        .error_for_status()
+        .map_err(|e| format!("Health check failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid response: {}", e))
+}
+
+pub fn routes() -> Router<ServerState> {
+    Router::new().route("/health", get(handle_health))
+}
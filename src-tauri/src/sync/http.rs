@@ -0,0 +1,115 @@
+//! How this device talks HTTP to sync peers: how long to wait for a
+//! connection and for an answer, how often to try again, and how long idle
+//! connections are kept open. Slow or flaky networks can need more patience
+//! than the defaults.
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::store;
+
+/// HTTP settings in the app data directory
+pub const HTTP_TUNING_FILE: &str = "sync_http.json";
+
+/// Idle connections kept open to each peer
+const MAX_IDLE_PER_PEER: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpTuning {
+    /// Seconds to wait for a peer to accept the connection
+    pub connect_timeout_secs: u64,
+    /// Seconds to wait for a peer to answer, including the transfer
+    pub read_timeout_secs: u64,
+    /// Further attempts after a peer could not be reached
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each further one
+    pub retry_delay_ms: u64,
+    /// Seconds an idle connection is kept open for the next request.
+    /// 0 opens a new connection for every request.
+    pub keep_alive_secs: u64,
+}
+
+impl Default for HttpTuning {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            retries: 2,
+            retry_delay_ms: 500,
+            keep_alive_secs: 90,
+        }
+    }
+}
+
+impl HttpTuning {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs.max(1))
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout_secs.max(1))
+    }
+
+    /// Wait before retry number `attempt` (from 0), with up to half of it
+    /// added at random so peers that failed together do not retry together
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        let base = self
+            .retry_delay_ms
+            .saturating_mul(1 << attempt.min(16))
+            .min(60_000);
+        let jitter = match base / 2 {
+            0 => 0,
+            spread => OsRng.next_u64() % (spread + 1),
+        };
+        Duration::from_millis(base + jitter)
+    }
+
+    fn build_client(&self) -> Result<reqwest::Client, String> {
+        let builder = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout())
+            .read_timeout(self.read_timeout());
+        let builder = if self.keep_alive_secs == 0 {
+            builder.pool_max_idle_per_host(0)
+        } else {
+            let keep_alive = Duration::from_secs(self.keep_alive_secs);
+            builder
+                .pool_max_idle_per_host(MAX_IDLE_PER_PEER)
+                .pool_idle_timeout(keep_alive)
+                .tcp_keepalive(keep_alive)
+        };
+        builder
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))
+    }
+}
+
+/// Saved settings, or the defaults when there are none
+pub fn load(app: &AppHandle) -> HttpTuning {
+    store::load_json(app, HTTP_TUNING_FILE).unwrap_or_default()
+}
+
+/// The client all requests to peers go through, so syncing many stories
+/// reuses open connections instead of connecting again for each one. It is
+/// built on first use and again whenever the tuning changes.
+pub fn client(tuning: &HttpTuning) -> reqwest::Client {
+    static CLIENT: OnceLock<Mutex<Option<(HttpTuning, reqwest::Client)>>> = OnceLock::new();
+    let mut shared = CLIENT
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some((built_with, client)) = shared.as_ref() {
+        if built_with == tuning {
+            return client.clone();
+        }
+    }
+    // Building only fails when TLS cannot be set up, which plain HTTP to a
+    // peer does not need
+    let client = tuning.build_client().unwrap_or_default();
+    *shared = Some((tuning.clone(), client.clone()));
+    client
+}
@@ -0,0 +1,261 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::device::DeviceIdentity;
+use crate::portable::{self, secrets};
+
+/// Keychain service name under which provider keys are stored
+pub const KEYCHAIN_SERVICE: &str = "aventura";
+
+/// How long a started exchange stays valid
+pub const EXCHANGE_TTL_MS: i64 = 5 * 60 * 1000;
+
+/// Prefix of the HKDF info strings, naming the protocol version
+const EXCHANGE_INFO: &str = "aventura-key-exchange-v2";
+
+/// An API key for one provider, as transferred between devices
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyEntry {
+    /// Provider identifier, used as the keychain account name
+    pub provider: String,
+    pub api_key: String,
+}
+
+/// Encrypted key bundle sent from one device to the other
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedKeys {
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Public values of an exchange. Both devices derive the code and the
+/// encryption key from an X25519 agreement between the two public keys,
+/// so the pairing token alone is not enough to read the keys.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyExchangeHandshake {
+    pub exchange_id: String,
+    pub client_public: String,
+    pub server_public: String,
+    /// Code shown on both screens; the user checks they match before confirming
+    pub code: String,
+    /// The other device, if it introduced itself
+    #[serde(default)]
+    pub peer: Option<DeviceIdentity>,
+}
+
+/// Exchange waiting for the host user to compare codes
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingKeyExchange {
+    #[serde(flatten)]
+    pub handshake: KeyExchangeHandshake,
+    pub confirmed: bool,
+    pub expires_at: i64,
+    /// What the client committed to before learning the host's public key
+    #[serde(skip)]
+    pub client_commitment: String,
+    #[serde(skip)]
+    pub secret: ExchangeSecret,
+}
+
+/// One side's X25519 secret, made for a single exchange and kept in memory
+#[derive(Clone)]
+pub struct ExchangeSecret(StaticSecret);
+
+impl std::fmt::Debug for ExchangeSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ExchangeSecret(..)")
+    }
+}
+
+impl Default for ExchangeSecret {
+    fn default() -> Self {
+        Self::generate()
+    }
+}
+
+impl ExchangeSecret {
+    pub fn generate() -> Self {
+        Self(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// The public key, base64 encoded
+    pub fn public_key(&self) -> String {
+        STANDARD.encode(PublicKey::from(&self.0).as_bytes())
+    }
+
+    fn agree(&self, peer_public: &str) -> Result<[u8; 32], String> {
+        let shared = self
+            .0
+            .diffie_hellman(&PublicKey::from(decode_public(peer_public)?));
+        // A low-order peer key forces a known shared secret
+        if !shared.was_contributory() {
+            return Err("Invalid key exchange public key".to_string());
+        }
+        Ok(shared.to_bytes())
+    }
+}
+
+pub fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn decode_public(key: &str) -> Result<[u8; 32], String> {
+    STANDARD
+        .decode(key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| "Invalid key exchange public key".to_string())
+}
+
+/// Hash of the client's public key, sent before the host picks its own so
+/// neither side can choose a key after seeing the other's to steer the code
+pub fn commitment(public_key: &str) -> Result<String, String> {
+    let digest = Sha256::new()
+        .chain_update(format!("{}:commit", EXCHANGE_INFO))
+        .chain_update(decode_public(public_key)?)
+        .finalize();
+    Ok(STANDARD.encode(digest))
+}
+
+/// HKDF over the shared secret, with the pairing token as salt and both
+/// public keys bound into the output
+fn expand(
+    secret: &ExchangeSecret,
+    peer_public: &str,
+    token: &str,
+    handshake: &KeyExchangeHandshake,
+    purpose: &str,
+    out: &mut [u8],
+) -> Result<(), String> {
+    let shared = secret.agree(peer_public)?;
+    let mut info = format!("{}:{}", EXCHANGE_INFO, purpose).into_bytes();
+    info.extend(decode_public(&handshake.client_public)?);
+    info.extend(decode_public(&handshake.server_public)?);
+    Hkdf::<Sha256>::new(Some(token.as_bytes()), &shared)
+        .expand(&info, out)
+        .map_err(|e| format!("Failed to derive key: {}", e))
+}
+
+/// Six-digit short authentication string for both users to compare. A
+/// device in the middle ends up with a different shared secret on each
+/// side, so the codes differ.
+pub fn confirmation_code(
+    secret: &ExchangeSecret,
+    peer_public: &str,
+    token: &str,
+    handshake: &KeyExchangeHandshake,
+) -> Result<String, String> {
+    let mut bytes = [0u8; 4];
+    expand(secret, peer_public, token, handshake, "code", &mut bytes)?;
+    Ok(format!("{:06}", u32::from_be_bytes(bytes) % 1_000_000))
+}
+
+fn derive_key(
+    secret: &ExchangeSecret,
+    peer_public: &str,
+    token: &str,
+    handshake: &KeyExchangeHandshake,
+) -> Result<Key, String> {
+    let mut key = Key::default();
+    expand(secret, peer_public, token, handshake, "key", &mut key)?;
+    Ok(key)
+}
+
+/// Encrypt keys on the client, which holds the client secret
+pub fn encrypt_keys(
+    secret: &ExchangeSecret,
+    token: &str,
+    handshake: &KeyExchangeHandshake,
+    keys: &[ApiKeyEntry],
+) -> Result<EncryptedKeys, String> {
+    let key = derive_key(secret, &handshake.server_public, token, handshake)?;
+    let cipher = ChaCha20Poly1305::new(&key);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let plaintext =
+        serde_json::to_vec(keys).map_err(|e| format!("Failed to encode keys: {}", e))?;
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| "Failed to encrypt keys".to_string())?;
+    Ok(EncryptedKeys {
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+/// Decrypt keys on the host, which holds the host secret
+pub fn decrypt_keys(
+    secret: &ExchangeSecret,
+    token: &str,
+    handshake: &KeyExchangeHandshake,
+    payload: &EncryptedKeys,
+) -> Result<Vec<ApiKeyEntry>, String> {
+    let key = derive_key(secret, &handshake.client_public, token, handshake)?;
+    let cipher = ChaCha20Poly1305::new(&key);
+    let nonce = STANDARD
+        .decode(&payload.nonce)
+        .map_err(|_| "Invalid payload nonce".to_string())?;
+    if nonce.len() != 12 {
+        return Err("Invalid payload nonce".to_string());
+    }
+    let ciphertext = STANDARD
+        .decode(&payload.ciphertext)
+        .map_err(|_| "Invalid payload".to_string())?;
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| "Key payload could not be decrypted; check the codes match".to_string())?;
+    serde_json::from_slice(&plaintext).map_err(|_| "Invalid key payload".to_string())
+}
+
+/// Write keys into the OS keychain. Keys never touch the app data directory,
+/// except in portable mode, where they go to its encrypted secrets file.
+pub fn store_in_keychain(keys: &[ApiKeyEntry]) -> Result<usize, String> {
+    if portable::is_enabled() {
+        secrets::set_all(
+            keys.iter()
+                .map(|entry| (entry.provider.as_str(), entry.api_key.as_str())),
+        )?;
+        return Ok(keys.len());
+    }
+    for entry in keys {
+        keyring::Entry::new(KEYCHAIN_SERVICE, &entry.provider)
+            .and_then(|e| e.set_password(&entry.api_key))
+            .map_err(|e| format!("Failed to store key for {}: {}", entry.provider, e))?;
+    }
+    Ok(keys.len())
+}
+
+/// Read a provider key from the OS keychain
+pub fn read_from_keychain(provider: &str) -> Result<Option<String>, String> {
+    if portable::is_enabled() {
+        return secrets::get(provider);
+    }
+    match keyring::Entry::new(KEYCHAIN_SERVICE, provider).and_then(|e| e.get_password()) {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read key for {}: {}", provider, e)),
+    }
+}
+
+/// Remove a key from the OS keychain, or from the secrets file in portable
+/// mode; a missing key is not an error
+pub fn remove_from_keychain(provider: &str) -> Result<(), String> {
+    if portable::is_enabled() {
+        return secrets::remove(provider);
+    }
+    match keyring::Entry::new(KEYCHAIN_SERVICE, provider).and_then(|e| e.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("Failed to remove key for {}: {}", provider, e)),
+    }
+}
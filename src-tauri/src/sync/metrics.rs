@@ -0,0 +1,214 @@
+//! Request counters for the sync server, readable in the app with
+//! `get_sync_metrics` or scraped from `/metrics` in Prometheus text format
+//! when the host turned the endpoint on.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::keys::now_ms;
+use super::server::ServerState;
+
+/// Counts for one kind of sync action
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionMetrics {
+    pub action: String,
+    pub requests: u64,
+    pub errors: u64,
+}
+
+/// Point-in-time view of the server's counters
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncMetricsSnapshot {
+    pub uptime_secs: u64,
+    pub actions: Vec<ActionMetrics>,
+    pub total_requests: u64,
+    pub total_errors: u64,
+    /// Share of requests that failed, from 0 to 1
+    pub error_rate: f64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Players in the multiplayer session, host included
+    pub active_players: usize,
+    pub active_spectators: usize,
+}
+
+/// Counters shared by every transport of one server
+#[derive(Debug)]
+pub struct SyncMetrics {
+    started_at: i64,
+    actions: Mutex<BTreeMap<&'static str, ActionMetrics>>,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+impl Default for SyncMetrics {
+    fn default() -> Self {
+        Self {
+            started_at: now_ms(),
+            actions: Mutex::default(),
+            bytes_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+        }
+    }
+}
+
+impl SyncMetrics {
+    /// Count a request for `action`, and whether it failed
+    pub fn record(&self, action: &'static str, failed: bool) {
+        if let Ok(mut actions) = self.actions.lock() {
+            let counts = actions.entry(action).or_default();
+            counts.requests += 1;
+            if failed {
+                counts.errors += 1;
+            }
+        }
+    }
+
+    pub fn record_transfer(&self, received: usize, sent: usize) {
+        self.bytes_received
+            .fetch_add(received as u64, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(sent as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, active_players: usize, active_spectators: usize) -> SyncMetricsSnapshot {
+        let actions: Vec<ActionMetrics> = self
+            .actions
+            .lock()
+            .map(|actions| {
+                actions
+                    .iter()
+                    .map(|(name, counts)| ActionMetrics {
+                        action: name.to_string(),
+                        ..counts.clone()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let total_requests = actions.iter().map(|a| a.requests).sum();
+        let total_errors = actions.iter().map(|a| a.errors).sum();
+        SyncMetricsSnapshot {
+            uptime_secs: (now_ms() - self.started_at).max(0) as u64 / 1000,
+            actions,
+            total_requests,
+            total_errors,
+            error_rate: if total_requests == 0 {
+                0.0
+            } else {
+                total_errors as f64 / total_requests as f64
+            },
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            active_players,
+            active_spectators,
+        }
+    }
+}
+
+/// Current metrics of a running server
+pub async fn snapshot(state: &ServerState) -> SyncMetricsSnapshot {
+    let players = state
+        .game
+        .lock()
+        .await
+        .as_ref()
+        .map_or(0, |game| game.status().players.len());
+    state
+        .metrics
+        .snapshot(players, state.spectators.viewer_count())
+}
+
+/// Render a snapshot in the Prometheus text exposition format
+pub fn render_prometheus(metrics: &SyncMetricsSnapshot) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+        let _ = writeln!(out, "# HELP aventura_sync_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE aventura_sync_{} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "aventura_sync_{}{} {}", name, labels, value);
+        }
+    };
+    let per_action = |value: fn(&ActionMetrics) -> u64| {
+        metrics
+            .actions
+            .iter()
+            .map(|a| (format!("{{action=\"{}\"}}", a.action), value(a).to_string()))
+            .collect()
+    };
+    let single = |value: String| vec![(String::new(), value)];
+
+    metric(
+        "requests_total",
+        "counter",
+        "Sync requests handled, by action.",
+        per_action(|a| a.requests),
+    );
+    metric(
+        "errors_total",
+        "counter",
+        "Sync requests answered with an error, by action.",
+        per_action(|a| a.errors),
+    );
+    metric(
+        "received_bytes_total",
+        "counter",
+        "Request bytes received.",
+        single(metrics.bytes_received.to_string()),
+    );
+    metric(
+        "sent_bytes_total",
+        "counter",
+        "Response bytes sent.",
+        single(metrics.bytes_sent.to_string()),
+    );
+    metric(
+        "active_players",
+        "gauge",
+        "Players in the multiplayer session.",
+        single(metrics.active_players.to_string()),
+    );
+    metric(
+        "active_spectators",
+        "gauge",
+        "Spectators following the story.",
+        single(metrics.active_spectators.to_string()),
+    );
+    metric(
+        "uptime_seconds",
+        "gauge",
+        "Seconds since the server started.",
+        single(metrics.uptime_secs.to_string()),
+    );
+    out
+}
+
+/// Prometheus scrape endpoint; needs the sync token as a bearer token
+async fn scrape(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if token != Some(state.token.as_str()) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render_prometheus(&snapshot(&state).await),
+    )
+        .into_response()
+}
+
+pub fn routes() -> Router<ServerState> {
+    Router::new().route("/metrics", get(scrape))
+}
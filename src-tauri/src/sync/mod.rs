@@ -1,5 +1,27 @@
+pub mod access_log;
+pub mod clock;
+pub mod codec;
 pub mod commands;
+pub mod device;
+pub mod folder;
+pub mod health;
+pub mod http;
+pub mod keys;
+pub mod metrics;
+pub mod network;
+pub mod opds;
+pub mod outbox;
+pub mod partial;
+pub mod payload;
+pub mod plan;
+pub mod preview;
 pub mod server;
+pub mod session;
+pub mod settings;
+pub mod share;
+pub mod supervisor;
+pub mod tombstones;
+pub mod transport;
 pub mod types;
 
 pub use commands::SyncState;
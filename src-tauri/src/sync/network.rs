@@ -0,0 +1,116 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+
+use super::server::ServerState;
+
+/// Which address the sync server listens on and who may reach it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkBinding {
+    /// Interface name (e.g. "en0") or IP address to bind; all interfaces when unset
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// Reject requests from addresses outside private, link-local and loopback ranges
+    #[serde(default)]
+    pub lan_only: bool,
+}
+
+/// A network the sync server can be reached on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkInterfaceInfo {
+    pub name: String,
+    pub ip: String,
+    /// True for private (RFC 1918 / unique local) and link-local addresses
+    pub is_private: bool,
+    pub is_loopback: bool,
+}
+
+/// Whether an address belongs to a local network rather than the internet
+pub fn is_local_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_private() || v4.is_link_local() || v4.is_loopback(),
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_local_address(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            // fc00::/7 unique local, fe80::/10 link-local
+            v6.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+/// IPv4 and IPv6 addresses of this machine's interfaces
+pub fn list_interfaces() -> Result<Vec<NetworkInterfaceInfo>, String> {
+    let interfaces = local_ip_address::list_afinet_netifas()
+        .map_err(|e| format!("Failed to list network interfaces: {}", e))?;
+    Ok(interfaces
+        .into_iter()
+        .map(|(name, ip)| NetworkInterfaceInfo {
+            name,
+            ip: ip.to_string(),
+            is_private: !ip.is_loopback() && is_local_address(ip),
+            is_loopback: ip.is_loopback(),
+        })
+        .collect())
+}
+
+/// Resolve the address to bind and the networks the server will be visible on
+pub fn resolve_binding(
+    binding: &NetworkBinding,
+) -> Result<(IpAddr, Vec<NetworkInterfaceInfo>), String> {
+    let interfaces = list_interfaces()?;
+    let Some(wanted) = binding.interface.as_deref() else {
+        let visible = interfaces
+            .into_iter()
+            .filter(|i| !i.is_loopback && (i.is_private || !binding.lan_only))
+            .collect();
+        return Ok((IpAddr::from([0, 0, 0, 0]), visible));
+    };
+
+    // Prefer IPv4 when an interface has several addresses; QR codes carry one IP
+    let mut matches: Vec<_> = interfaces
+        .into_iter()
+        .filter(|i| i.name == wanted || i.ip == wanted)
+        .collect();
+    matches.sort_by_key(|i| i.ip.contains(':'));
+    let chosen = matches
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Network interface not found: {}", wanted))?;
+    let ip: IpAddr = chosen
+        .ip
+        .parse()
+        .map_err(|e| format!("Invalid interface address {}: {}", chosen.ip, e))?;
+    if binding.lan_only && !is_local_address(ip) {
+        return Err(format!(
+            "{} ({}) is not a local network address",
+            chosen.name, chosen.ip
+        ));
+    }
+    Ok((ip, vec![chosen]))
+}
+
+/// Middleware rejecting requests from outside the local network in LAN-only mode
+pub async fn lan_guard(
+    State(state): State<ServerState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.lan_only && !is_local_address(peer.ip()) {
+        return (
+            StatusCode::FORBIDDEN,
+            "This server only accepts connections from the local network",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
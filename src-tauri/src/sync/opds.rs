@@ -0,0 +1,148 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::collections::HashSet;
+
+use super::access_log::AccessNote;
+use super::server::ServerState;
+use crate::export::cover;
+use crate::export::epub::{build_epub, rfc3339};
+use crate::export::site::escape_html;
+use crate::story::StoryExport;
+
+const FEED_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+
+/// OPDS catalog published on the sync server
+#[derive(Debug, Clone)]
+pub struct OpdsCatalog {
+    /// Secret path segment; e-reader apps rarely support custom headers
+    pub token: String,
+    /// Stories listed in the catalog
+    pub story_ids: HashSet<String>,
+}
+
+/// Routes for e-reader apps, merged into the sync router
+pub fn routes() -> Router<ServerState> {
+    Router::new()
+        .route("/opds/{token}", get(handle_feed))
+        .route("/opds/{token}/stories/{file}", get(handle_download))
+}
+
+async fn catalog_for(state: &ServerState, token: &str) -> Option<OpdsCatalog> {
+    state
+        .opds
+        .lock()
+        .await
+        .clone()
+        .filter(|catalog| catalog.token == token)
+}
+
+async fn handle_feed(State(state): State<ServerState>, Path(token): Path<String>) -> Response {
+    let Some(catalog) = catalog_for(&state, &token).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let stories = state.stories.lock().await;
+    let base = format!("/opds/{}", token);
+
+    let mut entries = String::new();
+    let mut latest = 0;
+    for story in stories
+        .iter()
+        .filter(|s| catalog.story_ids.contains(&s.preview.id))
+    {
+        let preview = &story.preview;
+        latest = latest.max(preview.updated_at);
+        let summary = preview
+            .genre
+            .as_deref()
+            .map(|g| format!("<summary>{}</summary>", escape_html(g)))
+            .unwrap_or_default();
+        entries.push_str(&format!(
+            r#"  <entry>
+    <title>{title}</title>
+    <id>urn:uuid:{id}</id>
+    <updated>{updated}</updated>
+    <author><name>Aventura</name></author>
+    {summary}
+    <link rel="http://opds-spec.org/acquisition" href="{base}/stories/{id}.epub" type="application/epub+zip"/>
+  </entry>
+"#,
+            title = escape_html(&preview.title),
+            id = escape_html(&preview.id),
+            updated = rfc3339(preview.updated_at),
+        ));
+    }
+
+    let feed = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:opds="http://opds-spec.org/2010/catalog">
+  <id>urn:aventura:catalog:{token}</id>
+  <title>Aventura Library</title>
+  <updated>{updated}</updated>
+  <link rel="self" href="{base}" type="{FEED_TYPE}"/>
+  <link rel="start" href="{base}" type="{FEED_TYPE}"/>
+{entries}</feed>
+"#,
+        token = escape_html(&token),
+        updated = rfc3339(latest),
+    );
+    ([(header::CONTENT_TYPE, FEED_TYPE)], feed).into_response()
+}
+
+async fn handle_download(
+    State(state): State<ServerState>,
+    Path((token, file)): Path<(String, String)>,
+) -> Response {
+    let Some(catalog) = catalog_for(&state, &token).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(story_id) = file.strip_suffix(".epub") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !catalog.story_ids.contains(story_id) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Some(json) = state
+        .stories
+        .lock()
+        .await
+        .iter()
+        .find(|s| s.preview.id == story_id)
+        .map(|s| s.full_data.clone())
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    // EPUBs are generated on demand; building one is CPU-bound
+    let app = state.app.clone();
+    let built = tokio::task::spawn_blocking(move || {
+        json.read()
+            .and_then(|json| StoryExport::from_json(&json))
+            .and_then(|export| {
+                build_epub(&export, cover::cover_for(&app, &export.story.id).as_deref())
+            })
+    })
+    .await;
+    let note = AccessNote {
+        story_id: Some(story_id.to_string()),
+        ..Default::default()
+    };
+    note.on(match built {
+        Ok(Ok(epub)) => (
+            [
+                (header::CONTENT_TYPE, "application/epub+zip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.epub\"", story_id),
+                ),
+            ],
+            epub,
+        )
+            .into_response(),
+        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    })
+}
@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::commands::{parse_story_preview, push_story_to, PushError};
+use super::http;
+use super::keys::now_ms;
+use super::SyncState;
+use crate::store;
+
+/// Index of queued operations in the app data directory
+const OUTBOX_FILE: &str = "sync_outbox.json";
+
+/// Directory holding the story payload of each queued push
+const OUTBOX_DIR: &str = "sync_outbox";
+
+/// How often the worker checks for operations that are due
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+const INITIAL_BACKOFF_MS: i64 = 30_000;
+const MAX_BACKOFF_MS: i64 = 60 * 60 * 1000;
+
+/// A push waiting for its peer to come back online
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingSyncOp {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    token: String,
+    pub story_id: String,
+    pub title: String,
+    pub attempts: u32,
+    pub created_at: i64,
+    pub next_attempt_at: i64,
+    pub last_error: Option<String>,
+}
+
+/// Serialized form handed to the frontend, without the pairing token
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingSyncOpInfo {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    pub story_id: String,
+    pub title: String,
+    pub attempts: u32,
+    pub created_at: i64,
+    pub next_attempt_at: i64,
+    pub last_error: Option<String>,
+}
+
+impl From<&PendingSyncOp> for PendingSyncOpInfo {
+    fn from(op: &PendingSyncOp) -> Self {
+        Self {
+            id: op.id.clone(),
+            ip: op.ip.clone(),
+            port: op.port,
+            story_id: op.story_id.clone(),
+            title: op.title.clone(),
+            attempts: op.attempts,
+            created_at: op.created_at,
+            next_attempt_at: op.next_attempt_at,
+            last_error: op.last_error.clone(),
+        }
+    }
+}
+
+/// Serializes access to the outbox and tracks the background worker
+#[derive(Clone, Default)]
+pub struct Outbox {
+    lock: Arc<Mutex<()>>,
+    worker_running: Arc<AtomicBool>,
+    /// Operations being pushed right now
+    sending: Arc<std::sync::Mutex<HashSet<String>>>,
+}
+
+fn payload_path(app: &AppHandle, op_id: &str) -> Result<PathBuf, String> {
+    let dir = store::data_file(app, OUTBOX_DIR)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create outbox directory: {}", e))?;
+    Ok(dir.join(format!("{}.json", op_id)))
+}
+
+fn backoff_ms(attempts: u32) -> i64 {
+    INITIAL_BACKOFF_MS
+        .saturating_mul(1 << attempts.min(16))
+        .min(MAX_BACKOFF_MS)
+}
+
+impl Outbox {
+    /// Queue a story push for later delivery and make sure the worker is running
+    pub async fn enqueue(
+        &self,
+        app: &AppHandle,
+        ip: &str,
+        port: u16,
+        token: &str,
+        story_json: &str,
+        last_error: String,
+    ) -> Result<String, String> {
+        let preview = parse_story_preview(story_json)?;
+        let guard = self.lock.lock().await;
+        let id = Uuid::new_v4().to_string();
+        fs::write(payload_path(app, &id)?, story_json)
+            .map_err(|e| format!("Failed to queue story: {}", e))?;
+
+        let mut ops: Vec<PendingSyncOp> = store::load_json(app, OUTBOX_FILE)?;
+        let now = now_ms();
+        ops.push(PendingSyncOp {
+            id: id.clone(),
+            ip: ip.to_string(),
+            port,
+            token: token.to_string(),
+            story_id: preview.id,
+            title: preview.title,
+            attempts: 1,
+            created_at: now,
+            next_attempt_at: now + backoff_ms(0),
+            last_error: Some(last_error),
+        });
+        store::save_json(app, OUTBOX_FILE, &ops)?;
+        drop(guard);
+
+        self.ensure_worker(app.clone());
+        Ok(id)
+    }
+
+    pub async fn list(&self, app: &AppHandle) -> Result<Vec<PendingSyncOpInfo>, String> {
+        let _guard = self.lock.lock().await;
+        let ops: Vec<PendingSyncOp> = store::load_json(app, OUTBOX_FILE)?;
+        Ok(ops.iter().map(PendingSyncOpInfo::from).collect())
+    }
+
+    /// Drop a queued operation. Returns false if it was not queued.
+    pub async fn cancel(&self, app: &AppHandle, op_id: &str) -> Result<bool, String> {
+        let _guard = self.lock.lock().await;
+        let mut ops: Vec<PendingSyncOp> = store::load_json(app, OUTBOX_FILE)?;
+        let before = ops.len();
+        ops.retain(|op| op.id != op_id);
+        if ops.len() == before {
+            return Ok(false);
+        }
+        store::save_json(app, OUTBOX_FILE, &ops)?;
+        let _ = fs::remove_file(payload_path(app, op_id)?);
+        Ok(true)
+    }
+
+    /// A peer at this address was just reached: adopt its current port and
+    /// token and retry everything queued for it straight away
+    pub async fn peer_seen(
+        &self,
+        app: &AppHandle,
+        ip: &str,
+        port: u16,
+        token: &str,
+    ) -> Result<(), String> {
+        let guard = self.lock.lock().await;
+        let mut ops: Vec<PendingSyncOp> = store::load_json(app, OUTBOX_FILE)?;
+        let mut changed = false;
+        for op in ops.iter_mut().filter(|op| op.ip == ip) {
+            op.port = port;
+            op.token = token.to_string();
+            op.next_attempt_at = 0;
+            changed = true;
+        }
+        if !changed {
+            return Ok(());
+        }
+        store::save_json(app, OUTBOX_FILE, &ops)?;
+        drop(guard);
+
+        let outbox = self.clone();
+        let handle = app.clone();
+        tauri::async_runtime::spawn(async move {
+            outbox.flush_due(&handle).await;
+        });
+        self.ensure_worker(app.clone());
+        Ok(())
+    }
+
+    /// Hold off every outbox operation while the guard lives, so none reads
+    /// one profile's queue and writes another's
+    pub async fn pause(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+
+    /// Start the retry loop unless it is already running
+    pub fn ensure_worker(&self, app: AppHandle) {
+        if self.worker_running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let outbox = self.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                if outbox.flush_due(&app).await > 0 {
+                    continue;
+                }
+                // Checked under the lock `enqueue` writes under, so a push
+                // queued now either is seen here or starts a new worker
+                let _guard = outbox.lock.lock().await;
+                let empty = store::load_json::<Vec<PendingSyncOp>>(&app, OUTBOX_FILE)
+                    .map_or(true, |ops| ops.is_empty());
+                if empty {
+                    outbox.worker_running.store(false, Ordering::SeqCst);
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Attempt every operation that is due, returning how many remain queued.
+    /// The lock is only held to read and update the queue, not while
+    /// pushing, so queuing and cancelling never wait on the network.
+    async fn flush_due(&self, app: &AppHandle) -> usize {
+        let due: Vec<PendingSyncOp> = {
+            let _guard = self.lock.lock().await;
+            let Ok(ops) = store::load_json::<Vec<PendingSyncOp>>(app, OUTBOX_FILE) else {
+                return 0;
+            };
+            let now = now_ms();
+            let Ok(mut sending) = self.sending.lock() else {
+                return ops.len();
+            };
+            // Another flush may already be pushing some of them
+            ops.into_iter()
+                .filter(|op| op.next_attempt_at <= now && sending.insert(op.id.clone()))
+                .collect()
+        };
+
+        let tuning = http::load(app);
+        let mut results = Vec::with_capacity(due.len());
+        for op in due {
+            let result = match payload_path(app, &op.id).ok().map(fs::read_to_string) {
+                Some(Ok(story_json)) => {
+                    push_story_to(&op.ip, op.port, &op.token, story_json, &tuning)
+                        .await
+                        .map(|()| true)
+                }
+                // Payload is gone; nothing left to deliver
+                Some(Err(_)) => Ok(false),
+                None => Err(PushError::Unreachable(
+                    "Outbox directory is missing".to_string(),
+                )),
+            };
+            results.push((op.id, result));
+        }
+
+        // The queue may have changed while pushing: reread it and only
+        // update operations that are still there
+        let _guard = self.lock.lock().await;
+        let Ok(mut ops) = store::load_json::<Vec<PendingSyncOp>>(app, OUTBOX_FILE) else {
+            return 0;
+        };
+        let now = now_ms();
+        for (id, result) in results {
+            if let Ok(mut sending) = self.sending.lock() {
+                sending.remove(&id);
+            }
+            let Some(index) = ops.iter().position(|op| op.id == id) else {
+                continue;
+            };
+            match result {
+                Ok(_) => {
+                    ops.remove(index);
+                    if let Ok(path) = payload_path(app, &id) {
+                        let _ = fs::remove_file(path);
+                    }
+                }
+                Err(e) => {
+                    let op = &mut ops[index];
+                    op.last_error = Some(e.to_string());
+                    op.next_attempt_at = now + backoff_ms(op.attempts);
+                    op.attempts += 1;
+                    // A rejected push will not succeed by retrying with the same token,
+                    // but the peer may be restarted and rediscovered later
+                    if matches!(e, PushError::Rejected(_)) {
+                        op.next_attempt_at = now + MAX_BACKOFF_MS;
+                    }
+                }
+            }
+        }
+        let _ = store::save_json(app, OUTBOX_FILE, &ops);
+        ops.len()
+    }
+}
+
+/// Resume retrying pushes queued in a previous run
+pub fn resume(app: &AppHandle) {
+    let has_pending = store::load_json::<Vec<PendingSyncOp>>(app, OUTBOX_FILE)
+        .map(|ops| !ops.is_empty())
+        .unwrap_or(false);
+    if has_pending {
+        let outbox = app.state::<SyncState>().outbox.clone();
+        outbox.ensure_worker(app.clone());
+    }
+}
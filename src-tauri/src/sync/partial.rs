@@ -0,0 +1,226 @@
+//! Pulling part of a long story. A peer answers `PullEntries` with the story
+//! and only the entries in the requested range, so a slow link can fetch the
+//! latest chapter first. Ranges pulled from a peer are stitched into a local
+//! partial copy that remembers which entries it has; once every range is
+//! there it is the whole story.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use crate::store;
+use crate::story::StoryExport;
+
+/// Directory in the app data directory holding partial copies
+pub const PARTIAL_DIR: &str = "sync_partial";
+
+/// A run of entries by their index in the story, `to` not included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryRange {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// The part of a story's entries between `from_entry` and `to_entry`,
+/// clamped to the entries it has. Images go with their entries.
+pub fn slice(
+    mut export: StoryExport,
+    from_entry: usize,
+    to_entry: usize,
+) -> (StoryExport, EntryRange) {
+    let total = export.entries.len();
+    let range = EntryRange {
+        from: from_entry.min(total),
+        to: to_entry.clamp(from_entry.min(total), total),
+    };
+    export.entries = export.entries.drain(range.from..range.to).collect();
+    let ids: HashSet<&str> = export.entries.iter().map(|e| e.id.as_str()).collect();
+    export
+        .embedded_images
+        .retain(|image| ids.contains(image.entry_id.as_str()));
+    (export, range)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartialStory {
+    total_entries: usize,
+    /// Ranges present, sorted and not overlapping
+    ranges: Vec<EntryRange>,
+    /// The story with the entries of `ranges`, in order
+    data: StoryExport,
+}
+
+/// What a partial copy holds
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialStoryInfo {
+    pub story_id: String,
+    pub title: String,
+    pub updated_at: i64,
+    pub total_entries: usize,
+    pub ranges: Vec<EntryRange>,
+    /// Every entry has been pulled
+    pub complete: bool,
+}
+
+impl PartialStory {
+    fn info(&self) -> PartialStoryInfo {
+        PartialStoryInfo {
+            story_id: self.data.story.id.clone(),
+            title: self.data.story.title.clone(),
+            updated_at: self.data.story.updated_at,
+            total_entries: self.total_entries,
+            ranges: self.ranges.clone(),
+            complete: self.ranges
+                == [EntryRange {
+                    from: 0,
+                    to: self.total_entries,
+                }]
+                || self.total_entries == 0,
+        }
+    }
+
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.ranges.iter().flat_map(|r| r.from..r.to)
+    }
+
+    /// Add a pulled range. The newest pull's story fields win; entries and
+    /// images pulled before are kept unless the story changed on the peer
+    /// since, in which case they are dropped as stale.
+    fn stitch(&mut self, mut pulled: StoryExport, range: EntryRange, total_entries: usize) {
+        if pulled.story.updated_at != self.data.story.updated_at
+            || total_entries != self.total_entries
+        {
+            self.ranges.clear();
+            self.data.entries.clear();
+            self.data.embedded_images.clear();
+        }
+
+        let indices: Vec<usize> = self.indices().collect();
+        let mut entries: BTreeMap<usize, _> = indices
+            .into_iter()
+            .zip(self.data.entries.drain(..))
+            .collect();
+        entries.extend((range.from..range.to).zip(std::mem::take(&mut pulled.entries)));
+        let mut images = std::mem::take(&mut self.data.embedded_images);
+        let new_ids: HashSet<&str> = pulled
+            .embedded_images
+            .iter()
+            .map(|i| i.id.as_str())
+            .collect();
+        images.retain(|image| !new_ids.contains(image.id.as_str()));
+        images.append(&mut pulled.embedded_images);
+
+        self.ranges.push(range);
+        self.ranges.sort_by_key(|r| r.from);
+        let mut merged: Vec<EntryRange> = Vec::with_capacity(self.ranges.len());
+        for range in self.ranges.drain(..).filter(|r| r.from < r.to) {
+            match merged.last_mut() {
+                Some(last) if range.from <= last.to => last.to = last.to.max(range.to),
+                _ => merged.push(range),
+            }
+        }
+        self.ranges = merged;
+        self.total_entries = total_entries;
+        self.data = StoryExport {
+            entries: entries.into_values().collect(),
+            embedded_images: images,
+            ..pulled
+        };
+    }
+}
+
+fn partial_path(app: &AppHandle, story_id: &str) -> Result<PathBuf, String> {
+    if story_id.is_empty() || story_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid story ID: {}", story_id));
+    }
+    let dir = store::data_file(app, PARTIAL_DIR)?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create partial story directory: {}", e))?;
+    Ok(dir.join(format!("{}.json", story_id)))
+}
+
+fn load(app: &AppHandle, story_id: &str) -> Result<Option<PartialStory>, String> {
+    let path = partial_path(app, story_id)?;
+    let Ok(json) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|e| format!("Invalid partial story: {}", e))
+}
+
+/// Stitch a range pulled from a peer into the local partial copy
+pub fn stitch(
+    app: &AppHandle,
+    pulled: StoryExport,
+    range: EntryRange,
+    total_entries: usize,
+) -> Result<PartialStoryInfo, String> {
+    let story_id = pulled.story.id.clone();
+    let mut partial = match load(app, &story_id)? {
+        Some(partial) => partial,
+        None => PartialStory {
+            total_entries,
+            ranges: Vec::new(),
+            data: StoryExport {
+                entries: Vec::new(),
+                embedded_images: Vec::new(),
+                ..pulled.clone()
+            },
+        },
+    };
+    partial.stitch(pulled, range, total_entries);
+
+    let json = serde_json::to_string(&partial)
+        .map_err(|e| format!("Failed to serialize partial story: {}", e))?;
+    let path = partial_path(app, &story_id)?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write partial story: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to save partial story: {}", e))?;
+    Ok(partial.info())
+}
+
+/// Partial copies on this device
+pub fn list(app: &AppHandle) -> Result<Vec<PartialStoryInfo>, String> {
+    let dir = store::data_file(app, PARTIAL_DIR)?;
+    let Ok(files) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut found = Vec::new();
+    for file in files.flatten() {
+        let path = file.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let Some(story_id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if let Ok(Some(partial)) = load(app, story_id) {
+            found.push(partial.info());
+        }
+    }
+    found.sort_by(|a, b| a.title.cmp(&b.title));
+    Ok(found)
+}
+
+/// The partial copy as Aventura export JSON, holding only the entries
+/// pulled so far
+pub fn story_json(app: &AppHandle, story_id: &str) -> Result<String, String> {
+    load(app, story_id)?
+        .ok_or_else(|| format!("No partial copy of story {}", story_id))?
+        .data
+        .to_json()
+}
+
+pub fn discard(app: &AppHandle, story_id: &str) -> Result<(), String> {
+    let path = partial_path(app, story_id)?;
+    if path.exists() {
+        fs::remove_file(&path).map_err(|e| format!("Failed to remove partial story: {}", e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+//! Story payloads held by the sync and API servers. Stories with embedded
+//! media can run to hundreds of megabytes; above a configurable size they are
+//! written to a temporary file and memory-mapped when served, so the host
+//! app is not carrying every large story in memory for the life of the server.
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Spill settings in the app data directory
+pub const SPILL_CONFIG_FILE: &str = "sync_spill.json";
+
+/// When story payloads leave memory
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SpillConfig {
+    /// Payloads larger than this many megabytes are kept on disk.
+    /// 0 keeps everything in memory.
+    pub threshold_mb: u64,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self { threshold_mb: 32 }
+    }
+}
+
+impl SpillConfig {
+    fn should_spill(&self, len: usize) -> bool {
+        self.threshold_mb > 0 && len as u64 > self.threshold_mb * 1024 * 1024
+    }
+}
+
+/// A payload written to disk, removed when the last copy is dropped
+#[derive(Debug)]
+pub struct SpillFile {
+    path: PathBuf,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn spill(json: &str) -> Result<SpillFile, String> {
+    let dir = std::env::temp_dir().join(format!("aventura-spill-{}", std::process::id()));
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create spill directory: {}", e))?;
+    let path = dir.join(format!("{}.json", uuid::Uuid::new_v4()));
+    fs::write(&path, json).map_err(|e| format!("Failed to spill story: {}", e))?;
+    Ok(SpillFile { path })
+}
+
+/// Story JSON in Aventura export format, in memory or spilled to disk.
+/// Clones share the same data.
+#[derive(Debug, Clone)]
+pub enum StoryPayload {
+    Memory(Arc<str>),
+    Spilled(Arc<SpillFile>),
+}
+
+impl StoryPayload {
+    /// Hold a payload, spilling it if it is over the threshold.
+    /// Payloads stay in memory if the temporary file cannot be written.
+    pub fn new(json: String, config: &SpillConfig) -> Self {
+        if config.should_spill(json.len()) {
+            match spill(&json) {
+                Ok(file) => return StoryPayload::Spilled(Arc::new(file)),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        StoryPayload::Memory(json.into())
+    }
+
+    /// The story JSON
+    pub fn read(&self) -> Result<String, String> {
+        match self {
+            StoryPayload::Memory(json) => Ok(json.to_string()),
+            StoryPayload::Spilled(file) => {
+                let handle = File::open(&file.path)
+                    .map_err(|e| format!("Failed to open spilled story: {}", e))?;
+                // SAFETY: spill files are private to this process and never
+                // written again after `spill` returns
+                let map = unsafe { Mmap::map(&handle) }
+                    .map_err(|e| format!("Failed to map spilled story: {}", e))?;
+                std::str::from_utf8(&map)
+                    .map(String::from)
+                    .map_err(|e| format!("Spilled story is corrupt: {}", e))
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! What a sync with a peer would do, worked out from both story lists
+//! before anything is transferred, so the user can review it first.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use tauri::AppHandle;
+
+use super::types::{StoryTombstone, SyncStoryPreview};
+use crate::profiles::{self, database};
+
+/// Which stories are synced, in the app data directory
+pub const FAVORITES_CONFIG_FILE: &str = "sync_favorites.json";
+
+/// Limit syncing to favorite stories
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FavoritesConfig {
+    /// A sync server started here only offers favorite stories
+    pub offer_only_favorites: bool,
+    /// Sync plans only include stories that are a favorite on either device
+    pub sync_only_favorites: bool,
+    /// Favorites newer on one device are pulled and pushed as soon as this
+    /// device connects to a sync server
+    pub auto_sync_favorites: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncDirection {
+    #[default]
+    Both,
+    Pull,
+    Push,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyncPlanOptions {
+    pub direction: SyncDirection,
+    /// Only plan these stories
+    pub story_ids: Option<Vec<String>>,
+    /// List stories that would be left alone, not just counted
+    pub include_skipped: bool,
+    /// Only plan stories that are a favorite on either device
+    pub favorites_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlannedAction {
+    Pull,
+    Push,
+    /// Both copies changed; pulling or pushing alone would lose entries
+    Merge,
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedStory {
+    /// The other device's ID when it has the story, which pulls ask for
+    pub story_id: String,
+    /// ID of the copy on this device. It only differs from `story_id` for
+    /// stories synced before IDs were kept, which are matched by title.
+    pub local_story_id: Option<String>,
+    pub title: String,
+    pub action: PlannedAction,
+    pub reason: String,
+    pub local_updated_at: Option<i64>,
+    pub remote_updated_at: Option<i64>,
+    pub local_entries: Option<usize>,
+    pub remote_entries: Option<usize>,
+    /// Estimated bytes transferred
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncPlan {
+    pub stories: Vec<PlannedStory>,
+    pub pulls: usize,
+    pub pushes: usize,
+    pub merges: usize,
+    pub skipped: usize,
+    /// Estimated bytes to download and upload
+    pub pull_bytes: u64,
+    pub push_bytes: u64,
+}
+
+/// A story in the local library, as far as planning needs it
+#[derive(Debug, Clone)]
+pub struct LocalStory {
+    pub id: String,
+    pub title: String,
+    pub updated_at: i64,
+    pub entry_count: usize,
+    pub favorite: bool,
+    /// Entry text and image data, most of what an export holds
+    pub bytes: u64,
+}
+
+/// Story ID, title, last change, favorite, entry count and bytes
+type LocalStoryRow = (String, String, i64, bool, i64, i64);
+
+/// The current profile's stories, without reading their entries
+pub async fn local_stories(app: &AppHandle) -> Result<Vec<LocalStory>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let rows: Result<Vec<LocalStoryRow>, String> = sqlx::query_as(
+        "SELECT s.id, s.title, s.updated_at, s.favorite, \
+         (SELECT COUNT(*) FROM story_entries e WHERE e.story_id = s.id), \
+         (SELECT COALESCE(SUM(LENGTH(e.content)), 0) FROM story_entries e WHERE e.story_id = s.id) \
+         + (SELECT COALESCE(SUM(LENGTH(i.image_data)), 0) FROM embedded_images i WHERE i.story_id = s.id) \
+         FROM stories s",
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| format!("Failed to read stories: {}", e));
+    pool.close().await;
+    Ok(rows?
+        .into_iter()
+        .map(
+            |(id, title, updated_at, favorite, entries, bytes)| LocalStory {
+                id,
+                title,
+                updated_at,
+                favorite,
+                entry_count: entries.max(0) as usize,
+                bytes: bytes.max(0) as u64,
+            },
+        )
+        .collect())
+}
+
+/// Remote stories should report their size; older peers are estimated
+/// from the entry count
+fn remote_bytes(story: &SyncStoryPreview) -> u64 {
+    const BYTES_PER_ENTRY: u64 = 2048;
+    story
+        .size
+        .unwrap_or(story.entry_count as u64 * BYTES_PER_ENTRY)
+}
+
+fn decide(
+    local: Option<&LocalStory>,
+    remote: Option<&SyncStoryPreview>,
+    deleted_here: bool,
+) -> (PlannedAction, &'static str) {
+    match (local, remote) {
+        (Some(_), None) => (PlannedAction::Push, "Only on this device"),
+        (None, Some(_)) if deleted_here => (PlannedAction::Skip, "Deleted on this device"),
+        (None, Some(_)) => (PlannedAction::Pull, "Only on the other device"),
+        (Some(local), Some(remote)) => {
+            if local.updated_at == remote.updated_at {
+                (PlannedAction::Skip, "Up to date")
+            } else if remote.updated_at > local.updated_at {
+                if remote.entry_count < local.entry_count {
+                    (
+                        PlannedAction::Merge,
+                        "The other copy is newer but lacks entries this one has",
+                    )
+                } else {
+                    (PlannedAction::Pull, "Newer on the other device")
+                }
+            } else if local.entry_count < remote.entry_count {
+                (
+                    PlannedAction::Merge,
+                    "This copy is newer but lacks entries the other one has",
+                )
+            } else {
+                (PlannedAction::Push, "Newer on this device")
+            }
+        }
+        (None, None) => (PlannedAction::Skip, "Not on either device"),
+    }
+}
+
+/// Plan a sync from both story lists. Remote times must already be on this
+/// device's clock.
+pub fn plan(
+    local: &[LocalStory],
+    remote: &[SyncStoryPreview],
+    deletions: &[StoryTombstone],
+    options: &SyncPlanOptions,
+) -> SyncPlan {
+    let wanted: Option<HashSet<&str>> = options
+        .story_ids
+        .as_ref()
+        .map(|ids| ids.iter().map(String::as_str).collect());
+    let deleted: HashSet<&str> = deletions.iter().map(|t| t.story_id.as_str()).collect();
+    let mut stories: BTreeMap<&str, (Option<&LocalStory>, Option<&SyncStoryPreview>)> =
+        BTreeMap::new();
+    for story in local {
+        stories.entry(&story.id).or_default().0 = Some(story);
+    }
+    for story in remote {
+        stories.entry(&story.id).or_default().1 = Some(story);
+    }
+    // Like the sync itself, fall back to the title for a story that has a
+    // different ID on each device
+    let remote_only: Vec<&str> = stories
+        .iter()
+        .filter(|(_, (local, remote))| local.is_none() && remote.is_some())
+        .map(|(id, _)| *id)
+        .collect();
+    for id in remote_only {
+        let Some(title) = stories[id].1.map(|r| r.title.as_str()) else {
+            continue;
+        };
+        let local_id = stories
+            .iter()
+            .find(|(_, (local, remote))| {
+                remote.is_none() && local.is_some_and(|l| l.title == title)
+            })
+            .map(|(local_id, _)| *local_id);
+        if let Some(local_id) = local_id {
+            let local = stories.remove(local_id).and_then(|(local, _)| local);
+            if let Some(entry) = stories.get_mut(id) {
+                entry.0 = local;
+            }
+        }
+    }
+
+    let mut plan = SyncPlan::default();
+    for (id, (local, remote)) in stories {
+        let local_id = local.map(|l| l.id.as_str());
+        if wanted.as_ref().is_some_and(|wanted| {
+            !wanted.contains(id) && !local_id.is_some_and(|l| wanted.contains(l))
+        }) {
+            continue;
+        }
+        let favorite = local.is_some_and(|l| l.favorite) || remote.is_some_and(|r| r.favorite);
+        if options.favorites_only && !favorite {
+            continue;
+        }
+        let (mut action, mut reason) = decide(local, remote, deleted.contains(id));
+        let blocked = match action {
+            PlannedAction::Pull => options.direction == SyncDirection::Push,
+            PlannedAction::Push => options.direction == SyncDirection::Pull,
+            PlannedAction::Merge => options.direction != SyncDirection::Both,
+            PlannedAction::Skip => false,
+        };
+        if blocked {
+            action = PlannedAction::Skip;
+            reason = "Not in the chosen sync direction";
+        }
+
+        let download = remote.map_or(0, remote_bytes);
+        let upload = local.map_or(0, |l| l.bytes);
+        let bytes = match action {
+            PlannedAction::Pull => {
+                plan.pulls += 1;
+                plan.pull_bytes += download;
+                download
+            }
+            PlannedAction::Push => {
+                plan.pushes += 1;
+                plan.push_bytes += upload;
+                upload
+            }
+            PlannedAction::Merge => {
+                plan.merges += 1;
+                plan.pull_bytes += download;
+                plan.push_bytes += upload;
+                download + upload
+            }
+            PlannedAction::Skip => {
+                plan.skipped += 1;
+                if !options.include_skipped {
+                    continue;
+                }
+                0
+            }
+        };
+        plan.stories.push(PlannedStory {
+            story_id: id.to_string(),
+            local_story_id: local_id.map(str::to_string),
+            title: local
+                .map(|l| l.title.clone())
+                .or_else(|| remote.map(|r| r.title.clone()))
+                .unwrap_or_default(),
+            action,
+            reason: reason.to_string(),
+            local_updated_at: local.map(|l| l.updated_at),
+            remote_updated_at: remote.map(|r| r.updated_at),
+            local_entries: local.map(|l| l.entry_count),
+            remote_entries: remote.map(|r| r.entry_count),
+            bytes,
+        });
+    }
+    plan
+}
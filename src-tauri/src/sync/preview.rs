@@ -0,0 +1,108 @@
+//! Reads the few fields a story preview needs without building the whole
+//! export in memory. Entries are counted and skipped, and every other
+//! top-level field (embedded images included) is stepped over unparsed.
+
+use serde::de::{self, DeserializeOwned, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+
+/// The parts of an Aventura export shown before a story is pulled
+#[derive(Debug, Deserialize)]
+pub struct ExportPreview {
+    #[serde(default)]
+    pub story: Option<StoryFields>,
+    #[serde(default)]
+    pub entries: EntryCount,
+}
+
+/// Story metadata. Fields of the wrong type read as missing, as they did
+/// when previews were read from a `serde_json::Value`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryFields {
+    #[serde(default, deserialize_with = "lenient")]
+    pub id: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub genre: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub updated_at: Option<i64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub favorite: Option<bool>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub pinned: Option<bool>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub age_rating: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub content_warnings: Option<Vec<String>>,
+}
+
+fn lenient<'de, D: Deserializer<'de>, T: DeserializeOwned>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(T::deserialize(value).ok())
+}
+
+/// Number of entries, counted without keeping them. Anything but an array counts as none.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EntryCount(pub usize);
+
+impl<'de> Deserialize<'de> for EntryCount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CountVisitor;
+
+        impl<'de> Visitor<'de> for CountVisitor {
+            type Value = EntryCount;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of entries")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<EntryCount, A::Error> {
+                let mut count = 0;
+                while seq.next_element::<IgnoredAny>()?.is_some() {
+                    count += 1;
+                }
+                Ok(EntryCount(count))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<EntryCount, A::Error> {
+                while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+                Ok(EntryCount(0))
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<EntryCount, E> {
+                Ok(EntryCount(0))
+            }
+
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<EntryCount, E> {
+                Ok(EntryCount(0))
+            }
+
+            fn visit_i64<E: de::Error>(self, _: i64) -> Result<EntryCount, E> {
+                Ok(EntryCount(0))
+            }
+
+            fn visit_u64<E: de::Error>(self, _: u64) -> Result<EntryCount, E> {
+                Ok(EntryCount(0))
+            }
+
+            fn visit_f64<E: de::Error>(self, _: f64) -> Result<EntryCount, E> {
+                Ok(EntryCount(0))
+            }
+
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<EntryCount, E> {
+                Ok(EntryCount(0))
+            }
+        }
+
+        deserializer.deserialize_any(CountVisitor)
+    }
+}
+
+/// Read the preview fields of an export
+pub fn read(json: &str) -> Result<ExportPreview, String> {
+    serde_json::from_str(json).map_err(|e| format!("Invalid JSON: {}", e))
+}
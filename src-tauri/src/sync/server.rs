@@ -1,103 +1,469 @@
 use axum::{
+    body::Bytes,
     extract::{DefaultBodyLimit, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
     routing::post,
-    Json, Router,
+    Router,
 };
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use tauri::AppHandle;
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 
+use crate::game::session::SharedGame;
+use crate::game::spectator::SpectatorHub;
+use crate::lorebook;
+use crate::story::StoryExport;
+use crate::webhooks::{self, WebhookEvent};
+
+use super::access_log::{AccessLog, AccessNote};
+use super::codec::WireFormat;
+use super::commands::parse_story_preview;
+use super::device::DeviceIdentity;
+use super::keys::{
+    self, ExchangeSecret, KeyExchangeHandshake, PendingKeyExchange, EXCHANGE_TTL_MS,
+};
+use super::metrics::SyncMetrics;
+use super::opds::OpdsCatalog;
+use super::partial;
+use super::payload::{SpillConfig, StoryPayload};
+use super::settings::SettingsBundle;
+use super::share::ReadShare;
+use super::tombstones;
 use super::types::{SyncAction, SyncRequest, SyncResponse, SyncStoryPreview};
 
 /// Shared state for the sync server
 #[derive(Clone)]
 pub struct ServerState {
+    /// Handle used to fire webhooks for server events
+    pub app: AppHandle,
     /// Authentication token
     pub token: String,
+    /// Port the server is listening on
+    pub port: u16,
+    /// How this device is shown to clients before they pair
+    pub device: DeviceIdentity,
+    /// Reject requests from outside the local network
+    pub lan_only: bool,
     /// Stories available on this server (JSON strings in Aventura format)
     pub stories: Arc<Mutex<Vec<StoriesData>>>,
     /// Stories received from clients (pushed stories)
-    pub received_stories: Arc<Mutex<Vec<String>>>,
+    pub received_stories: Arc<Mutex<Vec<StoryPayload>>>,
+    /// Size above which story payloads are kept on disk
+    pub spill: SpillConfig,
+    /// Settings the host has chosen to share, already restricted and stripped of secrets
+    pub shared_settings: Arc<Mutex<Option<SettingsBundle>>>,
+    /// Settings received from clients
+    pub received_settings: Arc<Mutex<Vec<SettingsBundle>>>,
+    /// API key exchange waiting for confirmation or delivery
+    pub key_exchange: Arc<Mutex<Option<PendingKeyExchange>>>,
+    /// Multiplayer session hosted on this server, if any
+    pub game: SharedGame,
+    /// Read-only viewers following the host's story
+    pub spectators: SpectatorHub,
+    /// OPDS catalog for e-reader apps, when published
+    pub opds: Arc<Mutex<Option<OpdsCatalog>>>,
+    /// Stories shared as read-only web pages, by link token
+    pub shares: Arc<Mutex<HashMap<String, ReadShare>>>,
+    /// Request and transfer counters
+    pub metrics: Arc<SyncMetrics>,
+    /// Serve `/metrics` for Prometheus scrapers
+    pub expose_metrics: bool,
+    /// Requests answered over HTTP
+    pub access_log: Arc<AccessLog>,
 }
 
 /// Data about a story available on the server
 #[derive(Clone)]
 pub struct StoriesData {
     pub preview: SyncStoryPreview,
-    pub full_data: String,
+    pub full_data: StoryPayload,
 }
 
 impl ServerState {
-    pub fn new(token: String) -> Self {
+    pub fn new(app: AppHandle, token: String) -> Self {
+        let access_log = Arc::new(AccessLog::new(&app));
         Self {
+            app,
             token,
+            port: 0,
+            device: DeviceIdentity::default(),
+            lan_only: false,
             stories: Arc::new(Mutex::new(Vec::new())),
             received_stories: Arc::new(Mutex::new(Vec::new())),
+            spill: SpillConfig::default(),
+            shared_settings: Arc::new(Mutex::new(None)),
+            received_settings: Arc::new(Mutex::new(Vec::new())),
+            key_exchange: Arc::new(Mutex::new(None)),
+            game: Arc::new(Mutex::new(None)),
+            spectators: SpectatorHub::default(),
+            opds: Arc::new(Mutex::new(None)),
+            shares: Arc::new(Mutex::new(HashMap::new())),
+            metrics: Arc::new(SyncMetrics::default()),
+            expose_metrics: false,
+            access_log,
         }
     }
 }
 
-/// Bind a listener for the sync HTTP server on a random local port
-pub async fn bind_listener() -> Result<TcpListener, String> {
-    TcpListener::bind("0.0.0.0:0")
+/// Bind a listener for the sync HTTP server on a random port of the given address
+pub async fn bind_listener(ip: IpAddr) -> Result<TcpListener, String> {
+    TcpListener::bind(SocketAddr::new(ip, 0))
         .await
         .map_err(|e| format!("Failed to bind server: {}", e))
 }
 
 /// Build the sync router with shared state
 pub fn build_router(state: ServerState) -> Router {
-    Router::new()
+    let mut router = Router::new()
         .route("/sync", post(handle_sync))
+        .merge(super::health::routes());
+    if state.expose_metrics {
+        router = router.merge(super::metrics::routes());
+    }
+    router
+        .merge(crate::game::server::routes())
+        .merge(crate::game::spectator::routes())
+        .merge(super::opds::routes())
+        .merge(super::share::routes())
         // Increase body limit to 100MB for large stories with embedded images
         .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            super::network::lan_guard,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            super::access_log::record,
+        ))
         .with_state(state)
 }
 
-/// Start the sync HTTP server task
-pub fn spawn_server(listener: TcpListener, app: Router) -> tokio::task::JoinHandle<()> {
+/// Start the sync HTTP server task. It only ends on its own if the listener
+/// fails; see `supervisor` for what happens then.
+pub fn spawn_server(
+    listener: TcpListener,
+    app: Router,
+) -> tokio::task::JoinHandle<Result<(), String>> {
     tokio::spawn(async move {
-        if let Err(e) = axum::serve(listener, app).await {
-            eprintln!("Sync server error: {}", e);
-        }
+        // Peer addresses are needed by the LAN-only guard
+        let service = app.into_make_service_with_connect_info::<SocketAddr>();
+        axum::serve(listener, service)
+            .await
+            .map_err(|e| format!("Sync server error: {}", e))
     })
 }
 
-/// Handle sync requests
+/// Handle sync requests over HTTP. Requests are decoded by their
+/// `Content-Type` and answered in the best format the `Accept` header allows.
 async fn handle_sync(
     State(state): State<ServerState>,
-    Json(request): Json<SyncRequest>,
-) -> Json<SyncResponse> {
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let header_value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let request_format =
+        header_value(header::CONTENT_TYPE).map_or(Some(WireFormat::Json), WireFormat::from_mime);
+    let response_format = WireFormat::negotiate(header_value(header::ACCEPT));
+
+    let request = match request_format {
+        Some(format) => format
+            .decode::<SyncRequest>(&body)
+            .map_err(|e| format!("Invalid request: {}", e)),
+        None => Err("Unsupported request encoding".to_string()),
+    };
+    let mut note = AccessNote::default();
+    let response = match request {
+        Ok(request) => {
+            note.action = Some(request.action.name());
+            note.story_id = story_id_of(&request.action);
+            dispatch(&state, request).await
+        }
+        Err(message) => {
+            state.metrics.record("invalid", true);
+            SyncResponse::Error { message }
+        }
+    };
+    if let SyncResponse::Error { message } = &response {
+        note.error = Some(message.clone());
+    }
+
+    note.on(match response_format.encode(&response) {
+        Ok(bytes) => {
+            state.metrics.record_transfer(body.len(), bytes.len());
+            ([(header::CONTENT_TYPE, response_format.mime())], bytes).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to encode response: {}", e),
+        )
+            .into_response(),
+    })
+}
+
+/// Story a sync action is about, for the access log
+fn story_id_of(action: &SyncAction) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Head {
+        story: HeadStory,
+    }
+    #[derive(Deserialize)]
+    struct HeadStory {
+        id: String,
+    }
+    match action {
+        SyncAction::PullStory { story_id } | SyncAction::PullEntries { story_id, .. } => {
+            Some(story_id.clone())
+        }
+        SyncAction::PushStory { story_data } => serde_json::from_str::<Head>(story_data)
+            .ok()
+            .map(|head| head.story.id),
+        _ => None,
+    }
+}
+
+/// Answer a sync request, whichever transport it arrived on
+pub async fn dispatch(state: &ServerState, request: SyncRequest) -> SyncResponse {
+    let action = request.action.name();
+    let response = answer(state, request).await;
+    state
+        .metrics
+        .record(action, matches!(response, SyncResponse::Error { .. }));
+    response
+}
+
+async fn answer(state: &ServerState, request: SyncRequest) -> SyncResponse {
     // Validate token
     if request.token != state.token {
-        return Json(SyncResponse::Error {
+        return SyncResponse::Error {
             message: "Invalid authentication token".to_string(),
-        });
+        };
     }
 
     match request.action {
         SyncAction::ListStories => {
             let stories = state.stories.lock().await;
-            let previews: Vec<SyncStoryPreview> = stories.iter().map(|s| s.preview.clone()).collect();
-            Json(SyncResponse::StoriesList { stories: previews })
+            let previews: Vec<SyncStoryPreview> =
+                stories.iter().map(|s| s.preview.clone()).collect();
+            SyncResponse::StoriesList { stories: previews }
         }
         SyncAction::PullStory { story_id } => {
-            let stories = state.stories.lock().await;
-            if let Some(story) = stories.iter().find(|s| s.preview.id == story_id) {
-                Json(SyncResponse::StoryData {
-                    data: story.full_data.clone(),
-                })
-            } else {
-                Json(SyncResponse::Error {
+            let payload = state
+                .stories
+                .lock()
+                .await
+                .iter()
+                .find(|s| s.preview.id == story_id)
+                .map(|s| s.full_data.clone());
+            match payload.map(|p| p.read()) {
+                Some(Ok(data)) => SyncResponse::StoryData { data },
+                Some(Err(message)) => SyncResponse::Error { message },
+                None => SyncResponse::Error {
                     message: format!("Story not found: {}", story_id),
-                })
+                },
             }
         }
+        SyncAction::PullEntries {
+            story_id,
+            from_entry,
+            to_entry,
+        } => match pull_entries(state, &story_id, from_entry, to_entry).await {
+            Ok(response) => response,
+            Err(message) => SyncResponse::Error { message },
+        },
         SyncAction::PushStory { story_data } => {
+            if let Ok(preview) = parse_story_preview(&story_data) {
+                webhooks::fire(
+                    &state.app,
+                    WebhookEvent::StoryReceived,
+                    format!("Received story \"{}\"", preview.title),
+                    serde_json::to_value(&preview).unwrap_or_default(),
+                );
+            }
             let mut received = state.received_stories.lock().await;
-            received.push(story_data);
-            Json(SyncResponse::Success {
+            received.push(StoryPayload::new(story_data, &state.spill));
+            SyncResponse::Success {
                 message: "Story received successfully".to_string(),
-            })
+            }
+        }
+        SyncAction::PullSettings { scopes } => match state.shared_settings.lock().await.clone() {
+            Some(settings) => SyncResponse::SettingsData {
+                settings: settings.restricted_to(&scopes),
+            },
+            None => SyncResponse::Error {
+                message: "No settings are shared by this device".to_string(),
+            },
+        },
+        SyncAction::PushSettings { settings } => {
+            // Clients strip secrets before sending, but never trust that
+            let scopes = settings.scopes();
+            let mut received = state.received_settings.lock().await;
+            received.push(settings.restricted_to(&scopes));
+            SyncResponse::Success {
+                message: "Settings received successfully".to_string(),
+            }
+        }
+        SyncAction::BeginKeyExchange {
+            client_commitment,
+            device,
+        } => {
+            let secret = ExchangeSecret::generate();
+            let server_public = secret.public_key();
+            let exchange_id = uuid::Uuid::new_v4().to_string();
+            // A new exchange replaces any earlier one that was never completed
+            *state.key_exchange.lock().await = Some(PendingKeyExchange {
+                handshake: KeyExchangeHandshake {
+                    exchange_id: exchange_id.clone(),
+                    client_public: String::new(),
+                    server_public: server_public.clone(),
+                    code: String::new(),
+                    peer: device,
+                },
+                confirmed: false,
+                expires_at: keys::now_ms() + EXCHANGE_TTL_MS,
+                client_commitment,
+                secret,
+            });
+            SyncResponse::KeyExchangeStarted {
+                exchange_id,
+                server_public,
+                device: Some(state.device.clone()),
+            }
         }
+        SyncAction::RevealKeyExchange {
+            exchange_id,
+            client_public,
+        } => reveal_key(state, &exchange_id, client_public).await,
+        SyncAction::DeliverKeys {
+            exchange_id,
+            payload,
+        } => receive_keys(state, &exchange_id, &payload).await,
+        SyncAction::SyncDeletions { tombstones } => {
+            if let Err(message) = tombstones::receive(&state.app, &tombstones, None, None).await {
+                return SyncResponse::Error { message };
+            }
+            match tombstones::list(&state.app) {
+                Ok(tombstones) => SyncResponse::Deletions { tombstones },
+                Err(message) => SyncResponse::Error { message },
+            }
+        }
+        SyncAction::ListLorebooks => match lorebook::list_shared(&state.app) {
+            Ok(lorebooks) => SyncResponse::LorebooksList { lorebooks },
+            Err(message) => SyncResponse::Error { message },
+        },
+        SyncAction::PullLorebook { lorebook_id } => {
+            match lorebook::load_shared(&state.app, &lorebook_id) {
+                Ok(lorebook) => SyncResponse::LorebookData { lorebook },
+                Err(message) => SyncResponse::Error { message },
+            }
+        }
+    }
+}
+
+/// A served story cut down to a range of its entries
+async fn pull_entries(
+    state: &ServerState,
+    story_id: &str,
+    from_entry: usize,
+    to_entry: usize,
+) -> Result<SyncResponse, String> {
+    let payload = state
+        .stories
+        .lock()
+        .await
+        .iter()
+        .find(|s| s.preview.id == story_id)
+        .map(|s| s.full_data.clone())
+        .ok_or_else(|| format!("Story not found: {}", story_id))?;
+    let export = StoryExport::from_json(&payload.read()?)?;
+    let total_entries = export.entries.len();
+    let (export, range) = partial::slice(export, from_entry, to_entry);
+    Ok(SyncResponse::StoryEntries {
+        data: export.to_json()?,
+        from_entry: range.from,
+        to_entry: range.to,
+        total_entries,
+    })
+}
+
+/// Take the client's public key if it matches its commitment, and derive
+/// the code the host shows
+async fn reveal_key(state: &ServerState, exchange_id: &str, client_public: String) -> SyncResponse {
+    let mut pending = state.key_exchange.lock().await;
+    let Some(exchange) = pending
+        .as_mut()
+        .filter(|p| p.handshake.exchange_id == exchange_id && p.handshake.client_public.is_empty())
+    else {
+        return SyncResponse::Error {
+            message: "No matching key exchange".to_string(),
+        };
+    };
+    if keys::commitment(&client_public).ok().as_ref() != Some(&exchange.client_commitment) {
+        *pending = None;
+        return SyncResponse::Error {
+            message: "Public key does not match the commitment".to_string(),
+        };
+    }
+    exchange.handshake.client_public = client_public;
+    let code = keys::confirmation_code(
+        &exchange.secret,
+        &exchange.handshake.client_public,
+        &state.token,
+        &exchange.handshake,
+    );
+    match code {
+        Ok(code) => {
+            exchange.handshake.code = code;
+            SyncResponse::Success {
+                message: "Compare the codes on both devices".to_string(),
+            }
+        }
+        Err(message) => {
+            *pending = None;
+            SyncResponse::Error { message }
+        }
+    }
+}
+
+/// Decrypt delivered keys into the keychain if the host confirmed the exchange
+async fn receive_keys(
+    state: &ServerState,
+    exchange_id: &str,
+    payload: &keys::EncryptedKeys,
+) -> SyncResponse {
+    let mut pending = state.key_exchange.lock().await;
+    let error = |message: &str| SyncResponse::Error {
+        message: message.to_string(),
+    };
+    let Some(exchange) = pending
+        .as_ref()
+        .filter(|p| p.handshake.exchange_id == exchange_id)
+    else {
+        return error("No matching key exchange");
+    };
+    if exchange.expires_at < keys::now_ms() {
+        *pending = None;
+        return error("Key exchange expired");
+    }
+    if !exchange.confirmed {
+        return error("Waiting for confirmation on the other device");
+    }
+
+    // One attempt per exchange, successful or not
+    let Some(exchange) = pending.take() else {
+        return error("No matching key exchange");
+    };
+    let result = keys::decrypt_keys(&exchange.secret, &state.token, &exchange.handshake, payload)
+        .and_then(|entries| keys::store_in_keychain(&entries));
+    match result {
+        Ok(count) => SyncResponse::Success {
+            message: format!("Stored {} API keys", count),
+        },
+        Err(message) => SyncResponse::Error { message },
     }
 }
@@ -0,0 +1,295 @@
+//! Sessions with a remote sync server. Opening one checks the address and
+//! token once; later commands name the session instead of passing them
+//! again, each session keeps its own request counts, and closing it cancels
+//! whatever it still has in flight. When the network drops mid-sync the
+//! session keeps trying to reach the peer for a while and tells the app so
+//! it can show that it is reconnecting. Only a request the peer never got,
+//! or a read, is sent again: a push whose answer was lost may have been
+//! applied already.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::{watch, Mutex};
+use uuid::Uuid;
+
+use super::clock::ClockSkew;
+use super::http::{self, HttpTuning};
+use super::keys::now_ms;
+use super::transport::{HttpTransport, SyncTransport, TransportError};
+use super::types::{SyncAction, SyncRequest, SyncResponse};
+
+/// Emitted with a `SessionConnection` when a session loses its peer, gets it
+/// back or gives up
+pub const SESSION_CONNECTION_EVENT: &str = "sync://session-connection";
+
+/// Times a request is repeated after the peer stopped answering. The
+/// session does its own retrying, so the transport's is turned off.
+const MAX_RECONNECTS: u32 = 6;
+
+/// Wait before the first reconnect, doubled for each further one
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(16);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum ConnectionState {
+    /// The peer stopped answering; the request is tried again after the delay
+    #[serde(rename_all = "camelCase")]
+    Reconnecting {
+        attempt: u32,
+        max_attempts: u32,
+        retry_in_ms: u64,
+        error: String,
+    },
+    /// The peer answered again
+    #[serde(rename_all = "camelCase")]
+    Reconnected { attempts: u32 },
+    /// The peer did not come back; the request failed
+    Lost { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionConnection {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub state: ConnectionState,
+}
+
+/// Requests made in one session
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionStats {
+    pub requests: u64,
+    pub errors: u64,
+    pub last_used_at: Option<i64>,
+    pub last_error: Option<String>,
+    /// Whether a request is waiting for the peer to come back
+    pub reconnecting: bool,
+    /// Requests repeated after the peer stopped answering
+    pub reconnects: u64,
+}
+
+/// An open session as shown to the app; the token stays in the backend
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerSessionInfo {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    pub opened_at: i64,
+    /// How far the peer's clock is from ours, when it could be measured
+    pub clock_skew: Option<ClockSkew>,
+    #[serde(flatten)]
+    pub stats: SessionStats,
+}
+
+pub struct PeerSession {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    token: String,
+    opened_at: i64,
+    pub clock: Option<ClockSkew>,
+    stats: std::sync::Mutex<SessionStats>,
+    closed: watch::Sender<bool>,
+}
+
+impl PeerSession {
+    pub fn new(ip: &str, port: u16, token: &str, clock: Option<ClockSkew>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            ip: ip.to_string(),
+            port,
+            token: token.to_string(),
+            opened_at: now_ms(),
+            clock,
+            stats: std::sync::Mutex::default(),
+            closed: watch::Sender::new(false),
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn info(&self) -> PeerSessionInfo {
+        PeerSessionInfo {
+            id: self.id.clone(),
+            ip: self.ip.clone(),
+            port: self.port,
+            opened_at: self.opened_at,
+            clock_skew: self.clock,
+            stats: self.stats.lock().map(|s| s.clone()).unwrap_or_default(),
+        }
+    }
+
+    fn update_stats(&self, change: impl FnOnce(&mut SessionStats)) {
+        if let Ok(mut stats) = self.stats.lock() {
+            change(&mut stats);
+        }
+    }
+
+    fn notify(&self, app: &AppHandle, state: ConnectionState) {
+        let connection = SessionConnection {
+            session_id: self.id.clone(),
+            state,
+        };
+        let _ = app.emit(SESSION_CONNECTION_EVENT, &connection);
+    }
+
+    /// Check the peer answers with the session's token. Does not wait for
+    /// an unreachable peer to come back.
+    pub async fn open(&self, app: &AppHandle) -> Result<SyncResponse, TransportError> {
+        self.request(app, SyncAction::ListStories, false).await
+    }
+
+    /// Send one request to the peer, repeating it while the peer is
+    /// unreachable for a short while. A request that changes something on
+    /// the peer is only repeated when it never got there; if the connection
+    /// dropped after it was sent, the error is returned instead.
+    pub async fn send(
+        &self,
+        app: &AppHandle,
+        action: SyncAction,
+    ) -> Result<SyncResponse, TransportError> {
+        self.request(app, action, true).await
+    }
+
+    /// Gives up as soon as the session is closed
+    async fn request(
+        &self,
+        app: &AppHandle,
+        action: SyncAction,
+        reconnect: bool,
+    ) -> Result<SyncResponse, TransportError> {
+        let tuning = http::load(app);
+        let request = SyncRequest {
+            token: self.token.clone(),
+            action,
+        };
+        let transport = HttpTransport::new(
+            &self.ip,
+            self.port,
+            &HttpTuning {
+                retries: 0,
+                ..tuning.clone()
+            },
+        );
+        let repeatable = request.action.is_read_only();
+        let mut closed = self.closed.subscribe();
+        let mut reconnects = 0;
+        let result = loop {
+            let result = tokio::select! {
+                result = transport.send(&request, tuning.read_timeout()) => result,
+                _ = closed.wait_for(|closed| *closed) => Err(TransportError::Cancelled),
+            };
+            let error = match result {
+                Err(TransportError::Unreachable(error)) if reconnect => error,
+                Err(TransportError::Interrupted(error)) if reconnect && repeatable => error,
+                result => break result,
+            };
+            if reconnects == MAX_RECONNECTS {
+                self.notify(
+                    app,
+                    ConnectionState::Lost {
+                        error: error.clone(),
+                    },
+                );
+                break Err(TransportError::Unreachable(error));
+            }
+
+            let delay = (RECONNECT_DELAY * 2u32.pow(reconnects)).min(MAX_RECONNECT_DELAY);
+            reconnects += 1;
+            self.update_stats(|s| {
+                s.reconnecting = true;
+                s.reconnects += 1;
+            });
+            self.notify(
+                app,
+                ConnectionState::Reconnecting {
+                    attempt: reconnects,
+                    max_attempts: MAX_RECONNECTS,
+                    retry_in_ms: delay.as_millis() as u64,
+                    error,
+                },
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = closed.wait_for(|closed| *closed) => break Err(TransportError::Cancelled),
+            }
+        };
+        if reconnects > 0 && matches!(result, Ok(_) | Err(TransportError::Invalid(_))) {
+            self.notify(
+                app,
+                ConnectionState::Reconnected {
+                    attempts: reconnects,
+                },
+            );
+        }
+
+        self.update_stats(|stats| {
+            stats.requests += 1;
+            stats.last_used_at = Some(now_ms());
+            stats.reconnecting = false;
+            let error = match &result {
+                Ok(SyncResponse::Error { message }) => Some(message.clone()),
+                Ok(_) => None,
+                Err(e) => Some(e.to_string()),
+            };
+            if error.is_some() {
+                stats.errors += 1;
+                stats.last_error = error;
+            }
+        });
+        result
+    }
+
+    /// Cancel requests still waiting on the peer
+    fn close(&self) {
+        self.closed.send_replace(true);
+    }
+}
+
+/// Open sessions, keyed by ID
+#[derive(Default)]
+pub struct Sessions(Mutex<HashMap<String, Arc<PeerSession>>>);
+
+impl Sessions {
+    pub async fn insert(&self, session: PeerSession) -> String {
+        let id = session.id.clone();
+        self.0.lock().await.insert(id.clone(), Arc::new(session));
+        id
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Arc<PeerSession>, String> {
+        self.0
+            .lock()
+            .await
+            .get(id)
+            .cloned()
+            .ok_or_else(|| format!("Sync session not found: {}", id))
+    }
+
+    pub async fn close(&self, id: &str) -> Result<(), String> {
+        let session = self
+            .0
+            .lock()
+            .await
+            .remove(id)
+            .ok_or_else(|| format!("Sync session not found: {}", id))?;
+        session.close();
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Vec<PeerSessionInfo> {
+        let mut sessions: Vec<PeerSessionInfo> =
+            self.0.lock().await.values().map(|s| s.info()).collect();
+        sessions.sort_by_key(|s| s.opened_at);
+        sessions
+    }
+}
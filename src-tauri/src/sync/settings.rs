@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ai::profile::AiProfile;
+use crate::style::StyleLibrary;
+
+/// Categories of settings that can be transferred between devices
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SettingsScope {
+    Templates,
+    Lorebooks,
+    AiProfiles,
+    Preferences,
+    StyleReferences,
+}
+
+impl SettingsScope {
+    /// Capability a peer must list to take this scope. Peers from before
+    /// the scope existed reject requests naming it.
+    pub fn capability(self) -> Option<&'static str> {
+        match self {
+            Self::StyleReferences => Some("styleReferences"),
+            _ => None,
+        }
+    }
+}
+
+/// Settings transferred alongside stories.
+/// Only the scopes the user selected are present, and secrets are stripped.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsBundle {
+    #[serde(default)]
+    pub templates: Option<Value>,
+    #[serde(default)]
+    pub lorebooks: Option<Value>,
+    #[serde(default)]
+    pub ai_profiles: Option<Vec<AiProfile>>,
+    #[serde(default)]
+    pub preferences: Option<Value>,
+    #[serde(default)]
+    pub style_references: Option<StyleLibrary>,
+}
+
+/// Field names treated as credentials and never transferred
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase().replace(['_', '-'], "");
+    ["apikey", "secret", "token", "password", "authorization"]
+        .iter()
+        .any(|s| key.contains(s))
+}
+
+/// Remove credential fields anywhere in a JSON value
+fn strip_secrets(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|key, _| !is_secret_key(key));
+            map.values_mut().for_each(strip_secrets);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_secrets),
+        _ => {}
+    }
+}
+
+impl SettingsBundle {
+    /// Keep only the selected scopes and remove every API key and secret
+    pub fn restricted_to(mut self, scopes: &[SettingsScope]) -> Self {
+        let has = |scope| scopes.contains(&scope);
+        if !has(SettingsScope::Templates) {
+            self.templates = None;
+        }
+        if !has(SettingsScope::Lorebooks) {
+            self.lorebooks = None;
+        }
+        if !has(SettingsScope::AiProfiles) {
+            self.ai_profiles = None;
+        }
+        if !has(SettingsScope::Preferences) {
+            self.preferences = None;
+        }
+        if !has(SettingsScope::StyleReferences) {
+            self.style_references = None;
+        }
+
+        for value in [
+            &mut self.templates,
+            &mut self.lorebooks,
+            &mut self.preferences,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            strip_secrets(value);
+        }
+        if let Some(ref mut profiles) = self.ai_profiles {
+            *profiles = profiles.iter().map(AiProfile::without_api_key).collect();
+        }
+        self
+    }
+
+    /// Scopes that have data in this bundle
+    pub fn scopes(&self) -> Vec<SettingsScope> {
+        let mut scopes = Vec::new();
+        if self.templates.is_some() {
+            scopes.push(SettingsScope::Templates);
+        }
+        if self.lorebooks.is_some() {
+            scopes.push(SettingsScope::Lorebooks);
+        }
+        if self.ai_profiles.is_some() {
+            scopes.push(SettingsScope::AiProfiles);
+        }
+        if self.preferences.is_some() {
+            scopes.push(SettingsScope::Preferences);
+        }
+        if self.style_references.is_some() {
+            scopes.push(SettingsScope::StyleReferences);
+        }
+        scopes
+    }
+}
@@ -0,0 +1,301 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::access_log::AccessNote;
+use super::keys::now_ms;
+use super::server::ServerState;
+use crate::export::cover;
+use crate::export::reader;
+use crate::export::site::{escape_html, paginate, render_content, SiteTheme};
+use crate::story::types::{EmbeddedImage, StoryEntry};
+use crate::story::{rows, StoryExport};
+
+/// A story shared as a read-only web page on the sync server
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadShare {
+    /// Secret path segment of the page
+    pub token: String,
+    pub story_id: String,
+    pub title: String,
+    pub theme: SiteTheme,
+    pub created_at: i64,
+    /// The link stops working after this time
+    pub expires_at: Option<i64>,
+    /// Times the page was opened
+    pub views: u64,
+}
+
+impl ReadShare {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|at| now >= at)
+    }
+}
+
+/// Routes for shared stories, merged into the sync router
+pub fn routes() -> Router<ServerState> {
+    Router::new()
+        .route("/read/{token}", get(handle_read))
+        .route("/reader/{version}/{file}", get(handle_asset))
+}
+
+/// Tags loading the embedded web reader
+fn reader_head() -> String {
+    reader::head(&format!("/reader/{}/", reader::version()))
+}
+
+fn message(status: StatusCode, title: &str, text: &str) -> Response {
+    let body = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{title}</title>\n{}</head>\n<body>\n<main>\n<h1>{title}</h1>\n<p class=\"meta\">{text}</p>\n</main>\n</body>\n</html>\n",
+        reader_head(),
+        title = escape_html(title),
+        text = escape_html(text),
+    );
+    (
+        status,
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn data_uri(bytes: &[u8]) -> String {
+    let mime = if bytes.starts_with(&[0xFF, 0xD8]) {
+        "image/jpeg"
+    } else {
+        "image/png"
+    };
+    format!("data:{};base64,{}", mime, STANDARD.encode(bytes))
+}
+
+fn render_entry(entry: &StoryEntry, images: &HashMap<&str, Vec<&EmbeddedImage>>) -> String {
+    let class = if entry.entry_type == "user_action" {
+        "entry action"
+    } else {
+        "entry"
+    };
+    let mut html = format!(
+        "<section class=\"{}\">\n{}",
+        class,
+        render_content(&entry.content)
+    );
+    for image in images.get(entry.id.as_str()).into_iter().flatten() {
+        html.push_str(&format!(
+            "<figure><img src=\"data:image/png;base64,{}\" alt=\"{}\" loading=\"lazy\"><figcaption>{}</figcaption></figure>\n",
+            escape_html(&image.image_data),
+            escape_html(&image.source_text),
+            escape_html(&image.source_text)
+        ));
+    }
+    html.push_str("</section>\n");
+    html
+}
+
+/// The main branch of a story as one page, with a table of contents when it
+/// has chapters. Images are inlined so the page needs nothing else.
+pub fn render_page(export: &StoryExport, theme: SiteTheme, cover: Option<&[u8]>) -> String {
+    let mut main: Vec<&StoryEntry> = export
+        .entries
+        .iter()
+        .filter(|e| e.branch_id.is_none() && e.entry_type != "system")
+        .collect();
+    main.sort_by_key(|e| e.position);
+    let mut images: HashMap<&str, Vec<&EmbeddedImage>> = HashMap::new();
+    for image in export
+        .embedded_images
+        .iter()
+        .filter(|i| !i.image_data.is_empty())
+    {
+        images
+            .entry(image.entry_id.as_str())
+            .or_default()
+            .push(image);
+    }
+
+    let title = &export.story.title;
+    let mut body = String::new();
+    if let Some(cover) = cover {
+        body.push_str(&format!(
+            "<img class=\"cover\" src=\"{}\" alt=\"{}\">\n",
+            data_uri(cover),
+            escape_html(title)
+        ));
+    }
+    body.push_str(&format!("<h1>{}</h1>\n", escape_html(title)));
+    if let Some(genre) = &export.story.genre {
+        body.push_str(&format!("<p class=\"meta\">{}</p>\n", escape_html(genre)));
+    }
+    if let Some(description) = &export.story.description {
+        body.push_str(&render_content(description));
+    }
+
+    let pages = paginate(export, main, "part-");
+    let chaptered = !export.chapters.is_empty() && pages.len() > 1;
+    if chaptered {
+        body.push_str("<nav><h2>Contents</h2>\n<ol>\n");
+        for (index, page) in pages.iter().enumerate() {
+            body.push_str(&format!(
+                "<li><a href=\"#part-{}\">{}</a></li>\n",
+                index + 1,
+                escape_html(&page.title)
+            ));
+        }
+        body.push_str("</ol>\n</nav>\n");
+    }
+    for (index, page) in pages.iter().enumerate() {
+        if chaptered {
+            body.push_str(&format!(
+                "<h2 id=\"part-{}\">{}</h2>\n",
+                index + 1,
+                escape_html(&page.title)
+            ));
+        }
+        for entry in &page.entries {
+            body.push_str(&render_entry(entry, &images));
+        }
+    }
+    body.push_str("<p class=\"meta\">Shared read-only from Aventura.</p>\n");
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en" data-theme="{}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>{}</title>
+{}</head>
+<body>
+<main>
+{}
+</main>
+</body>
+</html>
+"#,
+        theme.name(),
+        escape_html(title),
+        reader_head(),
+        body
+    )
+}
+
+async fn handle_read(State(state): State<ServerState>, Path(token): Path<String>) -> Response {
+    let share = {
+        let mut shares = state.shares.lock().await;
+        let Some(share) = shares.get_mut(&token) else {
+            return message(
+                StatusCode::NOT_FOUND,
+                "Not found",
+                "This link is not valid. Ask for a new one.",
+            );
+        };
+        if share.is_expired(now_ms()) {
+            return message(
+                StatusCode::GONE,
+                "Link expired",
+                "This link has expired. Ask for a new one.",
+            );
+        }
+        share.views += 1;
+        share.clone()
+    };
+
+    // Always the latest saved text, so a draft can be re-read as it grows
+    let export = match rows::load(&state.app, &share.story_id).await {
+        Ok(Some(export)) => export,
+        Ok(None) => {
+            return message(
+                StatusCode::NOT_FOUND,
+                "Not found",
+                "This story is no longer available.",
+            )
+        }
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let note = AccessNote {
+        story_id: Some(share.story_id.clone()),
+        ..Default::default()
+    };
+    let app = state.app.clone();
+    let page = tokio::task::spawn_blocking(move || {
+        render_page(
+            &export,
+            share.theme,
+            cover::cover_for(&app, &share.story_id).as_deref(),
+        )
+    })
+    .await;
+    note.on(match page {
+        Ok(page) => (
+            [
+                (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                (header::CACHE_CONTROL, "no-store"),
+                (header::REFERRER_POLICY, "no-referrer"),
+                (header::HeaderName::from_static("x-robots-tag"), "noindex"),
+            ],
+            page,
+        )
+            .into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    })
+}
+
+/// Serve a reader asset, Brotli-compressed when the browser accepts it.
+/// URLs carry the reader version, so a matching one can be cached forever.
+async fn handle_asset(
+    headers: HeaderMap,
+    Path((version, file)): Path<(String, String)>,
+) -> Response {
+    let Some(asset) = reader::asset(&file) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let cache = if version == reader::version() {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+    let unchanged = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|t| t.trim() == asset.etag));
+    let common = [
+        (header::CACHE_CONTROL, cache.to_string()),
+        (header::ETAG, asset.etag.clone()),
+        (header::VARY, "Accept-Encoding".to_string()),
+    ];
+    if unchanged {
+        return (StatusCode::NOT_MODIFIED, common).into_response();
+    }
+    let accepts_brotli = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .any(|e| e.split(';').next().map(str::trim) == Some("br"))
+        });
+    match &asset.brotli {
+        Some(compressed) if accepts_brotli => (
+            common,
+            [
+                (header::CONTENT_TYPE, asset.content_type),
+                (header::CONTENT_ENCODING, "br"),
+            ],
+            compressed.clone(),
+        )
+            .into_response(),
+        _ => (
+            common,
+            [(header::CONTENT_TYPE, asset.content_type)],
+            asset.body,
+        )
+            .into_response(),
+    }
+}
@@ -0,0 +1,151 @@
+//! Watches the sync server task. When it ends without being stopped, the
+//! app is told why and, if the host asked for it, the server is started
+//! again on the same port with the same token so paired devices keep working.
+
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+use tokio::net::TcpListener;
+use tokio::task::{JoinError, JoinHandle};
+
+use super::keys::now_ms;
+use super::server::{build_router, spawn_server, ServerState};
+
+/// Emitted with a `ServerStopped` whenever the server stops on its own
+pub const SERVER_STOPPED_EVENT: &str = "sync://server-stopped";
+
+/// Restarts in a row before giving up
+const MAX_RESTARTS: u32 = 5;
+
+/// Wait before the first restart; doubled for each further one in a row
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// A server that stayed up this long is no longer counted as restarting in
+/// a loop
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStopped {
+    pub at: i64,
+    pub cause: String,
+    /// Whether the server is being started again
+    pub restarting: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncServerStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    /// When the server last started listening
+    pub started_at: Option<i64>,
+    pub auto_restart: bool,
+    /// Times the server was started again since the host started it
+    pub restarts: u32,
+    pub last_stop: Option<ServerStopped>,
+}
+
+pub type SharedStatus = Arc<Mutex<SyncServerStatus>>;
+
+/// Aborts the server task when the supervisor is stopped, since dropping a
+/// join handle would leave it running
+struct AbortOnDrop(JoinHandle<Result<(), String>>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn cause(outcome: Result<Result<(), String>, JoinError>) -> String {
+    match outcome {
+        Ok(Ok(())) => "The server stopped accepting connections".to_string(),
+        Ok(Err(e)) => e,
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|m| m.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned());
+            match message {
+                Some(message) => format!("The server crashed: {}", message),
+                None => "The server crashed".to_string(),
+            }
+        }
+        Err(_) => "The server task was cancelled".to_string(),
+    }
+}
+
+fn update(status: &SharedStatus, change: impl FnOnce(&mut SyncServerStatus)) {
+    if let Ok(mut status) = status.lock() {
+        change(&mut status);
+    }
+}
+
+fn stopped(state: &ServerState, status: &SharedStatus, cause: String, restarting: bool) {
+    let stop = ServerStopped {
+        at: now_ms(),
+        cause,
+        restarting,
+    };
+    update(status, |s| {
+        s.running = false;
+        s.last_stop = Some(stop.clone());
+    });
+    let _ = state.app.emit(SERVER_STOPPED_EVENT, &stop);
+}
+
+/// Run the server on `listener` until it is aborted through the returned
+/// handle, restarting it on the same address when it stops on its own and
+/// `auto_restart` is set
+pub fn supervise(
+    state: ServerState,
+    listener: TcpListener,
+    address: SocketAddr,
+    auto_restart: bool,
+    status: SharedStatus,
+) -> JoinHandle<()> {
+    update(&status, |s| {
+        *s = SyncServerStatus {
+            port: Some(address.port()),
+            auto_restart,
+            ..Default::default()
+        }
+    });
+    tokio::spawn(async move {
+        let mut listener = listener;
+        let mut in_a_row = 0;
+        loop {
+            update(&status, |s| {
+                s.running = true;
+                s.started_at = Some(now_ms());
+            });
+            let started = Instant::now();
+            let mut server = AbortOnDrop(spawn_server(listener, build_router(state.clone())));
+            let outcome = (&mut server.0).await;
+            if started.elapsed() >= STABLE_AFTER {
+                in_a_row = 0;
+            }
+            let restarting = auto_restart && in_a_row < MAX_RESTARTS;
+            stopped(&state, &status, cause(outcome), restarting);
+            if !restarting {
+                return;
+            }
+
+            tokio::time::sleep(RESTART_DELAY * 2u32.pow(in_a_row)).await;
+            in_a_row += 1;
+            listener = match TcpListener::bind(address).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    let cause = format!("Failed to restart the server on {}: {}", address, e);
+                    stopped(&state, &status, cause, false);
+                    return;
+                }
+            };
+            update(&status, |s| s.restarts += 1);
+        }
+    })
+}
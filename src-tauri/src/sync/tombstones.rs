@@ -0,0 +1,224 @@
+//! Deleted stories, remembered so other devices remove their copies too.
+//! Peers swap their deletions with `SyncDeletions`. A deletion from another
+//! device is never applied straight away: it waits until the user confirms
+//! it, and confirmed deletions move the story to the trash, where it can be
+//! restored until the grace period ends.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tauri::{AppHandle, Emitter};
+
+use super::clock::ClockSkew;
+use super::keys::now_ms;
+use super::types::StoryTombstone;
+use crate::profiles::{self, database};
+use crate::store;
+use crate::story::archive::{self, ArchivedStory};
+
+/// Deletions made on this device, in the app data directory
+pub const TOMBSTONES_FILE: &str = "sync_tombstones.json";
+
+/// Deletions from other devices waiting for the user, in the app data directory
+pub const DELETION_INBOX_FILE: &str = "sync_deletion_inbox.json";
+
+/// Emitted with the pending deletions whenever another device's deletions
+/// add to them
+pub const DELETIONS_PENDING_EVENT: &str = "sync://deletions-pending";
+
+/// How long a deletion is passed on to other devices
+const TOMBSTONE_TTL_MS: i64 = 90 * 24 * 60 * 60 * 1000;
+
+/// How long a story deleted by another device stays in the trash
+pub const TRASH_GRACE_MS: i64 = 30 * 24 * 60 * 60 * 1000;
+
+/// A deletion from another device that applies to a story on this one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingDeletion {
+    pub story_id: String,
+    pub title: String,
+    pub deleted_at: i64,
+    pub received_at: i64,
+    /// The device the deletion came from, when known
+    #[serde(default)]
+    pub from: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct DeletionInbox {
+    pending: Vec<PendingDeletion>,
+    /// Deletions the user chose not to apply, so they are not asked again
+    declined: Vec<StoryTombstone>,
+}
+
+/// Deletions made on this device that are still passed on
+pub fn list(app: &AppHandle) -> Result<Vec<StoryTombstone>, String> {
+    let cutoff = now_ms() - TOMBSTONE_TTL_MS;
+    let mut tombstones: Vec<StoryTombstone> = store::load_json(app, TOMBSTONES_FILE)?;
+    tombstones.retain(|t| t.deleted_at >= cutoff);
+    Ok(tombstones)
+}
+
+/// Remember that stories were deleted on this device
+pub fn record(app: &AppHandle, story_ids: &[&str]) -> Result<(), String> {
+    let now = now_ms();
+    let mut tombstones = list(app)?;
+    tombstones.retain(|t| !story_ids.contains(&t.story_id.as_str()));
+    tombstones.extend(story_ids.iter().map(|id| StoryTombstone {
+        story_id: id.to_string(),
+        deleted_at: now,
+    }));
+    store::save_json(app, TOMBSTONES_FILE, &tombstones)
+}
+
+/// Stop passing on the deletion of stories that were brought back
+pub fn forget(app: &AppHandle, story_ids: &[&str]) -> Result<(), String> {
+    let mut tombstones = list(app)?;
+    tombstones.retain(|t| !story_ids.contains(&t.story_id.as_str()));
+    store::save_json(app, TOMBSTONES_FILE, &tombstones)
+}
+
+/// Title and last change of the local stories deletions name
+async fn local_stories(
+    app: &AppHandle,
+    story_ids: &[&str],
+) -> Result<Vec<(String, String, i64)>, String> {
+    let pool = database::open(&profiles::current(app)?.database_path, false).await?;
+    let read = async {
+        let mut found = Vec::new();
+        for story_id in story_ids {
+            let row: Option<(String, i64)> =
+                sqlx::query_as("SELECT title, updated_at FROM stories WHERE id = ?")
+                    .bind(story_id)
+                    .fetch_optional(&pool)
+                    .await
+                    .map_err(|e| format!("Failed to read stories: {}", e))?;
+            if let Some((title, updated_at)) = row {
+                found.push((story_id.to_string(), title, updated_at));
+            }
+        }
+        Ok(found)
+    }
+    .await;
+    pool.close().await;
+    read
+}
+
+/// Queue another device's deletions of stories this device still has,
+/// unless the story changed here after it was deleted there. Deletion times
+/// are moved onto this device's clock with `clock` when the peer's offset is
+/// known; a peer that sends times already on this clock passes None.
+/// Returns every pending deletion.
+pub async fn receive(
+    app: &AppHandle,
+    tombstones: &[StoryTombstone],
+    clock: Option<ClockSkew>,
+    from: Option<String>,
+) -> Result<Vec<PendingDeletion>, String> {
+    let tombstones: Vec<StoryTombstone> = tombstones
+        .iter()
+        .map(|t| StoryTombstone {
+            story_id: t.story_id.clone(),
+            deleted_at: clock.map_or(t.deleted_at, |c| c.local_time(t.deleted_at)),
+        })
+        .collect();
+    let mut inbox: DeletionInbox = store::load_json(app, DELETION_INBOX_FILE)?;
+    let own: HashSet<String> = list(app)?.into_iter().map(|t| t.story_id).collect();
+    let profile = profiles::active(app)?;
+    let candidates: Vec<&StoryTombstone> = tombstones
+        .iter()
+        .filter(|t| !own.contains(&t.story_id))
+        .filter(|t| !inbox.pending.iter().any(|p| p.story_id == t.story_id))
+        .filter(|t| {
+            !inbox
+                .declined
+                .iter()
+                .any(|d| d.story_id == t.story_id && d.deleted_at >= t.deleted_at)
+        })
+        .filter(|t| profiles::check_story_id(&profile, &t.story_id).is_ok())
+        .collect();
+    if candidates.is_empty() {
+        return Ok(inbox.pending);
+    }
+
+    let ids: Vec<&str> = candidates.iter().map(|t| t.story_id.as_str()).collect();
+    let now = now_ms();
+    let mut added = false;
+    for (story_id, title, updated_at) in local_stories(app, &ids).await? {
+        let Some(tombstone) = candidates.iter().find(|t| t.story_id == story_id) else {
+            continue;
+        };
+        if updated_at > tombstone.deleted_at {
+            continue;
+        }
+        inbox.pending.push(PendingDeletion {
+            story_id,
+            title,
+            deleted_at: tombstone.deleted_at,
+            received_at: now,
+            from: from.clone(),
+        });
+        added = true;
+    }
+    if added {
+        store::save_json(app, DELETION_INBOX_FILE, &inbox)?;
+        let _ = app.emit(DELETIONS_PENDING_EVENT, &inbox.pending);
+    }
+    Ok(inbox.pending)
+}
+
+/// Deletions from other devices waiting for the user
+pub fn pending(app: &AppHandle) -> Result<Vec<PendingDeletion>, String> {
+    let inbox: DeletionInbox = store::load_json(app, DELETION_INBOX_FILE)?;
+    Ok(inbox.pending)
+}
+
+/// Move the stories of confirmed deletions to the trash and pass the
+/// deletions on. Stories that fail stay pending and are named in the error.
+pub async fn apply(app: &AppHandle, story_ids: &[String]) -> Result<Vec<ArchivedStory>, String> {
+    let mut inbox: DeletionInbox = store::load_json(app, DELETION_INBOX_FILE)?;
+    let purge_at = now_ms() + TRASH_GRACE_MS;
+    let mut trashed = Vec::new();
+    let mut errors = Vec::new();
+    for story_id in story_ids {
+        if !inbox.pending.iter().any(|p| &p.story_id == story_id) {
+            errors.push(format!("No pending deletion for story {}", story_id));
+            continue;
+        }
+        match archive::trash(app, story_id, purge_at).await {
+            Ok(story) => {
+                inbox.pending.retain(|p| &p.story_id != story_id);
+                trashed.push(story);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    store::save_json(app, DELETION_INBOX_FILE, &inbox)?;
+    let ids: Vec<&str> = trashed.iter().map(|s| s.story_id.as_str()).collect();
+    record(app, &ids)?;
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    Ok(trashed)
+}
+
+/// Keep the stories of pending deletions and do not ask about them again
+pub fn decline(app: &AppHandle, story_ids: &[String]) -> Result<(), String> {
+    let mut inbox: DeletionInbox = store::load_json(app, DELETION_INBOX_FILE)?;
+    let (declined, pending): (Vec<PendingDeletion>, Vec<PendingDeletion>) = inbox
+        .pending
+        .into_iter()
+        .partition(|p| story_ids.contains(&p.story_id));
+    inbox.pending = pending;
+    inbox
+        .declined
+        .retain(|d| !declined.iter().any(|p| p.story_id == d.story_id));
+    inbox
+        .declined
+        .extend(declined.into_iter().map(|p| StoryTombstone {
+            story_id: p.story_id,
+            deleted_at: p.deleted_at,
+        }));
+    store::save_json(app, DELETION_INBOX_FILE, &inbox)
+}
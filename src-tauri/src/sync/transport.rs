@@ -0,0 +1,297 @@
+//! Transports carrying the `SyncAction` protocol. The network sync server
+//! speaks HTTP; any other byte stream can carry the same requests as
+//! length-prefixed JSON frames. Bluetooth RFCOMM links show up as serial
+//! devices on every desktop platform (`/dev/rfcomm0`, `/dev/tty.<name>`,
+//! `COM5`), which lets two devices sync without a shared Wi-Fi network.
+//! Wi-Fi Direct groups get their own network interface and use HTTP.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+use super::codec::WireFormat;
+use super::http::{self, HttpTuning};
+use super::server::{dispatch, ServerState};
+use super::types::{SyncRequest, SyncResponse};
+
+/// Largest frame accepted over a stream, matching the HTTP body limit
+const MAX_FRAME_BYTES: usize = 100 * 1024 * 1024;
+
+/// Why a request did not get an answer
+#[derive(Debug)]
+pub enum TransportError {
+    /// The peer could not be reached, so the request never got to it
+    Unreachable(String),
+    /// The connection timed out or dropped once the request was on its way,
+    /// so the peer may have acted on it
+    Interrupted(String),
+    /// The peer answered with something that is not a sync response
+    Invalid(String),
+    /// The request was abandoned because its session was closed
+    Cancelled,
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::Unreachable(message)
+            | TransportError::Interrupted(message)
+            | TransportError::Invalid(message) => f.write_str(message),
+            TransportError::Cancelled => f.write_str("The sync session was closed"),
+        }
+    }
+}
+
+impl From<TransportError> for String {
+    fn from(error: TransportError) -> Self {
+        error.to_string()
+    }
+}
+
+/// A channel to a sync peer
+pub trait SyncTransport: Send + Sync {
+    /// Send one request and wait for its response
+    fn send(
+        &self,
+        request: &SyncRequest,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<SyncResponse, TransportError>> + Send;
+}
+
+/// Formats peers answered in, keyed by URL. Requests to a peer use JSON until
+/// it has shown it understands something more compact.
+fn peer_formats() -> &'static std::sync::Mutex<HashMap<String, WireFormat>> {
+    static FORMATS: OnceLock<std::sync::Mutex<HashMap<String, WireFormat>>> = OnceLock::new();
+    FORMATS.get_or_init(Default::default)
+}
+
+/// The sync server's `/sync` endpoint over HTTP
+pub struct HttpTransport {
+    url: String,
+    client: reqwest::Client,
+    tuning: HttpTuning,
+}
+
+impl HttpTransport {
+    pub fn new(ip: &str, port: u16, tuning: &HttpTuning) -> Self {
+        Self {
+            url: format!("http://{}:{}/sync", ip, port),
+            client: http::client(tuning),
+            tuning: tuning.clone(),
+        }
+    }
+
+    async fn send_once(
+        &self,
+        request: &SyncRequest,
+        timeout: Duration,
+    ) -> Result<SyncResponse, TransportError> {
+        let request_format = peer_formats()
+            .lock()
+            .ok()
+            .and_then(|formats| formats.get(&self.url).copied())
+            .unwrap_or(WireFormat::Json);
+        let body = request_format
+            .encode(request)
+            .map_err(|e| TransportError::Invalid(format!("Failed to encode request: {}", e)))?;
+
+        let response = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, request_format.mime())
+            .header(reqwest::header::ACCEPT, WireFormat::ACCEPT)
+            .body(body)
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                let message = format!("Connection failed: {}", e);
+                if e.is_connect() {
+                    TransportError::Unreachable(message)
+                } else {
+                    TransportError::Interrupted(message)
+                }
+            })?;
+
+        // Servers from before format negotiation send JSON without saying so
+        let response_format = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(WireFormat::from_mime)
+            .unwrap_or(WireFormat::Json);
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| TransportError::Interrupted(format!("Connection failed: {}", e)))?;
+        let decoded = response_format
+            .decode(&bytes)
+            .map_err(|e| TransportError::Invalid(format!("Invalid response: {}", e)))?;
+        if let Ok(mut formats) = peer_formats().lock() {
+            formats.insert(self.url.clone(), response_format);
+        }
+        Ok(decoded)
+    }
+}
+
+impl SyncTransport for HttpTransport {
+    /// Retries requests that could not connect to the peer, as set in the
+    /// tuning. A request that may have reached it is never sent again.
+    async fn send(
+        &self,
+        request: &SyncRequest,
+        timeout: Duration,
+    ) -> Result<SyncResponse, TransportError> {
+        let mut attempt = 0;
+        loop {
+            match self.send_once(request, timeout).await {
+                Err(TransportError::Unreachable(_)) if attempt < self.tuning.retries => {
+                    tokio::time::sleep(self.tuning.retry_delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, bytes: &[u8]) -> std::io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Frame too large"))?;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(bytes).await?;
+    stream.flush().await
+}
+
+async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S) -> std::io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_BYTES {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Frame too large",
+        ));
+    }
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
+
+/// Requests as length-prefixed JSON frames over a connected byte stream.
+/// One request is in flight at a time.
+pub struct StreamTransport<S> {
+    stream: Mutex<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> StreamTransport<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream: Mutex::new(stream),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> SyncTransport for StreamTransport<S> {
+    async fn send(
+        &self,
+        request: &SyncRequest,
+        timeout: Duration,
+    ) -> Result<SyncResponse, TransportError> {
+        let body = serde_json::to_vec(request)
+            .map_err(|e| TransportError::Invalid(format!("Failed to encode request: {}", e)))?;
+        let mut stream = self.stream.lock().await;
+        let exchange = async {
+            write_frame(&mut *stream, &body)
+                .await
+                .map_err(|e| TransportError::Unreachable(format!("Connection failed: {}", e)))?;
+            read_frame(&mut *stream)
+                .await
+                .map_err(|e| TransportError::Interrupted(format!("Connection failed: {}", e)))
+        };
+        let frame = tokio::time::timeout(timeout, exchange)
+            .await
+            .map_err(|_| TransportError::Interrupted("Connection timed out".to_string()))??;
+        serde_json::from_slice(&frame)
+            .map_err(|e| TransportError::Invalid(format!("Invalid response: {}", e)))
+    }
+}
+
+/// Path Windows opens a serial port by. `COM1` to `COM9` open as plain
+/// names, but higher ports only with the device namespace prefix.
+fn device_path(path: &str) -> String {
+    let is_com_port = path.len() > 3
+        && path[..3].eq_ignore_ascii_case("com")
+        && path[3..].bytes().all(|b| b.is_ascii_digit());
+    if cfg!(windows) && is_com_port {
+        format!(r"\\.\{}", path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Put a terminal device in raw mode, so bytes pass through unchanged
+/// instead of being echoed, line-buffered or translated
+#[cfg(unix)]
+fn set_raw_mode(file: &tokio::fs::File) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let fd = file.as_raw_fd();
+    // SAFETY: `fd` is an open descriptor owned by `file` for the duration of
+    // these calls, and `termios` is fully written by `tcgetattr` before use
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Open a serial device (such as a bound RFCOMM channel) for reading and writing
+pub async fn open_device(path: &str) -> Result<tokio::fs::File, String> {
+    let file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(device_path(path))
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    #[cfg(unix)]
+    set_raw_mode(&file).map_err(|e| format!("Failed to configure {}: {}", path, e))?;
+    Ok(file)
+}
+
+/// Answer requests arriving on a stream until the peer disconnects
+pub async fn serve_stream<S: AsyncRead + AsyncWrite + Unpin + Send>(
+    state: ServerState,
+    mut stream: S,
+) -> Result<(), String> {
+    loop {
+        let frame = match read_frame(&mut stream).await {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(format!("Connection failed: {}", e)),
+        };
+        let response = match serde_json::from_slice::<SyncRequest>(&frame) {
+            Ok(request) => dispatch(&state, request).await,
+            Err(e) => {
+                state.metrics.record("invalid", true);
+                SyncResponse::Error {
+                    message: format!("Invalid request: {}", e),
+                }
+            }
+        };
+        let body = serde_json::to_vec(&response)
+            .map_err(|e| format!("Failed to encode response: {}", e))?;
+        state.metrics.record_transfer(frame.len(), body.len());
+        write_frame(&mut stream, &body)
+            .await
+            .map_err(|e| format!("Connection failed: {}", e))?;
+    }
+}
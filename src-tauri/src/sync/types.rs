@@ -1,5 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+use crate::lorebook::{Lorebook, LorebookInfo};
+use crate::story::rating::ContentRating;
+
+use super::device::DeviceIdentity;
+use super::keys::EncryptedKeys;
+use super::network::NetworkInterfaceInfo;
+use super::settings::{SettingsBundle, SettingsScope};
+
 /// Information about the sync server, returned when starting a server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -8,6 +16,11 @@ pub struct SyncServerInfo {
     pub port: u16,
     pub token: String,
     pub qr_code_base64: String,
+    /// How this device is shown to others
+    pub device: DeviceIdentity,
+    /// Networks devices can reach the server on
+    pub visible_on: Vec<NetworkInterfaceInfo>,
+    pub lan_only: bool,
 }
 
 /// Preview of a story available for sync
@@ -19,6 +32,19 @@ pub struct SyncStoryPreview {
     pub genre: Option<String>,
     pub updated_at: i64,
     pub entry_count: usize,
+    /// Bytes of export JSON, when known. Missing from older servers.
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub rating: ContentRating,
+    /// Small base64 JPEG of the story's cover. Only sent by servers with the
+    /// "thumbnails" capability.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
 }
 
 /// Request sent to the sync server
@@ -36,8 +62,77 @@ pub enum SyncAction {
     ListStories,
     /// Pull a specific story by ID
     PullStory { story_id: String },
+    /// Pull a story with only the entries from `from_entry` up to, not
+    /// including, `to_entry`, counted from the first entry
+    PullEntries {
+        story_id: String,
+        from_entry: usize,
+        to_entry: usize,
+    },
     /// Push a story to the server
     PushStory { story_data: String },
+    /// Pull the settings the host has shared, limited to the given scopes
+    PullSettings { scopes: Vec<SettingsScope> },
+    /// Push settings to the server
+    PushSettings { settings: SettingsBundle },
+    /// Start an API key exchange with a commitment to the client's public key
+    BeginKeyExchange {
+        client_commitment: String,
+        /// The client's identity, shown on the host's confirmation screen
+        #[serde(default)]
+        device: Option<DeviceIdentity>,
+    },
+    /// Reveal the committed public key; the host then shows the code for comparison
+    RevealKeyExchange {
+        exchange_id: String,
+        client_public: String,
+    },
+    /// Deliver encrypted API keys once both users have confirmed the code
+    DeliverKeys {
+        exchange_id: String,
+        payload: EncryptedKeys,
+    },
+    /// Swap deletions: the client's are queued for the host to confirm and
+    /// the host answers with its own
+    SyncDeletions { tombstones: Vec<StoryTombstone> },
+    /// List the lorebooks the host shares
+    ListLorebooks,
+    /// Pull a shared lorebook by ID
+    PullLorebook { lorebook_id: String },
+}
+
+impl SyncAction {
+    /// Name used for the action in metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyncAction::ListStories => "listStories",
+            SyncAction::PullStory { .. } => "pullStory",
+            SyncAction::PullEntries { .. } => "pullEntries",
+            SyncAction::PushStory { .. } => "pushStory",
+            SyncAction::PullSettings { .. } => "pullSettings",
+            SyncAction::PushSettings { .. } => "pushSettings",
+            SyncAction::BeginKeyExchange { .. } => "beginKeyExchange",
+            SyncAction::RevealKeyExchange { .. } => "revealKeyExchange",
+            SyncAction::DeliverKeys { .. } => "deliverKeys",
+            SyncAction::SyncDeletions { .. } => "syncDeletions",
+            SyncAction::ListLorebooks => "listLorebooks",
+            SyncAction::PullLorebook { .. } => "pullLorebook",
+        }
+    }
+
+    /// Whether the action only reads, so asking again after a lost answer
+    /// changes nothing on the peer
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            SyncAction::ListStories
+                | SyncAction::PullStory { .. }
+                | SyncAction::PullEntries { .. }
+                | SyncAction::PullSettings { .. }
+                | SyncAction::ListLorebooks
+                | SyncAction::PullLorebook { .. }
+        )
+    }
 }
 
 /// Response from the sync server
@@ -48,6 +143,29 @@ pub enum SyncResponse {
     StoriesList { stories: Vec<SyncStoryPreview> },
     /// Full story data (Aventura export JSON)
     StoryData { data: String },
+    /// Story data holding only the entries of the range sent, which is the
+    /// range asked for clamped to the entries the story has
+    StoryEntries {
+        data: String,
+        from_entry: usize,
+        to_entry: usize,
+        total_entries: usize,
+    },
+    /// Settings shared by the host
+    SettingsData { settings: SettingsBundle },
+    /// Key exchange accepted, waiting for confirmation
+    KeyExchangeStarted {
+        exchange_id: String,
+        server_public: String,
+        #[serde(default)]
+        device: Option<DeviceIdentity>,
+    },
+    /// Stories deleted on the host
+    Deletions { tombstones: Vec<StoryTombstone> },
+    /// Lorebooks the host shares
+    LorebooksList { lorebooks: Vec<LorebookInfo> },
+    /// A shared lorebook
+    LorebookData { lorebook: Lorebook },
     /// Operation succeeded
     Success { message: String },
     /// Operation failed
@@ -61,4 +179,15 @@ pub struct QrCodeData {
     pub port: u16,
     pub token: String,
     pub version: String, // App version for compatibility check
+    /// Missing from codes made by older versions
+    #[serde(default)]
+    pub device: Option<DeviceIdentity>,
+}
+
+/// Record that a story was deleted, so other devices can remove their copies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryTombstone {
+    pub story_id: String,
+    pub deleted_at: i64,
 }
@@ -0,0 +1,34 @@
+use std::path::Path;
+use tauri::AppHandle;
+
+use super::{TablePack, TableRoll};
+use crate::sync::keys::now_ms;
+
+/// Add a `.json` or `.csv` table pack, replacing an imported pack of the
+/// same name
+#[tauri::command]
+pub async fn import_table_pack(app: AppHandle, path: String) -> Result<TablePack, String> {
+    super::import(&app, Path::new(&path))
+}
+
+#[tauri::command]
+pub async fn list_table_packs(app: AppHandle) -> Result<Vec<TablePack>, String> {
+    super::list(&app)
+}
+
+#[tauri::command]
+pub async fn delete_table_pack(app: AppHandle, pack_id: String) -> Result<bool, String> {
+    super::delete(&app, &pack_id)
+}
+
+/// Roll on a table, filling in its nested rolls. The seed decides every
+/// roll; without one the roll gets a fresh seed.
+#[tauri::command]
+pub async fn roll_table(
+    app: AppHandle,
+    table_id: String,
+    seed: Option<u64>,
+) -> Result<TableRoll, String> {
+    let packs = super::list(&app)?;
+    super::roll(&packs, &table_id, seed.unwrap_or_else(|| now_ms() as u64))
+}
@@ -0,0 +1,453 @@
+//! Random tables for names, encounters, loot and the like, imported in packs
+//! from JSON or CSV. An entry may roll on other tables or dice with
+//! `{{roll:tavern-names}}` or `{{roll:2d6+1}}`, and system prompts sent to
+//! the model may use the same macros. Tables are known by `pack/table` IDs;
+//! inside a pack its own tables can be named alone.
+//!
+//! A JSON pack is `{"name": ..., "tables": [{"name": ..., "entries": [...]}]}`
+//! where entries are strings or `{"text": ..., "weight": n}`. A CSV pack has
+//! a header row with a `text` column and optional `table` and `weight`
+//! columns; it is named after the file, as is its table when there is no
+//! `table` column.
+
+pub mod commands;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tauri::AppHandle;
+
+use crate::annotations::feedback::csv_rows;
+use crate::export::schedule::slug;
+use crate::rng::SplitMix64;
+use crate::store;
+use crate::sync::keys::now_ms;
+
+/// Imported table packs, in the app data directory
+pub const TABLE_PACKS_FILE: &str = "table_packs.json";
+
+/// Tables rolled within one roll at most, so tables that name each other
+/// can't roll forever
+const MAX_ROLLS: usize = 64;
+
+/// Tables deep a roll may nest
+const MAX_DEPTH: usize = 8;
+
+const MAX_DICE: u32 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableEntry {
+    pub text: String,
+    /// Relative chance of the entry coming up
+    pub weight: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RandomTable {
+    /// `pack/table`
+    pub id: String,
+    pub name: String,
+    pub entries: Vec<TableEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TablePack {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub tables: Vec<RandomTable>,
+    pub imported_at: i64,
+}
+
+/// One table rolled on, and what came up
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableRollStep {
+    pub table_id: String,
+    pub entry: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableRoll {
+    pub table_id: String,
+    /// The result with every nested roll filled in
+    pub text: String,
+    /// Every table rolled on, in the order they were rolled
+    pub steps: Vec<TableRollStep>,
+    pub seed: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EntryFile {
+    Text(String),
+    Weighted {
+        text: String,
+        #[serde(default = "default_weight")]
+        weight: u32,
+    },
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct TableFile {
+    name: String,
+    entries: Vec<EntryFile>,
+}
+
+#[derive(Deserialize)]
+struct PackFile {
+    name: String,
+    #[serde(default)]
+    description: String,
+    tables: Vec<TableFile>,
+}
+
+fn key(name: &str) -> String {
+    slug(name.trim()).to_lowercase()
+}
+
+/// `{{roll:...}}` macros
+fn roll_macro() -> &'static Regex {
+    static MACRO: OnceLock<Regex> = OnceLock::new();
+    MACRO.get_or_init(|| Regex::new(r"\{\{\s*roll:\s*([^}]+?)\s*\}\}").unwrap())
+}
+
+/// Dice such as `d20`, `3d6` or `2d4+1`
+fn dice() -> &'static Regex {
+    static DICE: OnceLock<Regex> = OnceLock::new();
+    DICE.get_or_init(|| Regex::new(r"^(\d*)d(\d+)(?:\s*([+-])\s*(\d+))?$").unwrap())
+}
+
+/// Roll dice, or None if the text isn't dice
+fn roll_dice(text: &str, rng: &mut SplitMix64) -> Option<Result<i64, String>> {
+    let captures = dice().captures(text.trim())?;
+    let count: u32 = match &captures[1] {
+        "" => 1,
+        count => count.parse().unwrap_or(u32::MAX),
+    };
+    let sides: u32 = captures[2].parse().unwrap_or(u32::MAX);
+    if count == 0 || count > MAX_DICE || sides == 0 || sides > 1000 {
+        return Some(Err(format!("Dice out of range: {}", text)));
+    }
+    let mut total: i64 = (0..count).map(|_| rng.roll(sides) as i64).sum();
+    if let Some(modifier) = captures.get(4) {
+        let modifier: i64 = modifier.as_str().parse().unwrap_or(0);
+        total += if &captures[3] == "-" {
+            -modifier
+        } else {
+            modifier
+        };
+    }
+    Some(Ok(total))
+}
+
+/// Build a pack from the file's tables, checking names and weights
+fn build_pack(
+    name: &str,
+    description: String,
+    tables: Vec<TableFile>,
+) -> Result<TablePack, String> {
+    let pack_id = key(name);
+    if name.trim().is_empty() || pack_id.is_empty() {
+        return Err("Table packs need a name".to_string());
+    }
+    let mut built: Vec<RandomTable> = Vec::new();
+    for table in tables {
+        let id = format!("{}/{}", pack_id, key(&table.name));
+        if table.name.trim().is_empty() {
+            return Err("Tables need a name".to_string());
+        }
+        if built.iter().any(|t| t.id == id) {
+            return Err(format!(
+                "The pack has two tables named {}",
+                table.name.trim()
+            ));
+        }
+        let entries: Vec<TableEntry> = table
+            .entries
+            .into_iter()
+            .map(|e| match e {
+                EntryFile::Text(text) => TableEntry { text, weight: 1 },
+                EntryFile::Weighted { text, weight } => TableEntry { text, weight },
+            })
+            .map(|e| TableEntry {
+                text: e.text.trim().to_string(),
+                weight: e.weight,
+            })
+            .filter(|e| !e.text.is_empty())
+            .collect();
+        if entries.iter().all(|e| e.weight == 0) {
+            return Err(format!("Table {} has nothing to roll", table.name.trim()));
+        }
+        built.push(RandomTable {
+            id,
+            name: table.name.trim().to_string(),
+            entries,
+        });
+    }
+    if built.is_empty() {
+        return Err("The pack has no tables".to_string());
+    }
+    Ok(TablePack {
+        id: pack_id,
+        name: name.trim().to_string(),
+        description: description.trim().to_string(),
+        tables: built,
+        imported_at: now_ms(),
+    })
+}
+
+fn parse_csv(name: &str, text: &str) -> Result<TablePack, String> {
+    let mut rows = csv_rows(text).into_iter();
+    let header: Vec<String> = rows
+        .next()
+        .ok_or("The CSV file is empty")?
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let text_column = column("text").ok_or("The CSV file has no text column")?;
+    let (table_column, weight_column) = (column("table"), column("weight"));
+    let mut tables: Vec<TableFile> = Vec::new();
+    for (line, row) in rows.enumerate() {
+        let field = |index: usize| row.get(index).map(|f| f.trim()).unwrap_or_default();
+        let table = table_column
+            .map(field)
+            .filter(|t| !t.is_empty())
+            .unwrap_or(name);
+        let weight = match weight_column.map(field).filter(|w| !w.is_empty()) {
+            Some(weight) => weight
+                .parse()
+                .map_err(|_| format!("Row {} has a weight that isn't a whole number", line + 2))?,
+            None => 1,
+        };
+        let entry = EntryFile::Weighted {
+            text: field(text_column).to_string(),
+            weight,
+        };
+        match tables.iter_mut().find(|t| t.name == table) {
+            Some(existing) => existing.entries.push(entry),
+            None => tables.push(TableFile {
+                name: table.to_string(),
+                entries: vec![entry],
+            }),
+        }
+    }
+    build_pack(name, String::new(), tables)
+}
+
+/// Read a pack from a `.json` or `.csv` file
+pub fn read_pack(path: &Path) -> Result<TablePack, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("json") => {
+            let file: PackFile =
+                serde_json::from_str(&text).map_err(|e| format!("Invalid table pack: {}", e))?;
+            build_pack(&file.name, file.description, file.tables)
+        }
+        Some("csv") => parse_csv(&stem, &text),
+        _ => Err(format!(
+            "Unsupported table pack: {} (expected .json or .csv)",
+            path.display()
+        )),
+    }
+}
+
+pub fn list(app: &AppHandle) -> Result<Vec<TablePack>, String> {
+    store::load_json(app, TABLE_PACKS_FILE)
+}
+
+/// Add a pack, replacing one with the same ID
+pub fn import(app: &AppHandle, path: &Path) -> Result<TablePack, String> {
+    let pack = read_pack(path)?;
+    let mut packs = list(app)?;
+    packs.retain(|p| p.id != pack.id);
+    packs.push(pack.clone());
+    packs.sort_by_key(|p| p.name.to_lowercase());
+    store::save_json(app, TABLE_PACKS_FILE, &packs)?;
+    Ok(pack)
+}
+
+/// Returns whether the pack existed
+pub fn delete(app: &AppHandle, pack_id: &str) -> Result<bool, String> {
+    let mut packs = list(app)?;
+    let before = packs.len();
+    packs.retain(|p| p.id != pack_id);
+    if packs.len() == before {
+        return Ok(false);
+    }
+    store::save_json(app, TABLE_PACKS_FILE, &packs)?;
+    Ok(true)
+}
+
+/// Rolls on the imported tables
+pub struct Roller<'a> {
+    packs: &'a [TablePack],
+    rng: SplitMix64,
+    steps: Vec<TableRollStep>,
+    /// Leave macros naming no table, or dice that can't be rolled, as they
+    /// were written instead of failing
+    keep_unknown: bool,
+}
+
+impl<'a> Roller<'a> {
+    pub fn new(packs: &'a [TablePack], seed: u64) -> Self {
+        Self {
+            packs,
+            rng: SplitMix64(seed),
+            steps: Vec::new(),
+            keep_unknown: false,
+        }
+    }
+
+    /// Leave macros that can't be rolled as text
+    pub fn keeping_unknown(mut self) -> Self {
+        self.keep_unknown = true;
+        self
+    }
+
+    /// A table by full ID, by name within the pack rolling on it, or by a
+    /// name only one pack has
+    fn find(&self, reference: &str, pack: Option<&str>) -> Result<&'a RandomTable, String> {
+        let reference = reference.trim().to_lowercase();
+        let tables = || self.packs.iter().flat_map(|p| &p.tables);
+        if let Some(table) = tables().find(|t| t.id == reference) {
+            return Ok(table);
+        }
+        let local = key(&reference);
+        if let Some(pack) = pack {
+            let id = format!("{}/{}", pack, local);
+            if let Some(table) = tables().find(|t| t.id == id) {
+                return Ok(table);
+            }
+        }
+        let mut named = tables().filter(|t| t.id.rsplit('/').next() == Some(local.as_str()));
+        match (named.next(), named.next()) {
+            (Some(table), None) => Ok(table),
+            (Some(_), Some(_)) => Err(format!(
+                "More than one pack has a table named {}; use pack/table",
+                reference
+            )),
+            _ => Err(format!("Table not found: {}", reference)),
+        }
+    }
+
+    /// Fill in the `{{roll:...}}` macros of a text
+    pub fn expand(
+        &mut self,
+        text: &str,
+        pack: Option<&str>,
+        depth: usize,
+    ) -> Result<String, String> {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for captures in roll_macro().captures_iter(text) {
+            let whole = captures.get(0).unwrap();
+            out.push_str(&text[last..whole.start()]);
+            let target = &captures[1];
+            match roll_dice(target, &mut self.rng) {
+                Some(Err(_)) if self.keep_unknown => out.push_str(whole.as_str()),
+                Some(total) => out.push_str(&total?.to_string()),
+                None if self.keep_unknown && self.find(target, pack).is_err() => {
+                    out.push_str(whole.as_str())
+                }
+                None => out.push_str(&self.roll_on(target, pack, depth)?),
+            }
+            last = whole.end();
+        }
+        out.push_str(&text[last..]);
+        Ok(out)
+    }
+
+    fn roll_on(
+        &mut self,
+        reference: &str,
+        pack: Option<&str>,
+        depth: usize,
+    ) -> Result<String, String> {
+        if depth >= MAX_DEPTH {
+            return Err("Tables nest too deeply".to_string());
+        }
+        if self.steps.len() >= MAX_ROLLS {
+            return Err("Too many tables rolled at once".to_string());
+        }
+        let table = self.find(reference, pack)?;
+        let total: u64 = table.entries.iter().map(|e| e.weight as u64).sum();
+        let mut pick = self.rng.next() % total.max(1);
+        let index = table
+            .entries
+            .iter()
+            .position(|e| {
+                if pick < e.weight as u64 {
+                    true
+                } else {
+                    pick -= e.weight as u64;
+                    false
+                }
+            })
+            .unwrap_or(0);
+        let entry = &table.entries[index];
+        self.steps.push(TableRollStep {
+            table_id: table.id.clone(),
+            entry: index,
+            text: entry.text.clone(),
+        });
+        let own_pack = table.id.split('/').next();
+        self.expand(&entry.text, own_pack, depth + 1)
+    }
+}
+
+/// Roll on a table, filling in every nested roll
+pub fn roll(packs: &[TablePack], table_id: &str, seed: u64) -> Result<TableRoll, String> {
+    let mut roller = Roller::new(packs, seed);
+    let table = roller.find(table_id, None)?;
+    let text = roller.roll_on(&table.id, None, 0)?;
+    Ok(TableRoll {
+        table_id: table.id.clone(),
+        text,
+        steps: roller.steps,
+        seed,
+    })
+}
+
+/// Fill in `{{roll:...}}` macros in the system messages of a prompt, which
+/// come from the story's templates and profile. Story text and the user's
+/// own messages are sent as written, as are macros naming a table that
+/// does not exist.
+pub fn expand_messages(
+    app: &AppHandle,
+    messages: &mut [crate::ai::types::ChatMessage],
+) -> Result<(), String> {
+    let expandable =
+        |m: &crate::ai::types::ChatMessage| m.role == "system" && roll_macro().is_match(&m.content);
+    if !messages.iter().any(expandable) {
+        return Ok(());
+    }
+    let packs = list(app)?;
+    let mut roller = Roller::new(&packs, now_ms() as u64).keeping_unknown();
+    for message in messages.iter_mut().filter(|m| expandable(m)) {
+        message.content = roller.expand(&message.content, None, 0)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use tauri::AppHandle;
+
+use super::{deliver, fire, WebhookConfig, WebhookDelivery, WebhookEvent, WEBHOOKS_FILE};
+use crate::store;
+
+/// Get the configured webhooks
+#[tauri::command]
+pub async fn get_webhooks(app: AppHandle) -> Result<Vec<WebhookConfig>, String> {
+    store::load_json(&app, WEBHOOKS_FILE)
+}
+
+/// Replace the webhook configuration after validating every URL
+#[tauri::command]
+pub async fn save_webhooks(app: AppHandle, webhooks: Vec<WebhookConfig>) -> Result<(), String> {
+    for webhook in &webhooks {
+        webhook.validate()?;
+    }
+    store::save_json(&app, WEBHOOKS_FILE, &webhooks)
+}
+
+/// Send a test event to one webhook and report what happened
+#[tauri::command]
+pub async fn test_webhook(app: AppHandle, webhook_id: String) -> Result<WebhookDelivery, String> {
+    let webhooks: Vec<WebhookConfig> = store::load_json(&app, WEBHOOKS_FILE)?;
+    let webhook = webhooks
+        .iter()
+        .find(|w| w.id == webhook_id)
+        .ok_or_else(|| format!("Webhook not found: {}", webhook_id))?;
+    Ok(deliver(
+        webhook,
+        WebhookEvent::Test,
+        "Test notification from Aventura",
+        &serde_json::Value::Null,
+    )
+    .await)
+}
+
+/// Fire an event that happened in the frontend, such as a finished backup
+#[tauri::command]
+pub async fn notify_webhook_event(
+    app: AppHandle,
+    event: WebhookEvent,
+    summary: String,
+    data: Option<serde_json::Value>,
+) -> Result<(), String> {
+    if event == WebhookEvent::Test {
+        return Err("Use test_webhook to send test events".to_string());
+    }
+    fire(&app, event, summary, data.unwrap_or_default());
+    Ok(())
+}
@@ -0,0 +1,205 @@
+pub mod commands;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::store;
+
+/// File in the app data directory holding webhook configuration
+pub const WEBHOOKS_FILE: &str = "webhooks.json";
+
+/// Delivery attempts per event before giving up
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Server events that can trigger a webhook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WebhookEvent {
+    StoryReceived,
+    BackupCompleted,
+    SessionStarted,
+    Test,
+}
+
+impl WebhookEvent {
+    fn name(self) -> &'static str {
+        match self {
+            WebhookEvent::StoryReceived => "storyReceived",
+            WebhookEvent::BackupCompleted => "backupCompleted",
+            WebhookEvent::SessionStarted => "sessionStarted",
+            WebhookEvent::Test => "test",
+        }
+    }
+}
+
+/// Body layout expected by the receiving service
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WebhookFormat {
+    /// `{ event, timestamp, summary, data }`
+    #[default]
+    Json,
+    /// `{ content }`, as accepted by Discord webhooks
+    Discord,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookConfig {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    /// Key for the `X-Aventura-Signature` HMAC; unsigned when empty
+    #[serde(default)]
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let url = self.url.trim();
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!(
+                "Webhook '{}' must use an http or https URL",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of a single delivery
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDelivery {
+    pub webhook_id: String,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Hex-encoded HMAC-SHA256 of `timestamp.body`
+fn sign(secret: &str, timestamp: i64, body: &[u8]) -> Result<String, String> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .map_err(|e| format!("Invalid webhook secret: {}", e))?;
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    Ok(mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn build_body(
+    config: &WebhookConfig,
+    event: WebhookEvent,
+    timestamp: i64,
+    summary: &str,
+    data: &Value,
+) -> Value {
+    match config.format {
+        WebhookFormat::Json => serde_json::json!({
+            "event": event,
+            "timestamp": timestamp,
+            "summary": summary,
+            "data": data,
+        }),
+        WebhookFormat::Discord => serde_json::json!({ "content": summary }),
+    }
+}
+
+/// Deliver one event to one webhook, retrying with a short backoff
+pub async fn deliver(
+    config: &WebhookConfig,
+    event: WebhookEvent,
+    summary: &str,
+    data: &Value,
+) -> WebhookDelivery {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let body = build_body(config, event, timestamp, summary, data).to_string();
+    let mut delivery = WebhookDelivery {
+        webhook_id: config.id.clone(),
+        status: None,
+        error: None,
+    };
+
+    let client = reqwest::Client::new();
+    for attempt in 0..MAX_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+        }
+
+        let mut builder = client
+            .post(config.url.trim())
+            .header("Content-Type", "application/json")
+            .header("X-Aventura-Event", event.name())
+            .header("X-Aventura-Timestamp", timestamp.to_string())
+            .body(body.clone())
+            .timeout(Duration::from_secs(10));
+        if !config.secret.is_empty() {
+            match sign(&config.secret, timestamp, body.as_bytes()) {
+                Ok(signature) => {
+                    builder =
+                        builder.header("X-Aventura-Signature", format!("sha256={}", signature));
+                }
+                Err(e) => {
+                    delivery.error = Some(e);
+                    return delivery;
+                }
+            }
+        }
+
+        match builder.send().await {
+            Ok(response) => {
+                let status = response.status();
+                delivery.status = Some(status.as_u16());
+                // Client errors will not be fixed by retrying
+                if status.is_success() || status.is_client_error() {
+                    delivery.error =
+                        (!status.is_success()).then(|| format!("Webhook returned {}", status));
+                    return delivery;
+                }
+                delivery.error = Some(format!("Webhook returned {}", status));
+            }
+            Err(e) => delivery.error = Some(format!("Delivery failed: {}", e)),
+        }
+    }
+    delivery
+}
+
+/// Send an event to every enabled webhook subscribed to it, in the background
+pub fn fire(app: &AppHandle, event: WebhookEvent, summary: String, data: Value) {
+    let Ok(webhooks) = store::load_json::<Vec<WebhookConfig>>(app, WEBHOOKS_FILE) else {
+        return;
+    };
+    for config in webhooks
+        .into_iter()
+        .filter(|w| w.enabled && w.events.contains(&event))
+    {
+        let summary = summary.clone();
+        let data = data.clone();
+        tauri::async_runtime::spawn(async move {
+            let delivery = deliver(&config, event, &summary, &data).await;
+            if let Some(error) = delivery.error {
+                eprintln!("Webhook '{}' failed: {}", config.name, error);
+            }
+        });
+    }
+}
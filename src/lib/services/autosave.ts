@@ -0,0 +1,75 @@
+import { invoke } from '@tauri-apps/api/core';
+
+// How long a story must go without writes before it is saved, so a burst
+// of entry writes counts as one save
+const SETTLE_MS = 5000;
+
+// Prefix of the error a save based on an older revision fails with
+const REVISION_CONFLICT = 'RevisionConflict: ';
+
+interface StoryRevision {
+  storyId: string;
+  revision: number;
+  savedAt: number;
+}
+
+/**
+ * Saves stories once their writes settle. Stories are written row by row
+ * through the SQL plugin, so the backend cannot see a save happen; every
+ * write marks the story as touched and, once it settles, the story is
+ * saved based on the revision this window last loaded or saved.
+ */
+class AutosaveService {
+  private timers = new Map<string, ReturnType<typeof setTimeout>>();
+  private revisions = new Map<string, number>();
+
+  /** Record the revision a story was loaded at */
+  loaded(storyId: string, revision: number): void {
+    this.revisions.set(storyId, revision);
+  }
+
+  touched(storyId: string): void {
+    clearTimeout(this.timers.get(storyId));
+    this.timers.set(
+      storyId,
+      setTimeout(() => {
+        this.timers.delete(storyId);
+        this.saved(storyId).catch(console.error);
+      }, SETTLE_MS)
+    );
+  }
+
+  private async expectedRevision(storyId: string): Promise<number> {
+    const known = this.revisions.get(storyId);
+    if (known !== undefined) return known;
+    const current = await invoke<StoryRevision | null>('get_story_revision', { storyId });
+    return current?.revision ?? 0;
+  }
+
+  private async saved(storyId: string): Promise<void> {
+    const expectedRevision = await this.expectedRevision(storyId);
+    try {
+      const saved = await invoke<StoryRevision>('save_story', {
+        storyId,
+        expectedRevision,
+        lockId: null,
+      });
+      this.revisions.set(storyId, saved.revision);
+    } catch (error) {
+      const message = String(error);
+      if (!message.startsWith(REVISION_CONFLICT)) throw error;
+      // Someone else saved first: take their copy
+      const conflict = JSON.parse(message.slice(REVISION_CONFLICT.length));
+      this.revisions.set(storyId, conflict.currentRevision);
+      console.warn(`[Autosave] ${storyId} was saved elsewhere, reloading`);
+      const { story } = await import('$lib/stores/story.svelte');
+      if (story.currentStory?.id === storyId) {
+        await story.loadStory(storyId);
+      }
+      return;
+    }
+    await invoke('commit_story_history', { storyId });
+  }
+}
+
+export const autosaveService = new AutosaveService();
@@ -6,6 +6,7 @@
  * Converts character cards into scenario settings with the card character as an NPC.
  */
 
+import { invoke } from '@tauri-apps/api/core';
 import type { StoryMode } from '$lib/types';
 import type { Genre, GeneratedCharacter } from '$lib/services/ai/scenario';
 import { OpenAIProvider } from './ai/openrouter';
@@ -21,170 +22,7 @@ function log(...args: any[]) {
   }
 }
 
-// ===== PNG Metadata Extraction =====
-
-/**
- * PNG signature bytes
- */
-const PNG_SIGNATURE = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
-
-/**
- * Check if the data is a PNG file by examining the signature
- */
-export function isPngFile(data: ArrayBuffer | Uint8Array): boolean {
-  const bytes = data instanceof ArrayBuffer ? new Uint8Array(data) : data;
-  if (bytes.length < 8) return false;
-  
-  for (let i = 0; i < 8; i++) {
-    if (bytes[i] !== PNG_SIGNATURE[i]) return false;
-  }
-  return true;
-}
-
-/**
- * Extract character card JSON from PNG metadata.
- * SillyTavern embeds character data in a tEXt chunk with keyword "chara",
- * containing base64-encoded JSON.
- * 
- * @param data - PNG file data as ArrayBuffer or Uint8Array
- * @returns The decoded JSON string, or null if not found
- */
-export function extractCharacterCardFromPng(data: ArrayBuffer | Uint8Array): string | null {
-  const bytes = data instanceof ArrayBuffer ? new Uint8Array(data) : data;
-  
-  if (!isPngFile(bytes)) {
-    log('Not a valid PNG file');
-    return null;
-  }
-  
-  // Skip PNG signature (8 bytes)
-  let offset = 8;
-  
-  while (offset < bytes.length) {
-    // Read chunk length (4 bytes, big-endian)
-    if (offset + 4 > bytes.length) break;
-    const length = (bytes[offset] << 24) | (bytes[offset + 1] << 16) | (bytes[offset + 2] << 8) | bytes[offset + 3];
-    offset += 4;
-    
-    // Read chunk type (4 bytes)
-    if (offset + 4 > bytes.length) break;
-    const type = String.fromCharCode(bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]);
-    offset += 4;
-    
-    // Check if this is a tEXt chunk
-    if (type === 'tEXt') {
-      // Read chunk data
-      if (offset + length > bytes.length) break;
-      const chunkData = bytes.slice(offset, offset + length);
-      
-      // tEXt format: keyword (null-terminated) + text
-      // Find the null separator
-      let nullIndex = -1;
-      for (let i = 0; i < chunkData.length; i++) {
-        if (chunkData[i] === 0) {
-          nullIndex = i;
-          break;
-        }
-      }
-      
-      if (nullIndex > 0) {
-        const keyword = new TextDecoder('latin1').decode(chunkData.slice(0, nullIndex));
-        
-        // Check if this is the "chara" chunk
-        if (keyword.toLowerCase() === 'chara') {
-          const textData = chunkData.slice(nullIndex + 1);
-          const base64String = new TextDecoder('latin1').decode(textData);
-          
-          try {
-            // Decode base64 to get JSON
-            const jsonString = atob(base64String);
-            log('Found character data in PNG tEXt chunk');
-            return jsonString;
-          } catch (e) {
-            log('Failed to decode base64 character data:', e);
-            return null;
-          }
-        }
-      }
-    }
-    
-    // Skip chunk data and CRC (4 bytes)
-    offset += length + 4;
-    
-    // Stop at IEND chunk
-    if (type === 'IEND') break;
-  }
-  
-  log('No character data found in PNG');
-  return null;
-}
-
-/**
- * Read a file and extract character card JSON.
- * Handles both JSON files (direct text) and PNG files (embedded metadata).
- * 
- * @param file - The file to read
- * @returns The JSON string containing character card data
- */
-export async function readCharacterCardFile(file: File): Promise<string> {
-  const fileName = file.name.toLowerCase();
-  
-  if (fileName.endsWith('.png')) {
-    log('Reading PNG file:', file.name);
-    const arrayBuffer = await file.arrayBuffer();
-    const jsonString = extractCharacterCardFromPng(arrayBuffer);
-    
-    if (!jsonString) {
-      throw new Error('No character data found in PNG file. The image may not be a valid SillyTavern character card.');
-    }
-    
-    return jsonString;
-  }
-  
-  // Assume JSON for other files
-  log('Reading JSON file:', file.name);
-  return await file.text();
-}
-
-// ===== SillyTavern Card Types =====
-
-/**
- * SillyTavern V1 card format (also the core data for V2)
- */
-export interface SillyTavernCardV1 {
-  name: string;
-  description: string;
-  personality: string;
-  scenario: string;
-  first_mes: string;
-  mes_example: string;
-}
-
-/**
- * SillyTavern V2/V3 card format (V3 has same structure as V2)
- */
-export interface SillyTavernCardV2 {
-  spec: 'chara_card_v2' | 'chara_card_v3';
-  spec_version: string;
-  data: SillyTavernCardV1 & {
-    creator_notes?: string;
-    system_prompt?: string;
-    post_history_instructions?: string;
-    alternate_greetings?: string[];
-    character_book?: unknown; // Lorebook - ignored for this import
-    tags?: string[];
-    creator?: string;
-    character_version?: string;
-    extensions?: Record<string, unknown>;
-  };
-  // V3 cards also duplicate fields at root level
-  name?: string;
-  description?: string;
-  personality?: string;
-  scenario?: string;
-  first_mes?: string;
-  mes_example?: string;
-}
+// ===== Card Reading =====
 
 /**
  * Parsed card data (normalized from V1, V2, or V3)
@@ -219,74 +57,19 @@ export interface CardImportResult {
   errors: string[];
 }
 
-// ===== Card Parsing =====
-
 /**
- * Check if the data is a V2 or V3 card
- */
-function isV2OrV3Card(data: unknown): data is SillyTavernCardV2 {
-  if (typeof data !== 'object' || data === null) return false;
-  if (!('spec' in data) || !('data' in data)) return false;
-  const spec = (data as SillyTavernCardV2).spec;
-  return spec === 'chara_card_v2' || spec === 'chara_card_v3';
-}
-
-/**
- * Check if the data is a V1 card
- */
-function isV1Card(data: unknown): data is SillyTavernCardV1 {
-  return (
-    typeof data === 'object' &&
-    data !== null &&
-    'name' in data &&
-    'description' in data &&
-    !('spec' in data) // V2 cards have spec field
-  );
-}
-
-/**
- * Parse a character card JSON string into normalized format.
- * Supports both V1 and V2 SillyTavern formats.
+ * Read a character card from a PNG or JSON file.
+ * The backend's card reader does the parsing, so cards read here and cards
+ * imported into the library go through the same code: V1, V2 and V3 cards,
+ * in tEXt, zTXt or iTXt chunks.
+ *
+ * @param file - The file to read
+ * @returns The normalized card
  */
-export function parseCharacterCard(jsonString: string): ParsedCard | null {
-  try {
-    const data = JSON.parse(jsonString);
-
-    if (isV2OrV3Card(data)) {
-      const version = data.spec === 'chara_card_v3' ? 'v3' : 'v2';
-      log(`Detected ${version.toUpperCase()} card format`);
-      return {
-        name: data.data.name || data.name || 'Unknown Character',
-        description: data.data.description || data.description || '',
-        personality: data.data.personality || data.personality || '',
-        scenario: data.data.scenario || data.scenario || '',
-        firstMessage: data.data.first_mes || data.first_mes || '',
-        alternateGreetings: data.data.alternate_greetings || [],
-        exampleMessages: data.data.mes_example || data.mes_example || '',
-        version,
-      };
-    }
-
-    if (isV1Card(data)) {
-      log('Detected V1 card format');
-      return {
-        name: data.name || 'Unknown Character',
-        description: data.description || '',
-        personality: data.personality || '',
-        scenario: data.scenario || '',
-        firstMessage: data.first_mes || '',
-        alternateGreetings: [],
-        exampleMessages: data.mes_example || '',
-        version: 'v1',
-      };
-    }
-
-    log('Unknown card format');
-    return null;
-  } catch (error) {
-    log('Failed to parse card JSON:', error);
-    return null;
-  }
+export async function readCharacterCard(file: File): Promise<ParsedCard> {
+  log('Reading character card:', file.name);
+  const data = new Uint8Array(await file.arrayBuffer());
+  return invoke<ParsedCard>('read_character_card', { data: Array.from(data) });
 }
 
 // ===== Macro Replacement =====
@@ -341,26 +124,11 @@ function buildCardContext(card: ParsedCard): string {
  * Convert a parsed character card into a scenario setting using LLM.
  */
 export async function convertCardToScenario(
-  jsonString: string,
+  card: ParsedCard,
   mode: StoryMode,
   genre: Genre,
   profileId?: string | null
 ): Promise<CardImportResult> {
-  // Parse the card
-  const card = parseCharacterCard(jsonString);
-  if (!card) {
-    return {
-      success: false,
-      settingSeed: '',
-      npcs: [],
-      primaryCharacterName: '',
-      storyTitle: '',
-      firstMessage: '',
-      alternateGreetings: [],
-      errors: ['Failed to parse character card. Please ensure the file is a valid SillyTavern character card JSON.'],
-    };
-  }
-
   log('Parsed card:', { name: card.name, version: card.version });
 
   const cardTitle = card.name;
@@ -1,4 +1,7 @@
 import Database from '@tauri-apps/plugin-sql';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { autosaveService } from './autosave';
 import type {
   Story,
   StoryEntry,
@@ -24,10 +27,29 @@ import type {
 
 class DatabaseService {
   private db: Database | null = null;
+  private watchingProfiles = false;
 
   async init(): Promise<void> {
     if (this.db) return;
-    this.db = await Database.load('sqlite:aventura.db');
+    // The active profile's database, which moves with the data folder
+    // and lives next to the app in portable mode
+    const { databaseUrl } = await invoke<{ databaseUrl: string }>('get_active_profile');
+    this.db = await Database.load(databaseUrl);
+    await this.watchProfileSwitches();
+  }
+
+  // After a profile switch the old connection points at the previous
+  // profile's data, and every store holds its stories, so the database is
+  // closed and the window reloaded to start over on the new profile
+  private async watchProfileSwitches(): Promise<void> {
+    if (this.watchingProfiles) return;
+    this.watchingProfiles = true;
+    await listen('profile://switched', async () => {
+      const db = this.db;
+      this.db = null;
+      await db?.close();
+      window.location.reload();
+    });
   }
 
   private async getDb(): Promise<Database> {
@@ -56,23 +78,49 @@ class DatabaseService {
   }
 
   // Story operations
+  // Stories the active profile may see, by their IDs and ratings, for the
+  // library list
+  async getVisibleStories(): Promise<Story[]> {
+    const stories = await this.getAllStories();
+    const visible = new Set(
+      await invoke<string[]>('filter_visible_stories', { storyIds: stories.map((s) => s.id) })
+    );
+    return stories.filter((s) => visible.has(s.id));
+  }
+
   async getAllStories(): Promise<Story[]> {
     const db = await this.getDb();
     const results = await db.select<any[]>(
-      'SELECT * FROM stories ORDER BY updated_at DESC'
+      'SELECT * FROM stories ORDER BY pinned DESC, updated_at DESC'
     );
     return results.map(this.mapStory);
   }
 
   async getStory(id: string): Promise<Story | null> {
     const db = await this.getDb();
-    const results = await db.select<any[]>(
+    let results = await db.select<any[]>(
       'SELECT * FROM stories WHERE id = ?',
       [id]
     );
+    // Archived stories are restored the first time they are opened
+    if (results.length === 0 && (await this.unarchiveStory(id))) {
+      results = await db.select<any[]>(
+        'SELECT * FROM stories WHERE id = ?',
+        [id]
+      );
+    }
     return results.length > 0 ? this.mapStory(results[0]) : null;
   }
 
+  private async unarchiveStory(id: string): Promise<boolean> {
+    try {
+      await invoke('unarchive_story', { storyId: id });
+      return true;
+    } catch {
+      return false;
+    }
+  }
+
   async createStory(story: Omit<Story, 'createdAt' | 'updatedAt'>): Promise<Story> {
     const db = await this.getDb();
     const now = Date.now();
@@ -90,9 +138,15 @@ class DatabaseService {
         memory_config,
         retry_state,
         style_review_state,
-        time_tracker
+        time_tracker,
+        age_rating,
+        content_warnings,
+        favorite,
+        pinned,
+        series,
+        variant_of
       )
-       VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)`,
+       VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)`,
       [
         story.id,
         story.title,
@@ -107,6 +161,12 @@ class DatabaseService {
         story.retryState ? JSON.stringify(story.retryState) : null,
         story.styleReviewState ? JSON.stringify(story.styleReviewState) : null,
         story.timeTracker ? JSON.stringify(story.timeTracker) : null,
+        story.ageRating ?? null,
+        story.contentWarnings ? JSON.stringify(story.contentWarnings) : null,
+        story.favorite ? 1 : 0,
+        story.pinned ? 1 : 0,
+        story.series ? JSON.stringify(story.series) : null,
+        story.variantOf ? JSON.stringify(story.variantOf) : null,
       ]
     );
     return { ...story, createdAt: now, updatedAt: now };
@@ -154,12 +214,25 @@ class DatabaseService {
       setClauses.push('time_tracker = ?');
       values.push(updates.timeTracker ? JSON.stringify(updates.timeTracker) : null);
     }
+    if (updates.favorite !== undefined) {
+      setClauses.push('favorite = ?');
+      values.push(updates.favorite ? 1 : 0);
+    }
+    if (updates.pinned !== undefined) {
+      setClauses.push('pinned = ?');
+      values.push(updates.pinned ? 1 : 0);
+    }
+    if (updates.ambience !== undefined) {
+      setClauses.push('ambience = ?');
+      values.push(updates.ambience ? JSON.stringify(updates.ambience) : null);
+    }
 
     values.push(id);
     await db.execute(
       `UPDATE stories SET ${setClauses.join(', ')} WHERE id = ?`,
       values
     );
+    autosaveService.touched(id);
   }
 
   /**
@@ -238,9 +311,10 @@ class DatabaseService {
     );
   }
 
-  async deleteStory(id: string): Promise<void> {
-    const db = await this.getDb();
-    await db.execute('DELETE FROM stories WHERE id = ?', [id]);
+  async deleteStory(id: string, options: { replacing?: boolean } = {}): Promise<void> {
+    // Deleted in the backend so the story can be restored with undo. A story
+    // being replaced by a new copy is not passed on to other devices as deleted.
+    await invoke('delete_story', { storyId: id, replacing: options.replacing ?? false });
   }
 
   // Story entries operations
@@ -1472,6 +1546,15 @@ class DatabaseService {
       styleReviewState: row.style_review_state ? JSON.parse(row.style_review_state) : null,
       timeTracker: row.time_tracker ? JSON.parse(row.time_tracker) : null,
       currentBranchId: row.current_branch_id || null,
+      tags: row.tags ? JSON.parse(row.tags) : [],
+      favorite: row.favorite === 1,
+      pinned: row.pinned === 1,
+      series: row.series ? JSON.parse(row.series) : null,
+      variantOf: row.variant_of ? JSON.parse(row.variant_of) : null,
+      ambience: row.ambience ? JSON.parse(row.ambience) : null,
+      revision: row.revision ?? 0,
+      ageRating: row.age_rating ?? null,
+      contentWarnings: row.content_warnings ? JSON.parse(row.content_warnings) : [],
     };
   }
 
@@ -1,7 +1,9 @@
+import { invoke } from '@tauri-apps/api/core';
 import { save, open } from '@tauri-apps/plugin-dialog';
 import { writeTextFile, readTextFile } from '@tauri-apps/plugin-fs';
 import { database } from './database';
-import type { Story, StoryEntry, Character, Location, Item, StoryBeat, Chapter, Entry, Checkpoint, Branch, PersistentStyleReviewState, EmbeddedImage } from '$lib/types';
+import type { ImportPreview } from '$lib/types/sync';
+import type { Story, StoryEntry, Character, Location, Item, StoryBeat, Chapter, Entry, Checkpoint, Branch, PersistentStyleReviewState, EmbeddedImage, AmbienceTrack } from '$lib/types';
 
 export interface AventuraExport {
   version: string;
@@ -257,9 +259,15 @@ class ExportService {
     }
   }
 
+  // Show what importing a story would do: warnings about the export and,
+  // when the story is already in the library, how the copies differ
+  async previewImport(content: string): Promise<ImportPreview> {
+    return invoke('preview_import', { json: content });
+  }
+
   // Import from file content string (for HTML file input / mobile compatibility)
   // Set skipImportedSuffix to true for sync operations to keep the original title
-  async importFromContent(content: string, skipImportedSuffix: boolean = false): Promise<{ success: boolean; storyId?: string; error?: string }> {
+  async importFromContent(content: string, skipImportedSuffix: boolean = false, keepIds: boolean = false): Promise<{ success: boolean; storyId?: string; error?: string }> {
     try {
       let data: AventuraExport;
       try {
@@ -280,29 +288,31 @@ class ExportService {
       // Log warnings for older export versions that may be missing newer features
       this.logVersionCompatibilityWarnings(data.version);
 
-      // Generate new IDs to avoid conflicts
+      // Generate new IDs to avoid conflicts, unless the original IDs are kept
+      // so both devices know the story and its rows by the same identity
+      const newId = (oldId: string) => (keepIds ? oldId : crypto.randomUUID());
       const oldToNewId = new Map<string, string>();
       const branchIdMap = new Map<string, string>();
       const checkpointIdMap = new Map<string, string>();
 
       // Create new story ID
-      const newStoryId = crypto.randomUUID();
+      const newStoryId = newId(data.story.id);
       oldToNewId.set(data.story.id, newStoryId);
 
       // Pre-map entry IDs (branches can reference fork entries)
       for (const entry of data.entries) {
-        oldToNewId.set(entry.id, crypto.randomUUID());
+        oldToNewId.set(entry.id, newId(entry.id));
       }
 
       // Pre-map branch/checkpoint IDs if present
       if (data.branches) {
         for (const branch of data.branches) {
-          branchIdMap.set(branch.id, crypto.randomUUID());
+          branchIdMap.set(branch.id, newId(branch.id));
         }
       }
       if (data.checkpoints) {
         for (const checkpoint of data.checkpoints) {
-          checkpointIdMap.set(checkpoint.id, crypto.randomUUID());
+          checkpointIdMap.set(checkpoint.id, newId(checkpoint.id));
         }
       }
 
@@ -319,6 +329,12 @@ class ExportService {
         retryState: null, // Clear retry state on import
         styleReviewState: data.styleReviewState ?? null, // Restore style review state from export (v1.2.0+)
         timeTracker: data.story.timeTracker ?? null, // Restore time tracker from export
+        ageRating: data.story.ageRating ?? null,
+        contentWarnings: data.story.contentWarnings ?? [],
+        favorite: data.story.favorite ?? false,
+        pinned: data.story.pinned ?? false,
+        series: data.story.series ?? null, // Neighbouring parts resolve when imported with their IDs
+        variantOf: data.story.variantOf ?? null,
         currentBranchId: null, // Set after branch import (if available)
       };
 
@@ -397,7 +413,7 @@ class ExportService {
 
       // Import entries
       for (const entry of data.entries) {
-        const newEntryId = oldToNewId.get(entry.id) ?? crypto.randomUUID();
+        const newEntryId = oldToNewId.get(entry.id) ?? newId(entry.id);
         oldToNewId.set(entry.id, newEntryId);
 
         await database.addStoryEntry({
@@ -415,7 +431,7 @@ class ExportService {
       // Import characters
       if (data.characters) {
         for (const char of data.characters) {
-          const newCharId = crypto.randomUUID();
+          const newCharId = newId(char.id);
           oldToNewId.set(char.id, newCharId);
 
           await database.addCharacter({
@@ -437,7 +453,7 @@ class ExportService {
       // Import locations
       if (data.locations) {
         for (const loc of data.locations) {
-          const newLocId = crypto.randomUUID();
+          const newLocId = newId(loc.id);
           oldToNewId.set(loc.id, newLocId);
 
           await database.addLocation({
@@ -457,7 +473,7 @@ class ExportService {
       // Import items
       if (data.items) {
         for (const item of data.items) {
-          const newItemId = crypto.randomUUID();
+          const newItemId = newId(item.id);
           oldToNewId.set(item.id, newItemId);
 
           const mappedLocation = item.location === 'inventory'
@@ -481,7 +497,7 @@ class ExportService {
       // Import story beats
       if (data.storyBeats) {
         for (const beat of data.storyBeats) {
-          const newBeatId = crypto.randomUUID();
+          const newBeatId = newId(beat.id);
           oldToNewId.set(beat.id, newBeatId);
 
           await database.addStoryBeat({
@@ -502,7 +518,7 @@ class ExportService {
       // Import lorebook entries (added in v1.1.0)
       if (data.lorebookEntries) {
         for (const entry of data.lorebookEntries) {
-          const newEntryId = crypto.randomUUID();
+          const newEntryId = newId(entry.id);
           oldToNewId.set(entry.id, newEntryId);
 
           await database.addEntry({
@@ -587,7 +603,7 @@ class ExportService {
         });
 
         for (const checkpoint of data.checkpoints) {
-          const newCheckpointId = checkpointIdMap.get(checkpoint.id) ?? crypto.randomUUID();
+          const newCheckpointId = checkpointIdMap.get(checkpoint.id) ?? newId(checkpoint.id);
           checkpointIdMap.set(checkpoint.id, newCheckpointId);
 
           await database.createCheckpoint({
@@ -632,7 +648,7 @@ class ExportService {
       // Import chapters (added in v1.7.0)
       if (data.chapters) {
         for (const chapter of data.chapters) {
-          const newChapterId = crypto.randomUUID();
+          const newChapterId = newId(chapter.id);
           oldToNewId.set(chapter.id, newChapterId);
 
           await database.addChapter({
@@ -657,10 +673,25 @@ class ExportService {
         }
       }
 
+      // Restore ambience once the locations and chapters it names have their new IDs
+      if (data.story.ambience) {
+        const remapTracks = (tracks: Record<string, AmbienceTrack> = {}) =>
+          Object.fromEntries(
+            Object.entries(tracks).map(([id, track]) => [oldToNewId.get(id) ?? id, track])
+          );
+        await database.updateStory(newStoryId, {
+          ambience: {
+            defaultTrack: data.story.ambience.defaultTrack ?? null,
+            locations: remapTracks(data.story.ambience.locations),
+            chapters: remapTracks(data.story.ambience.chapters),
+          },
+        });
+      }
+
       // Import embedded images (added in v1.4.0)
       if (data.embeddedImages) {
         for (const image of data.embeddedImages) {
-          const newImageId = crypto.randomUUID();
+          const newImageId = newId(image.id);
           oldToNewId.set(image.id, newImageId);
 
           // Map the entry ID to the new entry ID
@@ -3,11 +3,34 @@ import type {
   SyncServerInfo,
   SyncStoryPreview,
   SyncConnectionData,
+  SettingsBundle,
+  SettingsScope,
+  ApiKeyEntry,
+  KeyExchangeHandshake,
+  PendingKeyExchange,
+  PushOutcome,
+  PendingSyncOp,
+  OpdsCatalogInfo,
+  NetworkBinding,
+  NetworkInterfaceInfo,
+  StoryTombstone,
+  FavoritesConfig,
+  FolderSyncReport,
+  SpillConfig,
+  SyncMetrics,
+  SyncHealthInfo,
+  DeviceProfile,
+  DeviceIdentity,
 } from '$lib/types/sync';
-import type { AventuraExport } from './export';
+import { exportService, type AventuraExport } from './export';
 import { database } from './database';
 import { story } from '$lib/stores/story.svelte';
 
+// Scopes a peer must list a capability for; older peers reject them
+const SCOPE_CAPABILITIES: Partial<Record<SettingsScope, string>> = {
+  styleReferences: 'styleReferences',
+};
+
 /**
  * Service for local network sync functionality
  */
@@ -15,10 +38,65 @@ class SyncService {
   /**
    * Start the sync server with all local stories available
    * @param storiesJson Array of story JSON strings in Aventura export format
-   * @returns Server info including QR code
+   * @param binding Interface to listen on and whether to accept only local networks
+   * @param exposeMetrics Serve Prometheus metrics at `/metrics` (bearer token required)
+   * @returns Server info including QR code and the networks the server is visible on
+   */
+  async startServer(
+    storiesJson: string[],
+    binding?: NetworkBinding,
+    exposeMetrics?: boolean
+  ): Promise<SyncServerInfo> {
+    return invoke('start_sync_server', { storiesJson, binding, exposeMetrics });
+  }
+
+  /**
+   * Request counts, transfer totals and active sessions of the running server
    */
-  async startServer(storiesJson: string[]): Promise<SyncServerInfo> {
-    return invoke('start_sync_server', { storiesJson });
+  async getMetrics(): Promise<SyncMetrics | null> {
+    return invoke('get_sync_metrics');
+  }
+
+  /**
+   * List network interfaces the server can be bound to
+   */
+  async listNetworkInterfaces(): Promise<NetworkInterfaceInfo[]> {
+    return invoke('list_network_interfaces');
+  }
+
+  /**
+   * When large stories are kept on disk instead of in memory while served
+   */
+  async getSpillConfig(): Promise<SpillConfig> {
+    return invoke('get_sync_spill_config');
+  }
+
+  /**
+   * Change the spill threshold. Applies to servers started afterwards.
+   */
+  async setSpillConfig(config: SpillConfig): Promise<void> {
+    return invoke('set_sync_spill_config', { config });
+  }
+
+  /**
+   * Name, version and features of a server, checked before pairing
+   */
+  async checkHealth(ip: string, port: number): Promise<SyncHealthInfo> {
+    return invoke('sync_check_health', { ip, port });
+  }
+
+  /**
+   * Name and avatar other devices see
+   */
+  async getDeviceProfile(): Promise<DeviceProfile> {
+    return invoke('get_device_profile');
+  }
+
+  /**
+   * Save the device name and avatar; returns how the device will be shown
+   */
+  async setDeviceProfile(profile: DeviceProfile): Promise<DeviceIdentity> {
+    return invoke('set_device_profile', { profile });
   }
 
   /**
@@ -42,6 +120,61 @@ class SyncService {
     return invoke('clear_received_stories');
   }
 
+  /**
+   * Get which stories are synced
+   */
+  async getFavoritesConfig(): Promise<FavoritesConfig> {
+    return invoke('get_sync_favorites_config');
+  }
+
+  /**
+   * Limit syncing to favorite stories. Offering applies to servers started afterwards.
+   */
+  async setFavoritesConfig(config: FavoritesConfig): Promise<void> {
+    return invoke('set_sync_favorites_config', { config });
+  }
+
+  /**
+   * Pull favorites that are newer on the server and push local favorites
+   * that are newer here, as the auto-sync favorites option does on connect.
+   * Returns the titles synced each way.
+   */
+  async autoSyncFavorites(
+    connection: SyncConnectionData,
+    remoteStories: SyncStoryPreview[]
+  ): Promise<{ pulled: string[]; pushed: string[] }> {
+    const localStories = await database.getAllStories();
+    const pulled: string[] = [];
+    const pushed: string[] = [];
+
+    for (const remote of remoteStories) {
+      const localId = await this.findLocalStoryId(remote);
+      const local = localStories.find((s) => s.id === localId);
+      if (!(remote.favorite || local?.favorite)) continue;
+      if (local && local.updatedAt >= remote.updatedAt) continue;
+
+      const storyJson = await this.pullStory(connection, remote.id);
+      if (local) {
+        await this.createPreSyncBackup(local.id);
+        await this.deleteReplacedStory(local.id);
+      }
+      const result = await exportService.importFromContent(storyJson, true, true);
+      if (result.success) pulled.push(remote.title);
+    }
+
+    for (const local of localStories) {
+      if (!local.favorite) continue;
+      const remote =
+        remoteStories.find((s) => s.id === local.id) ??
+        remoteStories.find((s) => s.title === local.title);
+      if (remote && remote.updatedAt >= local.updatedAt) continue;
+
+      await this.pushStory(connection, await this.exportStoryToJson(local.id));
+      pushed.push(local.title);
+    }
+    return { pulled, pushed };
+  }
+
   /**
    * Connect to a remote sync server and list available stories
    */
@@ -70,12 +203,13 @@ class SyncService {
   }
 
   /**
-   * Push a story to a remote server
+   * Push a story to a remote server. If the peer is unreachable the push is
+   * queued and retried in the background.
    */
   async pushStory(
     connection: SyncConnectionData,
     storyJson: string
-  ): Promise<void> {
+  ): Promise<PushOutcome> {
     return invoke('sync_push_story', {
       ip: connection.ip,
       port: connection.port,
@@ -84,6 +218,174 @@ class SyncService {
     });
   }
 
+  /**
+   * List pushes waiting for their peer to come back online
+   */
+  async listPendingOps(): Promise<PendingSyncOp[]> {
+    return invoke('list_pending_sync_ops');
+  }
+
+  /**
+   * Cancel a queued push
+   */
+  async cancelPendingOp(opId: string): Promise<boolean> {
+    return invoke('cancel_pending_sync_op', { opId });
+  }
+
+  /**
+   * Publish stories offered by the running server as an OPDS catalog for
+   * e-reader apps. EPUBs are generated when a reader downloads them.
+   */
+  async publishOpdsCatalog(storyIds: string[]): Promise<OpdsCatalogInfo> {
+    return invoke('publish_opds_catalog', { storyIds });
+  }
+
+  /**
+   * Stop serving the OPDS catalog
+   */
+  async unpublishOpdsCatalog(): Promise<void> {
+    return invoke('unpublish_opds_catalog');
+  }
+
+  /**
+   * Write local stories and deletions to a sync folder. Stories whose folder
+   * copy is newer are left alone.
+   */
+  async syncToFolder(
+    path: string,
+    storiesJson: string[],
+    tombstones?: StoryTombstone[]
+  ): Promise<FolderSyncReport> {
+    return invoke('sync_to_folder', { path, storiesJson, tombstones });
+  }
+
+  /**
+   * Read stories from a sync folder that are newer than the local copies.
+   * The caller imports the stories with importFolderStories and confirms deletions.
+   */
+  async syncFromFolder(
+    path: string,
+    storiesJson: string[]
+  ): Promise<FolderSyncReport> {
+    return invoke('sync_from_folder', { path, storiesJson });
+  }
+
+  /**
+   * The scopes a peer can take, for the settings the user may pick.
+   * Scopes newer than the peer are left out; the backend drops them too.
+   */
+  settingsScopesFor(health: SyncHealthInfo, scopes: SettingsScope[]): SettingsScope[] {
+    return scopes.filter((scope) => {
+      const capability = SCOPE_CAPABILITIES[scope];
+      return !capability || health.capabilities.includes(capability);
+    });
+  }
+
+  /**
+   * Share settings with devices connecting to this server.
+   * AI profiles and style references are read by the backend; only the
+   * selected scopes are shared.
+   */
+  async shareSettings(
+    settings: SettingsBundle,
+    scopes: SettingsScope[]
+  ): Promise<void> {
+    return invoke('share_sync_settings', { settings, scopes });
+  }
+
+  /**
+   * Take settings pushed to this server. AI profiles and style references
+   * are saved by the backend; the other scopes are returned to be applied.
+   */
+  async applyReceivedSettings(): Promise<SettingsBundle[]> {
+    return invoke('apply_received_settings');
+  }
+
+  /**
+   * Pull shared settings from a remote server. Scopes the peer does not
+   * offer are left out.
+   */
+  async pullSettings(
+    connection: SyncConnectionData,
+    scopes: SettingsScope[]
+  ): Promise<SettingsBundle> {
+    return invoke('sync_pull_settings', {
+      ip: connection.ip,
+      port: connection.port,
+      token: connection.token,
+      scopes,
+    });
+  }
+
+  /**
+   * Push settings to a remote server
+   */
+  async pushSettings(
+    connection: SyncConnectionData,
+    settings: SettingsBundle,
+    scopes: SettingsScope[]
+  ): Promise<void> {
+    return invoke('sync_push_settings', {
+      ip: connection.ip,
+      port: connection.port,
+      token: connection.token,
+      settings,
+      scopes,
+    });
+  }
+
+  /**
+   * Get the key exchange waiting for this device to compare codes
+   */
+  async getPendingKeyExchange(): Promise<PendingKeyExchange | null> {
+    return invoke('get_pending_key_exchange');
+  }
+
+  /**
+   * Accept or reject a key exchange after comparing codes
+   */
+  async confirmKeyExchange(exchangeId: string, accept: boolean): Promise<void> {
+    return invoke('confirm_key_exchange', { exchangeId, accept });
+  }
+
+  /**
+   * Start sending API keys to a remote device
+   * @returns Handshake including the code to compare with the other screen
+   */
+  async beginKeyExchange(
+    connection: SyncConnectionData
+  ): Promise<KeyExchangeHandshake> {
+    return invoke('sync_begin_key_exchange', {
+      ip: connection.ip,
+      port: connection.port,
+      token: connection.token,
+    });
+  }
+
+  /**
+   * Send encrypted API keys once both users have confirmed the code
+   */
+  async sendApiKeys(
+    connection: SyncConnectionData,
+    handshake: KeyExchangeHandshake,
+    keys: ApiKeyEntry[]
+  ): Promise<void> {
+    return invoke('sync_send_api_keys', {
+      ip: connection.ip,
+      port: connection.port,
+      token: connection.token,
+      handshake,
+      keys,
+    });
+  }
+
+  /**
+   * Read a provider's API key from the OS keychain
+   */
+  async getKeychainApiKey(provider: string): Promise<string | null> {
+    return invoke('get_keychain_api_key', { provider });
+  }
+
   /**
    * Create a pre-sync backup checkpoint for a story
    */
@@ -180,27 +482,54 @@ class SyncService {
   }
 
   /**
-   * Check if a story with the given title already exists locally
+   * Check if a local copy of a synced story exists
    */
-  async checkStoryExists(title: string): Promise<boolean> {
-    const allStories = await database.getAllStories();
-    return allStories.some((s) => s.title === title);
+  async checkStoryExists(preview: { id: string; title: string }): Promise<boolean> {
+    return (await this.findLocalStoryId(preview)) !== null;
   }
 
   /**
-   * Find story ID by title (for replacement)
+   * Find the local copy of a synced story (for replacement). Synced stories
+   * keep their ID on every device; stories synced before that are matched by title.
    */
-  async findStoryIdByTitle(title: string): Promise<string | null> {
+  async findLocalStoryId(preview: { id: string; title: string }): Promise<string | null> {
     const allStories = await database.getAllStories();
-    const found = allStories.find((s) => s.title === title);
+    const found =
+      allStories.find((s) => s.id === preview.id) ??
+      allStories.find((s) => s.title === preview.title);
     return found?.id ?? null;
   }
 
   /**
-   * Delete a story by ID
+   * Import the stories read from a sync folder, replacing local copies and
+   * keeping the stories' IDs so the next folder sync matches them.
+   * Returns the titles of stories that failed to import.
+   */
+  async importFolderStories(report: FolderSyncReport): Promise<string[]> {
+    const failed: string[] = [];
+    for (const json of report.stories) {
+      const preview = this.getStoryPreview(json);
+      if (!preview) {
+        failed.push('Unreadable story');
+        continue;
+      }
+      const existingId = await this.findLocalStoryId(preview);
+      if (existingId) {
+        await this.createPreSyncBackup(existingId);
+        await this.deleteReplacedStory(existingId);
+      }
+      const result = await exportService.importFromContent(json, true, true);
+      if (!result.success) failed.push(preview.title);
+    }
+    return failed;
+  }
+
+  /**
+   * Delete a local story that a synced copy is about to replace. No deletion
+   * is recorded for other devices, since the story lives on.
    */
-  async deleteStory(storyId: string): Promise<void> {
-    await database.deleteStory(storyId);
+  async deleteReplacedStory(storyId: string): Promise<void> {
+    await database.deleteStory(storyId, { replacing: true });
   }
 
   /**
@@ -0,0 +1,56 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { exportService } from './export';
+import { story } from '$lib/stores/story.svelte';
+
+// A story queued by the backend: a file dropped into the watched import
+// folder, a file opened with the app or a followed import link
+export interface WatchedImport {
+  id: string;
+  path: string | null;
+  fileName: string;
+  storyId: string;
+  title: string;
+  storyJson: string;
+}
+
+/**
+ * Imports the stories the backend queues. The queue outlives the app, so
+ * stories offered while it was closing are imported on the next launch.
+ */
+class WatchedImportService {
+  private started = false;
+  private importing = new Set<string>();
+
+  async start(): Promise<void> {
+    if (this.started) return;
+    this.started = true;
+    await listen<WatchedImport>('import://story', (event) => {
+      this.importOne(event.payload).catch(console.error);
+    });
+    const pending = await invoke<WatchedImport[]>('get_watched_imports');
+    for (const item of pending) {
+      await this.importOne(item);
+    }
+  }
+
+  private async importOne(item: WatchedImport): Promise<void> {
+    // The event and the startup queue can both name the same story
+    if (this.importing.has(item.id)) return;
+    this.importing.add(item.id);
+    try {
+      const result = await exportService.importFromContent(item.storyJson);
+      const error = result.success ? null : (result.error ?? 'Import failed');
+      if (error) {
+        console.error(`[WatchedImport] ${item.fileName}: ${error}`);
+      } else {
+        await story.loadAllStories();
+      }
+      await invoke('finish_watched_import', { id: item.id, error });
+    } finally {
+      this.importing.delete(item.id);
+    }
+  }
+}
+
+export const watchedImportService = new WatchedImportService();
@@ -1,5 +1,7 @@
+import { invoke } from '@tauri-apps/api/core';
 import type { Story, StoryEntry, Character, Location, Item, StoryBeat, Chapter, Checkpoint, Branch, MemoryConfig, StoryMode, StorySettings, Entry, TimeTracker, EmbeddedImage, PersistentCharacterSnapshot } from '$lib/types';
 import { database } from '$lib/services/database';
+import { autosaveService } from '$lib/services/autosave';
 import { BUILTIN_TEMPLATES } from '$lib/services/templates';
 import { ui } from './ui.svelte';
 import type { ClassificationResult } from '$lib/services/ai/classifier';
@@ -373,9 +375,18 @@ class StoryStore {
     return repairsMade;
   }
 
+  // The backend locks stories while it syncs, merges, archives or restores
+  // them; writing to one then would mix changes into that operation, so the
+  // write fails with the backend's StoryLocked error instead
+  private async assertWritable(storyId: string | undefined): Promise<void> {
+    if (storyId) {
+      await invoke('check_story_writable', { storyId });
+    }
+  }
+
   // Load all stories for library view
   async loadAllStories(): Promise<void> {
-    this.allStories = await database.getAllStories();
+    this.allStories = await database.getVisibleStories();
   }
 
   // Load a specific story with all its data
@@ -390,6 +401,7 @@ class StoryStore {
     await database.cleanupOrphanedEmbeddedImages();
 
     this.currentStory = story;
+    autosaveService.loaded(storyId, story.revision ?? 0);
 
     // Load branch-independent data first
     const [characters, locations, items, storyBeats, checkpoints, lorebookEntries, branches] = await Promise.all([
@@ -604,6 +616,7 @@ class StoryStore {
     if (!this.currentStory) {
       throw new Error('No story loaded');
     }
+    await this.assertWritable(this.currentStory.id);
 
     // Count tokens for accurate auto-summarize threshold detection
     const tokenCount = countTokens(content);
@@ -642,6 +655,7 @@ class StoryStore {
   // Update a story entry
   async updateEntry(entryId: string, content: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existingEntry = this.entries.find(e => e.id === entryId);
     if (!existingEntry) throw new Error('Entry not found');
@@ -676,6 +690,7 @@ class StoryStore {
   // Delete a story entry
   async deleteEntry(entryId: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existingEntry = this.entries.find(e => e.id === entryId);
     if (!existingEntry) throw new Error('Entry not found');
@@ -717,6 +732,7 @@ class StoryStore {
    */
   async updateEntryTimeEnd(entryId: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const entry = this.entries.find(e => e.id === entryId);
     if (!entry) {
@@ -745,6 +761,7 @@ class StoryStore {
    */
   async deleteEntriesFromPosition(position: number): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     // Find entries to delete (position >= the given position)
     const entriesToDelete = this.entries.filter(e => e.position >= position);
@@ -811,6 +828,7 @@ class StoryStore {
     embeddedImageIds?: string[];
   }): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const characterIdsSet = new Set(savedIds.characterIds);
     const locationIdsSet = new Set(savedIds.locationIds);
@@ -876,6 +894,7 @@ class StoryStore {
   // Add a character
   async addCharacter(name: string, description?: string, relationship?: string): Promise<Character> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const character: Character = {
       id: crypto.randomUUID(),
@@ -899,6 +918,7 @@ class StoryStore {
   // Update an existing character (except protagonist swap)
   async updateCharacter(id: string, updates: Partial<Character>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existing = this.characters.find(c => c.id === id);
     if (!existing) throw new Error('Character not found');
@@ -921,6 +941,7 @@ class StoryStore {
   // Delete a character (protagonist cannot be deleted)
   async deleteCharacter(id: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existing = this.characters.find(c => c.id === id);
     if (!existing) throw new Error('Character not found');
@@ -935,6 +956,7 @@ class StoryStore {
   // Add a location
   async addLocation(name: string, description?: string, makeCurrent = false): Promise<Location> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const location: Location = {
       id: crypto.randomUUID(),
@@ -962,6 +984,7 @@ class StoryStore {
   // Update a location's details
   async updateLocation(id: string, updates: Partial<Location>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existing = this.locations.find(l => l.id === id);
     if (!existing) throw new Error('Location not found');
@@ -984,6 +1007,7 @@ class StoryStore {
   // Set current location
   async setCurrentLocation(locationId: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     await database.setCurrentLocation(this.currentStory.id, locationId);
     this.locations = this.locations.map(l => ({
@@ -996,6 +1020,7 @@ class StoryStore {
   // Toggle location visited status
   async toggleLocationVisited(locationId: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const location = this.locations.find(l => l.id === locationId);
     if (!location) throw new Error('Location not found');
@@ -1011,6 +1036,7 @@ class StoryStore {
   // Delete a location
   async deleteLocation(locationId: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const location = this.locations.find(l => l.id === locationId);
     if (!location) throw new Error('Location not found');
@@ -1023,6 +1049,7 @@ class StoryStore {
   // Add an item to inventory
   async addItem(name: string, description?: string, quantity = 1): Promise<Item> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const item: Item = {
       id: crypto.randomUUID(),
@@ -1044,6 +1071,7 @@ class StoryStore {
   // Update an existing item
   async updateItem(id: string, updates: Partial<Item>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existing = this.items.find(i => i.id === id);
     if (!existing) throw new Error('Item not found');
@@ -1057,6 +1085,7 @@ class StoryStore {
   // Delete an item
   async deleteItem(id: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existing = this.items.find(i => i.id === id);
     if (!existing) throw new Error('Item not found');
@@ -1068,6 +1097,7 @@ class StoryStore {
   // Add a story beat
   async addStoryBeat(title: string, type: StoryBeat['type'], description?: string): Promise<StoryBeat> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const beat: StoryBeat = {
       id: crypto.randomUUID(),
@@ -1090,6 +1120,7 @@ class StoryStore {
   // Update a story beat
   async updateStoryBeat(id: string, updates: Partial<StoryBeat>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existing = this.storyBeats.find(b => b.id === id);
     if (!existing) throw new Error('Story beat not found');
@@ -1114,6 +1145,7 @@ class StoryStore {
   // Delete a story beat
   async deleteStoryBeat(id: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const existing = this.storyBeats.find(b => b.id === id);
     if (!existing) throw new Error('Story beat not found');
@@ -1125,6 +1157,7 @@ class StoryStore {
   // Swap the protagonist to another character, updating the old label
   async setProtagonist(newCharacterId: string, previousRelationshipLabel?: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const currentProtagonist = this.characters.find(c => c.relationship === 'self') ?? null;
     const newProtagonist = this.characters.find(c => c.id === newCharacterId);
@@ -1177,6 +1210,7 @@ class StoryStore {
    */
   async addLorebookEntry(entryData: Omit<Entry, 'id' | 'storyId' | 'createdAt' | 'updatedAt' | 'branchId'> & { branchId?: string | null }): Promise<Entry> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const now = Date.now();
     const entry: Entry = {
@@ -1200,6 +1234,7 @@ class StoryStore {
    */
   async updateLorebookEntry(id: string, updates: Partial<Entry>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const updatesWithTimestamp = {
       ...updates,
@@ -1218,6 +1253,7 @@ class StoryStore {
    */
   async deleteLorebookEntry(id: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     await database.deleteEntry(id);
     this.lorebookEntries = this.lorebookEntries.filter(e => e.id !== id);
@@ -1252,6 +1288,7 @@ class StoryStore {
       log('applyClassificationResult: No story loaded, skipping');
       return;
     }
+    await this.assertWritable(this.currentStory.id);
 
     log('applyClassificationResult called', {
       characterUpdates: result.entryUpdates.characterUpdates.length,
@@ -1583,6 +1620,7 @@ class StoryStore {
   // Update story mode
   async setStoryMode(mode: StoryMode): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     await database.updateStory(this.currentStory.id, { mode });
     this.currentStory = { ...this.currentStory, mode };
@@ -1595,6 +1633,7 @@ class StoryStore {
   // Update memory configuration
   async setMemoryConfig(config: MemoryConfig): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     await database.updateStory(this.currentStory.id, { memoryConfig: config });
     this.currentStory = { ...this.currentStory, memoryConfig: config };
@@ -1604,6 +1643,7 @@ class StoryStore {
   // Add a chapter
   async addChapter(chapter: Chapter): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     await database.addChapter(chapter);
     this.chapters = [...this.chapters, chapter];
@@ -1635,6 +1675,7 @@ class StoryStore {
   // Update a chapter's summary
   async updateChapterSummary(chapterId: string, summary: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     await database.updateChapter(chapterId, { summary });
     this.chapters = this.chapters.map(ch =>
@@ -1646,6 +1687,7 @@ class StoryStore {
   // Update a chapter with multiple fields
   async updateChapter(chapterId: string, updates: Partial<Chapter>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     await database.updateChapter(chapterId, updates);
     this.chapters = this.chapters.map(ch =>
@@ -1670,6 +1712,7 @@ class StoryStore {
   // Delete a chapter
   async deleteChapter(chapterId: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     await database.deleteChapter(chapterId);
     this.chapters = this.chapters.filter(ch => ch.id !== chapterId);
@@ -1683,6 +1726,7 @@ class StoryStore {
   // Update memory configuration (partial updates)
   async updateMemoryConfig(updates: Partial<MemoryConfig>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
 const newConfig = { ...this.memoryConfig, ...updates };
     await database.updateStory(this.currentStory.id, { memoryConfig: newConfig });
@@ -1693,6 +1737,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
   // Update story settings (partial updates)
   async updateStorySettings(updates: Partial<StorySettings>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const newSettings = { ...(this.currentStory.settings ?? {}), ...updates };
     await database.updateStory(this.currentStory.id, { settings: newSettings });
@@ -1756,6 +1801,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
   // Set time tracker directly
   async setTimeTracker(time: TimeTracker): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const normalized = this.normalizeTime(time);
     await database.saveTimeTracker(this.currentStory.id, normalized);
@@ -1766,6 +1812,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
   // Update time tracker with partial values (adds to current time)
   async addTime(updates: Partial<TimeTracker>): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const current = this.timeTracker;
     const newTime: TimeTracker = {
@@ -1807,6 +1854,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
    */
   async restoreTimeTrackerSnapshot(snapshot: TimeTracker | null | undefined): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
     if (snapshot === undefined) return;
 
     if (snapshot === null) {
@@ -1886,6 +1934,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
   // Create a checkpoint (snapshot of current state)
   async createCheckpoint(name: string): Promise<Checkpoint> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     const lastEntry = this.entries[this.entries.length - 1];
     if (!lastEntry) throw new Error('No entries to checkpoint');
@@ -1932,6 +1981,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
 
   // Delete a checkpoint
   async deleteCheckpoint(checkpointId: string): Promise<void> {
+    await this.assertWritable(this.currentStory?.id);
     await database.deleteCheckpoint(checkpointId);
     this.checkpoints = this.checkpoints.filter(cp => cp.id !== checkpointId);
     log('Checkpoint deleted:', checkpointId);
@@ -1953,6 +2003,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
    */
   async createBranchFromCheckpoint(name: string, forkEntryId: string, checkpointId: string): Promise<Branch> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     // Verify the checkpoint exists in memory
     const checkpoint = this.checkpoints.find(cp => cp.id === checkpointId);
@@ -2128,6 +2179,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
    */
   async switchBranch(branchId: string | null, skipReload: boolean = false): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     // Validate branch exists (if not null)
     if (branchId !== null) {
@@ -2311,6 +2363,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
    * Rename a branch.
    */
   async renameBranch(branchId: string, newName: string): Promise<void> {
+    await this.assertWritable(this.currentStory?.id);
     await database.updateBranch(branchId, { name: newName });
     this.branches = this.branches.map(b =>
       b.id === branchId ? { ...b, name: newName } : b
@@ -2324,6 +2377,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
    */
   async deleteBranch(branchId: string): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     // Cannot delete current branch
     if (this.currentStory.currentBranchId === branchId) {
@@ -2440,6 +2494,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
     timeTracker?: TimeTracker | null;
   }): Promise<void> {
     if (!this.currentStory) throw new Error('No story loaded');
+    await this.assertWritable(this.currentStory.id);
 
     // Debug: Log character visual descriptors before restore
     const currentCharDescriptors = this.characters.map(c => ({
@@ -2540,6 +2595,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
       log('restoreCharacterSnapshots: early return - no story or no snapshots');
       return;
     }
+    await this.assertWritable(this.currentStory.id);
 
     const snapshotById = new Map(snapshots.map(snapshot => [snapshot.id, snapshot]));
     const updates: Array<{ id: string; updates: Partial<Character> }> = [];
@@ -2596,6 +2652,7 @@ const newConfig = { ...this.memoryConfig, ...updates };
 
   // Delete a story
   async deleteStory(storyId: string): Promise<void> {
+    await this.assertWritable(storyId);
     await database.deleteStory(storyId);
     this.allStories = this.allStories.filter(s => s.id !== storyId);
 
@@ -27,6 +27,62 @@ export interface Story {
   styleReviewState: PersistentStyleReviewState | null;
   timeTracker: TimeTracker | null;
   currentBranchId: string | null;  // Active branch (null = main branch for legacy stories)
+  tags?: string[];  // Library tags, set in bulk from the library
+  favorite?: boolean;
+  pinned?: boolean;  // Kept at the top of the library
+  ageRating?: AgeRating | null;
+  contentWarnings?: ContentWarning[];
+  series?: SeriesLink | null;  // Set on the parts of a split story
+  variantOf?: VariantLink | null;  // Set on translated variants
+  ambience?: StoryAmbience | null;  // Tracks played for the story's scenes
+  revision?: number;  // Bumped by every save; saves name the revision they build on
+}
+
+/** An audio attachment played as ambience */
+export interface AmbienceTrack {
+  attachmentId: string;
+  looping: boolean;
+  volume: number;  // 0 to 1
+}
+
+/** Ambience for a story: the current location's track, else the current chapter's, else the default */
+export interface StoryAmbience {
+  defaultTrack: AmbienceTrack | null;
+  locations: Record<string, AmbienceTrack>;  // By location ID
+  chapters: Record<string, AmbienceTrack>;  // By chapter ID
+}
+
+/** Where a part sits in a split story */
+export interface SeriesLink {
+  id: string;
+  title: string;
+  part: number;
+  previousStoryId: string | null;
+  nextStoryId: string | null;
+}
+
+/** The story a translated variant was made from */
+export interface VariantLink {
+  sourceStoryId: string;
+  language: string;
+}
+
+export type AgeRating = 'everyone' | 'teen' | 'mature' | 'adult';
+
+export type ContentWarning =
+  | 'violence'
+  | 'gore'
+  | 'sexualContent'
+  | 'selfHarm'
+  | 'substanceUse'
+  | 'strongLanguage'
+  | 'abuse'
+  | 'horror'
+  | 'discrimination';
+
+export interface ContentRating {
+  ageRating: AgeRating | null;
+  warnings: ContentWarning[];
 }
 
 // Persistent retry state - lightweight version saved to database
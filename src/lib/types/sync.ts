@@ -1,3 +1,5 @@
+import type { ContentRating } from './index';
+
 /**
  * Types for the local network sync feature
  */
@@ -10,6 +12,32 @@ export interface SyncServerInfo {
   port: number;
   token: string;
   qrCodeBase64: string;
+  /** How this device is shown to others */
+  device: DeviceIdentity;
+  /** Networks devices can reach the server on */
+  visibleOn: NetworkInterfaceInfo[];
+  lanOnly: boolean;
+}
+
+/**
+ * A network interface of this device
+ */
+export interface NetworkInterfaceInfo {
+  name: string;
+  ip: string;
+  /** Private (RFC 1918 / unique local) or link-local address */
+  isPrivate: boolean;
+  isLoopback: boolean;
+}
+
+/**
+ * Where the sync server listens. All interfaces when no interface is given.
+ */
+export interface NetworkBinding {
+  /** Interface name or IP address */
+  interface?: string;
+  /** Reject requests from outside the local network */
+  lanOnly?: boolean;
 }
 
 /**
@@ -21,6 +49,71 @@ export interface SyncStoryPreview {
   genre: string | null;
   updatedAt: number;
   entryCount: number;
+  size?: number | null;  // Bytes of export JSON; missing from older servers
+  favorite?: boolean;
+  pinned?: boolean;
+  rating?: ContentRating;
+  thumbnail?: string;  // Base64 JPEG of the cover, from servers with the "thumbnails" capability
+}
+
+/**
+ * Which stories are synced
+ */
+export interface FavoritesConfig {
+  /** A sync server started here only offers favorite stories */
+  offerOnlyFavorites: boolean;
+  /** Sync plans only include stories that are a favorite on either device */
+  syncOnlyFavorites: boolean;
+  /** Newer favorites are pulled and pushed as soon as this device connects */
+  autoSyncFavorites: boolean;
+}
+
+/**
+ * Counts of records an import adds, removes and changes
+ */
+export interface ImportChangeCounts {
+  added: number;
+  removed: number;
+  changed: number;
+  unchanged: number;
+}
+
+/**
+ * How an import differs from the copy already in the library
+ */
+export interface ImportDiff {
+  source: 'provided' | 'library';
+  /** Differs from the import's ID for copies matched by title */
+  localStoryId: string;
+  localTitle: string;
+  localUpdatedAt: number;
+  localIsNewer: boolean;
+  titleChanged: boolean;
+  entries: ImportChangeCounts;
+  entryChanges: {
+    entryId: string;
+    kind: 'added' | 'removed' | 'changed';
+    localExcerpt: string | null;
+    incomingExcerpt: string | null;
+  }[];
+  characters: ImportChangeCounts;
+  locations: ImportChangeCounts;
+  items: ImportChangeCounts;
+  storyBeats: ImportChangeCounts;
+  lorebookEntries: ImportChangeCounts;
+  chapters: ImportChangeCounts;
+  branches: ImportChangeCounts;
+}
+
+/**
+ * What importing a story would do, shown before it is imported
+ */
+export interface ImportPreview {
+  story: SyncStoryPreview;
+  warnings: string[];
+  /** Present when the story is already in the library */
+  diff: ImportDiff | null;
+  archived: boolean;
 }
 
 /**
@@ -31,6 +124,7 @@ export interface SyncConnectionData {
   port: number;
   token: string;
   version?: string; // App version for compatibility check (optional for backwards compat)
+  device?: DeviceIdentity; // Missing from codes made by older versions
 }
 
 /**
@@ -42,3 +136,168 @@ export type SyncMode = 'select' | 'generate' | 'scan' | 'connected' | 'syncing';
  * Action to perform when syncing
  */
 export type SyncAction = 'push' | 'pull';
+
+/**
+ * Categories of settings that can be transferred between devices
+ */
+export type SettingsScope =
+  | 'templates'
+  | 'lorebooks'
+  | 'aiProfiles'
+  | 'preferences'
+  | 'styleReferences';
+
+/**
+ * Settings transferred alongside stories. API keys and other secrets are
+ * always stripped before leaving the device.
+ */
+export interface SettingsBundle {
+  templates?: unknown;
+  lorebooks?: unknown;
+  aiProfiles?: unknown[];
+  preferences?: unknown;
+  /** Read and saved by the backend, like AI profiles */
+  styleReferences?: unknown;
+}
+
+/**
+ * API key for one provider, transferred during a key exchange
+ */
+export interface ApiKeyEntry {
+  provider: string;
+  apiKey: string;
+}
+
+/**
+ * Public keys both devices derive the comparison code and encryption key from
+ */
+export interface KeyExchangeHandshake {
+  exchangeId: string;
+  clientPublic: string;
+  serverPublic: string;
+  code: string;
+  /** The other device, if it introduced itself */
+  peer?: DeviceIdentity | null;
+}
+
+/**
+ * Key exchange waiting for the host to compare codes
+ */
+export interface PendingKeyExchange extends KeyExchangeHandshake {
+  confirmed: boolean;
+  expiresAt: number;
+}
+
+/**
+ * Result of pushing a story. Pushes to unreachable peers are queued and retried.
+ */
+export type PushOutcome = { status: 'delivered' } | { status: 'queued'; opId: string };
+
+/**
+ * A push waiting in the outbox for its peer to come back online
+ */
+export interface PendingSyncOp {
+  id: string;
+  ip: string;
+  port: number;
+  storyId: string;
+  title: string;
+  attempts: number;
+  createdAt: number;
+  nextAttemptAt: number;
+  lastError: string | null;
+}
+
+/**
+ * Address of the OPDS catalog served to e-reader apps
+ */
+export interface OpdsCatalogInfo {
+  url: string;
+  storyCount: number;
+}
+
+/**
+ * Record that a story was deleted, so other devices can remove their copies
+ */
+export interface StoryTombstone {
+  storyId: string;
+  deletedAt: number;
+}
+
+/**
+ * Outcome of syncing with a folder (USB stick or shared drive)
+ */
+export interface FolderSyncReport {
+  /** Story IDs written to the folder */
+  written: string[];
+  /** Story IDs skipped because the other side was newer or identical */
+  skipped: string[];
+  /** Stories to import, in Aventura export format */
+  stories: string[];
+  /** Deletions that apply to local stories */
+  deletions: StoryTombstone[];
+  /** Stories that could not be written or read; the rest were still synced */
+  errors: string[];
+}
+
+/**
+ * When story payloads served by the sync and API servers leave memory
+ */
+export interface SpillConfig {
+  /** Payloads larger than this many megabytes are kept on disk; 0 disables */
+  thresholdMb: number;
+}
+
+/**
+ * Counts for one kind of sync action
+ */
+export interface SyncActionMetrics {
+  action: string;
+  requests: number;
+  errors: number;
+}
+
+/**
+ * Point-in-time counters of the sync server
+ */
+export interface SyncMetrics {
+  uptimeSecs: number;
+  actions: SyncActionMetrics[];
+  totalRequests: number;
+  totalErrors: number;
+  /** Share of requests that failed, from 0 to 1 */
+  errorRate: number;
+  bytesReceived: number;
+  bytesSent: number;
+  activePlayers: number;
+  activeSpectators: number;
+}
+
+/**
+ * Identity and abilities of a sync server, public without a token
+ */
+export interface SyncHealthInfo extends DeviceIdentity {
+  appVersion: string;
+  protocolVersion: number;
+  /** Optional features, e.g. "msgpack", "game", "opds", "styleReferences" */
+  capabilities: string[];
+}
+
+/**
+ * Name and avatar this device chose; unset fields use defaults
+ */
+export interface DeviceProfile {
+  name?: string | null;
+  avatarEmoji?: string | null;
+  /** Hex color such as "#3b82f6" */
+  avatarColor?: string | null;
+}
+
+/**
+ * How a device is shown in pairing screens
+ */
+export interface DeviceIdentity {
+  name: string;
+  avatarEmoji?: string;
+  avatarColor?: string;
+}